@@ -0,0 +1,260 @@
+//! `mado`: work with the daemon's sessions from a terminal. Talks to the
+//! same daemon as the desktop app, so both see the same sessions.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+
+use mado_core::client::{default_socket_path, DaemonClient};
+use mado_core::types::{MessageRole, Session, SessionActivity, SessionStatus, StreamEvent};
+
+/// Milestones searched for one matching an abbreviated oid.
+const OID_SEARCH_LIMIT: usize = 200;
+
+/// Command-line client for the Mado daemon.
+#[derive(Debug, Parser)]
+#[command(name = "mado", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Path to the daemon's Unix domain socket.
+    #[arg(long, global = true, value_name = "PATH", default_value_os_t = default_socket_path())]
+    socket_path: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the daemon's health.
+    Status,
+    /// List sessions.
+    Sessions,
+    /// Send a prompt to a session and print the reply as it streams.
+    Send {
+        /// Session id or name.
+        session: String,
+        prompt: String,
+        /// Model for this message only.
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// List a session's milestones, newest first.
+    Milestones {
+        /// Session id or name.
+        session: String,
+        /// Number of milestones to list.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Restore a session's workspace to a milestone.
+    Restore {
+        /// Session id or name.
+        session: String,
+        /// Milestone oid, or a unique prefix of one.
+        oid: String,
+    },
+    /// Let sessions run in a directory and its subdirectories.
+    Trust {
+        /// Directory to trust; defaults to the current one.
+        path: Option<PathBuf>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let client = DaemonClient::new(&cli.socket_path);
+
+    let result = match cli.command {
+        Command::Status => status(&client, &cli.socket_path).await,
+        Command::Sessions => sessions(&client).await,
+        Command::Send {
+            session,
+            prompt,
+            model,
+        } => send(&client, &session, &prompt, model.as_deref()).await,
+        Command::Milestones { session, limit } => milestones(&client, &session, limit).await,
+        Command::Restore { session, oid } => restore(&client, &session, &oid).await,
+        Command::Trust { path } => trust(&client, path).await,
+    };
+    if let Err(e) = result {
+        eprintln!("mado: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// The session whose id is `query`, or else the only one named `query`.
+async fn find_session(client: &DaemonClient, query: &str) -> Result<Session, String> {
+    let sessions = client.list_sessions().await.map_err(|e| e.to_string())?;
+    if let Some(session) = sessions.iter().find(|s| s.id.as_str() == query) {
+        return Ok(session.clone());
+    }
+    let mut named = sessions.into_iter().filter(|s| s.name == query);
+    match (named.next(), named.next()) {
+        (Some(session), None) => Ok(session),
+        (Some(_), Some(_)) => Err(format!("Several sessions are named {:?}; use an id", query)),
+        (None, _) => Err(format!("No session {:?}", query)),
+    }
+}
+
+fn state_label(session: &Session) -> &'static str {
+    match (&session.status, session.activity) {
+        (SessionStatus::Terminated, _) => "terminated",
+        (_, SessionActivity::Streaming) => "streaming",
+        (_, SessionActivity::WaitingOnUser) => "waiting",
+        (_, SessionActivity::Idle) => "idle",
+        (_, SessionActivity::Exited) => "exited",
+    }
+}
+
+async fn status(client: &DaemonClient, socket_path: &std::path::Path) -> Result<(), String> {
+    let status = client
+        .health()
+        .await
+        .map_err(|e| format!("Daemon is not running at {}: {}", socket_path.display(), e))?;
+
+    println!("running: pid {}", status.pid);
+    println!("version: {} (protocol {})", status.version, status.protocol_version);
+    println!("uptime: {}s", status.uptime);
+    println!("sessions: {}", status.session_count);
+    Ok(())
+}
+
+async fn sessions(client: &DaemonClient) -> Result<(), String> {
+    let sessions = client.list_sessions().await.map_err(|e| e.to_string())?;
+    for session in sessions {
+        println!(
+            "{}  {:<24}  {:<10}  {:<8}  {}",
+            session.id,
+            session.name,
+            state_label(&session),
+            session.model,
+            session.working_dir.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+/// Send `prompt` and print the reply until the session goes idle. A slash
+/// command's result is printed instead.
+async fn send(client: &DaemonClient, query: &str, prompt: &str, model: Option<&str>) -> Result<(), String> {
+    let session = find_session(client, query).await?;
+    // Subscribe first so no part of the reply is missed.
+    let mut events = client
+        .subscribe_events(session.id.as_str())
+        .await
+        .map_err(|e| e.to_string())?;
+    let message_id = client
+        .send_message(session.id.as_str(), prompt, model, &[], &[])
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut stdout = std::io::stdout();
+    let mut failed = None;
+    while let Some(event) = events.next().await {
+        match event {
+            StreamEvent::TextDelta { text } => {
+                print!("{}", text);
+                let _ = stdout.flush();
+            }
+            StreamEvent::MessageComplete { message }
+                if message.id == message_id && message.role == MessageRole::System =>
+            {
+                println!("{}", message.content);
+                return Ok(());
+            }
+            StreamEvent::QueuePosition { position, .. } => {
+                eprintln!("Queued behind {} message(s)", position);
+            }
+            StreamEvent::InputRequested { prompt, options } => {
+                let answer = ask(prompt, options).await?;
+                client
+                    .send_response_input(session.id.as_str(), &answer)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            StreamEvent::Error { message, .. } => failed = Some(message),
+            StreamEvent::Idle => {
+                println!();
+                return match failed {
+                    Some(message) => Err(message),
+                    None => Ok(()),
+                };
+            }
+            _ => {}
+        }
+    }
+    Err("The daemon closed the stream".to_string())
+}
+
+/// Ask the user to answer a prompt from the Claude CLI on the terminal.
+async fn ask(prompt: String, options: Vec<String>) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        eprintln!();
+        eprintln!("{}", prompt);
+        if !options.is_empty() {
+            eprintln!("({})", options.join(" / "));
+        }
+        eprint!("> ");
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+        Ok(answer.trim_end_matches(['\r', '\n']).to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn milestones(client: &DaemonClient, query: &str, limit: usize) -> Result<(), String> {
+    let session = find_session(client, query).await?;
+    let milestones = client
+        .list_milestones(session.id.as_str(), limit)
+        .await
+        .map_err(|e| e.to_string())?;
+    for milestone in milestones {
+        println!(
+            "{}  {}  +{} -{}  {}",
+            &milestone.oid[..milestone.oid.len().min(8)],
+            milestone.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            milestone.insertions,
+            milestone.deletions,
+            milestone.message,
+        );
+    }
+    Ok(())
+}
+
+async fn restore(client: &DaemonClient, query: &str, oid: &str) -> Result<(), String> {
+    let session = find_session(client, query).await?;
+    let milestones = client
+        .list_milestones(session.id.as_str(), OID_SEARCH_LIMIT)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut matching = milestones.iter().filter(|m| m.oid.starts_with(oid));
+    let milestone = match (matching.next(), matching.next()) {
+        (Some(milestone), None) => milestone,
+        (Some(_), Some(_)) => return Err(format!("Several milestones start with {}", oid)),
+        (None, _) => return Err(format!("No milestone {} in session {}", oid, session.name)),
+    };
+
+    client
+        .restore_milestone(session.id.as_str(), &milestone.oid)
+        .await
+        .map_err(|e| e.to_string())?;
+    println!("Restored {} to {}", session.name, milestone.message);
+    Ok(())
+}
+
+async fn trust(client: &DaemonClient, path: Option<PathBuf>) -> Result<(), String> {
+    let path = match path {
+        Some(path) => path,
+        None => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+    let trusted = client
+        .trust_workspace(&path.to_string_lossy())
+        .await
+        .map_err(|e| e.to_string())?;
+    println!("Trusted {}", trusted);
+    Ok(())
+}
@@ -1,14 +1,67 @@
+use std::collections::BTreeMap;
+use std::error::Error as _;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper::Request;
-use hyper_util::rt::TokioIo;
-use tokio::net::UnixStream;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
+use tokio_rustls::rustls;
 use tracing;
 
-use crate::protocol::DaemonResponse;
-use crate::types::DaemonStatus;
+use crate::protocol::{
+    ApplyComparisonBody, ApplyReviewBody, CloneSessionBody, CompactBody, CompareBody, CreatePullRequestBody, CreateScheduleBody, CreateSessionBody, DaemonResponse, ErrorCode, MergeBranchBody, ResizeBody, RestoreMilestoneBody, ReviveSessionBody,
+    ResponseInputBody, RunCommandBody, SaveMilestoneBody, SendMessageBody, StageFileBody, StageFilesBody, StageHunkBody, WriteFileBody, PROTOCOL_VERSION,
+};
+use crate::session_socket::SessionSocket;
+use crate::sse::{EventSource, EventStream};
+use crate::transport::{self, ConnectError, Connector, DaemonEndpoint, DaemonStream};
+
+pub use crate::mock::{MockRequest, MockTransport};
+use crate::types::{ActivityEvent, CommandRun, Compaction, CompareEvent, Comparison, DaemonStatus, PullRequest, RunEvent, StreamEvent, VersionInfo};
+
+/// How long an idle pooled connection is kept.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a request may take, unless changed with `with_timeout`.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for liveness checks (`ping`, `health`, `version`), which a
+/// working daemon answers at once.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout for `git_push`, which waits on the remote.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Timeout for `clone_session`, which copies a whole workspace.
+const CLONE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Timeout for pull request calls, which wait on the forge's API.
+const FORGE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Timeout for `compact` and `suggest_memory`, which wait for the model to
+/// reply.
+const COMPACT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Extra attempts at a GET that failed to reach the daemon, waiting
+/// [`RETRY_BACKOFF`] before the first and twice as long before each next.
+const GET_RETRIES: u32 = 2;
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Timeouts in a row after which the daemon is considered unresponsive, and
+/// how long requests then fail fast before one is let through to check.
+const BREAKER_THRESHOLD: u32 = 3;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Idle connections kept for reuse.
+const POOL_MAX_IDLE: usize = 4;
+
+type HttpClient = Client<Connector, Full<Bytes>>;
 
 /// Errors that can occur when communicating with the daemon.
 #[derive(Debug, thiserror::Error)]
@@ -19,21 +72,60 @@ pub enum ClientError {
         source: std::io::Error,
     },
 
+    #[error("Failed to connect to daemon at {addr}: {source}")]
+    TcpConnectFailed {
+        addr: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid daemon URL '{0}' (expected unix://, tcp:// or tls://)")]
+    InvalidUrl(String),
+
+    #[error("TLS error: {0}")]
+    Tls(String),
+
     #[error("HTTP request failed: {0}")]
     HttpError(#[from] hyper::Error),
 
     #[error("HTTP client error: {0}")]
     HttpClientError(#[from] hyper_util::client::legacy::Error),
 
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+
     #[error("Failed to deserialize response: {0}")]
     DeserializeError(#[from] serde_json::Error),
 
     #[error("Daemon returned error: {0}")]
     DaemonError(String),
 
+    #[error("{0}")]
+    SessionNotFound(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("Daemon rejected the credentials")]
+    Unauthorized,
+
+    #[error("{0}")]
+    BudgetExceeded(String),
+
+    #[error("{0}")]
+    WorkspaceUntrusted(String),
+
     #[error("Unexpected response from daemon")]
     UnexpectedResponse,
 
+    #[error("Not a git repository: {0}")]
+    NotARepository(String),
+
     #[error("Socket not found at {0}")]
     SocketNotFound(PathBuf),
 
@@ -42,63 +134,350 @@ pub enum ClientError {
 
     #[error("Daemon did not start in time (socket not found after timeout)")]
     StartTimeout,
+
+    #[error(
+        "Incompatible daemon: version {daemon_version} speaks protocol v{daemon_protocol}, \
+         this client speaks v{client_protocol}"
+    )]
+    IncompatibleDaemon {
+        daemon_version: String,
+        daemon_protocol: u32,
+        client_protocol: u32,
+    },
+
+    #[error("Failed to stop daemon: {0}")]
+    StopFailed(String),
+
+    #[error("Daemon did not respond within {}s", .0.as_secs())]
+    Timeout(Duration),
+
+    #[error("Daemon is unresponsive")]
+    Unresponsive,
+}
+
+impl ClientError {
+    /// Map a daemon error response to a typed client error.
+    pub fn from_daemon(code: ErrorCode, message: String) -> Self {
+        match code {
+            ErrorCode::SessionNotFound => ClientError::SessionNotFound(message),
+            ErrorCode::NotFound => ClientError::NotFound(message),
+            ErrorCode::Conflict => ClientError::Conflict(message),
+            ErrorCode::Validation => ClientError::Validation(message),
+            ErrorCode::NotARepository => ClientError::NotARepository(message),
+            ErrorCode::Unauthorized => ClientError::Unauthorized,
+            ErrorCode::BudgetExceeded => ClientError::BudgetExceeded(message),
+            ErrorCode::WorkspaceUntrusted => ClientError::WorkspaceUntrusted(message),
+            ErrorCode::Internal => ClientError::DaemonError(message),
+        }
+    }
+
+    /// Whether the request never reached the daemon, so it can be sent
+    /// again once the daemon is back without running twice. A timeout or a
+    /// connection dropped mid-request doesn't count: the daemon may have
+    /// acted on it.
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            ClientError::ConnectionFailed { .. }
+            | ClientError::TcpConnectFailed { .. }
+            | ClientError::SocketNotFound(_)
+            | ClientError::Unresponsive => true,
+            ClientError::HttpClientError(e) => e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether the request never reached the daemon in a way that may
+    /// succeed if tried again (e.g. mid-restart), as opposed to a missing
+    /// socket, a timeout or an answer.
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::ConnectionFailed { source, .. } => source.kind() != std::io::ErrorKind::NotFound,
+            ClientError::TcpConnectFailed { .. } | ClientError::HttpClientError(_) | ClientError::HttpError(_) => true,
+            _ => false,
+        }
+    }
 }
 
-/// Client for communicating with the mado daemon over a Unix domain socket.
+/// Client for communicating with the mado daemon over its Unix domain socket
+/// or, for a remote daemon, its TCP listener.
+///
+/// Clones share one connection pool.
 #[derive(Debug, Clone)]
 pub struct DaemonClient {
-    socket_path: PathBuf,
+    endpoint: DaemonEndpoint,
+    /// Bearer token sent with every request.
+    token: Option<String>,
+    /// TLS config for `tls://` endpoints; webpki roots when unset.
+    tls: Option<Arc<rustls::ClientConfig>>,
+    http: HttpClient,
+    timeout: Duration,
+    breaker: Arc<Breaker>,
 }
 
 impl DaemonClient {
     /// Create a new client targeting the given socket path.
+    ///
+    /// The API token is read from the `token` file next to the socket, if the
+    /// daemon has created one.
     pub fn new(socket_path: impl Into<PathBuf>) -> Self {
+        let socket_path = socket_path.into();
+        let token = std::fs::read_to_string(socket_path.with_file_name(TOKEN_FILE_NAME))
+            .ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+
+        Self {
+            token,
+            ..Self::with_endpoint(DaemonEndpoint::Unix(socket_path))
+        }
+    }
+
+    /// Create a client from a URL: `unix:///path`, `tcp://HOST:PORT` or `tls://HOST:PORT`.
+    pub fn from_url(url: &str) -> Result<Self, ClientError> {
+        Ok(Self::with_endpoint(DaemonEndpoint::parse(url)?))
+    }
+
+    /// Create a client for `endpoint`, without a token.
+    pub fn with_endpoint(endpoint: DaemonEndpoint) -> Self {
         Self {
-            socket_path: socket_path.into(),
+            http: http_client(&endpoint, None),
+            endpoint,
+            token: None,
+            tls: None,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            breaker: Arc::default(),
         }
     }
 
-    /// Get the socket path this client connects to.
-    pub fn socket_path(&self) -> &Path {
-        &self.socket_path
+    /// Send `Authorization: Bearer <token>` with every request.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Give up on a request after `timeout` (default
+    /// [`DEFAULT_REQUEST_TIMEOUT`]) with `ClientError::Timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Additionally trust the PEM-encoded CA certificate(s) for TLS, e.g. a
+    /// self-signed certificate on a dev server.
+    pub fn with_ca_cert_pem(mut self, pem: &[u8]) -> Result<Self, ClientError> {
+        let tls = transport::tls_config(Some(pem))?;
+        self.http = http_client(&self.endpoint, Some(tls.clone()));
+        self.tls = Some(tls);
+        Ok(self)
+    }
+
+    /// Get the endpoint this client connects to.
+    pub fn endpoint(&self) -> &DaemonEndpoint {
+        &self.endpoint
+    }
+
+    /// Get the socket path this client connects to, if it is a local client.
+    pub fn socket_path(&self) -> Option<&Path> {
+        match &self.endpoint {
+            DaemonEndpoint::Unix(path) => Some(path),
+            DaemonEndpoint::Tcp { .. } | DaemonEndpoint::Memory(_) => None,
+        }
     }
 
-    /// Check if the daemon socket file exists.
+    /// Check if the daemon socket file exists. Always true for TCP and
+    /// in-memory endpoints.
     pub fn socket_exists(&self) -> bool {
-        self.socket_path.exists()
+        self.socket_path().is_none_or(|path| path.exists())
     }
 
-    /// Attempt to connect and verify the daemon is alive.
+    /// Attempt to connect and verify the daemon is alive and speaks our
+    /// protocol version.
     pub async fn connect(&self) -> Result<(), ClientError> {
-        if !self.socket_exists() {
-            return Err(ClientError::SocketNotFound(self.socket_path.clone()));
+        if let Some(path) = self.socket_path()
+            && !path.exists()
+        {
+            return Err(ClientError::SocketNotFound(path.to_path_buf()));
         }
 
-        // Try a quick ping to verify the daemon is responsive.
-        self.ping().await?;
+        let daemon = self.version().await?;
+        if daemon.protocol_version != PROTOCOL_VERSION {
+            return Err(ClientError::IncompatibleDaemon {
+                daemon_version: daemon.version,
+                daemon_protocol: daemon.protocol_version,
+                client_protocol: PROTOCOL_VERSION,
+            });
+        }
         Ok(())
     }
 
+    /// Get the daemon's build and protocol version.
+    pub async fn version(&self) -> Result<VersionInfo, ClientError> {
+        let body = self.probe("/version").await?;
+
+        match serde_json::from_slice(&body) {
+            Ok(DaemonResponse::Version { version }) => Ok(version),
+            Ok(DaemonResponse::Error { code, message }) => {
+                Err(ClientError::from_daemon(code, message))
+            }
+            Ok(_) => Err(ClientError::UnexpectedResponse),
+            // Daemons predating `/version` answer with an empty 404; their
+            // health status reports protocol 0.
+            Err(_) => {
+                let status = self.health().await?;
+                Ok(VersionInfo {
+                    version: status.version,
+                    protocol_version: status.protocol_version,
+                })
+            }
+        }
+    }
+
     /// Send a health check request and return the daemon status.
     pub async fn health(&self) -> Result<DaemonStatus, ClientError> {
-        let body = self.get("/health").await?;
+        let body = self.probe("/health").await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
 
         match response {
             DaemonResponse::Health { status } => Ok(status),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
     /// Send a ping request to verify liveness.
     pub async fn ping(&self) -> Result<(), ClientError> {
-        let body = self.get("/ping").await?;
+        let body = self.probe("/ping").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Restart the daemon in place onto its (possibly upgraded) binary, keeping
+    /// live PTY sessions. Returns the new daemon's pid; this client keeps
+    /// working once the old process has exited.
+    pub async fn graceful_restart(&self) -> Result<u32, ClientError> {
+        let body = self.post("/admin/restart", &serde_json::json!({})).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::Restarting { pid } => Ok(pid),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Delete the daemon's data in `scope`.
+    pub async fn purge(&self, scope: crate::types::PurgeScope) -> Result<crate::types::PurgeReport, ClientError> {
+        let body = self
+            .post(&format!("/admin/purge?scope={}", scope.as_str()), &serde_json::json!({}))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::Purged { report } => Ok(report),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Recent lines of the daemon log, optionally only those at `level`
+    /// ("error", "warn", ...) or more severe.
+    pub async fn daemon_logs(
+        &self,
+        lines: Option<usize>,
+        level: Option<&str>,
+    ) -> Result<Vec<String>, ClientError> {
+        let mut path = "/logs".to_string();
+        let mut params = Vec::new();
+        if let Some(n) = lines {
+            params.push(format!("lines={}", n));
+        }
+        if let Some(level) = level {
+            params.push(format!("level={}", level));
+        }
+        if !params.is_empty() {
+            path.push('?');
+            path.push_str(&params.join("&"));
+        }
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::Logs { lines } => Ok(lines),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The Claude CLI the daemon spawns, and whether it works.
+    pub async fn cli_info(&self) -> Result<crate::types::CliInfo, ClientError> {
+        let body = self.get("/cli/info").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::CliInfo { info } => Ok(info),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Claude CLI sessions recorded for `cwd`, newest first, at most `limit`.
+    pub async fn list_cli_sessions(
+        &self,
+        cwd: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<crate::types::CliSessionInfo>, ClientError> {
+        let mut path = format!("/cli/sessions?cwd={}", encode_query_value(cwd));
+        if let Some(limit) = limit {
+            path.push_str(&format!("&limit={}", limit));
+        }
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::CliSessions { sessions } => Ok(sessions),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Coding agents with chat history for `cwd`.
+    pub async fn history_sources(&self, cwd: &str) -> Result<Vec<crate::types::HistorySource>, ClientError> {
+        let path = format!("/history/sources?cwd={}", encode_query_value(cwd));
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::HistorySources { sources } => Ok(sources),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Crash reports left by daemon panics, newest first.
+    pub async fn crash_reports(&self) -> Result<Vec<crate::types::CrashReport>, ClientError> {
+        let body = self.get("/crashes").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+
+        match response {
+            DaemonResponse::CrashReports { reports } => Ok(reports),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Delete a crash report once the user has seen it.
+    pub async fn delete_crash_report(&self, id: &str) -> Result<(), ClientError> {
+        let body = self.delete(&format!("/crashes/{}", id)).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
 
         match response {
             DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
@@ -114,6 +493,9 @@ impl DaemonClient {
     /// 2. If connection fails, check PID file.
     /// 3. If PID is dead or missing, spawn the daemon binary.
     /// 4. Wait for socket to appear and connect.
+    ///
+    /// A running daemon with a different protocol version is left alone and
+    /// reported as `IncompatibleDaemon`; see `restart_daemon`.
     pub async fn ensure_daemon_running(
         socket_path: &Path,
         daemon_binary: &Path,
@@ -122,6 +504,7 @@ impl DaemonClient {
 
         // Try to connect to existing daemon.
         if client.is_alive().await {
+            client.connect().await?;
             tracing::info!("Connected to existing daemon");
             return Ok(client);
         }
@@ -130,22 +513,21 @@ impl DaemonClient {
 
         // Clean up stale PID file if the process is dead.
         let pid_path = socket_path.with_file_name("mado.pid");
-        if pid_path.exists() {
-            if let Ok(contents) = std::fs::read_to_string(&pid_path) {
-                if let Ok(pid) = contents.trim().parse::<u32>() {
-                    let alive = unsafe { libc::kill(pid as i32, 0) == 0 };
-                    if !alive {
-                        tracing::warn!("Cleaning up stale PID file for dead process {}", pid);
-                        let _ = std::fs::remove_file(&pid_path);
-                        let _ = std::fs::remove_file(socket_path);
-                    } else {
-                        // Process is alive but socket is unresponsive -- something is wrong.
-                        return Err(ClientError::StartFailed(format!(
-                            "Daemon process {} is alive but socket is unresponsive",
-                            pid
-                        )));
-                    }
-                }
+        if pid_path.exists()
+            && let Ok(contents) = std::fs::read_to_string(&pid_path)
+            && let Ok(pid) = contents.trim().parse::<u32>()
+        {
+            let alive = unsafe { libc::kill(pid as i32, 0) == 0 };
+            if !alive {
+                tracing::warn!("Cleaning up stale PID file for dead process {}", pid);
+                let _ = std::fs::remove_file(&pid_path);
+                let _ = std::fs::remove_file(socket_path);
+            } else {
+                // Process is alive but socket is unresponsive -- something is wrong.
+                return Err(ClientError::StartFailed(format!(
+                    "Daemon process {} is alive but socket is unresponsive",
+                    pid
+                )));
             }
         }
 
@@ -169,13 +551,23 @@ impl DaemonClient {
             }
         }
 
-        // Wait for the socket to appear and become responsive.
-        let timeout = std::time::Duration::from_secs(5);
+        Self::wait_for_daemon(socket_path, std::time::Duration::from_secs(5)).await
+    }
+
+    /// Wait for a daemon that is starting (spawned, or started by a service
+    /// manager) to answer on `socket_path`, and connect to it.
+    pub async fn wait_for_daemon(
+        socket_path: &Path,
+        timeout: std::time::Duration,
+    ) -> Result<Self, ClientError> {
         let poll_interval = std::time::Duration::from_millis(100);
         let start = std::time::Instant::now();
 
         while start.elapsed() < timeout {
+            // Re-create the client so it picks up a token written by the new daemon.
+            let client = Self::new(socket_path);
             if client.is_alive().await {
+                client.connect().await?;
                 tracing::info!("Connected to newly started daemon");
                 return Ok(client);
             }
@@ -185,13 +577,60 @@ impl DaemonClient {
         Err(ClientError::StartTimeout)
     }
 
+    /// Stop the running daemon (SIGTERM via its PID file) and start
+    /// `daemon_binary` in its place, e.g. after an upgrade left an
+    /// incompatible daemon running. Running sessions are terminated.
+    pub async fn restart_daemon(
+        socket_path: &Path,
+        daemon_binary: &Path,
+    ) -> Result<Self, ClientError> {
+        Self::stop_daemon(&socket_path.with_file_name("mado.pid")).await?;
+        Self::ensure_daemon_running(socket_path, daemon_binary).await
+    }
+
+    /// Stop the daemon whose PID is in `pid_path` (SIGTERM) and wait for it
+    /// to save state and exit. Returns its pid, or `None` if none was running.
+    pub async fn stop_daemon(pid_path: &Path) -> Result<Option<u32>, ClientError> {
+        let Ok(contents) = std::fs::read_to_string(pid_path) else {
+            return Ok(None);
+        };
+        let Ok(pid) = contents.trim().parse::<u32>() else {
+            return Ok(None);
+        };
+
+        tracing::info!("Stopping daemon process {}", pid);
+        if unsafe { libc::kill(pid as i32, libc::SIGTERM) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ESRCH) {
+                return Ok(None);
+            }
+            return Err(ClientError::StopFailed(format!(
+                "Failed to signal process {}: {}",
+                pid, err
+            )));
+        }
+
+        let timeout = std::time::Duration::from_secs(10);
+        let start = std::time::Instant::now();
+        while unsafe { libc::kill(pid as i32, 0) == 0 } {
+            if start.elapsed() >= timeout {
+                return Err(ClientError::StopFailed(format!(
+                    "Daemon process {} did not exit in time",
+                    pid
+                )));
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        Ok(Some(pid))
+    }
+
     /// List all sessions.
     pub async fn list_sessions(&self) -> Result<Vec<crate::types::Session>, ClientError> {
         let body = self.get("/sessions").await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
             DaemonResponse::Sessions { sessions } => Ok(sessions),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
@@ -204,21 +643,31 @@ impl DaemonClient {
         rows: u16,
         cols: u16,
         cwd: Option<&str>,
+        system_prompt: Option<&str>,
     ) -> Result<crate::types::Session, ClientError> {
-        let mut body_json = serde_json::json!({
-            "name": name,
-            "model": model,
-            "rows": rows,
-            "cols": cols,
-        });
-        if let Some(dir) = cwd {
-            body_json["cwd"] = serde_json::json!(dir);
-        }
-        let body = self.post("/sessions", &body_json).await?;
+        self.create_session_with(&CreateSessionBody {
+            name: name.to_string(),
+            model: Some(model.to_string()),
+            rows: Some(rows),
+            cols: Some(cols),
+            cwd: cwd.map(str::to_string),
+            system_prompt: system_prompt.map(str::to_string),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Create a session from a full request body, e.g. one running a custom
+    /// `command` instead of the Claude CLI.
+    pub async fn create_session_with(
+        &self,
+        request: &CreateSessionBody,
+    ) -> Result<crate::types::Session, ClientError> {
+        let body = self.post("/sessions", request).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
             DaemonResponse::SessionCreated { session } => Ok(session),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
@@ -229,441 +678,1600 @@ impl DaemonClient {
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
             DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Write input to a session's PTY.
-    pub async fn write_input(&self, session_id: &str, data: &[u8]) -> Result<(), ClientError> {
-        use base64::Engine;
-        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
-        let body_json = serde_json::json!({ "data": encoded });
+    /// Change a session's name, model, working directory, system prompt,
+    /// permission mode or metadata; fields left `None` in `update` are kept.
+    pub async fn update_session(
+        &self,
+        id: &str,
+        update: &crate::types::SessionUpdate,
+    ) -> Result<crate::types::Session, ClientError> {
         let body = self
-            .post(&format!("/sessions/{}/input", session_id), &body_json)
+            .patch(&format!("/sessions/{}", id), update)
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionUpdated { session } => Ok(session),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Resize a session's PTY.
-    pub async fn resize_session(
+    /// Get a session's settings.
+    pub async fn session_settings(
         &self,
-        session_id: &str,
-        rows: u16,
-        cols: u16,
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "rows": rows, "cols": cols });
-        let body = self
-            .post(&format!("/sessions/{}/resize", session_id), &body_json)
-            .await?;
+        id: &str,
+    ) -> Result<crate::types::SessionSettings, ClientError> {
+        let body = self.get(&format!("/sessions/{}/settings", id)).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionSettings { settings } => Ok(settings),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Save a milestone for a session.
-    pub async fn save_milestone(
+    /// The settings that apply to a session, from config.json, the
+    /// project's `.mado.toml` and the session, with where each comes from.
+    pub async fn effective_config(&self, id: &str) -> Result<crate::types::EffectiveConfig, ClientError> {
+        let body = self.get(&format!("/sessions/{}/config", id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::EffectiveConfig { config } => Ok(config),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Replace a session's settings.
+    pub async fn update_session_settings(
         &self,
-        session_id: &str,
-        message: &str,
-    ) -> Result<crate::types::Milestone, ClientError> {
-        let body_json = serde_json::json!({ "message": message });
+        id: &str,
+        settings: &crate::types::SessionSettings,
+    ) -> Result<crate::types::SessionSettings, ClientError> {
         let body = self
-            .post(&format!("/sessions/{}/save", session_id), &body_json)
+            .put(&format!("/sessions/{}/settings", id), &serde_json::to_value(settings)?)
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::MilestoneSaved { milestone } => Ok(milestone),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionSettings { settings } => Ok(settings),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// List milestones for a session.
-    pub async fn list_milestones(
+    /// Respawn the process of a session left without one (e.g. after a daemon
+    /// restart), resuming its Claude conversation.
+    pub async fn revive_session(
         &self,
-        session_id: &str,
-        limit: usize,
-    ) -> Result<Vec<crate::types::Milestone>, ClientError> {
-        let body = self
-            .get(&format!("/sessions/{}/milestones?limit={}", session_id, limit))
-            .await?;
+        id: &str,
+        rows: u16,
+        cols: u16,
+    ) -> Result<crate::types::Session, ClientError> {
+        let request = ReviveSessionBody {
+            rows: Some(rows),
+            cols: Some(cols),
+        };
+        let body = self.post(&format!("/sessions/{}/revive", id), &request).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Milestones { milestones } => Ok(milestones),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionRevived { session } => Ok(session),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get diff between two milestones.
-    pub async fn diff_milestones(
+    /// Kill a session's process, if still running, and spawn a fresh one
+    /// resuming its Claude conversation.
+    pub async fn restart_session(
         &self,
-        session_id: &str,
-        from_oid: &str,
-        to_oid: &str,
-    ) -> Result<crate::types::DiffSummary, ClientError> {
-        let body = self
-            .get(&format!(
-                "/sessions/{}/diff?from={}&to={}",
-                session_id, from_oid, to_oid
-            ))
-            .await?;
+        id: &str,
+        rows: u16,
+        cols: u16,
+    ) -> Result<crate::types::Session, ClientError> {
+        let request = ReviveSessionBody {
+            rows: Some(rows),
+            cols: Some(cols),
+        };
+        let body = self.post(&format!("/sessions/{}/restart", id), &request).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::DiffResult { diff } => Ok(diff),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionRevived { session } => Ok(session),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get current workspace changes (uncommitted modifications).
-    pub async fn workspace_changes(
+    /// Start a new session continuing `id`'s conversation from
+    /// `at_message_id` (from its latest message if `None`).
+    pub async fn fork_session(
         &self,
-        session_id: &str,
-    ) -> Result<crate::types::DiffSummary, ClientError> {
-        let body = self
-            .get(&format!("/sessions/{}/changes", session_id))
-            .await?;
+        id: &str,
+        at_message_id: Option<&str>,
+    ) -> Result<crate::types::Session, ClientError> {
+        let mut path = format!("/sessions/{}/fork", id);
+        if let Some(message_id) = at_message_id {
+            path.push_str(&format!("?at_message_id={}", encode_query_value(message_id)));
+        }
+        let body = self.post(&path, &serde_json::json!({})).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::WorkspaceChanges { changes } => Ok(changes),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionCreated { session } => Ok(session),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Restore to a milestone.
-    pub async fn restore_milestone(
+    /// Clone a session's workspace into a new directory and start a session
+    /// there with the same settings.
+    pub async fn clone_session(
         &self,
-        session_id: &str,
-        oid: &str,
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "oid": oid });
+        id: &str,
+        body: &CloneSessionBody,
+    ) -> Result<crate::types::Session, ClientError> {
         let body = self
-            .post(&format!("/sessions/{}/restore", session_id), &body_json)
+            .send_json("POST", &format!("/sessions/{}/clone", id), body, CLONE_TIMEOUT)
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionCreated { session } => Ok(session),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    // ── Git staging methods ──
-
-    /// Get git staging status (staged + unstaged files).
-    pub async fn git_status(
-        &self,
-        session_id: &str,
-    ) -> Result<crate::types::GitStatus, ClientError> {
+    /// Archive a session's conversation and milestones on the daemon and
+    /// remove the session. Returns the archive's path.
+    pub async fn archive_session(&self, id: &str) -> Result<String, ClientError> {
         let body = self
-            .get(&format!("/sessions/{}/git/status", session_id))
+            .post(&format!("/sessions/{}/archive", id), &serde_json::json!({}))
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::GitStatusResult { status } => Ok(status),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::SessionArchived { path } => Ok(path),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get unified diff content for a single file.
+    /// Dead sessions the daemon's retention policy would remove on its next run.
+    pub async fn retention_plan(
+        &self,
+    ) -> Result<Vec<crate::types::RetentionCandidate>, ClientError> {
+        let body = self.get("/retention").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::RetentionPlan { candidates } => Ok(candidates),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Write input to a session's PTY.
+    ///
+    /// Bytes are sent as a raw `application/octet-stream` body, skipping the
+    /// base64 JSON encoding used by the legacy `/input` route.
+    pub async fn write_input(&self, session_id: &str, data: &[u8]) -> Result<(), ClientError> {
+        self.send_input(&format!("/sessions/{}/input/raw", session_id), data)
+            .await
+    }
+
+    /// Paste text into a session's PTY: the daemon brackets it with
+    /// bracketed-paste sequences and writes it at a pace the program can
+    /// keep up with.
+    pub async fn paste_input(&self, session_id: &str, data: &[u8]) -> Result<(), ClientError> {
+        self.send_input(&format!("/sessions/{}/input/raw?paste=true", session_id), data)
+            .await
+    }
+
+    async fn send_input(&self, path: &str, data: &[u8]) -> Result<(), ClientError> {
+        let body = self.post_raw(path, Bytes::copy_from_slice(data)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Resize a session's PTY.
+    pub async fn resize_session(
+        &self,
+        session_id: &str,
+        rows: u16,
+        cols: u16,
+    ) -> Result<(), ClientError> {
+        let body = self
+            .post(&format!("/sessions/{}/resize", session_id), &ResizeBody { rows, cols })
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get a session's recent PTY output and the stream offset at its end.
+    /// `subscribe_output` replays the same bytes before live output.
+    pub async fn get_scrollback(&self, session_id: &str) -> Result<(Vec<u8>, u64), ClientError> {
+        use base64::Engine;
+
+        let body = self.get(&format!("/sessions/{}/scrollback", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Scrollback { data, offset } => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|_| ClientError::UnexpectedResponse)?;
+                Ok((data, offset))
+            }
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Download a session's terminal recording (asciicast v2).
+    pub async fn get_recording(&self, session_id: &str) -> Result<String, ClientError> {
+        let body = self.get(&format!("/sessions/{}/recording", session_id)).await?;
+        // Errors come back as JSON; a recording is several lines of it.
+        if let Ok(DaemonResponse::Error { code, message }) = serde_json::from_slice(&body) {
+            return Err(ClientError::from_daemon(code, message));
+        }
+        String::from_utf8(body.to_vec()).map_err(|_| ClientError::UnexpectedResponse)
+    }
+
+    /// Save a milestone for a session.
+    pub async fn save_milestone(
+        &self,
+        session_id: &str,
+        message: &str,
+    ) -> Result<crate::types::Milestone, ClientError> {
+        let request = SaveMilestoneBody {
+            message: message.to_string(),
+        };
+        let body = self
+            .post(&format!("/sessions/{}/save", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MilestoneSaved { milestone } => Ok(milestone),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List milestones for a session.
+    pub async fn list_milestones(
+        &self,
+        session_id: &str,
+        limit: usize,
+    ) -> Result<Vec<crate::types::Milestone>, ClientError> {
+        let body = self
+            .get(&format!("/sessions/{}/milestones?limit={}", session_id, limit))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Milestones { milestones } => Ok(milestones),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get diff between two milestones.
+    pub async fn diff_milestones(
+        &self,
+        session_id: &str,
+        from_oid: &str,
+        to_oid: &str,
+    ) -> Result<crate::types::DiffSummary, ClientError> {
+        let body = self
+            .get(&format!(
+                "/sessions/{}/diff?from={}&to={}",
+                session_id, from_oid, to_oid
+            ))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::DiffResult { diff } => Ok(diff),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get current workspace changes (uncommitted modifications).
+    pub async fn workspace_changes(
+        &self,
+        session_id: &str,
+    ) -> Result<crate::types::DiffSummary, ClientError> {
+        let body = self
+            .get(&format!("/sessions/{}/changes", session_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::WorkspaceChanges { changes } => Ok(changes),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Restore to a milestone.
+    pub async fn restore_milestone(
+        &self,
+        session_id: &str,
+        oid: &str,
+    ) -> Result<(), ClientError> {
+        let request = RestoreMilestoneBody { oid: oid.to_string() };
+        let body = self
+            .post(&format!("/sessions/{}/restore", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Save a milestone and a copy of the conversation together.
+    pub async fn save_snapshot(
+        &self,
+        session_id: &str,
+        message: &str,
+    ) -> Result<crate::types::Snapshot, ClientError> {
+        let request = SaveMilestoneBody {
+            message: message.to_string(),
+        };
+        let body = self
+            .post(&format!("/sessions/{}/snapshot", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::SnapshotSaved { snapshot } => Ok(snapshot),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List a session's snapshots, newest first.
+    pub async fn list_snapshots(&self, session_id: &str) -> Result<Vec<crate::types::Snapshot>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/snapshots", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Snapshots { snapshots } => Ok(snapshots),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Files changed since the turn of `since_message_id` started (the
+    /// latest turn if `None`), with their diffs.
+    pub async fn review(
+        &self,
+        session_id: &str,
+        since_message_id: Option<&str>,
+    ) -> Result<crate::types::Review, ClientError> {
+        let mut path = format!("/sessions/{}/review", session_id);
+        if let Some(message_id) = since_message_id {
+            path.push_str(&format!("?since_message_id={}", encode_query_value(message_id)));
+        }
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Review { review } => Ok(review),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Keep or revert files of a review. Returns the review afterwards.
+    pub async fn apply_review(
+        &self,
+        session_id: &str,
+        body: &ApplyReviewBody,
+    ) -> Result<crate::types::Review, ClientError> {
+        let body = self
+            .post(&format!("/sessions/{}/review/apply", session_id), body)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Review { review } => Ok(review),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Undo the file changes of the turn of `message_id`, keeping later
+    /// ones. Returns the files touched.
+    pub async fn revert_turn_changes(&self, session_id: &str, message_id: &str) -> Result<Vec<String>, ClientError> {
+        let body = self
+            .post(
+                &format!(
+                    "/sessions/{}/messages/{}/revert-changes",
+                    session_id,
+                    encode_query_value(message_id)
+                ),
+                &serde_json::json!({}),
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::TurnReverted { files } => Ok(files),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The commands in the session's `.mado.toml`, by name.
+    pub async fn project_commands(&self, session_id: &str) -> Result<BTreeMap<String, String>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/commands", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::ProjectCommands { commands } => Ok(commands),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Start one of the project's commands; follow it with `follow_run`.
+    pub async fn run_command(&self, session_id: &str, body: &RunCommandBody) -> Result<CommandRun, ClientError> {
+        let body = self.post(&format!("/sessions/{}/run", session_id), body).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::CommandRunStarted { run } => Ok(run),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Ask two or three models the same question in read-only forks of the
+    /// conversation. Follow their answers with [`Self::follow_comparison`].
+    pub async fn compare(&self, session_id: &str, body: &CompareBody) -> Result<Comparison, ClientError> {
+        let body = self.post(&format!("/sessions/{}/messages/compare", session_id), body).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::ComparisonStarted { comparison } => Ok(comparison),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The session's comparisons, newest first.
+    pub async fn list_comparisons(&self, session_id: &str) -> Result<Vec<Comparison>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/comparisons", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Comparisons { comparisons } => Ok(comparisons),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Replace the older messages of a conversation with a summary.
+    pub async fn compact(&self, session_id: &str, body: &CompactBody) -> Result<Compaction, ClientError> {
+        let body = self
+            .send_json(
+                "POST",
+                &format!("/sessions/{}/compact", session_id),
+                body,
+                COMPACT_TIMEOUT,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Compacted { compaction } => Ok(compaction),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Add a variant of a finished comparison to the conversation.
+    pub async fn apply_comparison(
+        &self,
+        session_id: &str,
+        comparison_id: &str,
+        variant_id: &str,
+    ) -> Result<Comparison, ClientError> {
+        let body = self
+            .post(
+                &format!(
+                    "/sessions/{}/comparisons/{}/apply",
+                    session_id,
+                    encode_query_value(comparison_id)
+                ),
+                &ApplyComparisonBody {
+                    variant_id: variant_id.to_string(),
+                },
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::ComparisonApplied { comparison } => Ok(comparison),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The session's command runs, newest first.
+    pub async fn list_runs(&self, session_id: &str) -> Result<Vec<CommandRun>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/runs", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::CommandRuns { runs } => Ok(runs),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Restore a snapshot's workspace and conversation.
+    pub async fn restore_snapshot(&self, session_id: &str, snapshot_id: &str) -> Result<(), ClientError> {
+        let body = self
+            .post(
+                &format!(
+                    "/sessions/{}/snapshot/{}/restore",
+                    session_id,
+                    encode_query_value(snapshot_id)
+                ),
+                &serde_json::json!({}),
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    // ── Git staging methods ──
+
+    /// Get git staging status (staged + unstaged files).
+    pub async fn git_status(
+        &self,
+        session_id: &str,
+    ) -> Result<crate::types::GitStatus, ClientError> {
+        let body = self
+            .get(&format!("/sessions/{}/git/status", session_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitStatusResult { status } => Ok(status),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get unified diff content for a single file.
     pub async fn git_file_diff(
         &self,
         session_id: &str,
-        file_path: &str,
-        staged: bool,
+        file_path: &str,
+        staged: bool,
+    ) -> Result<String, ClientError> {
+        let body = self
+            .get(&format!(
+                "/sessions/{}/git/diff?file_path={}&staged={}",
+                session_id, file_path, staged
+            ))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::FileDiffContent { diff } => Ok(diff),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Stage a single file.
+    pub async fn git_stage_file(
+        &self,
+        session_id: &str,
+        file_path: &str,
+    ) -> Result<(), ClientError> {
+        let request = StageFileBody {
+            file_path: file_path.to_string(),
+        };
+        let body = self
+            .post(&format!("/sessions/{}/git/stage", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Unstage a single file.
+    pub async fn git_unstage_file(
+        &self,
+        session_id: &str,
+        file_path: &str,
+    ) -> Result<(), ClientError> {
+        let request = StageFileBody {
+            file_path: file_path.to_string(),
+        };
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/unstage", session_id),
+                &request,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Stage multiple files in a single batch operation.
+    pub async fn git_stage_files(
+        &self,
+        session_id: &str,
+        file_paths: &[String],
+    ) -> Result<(), ClientError> {
+        let request = StageFilesBody {
+            file_paths: file_paths.to_vec(),
+        };
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/stage-files", session_id),
+                &request,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Unstage multiple files in a single batch operation.
+    pub async fn git_unstage_files(
+        &self,
+        session_id: &str,
+        file_paths: &[String],
+    ) -> Result<(), ClientError> {
+        let request = StageFilesBody {
+            file_paths: file_paths.to_vec(),
+        };
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/unstage-files", session_id),
+                &request,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Stage a single hunk from a file.
+    pub async fn git_stage_hunk(
+        &self,
+        session_id: &str,
+        file_path: &str,
+        hunk_index: usize,
+    ) -> Result<(), ClientError> {
+        let request = StageHunkBody {
+            file_path: file_path.to_string(),
+            hunk_index,
+        };
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/stage-hunk", session_id),
+                &request,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Commit staged files with a message.
+    pub async fn git_commit(
+        &self,
+        session_id: &str,
+        message: &str,
+    ) -> Result<String, ClientError> {
+        let body_json = serde_json::json!({ "message": message });
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/commit", session_id),
+                &body_json,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitCommitResult { oid } => Ok(oid),
+            DaemonResponse::Pong => Ok(String::new()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get git commit log.
+    pub async fn git_log(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<crate::types::GitLogEntry>, ClientError> {
+        let url = match limit {
+            Some(n) => format!("/sessions/{}/git/log?limit={}", session_id, n),
+            None => format!("/sessions/{}/git/log", session_id),
+        };
+        let body = self.get(&url).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitLogResult { entries } => Ok(entries),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get branch info (name + remote existence).
+    pub async fn git_branch_info(
+        &self,
+        session_id: &str,
+    ) -> Result<crate::types::BranchInfo, ClientError> {
+        let body = self
+            .get(&format!("/sessions/{}/git/branch-info", session_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitBranchInfo { info } => Ok(info),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Initialize a git repository in a session's working directory.
+    ///
+    /// Returns the repository root. A no-op if the directory is already inside a repo.
+    pub async fn git_init(&self, session_id: &str) -> Result<String, ClientError> {
+        let body = self
+            .post(
+                &format!("/sessions/{}/git/init", session_id),
+                &serde_json::json!({}),
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitInitialized { repo_root } => Ok(repo_root),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List the roots of all git repositories in a session's workspace.
+    pub async fn git_repos(&self, session_id: &str) -> Result<Vec<String>, ClientError> {
+        let body = self
+            .get(&format!("/sessions/{}/git/repos", session_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitRepos { repos } => Ok(repos),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Push current branch to origin.
+    pub async fn git_push(
+        &self,
+        session_id: &str,
+    ) -> Result<(), ClientError> {
+        let body = self
+            .send_json(
+                "POST",
+                &format!("/sessions/{}/git/push", session_id),
+                &serde_json::json!({}),
+                PUSH_TIMEOUT,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::GitPushResult => Ok(()),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Merge the session's own branch back. Returns the updated session and
+    /// the commit the target branch now points to.
+    pub async fn merge_branch(
+        &self,
+        session_id: &str,
+        body: &MergeBranchBody,
+    ) -> Result<(crate::types::Session, String), ClientError> {
+        let body = self
+            .send_json(
+                "POST",
+                &format!("/sessions/{}/git/merge", session_id),
+                body,
+                DEFAULT_REQUEST_TIMEOUT,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::BranchMerged { session, commit } => Ok((session, commit)),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Open a pull request for the session's pushed branch.
+    pub async fn create_pull_request(
+        &self,
+        session_id: &str,
+        body: &CreatePullRequestBody,
+    ) -> Result<PullRequest, ClientError> {
+        let body = self
+            .send_json(
+                "POST",
+                &format!("/sessions/{}/git/pull-request", session_id),
+                body,
+                FORGE_TIMEOUT,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::PullRequestCreated { pull_request } => Ok(pull_request),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Pull requests from the session's current branch, newest first.
+    pub async fn list_pull_requests(&self, session_id: &str) -> Result<Vec<PullRequest>, ClientError> {
+        let body = self
+            .send(
+                "GET",
+                &format!("/sessions/{}/git/pull-requests", session_id),
+                None,
+                Bytes::new(),
+                FORGE_TIMEOUT,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::PullRequests { pull_requests } => Ok(pull_requests),
+            DaemonResponse::NotARepository { path } => Err(ClientError::NotARepository(path)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    // ── Chat mode methods ──
+
+    /// Send a message to a session (chat mode), with `context_files` from the
+    /// workspace included as context and uploaded `attachments` (by id).
+    pub async fn send_message(
+        &self,
+        session_id: &str,
+        content: &str,
+        model: Option<&str>,
+        context_files: &[String],
+        attachments: &[String],
     ) -> Result<String, ClientError> {
+        let request = SendMessageBody {
+            content: content.to_string(),
+            model: model.map(str::to_string),
+            context_files: context_files.to_vec(),
+            attachments: attachments.to_vec(),
+        };
+        let body = self
+            .post(&format!("/sessions/{}/messages", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MessageAccepted { message_id } => Ok(message_id),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Replace an earlier user message, drop the conversation after it and
+    /// run it again (chat mode).
+    pub async fn edit_message(
+        &self,
+        session_id: &str,
+        message_id: &str,
+        content: &str,
+        model: Option<&str>,
+        context_files: &[String],
+        attachments: &[String],
+    ) -> Result<(), ClientError> {
+        let request = SendMessageBody {
+            content: content.to_string(),
+            model: model.map(str::to_string),
+            context_files: context_files.to_vec(),
+            attachments: attachments.to_vec(),
+        };
+        let body = self
+            .post(&format!("/sessions/{}/messages/{}/edit", session_id, message_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MessageAccepted { .. } => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Answer a prompt the Claude CLI raised during the current response
+    /// (chat mode).
+    pub async fn send_response_input(&self, session_id: &str, text: &str) -> Result<(), ClientError> {
+        let request = ResponseInputBody { text: text.to_string() };
+        let body = self
+            .post(&format!("/sessions/{}/messages/current/input", session_id), &request)
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Run the last user message again after the response to it failed
+    /// (chat mode). Returns the message's id.
+    pub async fn retry_message(&self, session_id: &str) -> Result<String, ClientError> {
+        let body = self
+            .post(&format!("/sessions/{}/messages/retry", session_id), &serde_json::json!({}))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MessageAccepted { message_id } => Ok(message_id),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get messages from a session (chat mode).
+    pub async fn get_messages(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+        before_id: Option<&str>,
+    ) -> Result<Vec<crate::types::Message>, ClientError> {
+        let mut path = format!("/sessions/{}/messages", session_id);
+        let mut params = Vec::new();
+        if let Some(l) = limit {
+            params.push(format!("limit={}", l));
+        }
+        if let Some(bid) = before_id {
+            params.push(format!("before_id={}", bid));
+        }
+        if !params.is_empty() {
+            path.push('?');
+            path.push_str(&params.join("&"));
+        }
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Messages { messages } => Ok(messages),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Cancel an in-progress response (chat mode).
+    pub async fn cancel_response(&self, session_id: &str) -> Result<(), ClientError> {
+        let body = self
+            .delete(&format!("/sessions/{}/messages/current", session_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::CancelAccepted => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Delete one message from a conversation (chat mode).
+    pub async fn delete_message(&self, session_id: &str, message_id: &str) -> Result<(), ClientError> {
+        let body = self
+            .delete(&format!("/sessions/{}/messages/{}", session_id, message_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MessagesDeleted { .. } => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Delete all messages of a conversation and start a new Claude session
+    /// (chat mode). Returns how many messages were deleted.
+    pub async fn clear_messages(&self, session_id: &str) -> Result<usize, ClientError> {
+        let body = self.delete(&format!("/sessions/{}/messages", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MessagesDeleted { count } => Ok(count),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Remove a queued message before it starts (chat mode).
+    pub async fn cancel_queued_message(
+        &self,
+        session_id: &str,
+        message_id: &str,
+    ) -> Result<(), ClientError> {
+        let body = self
+            .delete(&format!("/sessions/{}/queue/{}", session_id, message_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::CancelAccepted => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Upload a file to attach to messages of a session (chat mode). Pass the
+    /// returned attachment's id to `send_message`.
+    pub async fn upload_attachment(
+        &self,
+        session_id: &str,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<crate::types::Attachment, ClientError> {
+        let path = format!(
+            "/sessions/{}/attachments?name={}",
+            session_id,
+            encode_query_value(name)
+        );
+        let body = self.post_raw(&path, Bytes::from(data)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::AttachmentUploaded { attachment } => Ok(attachment),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List a directory of a session's working directory (the working
+    /// directory itself when `path` is `None`) down to `depth` levels.
+    /// Returns the entries and whether the listing was truncated.
+    pub async fn list_files(
+        &self,
+        session_id: &str,
+        path: Option<&str>,
+        depth: Option<usize>,
+    ) -> Result<(Vec<crate::types::FileEntry>, bool), ClientError> {
+        let mut url = format!("/sessions/{}/files", session_id);
+        let mut params = Vec::new();
+        if let Some(path) = path {
+            params.push(format!("path={}", encode_query_value(path)));
+        }
+        if let Some(depth) = depth {
+            params.push(format!("depth={}", depth));
+        }
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
+        }
+        let body = self.get(&url).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Files { entries, truncated } => Ok((entries, truncated)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Read a text file from a session's working directory. Returns the
+    /// content and the ETag to pass to `write_file`.
+    pub async fn read_file(
+        &self,
+        session_id: &str,
+        path: &str,
+    ) -> Result<(String, String), ClientError> {
         let body = self
             .get(&format!(
-                "/sessions/{}/git/diff?file_path={}&staged={}",
-                session_id, file_path, staged
+                "/sessions/{}/files/content?path={}",
+                session_id,
+                encode_query_value(path)
             ))
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::FileDiffContent { diff } => Ok(diff),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::FileContent { content, etag, .. } => Ok((content, etag)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Write a text file in a session's working directory. With `etag` (from
+    /// `read_file`), fails with `ClientError::Conflict` if the file changed
+    /// since it was read. Returns the new ETag.
+    pub async fn write_file(
+        &self,
+        session_id: &str,
+        path: &str,
+        content: &str,
+        etag: Option<&str>,
+    ) -> Result<String, ClientError> {
+        let request = WriteFileBody {
+            content: content.to_string(),
+            etag: etag.map(str::to_string),
+        };
+        let body = self
+            .put(
+                &format!("/sessions/{}/files/content?path={}", session_id, encode_query_value(path)),
+                &request,
+            )
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::FileWritten { etag } => Ok(etag),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Search file contents in a session's working directory. `query` is a
+    /// literal unless `regex` is set; `glob` limits the files searched.
+    pub async fn search(
+        &self,
+        session_id: &str,
+        query: &str,
+        glob: Option<&str>,
+        regex: bool,
+    ) -> Result<crate::types::SearchResults, ClientError> {
+        let mut path = format!("/sessions/{}/search?q={}", session_id, encode_query_value(query));
+        if let Some(glob) = glob {
+            path.push_str(&format!("&glob={}", encode_query_value(glob)));
+        }
+        if regex {
+            path.push_str("&regex=true");
+        }
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::SearchResults { results } => Ok(results),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get a session's cumulative token usage and context window utilization.
+    pub async fn session_usage(
+        &self,
+        session_id: &str,
+    ) -> Result<crate::types::SessionUsage, ClientError> {
+        let body = self.get(&format!("/sessions/{}/usage", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::SessionUsage { usage } => Ok(usage),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Get daemon-wide token usage and cost per day and project, from
+    /// `since` on (every day when `None`).
+    pub async fn usage_report(
+        &self,
+        since: Option<chrono::NaiveDate>,
+    ) -> Result<crate::types::UsageReport, ClientError> {
+        let path = match since {
+            Some(since) => format!("/usage?since={}", since),
+            None => "/usage".to_string(),
+        };
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Usage { report } => Ok(report),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List scheduled prompts.
+    pub async fn list_schedules(&self) -> Result<Vec<crate::types::Schedule>, ClientError> {
+        let body = self.get("/schedules").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Schedules { schedules } => Ok(schedules),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Run `prompt` in `cwd` whenever the five-field `cron` expression
+    /// matches, with the daemon's default model unless `model` is given.
+    pub async fn create_schedule(
+        &self,
+        cron: &str,
+        prompt: &str,
+        cwd: &str,
+        model: Option<&str>,
+        permission_mode: crate::types::PermissionMode,
+    ) -> Result<crate::types::Schedule, ClientError> {
+        let request = CreateScheduleBody {
+            cron: cron.to_string(),
+            prompt: prompt.to_string(),
+            cwd: cwd.to_string(),
+            model: model.map(str::to_string),
+            permission_mode,
+        };
+        let body = self.post("/schedules", &request).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::ScheduleCreated { schedule } => Ok(schedule),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Remove a schedule and its run history.
+    pub async fn delete_schedule(&self, id: &str) -> Result<(), ClientError> {
+        let body = self.delete(&format!("/schedules/{}", id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Pong => Ok(()),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Past runs of a schedule, newest first.
+    pub async fn schedule_runs(&self, id: &str) -> Result<Vec<crate::types::ScheduleRun>, ClientError> {
+        let body = self.get(&format!("/schedules/{}/runs", id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::ScheduleRuns { runs } => Ok(runs),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Session templates, by name.
+    pub async fn list_templates(&self) -> Result<Vec<crate::types::SessionTemplate>, ClientError> {
+        let body = self.get("/templates").await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Templates { templates } => Ok(templates),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Add a session template, replacing any of the same name.
+    pub async fn save_template(
+        &self,
+        template: &crate::types::SessionTemplate,
+    ) -> Result<crate::types::SessionTemplate, ClientError> {
+        let body = self.post("/templates", template).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::TemplateSaved { template } => Ok(template),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Stage a single file.
-    pub async fn git_stage_file(
-        &self,
-        session_id: &str,
-        file_path: &str,
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "file_path": file_path });
-        let body = self
-            .post(&format!("/sessions/{}/git/stage", session_id), &body_json)
-            .await?;
+    pub async fn delete_template(&self, name: &str) -> Result<(), ClientError> {
+        let body = self.delete(&format!("/templates/{}", encode_query_value(name))).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
             DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Unstage a single file.
-    pub async fn git_unstage_file(
-        &self,
-        session_id: &str,
-        file_path: &str,
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "file_path": file_path });
-        let body = self
-            .post(
-                &format!("/sessions/{}/git/unstage", session_id),
-                &body_json,
-            )
-            .await?;
+    /// The prompt library, by name; with `session_id`, including its
+    /// project's prompts.
+    pub async fn list_prompts(&self, session_id: Option<&str>) -> Result<Vec<crate::types::SavedPrompt>, ClientError> {
+        let path = match session_id {
+            Some(id) => format!("/prompts?session_id={}", encode_query_value(id)),
+            None => "/prompts".to_string(),
+        };
+        let body = self.get(&path).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Prompts { prompts } => Ok(prompts),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Stage multiple files in a single batch operation.
-    pub async fn git_stage_files(
+    /// Add a prompt to the library, replacing any of the same name.
+    pub async fn save_prompt(
         &self,
-        session_id: &str,
-        file_paths: &[String],
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "file_paths": file_paths });
-        let body = self
-            .post(
-                &format!("/sessions/{}/git/stage-files", session_id),
-                &body_json,
-            )
-            .await?;
+        prompt: &crate::types::SavedPrompt,
+    ) -> Result<crate::types::SavedPrompt, ClientError> {
+        let body = self.post("/prompts", prompt).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::PromptSaved { prompt } => Ok(prompt),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn delete_prompt(&self, name: &str) -> Result<(), ClientError> {
+        let body = self.delete(&format!("/prompts/{}", encode_query_value(name))).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
             DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Unstage multiple files in a single batch operation.
-    pub async fn git_unstage_files(
+    /// A saved prompt with its variables filled in.
+    pub async fn render_prompt(
         &self,
-        session_id: &str,
-        file_paths: &[String],
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({ "file_paths": file_paths });
+        name: &str,
+        body: &crate::protocol::RenderPromptBody,
+    ) -> Result<crate::types::RenderedPrompt, ClientError> {
         let body = self
-            .post(
-                &format!("/sessions/{}/git/unstage-files", session_id),
-                &body_json,
-            )
+            .post(&format!("/prompts/{}/render", encode_query_value(name)), body)
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::PromptRendered { rendered } => Ok(rendered),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Stage a single hunk from a file.
-    pub async fn git_stage_hunk(
+    /// The project memory file (`CLAUDE.md`) of a session's workspace.
+    pub async fn memory(&self, session_id: &str) -> Result<crate::types::MemoryFile, ClientError> {
+        let body = self.get(&format!("/sessions/{}/memory", session_id)).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::Memory { memory } => Ok(memory),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Replace the project memory file. With `etag`, only if it is unchanged
+    /// since it was read.
+    pub async fn write_memory(
         &self,
         session_id: &str,
-        file_path: &str,
-        hunk_index: usize,
-    ) -> Result<(), ClientError> {
-        let body_json = serde_json::json!({
-            "file_path": file_path,
-            "hunk_index": hunk_index
-        });
+        content: &str,
+        etag: Option<&str>,
+    ) -> Result<crate::types::MemoryFile, ClientError> {
         let body = self
-            .post(
-                &format!("/sessions/{}/git/stage-hunk", session_id),
-                &body_json,
+            .put(
+                &format!("/sessions/{}/memory", session_id),
+                &crate::protocol::WriteMemoryBody {
+                    content: content.to_string(),
+                    etag: etag.map(String::from),
+                },
             )
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Pong => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Memory { memory } => Ok(memory),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Commit staged files with a message.
-    pub async fn git_commit(
-        &self,
-        session_id: &str,
-        message: &str,
-    ) -> Result<String, ClientError> {
-        let body_json = serde_json::json!({ "message": message });
+    /// Add an entry to the project memory file.
+    pub async fn append_memory(&self, session_id: &str, entry: &str) -> Result<crate::types::MemoryFile, ClientError> {
         let body = self
             .post(
-                &format!("/sessions/{}/git/commit", session_id),
-                &body_json,
+                &format!("/sessions/{}/memory/append", session_id),
+                &crate::protocol::AppendMemoryBody { entry: entry.to_string() },
             )
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::GitCommitResult { oid } => Ok(oid),
-            DaemonResponse::Pong => Ok(String::new()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Memory { memory } => Ok(memory),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get git commit log.
-    pub async fn git_log(
-        &self,
-        session_id: &str,
-        limit: Option<usize>,
-    ) -> Result<Vec<crate::types::GitLogEntry>, ClientError> {
-        let url = match limit {
-            Some(n) => format!("/sessions/{}/git/log?limit={}", session_id, n),
-            None => format!("/sessions/{}/git/log", session_id),
-        };
-        let body = self.get(&url).await?;
+    /// Memory entries suggested for a session and not yet accepted or
+    /// dismissed.
+    pub async fn memory_suggestions(&self, session_id: &str) -> Result<Vec<crate::types::MemorySuggestion>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/memory/suggestions", session_id)).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::GitLogResult { entries } => Ok(entries),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::MemorySuggestions { suggestions } => Ok(suggestions),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get branch info (name + remote existence).
-    pub async fn git_branch_info(
-        &self,
-        session_id: &str,
-    ) -> Result<crate::types::BranchInfo, ClientError> {
+    /// Ask the session's model for memory entries from the recent
+    /// conversation. Returns all pending suggestions.
+    pub async fn suggest_memory(&self, session_id: &str) -> Result<Vec<crate::types::MemorySuggestion>, ClientError> {
         let body = self
-            .get(&format!("/sessions/{}/git/branch-info", session_id))
+            .send_json(
+                "POST",
+                &format!("/sessions/{}/memory/suggestions", session_id),
+                &serde_json::json!({}),
+                COMPACT_TIMEOUT,
+            )
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::GitBranchInfo { info } => Ok(info),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::MemorySuggestions { suggestions } => Ok(suggestions),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Push current branch to origin.
-    pub async fn git_push(
+    /// Add a suggested entry to the project memory file.
+    pub async fn accept_memory_suggestion(
         &self,
         session_id: &str,
-    ) -> Result<(), ClientError> {
+        suggestion_id: &str,
+    ) -> Result<crate::types::MemoryFile, ClientError> {
         let body = self
             .post(
-                &format!("/sessions/{}/git/push", session_id),
+                &format!("/sessions/{}/memory/suggestions/{}/accept", session_id, suggestion_id),
                 &serde_json::json!({}),
             )
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::GitPushResult => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::Memory { memory } => Ok(memory),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    // ── Chat mode methods ──
-
-    /// Send a message to a session (chat mode).
-    pub async fn send_message(
+    /// Drop a suggested memory entry. Returns those left.
+    pub async fn dismiss_memory_suggestion(
         &self,
         session_id: &str,
-        content: &str,
-        model: Option<&str>,
-    ) -> Result<String, ClientError> {
-        let mut body_json = serde_json::json!({ "content": content });
-        if let Some(m) = model {
-            body_json["model"] = serde_json::json!(m);
+        suggestion_id: &str,
+    ) -> Result<Vec<crate::types::MemorySuggestion>, ClientError> {
+        let body = self
+            .delete(&format!("/sessions/{}/memory/suggestions/{}", session_id, suggestion_id))
+            .await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::MemorySuggestions { suggestions } => Ok(suggestions),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
         }
+    }
+
+    /// Let sessions run in `path` and below. Returns the directory as the
+    /// daemon stored it.
+    pub async fn trust_workspace(&self, path: &str) -> Result<String, ClientError> {
         let body = self
-            .post(&format!("/sessions/{}/messages", session_id), &body_json)
+            .post("/workspaces/trust", &crate::protocol::TrustWorkspaceBody { path: path.to_string() })
             .await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::MessageAccepted { message_id } => Ok(message_id),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::WorkspaceTrusted { path } => Ok(path),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Get messages from a session (chat mode).
-    pub async fn get_messages(
+    /// Render a session's conversation for export, with Claude's thinking if
+    /// `include_thinking`. Returns a file name to save it under and the
+    /// rendered content.
+    pub async fn export_conversation(
         &self,
         session_id: &str,
-        limit: Option<usize>,
-        before_id: Option<&str>,
-    ) -> Result<Vec<crate::types::Message>, ClientError> {
-        let mut path = format!("/sessions/{}/messages", session_id);
-        let mut params = Vec::new();
-        if let Some(l) = limit {
-            params.push(format!("limit={}", l));
-        }
-        if let Some(bid) = before_id {
-            params.push(format!("before_id={}", bid));
-        }
-        if !params.is_empty() {
-            path.push('?');
-            path.push_str(&params.join("&"));
+        format: crate::types::ExportFormat,
+        include_thinking: bool,
+    ) -> Result<(String, String), ClientError> {
+        let mut path = format!("/sessions/{}/export?format={}", session_id, format.as_str());
+        if include_thinking {
+            path.push_str("&include_thinking=true");
         }
         let body = self.get(&path).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Messages { messages } => Ok(messages),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::ConversationExport { file_name, content } => Ok((file_name, content)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Cancel an in-progress response (chat mode).
-    pub async fn cancel_response(&self, session_id: &str) -> Result<(), ClientError> {
-        let body = self
-            .delete(&format!("/sessions/{}/messages/current", session_id))
-            .await?;
+    /// A session's audit log, oldest first.
+    pub async fn audit_log(&self, session_id: &str) -> Result<Vec<crate::types::AuditEntry>, ClientError> {
+        let body = self.get(&format!("/sessions/{}/audit", session_id)).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::CancelAccepted => Ok(()),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::AuditLog { entries } => Ok(entries),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Render a session's audit log for export. Returns a file name to save
+    /// it under and the content.
+    pub async fn export_audit_log(
+        &self,
+        session_id: &str,
+        format: crate::types::AuditFormat,
+    ) -> Result<(String, String), ClientError> {
+        let path = format!("/sessions/{}/audit/export?format={}", session_id, format.as_str());
+        let body = self.get(&path).await?;
+        let response: DaemonResponse = serde_json::from_slice(&body)?;
+        match response {
+            DaemonResponse::AuditExport { file_name, content } => Ok((file_name, content)),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Import Claude CLI history for a session's working directory.
+    /// Import `source`'s history (the Claude CLI's if `None`) for a session's
+    /// working directory into its conversation, skipping messages it has.
     /// If `target_cli_session_id` is provided, imports that specific CLI session.
+    /// `limit` and `offset` select the newest `limit` messages after skipping
+    /// the newest `offset`.
     pub async fn import_history(
         &self,
         session_id: &str,
         limit: Option<usize>,
+        offset: Option<usize>,
         all_sessions: Option<bool>,
         target_cli_session_id: Option<&str>,
-    ) -> Result<Vec<crate::types::Message>, ClientError> {
+        source: Option<crate::types::HistorySource>,
+    ) -> Result<crate::types::HistoryImport, ClientError> {
         let mut path = format!("/sessions/{}/history", session_id);
         let mut params = Vec::new();
         if let Some(l) = limit {
             params.push(format!("limit={}", l));
         }
+        if let Some(offset) = offset {
+            params.push(format!("offset={}", offset));
+        }
         if let Some(all) = all_sessions {
             params.push(format!("all_sessions={}", all));
         }
         if let Some(target_id) = target_cli_session_id {
-            params.push(format!("target_session_id={}", target_id));
+            params.push(format!("target_session_id={}", encode_query_value(target_id)));
+        }
+        if let Some(source) = source {
+            params.push(format!("source={}", source.as_str()));
         }
         if !params.is_empty() {
             path.push('?');
@@ -672,116 +2280,304 @@ impl DaemonClient {
         let body = self.get(&path).await?;
         let response: DaemonResponse = serde_json::from_slice(&body)?;
         match response {
-            DaemonResponse::Messages { messages } => Ok(messages),
-            DaemonResponse::Error { message } => Err(ClientError::DaemonError(message)),
+            DaemonResponse::HistoryImported { import } => Ok(import),
+            DaemonResponse::Error { code, message } => Err(ClientError::from_daemon(code, message)),
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
 
-    /// Send an HTTP GET request to the daemon over the Unix socket.
-    async fn get(&self, path: &str) -> Result<Bytes, ClientError> {
-        let stream = UnixStream::connect(&self.socket_path)
-            .await
-            .map_err(|e| ClientError::ConnectionFailed {
-                path: self.socket_path.clone(),
-                source: e,
-            })?;
+    /// Open a WebSocket to a session for PTY I/O and chat events.
+    pub async fn session_socket(&self, session_id: &str) -> Result<SessionSocket, ClientError> {
+        let stream = self.open_stream().await?;
+        SessionSocket::connect(stream, self, session_id).await
+    }
 
-        let io = TokioIo::new(stream);
+    /// Follow a session's chat events. Returns once the daemon has accepted
+    /// the subscription, so events caused by a request made afterwards are
+    /// not missed. Dropped connections are resumed with `Last-Event-ID`.
+    pub async fn subscribe_events(&self, session_id: &str) -> Result<EventStream<StreamEvent>, ClientError> {
+        let source = EventSource::connect(self, &format!("/sessions/{}/stream", session_id)).await?;
+        Ok(source.into_stream(|event| match event.event.as_str() {
+            "message" => ControlFlow::Continue(serde_json::from_str(&event.data).ok()),
+            "resync" => {
+                tracing::warn!("Missed chat events that can no longer be replayed");
+                ControlFlow::Continue(None)
+            }
+            _ => ControlFlow::Continue(None),
+        }))
+    }
 
-        let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
-            .await
-            .map_err(ClientError::HttpError)?;
+    /// Follow a session's PTY output: scrollback first, then live output.
+    /// Ends when the process exits. Dropped connections are resumed with
+    /// `Last-Event-ID`, replaying the output missed in between.
+    pub async fn subscribe_output(&self, session_id: &str) -> Result<EventStream<Vec<u8>>, ClientError> {
+        use base64::Engine;
 
-        // Spawn connection driver.
-        tokio::spawn(async move {
-            if let Err(e) = conn.await {
-                tracing::error!("Connection error: {}", e);
+        let mut source = EventSource::connect(self, &format!("/sessions/{}/pty/stream", session_id)).await?;
+        // The daemon answers an unknown session with an `error` event.
+        match source.next().await {
+            Some(event) if event.event == "started" => {}
+            Some(event) if event.event == "error" => {
+                return Err(ClientError::SessionNotFound(format!("Session not found: {}", session_id)));
             }
-        });
+            _ => return Err(ClientError::UnexpectedResponse),
+        }
+        Ok(source.into_stream(|event| match event.event.as_str() {
+            "scrollback" | "output" => {
+                ControlFlow::Continue(base64::engine::general_purpose::STANDARD.decode(&event.data).ok())
+            }
+            "exit" | "error" => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(None),
+        }))
+    }
 
-        let req = Request::builder()
-            .uri(path)
-            .header("Host", "localhost")
-            .body(Full::new(Bytes::new()))
-            .expect("Failed to build request");
+    /// Follow the daemon-wide activity feed (`/events`). Dropped connections
+    /// are resumed with `Last-Event-ID`.
+    pub async fn subscribe_activity(&self) -> Result<EventStream<ActivityEvent>, ClientError> {
+        let source = EventSource::connect(self, "/events").await?;
+        Ok(source.into_stream(|event| match event.event.as_str() {
+            "activity" => ControlFlow::Continue(serde_json::from_str(&event.data).ok()),
+            _ => ControlFlow::Continue(None),
+        }))
+    }
+
+    /// Follow a command run: its output from the start, then the finished
+    /// run, after which the stream ends. Dropped connections are resumed
+    /// with `Last-Event-ID`.
+    pub async fn follow_run(&self, session_id: &str, run_id: &str) -> Result<EventStream<RunEvent>, ClientError> {
+        let source = EventSource::connect(self, &format!("/sessions/{}/runs/{}/stream", session_id, run_id)).await?;
+        let mut complete = false;
+        Ok(source.into_stream(move |event| {
+            if complete {
+                return ControlFlow::Break(());
+            }
+            match serde_json::from_str(&event.data) {
+                Ok(event @ RunEvent::Complete { .. }) => {
+                    complete = true;
+                    ControlFlow::Continue(Some(event))
+                }
+                Ok(event) => ControlFlow::Continue(Some(event)),
+                Err(_) => ControlFlow::Continue(None),
+            }
+        }))
+    }
 
-        let resp = sender.send_request(req).await.map_err(ClientError::HttpError)?;
+    /// Follow a comparison: each variant's chat events as they come, then
+    /// the finished comparison, after which the stream ends. Dropped
+    /// connections are resumed with `Last-Event-ID`.
+    pub async fn follow_comparison(
+        &self,
+        session_id: &str,
+        comparison_id: &str,
+    ) -> Result<EventStream<CompareEvent>, ClientError> {
+        let source = EventSource::connect(
+            self,
+            &format!(
+                "/sessions/{}/comparisons/{}/stream",
+                session_id,
+                encode_query_value(comparison_id)
+            ),
+        )
+        .await?;
+        let mut complete = false;
+        Ok(source.into_stream(move |event| {
+            if complete {
+                return ControlFlow::Break(());
+            }
+            match serde_json::from_str(&event.data) {
+                Ok(event @ CompareEvent::Complete { .. }) => {
+                    complete = true;
+                    ControlFlow::Continue(Some(event))
+                }
+                Ok(event) => ControlFlow::Continue(Some(event)),
+                Err(_) => ControlFlow::Continue(None),
+            }
+        }))
+    }
 
-        let body = resp.into_body().collect().await.map_err(ClientError::HttpError)?;
-        Ok(body.to_bytes())
+    /// Open a raw connection to the daemon, for callers that speak HTTP themselves
+    /// (e.g. long-lived SSE streams). Pair with `request` to get the right headers.
+    pub async fn open_stream(&self) -> Result<DaemonStream, ClientError> {
+        transport::connect(&self.endpoint, self.tls.as_ref()).await
     }
 
-    /// Send an HTTP POST request with JSON body to the daemon over the Unix socket.
-    async fn post(&self, path: &str, json_body: &serde_json::Value) -> Result<Bytes, ClientError> {
-        let body_bytes = serde_json::to_vec(json_body)?;
+    /// Start a request to `path` with the `Host` and `Authorization` headers set.
+    pub fn request(&self, path: &str) -> hyper::http::request::Builder {
+        let builder = Request::builder()
+            .uri(path)
+            .header("Host", self.endpoint.host_header());
+        match self.authorization() {
+            Some(authorization) => builder.header("Authorization", authorization),
+            None => builder,
+        }
+    }
 
-        let stream = UnixStream::connect(&self.socket_path)
-            .await
-            .map_err(|e| ClientError::ConnectionFailed {
-                path: self.socket_path.clone(),
-                source: e,
-            })?;
+    /// Value of the `Authorization` header, if a token is configured.
+    pub(crate) fn authorization(&self) -> Option<String> {
+        self.token.as_ref().map(|token| format!("Bearer {}", token))
+    }
 
-        let io = TokioIo::new(stream);
+    /// Send an HTTP GET request to the daemon.
+    async fn get(&self, path: &str) -> Result<Bytes, ClientError> {
+        self.send("GET", path, None, Bytes::new(), self.timeout).await
+    }
 
-        let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+    /// Send a liveness check, which gets a short timeout.
+    async fn probe(&self, path: &str) -> Result<Bytes, ClientError> {
+        self.send("GET", path, None, Bytes::new(), self.timeout.min(PROBE_TIMEOUT))
             .await
-            .map_err(ClientError::HttpError)?;
+    }
 
-        tokio::spawn(async move {
-            if let Err(e) = conn.await {
-                tracing::error!("Connection error: {}", e);
-            }
-        });
+    /// Send an HTTP POST request with JSON body to the daemon.
+    async fn post(&self, path: &str, json_body: &impl Serialize) -> Result<Bytes, ClientError> {
+        self.send_json("POST", path, json_body, self.timeout).await
+    }
 
-        let req = Request::builder()
-            .method("POST")
-            .uri(path)
-            .header("Host", "localhost")
-            .header("Content-Type", "application/json")
-            .body(Full::new(Bytes::from(body_bytes)))
-            .expect("Failed to build request");
+    /// Send an HTTP PUT request with JSON body to the daemon.
+    async fn put(&self, path: &str, json_body: &impl Serialize) -> Result<Bytes, ClientError> {
+        self.send_json("PUT", path, json_body, self.timeout).await
+    }
 
-        let resp = sender.send_request(req).await.map_err(ClientError::HttpError)?;
-        let body = resp.into_body().collect().await.map_err(ClientError::HttpError)?;
-        Ok(body.to_bytes())
+    /// Send an HTTP PATCH request with JSON body to the daemon.
+    async fn patch(&self, path: &str, json_body: &impl Serialize) -> Result<Bytes, ClientError> {
+        self.send_json("PATCH", path, json_body, self.timeout).await
     }
 
-    /// Send an HTTP DELETE request to the daemon over the Unix socket.
-    async fn delete(&self, path: &str) -> Result<Bytes, ClientError> {
-        let stream = UnixStream::connect(&self.socket_path)
+    async fn send_json(
+        &self,
+        method: &str,
+        path: &str,
+        json_body: &impl Serialize,
+        timeout: Duration,
+    ) -> Result<Bytes, ClientError> {
+        let body_bytes = serde_json::to_vec(json_body)?;
+        self.send(method, path, Some("application/json"), Bytes::from(body_bytes), timeout)
             .await
-            .map_err(|e| ClientError::ConnectionFailed {
-                path: self.socket_path.clone(),
-                source: e,
-            })?;
-
-        let io = TokioIo::new(stream);
+    }
 
-        let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+    /// Send an HTTP POST request with a binary body to the daemon.
+    async fn post_raw(&self, path: &str, body_bytes: Bytes) -> Result<Bytes, ClientError> {
+        self.send("POST", path, Some("application/octet-stream"), body_bytes, self.timeout)
             .await
-            .map_err(ClientError::HttpError)?;
+    }
 
-        tokio::spawn(async move {
-            if let Err(e) = conn.await {
-                tracing::error!("Connection error: {}", e);
-            }
-        });
+    /// Send an HTTP DELETE request to the daemon.
+    async fn delete(&self, path: &str) -> Result<Bytes, ClientError> {
+        self.send("DELETE", path, None, Bytes::new(), self.timeout).await
+    }
 
-        let req = Request::builder()
-            .method("DELETE")
-            .uri(path)
-            .header("Host", "localhost")
-            .body(Full::new(Bytes::new()))
-            .expect("Failed to build request");
+    /// Send a request, giving up after `timeout`. A GET that didn't reach
+    /// the daemon is retried with backoff; other methods aren't, as the
+    /// daemon may have acted on them. While the daemon is considered
+    /// unresponsive this fails at once with `ClientError::Unresponsive`.
+    async fn send(
+        &self,
+        method: &str,
+        path: &str,
+        content_type: Option<&str>,
+        body: Bytes,
+        timeout: Duration,
+    ) -> Result<Bytes, ClientError> {
+        if !self.breaker.allows() {
+            return Err(ClientError::Unresponsive);
+        }
+
+        let retries = if method == "GET" { GET_RETRIES } else { 0 };
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 0;
+        let result = loop {
+            let result = tokio::time::timeout(timeout, self.send_once(method, path, content_type, body.clone()))
+                .await
+                .unwrap_or(Err(ClientError::Timeout(timeout)));
+            match result {
+                Err(e) if e.is_retryable() && attempt < retries => {
+                    tracing::debug!("{} {} failed, retrying: {}", method, path, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        self.breaker.record(matches!(result, Err(ClientError::Timeout(_))));
+        result
+    }
 
-        let resp = sender.send_request(req).await.map_err(ClientError::HttpError)?;
+    /// Send a request over a pooled connection and collect the response body.
+    async fn send_once(
+        &self,
+        method: &str,
+        path: &str,
+        content_type: Option<&str>,
+        body: Bytes,
+    ) -> Result<Bytes, ClientError> {
+        // The pool wants an absolute URI; the connector ignores it and dials
+        // the endpoint.
+        let uri = format!("http://{}{}", self.endpoint.host_header(), path);
+        let mut builder = self.request(&uri).method(method);
+        if let Some(content_type) = content_type {
+            builder = builder.header("Content-Type", content_type);
+        }
+        let req = builder.body(Full::new(body)).expect("Failed to build request");
+
+        let resp = self.http.request(req).await.map_err(|e| {
+            // Surface connect failures as themselves (e.g. `ConnectionFailed`).
+            let original = e
+                .source()
+                .and_then(|source| source.downcast_ref::<ConnectError>())
+                .and_then(ConnectError::take);
+            original.unwrap_or(ClientError::HttpClientError(e))
+        })?;
         let body = resp.into_body().collect().await.map_err(ClientError::HttpError)?;
         Ok(body.to_bytes())
     }
 }
 
+/// Tracks timeouts in a row, shared by a client and its clones. After
+/// [`BREAKER_THRESHOLD`] the daemon is considered unresponsive and requests
+/// fail fast for [`BREAKER_COOLDOWN`]; then requests go through again, and
+/// the first one to time out restarts the cooldown.
+#[derive(Debug, Default)]
+struct Breaker(std::sync::Mutex<BreakerState>);
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    timeouts: u32,
+    open_until: Option<std::time::Instant>,
+}
+
+impl Breaker {
+    /// Whether a request may be sent now.
+    fn allows(&self) -> bool {
+        let state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.open_until.is_none_or(|until| std::time::Instant::now() >= until)
+    }
+
+    /// Record a finished request.
+    fn record(&self, timed_out: bool) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !timed_out {
+            *state = BreakerState::default();
+            return;
+        }
+        state.timeouts += 1;
+        if state.timeouts >= BREAKER_THRESHOLD {
+            tracing::warn!("Daemon unresponsive after {} timeouts", state.timeouts);
+            state.open_until = Some(std::time::Instant::now() + BREAKER_COOLDOWN);
+        }
+    }
+}
+
+/// Pooled HTTP client for request/response calls. Idle connections are
+/// kept for reuse, so a UI polling the daemon doesn't pay for a new
+/// connection and handshake on every call.
+fn http_client(endpoint: &DaemonEndpoint, tls: Option<Arc<rustls::ClientConfig>>) -> HttpClient {
+    Client::builder(TokioExecutor::new())
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE)
+        .build(Connector::new(endpoint.clone(), tls))
+}
+
 /// Default socket path: ~/.mado/mado.sock
 pub fn default_socket_path() -> PathBuf {
     dirs_path().join("mado.sock")
@@ -792,6 +2588,14 @@ pub fn default_pid_path() -> PathBuf {
     dirs_path().join("mado.pid")
 }
 
+/// Name of the API token file, stored next to the socket.
+pub const TOKEN_FILE_NAME: &str = "token";
+
+/// Default API token path: ~/.mado/token
+pub fn default_token_path() -> PathBuf {
+    dirs_path().join(TOKEN_FILE_NAME)
+}
+
 /// Default state file path: ~/.mado/state.json
 pub fn default_state_path() -> PathBuf {
     dirs_path().join("state.json")
@@ -803,3 +2607,77 @@ pub fn dirs_path() -> PathBuf {
         .expect("Failed to determine home directory")
         .join(".mado")
 }
+
+/// Percent-encode a query string value or path segment (everything but
+/// RFC 3986 unreserved characters).
+pub fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker() {
+        let breaker = Breaker::default();
+        for _ in 0..BREAKER_THRESHOLD - 1 {
+            breaker.record(true);
+        }
+        assert!(breaker.allows());
+        // Anything but a timeout starts the count again.
+        breaker.record(false);
+        for _ in 0..BREAKER_THRESHOLD - 1 {
+            breaker.record(true);
+        }
+        assert!(breaker.allows());
+
+        breaker.record(true);
+        assert!(!breaker.allows());
+
+        // After the cooldown requests go through again; another timeout
+        // restarts it at once.
+        breaker.0.lock().unwrap().open_until = Some(std::time::Instant::now());
+        assert!(breaker.allows());
+        breaker.record(true);
+        assert!(!breaker.allows());
+    }
+
+    #[tokio::test]
+    async fn test_hung_daemon_times_out() {
+        let socket_path = std::env::temp_dir().join(format!("mado-hung-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        // Accepts connections but never answers.
+        let server = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let client = DaemonClient::new(&socket_path).with_timeout(Duration::from_millis(50));
+        for _ in 0..BREAKER_THRESHOLD {
+            let err = client.ping().await.unwrap_err();
+            assert!(matches!(err, ClientError::Timeout(_)));
+            // The daemon got the request and may have acted on it.
+            assert!(!err.is_unreachable());
+        }
+        // Clones share the breaker.
+        let err = client.clone().health().await.unwrap_err();
+        assert!(matches!(err, ClientError::Unresponsive));
+        assert!(err.is_unreachable());
+
+        server.abort();
+        let _ = std::fs::remove_file(&socket_path);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod client;
+pub mod mock;
 pub mod protocol;
+pub mod session_socket;
+pub mod sse;
+pub mod transport;
 pub mod types;
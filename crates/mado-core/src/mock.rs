@@ -0,0 +1,321 @@
+//! An in-memory daemon for testing code that uses `DaemonClient`, without
+//! binding a socket or running the real daemon.
+//!
+//! Script responses by method and path, point a client at it with
+//! [`MockTransport::client`], then check what was sent with
+//! [`MockTransport::requests`]:
+//!
+//! ```no_run
+//! # async fn example() {
+//! use mado_core::client::MockTransport;
+//! use mado_core::protocol::DaemonResponse;
+//!
+//! let mock = MockTransport::new();
+//! mock.respond("POST", "/sessions/abc/archive", DaemonResponse::SessionArchived { path: "abc.json".into() });
+//!
+//! let path = mock.client().archive_session("abc").await.unwrap();
+//! assert_eq!(path, "abc.json");
+//! assert_eq!(mock.requests()[0].path, "/sessions/abc/archive");
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+
+use crate::client::DaemonClient;
+use crate::protocol::{DaemonResponse, ErrorCode};
+use crate::sse::SseEvent;
+use crate::transport::{DaemonEndpoint, DaemonStream};
+
+/// Buffer size of each in-memory connection.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Scripted stand-in for the daemon. Clones share the same script and
+/// request log.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    routes: Vec<MockRoute>,
+    requests: Vec<MockRequest>,
+}
+
+struct MockRoute {
+    method: String,
+    path: String,
+    /// Played in order; the last one is repeated.
+    responses: VecDeque<MockResponse>,
+}
+
+#[derive(Clone)]
+enum MockResponse {
+    Body { status: u16, body: Bytes },
+    /// Server-sent events, after which the stream stays open like the
+    /// daemon's do.
+    Events(Vec<SseEvent>),
+}
+
+/// A request the mock received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path and query, e.g. `/sessions/abc/milestones?limit=20`.
+    pub path: String,
+    pub body: Bytes,
+}
+
+impl MockRequest {
+    /// Parse the body as JSON, e.g. into one of the `protocol` request bodies.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A client whose requests go to this mock.
+    pub fn client(&self) -> DaemonClient {
+        DaemonClient::with_endpoint(DaemonEndpoint::Memory(self.clone()))
+    }
+
+    /// Answer `method` requests to `path` with `response`. A path without a
+    /// query matches any query. Responses scripted for the same route are
+    /// played in order, and the last one is repeated.
+    pub fn respond(&self, method: &str, path: &str, response: DaemonResponse) -> &Self {
+        let status = match response {
+            DaemonResponse::Error { .. } => 400,
+            _ => 200,
+        };
+        let body = serde_json::to_vec(&response).expect("DaemonResponse serializes");
+        self.push(method, path, MockResponse::Body {
+            status,
+            body: Bytes::from(body),
+        })
+    }
+
+    /// Answer `method` requests to `path` with a raw status and body, e.g. to
+    /// test how a client handles a malformed response.
+    pub fn respond_raw(&self, method: &str, path: &str, status: u16, body: impl Into<Bytes>) -> &Self {
+        self.push(method, path, MockResponse::Body {
+            status,
+            body: body.into(),
+        })
+    }
+
+    /// Answer GET requests to the SSE endpoint `path` with `events`. The
+    /// stream then stays open until the client drops it.
+    pub fn respond_events(&self, path: &str, events: Vec<SseEvent>) -> &Self {
+        self.push("GET", path, MockResponse::Events(events))
+    }
+
+    /// Requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    fn push(&self, method: &str, path: &str, response: MockResponse) -> &Self {
+        let mut state = self.lock();
+        match state
+            .routes
+            .iter_mut()
+            .find(|route| route.method == method && route.path == path)
+        {
+            Some(route) => route.responses.push_back(response),
+            None => state.routes.push(MockRoute {
+                method: method.to_string(),
+                path: path.to_string(),
+                responses: VecDeque::from([response]),
+            }),
+        }
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Log `request` and pick its scripted response.
+    fn answer(&self, request: MockRequest) -> MockResponse {
+        let mut state = self.lock();
+        let path_only = request.path.split('?').next().unwrap_or_default();
+        let route = state.routes.iter_mut().find(|route| {
+            route.method == request.method && (route.path == request.path || route.path == path_only)
+        });
+        let response = match route {
+            Some(route) if route.responses.len() > 1 => route.responses.pop_front(),
+            Some(route) => route.responses.front().cloned(),
+            None => None,
+        };
+        let response = response.unwrap_or_else(|| {
+            let error = DaemonResponse::Error {
+                code: ErrorCode::NotFound,
+                message: format!("No mock response for {} {}", request.method, request.path),
+            };
+            MockResponse::Body {
+                status: 404,
+                body: Bytes::from(serde_json::to_vec(&error).expect("DaemonResponse serializes")),
+            }
+        });
+        state.requests.push(request);
+        response
+    }
+
+    /// Open an in-memory connection served by this mock.
+    pub(crate) fn connect(&self) -> DaemonStream {
+        let (client, server) = tokio::io::duplex(PIPE_CAPACITY);
+        let mock = self.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let mock = mock.clone();
+                async move { Ok::<_, Infallible>(mock.serve(req).await) }
+            });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(server), service)
+                .await
+            {
+                tracing::debug!("Mock connection error: {}", e);
+            }
+        });
+        Box::new(client)
+    }
+
+    async fn serve(&self, req: Request<Incoming>) -> Response<BoxBody<Bytes, Infallible>> {
+        let method = req.method().to_string();
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+        let body = match req.into_body().collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => Bytes::new(),
+        };
+
+        match self.answer(MockRequest { method, path, body }) {
+            MockResponse::Body { status, body } => Response::builder()
+                .status(status)
+                .header("Content-Type", "application/json")
+                .body(Full::new(body).boxed())
+                .expect("Failed to build response"),
+            MockResponse::Events(events) => {
+                let frames = events.into_iter().map(|event| {
+                    let mut text = String::new();
+                    if !event.event.is_empty() {
+                        text.push_str(&format!("event: {}\n", event.event));
+                    }
+                    if let Some(id) = &event.id {
+                        text.push_str(&format!("id: {}\n", id));
+                    }
+                    for line in event.data.split('\n') {
+                        text.push_str(&format!("data: {}\n", line));
+                    }
+                    text.push('\n');
+                    Ok(Frame::data(Bytes::from(text)))
+                });
+                let stream = futures::stream::iter(frames).chain(futures::stream::pending());
+                Response::builder()
+                    .header("Content-Type", "text/event-stream")
+                    .body(BodyExt::boxed(StreamBody::new(stream)))
+                    .expect("Failed to build response")
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockTransport").finish_non_exhaustive()
+    }
+}
+
+/// Mocks are equal when they share a script, i.e. one is a clone of the other.
+impl PartialEq for MockTransport {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for MockTransport {}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::client::ClientError;
+    use crate::protocol::ResizeBody;
+    use crate::types::StreamEvent;
+
+    #[tokio::test]
+    async fn test_mock_transport() {
+        let mock = MockTransport::new();
+        mock.respond("POST", "/sessions/abc/archive", DaemonResponse::SessionArchived { path: "1".into() })
+            .respond("POST", "/sessions/abc/archive", DaemonResponse::SessionArchived { path: "2".into() })
+            .respond("POST", "/sessions/abc/resize", DaemonResponse::Pong)
+            .respond(
+                "DELETE",
+                "/sessions/gone",
+                DaemonResponse::Error {
+                    code: ErrorCode::SessionNotFound,
+                    message: "Session not found: gone".into(),
+                },
+            );
+        let client = mock.client();
+
+        assert_eq!(client.archive_session("abc").await.unwrap(), "1");
+        assert_eq!(client.archive_session("abc").await.unwrap(), "2");
+        assert_eq!(client.archive_session("abc").await.unwrap(), "2");
+        client.resize_session("abc", 40, 120).await.unwrap();
+        assert!(matches!(
+            client.destroy_session("gone").await,
+            Err(ClientError::SessionNotFound(_))
+        ));
+        assert!(matches!(client.list_sessions().await, Err(ClientError::NotFound(_))));
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 6);
+        let body: ResizeBody = requests[3].json().unwrap();
+        assert_eq!(body, ResizeBody { rows: 40, cols: 120 });
+        assert_eq!(requests[5].method, "GET");
+        assert_eq!(requests[5].path, "/sessions");
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_events() {
+        let mock = MockTransport::new();
+        mock.respond_events(
+            "/sessions/abc/stream",
+            vec![
+                SseEvent {
+                    event: "message".to_string(),
+                    data: r#"{"type":"text_delta","text":"hi"}"#.to_string(),
+                    id: Some("1".to_string()),
+                },
+                SseEvent {
+                    event: "message".to_string(),
+                    data: r#"{"type":"idle"}"#.to_string(),
+                    id: Some("2".to_string()),
+                },
+            ],
+        );
+
+        let mut events = mock.client().subscribe_events("abc").await.unwrap();
+        assert!(matches!(events.next().await, Some(StreamEvent::TextDelta { text }) if text == "hi"));
+        assert!(matches!(events.next().await, Some(StreamEvent::Idle)));
+    }
+}
@@ -1,6 +1,19 @@
+use std::collections::{BTreeMap, HashMap};
+
 use serde::{Deserialize, Serialize};
 
-use crate::types::{BranchInfo, DaemonStatus, DiffSummary, GitLogEntry, GitStatus, Message, Milestone, Session, SessionId};
+use crate::types::{
+    Attachment, AuditEntry, BranchInfo, CliInfo, CloneMode, CliSessionInfo, CommandRun, Compaction, Comparison, CrashReport, DaemonStatus, DiffSummary, EffectiveConfig, FileEntry, GitLogEntry, GitStatus, HistoryImport, HistorySource, MemoryFile, MemorySuggestion, Message, Milestone,
+    PermissionMode, PullRequest, PurgeReport, RenderedPrompt, RetentionCandidate, Review, ReviewAction, SandboxSettings, SavedPrompt, Schedule, ScheduleRun, SearchResults, Session, SessionId, SessionSettings, SessionTemplate, SessionUsage, Snapshot, StreamEvent,
+    UsageReport, VersionInfo,
+};
+
+/// Version of the daemon HTTP API, reported by `/version` and `/health`.
+///
+/// Bumped on any change an older client or daemon can't cope with. Clients
+/// refuse to talk to a daemon with a different version (see
+/// `DaemonClient::connect`).
+pub const PROTOCOL_VERSION: u32 = 1;
 
 /// Requests that can be sent to the daemon.
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,24 +49,122 @@ pub enum DaemonRequest {
     },
 }
 
+/// Machine-readable category of a `DaemonResponse::Error`.
+///
+/// Mirrors the HTTP status the daemon responds with (401/402/403/404/409/422/500).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The session does not exist.
+    SessionNotFound,
+    /// Some other resource (commit, file, message) does not exist.
+    NotFound,
+    /// The request conflicts with current state (e.g. git conflict, nothing to cancel).
+    Conflict,
+    /// The request is malformed or fails validation.
+    Validation,
+    /// The session's working directory is not a git repository.
+    NotARepository,
+    /// Missing or invalid credentials.
+    Unauthorized,
+    /// A budget limit is exceeded and the daemon refuses new messages.
+    BudgetExceeded,
+    /// The working directory hasn't been trusted with `POST /workspaces/trust`.
+    WorkspaceUntrusted,
+    /// Unexpected server-side failure.
+    #[default]
+    Internal,
+}
+
 /// Responses from the daemon.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum DaemonResponse {
     /// Health check response with daemon status.
     Health { status: DaemonStatus },
+    /// Daemon build and protocol version.
+    Version { version: VersionInfo },
+    /// The Claude CLI the daemon spawns, and whether it works.
+    CliInfo { info: CliInfo },
+    /// Claude CLI sessions for a working directory, newest first.
+    CliSessions { sessions: Vec<CliSessionInfo> },
+    /// Coding agents with chat history for a working directory.
+    HistorySources { sources: Vec<HistorySource> },
+    /// History imported into a session's conversation.
+    HistoryImported { import: HistoryImport },
+    /// A graceful restart handed over to the successor daemon with this pid.
+    Restarting { pid: u32 },
+    /// Daemon data was deleted.
+    Purged { report: PurgeReport },
+    /// Recent lines of the daemon log, oldest first.
+    Logs { lines: Vec<String> },
+    /// Crash reports, newest first.
+    CrashReports { reports: Vec<CrashReport> },
+    /// A single crash report.
+    CrashReport { report: CrashReport },
     /// List of sessions.
     Sessions { sessions: Vec<Session> },
     /// A session was created.
     SessionCreated { session: Session },
+    /// A session's fields were changed.
+    SessionUpdated { session: Session },
+    /// A session's process was respawned.
+    SessionRevived { session: Session },
+    /// A session was archived to this file and removed.
+    SessionArchived { path: String },
+    /// A session's current settings.
+    SessionSettings { settings: SessionSettings },
+    /// The settings that apply to a session, with where each comes from.
+    EffectiveConfig { config: EffectiveConfig },
+    /// Recent PTY output of a session, base64, and the stream offset at
+    /// its end.
+    Scrollback { data: String, offset: u64 },
+    /// Dead sessions the retention policy would remove on its next run.
+    RetentionPlan { candidates: Vec<RetentionCandidate> },
     /// An error occurred.
-    Error { message: String },
+    Error {
+        /// Machine-readable error category.
+        #[serde(default)]
+        code: ErrorCode,
+        message: String,
+    },
     /// Pong response to a ping.
     Pong,
     /// A milestone was saved.
     MilestoneSaved { milestone: Milestone },
     /// List of milestones.
     Milestones { milestones: Vec<Milestone> },
+    /// A snapshot of the workspace and conversation was saved.
+    SnapshotSaved { snapshot: Snapshot },
+    /// A session's snapshots, newest first.
+    Snapshots { snapshots: Vec<Snapshot> },
+    /// Changes since a turn, for review.
+    Review { review: Review },
+    /// A turn's file changes were undone; the files they touched.
+    TurnReverted { files: Vec<String> },
+    /// A pull request was opened.
+    PullRequestCreated { pull_request: PullRequest },
+    /// Pull requests for a branch, newest first.
+    PullRequests { pull_requests: Vec<PullRequest> },
+    /// A session's branch was merged back; `commit` is where the target
+    /// branch now points.
+    BranchMerged { session: Session, commit: String },
+    /// Older messages were replaced by a summary.
+    Compacted { compaction: Compaction },
+    /// A comparison was started.
+    ComparisonStarted { comparison: Comparison },
+    /// A session's comparisons, newest first.
+    Comparisons { comparisons: Vec<Comparison> },
+    /// A comparison's variant was applied to the session.
+    ComparisonApplied { comparison: Comparison },
+    /// A project's commands, by name.
+    ProjectCommands { commands: BTreeMap<String, String> },
+    /// A project command was started.
+    CommandRunStarted { run: CommandRun },
+    /// A session's command runs, newest first.
+    CommandRuns { runs: Vec<CommandRun> },
     /// Diff result between two commits.
     DiffResult { diff: DiffSummary },
     /// Current workspace changes (uncommitted).
@@ -70,12 +181,658 @@ pub enum DaemonResponse {
     GitBranchInfo { info: BranchInfo },
     /// Git push succeeded.
     GitPushResult,
+    /// The session's working directory is not inside a git repository.
+    NotARepository { path: String },
+    /// A git repository was initialized (or already existed) for a session.
+    GitInitialized { repo_root: String },
+    /// Roots of the git repositories in a session's workspace.
+    GitRepos { repos: Vec<String> },
+    /// A directory of a session's workspace, as a tree.
+    Files {
+        entries: Vec<FileEntry>,
+        /// The listing stopped at the entry limit.
+        truncated: bool,
+    },
+    /// A text file of a session's workspace. Send `etag` back when writing
+    /// it to detect changes made in the meantime.
+    FileContent {
+        path: String,
+        content: String,
+        etag: String,
+    },
+    /// A file was written; `etag` identifies the new contents.
+    FileWritten { etag: String },
+    /// Lines matching a search of a session's workspace.
+    SearchResults { results: SearchResults },
 
     // Chat mode responses
     /// Full conversation history.
     Messages { messages: Vec<Message> },
-    /// Acknowledgment that a message was received and streaming started.
+    /// Acknowledgment that a message was received and streaming started, or
+    /// that it was queued behind an in-progress response.
     MessageAccepted { message_id: String },
-    /// Acknowledgment that cancellation was requested.
+    /// Acknowledgment that cancellation was requested (or a queued message removed).
     CancelAccepted,
+    /// A session's token usage and context window utilization.
+    SessionUsage { usage: SessionUsage },
+    /// Daemon-wide token usage and cost per day and project.
+    Usage { report: UsageReport },
+    /// Scheduled prompts.
+    Schedules { schedules: Vec<Schedule> },
+    /// A prompt was scheduled.
+    ScheduleCreated { schedule: Schedule },
+    /// Runs of a schedule, newest first.
+    ScheduleRuns { runs: Vec<ScheduleRun> },
+    /// A conversation rendered for export, with a file name to save it under.
+    ConversationExport { file_name: String, content: String },
+    /// This many messages were deleted from a conversation.
+    MessagesDeleted { count: usize },
+    /// A file was uploaded; send its id with a message to attach it.
+    AttachmentUploaded { attachment: Attachment },
+    /// Session templates, by name.
+    Templates { templates: Vec<SessionTemplate> },
+    /// A session template was added or replaced.
+    TemplateSaved { template: SessionTemplate },
+    /// A session's project memory file.
+    Memory { memory: MemoryFile },
+    /// Memory entries waiting to be accepted or dismissed, oldest first.
+    MemorySuggestions { suggestions: Vec<MemorySuggestion> },
+    /// Saved prompts, by name.
+    Prompts { prompts: Vec<SavedPrompt> },
+    /// A prompt was added to the library or replaced.
+    PromptSaved { prompt: SavedPrompt },
+    /// A saved prompt with its variables filled in.
+    PromptRendered { rendered: RenderedPrompt },
+    /// Sessions may now run in this directory and below.
+    WorkspaceTrusted { path: String },
+    /// A session's audit log, oldest first.
+    AuditLog { entries: Vec<AuditEntry> },
+    /// An audit log rendered for export, with a file name to save it under.
+    AuditExport { file_name: String, content: String },
+}
+
+// ── Request bodies ──
+//
+// JSON bodies of the daemon's HTTP endpoints, shared by `DaemonClient` and
+// the daemon so the two can't drift apart. Optional fields may be omitted.
+
+/// Body of `POST /sessions`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateSessionBody {
+    pub name: String,
+    /// Defaults to `default_model` from config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cols: Option<u16>,
+    /// Working directory for the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Instructions appended to Claude's system prompt in chat mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Defaults to `ask`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<PermissionMode>,
+    /// Defaults to unsandboxed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxSettings>,
+    /// Program to run instead of the Claude CLI: a name on PATH or a path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Arguments for `command`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Environment variables for the session's processes; see `Session::env`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// MCP server config files passed to Claude with `--mcp-config`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_config: Vec<String>,
+    /// Defaults to `auto_milestone` from config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_milestone: Option<bool>,
+    /// Name of a template (see `GET /templates`) to take the fields left
+    /// out from. `env` is merged into the template's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+/// Body of `POST /tasks`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateTaskBody {
+    pub prompt: String,
+    /// Directory to run in. Must exist.
+    pub cwd: String,
+    /// Defaults to `default_model` from config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Instructions appended to Claude's system prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub permission_mode: PermissionMode,
+}
+
+/// Body of `POST /schedules`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateScheduleBody {
+    /// Five-field cron expression in local time, e.g. `0 2 * * *`.
+    pub cron: String,
+    pub prompt: String,
+    /// Directory to run in. Must exist.
+    pub cwd: String,
+    /// Defaults to `default_model` from config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub permission_mode: PermissionMode,
+}
+
+/// Body of `POST /sessions/{id}/input`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InputBody {
+    /// Base64-encoded input data.
+    pub data: String,
+    /// Write the data as a bracketed paste, in chunks the program can keep
+    /// up with, rather than as typed keystrokes.
+    #[serde(default)]
+    pub paste: bool,
+}
+
+/// Body of `POST /sessions/{id}/resize`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResizeBody {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// Body of `POST /sessions/{id}/revive` and `POST /sessions/{id}/restart`.
+/// Defaults to 24x80.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReviveSessionBody {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cols: Option<u16>,
+}
+
+/// Body of `POST /sessions/{id}/save`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SaveMilestoneBody {
+    pub message: String,
+}
+
+/// Body of `POST /sessions/{id}/restore`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RestoreMilestoneBody {
+    pub oid: String,
+}
+
+/// Body of `POST /sessions/{id}/git/stage` and `/git/unstage`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StageFileBody {
+    pub file_path: String,
+}
+
+/// Body of `POST /sessions/{id}/git/stage-files` and `/git/unstage-files`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StageFilesBody {
+    pub file_paths: Vec<String>,
+}
+
+/// Body of `POST /sessions/{id}/git/stage-hunk`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StageHunkBody {
+    pub file_path: String,
+    pub hunk_index: usize,
+}
+
+/// Body of `POST /sessions/{id}/messages` and `/messages/{message_id}/edit`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SendMessageBody {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Workspace files (relative to the working directory) to include as context.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_files: Vec<String>,
+    /// Ids of attachments uploaded to `/sessions/{id}/attachments`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+}
+
+/// Body of `POST /sessions/{id}/messages/current/input`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResponseInputBody {
+    /// The answer to the Claude CLI's prompt.
+    pub text: String,
+}
+
+/// Body of `PUT /sessions/{id}/files/content`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WriteFileBody {
+    pub content: String,
+    /// ETag from reading the file; the write fails with 409 if the file
+    /// changed since. An empty string requires that the file does not exist.
+    /// May also be sent as an `If-Match` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+/// Body of `POST /sessions/{id}/clone`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CloneSessionBody {
+    /// Directory to clone the workspace into; must not exist or be empty.
+    pub target_dir: String,
+    #[serde(default)]
+    pub mode: CloneMode,
+    /// Carry the conversation over to the new session.
+    #[serde(default)]
+    pub fork_conversation: bool,
+    /// Name of the new session; the original's with " (clone)" if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Body of `POST /sessions/{id}/git/pull-request`. The current branch,
+/// which must have been pushed, is proposed for merging into `base`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreatePullRequestBody {
+    /// Required unless `from_conversation` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Target branch; the repository's default branch if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    /// Fill in a missing title and body from the session's conversation.
+    #[serde(default)]
+    pub from_conversation: bool,
+}
+
+/// Body of `POST /sessions/{id}/git/merge`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MergeBranchBody {
+    /// Branch to merge into; the one the session's branch was created from
+    /// if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub into: Option<String>,
+    /// Keep the session's branch after merging instead of deleting it.
+    #[serde(default)]
+    pub keep_branch: bool,
+}
+
+/// Body of `POST /sessions/{id}/compact`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CompactBody {
+    /// Latest messages to keep as they are; defaults to the last 4. Moved
+    /// back to the start of a turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_recent: Option<usize>,
+    /// What the summary should focus on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// Body of `POST /sessions/{id}/messages/compare`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CompareBody {
+    pub content: String,
+    /// Two or three models to answer, e.g. `["sonnet", "opus"]`.
+    pub models: Vec<String>,
+}
+
+/// Body of `POST /sessions/{id}/comparisons/{comparison_id}/apply`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApplyComparisonBody {
+    pub variant_id: String,
+}
+
+/// Body of `POST /sessions/{id}/run`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RunCommandBody {
+    /// Name of the command in the project's `[commands]`.
+    pub name: String,
+    /// If the command fails, send its output to the conversation and ask
+    /// for a fix.
+    #[serde(default)]
+    pub feed_back: bool,
+}
+
+/// Body of `POST /sessions/{id}/review/apply`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApplyReviewBody {
+    /// The message the review is since; the latest turn if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since_message_id: Option<String>,
+    pub decisions: Vec<ReviewDecision>,
+}
+
+/// A decision on one file of a review.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReviewDecision {
+    pub path: String,
+    pub action: ReviewAction,
+}
+
+/// Body of `PUT /sessions/{id}/memory`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WriteMemoryBody {
+    pub content: String,
+    /// ETag from reading the file; the write is refused if it changed
+    /// since. Empty requires that it doesn't exist yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+/// Body of `POST /sessions/{id}/memory/append`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AppendMemoryBody {
+    /// What to remember; added as a list item of one line.
+    pub entry: String,
+}
+
+/// Body of `POST /prompts/{name}/render`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RenderPromptBody {
+    /// Session to render for: its project's prompts apply, and `{{branch}}`
+    /// is its git branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Values by variable name, e.g. `file`; override the session's.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, String>,
+}
+
+/// Body of `POST /workspaces/trust`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TrustWorkspaceBody {
+    /// Directory to trust, with everything below it.
+    pub path: String,
+}
+
+// ── PTY stream (v1) ──
+
+/// Version of the `/sessions/{id}/pty/stream` protocol, echoed in the
+/// `X-Mado-Pty-Stream-Version` response header.
+pub const PTY_STREAM_VERSION: u32 = 1;
+
+/// Media type selecting binary frames on `/sessions/{id}/pty/stream`.
+/// Any other `Accept` value gets the SSE encoding.
+pub const PTY_STREAM_BINARY_MEDIA_TYPE: &str = "application/vnd.mado.pty.v1";
+
+/// Frame kinds on the binary PTY stream.
+///
+/// Each frame is `[kind: u8][len: u32 big-endian][payload: len bytes]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PtyFrameKind {
+    /// Replay of recent output, sent once before live output.
+    Scrollback = 1,
+    /// Live output.
+    Output = 2,
+    /// The process exited. The payload is the exit code as a big-endian
+    /// `i32`, or empty if unknown; the stream ends after it.
+    Exit = 3,
+}
+
+impl PtyFrameKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Scrollback),
+            2 => Some(Self::Output),
+            3 => Some(Self::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// Length of a binary PTY frame header.
+pub const PTY_FRAME_HEADER_LEN: usize = 5;
+
+/// Encode a binary PTY frame.
+pub fn encode_pty_frame(kind: PtyFrameKind, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(PTY_FRAME_HEADER_LEN + payload.len());
+    frame.push(kind as u8);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Decode one binary PTY frame from the front of `buf`.
+///
+/// Returns the frame and the number of bytes consumed, or `None` if `buf` does
+/// not yet hold a complete frame. Unknown frame kinds are returned as `Err` with
+/// the raw kind byte so callers can skip them.
+pub fn decode_pty_frame(buf: &[u8]) -> Option<(Result<PtyFrameKind, u8>, &[u8], usize)> {
+    if buf.len() < PTY_FRAME_HEADER_LEN {
+        return None;
+    }
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    let end = PTY_FRAME_HEADER_LEN + len;
+    if buf.len() < end {
+        return None;
+    }
+    let kind = PtyFrameKind::from_u8(buf[0]).ok_or(buf[0]);
+    Some((kind, &buf[PTY_FRAME_HEADER_LEN..end], end))
+}
+
+// ── Session WebSocket ──
+
+/// Version of the `/sessions/{id}/ws` protocol, sent in `WsServerMessage::Connected`.
+pub const WS_PROTOCOL_VERSION: u32 = 1;
+
+/// Control messages sent by the client on `/sessions/{id}/ws` as text frames.
+///
+/// PTY input is sent as binary frames carrying raw bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsClientMessage {
+    /// Resize the session's PTY.
+    Resize { rows: u16, cols: u16 },
+    /// Send a chat message and start streaming the response.
+    SendMessage {
+        content: String,
+        /// Override model for this message.
+        #[serde(default)]
+        model: Option<String>,
+        /// Workspace files to include as context.
+        #[serde(default)]
+        context_files: Vec<String>,
+        /// Ids of uploaded attachments.
+        #[serde(default)]
+        attachments: Vec<String>,
+    },
+    /// Cancel the in-progress chat response.
+    Cancel,
+}
+
+/// Messages sent by the daemon on `/sessions/{id}/ws` as text frames.
+///
+/// PTY output is sent as binary frames, each holding exactly one frame in the
+/// `encode_pty_frame` format (scrollback, output, exit).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsServerMessage {
+    /// First message on every connection.
+    Connected {
+        version: u32,
+        /// Whether PTY frames will follow (false for sessions without a live process).
+        pty: bool,
+    },
+    /// A chat stream event.
+    Event { event: StreamEvent },
+    /// A `SendMessage` was accepted.
+    MessageAccepted { message_id: String },
+    /// A `Cancel` was accepted.
+    CancelAccepted,
+    /// A client message failed. The connection stays open.
+    Error {
+        #[serde(default)]
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pty_frame_roundtrip() {
+        let mut buf = encode_pty_frame(PtyFrameKind::Scrollback, b"hello");
+        buf.extend(encode_pty_frame(PtyFrameKind::Exit, b""));
+
+        let (kind, payload, used) = decode_pty_frame(&buf).unwrap();
+        assert_eq!(kind, Ok(PtyFrameKind::Scrollback));
+        assert_eq!(payload, b"hello");
+
+        let (kind, payload, used2) = decode_pty_frame(&buf[used..]).unwrap();
+        assert_eq!(kind, Ok(PtyFrameKind::Exit));
+        assert!(payload.is_empty());
+        assert_eq!(used + used2, buf.len());
+
+        // Partial frames wait for more data.
+        assert!(decode_pty_frame(&buf[..3]).is_none());
+        assert!(decode_pty_frame(&buf[..7]).is_none());
+    }
+
+    #[test]
+    fn test_error_code_defaults_to_internal() {
+        let json = r#"{"type":"error","message":"boom"}"#;
+        match serde_json::from_str::<DaemonResponse>(json).unwrap() {
+            DaemonResponse::Error { code, message } => {
+                assert_eq!(code, ErrorCode::Internal);
+                assert_eq!(message, "boom");
+            }
+            other => panic!("Expected Error, got: {:?}", other),
+        }
+
+        let json = serde_json::to_string(&DaemonResponse::Error {
+            code: ErrorCode::SessionNotFound,
+            message: "gone".into(),
+        })
+        .unwrap();
+        assert!(json.contains(r#""code":"session_not_found""#));
+    }
+
+    fn roundtrip<T>(body: &T) -> String
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_string(body).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), body);
+        json
+    }
+
+    #[test]
+    fn test_request_body_roundtrip() {
+        let json = roundtrip(&CreateSessionBody {
+            name: "api".into(),
+            model: Some("opus".into()),
+            rows: Some(40),
+            cols: Some(120),
+            cwd: Some("/src/api".into()),
+            system_prompt: None,
+            permission_mode: Some(PermissionMode::Plan),
+            sandbox: Some(SandboxSettings { enabled: true, allowed_hosts: vec!["github.com".into()] }),
+            command: Some("aider".into()),
+            args: vec!["--no-git".into()],
+            env: HashMap::from([("OPENAI_API_KEY".into(), "keychain:openai".into())]),
+            mcp_config: Vec::new(),
+            auto_milestone: Some(true),
+            template: Some("backend".into()),
+        });
+        assert!(!json.contains("system_prompt"));
+        assert!(json.contains(r#""permission_mode":"plan""#), "{}", json);
+
+        roundtrip(&CreateTaskBody {
+            prompt: "Fix the build".into(),
+            cwd: "/src/api".into(),
+            model: None,
+            system_prompt: Some("Be brief".into()),
+            permission_mode: PermissionMode::AutoEdit,
+        });
+        roundtrip(&CreateScheduleBody {
+            cron: "0 2 * * *".into(),
+            prompt: "Update the CHANGELOG".into(),
+            cwd: "/src/api".into(),
+            model: Some("sonnet".into()),
+            permission_mode: PermissionMode::Ask,
+        });
+        roundtrip(&InputBody { data: "bHM=".into(), paste: true });
+        roundtrip(&ResizeBody { rows: 24, cols: 80 });
+        roundtrip(&ReviveSessionBody::default());
+        roundtrip(&SaveMilestoneBody { message: "Before refactor".into() });
+        roundtrip(&RestoreMilestoneBody { oid: "abc123".into() });
+        roundtrip(&StageFileBody { file_path: "src/main.rs".into() });
+        roundtrip(&StageFilesBody {
+            file_paths: vec!["a.rs".into(), "b.rs".into()],
+        });
+        roundtrip(&StageHunkBody {
+            file_path: "src/main.rs".into(),
+            hunk_index: 2,
+        });
+        roundtrip(&SendMessageBody {
+            content: "hi".into(),
+            model: None,
+            context_files: vec!["README.md".into()],
+            attachments: vec!["att-1".into()],
+        });
+        roundtrip(&WriteFileBody {
+            content: "fn main() {}".into(),
+            etag: Some(String::new()),
+        });
+    }
+
+    #[test]
+    fn test_request_body_defaults() {
+        let body: SendMessageBody = serde_json::from_str(r#"{"content":"hi"}"#).unwrap();
+        assert_eq!(
+            body,
+            SendMessageBody {
+                content: "hi".into(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(serde_json::to_string(&body).unwrap(), r#"{"content":"hi"}"#);
+
+        let body: CreateTaskBody = serde_json::from_str(r#"{"prompt":"p","cwd":"/"}"#).unwrap();
+        assert_eq!(body.permission_mode, PermissionMode::default());
+    }
 }
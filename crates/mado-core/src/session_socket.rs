@@ -0,0 +1,138 @@
+//! Async client for the `/sessions/{id}/ws` WebSocket.
+
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::client::{ClientError, DaemonClient};
+use crate::protocol::{decode_pty_frame, PtyFrameKind, WsClientMessage, WsServerMessage};
+use crate::transport::DaemonStream;
+
+/// Something received on a session socket.
+#[derive(Debug, Clone)]
+pub enum SessionSocketEvent {
+    /// A PTY frame (scrollback, output, or exit).
+    Pty { kind: PtyFrameKind, data: Vec<u8> },
+    /// A control message (chat event, acknowledgement, or error).
+    Message(WsServerMessage),
+}
+
+/// A WebSocket connection to one session, multiplexing PTY I/O and chat.
+///
+/// Obtain one with `DaemonClient::session_socket`.
+pub struct SessionSocket {
+    inner: WebSocketStream<DaemonStream>,
+}
+
+impl SessionSocket {
+    pub(crate) async fn connect(
+        stream: DaemonStream,
+        client: &DaemonClient,
+        session_id: &str,
+    ) -> Result<Self, ClientError> {
+        let url = format!("ws://{}/sessions/{}/ws", client.endpoint().host_header(), session_id);
+        let mut request = url.into_client_request()?;
+        if let Some(authorization) = client.authorization() {
+            let value = HeaderValue::from_str(&authorization).map_err(|_| ClientError::Unauthorized)?;
+            request.headers_mut().insert("Authorization", value);
+        }
+
+        match tokio_tungstenite::client_async(request, stream).await {
+            Ok((inner, _)) => Ok(Self { inner }),
+            Err(tungstenite::Error::Http(resp)) => {
+                // The daemon rejects the upgrade with a JSON error body.
+                let error = resp
+                    .body()
+                    .as_deref()
+                    .and_then(|body| serde_json::from_slice(body).ok());
+                match error {
+                    Some(crate::protocol::DaemonResponse::Error { code, message }) => {
+                        Err(ClientError::from_daemon(code, message))
+                    }
+                    _ => Err(ClientError::UnexpectedResponse),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write raw bytes to the session's PTY.
+    pub async fn send_input(&mut self, data: &[u8]) -> Result<(), ClientError> {
+        self.inner.send(Message::Binary(data.to_vec().into())).await?;
+        Ok(())
+    }
+
+    /// Send a control message.
+    pub async fn send(&mut self, message: &WsClientMessage) -> Result<(), ClientError> {
+        let text = serde_json::to_string(message)?;
+        self.inner.send(Message::Text(text.into())).await?;
+        Ok(())
+    }
+
+    /// Resize the session's PTY.
+    pub async fn resize(&mut self, rows: u16, cols: u16) -> Result<(), ClientError> {
+        self.send(&WsClientMessage::Resize { rows, cols }).await
+    }
+
+    /// Send a chat message. Acceptance arrives as `WsServerMessage::MessageAccepted`.
+    pub async fn send_message(
+        &mut self,
+        content: &str,
+        model: Option<&str>,
+        context_files: &[String],
+        attachments: &[String],
+    ) -> Result<(), ClientError> {
+        self.send(&WsClientMessage::SendMessage {
+            content: content.to_string(),
+            model: model.map(|m| m.to_string()),
+            context_files: context_files.to_vec(),
+            attachments: attachments.to_vec(),
+        })
+        .await
+    }
+
+    /// Cancel the in-progress chat response.
+    pub async fn cancel(&mut self) -> Result<(), ClientError> {
+        self.send(&WsClientMessage::Cancel).await
+    }
+
+    /// Receive the next event. Returns `None` once the connection is closed.
+    pub async fn next_event(&mut self) -> Option<Result<SessionSocketEvent, ClientError>> {
+        loop {
+            let message = match self.inner.next().await? {
+                Ok(message) => message,
+                Err(e) => return Some(Err(e.into())),
+            };
+            match message {
+                Message::Binary(data) => match decode_pty_frame(&data) {
+                    Some((Ok(kind), payload, _)) => {
+                        return Some(Ok(SessionSocketEvent::Pty {
+                            kind,
+                            data: payload.to_vec(),
+                        }));
+                    }
+                    // Unknown frame kinds are skipped for forward compatibility.
+                    Some((Err(_), _, _)) => continue,
+                    None => return Some(Err(ClientError::UnexpectedResponse)),
+                },
+                Message::Text(text) => {
+                    return Some(
+                        serde_json::from_str(text.as_str())
+                            .map(SessionSocketEvent::Message)
+                            .map_err(ClientError::from),
+                    );
+                }
+                Message::Close(_) => return None,
+                _ => continue,
+            }
+        }
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) -> Result<(), ClientError> {
+        self.inner.close(None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Server-sent event streams from the daemon.
+//!
+//! The daemon's long-lived streams (`/sessions/{id}/stream`,
+//! `/sessions/{id}/pty/stream`, `/sessions/{id}/runs/{run_id}/stream`,
+//! `/sessions/{id}/comparisons/{comparison_id}/stream`, `/events`) are SSE.
+//! An [`EventSource`] reads one and, when the connection drops, reconnects
+//! with `Last-Event-ID` so the daemon replays what was missed.
+//! `DaemonClient::subscribe_events`, `subscribe_output`, `follow_run`,
+//! `follow_comparison` and `subscribe_activity` build typed streams on it.
+
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper_util::rt::TokioIo;
+
+use crate::client::{ClientError, DaemonClient};
+use crate::protocol::DaemonResponse;
+
+/// Wait before the first reconnect; doubled after each failed attempt.
+const RECONNECT_DELAY: Duration = Duration::from_millis(250);
+
+/// Failed reconnects in a row after which a stream ends.
+const RECONNECT_ATTEMPTS: u32 = 6;
+
+/// A stream of events from the daemon that reconnects by itself. It ends
+/// when the daemon ends it for good (e.g. the session exited) or can't be
+/// reached again.
+pub type EventStream<T> = BoxStream<'static, T>;
+
+/// One server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Event name; empty for unnamed events.
+    pub event: String,
+    /// Data lines, joined with `\n`.
+    pub data: String,
+    pub id: Option<String>,
+}
+
+/// Splits received bytes into server-sent events. Bytes are buffered until
+/// an event is complete, so a character split across reads stays intact.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Add received bytes, returning the events they complete.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let bytes: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let text = String::from_utf8_lossy(&bytes);
+            let mut event = SseEvent::default();
+            let mut data = Vec::new();
+            for line in text.lines() {
+                let (field, value) = line.split_once(':').unwrap_or((line, ""));
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                match field {
+                    "event" => event.event = value.to_string(),
+                    "data" => data.push(value),
+                    "id" => event.id = Some(value.to_string()),
+                    _ => {} // Comments (keep-alives) and `retry`.
+                }
+            }
+            // Keep-alive comments make empty events; they aren't dispatched.
+            if event.event.is_empty() && data.is_empty() {
+                continue;
+            }
+            event.data = data.join("\n");
+            events.push(event);
+        }
+        events
+    }
+}
+
+/// Reads one SSE endpoint, reconnecting when the connection drops.
+pub struct EventSource {
+    client: DaemonClient,
+    path: String,
+    /// Id of the last event received, sent as `Last-Event-ID` on reconnect.
+    last_event_id: Option<String>,
+    body: Option<Incoming>,
+    parser: SseParser,
+    pending: VecDeque<SseEvent>,
+}
+
+impl EventSource {
+    /// Connect to `path`. Returns once the daemon has accepted the request,
+    /// so events sent after this returns are not missed.
+    pub async fn connect(client: &DaemonClient, path: &str) -> Result<Self, ClientError> {
+        let mut source = Self {
+            client: client.clone(),
+            path: path.to_string(),
+            last_event_id: None,
+            body: None,
+            parser: SseParser::default(),
+            pending: VecDeque::new(),
+        };
+        source.body = Some(source.open().await?);
+        Ok(source)
+    }
+
+    async fn open(&self) -> Result<Incoming, ClientError> {
+        let io = TokioIo::new(self.client.open_stream().await?);
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+            .await
+            .map_err(ClientError::HttpError)?;
+
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                tracing::debug!("SSE connection error: {}", e);
+            }
+        });
+
+        let mut request = self.client.request(&self.path).header("Accept", "text/event-stream");
+        if let Some(id) = &self.last_event_id {
+            request = request.header("Last-Event-ID", id.as_str());
+        }
+        let req = request
+            .body(Full::new(Bytes::new()))
+            .expect("Failed to build request");
+
+        let resp = sender.send_request(req).await.map_err(ClientError::HttpError)?;
+        if resp.status().is_success() {
+            return Ok(resp.into_body());
+        }
+        let body = resp.into_body().collect().await.map_err(ClientError::HttpError)?;
+        match serde_json::from_slice(&body.to_bytes()) {
+            Ok(DaemonResponse::Error { code, message }) => Err(ClientError::from_daemon(code, message)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Reconnect with `Last-Event-ID`, backing off between attempts. Returns
+    /// false if every attempt failed.
+    async fn reconnect(&mut self) -> bool {
+        // A partial event from the old connection will never be completed.
+        self.parser = SseParser::default();
+        let mut delay = RECONNECT_DELAY;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            match self.open().await {
+                Ok(body) => {
+                    tracing::debug!("Reconnected to {}", self.path);
+                    self.body = Some(body);
+                    return true;
+                }
+                Err(e) => {
+                    tracing::debug!("Reconnect {} to {} failed: {}", attempt, self.path, e);
+                    delay *= 2;
+                }
+            }
+        }
+        tracing::warn!("Gave up reconnecting to {}", self.path);
+        false
+    }
+
+    /// The next event, or `None` once the daemon can't be reached again.
+    pub async fn next(&mut self) -> Option<SseEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                if event.id.is_some() {
+                    self.last_event_id.clone_from(&event.id);
+                }
+                return Some(event);
+            }
+
+            let frame = match &mut self.body {
+                Some(body) => body.frame().await,
+                None => None,
+            };
+            match frame {
+                Some(Ok(frame)) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.pending.extend(self.parser.push(&data));
+                    }
+                }
+                Some(Err(e)) => {
+                    tracing::debug!("SSE stream {} failed: {}", self.path, e);
+                    self.body = None;
+                    if !self.reconnect().await {
+                        return None;
+                    }
+                }
+                None => {
+                    tracing::debug!("SSE stream {} ended", self.path);
+                    self.body = None;
+                    if !self.reconnect().await {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Turn into a stream of what `map` makes of each event: an item, nothing
+    /// (the event is skipped), or `Break` to end the stream.
+    pub fn into_stream<T, F>(self, map: F) -> EventStream<T>
+    where
+        T: Send + 'static,
+        F: FnMut(SseEvent) -> ControlFlow<(), Option<T>> + Send + 'static,
+    {
+        let stream = futures::stream::unfold(Some((self, map)), |state| async move {
+            let (mut source, mut map) = state?;
+            loop {
+                let event = source.next().await?;
+                match map(event) {
+                    ControlFlow::Continue(Some(item)) => return Some((item, Some((source, map)))),
+                    ControlFlow::Continue(None) => continue,
+                    ControlFlow::Break(()) => return None,
+                }
+            }
+        });
+        Box::pin(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: connected\ndata: connected\n").is_empty());
+
+        let events = parser.push(b"\nevent: message\nid: 7\ndata: {\"type\":\"idle\"}\n\n: ping\n\ndata: a\ndata: b\n\n");
+        assert_eq!(
+            events,
+            [
+                SseEvent {
+                    event: "connected".to_string(),
+                    data: "connected".to_string(),
+                    id: None,
+                },
+                SseEvent {
+                    event: "message".to_string(),
+                    data: "{\"type\":\"idle\"}".to_string(),
+                    id: Some("7".to_string()),
+                },
+                SseEvent {
+                    event: String::new(),
+                    data: "a\nb".to_string(),
+                    id: None,
+                },
+            ]
+        );
+
+        // A character split across reads.
+        let text = "data: é\n\n".as_bytes();
+        assert!(parser.push(&text[..7]).is_empty());
+        assert_eq!(parser.push(&text[7..])[0].data, "é");
+    }
+}
@@ -0,0 +1,247 @@
+//! Connection targets for `DaemonClient`: the local Unix socket, a remote
+//! daemon's TCP listener with optional TLS, or an in-memory mock for tests.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::client::ClientError;
+use crate::mock::MockTransport;
+
+/// Where the daemon is listening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonEndpoint {
+    /// Local Unix domain socket.
+    Unix(PathBuf),
+    /// Remote TCP listener (`tcp://HOST:PORT`, or `tls://HOST:PORT` for TLS).
+    Tcp { host: String, port: u16, tls: bool },
+    /// In-memory [`MockTransport`], for tests.
+    Memory(MockTransport),
+}
+
+impl DaemonEndpoint {
+    /// Parse `unix:///path/to/mado.sock`, `tcp://HOST:PORT` or `tls://HOST:PORT`.
+    pub fn parse(url: &str) -> Result<Self, ClientError> {
+        let invalid = || ClientError::InvalidUrl(url.to_string());
+
+        if let Some(path) = url.strip_prefix("unix://") {
+            return Ok(DaemonEndpoint::Unix(PathBuf::from(path)));
+        }
+        let (rest, tls) = match (url.strip_prefix("tcp://"), url.strip_prefix("tls://")) {
+            (Some(rest), _) => (rest, false),
+            (_, Some(rest)) => (rest, true),
+            _ => return Err(invalid()),
+        };
+
+        let (host, port) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port.parse().map_err(|_| invalid())?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(DaemonEndpoint::Tcp {
+            host: host.to_string(),
+            port,
+            tls,
+        })
+    }
+
+    /// Value for the HTTP `Host` header.
+    pub(crate) fn host_header(&self) -> String {
+        match self {
+            DaemonEndpoint::Unix(_) | DaemonEndpoint::Memory(_) => "localhost".to_string(),
+            DaemonEndpoint::Tcp { host, port, .. } if host.contains(':') => {
+                format!("[{}]:{}", host, port)
+            }
+            DaemonEndpoint::Tcp { host, port, .. } => format!("{}:{}", host, port),
+        }
+    }
+}
+
+impl std::fmt::Display for DaemonEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonEndpoint::Unix(path) => write!(f, "{}", path.display()),
+            DaemonEndpoint::Tcp { tls, .. } => {
+                write!(f, "{}://{}", if *tls { "tls" } else { "tcp" }, self.host_header())
+            }
+            DaemonEndpoint::Memory(_) => f.write_str("memory"),
+        }
+    }
+}
+
+/// A connected byte stream to the daemon, whatever the transport.
+pub trait DaemonIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> DaemonIo for T {}
+
+/// Boxed connection returned by `DaemonClient::open_stream`.
+pub type DaemonStream = Box<dyn DaemonIo>;
+
+impl Connection for DaemonStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+/// Connects `DaemonClient`'s pooled HTTP client to the endpoint, whatever
+/// the request URI says.
+#[derive(Debug, Clone)]
+pub(crate) struct Connector {
+    endpoint: DaemonEndpoint,
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Connector {
+    pub(crate) fn new(endpoint: DaemonEndpoint, tls: Option<Arc<rustls::ClientConfig>>) -> Self {
+        Self { endpoint, tls }
+    }
+}
+
+impl tower::Service<Uri> for Connector {
+    type Response = TokioIo<DaemonStream>;
+    type Error = ConnectError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, ConnectError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), ConnectError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move {
+            connect(&connector.endpoint, connector.tls.as_ref())
+                .await
+                .map(TokioIo::new)
+                .map_err(|e| ConnectError(Mutex::new(Some(e))))
+        })
+    }
+}
+
+/// Why the pooled client couldn't connect. Holds the original error so the
+/// client can return it as is rather than wrapped in a pool error.
+#[derive(Debug)]
+pub(crate) struct ConnectError(Mutex<Option<ClientError>>);
+
+impl ConnectError {
+    /// The original error; `None` if already taken.
+    pub(crate) fn take(&self) -> Option<ClientError> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &*self.0.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(e) => e.fmt(f),
+            None => f.write_str("Failed to connect to daemon"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// Build a TLS client config trusting the webpki roots plus any extra PEM CAs.
+pub(crate) fn tls_config(extra_ca_pem: Option<&[u8]>) -> Result<Arc<rustls::ClientConfig>, ClientError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    if let Some(pem) = extra_ca_pem {
+        for cert in CertificateDer::pem_slice_iter(pem) {
+            let cert = cert.map_err(|e| ClientError::Tls(e.to_string()))?;
+            roots.add(cert).map_err(|e| ClientError::Tls(e.to_string()))?;
+        }
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| ClientError::Tls(e.to_string()))?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Open a connection to the endpoint.
+pub(crate) async fn connect(
+    endpoint: &DaemonEndpoint,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> Result<DaemonStream, ClientError> {
+    match endpoint {
+        DaemonEndpoint::Unix(path) => {
+            let stream = UnixStream::connect(path)
+                .await
+                .map_err(|e| ClientError::ConnectionFailed {
+                    path: path.clone(),
+                    source: e,
+                })?;
+            Ok(Box::new(stream))
+        }
+        DaemonEndpoint::Tcp { host, port, tls: use_tls } => {
+            let stream = TcpStream::connect((host.as_str(), *port))
+                .await
+                .map_err(|e| ClientError::TcpConnectFailed {
+                    addr: endpoint.to_string(),
+                    source: e,
+                })?;
+            if !use_tls {
+                return Ok(Box::new(stream));
+            }
+
+            let config = match tls {
+                Some(config) => config.clone(),
+                None => tls_config(None)?,
+            };
+            let server_name = ServerName::try_from(host.clone())
+                .map_err(|e| ClientError::Tls(e.to_string()))?;
+            let stream = TlsConnector::from(config)
+                .connect(server_name, stream)
+                .await
+                .map_err(|e| ClientError::Tls(e.to_string()))?;
+            Ok(Box::new(stream))
+        }
+        DaemonEndpoint::Memory(mock) => Ok(mock.connect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            DaemonEndpoint::parse("unix:///tmp/mado.sock").unwrap(),
+            DaemonEndpoint::Unix(PathBuf::from("/tmp/mado.sock"))
+        );
+        assert_eq!(
+            DaemonEndpoint::parse("tls://devbox.local:7420").unwrap(),
+            DaemonEndpoint::Tcp {
+                host: "devbox.local".to_string(),
+                port: 7420,
+                tls: true,
+            }
+        );
+
+        let v6 = DaemonEndpoint::parse("tcp://[::1]:7420").unwrap();
+        assert_eq!(v6.host_header(), "[::1]:7420");
+
+        assert!(DaemonEndpoint::parse("http://devbox:7420").is_err());
+        assert!(DaemonEndpoint::parse("tcp://devbox").is_err());
+        assert!(DaemonEndpoint::parse("tcp://:7420").is_err());
+    }
+}
@@ -1,9 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 
 /// Unique identifier for a session.
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SessionId(pub String);
 
 impl SessionId {
@@ -24,6 +26,7 @@ impl fmt::Display for SessionId {
 
 /// Status of a conversation session.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
     /// Actively running with a live PTY process.
@@ -36,8 +39,64 @@ pub enum SessionStatus {
     Terminated,
 }
 
+/// What a session is doing right now, derived from PTY output recency and chat state.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SessionActivity {
+    /// Output is flowing (PTY producing output or Claude generating a response).
+    Streaming,
+    /// Recently active; waiting on the user for the next input.
+    WaitingOnUser,
+    /// No recent activity.
+    #[default]
+    Idle,
+    /// The session's process has exited.
+    Exited,
+}
+
+/// How much Claude may do without asking.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum PermissionMode {
+    /// Read and plan only; no edits or commands.
+    Plan,
+    /// Ask before edits and commands (the CLI's default).
+    #[default]
+    Ask,
+    /// Edit files without asking; ask before commands.
+    AutoEdit,
+    /// Edit and run anything without asking.
+    FullAuto,
+}
+
+impl PermissionMode {
+    /// Value of the Claude CLI's `--permission-mode` flag.
+    pub fn cli_value(self) -> &'static str {
+        match self {
+            PermissionMode::Plan => "plan",
+            PermissionMode::Ask => "default",
+            PermissionMode::AutoEdit => "acceptEdits",
+            PermissionMode::FullAuto => "bypassPermissions",
+        }
+    }
+}
+
+/// A program a session runs in place of the Claude CLI, e.g. another agent
+/// CLI such as aider or a plain tool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CustomCommand {
+    /// Name of a program on PATH, or a path to one.
+    pub program: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
 /// A conversation session managed by the daemon.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Session {
     pub id: SessionId,
     pub name: String,
@@ -47,12 +106,31 @@ pub struct Session {
     pub updated_at: DateTime<Utc>,
     #[serde(default)]
     pub working_dir: Option<String>,
+    /// Root of the git repository containing `working_dir`, if it is inside one.
+    #[serde(default)]
+    pub repo_root: Option<String>,
     /// The actual command that was spawned (e.g., "claude --model sonnet" or "/bin/zsh").
     #[serde(default)]
     pub command: Option<String>,
     /// Whether the session is running in shell fallback mode (claude not found).
     #[serde(default)]
     pub shell_fallback: bool,
+    /// Program the session runs instead of the Claude CLI, also when revived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_command: Option<CustomCommand>,
+    /// Environment variables set on the session's processes. A value of the
+    /// form `keychain:<name>` refers to a secret in the OS keychain.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Current activity classification, maintained by the daemon's presence monitor.
+    #[serde(default)]
+    pub activity: SessionActivity,
+    /// Whether the session is producing output: `activity` is `Streaming`.
+    #[serde(default)]
+    pub is_busy: bool,
+    /// When the session last produced terminal output or chat events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<DateTime<Utc>>,
     /// Current conversation state (chat mode).
     #[serde(default)]
     pub conversation_state: ConversationState,
@@ -68,19 +146,569 @@ pub struct Session {
     /// Cumulative cost in USD.
     #[serde(default)]
     pub total_cost_usd: Option<f64>,
+    /// Instructions appended to Claude's system prompt (chat mode).
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Passed to the CLI as `--permission-mode`, in chat mode and to the
+    /// terminal's process.
+    #[serde(default)]
+    pub permission_mode: PermissionMode,
+    /// The daemon keeps Claude from changing the workspace: chat turns run
+    /// in a copy of the repository that's thrown away afterwards, and the
+    /// terminal starts in plan mode whatever `permission_mode` says.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Run the session's processes confined to the workspace.
+    #[serde(default)]
+    pub sandbox: SandboxSettings,
+    /// Ask Claude to think before replying (chat mode).
+    #[serde(default)]
+    pub extended_thinking: bool,
+    /// Labels for grouping sessions, e.g. by project or ticket.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Color of the session in the UI, as `#rgb` or `#rrggbb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Pinned sessions are listed first.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Free-form notes about the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// MCP server config files passed to Claude with `--mcp-config`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_config: Vec<String>,
+    /// Flags passed to the Claude CLI after `default_flags` from
+    /// config.json, e.g. to allow more tools in this session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claude_flags: Vec<String>,
+    /// Save a milestone after every completed response; follows
+    /// `auto_milestone` in config.json when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_milestone: Option<bool>,
+    /// Exit code of the session's last process once it has exited on its
+    /// own; `None` while it runs, or if it was killed by a signal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Branch the daemon created for the session, until it is merged back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<SessionBranch>,
+}
+
+/// A session's own git branch; see `branches` in config.json.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionBranch {
+    /// e.g. "mado/fix-login-timeout".
+    pub name: String,
+    /// Branch it was created from, and is merged back into by default.
+    pub base: String,
+}
+
+/// Confinement of a session's processes by the OS sandbox (bubblewrap on
+/// Linux, `sandbox-exec` on macOS).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SandboxSettings {
+    /// Of the home directory only the working directory, programs and the
+    /// Claude CLI's state are visible, and writes are limited to the working
+    /// directory, the temp directory and that state.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hosts the processes may connect to besides `sandbox.allowed_hosts`
+    /// in config.json, e.g. `github.com` or `*.npmjs.org`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Changes to a session for `PATCH /sessions/{id}`; fields left out stay
+/// as they are.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Must be an existing directory. Applies to chat and to the next
+    /// respawn; a running terminal stays where it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// An empty string removes the system prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Applies to chat and to the next respawn; a running terminal keeps
+    /// the mode it was started with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<PermissionMode>,
+    /// Only for sessions working in a git repository. Applies like
+    /// `permission_mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    /// Replaces the sandbox settings. Applies like `permission_mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_thinking: Option<bool>,
+    /// Replaces the session's tags. Blank and repeated tags are dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// `#rgb` or `#rrggbb`; an empty string removes the color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    /// An empty string removes the notes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_milestone: Option<bool>,
+    /// Replaces the session's Claude CLI flags; applies to chat from the
+    /// next turn and to the terminal from its next respawn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_flags: Option<Vec<String>>,
+}
+
+/// A named preset for new sessions, e.g. one pane of a standard setup.
+/// Sessions created from it take every field the request leaves out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionTemplate {
+    /// Letters, digits, `-`, `_` and spaces.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<PermissionMode>,
+    /// As in [`Session::env`].
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Program to run instead of the Claude CLI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<CustomCommand>,
+    /// MCP server config files, as in [`Session::mcp_config`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_config: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_milestone: Option<bool>,
+}
+
+/// A reusable prompt from the prompt library. `{{name}}` in its content is
+/// a variable, filled in when it is rendered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SavedPrompt {
+    /// Letters, digits, `-`, `_` and spaces.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub content: String,
+    /// Where it comes from; set by the daemon.
+    #[serde(default)]
+    pub scope: PromptScope,
+    /// The variables in `content`, in order; set by the daemon.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<String>,
+}
+
+/// Where a saved prompt comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PromptScope {
+    /// The user's own, in `~/.mado/prompts`.
+    #[default]
+    User,
+    /// The `[prompts]` of the session's `.mado.toml`; overrides the user's
+    /// of the same name.
+    Project,
+}
+
+/// A saved prompt with its variables filled in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RenderedPrompt {
+    pub content: String,
+    /// Variables there was no value for, left as `{{name}}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+/// The project memory file (`CLAUDE.md`) of a session's workspace.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MemoryFile {
+    /// Absolute path, whether or not the file exists yet.
+    pub path: String,
+    pub content: String,
+    /// To write it back with; empty if the file doesn't exist yet.
+    pub etag: String,
+}
+
+/// An entry for the memory file suggested by the model, waiting for the
+/// user to accept or dismiss it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MemorySuggestion {
+    pub id: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A tool call of a chat turn that wrote files or ran a command, in a
+/// session's audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuditEntry {
+    /// When Claude made the call.
+    pub timestamp: DateTime<Utc>,
+    /// e.g. `Bash` or `Edit`.
+    pub tool: String,
+    /// The command it ran or the file it wrote, shortened to a line.
+    pub summary: String,
+    /// Files it wrote; unknown for commands.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    pub outcome: AuditOutcome,
+    /// Id of the tool call in the conversation.
+    pub tool_call_id: String,
+}
+
+/// How an audited tool call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+    /// Stopped by the daemon before it ran, in a read-only session.
+    Blocked,
+    /// The turn ended, e.g. was cancelled, before the call returned.
+    Interrupted,
+}
+
+impl AuditOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditOutcome::Succeeded => "succeeded",
+            AuditOutcome::Failed => "failed",
+            AuditOutcome::Blocked => "blocked",
+            AuditOutcome::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// Format of an audit log export.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl AuditFormat {
+    /// File extension for the format, without the dot; also the value of
+    /// the `format` query parameter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditFormat::Json => "json",
+            AuditFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Per-session settings, changeable at any point of the conversation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionSettings {
+    /// Instructions appended to Claude's system prompt, e.g. project
+    /// guardrails. Applies from the next message on.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Environment variables for the session's processes, as in
+    /// [`Session::env`]. Apply from the next message, and to the terminal
+    /// from its next restart.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
 }
 
 /// Status information about the running daemon.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DaemonStatus {
     pub pid: u32,
     pub uptime: u64,
     pub session_count: usize,
     pub version: String,
+    /// HTTP API protocol version (see `protocol::PROTOCOL_VERSION`). Zero for
+    /// daemons that predate versioning.
+    #[serde(default)]
+    pub protocol_version: u32,
+}
+
+/// Daemon build and protocol version, from `GET /version`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionInfo {
+    /// Daemon crate version.
+    pub version: String,
+    /// HTTP API protocol version.
+    pub protocol_version: u32,
+}
+
+/// The Claude CLI the daemon spawns, from `GET /cli/info`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CliInfo {
+    /// Binary the daemon runs; `None` if it found none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Output of `claude --version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub auth: CliAuth,
+    /// Whether a `--output-format stream-json` run started and reported its
+    /// session, as chat mode needs.
+    pub stream_json: bool,
+    /// Problems that will keep sessions from working, for the user to fix.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// How the Claude CLI authenticates.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum CliAuth {
+    /// With an API key from Mado's keystore or the environment.
+    ApiKey,
+    /// With a subscription login (`claude login`).
+    Login,
+    /// Neither was found.
+    #[default]
+    LoggedOut,
+}
+
+/// A Claude CLI session recorded for a working directory, from
+/// `GET /cli/sessions`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CliSessionInfo {
+    /// The CLI's session id, to import and resume.
+    pub id: String,
+    /// Start of the first prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    pub modified: Option<DateTime<Utc>>,
+    pub message_count: usize,
+}
+
+/// The settings that apply to a session and where each comes from, from
+/// `GET /sessions/{id}/config`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EffectiveConfig {
+    /// The `.mado.toml` that applies to the session, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_file: Option<String>,
+    /// Why `project_file` couldn't be read; its settings are then ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_error: Option<String>,
+    pub settings: Vec<EffectiveSetting>,
+}
+
+/// One setting's value for a session.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EffectiveSetting {
+    pub name: String,
+    pub value: serde_json::Value,
+    /// For lists combined from several layers, the highest one that adds to it.
+    pub source: ConfigSource,
+}
+
+/// The layer a setting comes from, lowest first.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// Not set anywhere.
+    #[default]
+    Default,
+    /// config.json.
+    Global,
+    /// The project's `.mado.toml`.
+    Project,
+    /// The session itself.
+    Session,
+}
+
+/// A report the daemon wrote after a panic, kept in ~/.mado/crashes/.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CrashReport {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub daemon_version: String,
+    pub pid: u32,
+    /// Supervised background task that panicked, if any.
+    #[serde(default)]
+    pub task: Option<String>,
+    #[serde(default)]
+    pub thread: Option<String>,
+    pub message: String,
+    /// Source location of the panic (`file:line:column`).
+    #[serde(default)]
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// Last daemon log lines before the panic.
+    #[serde(default)]
+    pub recent_logs: Vec<String>,
+    /// Sessions at the time of the panic; empty if the state was locked.
+    #[serde(default)]
+    pub sessions: Vec<CrashSession>,
+}
+
+/// A session as recorded in a crash report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CrashSession {
+    pub id: SessionId,
+    pub name: String,
+    pub status: SessionStatus,
+    pub conversation_state: ConversationState,
+}
+
+/// Kind of a workspace file entry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// A file or directory in a session's workspace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FileEntry {
+    pub name: String,
+    /// Path relative to the session's working directory.
+    pub path: String,
+    pub kind: FileKind,
+    /// Size in bytes (files only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// "added", "modified", "deleted" or "renamed" when the entry has
+    /// uncommitted changes; directories are "modified" when anything below
+    /// them changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_status: Option<String>,
+    /// Entries of a directory listed below the requested depth are `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(no_recursion))]
+    pub children: Option<Vec<FileEntry>>,
+}
+
+/// A line matching a workspace search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SearchMatch {
+    /// File path relative to the session's working directory.
+    pub path: String,
+    pub line_number: u64,
+    pub line: String,
+    /// Context lines before the match, not repeated from an earlier match.
+    #[serde(default)]
+    pub before: Vec<String>,
+    /// Context lines after the match.
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+/// Result of a workspace search.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SearchResults {
+    pub matches: Vec<SearchMatch>,
+    /// The search stopped at the match limit.
+    pub truncated: bool,
+}
+
+/// Why the retention policy would remove a session.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionReason {
+    /// Not updated for longer than `max_age_days`.
+    Expired,
+    /// Older than the newest `max_sessions` dead sessions.
+    OverLimit,
+}
+
+/// A dead session the retention policy would archive and remove.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RetentionCandidate {
+    pub session_id: SessionId,
+    pub name: String,
+    pub updated_at: DateTime<Utc>,
+    pub reason: RetentionReason,
+}
+
+/// What `POST /admin/purge` deletes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeScope {
+    /// Chat history, attachments and archived sessions. Sessions stay but
+    /// start new conversations.
+    Conversations,
+    /// Every session, stopping its process, with its conversation and
+    /// terminal recording.
+    Sessions,
+    /// Daemon logs and crash reports.
+    Logs,
+    /// All of the above, plus usage totals, schedules, templates and
+    /// trusted workspaces.
+    Everything,
+}
+
+impl PurgeScope {
+    /// Value of the `scope` query parameter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PurgeScope::Conversations => "conversations",
+            PurgeScope::Sessions => "sessions",
+            PurgeScope::Logs => "logs",
+            PurgeScope::Everything => "everything",
+        }
+    }
+}
+
+/// What a purge deleted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PurgeReport {
+    /// Sessions removed.
+    pub sessions: usize,
+    /// Conversations dropped.
+    pub conversations: usize,
+    /// Files and directories deleted.
+    pub paths: Vec<String>,
 }
 
 /// A saved milestone (git commit) in a session's workspace.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Milestone {
     pub oid: String,
     pub message: String,
@@ -90,8 +718,199 @@ pub struct Milestone {
     pub deletions: usize,
 }
 
+/// A milestone saved together with a copy of the session's conversation,
+/// restored as one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Snapshot {
+    pub id: String,
+    /// The workspace as it was; the latest milestone if nothing had changed
+    /// since.
+    pub milestone: Milestone,
+    /// Messages the conversation had.
+    pub message_count: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What changed in a session's workspace since one of its turns started,
+/// from `GET /sessions/{id}/review`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Review {
+    /// The user message whose turn the changes are counted from.
+    pub message_id: String,
+    /// Tree the workspace is compared with: a snapshot taken as the turn
+    /// started, or the latest milestone before it.
+    pub base: String,
+    pub files: Vec<ReviewFile>,
+}
+
+/// A file changed since the turn under review, with its unified diff.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReviewFile {
+    pub path: String,
+    /// "added", "deleted" or "modified".
+    pub status: String,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub patch: String,
+}
+
+/// What to do with a reviewed file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewAction {
+    /// Leave the agent's change in place.
+    Keep,
+    /// Put the file back as it was at the review's base.
+    Revert,
+}
+
+/// A run of one of a project's commands (`[commands]` in `.mado.toml`),
+/// from `POST /sessions/{id}/run`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CommandRun {
+    pub id: String,
+    /// Name of the command, e.g. "test".
+    pub name: String,
+    /// The shell command run.
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    /// Unset while the command runs.
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Unset while running, or if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    /// The end of the combined output, once finished.
+    #[serde(default)]
+    pub output: String,
+    /// The message that sent the failure back to the conversation, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_up_message_id: Option<String>,
+}
+
+/// Which output of a command a line came from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// An event of a command run, from `GET /sessions/{id}/runs/{run_id}/stream`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunEvent {
+    /// A line of output, newline included.
+    Output { stream: OutputStream, text: String },
+    /// The command finished; always the last event.
+    Complete { run: CommandRun },
+}
+
+/// One prompt answered by several models side by side, from
+/// `POST /sessions/{id}/messages/compare`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Comparison {
+    pub id: String,
+    pub prompt: String,
+    pub created_at: DateTime<Utc>,
+    /// Unset until every variant has answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The conversation's last message when the comparison started; a
+    /// variant can only be applied while it still is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_message_id: Option<String>,
+    pub variants: Vec<ComparisonVariant>,
+    /// Id of the variant applied to the session, once one is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied: Option<String>,
+}
+
+/// One model's answer in a [`Comparison`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ComparisonVariant {
+    pub id: String,
+    pub model: String,
+    /// The turn: the prompt and the answer. Empty until it finishes.
+    #[serde(default)]
+    pub messages: Vec<Message>,
+    /// Why the answer failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    #[serde(default)]
+    pub cost_usd: f64,
+    /// Claude session the answer was given in, resumed if it is applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_session_id: Option<String>,
+}
+
+/// An event of a comparison, from
+/// `GET /sessions/{id}/comparisons/{comparison_id}/stream`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompareEvent {
+    /// A chat event of one variant's answer.
+    Variant { variant_id: String, event: StreamEvent },
+    /// Every variant has answered; always the last event.
+    Complete { comparison: Comparison },
+}
+
+/// Older messages of a conversation replaced by a summary of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Compaction {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    /// The system message holding the summary, now the conversation's first.
+    pub message: Message,
+    /// How many messages the summary replaced.
+    pub archived: usize,
+    /// Estimated tokens the conversation took up before and after.
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+    /// Spent on writing the summary.
+    pub cost_usd: f64,
+}
+
+impl Compaction {
+    /// Estimated tokens saved on every later turn.
+    pub fn tokens_saved(&self) -> u64 {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+}
+
+/// A pull request on GitHub, or a merge request on GitLab.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PullRequest {
+    /// Number within the repository (`iid` on GitLab).
+    pub number: u64,
+    pub title: String,
+    /// Web page of the request.
+    pub url: String,
+    /// "open", "closed" or "merged".
+    pub state: String,
+    pub draft: bool,
+    /// Branch with the changes.
+    pub head: String,
+    /// Branch the changes are to be merged into.
+    pub base: String,
+}
+
 /// Summary of a diff between two commits.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DiffSummary {
     pub files: Vec<FileDiff>,
     pub total_insertions: usize,
@@ -100,6 +919,7 @@ pub struct DiffSummary {
 
 /// Diff information for a single file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct FileDiff {
     pub path: String,
     pub insertions: usize,
@@ -109,6 +929,7 @@ pub struct FileDiff {
 
 /// Git staging status: staged and unstaged files separately.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct GitStatus {
     pub staged: Vec<FileDiff>,
     pub unstaged: Vec<FileDiff>,
@@ -116,6 +937,7 @@ pub struct GitStatus {
 
 /// Current branch and remote information.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct BranchInfo {
     /// Current branch name (e.g. "main").
     pub branch: String,
@@ -125,6 +947,7 @@ pub struct BranchInfo {
 
 /// A single entry in the git commit log.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct GitLogEntry {
     pub oid: String,
     pub message: String,
@@ -134,6 +957,7 @@ pub struct GitLogEntry {
 
 /// Terminal/PTY size in rows and columns.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct PtySize {
     pub rows: u16,
     pub cols: u16,
@@ -154,6 +978,7 @@ impl Default for PtySize {
 
 /// Role of a message in a conversation.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MessageRole {
     User,
@@ -163,6 +988,7 @@ pub enum MessageRole {
 
 /// Status of a tool invocation.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum ToolCallStatus {
     Running,
@@ -172,16 +998,32 @@ pub enum ToolCallStatus {
 
 /// A tool invocation within an assistant message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ToolCall {
     pub id: String,
     pub name: String,
     pub input: serde_json::Value,
     pub output: Option<String>,
     pub status: ToolCallStatus,
+    /// What the subagent a `Task` call started did, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subagent: Vec<SubagentStep>,
+}
+
+/// One step of a subagent's timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SubagentStep {
+    pub timestamp: DateTime<Utc>,
+    pub status: ToolCallStatus,
+    /// What the subagent did, e.g. `Read src/main.rs` or the first line of
+    /// its reply.
+    pub summary: String,
 }
 
 /// Token usage statistics.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TokenUsage {
     pub input_tokens: u64,
     pub output_tokens: u64,
@@ -191,8 +1033,140 @@ pub struct TokenUsage {
     pub cache_write_tokens: Option<u64>,
 }
 
+/// A session's cumulative token usage and how full its context window is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionUsage {
+    /// Tokens of all turns so far.
+    pub total: TokenUsage,
+    pub total_cost_usd: f64,
+    /// Context in use as of the latest turn: its prompt and its output.
+    pub context_tokens: u64,
+    /// Context window of the session's model.
+    pub context_window: u64,
+    /// `context_tokens` as a fraction of `context_window`.
+    pub context_utilization: f64,
+    /// Set while the session waits out a rate limit or an overloaded API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<RateLimitBackoff>,
+}
+
+/// A conversation held back by a rate limit or an overloaded API, until a
+/// response goes through.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RateLimitBackoff {
+    /// `rate_limited` or `overloaded`.
+    pub kind: ChatErrorKind,
+    /// When a retry should go through: as the API said, or after the
+    /// backoff.
+    pub until: DateTime<Utc>,
+    /// Responses that failed on it in a row.
+    pub failures: u32,
+    /// The CLI or the daemon will retry at `until`; otherwise it is up to
+    /// the user.
+    pub retrying: bool,
+}
+
+/// Tokens and cost of the turns one project ran on one day, as kept in the
+/// daemon's usage ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UsageEntry {
+    pub date: NaiveDate,
+    /// Working directory of the sessions the turns ran in.
+    pub project: String,
+    pub usage: TokenUsage,
+    pub cost_usd: f64,
+    pub turns: u64,
+}
+
+/// Daemon-wide usage across all sessions, per day and project.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UsageReport {
+    /// First day included; every day when unset.
+    pub since: Option<NaiveDate>,
+    pub entries: Vec<UsageEntry>,
+    pub total: TokenUsage,
+    pub total_cost_usd: f64,
+}
+
+/// What a headless task (`POST /tasks`) did, sent as its last event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TaskResult {
+    pub task_id: String,
+    /// The prompt, Claude's reply and any errors.
+    pub messages: Vec<Message>,
+    /// Changes to the working directory, if it is in a git repository.
+    pub changes: Option<DiffSummary>,
+    pub usage: TokenUsage,
+    pub cost_usd: f64,
+}
+
+/// A prompt the daemon runs on a cron schedule (`POST /schedules`), each
+/// time as a headless task in a fresh conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Schedule {
+    pub id: String,
+    /// Five-field cron expression in local time, e.g. `0 2 * * *`.
+    pub cron: String,
+    pub prompt: String,
+    pub cwd: String,
+    pub model: String,
+    #[serde(default)]
+    pub permission_mode: PermissionMode,
+    pub created_at: DateTime<Utc>,
+    /// When the prompt runs next.
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// How a scheduled run ended.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleRunStatus {
+    Succeeded,
+    Failed,
+}
+
+/// One run of a schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ScheduleRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: ScheduleRunStatus,
+    /// Claude's last reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+    /// Why the run failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Changes to the working directory, if it is in a git repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<DiffSummary>,
+    /// The milestone the changes were saved in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    pub usage: TokenUsage,
+    pub cost_usd: f64,
+}
+
+/// The period a budget limit covers.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
 /// A single message in a conversation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Message {
     pub id: String,
     pub role: MessageRole,
@@ -206,10 +1180,147 @@ pub struct Message {
     /// Cost in USD for this message (assistant messages only).
     #[serde(default)]
     pub cost_usd: Option<f64>,
+    /// Workspace files included with this message (user messages only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_files: Vec<ContextFile>,
+    /// Uploaded files attached to this message (user messages only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// Claude's reasoning ahead of the reply (assistant messages only). Kept
+    /// apart from `content`: UIs show it collapsed, exports leave it out
+    /// unless asked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    /// Tool whose history the message was imported from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<HistorySource>,
+    /// The compaction this message summarizes the earlier conversation for
+    /// (system messages only). The messages it replaced are archived under
+    /// that id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compaction: Option<String>,
+}
+
+/// History imported into a session's conversation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HistoryImport {
+    /// The page of history read, oldest first.
+    pub messages: Vec<Message>,
+    /// Messages added to the conversation.
+    pub added: usize,
+    /// Messages the conversation already had.
+    pub skipped: usize,
+}
+
+/// A coding agent whose chat history can be imported.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HistorySource {
+    /// The Claude CLI (`~/.claude/projects/`).
+    #[default]
+    Claude,
+    /// aider (`.aider.chat.history.md`).
+    Aider,
+    /// OpenAI's Codex CLI (`~/.codex/sessions/`).
+    Codex,
+    /// Google's Gemini CLI (`~/.gemini/tmp/`).
+    Gemini,
+}
+
+impl HistorySource {
+    /// Every source, in the order they are listed.
+    pub const ALL: [HistorySource; 4] = [
+        HistorySource::Claude,
+        HistorySource::Aider,
+        HistorySource::Codex,
+        HistorySource::Gemini,
+    ];
+
+    /// Value of the `source` query parameter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistorySource::Claude => "claude",
+            HistorySource::Aider => "aider",
+            HistorySource::Codex => "codex",
+            HistorySource::Gemini => "gemini",
+        }
+    }
+}
+
+/// A file uploaded to attach to a chat message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Attachment {
+    pub id: String,
+    /// File name as uploaded.
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Where the daemon stored the file.
+    pub path: String,
+}
+
+/// A workspace file whose contents were sent along with a message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ContextFile {
+    /// Path relative to the session's working directory.
+    pub path: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Only the start of the file was included.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// How `POST /sessions/{id}/clone` duplicates a workspace.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CloneMode {
+    /// Copy every file, uncommitted changes and `.git` included.
+    #[default]
+    Copy,
+    /// `git clone` the repository: committed history only.
+    Git,
+}
+
+/// Format of a conversation export.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    /// File extension for the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    /// Value of the `format` query parameter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
 }
 
 /// Current state of a conversation.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum ConversationState {
     /// No messages yet.
@@ -223,12 +1334,47 @@ pub enum ConversationState {
     Error,
 }
 
+/// Why a chat response failed, to decide how to recover.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ChatErrorKind {
+    /// The CLI isn't logged in, or its API key was rejected.
+    Auth,
+    /// A rate or usage limit was hit.
+    RateLimited,
+    /// The API is overloaded or unavailable.
+    Overloaded,
+    /// The CLI rejected its arguments, e.g. after an incompatible update.
+    InvalidArguments,
+    /// The API couldn't be reached.
+    Network,
+    /// The response took too long or stalled, and was stopped.
+    Timeout,
+    /// Claude tried to change the workspace of a read-only session.
+    ReadOnly,
+    /// Anything else.
+    #[default]
+    Other,
+}
+
 /// Streaming events sent from daemon to UI during a response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamEvent {
     /// Incremental text from the assistant.
     TextDelta { text: String },
+    /// Incremental reasoning from the assistant, ahead of its reply.
+    ThinkingDelta { text: String },
+    /// A subagent started with the `Task` tool took a step, or finished.
+    /// `id` is the tool call that started it.
+    SubagentUpdate {
+        id: String,
+        name: String,
+        status: ToolCallStatus,
+        summary: String,
+    },
     /// A tool is being invoked.
     ToolUseStart {
         tool_call_id: String,
@@ -241,10 +1387,126 @@ pub enum StreamEvent {
         output: String,
         is_error: bool,
     },
-    /// The assistant message is complete.
+    /// The assistant message is complete, or a system message (the result
+    /// of a slash command) was posted.
     MessageComplete { message: Box<Message> },
     /// An error occurred during processing.
-    Error { message: String },
+    Error {
+        message: String,
+        /// What failed, when the daemon could tell.
+        #[serde(default)]
+        kind: ChatErrorKind,
+        /// The Claude CLI's own words about the failure, e.g. the end of
+        /// its stderr.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    /// Claude hit a rate limit or an overloaded API: the CLI is retrying
+    /// on its own, or the response failed (see the `Error` sent with it).
+    RateLimited {
+        /// `rate_limited` or `overloaded`.
+        kind: ChatErrorKind,
+        /// Seconds until a retry should go through, if known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+        /// The CLI or the daemon will retry by itself.
+        retrying: bool,
+    },
     /// The conversation is idle (process exited cleanly).
     Idle,
+    /// The session's activity classification changed.
+    ActivityChanged { activity: SessionActivity },
+    /// The Claude CLI is waiting for the user to answer a prompt (e.g. a
+    /// trust dialog); answer with `POST /sessions/{id}/messages/current/input`.
+    InputRequested {
+        prompt: String,
+        /// Answers to choose from, if the prompt offers any.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<String>,
+    },
+    /// A message is waiting behind an in-progress response. `position` is 1
+    /// for next in line; 0 means it has left the queue and started.
+    QueuePosition { message_id: String, position: usize },
+    /// The conversation's messages were replaced or removed (by `/clear`,
+    /// `/resume`, an edit or a deletion); refetch them.
+    Reset,
+    /// Tokens used so far by the response in progress, and how full the
+    /// context window is. Sent at most twice a second while streaming.
+    UsageUpdate {
+        usage: TokenUsage,
+        context_tokens: u64,
+        context_window: u64,
+        /// `context_tokens` as a fraction of `context_window`.
+        context_utilization: f64,
+    },
+    /// Daemon-wide spending reached the warning level of a budget limit, or
+    /// went over it (`exceeded`).
+    BudgetWarning {
+        period: BudgetPeriod,
+        spent_usd: f64,
+        limit_usd: f64,
+        exceeded: bool,
+    },
+}
+
+/// A high-level event from one session, on the daemon-wide `/events` feed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ActivityEvent {
+    /// Empty for events about the daemon itself (`ConfigChanged`).
+    pub session_id: SessionId,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: ActivityKind,
+}
+
+/// What happened in an `ActivityEvent`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// An assistant response finished.
+    MessageComplete { message_id: String },
+    /// A milestone was committed.
+    MilestoneSaved { oid: String, message: String },
+    /// The session's PTY process exited, with this code if it has one.
+    ProcessExited {
+        #[serde(default)]
+        code: Option<i32>,
+    },
+    /// The session's branch was pushed.
+    GitPushed,
+    /// A pull (or merge) request was opened for the session's branch.
+    PullRequestCreated { url: String },
+    /// The session's own branch was merged back into `into`.
+    BranchMerged { branch: String, into: String },
+    /// The session started producing output.
+    Busy,
+    /// The session stopped producing output.
+    Idle,
+    /// The session's processes used more than a configured limit and were
+    /// killed. `limit` and `used` are in seconds for CPU time and megabytes
+    /// for memory.
+    ResourceLimitExceeded {
+        resource: LimitedResource,
+        limit: u64,
+        used: u64,
+    },
+    /// config.json changed and the daemon reloaded it; clients should
+    /// re-read the settings they show.
+    ConfigChanged,
+    /// The session's workspace is being cloned; `done` of `total` files
+    /// copied, or objects received for a git clone.
+    CloneProgress { done: u64, total: u64 },
+}
+
+/// A resource with a per-session limit.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LimitedResource {
+    /// CPU time.
+    Cpu,
+    /// Resident memory.
+    Memory,
 }
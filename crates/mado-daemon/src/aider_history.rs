@@ -0,0 +1,180 @@
+//! aider's chat history.
+//!
+//! aider appends every chat to `.aider.chat.history.md` at the root of the
+//! repository it runs in:
+//!
+//! ```text
+//! # aider chat started at 2025-05-01 10:00:00
+//!
+//! #### Add a --verbose flag
+//!
+//! I'll add the flag to the argument parser.
+//!
+//! > Applied edit to cli.py
+//! ```
+//!
+//! `####` lines are the user's prompts, `>` lines aider's own output (edits
+//! applied, commands run) and the rest the model's replies. The file is one
+//! session; its messages are dated by the chat they are in.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use mado_core::types::{HistorySource, Message, MessageRole};
+
+use crate::claude_history::HistoryError;
+use crate::history_import::{message_id, HistoryImporter};
+
+/// Name of aider's history file.
+pub const FILE_NAME: &str = ".aider.chat.history.md";
+
+const CHAT_STARTED: &str = "# aider chat started at ";
+
+/// Imports aider's `.aider.chat.history.md`.
+pub struct AiderImporter;
+
+impl HistoryImporter for AiderImporter {
+    fn source(&self) -> HistorySource {
+        HistorySource::Aider
+    }
+
+    fn sessions(&self, working_dir: &Path) -> Vec<PathBuf> {
+        find(working_dir).into_iter().collect()
+    }
+
+    fn parse(&self, session: &Path) -> Result<Vec<Message>, HistoryError> {
+        let contents = std::fs::read_to_string(session)?;
+        let modified = std::fs::metadata(session)?
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        Ok(parse(&contents, session, modified))
+    }
+}
+
+/// The history file for `dir`: in it or, up to the root of the repository
+/// it is in, the nearest parent.
+fn find(dir: &Path) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let path = dir.join(FILE_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Messages of a history file; `modified` dates messages before the first
+/// chat header.
+fn parse(contents: &str, session: &Path, modified: DateTime<Utc>) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut started = modified;
+    let mut current: Option<(MessageRole, Vec<&str>)> = None;
+
+    let mut flush = |current: &mut Option<(MessageRole, Vec<&str>)>, started: DateTime<Utc>| {
+        let Some((role, lines)) = current.take() else {
+            return;
+        };
+        let content = lines.join("\n").trim().to_string();
+        if content.is_empty() {
+            return;
+        }
+        messages.push(Message {
+            id: message_id(HistorySource::Aider, session, messages.len()),
+            role,
+            content,
+            tool_calls: Vec::new(),
+            timestamp: started,
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: Some(HistorySource::Aider),
+            compaction: None,
+        });
+    };
+
+    for line in contents.lines() {
+        if let Some(at) = line.strip_prefix(CHAT_STARTED) {
+            flush(&mut current, started);
+            started = NaiveDateTime::parse_from_str(at.trim(), "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|at| Local.from_local_datetime(&at).single())
+                .map_or(started, |at| at.with_timezone(&Utc));
+        } else if let Some(prompt) = line.strip_prefix("####") {
+            if !matches!(current, Some((MessageRole::User, _))) {
+                flush(&mut current, started);
+                current = Some((MessageRole::User, Vec::new()));
+            }
+            if let Some((_, lines)) = &mut current {
+                lines.push(prompt.strip_prefix(' ').unwrap_or(prompt));
+            }
+        } else if line.starts_with('>') {
+            continue;
+        } else {
+            match &mut current {
+                Some((MessageRole::Assistant, lines)) => {
+                    // One blank line where aider's output was left out.
+                    let blank = line.trim().is_empty();
+                    if !(blank && lines.last().is_some_and(|last| last.trim().is_empty())) {
+                        lines.push(line);
+                    }
+                }
+                // Blank lines between a prompt and its reply.
+                _ if line.trim().is_empty() => {}
+                _ => {
+                    flush(&mut current, started);
+                    current = Some((MessageRole::Assistant, vec![line]));
+                }
+            }
+        }
+    }
+    flush(&mut current, started);
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let contents = "\
+# aider chat started at 2025-05-01 10:00:00
+
+#### Add a --verbose flag
+#### and document it
+
+I'll add the flag.
+
+> Applied edit to cli.py
+> Commit 1a2b3c4 feat: Add --verbose
+
+Done.
+
+# aider chat started at 2025-05-02 09:30:00
+
+#### /ask what does main do?
+
+It parses arguments.
+";
+        let messages = parse(contents, Path::new("/repo/.aider.chat.history.md"), Utc::now());
+        let summary: Vec<_> = messages.iter().map(|m| (m.role.clone(), m.content.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                (MessageRole::User, "Add a --verbose flag\nand document it"),
+                (MessageRole::Assistant, "I'll add the flag.\n\nDone."),
+                (MessageRole::User, "/ask what does main do?"),
+                (MessageRole::Assistant, "It parses arguments."),
+            ]
+        );
+        assert!(messages[2].timestamp > messages[1].timestamp);
+        assert_eq!(messages[0].source, Some(HistorySource::Aider));
+        assert_eq!(messages[3].id, "imported-aider-.aider.chat.history-3");
+    }
+}
@@ -0,0 +1,137 @@
+//! Files uploaded to attach to chat messages.
+//!
+//! `POST /sessions/{id}/attachments` stores the request body under the
+//! conversation storage directory as `<session>/attachments/<id>-<name>` and
+//! returns an [`Attachment`]. Messages then refer to attachments by id; their
+//! paths are listed in the prompt, and the directory is added to what the
+//! Claude CLI may read, so it can open images (screenshots, mostly) itself.
+
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use mado_core::types::Attachment;
+
+/// Largest attachment accepted (20 MiB).
+pub const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+
+/// Errors from storing or looking up attachments.
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("Invalid attachment name: {0:?}")]
+    InvalidName(String),
+
+    #[error("Attachment is empty")]
+    Empty,
+
+    #[error("Attachment not found: {0}")]
+    NotFound(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Directory of a session's attachments under the conversation storage dir.
+pub fn dir(storage_dir: &Path, session_id: &str) -> PathBuf {
+    storage_dir.join(session_id).join("attachments")
+}
+
+/// MIME type of a file name, by extension.
+fn mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" | "md" | "log" => "text/plain",
+        "json" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Store `bytes` as a new attachment named `name` in `dir`.
+pub fn save(dir: &Path, name: &str, bytes: &[u8]) -> Result<Attachment, AttachmentError> {
+    let file_name = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .filter(|n| n == name && !n.starts_with('.'))
+        .ok_or_else(|| AttachmentError::InvalidName(name.to_string()))?;
+    if bytes.is_empty() {
+        return Err(AttachmentError::Empty);
+    }
+
+    std::fs::create_dir_all(dir)?;
+    let id = Uuid::new_v4().to_string();
+    let path = dir.join(format!("{}-{}", id, file_name));
+    std::fs::write(&path, bytes)?;
+    Ok(Attachment {
+        id,
+        mime_type: mime_type(&file_name).to_string(),
+        name: file_name,
+        size: bytes.len() as u64,
+        path: path.display().to_string(),
+    })
+}
+
+/// Look up a stored attachment by id.
+pub fn find(dir: &Path, id: &str) -> Result<Attachment, AttachmentError> {
+    let not_found = || AttachmentError::NotFound(id.to_string());
+    if Uuid::parse_str(id).is_err() {
+        return Err(not_found());
+    }
+    let prefix = format!("{}-", id);
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(not_found()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(name) = file_name.strip_prefix(&prefix) else {
+            continue;
+        };
+        return Ok(Attachment {
+            id: id.to_string(),
+            name: name.to_string(),
+            mime_type: mime_type(name).to_string(),
+            size: entry.metadata()?.len(),
+            path: entry.path().display().to_string(),
+        });
+    }
+    Err(not_found())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_find() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = dir(tmp.path(), "s1");
+
+        let saved = save(&dir, "Screen Shot.PNG", b"\x89PNG").unwrap();
+        assert_eq!(saved.mime_type, "image/png");
+        assert_eq!(saved.size, 4);
+        assert!(saved.path.starts_with(&dir.display().to_string()));
+
+        let found = find(&dir, &saved.id).unwrap();
+        assert_eq!(found.name, "Screen Shot.PNG");
+        assert_eq!(found.path, saved.path);
+
+        assert!(matches!(find(&dir, "../x"), Err(AttachmentError::NotFound(_))));
+        assert!(matches!(
+            find(&dir, &Uuid::new_v4().to_string()),
+            Err(AttachmentError::NotFound(_))
+        ));
+        assert!(matches!(save(&dir, "../evil.png", b"x"), Err(AttachmentError::InvalidName(_))));
+        assert!(matches!(save(&dir, ".hidden", b"x"), Err(AttachmentError::InvalidName(_))));
+        assert!(matches!(save(&dir, "empty.txt", b""), Err(AttachmentError::Empty)));
+    }
+}
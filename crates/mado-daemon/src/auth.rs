@@ -0,0 +1,121 @@
+//! Bearer-token authentication for the daemon API.
+//!
+//! The daemon generates a random token on first start and stores it with 0600
+//! permissions next to the socket (`~/.mado/token`). Every request, on the Unix
+//! socket and the optional TCP listener alike, must carry
+//! `Authorization: Bearer <token>`. Local clients read the file to get it.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing;
+
+use crate::error::ApiError;
+
+/// Read the token at `path`, generating and storing a new one if missing.
+///
+/// An existing file with looser permissions is tightened to 0600.
+pub fn load_or_create_token(path: &Path) -> std::io::Result<String> {
+    if path.exists() {
+        let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+        if mode != 0o600 {
+            tracing::warn!("Token file {} had mode {:o}, resetting to 600", path.display(), mode);
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+
+        let token = fs::read_to_string(path)?.trim().to_string();
+        if !token.is_empty() {
+            return Ok(token);
+        }
+        tracing::warn!("Token file {} is empty, regenerating", path.display());
+        fs::remove_file(path)?;
+    }
+
+    let token = generate_token();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(token.as_bytes())?;
+    file.sync_all()?;
+    tracing::info!("Generated API token at {}", path.display());
+    Ok(token)
+}
+
+/// Generate a random token (244 bits from the OS RNG, hex-encoded).
+fn generate_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Middleware rejecting requests without the expected bearer token.
+pub async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => ApiError::Unauthorized.into_response(),
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_or_create_token() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("token");
+
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        // Reused on the next start.
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+
+        // Loose permissions are tightened.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
@@ -0,0 +1,323 @@
+//! Which Claude CLI the daemon runs, and with what flags.
+//!
+//! `claude_path` in config.json picks the binary; without it, the CLI is
+//! looked up on PATH and in the usual install locations. `default_flags`
+//! (e.g. `--allowedTools`) are passed on every spawn, in terminal and chat
+//! mode, followed by a session's own `claude_flags`, which therefore win
+//! where the CLI takes the last value of a flag.
+//!
+//! [`info`] checks that this setup actually works, for `GET /cli/info`.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use mado_core::types::{CliAuth, CliInfo};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Flags the daemon sets itself; passing them too would break its use of
+/// the CLI's output or sessions.
+const RESERVED_FLAGS: &[&str] = &[
+    "-p",
+    "--print",
+    "--output-format",
+    "--input-format",
+    "-r",
+    "--resume",
+    "-c",
+    "--continue",
+    "--fork-session",
+    "--session-id",
+];
+
+/// How to run the Claude CLI for a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClaudeCli {
+    /// Configured binary; found on the system when unset.
+    pub path: Option<PathBuf>,
+    /// Extra arguments, after those the daemon passes itself.
+    pub flags: Vec<String>,
+}
+
+impl ClaudeCli {
+    /// The CLI with `default_flags` followed by a session's `session_flags`.
+    pub fn new(path: Option<PathBuf>, default_flags: &[String], session_flags: &[String]) -> Self {
+        Self {
+            path,
+            flags: default_flags.iter().chain(session_flags).cloned().collect(),
+        }
+    }
+
+    /// The binary to run, or `None` if the CLI isn't installed. A configured
+    /// path must be an executable file.
+    pub fn binary(&self) -> Result<Option<PathBuf>, CliError> {
+        match &self.path {
+            Some(path) if crate::process::is_executable(path) => Ok(Some(path.clone())),
+            Some(path) => Err(CliError::NotExecutable(path.clone())),
+            None => Ok(find_on_system()),
+        }
+    }
+}
+
+/// Check flags from config.json or a session before they are used.
+pub fn validate_flags(flags: &[String]) -> Result<(), CliError> {
+    for flag in flags {
+        if flag.trim().is_empty() {
+            return Err(CliError::InvalidFlag(flag.clone()));
+        }
+        let name = flag.split('=').next().unwrap_or_default();
+        if RESERVED_FLAGS.contains(&name) {
+            return Err(CliError::ReservedFlag(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Find the Claude CLI binary on the system.
+///
+/// Checks: PATH, ~/.claude/local/bin/claude, /usr/local/bin/claude,
+/// /opt/homebrew/bin/claude
+pub fn find_on_system() -> Option<PathBuf> {
+    // Check PATH first via `which`.
+    if let Ok(output) = std::process::Command::new("which").arg("claude").output()
+        && output.status.success()
+    {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !path.is_empty() {
+            let p = PathBuf::from(&path);
+            if p.exists() {
+                tracing::debug!("Found claude at: {}", p.display());
+                return Some(p);
+            }
+        }
+    }
+
+    // Check common install locations.
+    let candidates = [
+        dirs::home_dir().map(|h| h.join(".claude").join("local").join("bin").join("claude")),
+        Some(PathBuf::from("/usr/local/bin/claude")),
+        Some(PathBuf::from("/opt/homebrew/bin/claude")),
+    ];
+
+    let found = candidates.into_iter().flatten().find(|candidate| candidate.exists());
+    match &found {
+        Some(path) => tracing::debug!("Found claude at: {}", path.display()),
+        None => tracing::warn!("Claude CLI not found on system"),
+    }
+    found
+}
+
+/// Check `claude_path` and `default_flags` from config.json, returning the
+/// binary they resolve to, if any.
+pub fn check_config(path: Option<&Path>, default_flags: &[String]) -> Result<Option<PathBuf>, CliError> {
+    validate_flags(default_flags)?;
+    ClaudeCli::new(path.map(Path::to_path_buf), default_flags, &[]).binary()
+}
+
+/// Oldest CLI version known to work with the daemon.
+const MIN_VERSION: (u64, u64, u64) = (1, 0, 0);
+
+/// How long `claude --version` and the stream-json probe may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Check the CLI the daemon would run: its version, whether it is logged in
+/// and whether chat mode's stream-json output works.
+///
+/// The stream-json probe stops the CLI as soon as it reports its session,
+/// which it does before sending the prompt to the API.
+pub async fn info(cli: &ClaudeCli, api_key: Option<&str>) -> CliInfo {
+    let mut info = CliInfo {
+        auth: auth(api_key),
+        ..Default::default()
+    };
+    let path = match cli.binary() {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            info.warnings.push("Claude CLI not found; install it or set claude_path".to_string());
+            return info;
+        }
+        Err(e) => {
+            info.warnings.push(e.to_string());
+            return info;
+        }
+    };
+    info.path = Some(path.display().to_string());
+    if info.auth == CliAuth::LoggedOut {
+        info.warnings.push("Claude CLI is not logged in; run `claude login` or set an API key".to_string());
+    }
+
+    let version = tokio::process::Command::new(&path)
+        .arg("--version")
+        .env_remove("CLAUDECODE")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(PROBE_TIMEOUT, version).await {
+        Ok(Ok(output)) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if parse_version(&version).is_some_and(|v| v < MIN_VERSION) {
+                info.warnings.push(format!(
+                    "Claude CLI {} is older than {}.{}.{}; update it",
+                    version, MIN_VERSION.0, MIN_VERSION.1, MIN_VERSION.2
+                ));
+            }
+            info.version = Some(version);
+        }
+        Ok(Ok(output)) => info.warnings.push(format!("`claude --version` failed ({})", output.status)),
+        Ok(Err(e)) => info.warnings.push(format!("Failed to run Claude CLI: {}", e)),
+        Err(_) => info.warnings.push("`claude --version` timed out".to_string()),
+    }
+
+    match probe_stream_json(&path, cli, api_key).await {
+        Ok(()) => info.stream_json = true,
+        Err(e) => info.warnings.push(format!("stream-json output doesn't work: {}", e)),
+    }
+    info
+}
+
+/// How the CLI will authenticate: with an API key if there is one, else
+/// with a subscription login if its credentials are present.
+fn auth(api_key: Option<&str>) -> CliAuth {
+    if api_key.is_some() || std::env::var_os("ANTHROPIC_API_KEY").is_some() {
+        return CliAuth::ApiKey;
+    }
+    let credentials = dirs::home_dir().map(|h| h.join(".claude").join(".credentials.json"));
+    if credentials.is_some_and(|path| path.exists()) {
+        return CliAuth::Login;
+    }
+    // On macOS the CLI keeps its login in the keychain.
+    #[cfg(target_os = "macos")]
+    if std::process::Command::new("security")
+        .args(["find-generic-password", "-s", "Claude Code-credentials"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+    {
+        return CliAuth::Login;
+    }
+    CliAuth::LoggedOut
+}
+
+/// `major.minor.patch` at the start of `claude --version` output.
+fn parse_version(output: &str) -> Option<(u64, u64, u64)> {
+    let version = output.split_whitespace().next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// Start `claude -p` as chat mode does and wait for its `system`/`init`
+/// event, then stop it.
+async fn probe_stream_json(path: &Path, cli: &ClaudeCli, api_key: Option<&str>) -> Result<(), String> {
+    let mut cmd = tokio::process::Command::new(path);
+    cmd.args(&cli.flags)
+        .args(["-p", "Reply with OK.", "--output-format", "stream-json", "--verbose", "--max-turns", "1"])
+        .env_remove("CLAUDECODE")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true);
+    if let Some(key) = api_key {
+        cmd.env("ANTHROPIC_API_KEY", key);
+    }
+    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+    let stdout = child.stdout.take().ok_or("no stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+
+    let started = tokio::time::timeout(PROBE_TIMEOUT, async {
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Ok(event) = serde_json::from_str::<serde_json::Value>(&line)
+                && event["type"] == "system"
+                && event["subtype"] == "init"
+            {
+                return true;
+            }
+        }
+        false
+    })
+    .await;
+    if let Err(e) = crate::process_group::stop_child(&mut child).await {
+        tracing::warn!("Failed to stop Claude CLI probe: {}", e);
+    }
+
+    match started {
+        Ok(true) => Ok(()),
+        Err(_) => Err("no session reported in time".to_string()),
+        Ok(false) => {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                use tokio::io::AsyncReadExt;
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            let stderr = stderr.trim();
+            Err(if stderr.is_empty() {
+                "the CLI exited without reporting a session".to_string()
+            } else {
+                stderr.lines().last().unwrap_or(stderr).to_string()
+            })
+        }
+    }
+}
+
+/// Errors from configuring the Claude CLI.
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("claude_path is not an executable file: {}", .0.display())]
+    NotExecutable(PathBuf),
+
+    #[error("{0} is set by Mado and can't be passed as a Claude CLI flag")]
+    ReservedFlag(String),
+
+    #[error("Invalid Claude CLI flag: {0:?}")]
+    InvalidFlag(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(flags: &[&str]) -> Vec<String> {
+        flags.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_flags() {
+        assert!(validate_flags(&flags(&["--allowedTools", "Bash Edit", "--permission-mode", "plan"])).is_ok());
+        assert!(matches!(
+            validate_flags(&flags(&["--output-format=json"])),
+            Err(CliError::ReservedFlag(flag)) if flag == "--output-format"
+        ));
+        assert!(matches!(validate_flags(&flags(&["-p"])), Err(CliError::ReservedFlag(_))));
+        assert!(matches!(validate_flags(&flags(&[" "])), Err(CliError::InvalidFlag(_))));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("1.0.43 (Claude Code)"), Some((1, 0, 43)));
+        assert_eq!(parse_version("2.1.0"), Some((2, 1, 0)));
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[tokio::test]
+    async fn test_info_reports_missing_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli = ClaudeCli::new(Some(dir.path().join("claude")), &[], &[]);
+        let info = info(&cli, Some("key")).await;
+        assert_eq!(info.path, None);
+        assert_eq!(info.auth, CliAuth::ApiKey);
+        assert!(!info.stream_json);
+        assert_eq!(info.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_configured_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli = ClaudeCli::new(Some(dir.path().join("missing")), &flags(&["--a"]), &flags(&["--b"]));
+        assert_eq!(cli.flags, flags(&["--a", "--b"]));
+        assert!(matches!(cli.binary(), Err(CliError::NotExecutable(_))));
+
+        let sh = PathBuf::from("/bin/sh");
+        assert_eq!(check_config(Some(&sh), &[]).unwrap(), Some(sh));
+    }
+}
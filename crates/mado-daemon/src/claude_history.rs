@@ -2,16 +2,27 @@
 //!
 //! Parses Claude CLI session files from ~/.claude/projects/ to import
 //! conversation history into Mado.
+//!
+//! Each file is indexed the first time it is read, and again only from where
+//! it last ended, so listing sessions and paging through a long one don't
+//! parse whole multi-MB files.
+//!
+//! Assistant messages carry the token usage (and, from older CLI versions,
+//! the cost) of the API call they came from. The CLI writes a line per
+//! content block of a call, each repeating its usage, so only the first
+//! line of a call keeps it.
 
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
 
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::Value;
 
-use mado_core::types::{Message, MessageRole, ToolCall, ToolCallStatus};
+use mado_core::types::{CliSessionInfo, HistorySource, Message, MessageRole, ToolCall, ToolCallStatus};
 
 /// A parsed Claude CLI session entry.
 #[derive(Debug, Deserialize)]
@@ -20,14 +31,21 @@ struct ClaudeEntry {
     entry_type: String,
     message: Option<ClaudeMessage>,
     timestamp: Option<String>,
-    #[serde(rename = "sessionId")]
-    session_id: Option<String>,
+    /// Id of the entry, the same in every session file it was copied into
+    /// by `--resume`.
+    uuid: Option<String>,
+    /// Cost of the API call, written by older CLI versions.
+    #[serde(rename = "costUSD")]
+    cost_usd: Option<f64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ClaudeMessage {
     role: String,
     content: ClaudeContent,
+    /// Id of the API call (assistant messages).
+    id: Option<String>,
+    usage: Option<Value>,
 }
 
 /// Content can be a string (user) or array of blocks (assistant).
@@ -43,6 +61,7 @@ struct ClaudeContentBlock {
     #[serde(rename = "type")]
     block_type: String,
     text: Option<String>,
+    thinking: Option<String>,
     id: Option<String>,
     name: Option<String>,
     input: Option<Value>,
@@ -77,7 +96,7 @@ pub fn list_sessions(project_dir: &Path) -> Vec<PathBuf> {
         .flatten()
         .filter_map(|e| e.ok())
         .filter(|e| {
-            e.path().extension().map_or(false, |ext| ext == "jsonl")
+            e.path().extension().is_some_and(|ext| ext == "jsonl")
         })
         .map(|e| e.path())
         .collect();
@@ -92,259 +111,345 @@ pub fn list_sessions(project_dir: &Path) -> Vec<PathBuf> {
     sessions
 }
 
-/// Parse a Claude CLI session file into Kobo messages.
-pub fn parse_session(session_path: &Path) -> Result<Vec<Message>, HistoryError> {
-    let file = File::open(session_path)?;
-    let reader = BufReader::new(file);
-    let mut messages = Vec::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        if line.is_empty() {
-            continue;
-        }
-
-        let entry: ClaudeEntry = match serde_json::from_str(&line) {
-            Ok(e) => e,
-            Err(_) => continue, // Skip unparseable lines
-        };
-
-        // Only process user and assistant messages.
-        if entry.entry_type != "user" && entry.entry_type != "assistant" {
-            continue;
-        }
-
-        let Some(msg) = entry.message else {
-            continue;
-        };
+/// Parse one line of a session file.
+fn parse_line(line: &str) -> Option<ClaudeEntry> {
+    serde_json::from_str(line.trim_end()).ok()
+}
 
-        let role = match msg.role.as_str() {
-            "user" => MessageRole::User,
-            "assistant" => MessageRole::Assistant,
-            _ => continue,
-        };
+/// The message `entry` records, if it is a user or assistant message.
+/// Its id comes from the entry's uuid, or from `index`, its position among
+/// the file's messages, in files written before entries had one.
+fn to_message(entry: ClaudeEntry, session_id: &str, index: usize) -> Option<Message> {
+    // Only process user and assistant messages.
+    if entry.entry_type != "user" && entry.entry_type != "assistant" {
+        return None;
+    }
 
-        // Parse timestamp.
-        let timestamp = entry
-            .timestamp
-            .as_ref()
-            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(Utc::now);
-
-        // Extract content and tool calls.
-        let (content, tool_calls) = match msg.content {
-            ClaudeContent::Text(text) => (text, Vec::new()),
-            ClaudeContent::Blocks(blocks) => {
-                let mut text_parts = Vec::new();
-                let mut tools = Vec::new();
-
-                for block in blocks {
-                    match block.block_type.as_str() {
-                        "text" => {
-                            if let Some(text) = block.text {
-                                text_parts.push(text);
-                            }
+    let msg = entry.message?;
+    let usage = msg.usage.as_ref().map(crate::usage::parse);
+
+    let role = match msg.role.as_str() {
+        "user" => MessageRole::User,
+        "assistant" => MessageRole::Assistant,
+        _ => return None,
+    };
+
+    // Parse timestamp.
+    let timestamp = entry
+        .timestamp
+        .as_ref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+
+    // Extract content, thinking and tool calls.
+    let (content, thinking, tool_calls) = match msg.content {
+        ClaudeContent::Text(text) => (text, Vec::new(), Vec::new()),
+        ClaudeContent::Blocks(blocks) => {
+            let mut text_parts = Vec::new();
+            let mut thinking_parts = Vec::new();
+            let mut tools = Vec::new();
+
+            for block in blocks {
+                match block.block_type.as_str() {
+                    "text" => {
+                        if let Some(text) = block.text {
+                            text_parts.push(text);
+                        }
+                    }
+                    "thinking" => {
+                        if let Some(thinking) = block.thinking {
+                            thinking_parts.push(thinking);
                         }
-                        "tool_use" => {
-                            if let (Some(id), Some(name)) = (block.id, block.name) {
-                                tools.push(ToolCall {
-                                    id,
-                                    name,
-                                    input: block.input.unwrap_or(Value::Null),
-                                    output: None,
-                                    status: ToolCallStatus::Completed,
-                                });
-                            }
+                    }
+                    "tool_use" => {
+                        if let (Some(id), Some(name)) = (block.id, block.name) {
+                            tools.push(ToolCall {
+                                id,
+                                name,
+                                input: block.input.unwrap_or(Value::Null),
+                                output: None,
+                                status: ToolCallStatus::Completed,
+                                subagent: Vec::new(),
+                            });
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
-
-                (text_parts.join("\n"), tools)
             }
-        };
-
-        // Generate a deterministic ID from session + index.
-        let id = format!(
-            "imported-{}-{}",
-            session_path.file_stem().unwrap_or_default().to_string_lossy(),
-            messages.len()
-        );
 
-        messages.push(Message {
-            id,
-            role,
-            content,
-            tool_calls,
-            timestamp,
-            usage: None,
-            cost_usd: None,
-        });
-    }
+            (text_parts.join("\n"), thinking_parts, tools)
+        }
+    };
+
+    Some(Message {
+        id: match entry.uuid {
+            Some(uuid) => format!("imported-{}", uuid),
+            None => format!("imported-{}-{}", session_id, index),
+        },
+        role,
+        content,
+        tool_calls,
+        timestamp,
+        usage,
+        cost_usd: entry.cost_usd,
+        context_files: Vec::new(),
+        attachments: Vec::new(),
+        thinking: (!thinking.is_empty()).then(|| thinking.join("\n")),
+        source: Some(HistorySource::Claude),
+        compaction: None,
+    })
+}
 
-    Ok(messages)
+/// Where the messages of a session file start, so a page of them can be
+/// read without parsing the rest. Session files are only appended to, so an
+/// index stays valid until its file shrinks; lines appended since are
+/// indexed on next use.
+#[derive(Debug, Clone, Default)]
+struct FileIndex {
+    /// Bytes indexed, up to the end of the last complete line.
+    len: u64,
+    /// Offset of each message's line.
+    offsets: Vec<u64>,
+    /// Whether each message comes from the same API call as the one before,
+    /// so its usage is already counted.
+    repeats: Vec<bool>,
+    /// API call of the last message indexed.
+    last_call: Option<String>,
+    /// Start of the first prompt.
+    preview: Option<String>,
 }
 
-/// Import history from Claude CLI for a working directory.
-/// Returns messages from the most recent session.
-pub fn import_history(working_dir: &Path, limit: Option<usize>) -> Result<Vec<Message>, HistoryError> {
-    let project_dir = find_project_dir(working_dir)
-        .ok_or_else(|| HistoryError::ProjectNotFound(working_dir.to_path_buf()))?;
+/// Indexes of the session files read so far, by path.
+static INDEXES: LazyLock<Mutex<HashMap<PathBuf, FileIndex>>> = LazyLock::new(Default::default);
+
+/// Index `path`, reading only what was appended since it was last indexed.
+fn index(path: &Path) -> Result<FileIndex, HistoryError> {
+    let cached = INDEXES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(path)
+        .cloned()
+        .unwrap_or_default();
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut index = if len < cached.len { FileIndex::default() } else { cached };
+    if len == index.len {
+        return Ok(index);
+    }
 
-    let sessions = list_sessions(&project_dir);
-    if sessions.is_empty() {
-        return Ok(Vec::new());
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(index.len))?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        let entry = parse_line(&line);
+        // A line the CLI is still writing is indexed once it is complete.
+        if !line.ends_with('\n') && entry.is_none() {
+            break;
+        }
+        if let Some(entry) = entry {
+            let call = entry.message.as_ref().and_then(|m| m.id.clone());
+            if let Some(message) = to_message(entry, "", 0) {
+                if index.preview.is_none() && message.role == MessageRole::User {
+                    index.preview = preview(&message.content);
+                }
+                index.offsets.push(index.len);
+                index.repeats.push(call.is_some() && call == index.last_call);
+                index.last_call = call;
+            }
+        }
+        index.len += read as u64;
     }
 
-    // Get the most recent session.
-    let latest_session = &sessions[0];
-    let mut messages = parse_session(latest_session)?;
+    INDEXES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.to_path_buf(), index.clone());
+    Ok(index)
+}
 
-    // Apply limit.
-    if let Some(lim) = limit {
-        let start = messages.len().saturating_sub(lim);
-        messages = messages[start..].to_vec();
+/// `content` on one line, cut to [`PREVIEW_CHARS`]; `None` for tool results
+/// and the CLI's own `<command-name>`-style entries.
+fn preview(content: &str) -> Option<String> {
+    let text = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() || text.starts_with('<') {
+        return None;
     }
+    Some(match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    })
+}
 
+/// The newest `limit` messages (all if `None`) of a session file, after
+/// skipping the newest `offset`, oldest first. Only those messages' lines
+/// are parsed.
+pub fn read_messages(session_path: &Path, limit: Option<usize>, offset: usize) -> Result<Vec<Message>, HistoryError> {
+    let index = index(session_path)?;
+    let end = index.offsets.len().saturating_sub(offset);
+    let start = limit.map_or(0, |limit| end.saturating_sub(limit));
+    let wanted = &index.offsets[start..end];
+    let session_id = session_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut reader = BufReader::new(File::open(session_path)?);
+    let mut pos = 0;
+    let mut line = String::new();
+    let mut messages = Vec::with_capacity(wanted.len());
+    for (i, &offset) in wanted.iter().enumerate() {
+        reader.seek_relative(offset as i64 - pos as i64)?;
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        pos = offset + read as u64;
+        if let Some(mut message) = parse_line(&line).and_then(|entry| to_message(entry, &session_id, start + i)) {
+            if index.repeats[start + i] {
+                message.usage = None;
+                message.cost_usd = None;
+            }
+            messages.push(message);
+        }
+    }
     Ok(messages)
 }
 
-/// Import history from all sessions for a working directory.
-pub fn import_all_history(working_dir: &Path, limit: Option<usize>) -> Result<Vec<Message>, HistoryError> {
+/// Parse a Claude CLI session file into Mado messages.
+pub fn parse_session(session_path: &Path) -> Result<Vec<Message>, HistoryError> {
+    read_messages(session_path, None, 0)
+}
+
+/// Import history from Claude CLI for a working directory.
+/// Returns messages from the most recent session, paged as in [`read_messages`].
+pub fn import_history(working_dir: &Path, limit: Option<usize>, offset: usize) -> Result<Vec<Message>, HistoryError> {
     let project_dir = find_project_dir(working_dir)
         .ok_or_else(|| HistoryError::ProjectNotFound(working_dir.to_path_buf()))?;
 
-    let sessions = list_sessions(&project_dir);
-    let mut all_messages = Vec::new();
-
-    for session_path in sessions {
-        let mut messages = parse_session(&session_path)?;
-        all_messages.append(&mut messages);
+    match list_sessions(&project_dir).first() {
+        Some(latest_session) => read_messages(latest_session, limit, offset),
+        None => Ok(Vec::new()),
     }
-
-    // Sort by timestamp.
-    all_messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-
-    // Apply limit.
-    if let Some(lim) = limit {
-        let start = all_messages.len().saturating_sub(lim);
-        all_messages = all_messages[start..].to_vec();
-    }
-
-    Ok(all_messages)
 }
 
-/// List available Claude CLI sessions for a working directory.
-pub fn list_available_sessions(working_dir: &Path) -> Result<Vec<SessionInfo>, HistoryError> {
+/// Import history from all sessions for a working directory, paged as in
+/// [`read_messages`] once merged by timestamp. Messages a resumed session
+/// copied from an earlier one are kept once.
+pub fn import_all_history(
+    working_dir: &Path,
+    limit: Option<usize>,
+    offset: usize,
+) -> Result<Vec<Message>, HistoryError> {
     let project_dir = find_project_dir(working_dir)
         .ok_or_else(|| HistoryError::ProjectNotFound(working_dir.to_path_buf()))?;
 
-    let sessions = list_sessions(&project_dir);
-    let mut infos = Vec::new();
-
-    for session_path in sessions {
-        let id = session_path
-            .file_stem()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-
-        let modified = fs::metadata(&session_path)
-            .and_then(|m| m.modified())
-            .ok()
-            .and_then(|t| DateTime::<Utc>::from(t).into());
-
-        // Count messages.
-        let message_count = parse_session(&session_path)
-            .map(|m| m.len())
-            .unwrap_or(0);
-
-        infos.push(SessionInfo {
-            id,
-            modified,
-            message_count,
-        });
+    let mut all_messages = Vec::new();
+    let mut seen = HashSet::new();
+    for session_path in list_sessions(&project_dir) {
+        let messages = read_messages(&session_path, None, 0)?;
+        all_messages.extend(messages.into_iter().filter(|m| seen.insert(m.id.clone())));
     }
-
-    Ok(infos)
+    all_messages.sort_by_key(|a| a.timestamp);
+    Ok(crate::history_import::page(all_messages, limit, offset))
 }
 
-/// Info about a Claude CLI session.
-#[derive(Debug, Clone, Serialize)]
-pub struct SessionInfo {
-    pub id: String,
-    pub modified: Option<DateTime<Utc>>,
-    pub message_count: usize,
+/// Characters of the first prompt shown in a session listing.
+const PREVIEW_CHARS: usize = 120;
+
+/// List the Claude CLI sessions recorded for a working directory, newest
+/// first, at most `limit` (50 by default). A directory the CLI never ran in
+/// has none.
+pub fn list_session_summaries(working_dir: &Path, limit: Option<usize>) -> Vec<CliSessionInfo> {
+    let Some(project_dir) = find_project_dir(working_dir) else {
+        return Vec::new();
+    };
+    list_sessions(&project_dir)
+        .iter()
+        .take(limit.unwrap_or(50))
+        .map(|path| summarize_session(path))
+        .collect()
 }
 
-/// List session summaries for a working directory (fast — uses file metadata, no parsing).
-/// Returns at most `limit` sessions, sorted newest first.
-pub fn list_session_summaries(working_dir: &Path, limit: Option<usize>) -> Result<Vec<SessionInfo>, HistoryError> {
-    let project_dir = find_project_dir(working_dir)
-        .ok_or_else(|| HistoryError::ProjectNotFound(working_dir.to_path_buf()))?;
+/// Id, first prompt and message count of the session file at `path`.
+fn summarize_session(path: &Path) -> CliSessionInfo {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from);
+    let index = index(path).unwrap_or_else(|e| {
+        tracing::warn!("Failed to read Claude CLI session {}: {}", path.display(), e);
+        FileIndex::default()
+    });
 
-    let sessions = list_sessions(&project_dir);
-    let cap = limit.unwrap_or(50).min(sessions.len());
-    let mut infos = Vec::with_capacity(cap);
+    CliSessionInfo {
+        id: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        preview: index.preview,
+        modified,
+        message_count: index.offsets.len(),
+    }
+}
 
-    for session_path in sessions.iter().take(cap) {
-        let id = session_path
-            .file_stem()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-
-        let modified = fs::metadata(session_path)
-            .and_then(|m| m.modified())
-            .ok()
-            .map(|t| DateTime::<Utc>::from(t));
-
-        // Estimate message count from line count (fast) instead of full parse.
-        let message_count = fs::metadata(session_path)
-            .map(|m| {
-                // Rough estimate: each JSONL entry is ~500-2000 bytes.
-                // Divide file size by 1000 for a reasonable message count estimate.
-                // User/assistant pairs, so divide by 2-3 for actual exchanges.
-                let size = m.len();
-                (size / 1000).max(1) as usize
-            })
-            .unwrap_or(0);
-
-        infos.push(SessionInfo {
-            id,
-            modified,
-            message_count,
-        });
+/// The transcript of Claude session `session_id` in `working_dir`, if the
+/// CLI has a project for it.
+pub fn session_path(working_dir: &Path, session_id: &str) -> Option<PathBuf> {
+    if !is_session_id(session_id) {
+        return None;
     }
+    Some(find_project_dir(working_dir)?.join(format!("{}.jsonl", session_id)))
+}
 
-    Ok(infos)
+/// Messages in the session file at `path`, reading only what was added
+/// since it was last indexed.
+pub fn message_count(path: &Path) -> Result<usize, HistoryError> {
+    Ok(index(path)?.offsets.len())
 }
 
-/// Import history for a specific CLI session by its ID.
+/// Whether `id` can name a session file; keeps ids from reaching outside
+/// the project directory.
+fn is_session_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Import history for a specific CLI session by its ID, paged as in [`read_messages`].
 /// The `session_id` should be the UUID stem of a `.jsonl` file in the project sessions directory.
 pub fn import_session_by_id(
     working_dir: &Path,
     session_id: &str,
     limit: Option<usize>,
+    offset: usize,
 ) -> Result<Vec<Message>, HistoryError> {
     let project_dir = find_project_dir(working_dir)
         .ok_or_else(|| HistoryError::ProjectNotFound(working_dir.to_path_buf()))?;
 
+    if !is_session_id(session_id) {
+        return Err(HistoryError::SessionNotFound(session_id.to_string()));
+    }
     let session_file = project_dir.join(format!("{}.jsonl", session_id));
     if !session_file.exists() {
         return Err(HistoryError::SessionNotFound(session_id.to_string()));
     }
 
-    let mut messages = parse_session(&session_file)?;
+    read_messages(&session_file, limit, offset)
+}
 
-    // Apply limit.
-    if let Some(lim) = limit {
-        let start = messages.len().saturating_sub(lim);
-        messages = messages[start..].to_vec();
+/// Imports the Claude CLI's sessions, from `~/.claude/projects/`.
+pub struct ClaudeImporter;
+
+impl crate::history_import::HistoryImporter for ClaudeImporter {
+    fn source(&self) -> HistorySource {
+        HistorySource::Claude
     }
 
-    Ok(messages)
+    fn sessions(&self, working_dir: &Path) -> Vec<PathBuf> {
+        find_project_dir(working_dir)
+            .map(|dir| list_sessions(&dir))
+            .unwrap_or_default()
+    }
+
+    fn parse(&self, session: &Path) -> Result<Vec<Message>, HistoryError> {
+        parse_session(session)
+    }
 }
 
 /// Errors from history import.
@@ -362,3 +467,106 @@ pub enum HistoryError {
     #[error("JSON parse error: {0}")]
     JsonError(#[from] serde_json::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("0b5c-41d2.jsonl");
+        let prompt = "Fix   the flaky\n test ".to_string() + &"x".repeat(200);
+        let lines = [
+            serde_json::json!({"type": "user", "message": {"role": "user", "content": "<command-name>/clear</command-name>"}}),
+            serde_json::json!({"type": "summary", "summary": "Flaky test"}),
+            serde_json::json!({"type": "user", "message": {"role": "user", "content": prompt}}),
+            serde_json::json!({"type": "assistant", "message": {"role": "assistant", "content": [{"type": "text", "text": "Done."}]}}),
+        ];
+        let contents: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        fs::write(&path, contents.join("\n")).unwrap();
+
+        let info = summarize_session(&path);
+        assert_eq!(info.id, "0b5c-41d2");
+        assert_eq!(info.message_count, 3);
+        let preview = info.preview.unwrap();
+        assert!(preview.starts_with("Fix the flaky test xxx"));
+        assert_eq!(preview.chars().count(), PREVIEW_CHARS + 1);
+        assert!(info.modified.is_some());
+    }
+
+    fn entry(role: &str, text: &str) -> String {
+        serde_json::json!({"type": role, "message": {"role": role, "content": text}}).to_string() + "\n"
+    }
+
+    #[test]
+    fn test_incremental_index_and_paging() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.jsonl");
+        let mut contents = entry("user", "one") + &entry("assistant", "two");
+        // Still being written.
+        fs::write(&path, contents.clone() + "{\"type\": \"us").unwrap();
+        assert_eq!(index(&path).unwrap().offsets.len(), 2);
+
+        contents += &entry("user", "three");
+        contents += "{\"type\": \"summary\"}\n";
+        contents += &entry("assistant", "four");
+        fs::write(&path, &contents).unwrap();
+        let index = index(&path).unwrap();
+        assert_eq!(index.offsets.len(), 4);
+        assert_eq!(index.len, contents.len() as u64);
+        assert_eq!(index.preview.as_deref(), Some("one"));
+
+        let texts = |messages: Vec<Message>| messages.into_iter().map(|m| (m.id, m.content)).collect::<Vec<_>>();
+        assert_eq!(
+            texts(read_messages(&path, Some(2), 1).unwrap()),
+            [("imported-s-1".to_string(), "two".to_string()), ("imported-s-2".to_string(), "three".to_string())]
+        );
+        assert_eq!(read_messages(&path, None, 0).unwrap().len(), 4);
+        assert!(read_messages(&path, Some(2), 10).unwrap().is_empty());
+
+        // A rewritten, shorter file is indexed again.
+        fs::write(&path, entry("user", "new")).unwrap();
+        assert_eq!(texts(parse_session(&path).unwrap()), [("imported-s-0".to_string(), "new".to_string())]);
+    }
+
+    #[test]
+    fn test_ids_from_uuid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.jsonl");
+        let line = serde_json::json!({"type": "user", "uuid": "9f1c", "message": {"role": "user", "content": "hi"}});
+        fs::write(&path, entry("user", "old") + &line.to_string()).unwrap();
+        let ids: Vec<_> = parse_session(&path).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["imported-s-0", "imported-9f1c"]);
+    }
+
+    #[test]
+    fn test_usage_counted_once_per_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.jsonl");
+        let usage = serde_json::json!({"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 100});
+        let block = |text: &str| {
+            serde_json::json!({"type": "assistant", "costUSD": 0.01, "message": {"role": "assistant", "id": "msg_1", "usage": usage, "content": [{"type": "text", "text": text}]}})
+                .to_string()
+                + "\n"
+        };
+        fs::write(&path, entry("user", "hi") + &block("thinking it over") + &block("hello")).unwrap();
+
+        let messages = parse_session(&path).unwrap();
+        assert!(messages[0].usage.is_none());
+        let first = messages[1].usage.as_ref().unwrap();
+        assert_eq!((first.input_tokens, first.output_tokens, first.cache_read_tokens), (10, 5, Some(100)));
+        assert_eq!(messages[1].cost_usd, Some(0.01));
+        assert!(messages[2].usage.is_none());
+        assert_eq!(messages[2].cost_usd, None);
+        // Also when the page starts at the repeated line.
+        assert!(read_messages(&path, Some(1), 0).unwrap()[0].usage.is_none());
+    }
+
+    #[test]
+    fn test_session_ids_stay_in_project() {
+        assert!(is_session_id("6f1c2e1a-93b4-4c1e-8a5e-2b7f0d3c9e11"));
+        assert!(!is_session_id("../../.ssh/id_rsa"));
+        assert!(!is_session_id(""));
+    }
+}
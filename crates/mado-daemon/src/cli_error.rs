@@ -0,0 +1,148 @@
+//! Making sense of Claude CLI failures in chat mode.
+//!
+//! A failed `claude -p` exits non-zero, or ends with an `is_error` result,
+//! and says why on stderr or in the result text. The daemon matches that
+//! against known failures (expired login, rate limits, ...) so the UI can
+//! say what went wrong and whether retrying will help. Rate limits and an
+//! overloaded API are waited out, and retried if `chat.retry_rate_limits`
+//! is set in config.json.
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use mado_core::types::ChatErrorKind;
+use regex::Regex;
+
+use crate::config::ChatConfig;
+
+/// Longest wait between retries, whatever the backoff or the API says.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+/// How long the API says to wait: "retry-after: 30", "try again in 2 minutes".
+static RETRY_AFTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(?:retry[-_ ]after"?\s*[:=]?\s*"?|try again in\s+)(\d+)\s*(ms|m|min|minutes?|s|secs?|seconds?)?\b"#)
+        .expect("valid regex")
+});
+
+/// Substrings (lowercase) that identify each kind of failure, checked in order.
+const PATTERNS: &[(ChatErrorKind, &[&str])] = &[
+    (
+        ChatErrorKind::Auth,
+        &[
+            "invalid api key",
+            "/login",
+            "not logged in",
+            "authentication_error",
+            "oauth token",
+            "unauthorized",
+        ],
+    ),
+    (
+        ChatErrorKind::RateLimited,
+        &["rate_limit", "rate limit", "usage limit", "too many requests"],
+    ),
+    (ChatErrorKind::Overloaded, &["overloaded", "service unavailable"]),
+    (
+        ChatErrorKind::InvalidArguments,
+        &["unknown option", "unexpected argument", "invalid value for", "unrecognized option"],
+    ),
+    (
+        ChatErrorKind::Network,
+        &["econnrefused", "enotfound", "etimedout", "econnreset", "fetch failed", "network error"],
+    ),
+];
+
+/// Classify a failure from what the CLI printed about it.
+pub fn classify(output: &str) -> ChatErrorKind {
+    let output = output.to_lowercase();
+    PATTERNS
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|pattern| output.contains(pattern)))
+        .map(|(kind, _)| *kind)
+        .unwrap_or_default()
+}
+
+/// Whether a failure of `kind` passes by waiting.
+pub fn is_rate_limit(kind: ChatErrorKind) -> bool {
+    matches!(kind, ChatErrorKind::RateLimited | ChatErrorKind::Overloaded)
+}
+
+/// Seconds the CLI's `output` says to wait before retrying, if it does.
+pub fn retry_after(output: &str) -> Option<u64> {
+    let captures = RETRY_AFTER.captures(output)?;
+    let amount: u64 = captures[1].parse().ok()?;
+    match captures.get(2).map(|unit| unit.as_str().to_lowercase()).as_deref() {
+        Some("ms") => Some(amount.div_ceil(1000)),
+        Some(unit) if unit.starts_with('m') => amount.checked_mul(60),
+        _ => Some(amount),
+    }
+}
+
+/// How long to wait after `failures` responses in a row failed on a rate
+/// limit: `retry_after` seconds if the API said, or the configured backoff
+/// doubled for each failure before.
+pub fn backoff(failures: u32, retry_after: Option<u64>, config: &ChatConfig) -> Duration {
+    let seconds = retry_after.unwrap_or_else(|| {
+        let doublings = failures.saturating_sub(1).min(16);
+        config.retry_backoff_seconds.saturating_mul(1 << doublings)
+    });
+    Duration::from_secs(seconds).min(MAX_BACKOFF)
+}
+
+/// What to tell the user about a failure of `kind`; `exit` describes how the
+/// CLI exited, for failures without a known cause.
+pub fn describe(kind: ChatErrorKind, exit: &str) -> String {
+    match kind {
+        ChatErrorKind::Auth => {
+            "Claude CLI isn't logged in or its API key was rejected. Log in again, then retry.".to_string()
+        }
+        ChatErrorKind::RateLimited => "Claude hit a rate or usage limit. Retry later.".to_string(),
+        ChatErrorKind::Overloaded => "Claude's API is overloaded. Retry in a moment.".to_string(),
+        ChatErrorKind::InvalidArguments => {
+            "Claude CLI rejected its arguments; it may need updating.".to_string()
+        }
+        ChatErrorKind::Network => {
+            "Claude's API couldn't be reached. Check the connection, then retry.".to_string()
+        }
+        ChatErrorKind::Timeout => "Claude's response timed out. Retry, or raise the chat timeouts.".to_string(),
+        ChatErrorKind::ReadOnly => "Claude tried to change the workspace of a read-only session.".to_string(),
+        ChatErrorKind::Other => format!("Claude CLI failed ({}).", exit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("Invalid API key · Please run /login"), ChatErrorKind::Auth);
+        assert_eq!(
+            classify(r#"API Error: 429 {"type":"error","error":{"type":"rate_limit_error"}}"#),
+            ChatErrorKind::RateLimited
+        );
+        assert_eq!(classify("API Error: 529 Overloaded"), ChatErrorKind::Overloaded);
+        assert_eq!(
+            classify("error: unknown option '--max-thinking-tokens'"),
+            ChatErrorKind::InvalidArguments
+        );
+        assert_eq!(classify("TypeError: fetch failed"), ChatErrorKind::Network);
+        assert_eq!(classify("segmentation fault"), ChatErrorKind::Other);
+        assert_eq!(describe(ChatErrorKind::Other, "exit status: 1"), "Claude CLI failed (exit status: 1).");
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(retry_after(r#"429 {"retry-after": "30"}"#), Some(30));
+        assert_eq!(retry_after("Rate limited. Retry after 12 seconds"), Some(12));
+        assert_eq!(retry_after("Overloaded, try again in 2 minutes"), Some(120));
+        assert_eq!(retry_after("retry_after=1500ms"), Some(2));
+        assert_eq!(retry_after("API Error: 529 Overloaded"), None);
+
+        let config = ChatConfig::default();
+        assert_eq!(backoff(1, None, &config), Duration::from_secs(30));
+        assert_eq!(backoff(3, None, &config), Duration::from_secs(120));
+        assert_eq!(backoff(3, Some(5), &config), Duration::from_secs(5));
+        assert_eq!(backoff(40, None, &config), MAX_BACKOFF);
+    }
+}
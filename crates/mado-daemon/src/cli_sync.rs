@@ -0,0 +1,184 @@
+//! Following Claude CLI transcripts of chat sessions.
+//!
+//! A session's Claude session can also be continued from a terminal
+//! (`claude --resume <id>` in the same project). Every couple of seconds the
+//! daemon checks the transcript of each loaded conversation
+//! (`~/.claude/projects/<project>/<claude session id>.jsonl`) for messages
+//! it hasn't seen, appends them to the conversation and sends
+//! `MessageComplete` for each, as if they had streamed.
+//!
+//! The daemon's own turns are written to the same transcript, so whatever
+//! is added while the session runs a turn or command is skipped; a terminal
+//! turn taken at the same moment is missed too.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use mado_core::types::SessionId;
+
+use crate::conversation::SharedConversationManager;
+
+/// How often transcripts are checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A conversation whose transcript can be followed.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub session_id: SessionId,
+    pub claude_session_id: String,
+    pub working_dir: Option<String>,
+    /// A turn or command is running.
+    pub busy: bool,
+    /// Turns and commands the daemon has run in the conversation so far.
+    pub cli_runs: u64,
+}
+
+/// How much of a transcript a conversation has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Synced {
+    claude_session_id: String,
+    /// Messages in the transcript, as of the last check.
+    messages: usize,
+    cli_runs: u64,
+}
+
+/// What to do about a transcript now holding `messages` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Leave it until the daemon's turn is over.
+    Wait,
+    /// Record the count without importing: the transcript is new to the
+    /// conversation, or the daemon's own turns added to it.
+    Catch,
+    /// Import the newest this many messages.
+    Import(usize),
+}
+
+fn step(synced: Option<&Synced>, transcript: &Transcript, messages: usize) -> Step {
+    if transcript.busy {
+        return Step::Wait;
+    }
+    match synced {
+        Some(synced)
+            if synced.claude_session_id == transcript.claude_session_id
+                && synced.cli_runs == transcript.cli_runs
+                && messages > synced.messages =>
+        {
+            Step::Import(messages - synced.messages)
+        }
+        _ => Step::Catch,
+    }
+}
+
+/// Spawn the background task that follows transcripts.
+pub fn spawn(conversation_manager: SharedConversationManager) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("cli-sync", move || follow(conversation_manager.clone()))
+}
+
+async fn follow(conversation_manager: SharedConversationManager) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut synced: HashMap<SessionId, Synced> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+        let transcripts = conversation_manager.transcripts().await;
+        synced.retain(|id, _| transcripts.iter().any(|t| &t.session_id == id));
+
+        for transcript in transcripts {
+            let Some(path) = transcript_path(&transcript) else {
+                continue;
+            };
+            let counted = tokio::task::spawn_blocking({
+                let path = path.clone();
+                move || crate::claude_history::message_count(&path)
+            })
+            .await;
+            let messages = match counted {
+                Ok(Ok(messages)) => messages,
+                // Not written yet, or unreadable; try again next time.
+                _ => continue,
+            };
+
+            match step(synced.get(&transcript.session_id), &transcript, messages) {
+                Step::Wait => continue,
+                Step::Catch => {}
+                Step::Import(new) => {
+                    let read = tokio::task::spawn_blocking(move || {
+                        crate::claude_history::read_messages(&path, Some(new), 0).map_err(|e| e.to_string())
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+                    match read {
+                        Ok(imported) => {
+                            tracing::info!(
+                                "Synced {} message(s) from the Claude CLI into session {}",
+                                imported.len(),
+                                transcript.session_id
+                            );
+                            conversation_manager
+                                .append_external(&transcript.session_id, imported)
+                                .await;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to read transcript of session {}: {}", transcript.session_id, e);
+                            continue;
+                        }
+                    }
+                }
+            }
+            synced.insert(
+                transcript.session_id.clone(),
+                Synced {
+                    claude_session_id: transcript.claude_session_id,
+                    messages,
+                    cli_runs: transcript.cli_runs,
+                },
+            );
+        }
+    }
+}
+
+/// The transcript file of `transcript`'s Claude session.
+fn transcript_path(transcript: &Transcript) -> Option<PathBuf> {
+    let working_dir = match &transcript.working_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()?,
+    };
+    crate::claude_history::session_path(Path::new(&working_dir), &transcript.claude_session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(claude_session_id: &str, busy: bool, cli_runs: u64) -> Transcript {
+        Transcript {
+            session_id: SessionId::new("s1"),
+            claude_session_id: claude_session_id.to_string(),
+            working_dir: None,
+            busy,
+            cli_runs,
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let synced = Synced {
+            claude_session_id: "c1".to_string(),
+            messages: 4,
+            cli_runs: 1,
+        };
+        // First sight of the transcript.
+        assert_eq!(step(None, &transcript("c1", false, 1), 4), Step::Catch);
+        // Turns taken in a terminal.
+        assert_eq!(step(Some(&synced), &transcript("c1", false, 1), 6), Step::Import(2));
+        assert_eq!(step(Some(&synced), &transcript("c1", false, 1), 4), Step::Catch);
+        // The daemon's own turn, running and then finished.
+        assert_eq!(step(Some(&synced), &transcript("c1", true, 2), 5), Step::Wait);
+        assert_eq!(step(Some(&synced), &transcript("c1", false, 2), 6), Step::Catch);
+        // A different Claude session, after /resume or an edit.
+        assert_eq!(step(Some(&synced), &transcript("c2", false, 1), 9), Step::Catch);
+    }
+}
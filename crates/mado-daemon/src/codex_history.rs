@@ -0,0 +1,193 @@
+//! OpenAI Codex CLI sessions.
+//!
+//! The Codex CLI records each session as JSONL under
+//! `$CODEX_HOME/sessions/YYYY/MM/DD/rollout-<time>-<id>.jsonl`
+//! (`CODEX_HOME` defaults to `~/.codex`). The first line describes the
+//! session, including the directory it ran in:
+//!
+//! ```text
+//! {"timestamp":"…","type":"session_meta","payload":{"id":"…","cwd":"/repo"}}
+//! {"timestamp":"…","type":"response_item","payload":{"type":"message","role":"user","content":[{"type":"input_text","text":"…"}]}}
+//! {"timestamp":"…","type":"response_item","payload":{"type":"function_call","name":"shell","arguments":"{…}","call_id":"…"}}
+//! ```
+//!
+//! Messages and function calls are imported; the context Codex adds as
+//! user messages (`<environment_context>` and the like) is left out.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use mado_core::types::{HistorySource, Message, MessageRole, ToolCall, ToolCallStatus};
+use serde_json::Value;
+
+use crate::claude_history::HistoryError;
+use crate::history_import::{join_text, message_id, HistoryImporter};
+
+/// Imports Codex CLI sessions.
+pub struct CodexImporter;
+
+impl HistoryImporter for CodexImporter {
+    fn source(&self) -> HistorySource {
+        HistorySource::Codex
+    }
+
+    fn sessions(&self, working_dir: &Path) -> Vec<PathBuf> {
+        match codex_home() {
+            Some(home) => sessions(&home.join("sessions"), working_dir),
+            None => Vec::new(),
+        }
+    }
+
+    fn parse(&self, session: &Path) -> Result<Vec<Message>, HistoryError> {
+        parse(session)
+    }
+}
+
+/// Where the Codex CLI keeps its data.
+fn codex_home() -> Option<PathBuf> {
+    std::env::var_os("CODEX_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".codex")))
+}
+
+/// Session files under `dir` that ran in `working_dir`, newest first.
+fn sessions(dir: &Path, working_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_rollouts(dir, 0, &mut files);
+    files.retain(|file| cwd(file).is_some_and(|cwd| Path::new(&cwd) == working_dir));
+    // File names start with the time the session started.
+    files.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+    files
+}
+
+/// `rollout-*.jsonl` files in the year/month/day directories under `dir`.
+fn collect_rollouts(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && depth < 3 {
+            collect_rollouts(&path, depth + 1, files);
+        } else if path.extension().is_some_and(|ext| ext == "jsonl")
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("rollout-"))
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// The directory a session ran in, from its first line.
+fn cwd(session: &Path) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(File::open(session).ok()?).read_line(&mut line).ok()?;
+    let meta: Value = serde_json::from_str(&line).ok()?;
+    meta["payload"]["cwd"].as_str().map(str::to_string)
+}
+
+fn parse(session: &Path) -> Result<Vec<Message>, HistoryError> {
+    let mut messages: Vec<Message> = Vec::new();
+    for line in BufReader::new(File::open(session)?).lines() {
+        let Ok(entry) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        let timestamp = entry["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map_or_else(Utc::now, |t| t.with_timezone(&Utc));
+        // Older sessions have the items themselves on each line.
+        let item = if entry["type"] == "response_item" { &entry["payload"] } else { &entry };
+
+        match item["type"].as_str() {
+            Some("message") => {
+                let role = match item["role"].as_str() {
+                    Some("user") => MessageRole::User,
+                    Some("assistant") => MessageRole::Assistant,
+                    _ => continue,
+                };
+                let content = join_text(&item["content"]);
+                if content.trim().is_empty() || (role == MessageRole::User && content.trim_start().starts_with('<')) {
+                    continue;
+                }
+                messages.push(message(session, messages.len(), role, content, timestamp));
+            }
+            Some("function_call") => {
+                let arguments = item["arguments"].as_str().unwrap_or_default();
+                let call = ToolCall {
+                    id: item["call_id"].as_str().unwrap_or_default().to_string(),
+                    name: item["name"].as_str().unwrap_or("function").to_string(),
+                    input: serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string())),
+                    output: None,
+                    status: ToolCallStatus::Completed,
+                    subagent: Vec::new(),
+                };
+                match messages.last_mut() {
+                    Some(last) if last.role == MessageRole::Assistant => last.tool_calls.push(call),
+                    _ => {
+                        let mut reply = message(session, messages.len(), MessageRole::Assistant, String::new(), timestamp);
+                        reply.tool_calls.push(call);
+                        messages.push(reply);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(messages)
+}
+
+fn message(session: &Path, index: usize, role: MessageRole, content: String, timestamp: DateTime<Utc>) -> Message {
+    Message {
+        id: message_id(HistorySource::Codex, session, index),
+        role,
+        content,
+        tool_calls: Vec::new(),
+        timestamp,
+        usage: None,
+        cost_usd: None,
+        context_files: Vec::new(),
+        attachments: Vec::new(),
+        thinking: None,
+        source: Some(HistorySource::Codex),
+        compaction: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_and_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = dir.path().join("2025").join("06").join("01");
+        std::fs::create_dir_all(&day).unwrap();
+        let lines = [
+            serde_json::json!({"timestamp": "2025-06-01T10:00:00Z", "type": "session_meta", "payload": {"id": "a", "cwd": "/repo"}}),
+            serde_json::json!({"timestamp": "2025-06-01T10:00:01Z", "type": "response_item", "payload": {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "<environment_context>cwd</environment_context>"}]}}),
+            serde_json::json!({"timestamp": "2025-06-01T10:00:02Z", "type": "response_item", "payload": {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "List the files"}]}}),
+            serde_json::json!({"timestamp": "2025-06-01T10:00:03Z", "type": "response_item", "payload": {"type": "function_call", "name": "shell", "arguments": "{\"command\":[\"ls\"]}", "call_id": "c1"}}),
+            serde_json::json!({"timestamp": "2025-06-01T10:00:04Z", "type": "response_item", "payload": {"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "Two files."}]}}),
+        ];
+        let contents: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        let session = day.join("rollout-2025-06-01T10-00-00-a.jsonl");
+        std::fs::write(&session, contents.join("\n")).unwrap();
+        let other = serde_json::json!({"type": "session_meta", "payload": {"cwd": "/elsewhere"}});
+        std::fs::write(day.join("rollout-2025-06-01T11-00-00-b.jsonl"), other.to_string()).unwrap();
+
+        assert_eq!(sessions(dir.path(), Path::new("/repo")), vec![session.clone()]);
+
+        let messages = parse(&session).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].content, "List the files");
+        assert_eq!(messages[1].tool_calls[0].name, "shell");
+        assert_eq!(messages[1].tool_calls[0].input["command"][0], "ls");
+        assert_eq!(messages[2].content, "Two files.");
+        assert!(messages.iter().all(|m| m.source == Some(HistorySource::Codex)));
+    }
+}
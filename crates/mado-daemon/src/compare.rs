@@ -0,0 +1,382 @@
+//! Comparing models' answers to one prompt, for
+//! `POST /sessions/{id}/messages/compare`.
+//!
+//! Each model answers in its own fork of the session's conversation (see
+//! [`ConversationManager::fork`](crate::conversation::ConversationManager::fork))
+//! in plan mode, so none of them can change the workspace, and all run at
+//! once. Their chat events go to anyone following
+//! `GET /sessions/{id}/comparisons/{comparison_id}/stream`, tagged with the
+//! variant's id. Once every variant has answered, the comparison is saved as
+//! `<session>/comparisons/<id>.json` under the conversation storage
+//! directory. Applying a variant adds its turn to the session's conversation,
+//! which then carries on from that variant's Claude session.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use mado_core::types::{
+    CompareEvent, Comparison, ComparisonVariant, PermissionMode, SessionId, StreamEvent, TokenUsage,
+};
+
+use crate::conversation::{ConversationError, ConversationSession, MessageOptions, SharedConversationManager};
+use crate::replay::{REPLAY_CAPACITY, ReplayChannel};
+
+/// Most models one prompt can be compared across.
+pub const MAX_VARIANTS: usize = 3;
+
+/// Errors from comparing answers or loading comparisons.
+#[derive(Debug, thiserror::Error)]
+pub enum CompareError {
+    #[error("Compare 2 to {max} models, not {0}", max = MAX_VARIANTS)]
+    Variants(usize),
+
+    #[error("Comparison not found: {0}")]
+    NotFound(String),
+
+    #[error("Variant not found: {0}")]
+    VariantNotFound(String),
+
+    #[error("The comparison is still running")]
+    InProgress,
+
+    #[error("A variant of the comparison was applied already")]
+    Applied,
+
+    #[error("The variant has no answer to apply: {0}")]
+    Failed(String),
+
+    #[error(transparent)]
+    Conversation(#[from] ConversationError),
+
+    #[error("Unreadable comparison: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to save comparison: {0}")]
+    Write(#[from] crate::state::StateError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+struct LiveComparison {
+    session_id: String,
+    comparison: Comparison,
+    events: Arc<ReplayChannel<CompareEvent>>,
+}
+
+/// Comparisons in progress, by id.
+#[derive(Default)]
+pub struct Comparisons {
+    live: Mutex<HashMap<String, LiveComparison>>,
+}
+
+impl Comparisons {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, LiveComparison>> {
+        self.live.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Events of a session's comparison, if it is still in progress.
+    pub fn events(&self, session_id: &SessionId, comparison_id: &str) -> Option<Arc<ReplayChannel<CompareEvent>>> {
+        self.lock()
+            .get(comparison_id)
+            .filter(|live| live.session_id == session_id.as_str())
+            .map(|live| live.events.clone())
+    }
+
+    /// A session's comparisons in progress.
+    pub fn in_progress(&self, session_id: &SessionId) -> Vec<Comparison> {
+        self.lock()
+            .values()
+            .filter(|live| live.session_id == session_id.as_str())
+            .map(|live| live.comparison.clone())
+            .collect()
+    }
+
+    /// Ask each of `models` to answer `prompt` in a fork of the session's
+    /// conversation. Returns the comparison as started; it is saved in
+    /// `conversations.comparisons_dir()` once every variant has answered.
+    pub async fn start(
+        self: &Arc<Self>,
+        conversations: SharedConversationManager,
+        session_id: &SessionId,
+        prompt: String,
+        models: Vec<String>,
+    ) -> Result<Comparison, CompareError> {
+        if !(2..=MAX_VARIANTS).contains(&models.len()) {
+            return Err(CompareError::Variants(models.len()));
+        }
+        let base = conversations.fork(session_id, None).await?;
+        let comparison = Comparison {
+            id: Uuid::new_v4().to_string(),
+            prompt: prompt.clone(),
+            created_at: Utc::now(),
+            finished_at: None,
+            after_message_id: base.messages.last().map(|m| m.id.clone()),
+            variants: models
+                .into_iter()
+                .map(|model| ComparisonVariant {
+                    id: Uuid::new_v4().to_string(),
+                    model,
+                    messages: Vec::new(),
+                    error: None,
+                    usage: TokenUsage::default(),
+                    cost_usd: 0.0,
+                    claude_session_id: None,
+                })
+                .collect(),
+            applied: None,
+        };
+
+        let mut started = Vec::new();
+        for variant in &comparison.variants {
+            let fork_id = SessionId::new(format!("compare-{}", variant.id));
+            conversations
+                .insert_session(
+                    &fork_id,
+                    ConversationSession {
+                        model: variant.model.clone(),
+                        permission_mode: PermissionMode::Plan,
+                        ..base.clone()
+                    },
+                )
+                .await;
+            let live = conversations.subscribe(&fork_id, None).await.live;
+            if let Err(e) = conversations
+                .send_message(&fork_id, prompt.clone(), MessageOptions::default())
+                .await
+            {
+                conversations.remove_session(&fork_id).await;
+                for (_, fork_id, _) in started {
+                    conversations.remove_session(&fork_id).await;
+                }
+                return Err(e.into());
+            }
+            started.push((variant.id.clone(), fork_id, live));
+        }
+
+        let events = Arc::new(ReplayChannel::new(REPLAY_CAPACITY));
+        self.lock().insert(
+            comparison.id.clone(),
+            LiveComparison {
+                session_id: session_id.as_str().to_string(),
+                comparison: comparison.clone(),
+                events: events.clone(),
+            },
+        );
+        tracing::info!(
+            "Comparing {} models for session {} ({})",
+            comparison.variants.len(),
+            session_id,
+            comparison.id
+        );
+
+        let comparisons = self.clone();
+        let session_id = session_id.clone();
+        let skip = base.messages.len();
+        let mut finished = comparison.clone();
+        tokio::spawn(async move {
+            let answers = started.into_iter().map(|(variant_id, fork_id, live)| {
+                answer(conversations.clone(), events.clone(), variant_id, fork_id, live, skip)
+            });
+            let answers = futures::future::join_all(answers).await;
+            for (variant, answered) in finished.variants.iter_mut().zip(answers) {
+                variant.messages = answered.messages;
+                variant.error = answered.error;
+                variant.usage = answered.usage;
+                variant.cost_usd = answered.cost_usd;
+                variant.claude_session_id = answered.claude_session_id;
+            }
+            finished.finished_at = Some(Utc::now());
+
+            // Saved before it leaves `live`, so it can always be found.
+            if let Err(e) = save(&conversations.comparisons_dir(&session_id), &finished) {
+                tracing::warn!("Failed to save comparison {}: {}", finished.id, e);
+            }
+            tracing::info!("Comparison {} for session {} finished", finished.id, session_id);
+            let id = finished.id.clone();
+            events.send(CompareEvent::Complete { comparison: finished });
+            comparisons.lock().remove(&id);
+        });
+
+        Ok(comparison)
+    }
+
+    /// Add the turn of the variant `variant_id` of a finished comparison to
+    /// the session's conversation. Returns the comparison, marked applied.
+    pub async fn apply(
+        &self,
+        conversations: &SharedConversationManager,
+        session_id: &SessionId,
+        comparison_id: &str,
+        variant_id: &str,
+    ) -> Result<Comparison, CompareError> {
+        if self.events(session_id, comparison_id).is_some() {
+            return Err(CompareError::InProgress);
+        }
+        let dir = conversations.comparisons_dir(session_id);
+        let mut comparison = load(&dir, comparison_id)?;
+        if comparison.applied.is_some() {
+            return Err(CompareError::Applied);
+        }
+        let variant = comparison
+            .variants
+            .iter()
+            .find(|variant| variant.id == variant_id)
+            .ok_or_else(|| CompareError::VariantNotFound(variant_id.to_string()))?;
+        let claude_session_id = variant
+            .claude_session_id
+            .clone()
+            .filter(|_| variant.error.is_none())
+            .ok_or_else(|| CompareError::Failed(variant_id.to_string()))?;
+
+        conversations
+            .adopt_fork(
+                session_id,
+                comparison.after_message_id.as_deref(),
+                variant.messages.clone(),
+                claude_session_id,
+            )
+            .await?;
+        comparison.applied = Some(variant_id.to_string());
+        save(&dir, &comparison)?;
+        tracing::info!("Applied {} of comparison {} to session {}", variant.model, comparison.id, session_id);
+        Ok(comparison)
+    }
+}
+
+/// What one variant answered.
+struct Answer {
+    messages: Vec<mado_core::types::Message>,
+    error: Option<String>,
+    usage: TokenUsage,
+    cost_usd: f64,
+    claude_session_id: Option<String>,
+}
+
+/// Pass a variant's chat events on until it goes idle, then collect its
+/// turn (the messages after the first `skip`) and drop its conversation.
+async fn answer(
+    conversations: SharedConversationManager,
+    events: Arc<ReplayChannel<CompareEvent>>,
+    variant_id: String,
+    fork_id: SessionId,
+    mut live: broadcast::Receiver<(u64, StreamEvent)>,
+    skip: usize,
+) -> Answer {
+    let mut error = None;
+    loop {
+        let event = match live.recv().await {
+            Ok((_, event)) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let idle = matches!(event, StreamEvent::Idle);
+        if let StreamEvent::Error { message, .. } = &event {
+            error = Some(message.clone());
+        }
+        events.send(CompareEvent::Variant {
+            variant_id: variant_id.clone(),
+            event,
+        });
+        if idle {
+            break;
+        }
+    }
+
+    let messages: Vec<_> = conversations
+        .get_messages(&fork_id, None, None)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .skip(skip)
+        .collect();
+    let mut usage = TokenUsage::default();
+    for message in &messages {
+        if let Some(message_usage) = &message.usage {
+            crate::usage::add(&mut usage, message_usage);
+        }
+    }
+    let claude_session_id = conversations.claude_session_id(&fork_id).await;
+    conversations.remove_session(&fork_id).await;
+    Answer {
+        cost_usd: messages.iter().filter_map(|m| m.cost_usd).sum(),
+        messages,
+        error,
+        usage,
+        claude_session_id,
+    }
+}
+
+/// Directory of a session's comparisons under the conversation storage dir.
+pub fn dir(storage_dir: &Path, session_id: &str) -> PathBuf {
+    storage_dir.join(session_id).join("comparisons")
+}
+
+/// Save `comparison` in `dir`, with secrets redacted.
+pub fn save(dir: &Path, comparison: &Comparison) -> Result<(), CompareError> {
+    std::fs::create_dir_all(dir)?;
+    let json = serde_json::to_string(comparison)?;
+    crate::state::write_atomic(&dir.join(format!("{}.json", comparison.id)), &crate::redact::redact(&json))?;
+    Ok(())
+}
+
+/// The comparison `id` in `dir`.
+pub fn load(dir: &Path, id: &str) -> Result<Comparison, CompareError> {
+    // Ids are uuids; anything else can't name a file in `dir`.
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return Err(CompareError::NotFound(id.to_string()));
+    }
+    let contents = match std::fs::read_to_string(dir.join(format!("{}.json", id))) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(CompareError::NotFound(id.to_string())),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Finished comparisons in `dir`, newest first. Unreadable files are skipped.
+pub fn list(dir: &Path) -> Vec<Comparison> {
+    let mut comparisons: Vec<Comparison> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|contents| serde_json::from_str(&contents).ok())
+        .collect();
+    comparisons.sort_by_key(|comparison| std::cmp::Reverse(comparison.created_at));
+    comparisons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_list() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = dir(tmp.path(), "s1");
+        let comparison = |id: &str, minutes: i64| Comparison {
+            id: id.to_string(),
+            prompt: "Which is better for sk-ant-REDACTED?".to_string(),
+            created_at: Utc::now() - chrono::Duration::minutes(minutes),
+            finished_at: Some(Utc::now()),
+            after_message_id: None,
+            variants: Vec::new(),
+            applied: None,
+        };
+        save(&dir, &comparison("0a1b", 10)).unwrap();
+        save(&dir, &comparison("2c3d", 5)).unwrap();
+
+        assert_eq!(load(&dir, "0a1b").unwrap().prompt, "Which is better for [REDACTED]?");
+        let ids: Vec<_> = list(&dir).into_iter().map(|comparison| comparison.id).collect();
+        assert_eq!(ids, ["2c3d", "0a1b"]);
+        assert!(matches!(load(&dir, "9999"), Err(CompareError::NotFound(_))));
+        assert!(matches!(load(&dir, "../s1"), Err(CompareError::NotFound(_))));
+    }
+}
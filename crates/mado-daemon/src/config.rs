@@ -1,13 +1,19 @@
 //! Mado configuration management.
 //!
 //! Stores app settings in ~/.mado/config.json.
-//! API keys are NOT stored here — they use the OS keychain.
+//! API keys are NOT stored here — they use the OS keychain, or
+//! ~/.mado/secrets.json with `secrets_file` set.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing;
 
+use crate::keystore::{self, KeyStore};
+
 /// Configuration version for migrations.
 const CONFIG_VERSION: u32 = 1;
 
@@ -47,6 +53,292 @@ pub struct UiConfig {
     pub ai_name: Option<String>,
 }
 
+/// Daemon log file limits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogsConfig {
+    /// Maximum total size of the log directory, in megabytes.
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Days to keep old log files.
+    #[serde(default = "default_log_retention_days")]
+    pub retention_days: u32,
+}
+
+fn default_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_log_retention_days() -> u32 {
+    14
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_log_max_size_mb(),
+            retention_days: default_log_retention_days(),
+        }
+    }
+}
+
+/// When terminated sessions are archived and removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Remove terminated sessions not updated for this many days.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u32>,
+
+    /// Keep at most this many terminated sessions, removing the oldest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sessions: Option<usize>,
+
+    /// Archive sessions to ~/.mado/archive/ before removing them.
+    #[serde(default = "default_archive")]
+    pub archive: bool,
+}
+
+fn default_archive() -> bool {
+    true
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            max_sessions: None,
+            archive: default_archive(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Whether any limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days.is_some() || self.max_sessions.is_some()
+    }
+}
+
+/// Respawning of session processes that crash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RespawnConfig {
+    /// Revive a session whose process exits with a failure.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Crashes in a row after which the session is left terminated.
+    #[serde(default = "default_respawn_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_respawn_max_retries() -> u32 {
+    3
+}
+
+impl Default for RespawnConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_retries: default_respawn_max_retries(),
+        }
+    }
+}
+
+/// Limits on what one session's processes may use (see `crate::limits`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// CPU seconds a session's processes may use, including ones that have
+    /// since exited. Restarting the session starts the count over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds: Option<u64>,
+
+    /// Megabytes of memory a session's processes may hold at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u64>,
+}
+
+/// Asciicast recordings of session terminals (see `crate::recording`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingConfig {
+    /// Record every session's terminal output.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Megabytes after which a session's recording stops growing.
+    #[serde(default = "default_recording_max_mb")]
+    pub max_mb: u64,
+}
+
+fn default_recording_max_mb() -> u64 {
+    50
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_mb: default_recording_max_mb(),
+        }
+    }
+}
+
+/// Kind of software a forge runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    Github,
+    Gitlab,
+}
+
+/// A self-hosted forge, such as GitHub Enterprise or a GitLab instance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeConfig {
+    pub kind: ForgeKind,
+    /// API root, e.g. `https://git.example.com/api/v4`; derived from the
+    /// host when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+/// Time limits on chat responses, after which `claude -p` is stopped, and
+/// retries of responses that failed on rate limits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Minutes a response may take in total. Off by default, since agentic
+    /// turns can legitimately run for a long time.
+    #[serde(default)]
+    pub timeout_minutes: Option<u64>,
+
+    /// Seconds a response may go without a stream event (default 900).
+    /// Claude prints nothing while a tool runs, so this must outlast the
+    /// slowest expected tool call. `null` turns it off.
+    #[serde(default = "default_chat_stall_seconds")]
+    pub stall_seconds: Option<u64>,
+
+    /// Send a message again by itself when its response failed on a rate
+    /// limit or an overloaded API, once the backoff has passed.
+    #[serde(default)]
+    pub retry_rate_limits: bool,
+
+    /// Retries in a row before leaving it to the user (default 3).
+    #[serde(default = "default_chat_max_retries")]
+    pub max_retries: u32,
+
+    /// Seconds to wait before the first retry, doubling with each one
+    /// (default 30), unless the API says how long to wait.
+    #[serde(default = "default_chat_retry_backoff_seconds")]
+    pub retry_backoff_seconds: u64,
+}
+
+fn default_chat_stall_seconds() -> Option<u64> {
+    Some(900)
+}
+
+fn default_chat_max_retries() -> u32 {
+    3
+}
+
+fn default_chat_retry_backoff_seconds() -> u64 {
+    30
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            timeout_minutes: None,
+            stall_seconds: default_chat_stall_seconds(),
+            retry_rate_limits: false,
+            max_retries: default_chat_max_retries(),
+            retry_backoff_seconds: default_chat_retry_backoff_seconds(),
+        }
+    }
+}
+
+/// A git branch for each session, merged back when the work is done.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchesConfig {
+    /// Create and check out a branch when a session starts in a repository.
+    /// Sessions share the repository's working tree, so only one running
+    /// session at a time gets a branch there; the others work on whatever
+    /// it has checked out.
+    #[serde(default)]
+    pub per_session: bool,
+
+    /// Start of the branch names, followed by the session name (default
+    /// "mado/", giving e.g. "mado/fix-login-timeout").
+    #[serde(default = "default_branch_prefix")]
+    pub prefix: String,
+}
+
+fn default_branch_prefix() -> String {
+    "mado/".to_string()
+}
+
+impl Default for BranchesConfig {
+    fn default() -> Self {
+        Self {
+            per_session: false,
+            prefix: default_branch_prefix(),
+        }
+    }
+}
+
+/// Sandboxed sessions; see [`crate::sandbox`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxConfig {
+    /// Hosts every sandboxed session may connect to, exactly or as
+    /// `*.domain` (default the Anthropic API).
+    #[serde(default = "default_sandbox_hosts")]
+    pub allowed_hosts: Vec<String>,
+}
+
+fn default_sandbox_hosts() -> Vec<String> {
+    vec!["api.anthropic.com".to_string()]
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: default_sandbox_hosts(),
+        }
+    }
+}
+
+/// Spending limits across all sessions, in USD.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Limit on what one day's turns may cost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_usd: Option<f64>,
+
+    /// Limit on what one calendar month's turns may cost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_usd: Option<f64>,
+
+    /// Fraction of a limit at which to warn (default 0.8).
+    #[serde(default = "default_budget_warn_at")]
+    pub warn_at: f64,
+
+    /// Refuse new messages while a limit is exceeded.
+    #[serde(default)]
+    pub block: bool,
+}
+
+fn default_budget_warn_at() -> f64 {
+    0.8
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            daily_usd: None,
+            monthly_usd: None,
+            warn_at: default_budget_warn_at(),
+            block: false,
+        }
+    }
+}
+
 fn default_theme() -> String {
     "dark".to_string()
 }
@@ -86,15 +378,98 @@ pub struct MadoConfig {
     #[serde(default = "default_model")]
     pub default_model: String,
 
+    /// Claude CLI binary to run; found on PATH and in the usual install
+    /// locations when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_path: Option<String>,
+
+    /// Flags passed to the Claude CLI on every spawn, e.g.
+    /// `["--allowedTools", "Bash Edit"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_flags: Vec<String>,
+
+    /// API base URLs by provider (e.g. `{"anthropic": "https://proxy.example"}`),
+    /// passed to spawned CLIs as `ANTHROPIC_BASE_URL` and the like.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub base_urls: HashMap<String, String>,
+
+    /// Keep API keys and secrets in ~/.mado/secrets.json instead of the OS
+    /// keychain, for headless daemons without one.
+    #[serde(default)]
+    pub secrets_file: bool,
+
+    /// Self-hosted forges by the host in remote URLs, for opening pull
+    /// requests; github.com and gitlab.com are known already.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub forges: HashMap<String, ForgeConfig>,
+
     /// Whether onboarding/setup has been completed.
     #[serde(default)]
     pub setup_complete: bool,
 
+    /// Save a milestone after every completed response.
+    #[serde(default)]
+    pub auto_milestone: bool,
+
+    /// Daemon log filter (e.g. "debug"), overriding `--log-level`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+
+    /// Daemon log size and retention limits.
+    #[serde(default)]
+    pub logs: LogsConfig,
+
+    /// Kilobytes of recent terminal output kept per session and replayed to
+    /// panes that attach later.
+    #[serde(default = "default_scrollback_kb")]
+    pub scrollback_kb: usize,
+
+    /// Minutes the daemon may sit without sessions, streams or requests
+    /// before it exits. Never exits when unset or 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_minutes: Option<u64>,
+
+    /// Cleanup policy for terminated sessions.
+    #[serde(default)]
+    pub retention: RetentionConfig,
+
+    /// Respawning of crashed session processes.
+    #[serde(default)]
+    pub respawn: RespawnConfig,
+
+    /// Terminal recordings.
+    #[serde(default)]
+    pub recording: RecordingConfig,
+
+    /// CPU and memory limits per session.
+    #[serde(default)]
+    pub limits: LimitsConfig,
+
+    /// Daily and monthly spending limits.
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    /// Chat response timeouts.
+    #[serde(default)]
+    pub chat: ChatConfig,
+
+    /// Branch per session.
+    #[serde(default)]
+    pub branches: BranchesConfig,
+
+    /// Sandboxed sessions.
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+
     /// UI settings.
     #[serde(default)]
     pub ui: UiConfig,
 }
 
+fn default_scrollback_kb() -> usize {
+    256
+}
+
 fn default_version() -> u32 {
     CONFIG_VERSION
 }
@@ -118,7 +493,25 @@ impl Default for MadoConfig {
             provider: default_provider(),
             auth_method: default_auth_method(),
             default_model: default_model(),
+            claude_path: None,
+            default_flags: Vec::new(),
+            base_urls: HashMap::new(),
+            secrets_file: false,
+            forges: HashMap::new(),
             setup_complete: false,
+            auto_milestone: false,
+            log_level: None,
+            logs: LogsConfig::default(),
+            scrollback_kb: default_scrollback_kb(),
+            idle_timeout_minutes: None,
+            retention: RetentionConfig::default(),
+            respawn: RespawnConfig::default(),
+            recording: RecordingConfig::default(),
+            limits: LimitsConfig::default(),
+            budget: BudgetConfig::default(),
+            chat: ChatConfig::default(),
+            branches: BranchesConfig::default(),
+            sandbox: SandboxConfig::default(),
             ui: UiConfig::default(),
         }
     }
@@ -147,6 +540,19 @@ impl MadoConfig {
         Ok(config)
     }
 
+    /// Read config from `path` without creating it; defaults if missing.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ConfigError::ReadError(e.to_string()))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| ConfigError::ParseError(e.to_string()))
+    }
+
     /// Save config to ~/.mado/config.json.
     pub fn save(&self) -> Result<(), ConfigError> {
         let dir = config_dir();
@@ -166,6 +572,118 @@ impl MadoConfig {
         Ok(())
     }
 
+    /// Check every setting, returning all that are invalid.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, field: &str, message: String| {
+            if !ok {
+                errors.push(FieldError {
+                    field: field.to_string(),
+                    message,
+                });
+            }
+        };
+        let positive = |value: Option<u64>| value.is_none_or(|value| value > 0);
+
+        check(
+            ["cli", "api_key"].contains(&self.auth_method.as_str()),
+            "auth_method",
+            format!("must be \"cli\" or \"api_key\", not {:?}", self.auth_method),
+        );
+        check(
+            crate::process::VALID_MODELS.contains(&self.default_model.as_str()),
+            "default_model",
+            format!(
+                "unknown model {:?}; expected one of {}",
+                self.default_model,
+                crate::process::VALID_MODELS.join(", ")
+            ),
+        );
+        if let Some(path) = &self.claude_path {
+            check(
+                crate::process::is_executable(Path::new(path)),
+                "claude_path",
+                format!("{} is not an executable file", path),
+            );
+        }
+        if let Err(e) = crate::claude_cli::validate_flags(&self.default_flags) {
+            check(false, "default_flags", e.to_string());
+        }
+        for (provider, url) in &self.base_urls {
+            let field = format!("base_urls.{}", provider);
+            if let Err(e) = keystore::provider(provider) {
+                check(false, &field, e.to_string());
+            }
+            check(
+                url.starts_with("https://") || url.starts_with("http://"),
+                &field,
+                format!("{:?} is not an http(s) URL", url),
+            );
+        }
+        for (host, forge) in &self.forges {
+            if let Some(url) = &forge.api_url {
+                check(
+                    url.starts_with("https://") || url.starts_with("http://"),
+                    &format!("forges.{}.api_url", host),
+                    format!("{:?} is not an http(s) URL", url),
+                );
+            }
+        }
+        if let Some(level) = &self.log_level
+            && let Err(e) = tracing_subscriber::EnvFilter::try_new(level)
+        {
+            check(false, "log_level", e.to_string());
+        }
+        check(self.logs.max_size_mb > 0, "logs.max_size_mb", "must be positive".to_string());
+        check(self.scrollback_kb > 0, "scrollback_kb", "must be positive".to_string());
+        check(
+            self.retention.max_age_days.is_none_or(|days| days > 0),
+            "retention.max_age_days",
+            "must be positive".to_string(),
+        );
+        check(self.recording.max_mb > 0, "recording.max_mb", "must be positive".to_string());
+        check(positive(self.limits.cpu_seconds), "limits.cpu_seconds", "must be positive".to_string());
+        check(positive(self.limits.memory_mb), "limits.memory_mb", "must be positive".to_string());
+        for (field, limit) in [("budget.daily_usd", self.budget.daily_usd), ("budget.monthly_usd", self.budget.monthly_usd)] {
+            check(
+                limit.is_none_or(|usd| usd.is_finite() && usd > 0.0),
+                field,
+                "must be a positive amount".to_string(),
+            );
+        }
+        check(
+            self.budget.warn_at > 0.0 && self.budget.warn_at <= 1.0,
+            "budget.warn_at",
+            "must be a fraction between 0 and 1".to_string(),
+        );
+        check(positive(self.chat.timeout_minutes), "chat.timeout_minutes", "must be positive".to_string());
+        check(positive(self.chat.stall_seconds), "chat.stall_seconds", "must be positive".to_string());
+        check(
+            self.chat.retry_backoff_seconds > 0,
+            "chat.retry_backoff_seconds",
+            "must be positive".to_string(),
+        );
+        check(
+            git2::Reference::is_valid_name(&format!("refs/heads/{}session", self.branches.prefix)),
+            "branches.prefix",
+            "must be usable in a branch name".to_string(),
+        );
+        if let Err(message) = crate::sandbox::check_hosts(&self.sandbox.allowed_hosts) {
+            check(false, "sandbox.allowed_hosts", message);
+        }
+        check(
+            (50..=200).contains(&self.ui.zoom_level),
+            "ui.zoom_level",
+            "must be between 50 and 200".to_string(),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+
     /// Update a single field and save.
     pub fn update<F>(&mut self, f: F) -> Result<(), ConfigError>
     where
@@ -176,6 +694,149 @@ impl MadoConfig {
     }
 }
 
+/// The parts of the config the daemon acts on, rebuilt on SIGHUP and when
+/// config.json changes.
+#[derive(Debug, Clone)]
+pub struct DaemonSettings {
+    /// Model for new sessions that don't name one.
+    pub default_model: String,
+    /// Claude CLI binary, if configured.
+    pub claude_path: Option<PathBuf>,
+    /// Flags passed to the Claude CLI on every spawn. Invalid flags are
+    /// dropped with a warning.
+    pub default_flags: Vec<String>,
+    /// Save a milestone after every completed response.
+    pub auto_milestone: bool,
+    /// API key passed to spawned CLIs, when `auth_method` is "api_key".
+    pub api_key: Option<String>,
+    /// Other providers' stored keys and configured base URLs, as environment
+    /// variables for spawned CLIs.
+    pub provider_env: Vec<(String, String)>,
+    /// Log file limits, applied by the log pruner.
+    pub logs: LogsConfig,
+    /// PTY output kept per session for replay; applies to processes
+    /// spawned after a reload.
+    pub scrollback_bytes: usize,
+    /// Exit after this long without sessions, streams or requests.
+    pub idle_timeout: Option<Duration>,
+    /// Cleanup policy for terminated sessions.
+    pub retention: RetentionConfig,
+    /// Respawning of crashed session processes.
+    pub respawn: RespawnConfig,
+    /// Terminal recordings; apply to processes spawned after a reload.
+    pub recording: RecordingConfig,
+    /// CPU and memory limits per session, enforced by the watchdog.
+    pub limits: LimitsConfig,
+    /// Spending limits, checked against the usage ledger.
+    pub budget: BudgetConfig,
+    /// Chat response timeouts; apply to responses started after a reload.
+    pub chat: ChatConfig,
+    /// Self-hosted forges by host.
+    pub forges: HashMap<String, ForgeConfig>,
+    /// Branch per session; applies to sessions created after a reload.
+    pub branches: BranchesConfig,
+    /// Sandboxed sessions; apply to processes spawned after a reload.
+    pub sandbox: SandboxConfig,
+}
+
+impl Default for DaemonSettings {
+    fn default() -> Self {
+        Self {
+            default_model: default_model(),
+            claude_path: None,
+            default_flags: Vec::new(),
+            auto_milestone: false,
+            api_key: None,
+            provider_env: Vec::new(),
+            logs: LogsConfig::default(),
+            scrollback_bytes: default_scrollback_kb() * 1024,
+            idle_timeout: None,
+            retention: RetentionConfig::default(),
+            respawn: RespawnConfig::default(),
+            recording: RecordingConfig::default(),
+            limits: LimitsConfig::default(),
+            budget: BudgetConfig::default(),
+            chat: ChatConfig::default(),
+            forges: HashMap::new(),
+            branches: BranchesConfig::default(),
+            sandbox: SandboxConfig::default(),
+        }
+    }
+}
+
+impl DaemonSettings {
+    /// Derive settings from a config, reading API keys from the keystore.
+    pub fn from_config(config: &MadoConfig) -> Self {
+        let api_key = if config.auth_method == "api_key" {
+            KeyStore::get_api_key()
+                .inspect_err(|e| tracing::warn!("API key unavailable: {}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        // The Anthropic key would override a subscription login, so it only
+        // goes in `api_key`; other providers' keys are always passed.
+        let mut provider_env: Vec<(String, String)> = keystore::PROVIDERS
+            .iter()
+            .filter(|provider| provider.name != keystore::ANTHROPIC)
+            .filter_map(|provider| {
+                let key = KeyStore::get_provider_key(provider.name).ok()?;
+                Some((provider.key_var.to_string(), key))
+            })
+            .collect();
+        for (name, url) in &config.base_urls {
+            match keystore::provider(name) {
+                Ok(provider) => provider_env.push((provider.base_url_var.to_string(), url.clone())),
+                Err(e) => tracing::warn!("Ignoring base URL: {}", e),
+            }
+        }
+
+        let default_flags = match crate::claude_cli::validate_flags(&config.default_flags) {
+            Ok(()) => config.default_flags.clone(),
+            Err(e) => {
+                tracing::warn!("Ignoring default_flags: {}", e);
+                Vec::new()
+            }
+        };
+
+        Self {
+            default_model: config.default_model.clone(),
+            claude_path: config.claude_path.as_ref().map(PathBuf::from),
+            default_flags,
+            auto_milestone: config.auto_milestone,
+            api_key,
+            provider_env,
+            logs: config.logs.clone(),
+            scrollback_bytes: config.scrollback_kb.saturating_mul(1024),
+            idle_timeout: config
+                .idle_timeout_minutes
+                .filter(|minutes| *minutes > 0)
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            retention: config.retention.clone(),
+            respawn: config.respawn.clone(),
+            recording: config.recording.clone(),
+            limits: config.limits.clone(),
+            budget: config.budget.clone(),
+            chat: config.chat.clone(),
+            forges: config.forges.clone(),
+            branches: config.branches.clone(),
+            sandbox: config.sandbox.clone(),
+        }
+    }
+}
+
+/// Settings shared between the server and the reload handler.
+pub type SharedSettings = Arc<RwLock<DaemonSettings>>;
+
+/// A setting that failed [`MadoConfig::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Dotted path of the setting, e.g. `budget.daily_usd`.
+    pub field: String,
+    pub message: String,
+}
+
 /// Config-related errors.
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -190,4 +851,7 @@ pub enum ConfigError {
 
     #[error("Failed to write config: {0}")]
     WriteError(String),
+
+    #[error("Invalid config: {}", .0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join("; "))]
+    Invalid(Vec<FieldError>),
 }
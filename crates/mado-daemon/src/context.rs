@@ -0,0 +1,205 @@
+//! Workspace files attached to chat messages as context.
+//!
+//! A message can @-mention files in the session's working directory. Their
+//! contents are read when the message is sent and placed ahead of it in the
+//! prompt, each in a `<file path="...">` block, while the stored message
+//! keeps only the typed text plus a [`ContextFile`] per attachment for the
+//! UI to show. Each file and the whole set are size-capped; whatever doesn't
+//! fit is cut off and marked truncated.
+
+use std::io::Read;
+use std::path::Path;
+
+use mado_core::types::{Attachment, ContextFile, Message, MessageRole};
+
+use crate::files::{resolve, FilesError};
+
+/// Most files attached to one message.
+pub const MAX_CONTEXT_FILES: usize = 20;
+
+/// Most bytes included from one file (100 KiB).
+const MAX_FILE_BYTES: usize = 100 * 1024;
+
+/// Most bytes included from all files of a message (400 KiB).
+const MAX_TOTAL_BYTES: usize = 400 * 1024;
+
+/// A file read for a message, with the text that goes into the prompt.
+#[derive(Debug, Clone)]
+pub struct LoadedContext {
+    pub file: ContextFile,
+    pub content: String,
+}
+
+/// Read `paths` (relative to `root`) for a message. Repeated paths are
+/// included once.
+pub fn load(root: &Path, paths: &[String]) -> Result<Vec<LoadedContext>, FilesError> {
+    let mut loaded: Vec<LoadedContext> = Vec::new();
+    let mut budget = MAX_TOTAL_BYTES;
+    for relative in paths {
+        if loaded.iter().any(|l| &l.file.path == relative) {
+            continue;
+        }
+        let path = resolve(root, relative)?;
+        let metadata = std::fs::metadata(&path)?;
+        if !metadata.is_file() {
+            return Err(FilesError::NotAFile(relative.clone()));
+        }
+
+        let limit = MAX_FILE_BYTES.min(budget);
+        let mut bytes = Vec::new();
+        std::fs::File::open(&path)?
+            .take(limit as u64 + 1)
+            .read_to_end(&mut bytes)?;
+        let truncated = bytes.len() > limit;
+        bytes.truncate(limit);
+        if bytes.contains(&0) {
+            return Err(FilesError::NotText(relative.clone()));
+        }
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            // Cut mid-character; drop the partial one.
+            Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).expect("prefix is valid UTF-8")
+            }
+            Err(_) => return Err(FilesError::NotText(relative.clone())),
+        };
+
+        budget -= content.len();
+        loaded.push(LoadedContext {
+            file: ContextFile {
+                path: relative.clone(),
+                size: metadata.len(),
+                truncated,
+            },
+            content,
+        });
+    }
+    Ok(loaded)
+}
+
+/// The prompt for `content` with `context` ahead of it and the paths of
+/// `attachments` after it.
+pub fn prompt(context: &[LoadedContext], attachments: &[Attachment], content: &str) -> String {
+    let mut prompt = String::new();
+    for loaded in context {
+        let truncated = if loaded.file.truncated { " truncated=\"true\"" } else { "" };
+        prompt.push_str(&format!("<file path=\"{}\"{}>\n", loaded.file.path, truncated));
+        prompt.push_str(&loaded.content);
+        if !loaded.content.ends_with('\n') {
+            prompt.push('\n');
+        }
+        prompt.push_str("</file>\n\n");
+    }
+    prompt.push_str(content);
+    if !attachments.is_empty() {
+        prompt.push_str("\n\nAttached files:");
+        for attachment in attachments {
+            prompt.push_str(&format!("\n- {} ({})", attachment.path, attachment.mime_type));
+        }
+    }
+    prompt
+}
+
+/// The user and assistant turns of `messages`, to go ahead of a prompt when
+/// Claude has no session holding them (a conversation forked mid-way or
+/// compacted). Of the system messages only compaction summaries are kept.
+pub fn transcript(messages: &[Message]) -> String {
+    let mut transcript = String::new();
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System if message.compaction.is_some() => "summary",
+            MessageRole::System => continue,
+        };
+        transcript.push_str(&format!("<{role}>\n{}\n</{role}>\n", message.content.trim_end()));
+    }
+    if transcript.is_empty() {
+        return transcript;
+    }
+    format!("<conversation>\n{}</conversation>\n\n", transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_prompt() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("big.txt"), "é".repeat(MAX_FILE_BYTES)).unwrap();
+
+        let paths = ["src/lib.rs", "big.txt", "src/lib.rs"].map(String::from);
+        let context = load(root, &paths).unwrap();
+        assert_eq!(context.len(), 2);
+        assert!(!context[0].file.truncated);
+        assert_eq!(context[0].file.size, 12);
+        assert!(context[1].file.truncated);
+        assert_eq!(context[1].content.len(), MAX_FILE_BYTES);
+
+        let text = prompt(&context[..1], &[], "Explain this");
+        assert_eq!(text, "<file path=\"src/lib.rs\">\nfn main() {}\n</file>\n\nExplain this");
+        let screenshot = Attachment {
+            id: "a1".to_string(),
+            name: "shot.png".to_string(),
+            mime_type: "image/png".to_string(),
+            size: 4,
+            path: "/tmp/a1-shot.png".to_string(),
+        };
+        assert_eq!(
+            prompt(&[], &[screenshot], "Why is this broken?"),
+            "Why is this broken?\n\nAttached files:\n- /tmp/a1-shot.png (image/png)"
+        );
+
+        std::fs::write(root.join("blob.bin"), [0x7f, 0x00]).unwrap();
+        assert!(matches!(load(root, &["blob.bin".to_string()]), Err(FilesError::NotText(_))));
+        assert!(matches!(load(root, &["src".to_string()]), Err(FilesError::NotAFile(_))));
+        assert!(matches!(
+            load(root, &["../etc/passwd".to_string()]),
+            Err(FilesError::OutsideWorkspace(_))
+        ));
+    }
+
+    #[test]
+    fn test_transcript() {
+        let message = |role, content: &str| Message {
+            id: content.to_string(),
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            timestamp: chrono::Utc::now(),
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: None,
+            compaction: None,
+        };
+        let messages = [
+            message(MessageRole::User, "Add a test"),
+            message(MessageRole::System, "Switched model to opus."),
+            message(MessageRole::Assistant, "Done.\n"),
+        ];
+        assert_eq!(
+            transcript(&messages),
+            "<conversation>\n<user>\nAdd a test\n</user>\n<assistant>\nDone.\n</assistant>\n</conversation>\n\n"
+        );
+        assert_eq!(transcript(&messages[1..2]), "");
+
+        let summary = Message {
+            compaction: Some("c1".to_string()),
+            ..message(MessageRole::System, "Tests were added.")
+        };
+        assert_eq!(
+            transcript(&[summary]),
+            "<conversation>\n<summary>\nTests were added.\n</summary>\n</conversation>\n\n"
+        );
+    }
+}
@@ -3,61 +3,48 @@
 //! Unlike the PTY-based ProcessManager, this spawns `claude -p` per message
 //! and parses the structured JSON output for streaming to the UI.
 
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use serde_json::Value;
-use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStderr, ChildStdin, Command};
+use tokio::sync::{oneshot, Mutex, RwLock};
 use tracing;
 use uuid::Uuid;
 
 use mado_core::types::{
-    ConversationState, Message, MessageRole, SessionId, StreamEvent, TokenUsage, ToolCall,
-    ToolCallStatus,
+    ActivityKind, Attachment, AuditOutcome, BudgetPeriod, ChatErrorKind, Compaction, ConversationState, Message, MessageRole, PermissionMode,
+    RateLimitBackoff, SandboxSettings, Session, SessionId, SessionUsage, StreamEvent, TokenUsage, ToolCall, ToolCallStatus,
 };
 
+use crate::attachments::AttachmentError;
+use crate::claude_cli::{ClaudeCli, CliError};
+use crate::config::{ChatConfig, SharedSettings};
+use crate::context::LoadedContext;
+use crate::feed::SharedActivityFeed;
+use crate::files::FilesError;
+use crate::git_ops::GitError;
+use crate::ledger::{BudgetAlert, Ledger};
+use crate::replay::{ReplayChannel, ReplaySubscription, REPLAY_CAPACITY};
+use crate::read_only::Scratch;
+use crate::sandbox::{Policy, Proxy, Sandbox, SandboxError};
+use crate::slash::SlashCommand;
 use crate::state::DaemonState;
+use crate::subagent::Subagents;
+use crate::usage::TurnUsage;
 
-/// Find the Claude CLI binary on the system.
-fn find_claude_binary() -> Option<PathBuf> {
-    // Check PATH first via `which`.
-    if let Ok(output) = Command::new("which").arg("claude").output() {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !path.is_empty() {
-                let p = PathBuf::from(&path);
-                if p.exists() {
-                    return Some(p);
-                }
-            }
-        }
-    }
-
-    // Check common install locations.
-    let candidates = [
-        dirs::home_dir().map(|h| h.join(".claude").join("local").join("bin").join("claude")),
-        Some(PathBuf::from("/usr/local/bin/claude")),
-        Some(PathBuf::from("/opt/homebrew/bin/claude")),
-    ];
-
-    for candidate in candidates.into_iter().flatten() {
-        if candidate.exists() {
-            return Some(candidate);
-        }
-    }
-
-    None
-}
+/// Most of a Claude CLI's stderr kept for error reports.
+const MAX_STDERR_BYTES: usize = 16 * 1024;
 
-/// A running claude -p process.
-struct ActiveProcess {
-    child: Child,
-    session_id: SessionId,
-}
+/// Thinking budget requested with `--max-thinking-tokens` when a session has
+/// extended thinking on.
+const EXTENDED_THINKING_TOKENS: u32 = 31_999;
 
 /// Per-session conversation state.
 #[derive(Debug, Clone)]
@@ -68,14 +55,78 @@ pub struct ConversationSession {
     pub state: ConversationState,
     /// Claude CLI session ID for --resume.
     pub claude_session_id: Option<String>,
+    /// Claude CLI session of read-only turns, which run in a copy of the
+    /// workspace (see [`crate::read_only`]) and so can't resume
+    /// `claude_session_id`.
+    pub read_only_claude_session_id: Option<String>,
     /// Cumulative token usage.
     pub total_usage: TokenUsage,
     /// Cumulative cost in USD.
     pub total_cost_usd: f64,
+    /// Context in use as of the latest turn (see [`crate::usage`]).
+    pub context_tokens: u64,
     /// Working directory for this conversation.
     pub working_dir: Option<String>,
     /// Model to use.
     pub model: String,
+    /// Appended to Claude's system prompt on every turn.
+    pub system_prompt: Option<String>,
+    /// Environment variables for each turn's process (see [`crate::session_env`]).
+    pub env: HashMap<String, String>,
+    /// MCP server config files passed with `--mcp-config` on every turn.
+    pub mcp_config: Vec<String>,
+    /// Claude CLI flags of the session, after `default_flags`.
+    pub claude_flags: Vec<String>,
+    pub permission_mode: PermissionMode,
+    /// Keep turns from changing the workspace (see [`crate::read_only`]).
+    pub read_only: bool,
+    /// Run the CLI confined to the workspace (see [`crate::sandbox`]).
+    pub sandbox: SandboxSettings,
+    /// Set while waiting out a rate limit or an overloaded API.
+    pub backoff: Option<RateLimitBackoff>,
+    /// Request extended thinking on every turn.
+    pub extended_thinking: bool,
+    /// Pass `--fork-session` along with the next `--resume`, so a forked
+    /// conversation branches off the Claude session instead of adding to it.
+    pub fork_session: bool,
+    /// Replay `messages` ahead of the next prompt: a fork with no Claude
+    /// session to resume.
+    pub replay_history: bool,
+    /// When the conversation last changed state (message sent, response finished).
+    pub last_activity: Option<DateTime<Utc>>,
+    /// True from the start of a turn until its reader task finishes. Stays set
+    /// after a cancel until the killed process is reaped, so turns never overlap.
+    pub busy: bool,
+    /// Messages posted while busy, run in order once the current turn ends.
+    pub queue: VecDeque<QueuedMessage>,
+    /// Turns and commands run through the Claude CLI so far, which add to
+    /// its transcript (see [`crate::cli_sync`]).
+    pub cli_runs: u64,
+    /// Imported messages whose usage and cost are in the totals.
+    pub counted_imports: HashSet<String>,
+}
+
+/// A message waiting for the in-progress response to finish.
+#[derive(Debug, Clone)]
+pub struct QueuedMessage {
+    /// Id the user message will be stored under.
+    pub id: String,
+    pub content: String,
+    pub model: Option<String>,
+    /// Files read for the message, sent ahead of it.
+    pub context: Vec<LoadedContext>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// Per-message options for [`ConversationManager::send_message`].
+#[derive(Debug, Clone, Default)]
+pub struct MessageOptions {
+    /// Model for this message instead of the session's.
+    pub model: Option<String>,
+    /// Workspace files (relative to the working directory) to include as context.
+    pub context_files: Vec<String>,
+    /// Ids of uploaded attachments.
+    pub attachments: Vec<String>,
 }
 
 impl Default for ConversationSession {
@@ -84,28 +135,84 @@ impl Default for ConversationSession {
             messages: Vec::new(),
             state: ConversationState::Empty,
             claude_session_id: None,
+            read_only_claude_session_id: None,
             total_usage: TokenUsage::default(),
             total_cost_usd: 0.0,
+            context_tokens: 0,
             working_dir: None,
             model: "sonnet".to_string(),
+            system_prompt: None,
+            env: HashMap::new(),
+            mcp_config: Vec::new(),
+            claude_flags: Vec::new(),
+            permission_mode: PermissionMode::Ask,
+            read_only: false,
+            sandbox: SandboxSettings::default(),
+            backoff: None,
+            extended_thinking: false,
+            fork_session: false,
+            replay_history: false,
+            last_activity: None,
+            busy: false,
+            queue: VecDeque::new(),
+            cli_runs: 0,
+            counted_imports: HashSet::new(),
+        }
+    }
+}
+
+impl ConversationSession {
+    /// Drop all messages and the Claude session, starting over.
+    fn clear(&mut self) {
+        self.messages.clear();
+        self.claude_session_id = None;
+        self.fork_session = false;
+        self.replay_history = false;
+        self.context_tokens = 0;
+        self.state = ConversationState::Empty;
+    }
+
+    /// Add the usage and cost of imported `messages` to the totals, once
+    /// per message however often it is imported.
+    fn count_imported(&mut self, messages: &[Message]) {
+        for message in messages {
+            if (message.usage.is_none() && message.cost_usd.is_none())
+                || !self.counted_imports.insert(message.id.clone())
+            {
+                continue;
+            }
+            if let Some(usage) = &message.usage {
+                crate::usage::add(&mut self.total_usage, usage);
+            }
+            self.total_cost_usd += message.cost_usd.unwrap_or(0.0);
         }
     }
 }
 
 /// Manages conversations with Claude via `claude -p`.
+///
+/// Cloning is cheap and yields a handle to the same conversations, which lets
+/// a finished turn start the next queued one from its reader task.
+#[derive(Clone)]
 pub struct ConversationManager {
     /// Per-session conversation data (Arc-wrapped for sharing with tasks).
     sessions: Arc<RwLock<HashMap<String, ConversationSession>>>,
-    /// Active streaming processes (for cancellation).
-    active_processes: Arc<Mutex<HashMap<String, Child>>>,
-    /// Broadcast channels for streaming events per session.
-    event_senders: Arc<RwLock<HashMap<String, broadcast::Sender<StreamEvent>>>>,
+    /// Turns whose Claude CLI is running (for cancellation).
+    active_processes: Arc<Mutex<HashMap<String, ActiveResponse>>>,
+    /// Sequenced event channels per session, replayable via `Last-Event-ID`.
+    event_senders: Arc<RwLock<HashMap<String, Arc<ReplayChannel<StreamEvent>>>>>,
     /// Base directory for storing conversations.
     storage_dir: PathBuf,
     /// Shared daemon state for persisting claude_session_id.
     daemon_state: Arc<Mutex<DaemonState>>,
     /// Path to state file for persistence.
     state_path: PathBuf,
+    /// Daemon-wide feed that finished responses are announced on.
+    activity_feed: Option<SharedActivityFeed>,
+    /// Settings from config.json (API key for `claude -p`, budget limits).
+    settings: SharedSettings,
+    /// Daemon-wide usage ledger that finished turns are recorded in.
+    ledger: Arc<Ledger>,
 }
 
 impl ConversationManager {
@@ -117,9 +224,97 @@ impl ConversationManager {
             storage_dir,
             daemon_state,
             state_path,
+            activity_feed: None,
+            settings: SharedSettings::default(),
+            ledger: Arc::new(Ledger::default()),
+        }
+    }
+
+    /// Publish finished responses to the daemon-wide activity feed.
+    pub fn with_activity_feed(mut self, feed: SharedActivityFeed) -> Self {
+        self.activity_feed = Some(feed);
+        self
+    }
+
+    /// Pass the API key from these settings to `claude -p`.
+    pub fn with_settings(mut self, settings: SharedSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Record finished turns in this ledger instead of an in-memory one.
+    pub fn with_ledger(mut self, ledger: Arc<Ledger>) -> Self {
+        self.ledger = ledger;
+        self
+    }
+
+    /// Daemon-wide usage ledger.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// The budget limit spending is closest to breaking, if it has reached
+    /// its warning level.
+    pub fn budget_alert(&self) -> Option<BudgetAlert> {
+        let budget = self.settings.read().unwrap_or_else(|e| e.into_inner()).budget.clone();
+        crate::ledger::check(&budget, &self.ledger, Utc::now().date_naive())
+    }
+
+    /// Refuse to start a turn while a budget limit is exceeded, if the
+    /// config says to block.
+    fn check_budget(&self) -> Result<(), ConversationError> {
+        let block = self.settings.read().unwrap_or_else(|e| e.into_inner()).budget.block;
+        match self.budget_alert() {
+            Some(alert) if block && alert.exceeded => Err(ConversationError::BudgetExceeded(format!(
+                "spent ${:.2} of the {} limit of ${:.2}",
+                alert.spent_usd,
+                match alert.period {
+                    BudgetPeriod::Daily => "daily",
+                    BudgetPeriod::Monthly => "monthly",
+                },
+                alert.limit_usd
+            ))),
+            _ => Ok(()),
         }
     }
 
+    /// Record a turn's usage and cost in the ledger, sending a
+    /// `BudgetWarning` if that takes spending past a warning level or limit.
+    async fn record_spend(
+        &self,
+        session_id: &SessionId,
+        project: &str,
+        usage: &TokenUsage,
+        cost_usd: f64,
+    ) {
+        let before = self.budget_alert();
+        self.ledger.record(Utc::now().date_naive(), project, usage, cost_usd);
+        let Some(alert) = self.budget_alert() else {
+            return;
+        };
+        if before.is_some_and(|b| b.period == alert.period && b.exceeded == alert.exceeded) {
+            return;
+        }
+        tracing::warn!(
+            "Budget {:?} limit {}: ${:.2} of ${:.2}",
+            alert.period,
+            if alert.exceeded { "exceeded" } else { "nearly reached" },
+            alert.spent_usd,
+            alert.limit_usd
+        );
+        self.get_sender(session_id).await.send(StreamEvent::BudgetWarning {
+            period: alert.period,
+            spent_usd: alert.spent_usd,
+            limit_usd: alert.limit_usd,
+            exceeded: alert.exceeded,
+        });
+    }
+
+    /// Base directory for conversation storage (~/.mado/conversations/).
+    pub fn storage_dir(&self) -> &std::path::Path {
+        &self.storage_dir
+    }
+
     /// Initialize or get a conversation session.
     pub async fn get_or_create_session(
         &self,
@@ -143,48 +338,951 @@ impl ConversationManager {
         }
     }
 
-    /// Get a broadcast receiver for a session's events.
-    pub async fn subscribe(&self, session_id: &SessionId) -> broadcast::Receiver<StreamEvent> {
-        tracing::info!("SSE subscribe requested for session {}", session_id);
+    /// Subscribe to a session's events, replaying those after `last_event_id`.
+    pub async fn subscribe(
+        &self,
+        session_id: &SessionId,
+        last_event_id: Option<u64>,
+    ) -> ReplaySubscription<StreamEvent> {
+        tracing::info!(
+            "SSE subscribe requested for session {} (last event id: {:?})",
+            session_id,
+            last_event_id
+        );
         let mut senders = self.event_senders.write().await;
         if let Some(tx) = senders.get(session_id.as_str()) {
             tracing::info!("SSE subscribe: reusing existing channel for session {}", session_id);
-            tx.subscribe()
+            tx.subscribe(last_event_id)
         } else {
             tracing::info!("SSE subscribe: creating new channel for session {}", session_id);
-            let (tx, rx) = broadcast::channel(256);
+            let tx = Arc::new(ReplayChannel::new(REPLAY_CAPACITY));
+            let subscription = tx.subscribe(last_event_id);
             senders.insert(session_id.as_str().to_string(), tx);
-            rx
+            subscription
         }
     }
 
     /// Get a sender for a session's events.
-    async fn get_sender(&self, session_id: &SessionId) -> broadcast::Sender<StreamEvent> {
+    async fn get_sender(&self, session_id: &SessionId) -> Arc<ReplayChannel<StreamEvent>> {
         let mut senders = self.event_senders.write().await;
         if let Some(tx) = senders.get(session_id.as_str()) {
             tracing::info!("get_sender: reusing existing channel for session {} (receivers: {})", session_id, tx.receiver_count());
             tx.clone()
         } else {
             tracing::warn!("get_sender: creating NEW channel for session {} (no SSE subscriber yet!)", session_id);
-            let (tx, _) = broadcast::channel(256);
+            let tx = Arc::new(ReplayChannel::new(REPLAY_CAPACITY));
             senders.insert(session_id.as_str().to_string(), tx.clone());
             tx
         }
     }
 
     /// Send a message and start streaming the response.
+    ///
+    /// If a response is already in progress the message is queued behind it
+    /// (announced with a `QueuePosition` event) and runs once earlier turns
+    /// finish. Returns the id the user message is stored under either way.
+    /// Context files are read now, so a queued message sends them as they were
+    /// when it was posted.
+    ///
+    /// A slash command (see [`crate::slash`]) is run instead of being sent, and
+    /// the id of the system message with its result is returned.
     pub async fn send_message(
         &self,
         session_id: &SessionId,
         content: String,
-        model_override: Option<String>,
+        options: MessageOptions,
     ) -> Result<String, ConversationError> {
         tracing::info!("send_message called for session {}, content length: {}", session_id, content.len());
 
-        // Ensure we have a session.
+        if let Some(command) = crate::slash::parse(&content) {
+            return self.run_command(session_id, command).await;
+        }
+        self.check_budget()?;
+
+        let message = self
+            .prepare_message(session_id, Uuid::new_v4().to_string(), content, options)
+            .await?;
+        let message_id = message.id.clone();
+
+        let position = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                tracing::error!("Session {} not found in conversation manager!", session_id);
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                session.queue.push_back(message.clone());
+                Some(session.queue.len())
+            } else {
+                session.busy = true;
+                None
+            }
+        };
+
+        if let Some(position) = position {
+            tracing::info!("Queued message {} for session {} at position {}", message_id, session_id, position);
+            self.get_sender(session_id).await.send(StreamEvent::QueuePosition {
+                message_id: message_id.clone(),
+                position,
+            });
+            return Ok(message_id);
+        }
+
+        if let Err(e) = self.start_turn(session_id, message).await {
+            self.end_turn(session_id).await;
+            return Err(e);
+        }
+        Ok(message_id)
+    }
+
+    /// Replace the content of an earlier user message, drop everything after
+    /// it and run it again.
+    ///
+    /// The Claude session still holds the old turns and can't be cut short,
+    /// so the rerun starts a new one with the messages before the edited one
+    /// replayed ahead of it (like a fork at that point, see [`Self::fork`]).
+    /// The edited message keeps its id. Content is sent as is, even if it
+    /// looks like a slash command.
+    pub async fn edit_message(
+        &self,
+        session_id: &SessionId,
+        message_id: &str,
+        content: String,
+        options: MessageOptions,
+    ) -> Result<(), ConversationError> {
+        self.check_budget()?;
+        let message = self
+            .prepare_message(session_id, message_id.to_string(), content, options)
+            .await?;
+
+        {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            let index = session
+                .messages
+                .iter()
+                .position(|m| m.id == message_id)
+                .ok_or_else(|| ConversationError::MessageNotFound(message_id.to_string()))?;
+            if session.messages[index].role != MessageRole::User {
+                return Err(ConversationError::NotAUserMessage(message_id.to_string()));
+            }
+            session.messages.truncate(index);
+            session.claude_session_id = None;
+            session.context_tokens = 0;
+            session.fork_session = false;
+            session.replay_history = !session.messages.is_empty();
+            session.busy = true;
+        }
+        tracing::info!("Editing message {} of session {}", message_id, session_id);
+        self.persist_session(session_id, |s| s.claude_session_id = None).await;
+        self.get_sender(session_id).await.send(StreamEvent::Reset);
+
+        if let Err(e) = self.start_turn(session_id, message).await {
+            self.end_turn(session_id).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Run the last user message again after a failed response, with the
+    /// same context files and attachments. Returns the message's id.
+    ///
+    /// Like [`Self::edit_message`], the failed turn and anything after it
+    /// (e.g. the error) is dropped.
+    pub async fn retry_message(&self, session_id: &SessionId) -> Result<String, ConversationError> {
+        let (message_id, content, options) = {
+            let sessions = self.sessions.read().await;
+            let session = sessions.get(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.state != ConversationState::Error {
+                return Err(ConversationError::NothingToRetry);
+            }
+            let message = session
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == MessageRole::User)
+                .ok_or(ConversationError::NothingToRetry)?;
+            let options = MessageOptions {
+                model: None,
+                context_files: message.context_files.iter().map(|f| f.path.clone()).collect(),
+                attachments: message.attachments.iter().map(|a| a.id.clone()).collect(),
+            };
+            (message.id.clone(), message.content.clone(), options)
+        };
+        tracing::info!("Retrying message {} of session {}", message_id, session_id);
+        self.edit_message(session_id, &message_id, content, options).await?;
+        Ok(message_id)
+    }
+
+    /// Delete one message, e.g. an accidentally pasted secret.
+    ///
+    /// Claude's session still holds the message, so the conversation goes on
+    /// in a new one with the remaining messages replayed ahead of the next
+    /// prompt. The old session's transcript in `~/.claude` is left alone.
+    pub async fn delete_message(
+        &self,
+        session_id: &SessionId,
+        message_id: &str,
+    ) -> Result<(), ConversationError> {
+        {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            let index = session
+                .messages
+                .iter()
+                .position(|m| m.id == message_id)
+                .ok_or_else(|| ConversationError::MessageNotFound(message_id.to_string()))?;
+            session.messages.remove(index);
+            if session.messages.is_empty() {
+                session.clear();
+            } else {
+                session.claude_session_id = None;
+                session.context_tokens = 0;
+                session.fork_session = false;
+                session.replay_history = true;
+            }
+        }
+        tracing::info!("Deleted message {} of session {}", message_id, session_id);
+        self.persist_session(session_id, |s| s.claude_session_id = None).await;
+        self.get_sender(session_id).await.send(StreamEvent::Reset);
+        Ok(())
+    }
+
+    /// The conversation's messages, for a snapshot. Fails while a response
+    /// is in progress, since its messages would be missing.
+    pub async fn snapshot_messages(&self, session_id: &SessionId) -> Result<Vec<Message>, ConversationError> {
+        let sessions = self.sessions.read().await;
+        let session = sessions.get(session_id.as_str()).ok_or_else(|| {
+            ConversationError::SessionNotFound(session_id.as_str().to_string())
+        })?;
+        if session.busy {
+            return Err(ConversationError::Busy);
+        }
+        Ok(session.messages.clone())
+    }
+
+    /// The turn a review since `message_id` counts from: that of the user
+    /// message at or before it, or of the latest one if `None`. `None` if
+    /// there is no such user message.
+    pub async fn review_turn(
+        &self,
+        session_id: &SessionId,
+        message_id: Option<&str>,
+    ) -> Result<Option<crate::review::Turn>, ConversationError> {
+        let sessions = self.sessions.read().await;
+        let session = sessions.get(session_id.as_str()).ok_or_else(|| {
+            ConversationError::SessionNotFound(session_id.as_str().to_string())
+        })?;
+        let end = match message_id {
+            Some(id) => {
+                session
+                    .messages
+                    .iter()
+                    .position(|m| m.id == id)
+                    .ok_or_else(|| ConversationError::MessageNotFound(id.to_string()))?
+                    + 1
+            }
+            None => session.messages.len(),
+        };
+        Ok(session.messages[..end]
+            .iter()
+            .rfind(|m| m.role == MessageRole::User)
+            .map(|message| crate::review::Turn {
+                message_id: message.id.clone(),
+                sent_at: message.timestamp,
+                busy: session.busy,
+            }))
+    }
+
+    /// Put the conversation back to `messages`, from a snapshot. Claude's
+    /// session has heard what was said since, so the conversation goes on in
+    /// a new one with `messages` replayed ahead of the next prompt.
+    pub async fn restore_messages(
+        &self,
+        session_id: &SessionId,
+        messages: Vec<Message>,
+    ) -> Result<(), ConversationError> {
+        {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            session.clear();
+            if !messages.is_empty() {
+                session.messages = messages;
+                session.replay_history = true;
+                session.state = ConversationState::Idle;
+            }
+            session.last_activity = Some(Utc::now());
+        }
+        tracing::info!("Restored the conversation of session {} from a snapshot", session_id);
+        self.persist_session(session_id, |s| s.claude_session_id = None).await;
+        self.get_sender(session_id).await.send(StreamEvent::Reset);
+        Ok(())
+    }
+
+    /// Delete all messages and start a new Claude session, like `/clear`.
+    /// Returns how many messages there were.
+    pub async fn clear_messages(&self, session_id: &SessionId) -> Result<usize, ConversationError> {
+        let count = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            let count = session.messages.len();
+            session.clear();
+            count
+        };
+        tracing::info!("Cleared {} messages of session {}", count, session_id);
+        self.persist_session(session_id, |s| s.claude_session_id = None).await;
+        self.get_sender(session_id).await.send(StreamEvent::Reset);
+        Ok(count)
+    }
+
+    /// Replace the messages ahead of the latest `keep_recent` with a summary
+    /// written by the session's model (see [`crate::compact`]).
+    ///
+    /// Holds the session busy while the summary is written, so messages sent
+    /// meanwhile queue behind it and then start a new Claude session from
+    /// the compacted conversation.
+    pub async fn compact(
+        &self,
+        session_id: &SessionId,
+        keep_recent: Option<usize>,
+        instructions: Option<String>,
+    ) -> Result<Compaction, ConversationError> {
+        self.check_budget()?;
         let session = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            session.busy = true;
+            session.clone()
+        };
+
+        let result = self.summarize(session_id, &session, keep_recent, instructions).await;
+        self.end_turn(session_id).await;
+        let compaction = result?;
+        tracing::info!(
+            "Compacted {} messages of session {}, about {} tokens saved",
+            compaction.archived,
+            session_id,
+            compaction.tokens_saved()
+        );
+        Ok(compaction)
+    }
+
+    /// Write the summary for [`Self::compact`], archive the messages it
+    /// replaces and put it in their place.
+    async fn summarize(
+        &self,
+        session_id: &SessionId,
+        session: &ConversationSession,
+        keep_recent: Option<usize>,
+        instructions: Option<String>,
+    ) -> Result<Compaction, ConversationError> {
+        let keep_recent = keep_recent.unwrap_or(crate::compact::DEFAULT_KEEP_RECENT);
+        let at = crate::compact::split(&session.messages, keep_recent).ok_or(ConversationError::NothingToCompact)?;
+        let (older, kept) = session.messages.split_at(at);
+
+        let prompt = crate::compact::prompt(older, instructions.as_deref());
+        let (summary, usage, cost) = self.run_once(session_id, session, &prompt).await?;
+
+        let id = Uuid::new_v4().to_string();
+        let message = Message {
+            id: Uuid::new_v4().to_string(),
+            role: MessageRole::System,
+            content: summary,
+            tool_calls: Vec::new(),
+            timestamp: Utc::now(),
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: None,
+            compaction: Some(id.clone()),
+        };
+        let mut messages = vec![message.clone()];
+        messages.extend_from_slice(kept);
+        let compaction = Compaction {
+            id,
+            created_at: Utc::now(),
+            message,
+            archived: older.len(),
+            tokens_before: crate::compact::estimate_tokens(&session.messages),
+            tokens_after: crate::compact::estimate_tokens(&messages),
+            cost_usd: cost,
+        };
+
+        let archive = crate::compact::Archive {
+            compaction: compaction.clone(),
+            messages: older.to_vec(),
+        };
+        let dir = self.compactions_dir(session_id);
+        tokio::task::spawn_blocking(move || crate::compact::save(&dir, &archive))
+            .await
+            .map_err(|e| ConversationError::IoError(std::io::Error::other(e)))??;
+
+        {
+            let mut sessions = self.sessions.write().await;
+            let current = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            // Turns were held off, but messages synced from the CLI's
+            // transcript may have come in (see `append_external`).
+            if current.messages.last().map(|m| &m.id) != session.messages.last().map(|m| &m.id) {
+                return Err(ConversationError::Diverged);
+            }
+            current.messages = messages;
+            current.claude_session_id = None;
+            current.fork_session = false;
+            current.replay_history = true;
+            current.context_tokens = compaction.tokens_after;
+            crate::usage::add(&mut current.total_usage, &usage);
+            current.total_cost_usd += cost;
+            current.last_activity = Some(Utc::now());
+        }
+        self.persist_session(session_id, |s| s.claude_session_id = None).await;
+        self.get_sender(session_id).await.send(StreamEvent::Reset);
+        Ok(compaction)
+    }
+
+    /// Ask the session's model a one-off question outside its conversation.
+    /// The cost counts towards the session's. Returns the reply.
+    pub async fn ask(&self, session_id: &SessionId, prompt: &str) -> Result<String, ConversationError> {
+        self.check_budget()?;
+        let session = self
+            .sessions
+            .read()
+            .await
+            .get(session_id.as_str())
+            .cloned()
+            .ok_or_else(|| ConversationError::SessionNotFound(session_id.as_str().to_string()))?;
+        let (reply, usage, cost) = self.run_once(session_id, &session, prompt).await?;
+        self.update_session(session_id, |s| {
+            crate::usage::add(&mut s.total_usage, &usage);
+            s.total_cost_usd += cost;
+        })
+        .await;
+        Ok(reply)
+    }
+
+    /// Run `claude -p` for `prompt` in the session's directory, without
+    /// resuming its Claude session or letting it use tools, and record the
+    /// spend. Returns the reply with its usage and cost.
+    async fn run_once(
+        &self,
+        session_id: &SessionId,
+        session: &ConversationSession,
+        prompt: &str,
+    ) -> Result<(String, TokenUsage, f64), ConversationError> {
+        // The prompt goes over stdin; it can be longer than an argument may be.
+        let (mut cmd, _proxy) = self.claude_command(session, &[])?;
+        cmd.arg("-p");
+        cmd.arg("--output-format").arg("json");
+        cmd.arg("--model").arg(&session.model);
+        cmd.arg("--max-turns").arg("1");
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+        let mut child = cmd.spawn().map_err(|e| ConversationError::SpawnFailed(e.to_string()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(prompt.as_bytes())
+                .await
+                .map_err(|e| ConversationError::SpawnFailed(format!("Failed to send prompt: {}", e)))?;
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| ConversationError::SpawnFailed(e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(ConversationError::NoReply(stderr.trim().to_string()));
+        }
+
+        let result: Value = serde_json::from_slice(&output.stdout).unwrap_or_default();
+        let reply = result["result"].as_str().map(str::trim).unwrap_or_default();
+        if reply.is_empty() {
+            return Err(ConversationError::NoReply("The reply was empty".to_string()));
+        }
+        let usage = result.get("usage").map(crate::usage::parse).unwrap_or_default();
+        let cost = result["total_cost_usd"].as_f64().or(result["cost_usd"].as_f64()).unwrap_or(0.0);
+        let project = session.working_dir.clone().unwrap_or_default();
+        self.record_spend(session_id, &project, &usage, cost).await;
+        Ok((reply.to_string(), usage, cost))
+    }
+
+    /// Read a message's context files and look up its attachments.
+    async fn prepare_message(
+        &self,
+        session_id: &SessionId,
+        id: String,
+        content: String,
+        options: MessageOptions,
+    ) -> Result<QueuedMessage, ConversationError> {
+        let context = self.load_context(session_id, options.context_files).await?;
+        let attachments_dir = self.attachments_dir(session_id);
+        let attachments = tokio::task::spawn_blocking(move || {
+            options
+                .attachments
+                .iter()
+                .map(|id| crate::attachments::find(&attachments_dir, id))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .map_err(|e| ConversationError::IoError(std::io::Error::other(e)))??;
+        Ok(QueuedMessage {
+            id,
+            content,
+            model: options.model,
+            context,
+            attachments,
+        })
+    }
+
+    /// Read a message's context files from the session's working directory.
+    async fn load_context(
+        &self,
+        session_id: &SessionId,
+        paths: Vec<String>,
+    ) -> Result<Vec<LoadedContext>, ConversationError> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        if paths.len() > crate::context::MAX_CONTEXT_FILES {
+            return Err(ConversationError::TooManyContextFiles(paths.len()));
+        }
+        let working_dir = {
             let sessions = self.sessions.read().await;
-            sessions.get(session_id.as_str()).cloned()
+            let session = sessions.get(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            session.working_dir.clone().map(PathBuf::from).or_else(dirs::home_dir)
+        };
+        let Some(working_dir) = working_dir else {
+            return Err(ConversationError::ContextFile(FilesError::NotFound(
+                "working directory".to_string(),
+            )));
+        };
+        let context = tokio::task::spawn_blocking(move || crate::context::load(&working_dir, &paths))
+            .await
+            .map_err(|e| ConversationError::IoError(std::io::Error::other(e)))??;
+        Ok(context)
+    }
+
+    /// Run a slash command and post its result as a system message.
+    ///
+    /// Commands that change the Claude conversation hold the session busy
+    /// while they run, so messages sent meanwhile queue behind them.
+    async fn run_command(
+        &self,
+        session_id: &SessionId,
+        command: SlashCommand,
+    ) -> Result<String, ConversationError> {
+        tracing::info!("Running {:?} for session {}", command, session_id);
+        let session = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            if command.needs_idle() {
+                if session.busy {
+                    return Err(ConversationError::Busy);
+                }
+                session.busy = true;
+                session.cli_runs += 1;
+            }
+            session.clone()
+        };
+
+        let result = match self.execute_command(session_id, &session, &command).await {
+            Ok(text) => Ok(self.post_system_message(session_id, text).await),
+            Err(e) => Err(e),
+        };
+        if command.needs_idle() {
+            self.end_turn(session_id).await;
+        }
+        result
+    }
+
+    /// Carry out a slash command, returning the text to show for it.
+    async fn execute_command(
+        &self,
+        session_id: &SessionId,
+        session: &ConversationSession,
+        command: &SlashCommand,
+    ) -> Result<String, ConversationError> {
+        match command {
+            SlashCommand::Model(None) => Ok(format!("Model: {}.", session.model)),
+            SlashCommand::Model(Some(model)) => {
+                self.update_session(session_id, |s| s.model = model.clone()).await;
+                self.persist_session(session_id, |s| s.model = model.clone()).await;
+                Ok(format!("Switched model to {}.", model))
+            }
+            SlashCommand::Cost => Ok(format!(
+                "{} input and {} output tokens, ${:.4} so far.",
+                session.total_usage.input_tokens,
+                session.total_usage.output_tokens,
+                session.total_cost_usd
+            )),
+            SlashCommand::Clear => {
+                self.update_session(session_id, ConversationSession::clear).await;
+                self.persist_session(session_id, |s| s.claude_session_id = None).await;
+                self.get_sender(session_id).await.send(StreamEvent::Reset);
+                Ok("Started a new conversation.".to_string())
+            }
+            SlashCommand::Resume(None) => Ok("Usage: /resume <Claude session id>".to_string()),
+            SlashCommand::Resume(Some(claude_session_id)) => {
+                let working_dir = session.working_dir.clone().map(PathBuf::from);
+                let id = claude_session_id.clone();
+                let imported = tokio::task::spawn_blocking(move || {
+                    let working_dir = working_dir.or_else(dirs::home_dir).unwrap_or_default();
+                    crate::claude_history::import_session_by_id(&working_dir, &id, None, 0)
+                })
+                .await
+                .map_err(|e| ConversationError::IoError(std::io::Error::other(e)))?;
+                let messages = match imported {
+                    Ok(messages) => messages,
+                    Err(e) => return Ok(format!("Couldn't resume {}: {}", claude_session_id, e)),
+                };
+
+                let count = messages.len();
+                self.update_session(session_id, |s| {
+                    s.count_imported(&messages);
+                    s.messages = messages;
+                    s.claude_session_id = Some(claude_session_id.clone());
+                    s.fork_session = false;
+                    s.replay_history = false;
+                    s.state = ConversationState::Idle;
+                })
+                .await;
+                self.persist_session(session_id, |s| {
+                    s.claude_session_id = Some(claude_session_id.clone())
+                })
+                .await;
+                self.get_sender(session_id).await.send(StreamEvent::Reset);
+                Ok(format!("Resumed Claude session {} ({} messages).", claude_session_id, count))
+            }
+            SlashCommand::Compact(instructions) => {
+                let Some(claude_session_id) = session.claude_session_id.clone() else {
+                    return Ok("Nothing to compact yet.".to_string());
+                };
+                let prompt = match instructions {
+                    Some(instructions) => format!("/compact {}", instructions),
+                    None => "/compact".to_string(),
+                };
+                let (mut cmd, _proxy) = self.claude_command(session, &[])?;
+                cmd.arg("-p").arg(prompt);
+                cmd.arg("--output-format").arg("json");
+                cmd.arg("--resume").arg(&claude_session_id);
+                if session.fork_session {
+                    cmd.arg("--fork-session");
+                }
+                let output = cmd
+                    .output()
+                    .await
+                    .map_err(|e| ConversationError::SpawnFailed(e.to_string()))?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    return Ok(format!("Compaction failed: {}", stderr.trim()));
+                }
+
+                let result: Value = serde_json::from_slice(&output.stdout).unwrap_or_default();
+                let new_id = result["session_id"].as_str().map(String::from);
+                let cost = result["total_cost_usd"].as_f64().or(result["cost_usd"].as_f64());
+                if let Some(cost) = cost {
+                    let usage = result.get("usage").map(crate::usage::parse).unwrap_or_default();
+                    let project = session.working_dir.clone().unwrap_or_default();
+                    self.record_spend(session_id, &project, &usage, cost).await;
+                }
+                self.update_session(session_id, |s| {
+                    if let Some(id) = &new_id {
+                        s.claude_session_id = Some(id.clone());
+                        s.fork_session = false;
+                    }
+                    s.total_cost_usd += cost.unwrap_or(0.0);
+                })
+                .await;
+                if let Some(id) = new_id {
+                    self.persist_session(session_id, |s| s.claude_session_id = Some(id)).await;
+                }
+                Ok("Compacted the conversation.".to_string())
+            }
+        }
+    }
+
+    /// Store a system message and send it to subscribers. Returns its id.
+    async fn post_system_message(&self, session_id: &SessionId, content: String) -> String {
+        let message = system_message(content);
+        let id = message.id.clone();
+        self.update_session(session_id, |s| {
+            s.messages.push(message.clone());
+            s.last_activity = Some(Utc::now());
+        })
+        .await;
+        self.get_sender(session_id).await.send(StreamEvent::MessageComplete {
+            message: Box::new(message),
+        });
+        id
+    }
+
+    /// Change a conversation session in memory.
+    async fn update_session(&self, session_id: &SessionId, update: impl FnOnce(&mut ConversationSession)) {
+        let mut sessions = self.sessions.write().await;
+        if let Some(session) = sessions.get_mut(session_id.as_str()) {
+            update(session);
+        }
+    }
+
+    /// Change a session in the daemon state and save it to disk.
+    async fn persist_session(&self, session_id: &SessionId, update: impl FnOnce(&mut Session)) {
+        let mut daemon_state = self.daemon_state.lock().await;
+        if let Some(session) = daemon_state.sessions.get_mut(session_id.as_str()) {
+            update(session);
+            session.updated_at = Utc::now();
+            if let Err(e) = daemon_state.save(&self.state_path) {
+                tracing::error!("Failed to persist daemon state: {}", e);
+            }
+        }
+    }
+
+    /// `claude` with the environment and working directory of `session`.
+    /// Sandboxed, it can read its MCP configs and `readable` as well.
+    fn claude_command(
+        &self,
+        session: &ConversationSession,
+        readable: &[PathBuf],
+    ) -> Result<(Command, Option<Proxy>), ConversationError> {
+        let (cli, api_key, provider_env, cpu_seconds, sandbox) = {
+            let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+            (
+                ClaudeCli::new(settings.claude_path.clone(), &settings.default_flags, &session.claude_flags),
+                settings.api_key.clone(),
+                settings.provider_env.clone(),
+                settings.limits.cpu_seconds,
+                Policy::for_session(&session.sandbox, session.working_dir.as_deref(), &settings.sandbox).map(|policy| {
+                    let mcp_config = session.mcp_config.iter().map(PathBuf::from);
+                    policy.with_readable(mcp_config.chain(readable.iter().cloned()))
+                }),
+            )
+        };
+        let claude_path = cli.binary()?.ok_or_else(|| {
+            tracing::error!("Claude CLI not found!");
+            ConversationError::ClaudeNotFound
+        })?;
+        tracing::info!("Found Claude CLI at: {:?}", claude_path);
+        let sandbox = sandbox.as_ref().map(Sandbox::start).transpose()?;
+        let mut cmd = match &sandbox {
+            Some(sandbox) => sandbox.command(&claude_path),
+            None => Command::new(&claude_path),
+        };
+        cmd.args(&cli.flags);
+        // Its own process group, so stopping it also stops what it spawned.
+        cmd.process_group(0);
+
+        // CRITICAL: Remove CLAUDECODE env var to prevent "nested sessions" error.
+        // This allows mado-daemon to spawn Claude CLI even when running in a
+        // terminal that's inside another Claude Code session.
+        cmd.env_remove("CLAUDECODE");
+
+        if let Some(key) = api_key {
+            cmd.env("ANTHROPIC_API_KEY", key);
+        }
+        if let Some(seconds) = cpu_seconds {
+            crate::limits::limit_cpu(cmd.as_std_mut(), seconds);
+        }
+        cmd.envs(provider_env);
+        cmd.envs(crate::session_env::resolve(&session.env)?);
+        let proxy = sandbox.and_then(|sandbox| {
+            cmd.envs(sandbox.env);
+            sandbox.proxy
+        });
+
+        // Set working directory.
+        if let Some(ref dir) = session.working_dir {
+            cmd.current_dir(dir);
+        }
+        Ok((cmd, proxy))
+    }
+
+    /// Directory a session's uploaded attachments are stored in.
+    pub fn attachments_dir(&self, session_id: &SessionId) -> PathBuf {
+        crate::attachments::dir(&self.storage_dir, session_id.as_str())
+    }
+
+    /// Directory a session's snapshots are stored in.
+    pub fn snapshots_dir(&self, session_id: &SessionId) -> PathBuf {
+        crate::snapshot::dir(&self.storage_dir, session_id.as_str())
+    }
+
+    /// Directory a session's finished command runs are stored in.
+    pub fn runs_dir(&self, session_id: &SessionId) -> PathBuf {
+        crate::runs::dir(&self.storage_dir, session_id.as_str())
+    }
+
+    /// Directory the messages replaced by a session's compactions are
+    /// archived in.
+    pub fn compactions_dir(&self, session_id: &SessionId) -> PathBuf {
+        crate::compact::dir(&self.storage_dir, session_id.as_str())
+    }
+
+    /// A session's audit log (see [`crate::audit`]).
+    pub fn audit_path(&self, session_id: &SessionId) -> PathBuf {
+        crate::audit::path(&self.storage_dir, session_id.as_str())
+    }
+
+    /// File a session's pending memory suggestions are kept in.
+    pub fn memory_suggestions_path(&self, session_id: &SessionId) -> PathBuf {
+        crate::memory::suggestions_path(&self.storage_dir, session_id.as_str())
+    }
+
+    /// Directory a session's finished comparisons are stored in.
+    pub fn comparisons_dir(&self, session_id: &SessionId) -> PathBuf {
+        crate::compare::dir(&self.storage_dir, session_id.as_str())
+    }
+
+    /// Remove a queued message before it runs.
+    pub async fn cancel_queued(
+        &self,
+        session_id: &SessionId,
+        message_id: &str,
+    ) -> Result<(), ConversationError> {
+        let (index, behind) = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str()).ok_or_else(|| {
+                ConversationError::SessionNotFound(session_id.as_str().to_string())
+            })?;
+            let index = session
+                .queue
+                .iter()
+                .position(|m| m.id == message_id)
+                .ok_or_else(|| ConversationError::QueuedMessageNotFound(message_id.to_string()))?;
+            session.queue.remove(index);
+            let behind: Vec<String> = session.queue.iter().skip(index).map(|m| m.id.clone()).collect();
+            (index, behind)
+        };
+        tracing::info!("Removed queued message {} from session {}", message_id, session_id);
+
+        // Everything behind the removed message moves up one place.
+        self.announce_positions(session_id, behind, index + 1).await;
+        Ok(())
+    }
+
+    /// Emit `QueuePosition` for `ids`, the first being at `first_position`.
+    async fn announce_positions(&self, session_id: &SessionId, ids: Vec<String>, first_position: usize) {
+        let tx = self.get_sender(session_id).await;
+        for (offset, message_id) in ids.into_iter().enumerate() {
+            tx.send(StreamEvent::QueuePosition {
+                message_id,
+                position: first_position + offset,
+            });
+        }
+    }
+
+    /// Finish a turn: start the next queued message, or go idle.
+    ///
+    /// Boxed because starting a turn spawns the reader task that ends it.
+    fn end_turn<'a>(&'a self, session_id: &'a SessionId) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let Some(mut next) = self.next_queued(session_id).await else {
+                return;
+            };
+
+            // Spawned rather than awaited: this runs from a turn's reader task.
+            let manager = self.clone();
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                while let Err(e) = manager.start_turn(&session_id, next).await {
+                    tracing::error!("Failed to start queued message for session {}: {}", session_id, e);
+                    manager.get_sender(&session_id).await.send(StreamEvent::Error {
+                        message: e.to_string(),
+                        kind: ChatErrorKind::Other,
+                        detail: None,
+                    });
+                    match manager.next_queued(&session_id).await {
+                        Some(message) => next = message,
+                        None => break,
+                    }
+                }
+            });
+        })
+    }
+
+    /// Take the next queued message, keeping the session busy, or mark it
+    /// idle if the queue is empty.
+    async fn next_queued(&self, session_id: &SessionId) -> Option<QueuedMessage> {
+        let next = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id.as_str())?;
+            let next = session.queue.pop_front();
+            session.busy = next.is_some();
+            next.map(|next| {
+                let remaining: Vec<String> = session.queue.iter().map(|m| m.id.clone()).collect();
+                (next, remaining)
+            })
+        };
+
+        let Some((next, remaining)) = next else {
+            self.get_sender(session_id).await.send(StreamEvent::Idle);
+            return None;
+        };
+
+        // Position 0 marks the message as started; the rest move up one place.
+        let mut ids = vec![next.id.clone()];
+        ids.extend(remaining);
+        self.announce_positions(session_id, ids, 0).await;
+        Some(next)
+    }
+
+    /// Store the user message and spawn `claude -p` for it.
+    ///
+    /// The caller must have marked the session busy; the reader task calls
+    /// `end_turn` when the process exits.
+    async fn start_turn(
+        &self,
+        session_id: &SessionId,
+        message: QueuedMessage,
+    ) -> Result<(), ConversationError> {
+        let QueuedMessage {
+            id: user_msg_id,
+            content,
+            model: model_override,
+            context,
+            attachments,
+        } = message;
+
+        // Ensure we have a session.
+        let session = {
+            let mut sessions = self.sessions.write().await;
+            sessions.get_mut(session_id.as_str()).map(|session| {
+                session.cli_runs += 1;
+                session.clone()
+            })
         };
 
         let session = match session {
@@ -199,18 +1297,40 @@ impl ConversationManager {
         };
 
         let model = model_override.unwrap_or(session.model.clone());
+        let context_window = crate::usage::context_window(&model);
+        let project = session.working_dir.clone().unwrap_or_default();
 
         // Create user message.
         let user_msg = Message {
-            id: Uuid::new_v4().to_string(),
+            id: user_msg_id,
             role: MessageRole::User,
             content: content.clone(),
             tool_calls: Vec::new(),
             timestamp: Utc::now(),
             usage: None,
             cost_usd: None,
+            context_files: context.iter().map(|loaded| loaded.file.clone()).collect(),
+            attachments: attachments.clone(),
+            thinking: None,
+            source: None,
+            compaction: None,
         };
-        let user_msg_id = user_msg.id.clone();
+
+        // A read-only session's turn runs in a copy of the workspace, which
+        // is thrown away after it. Others are snapshotted so their changes
+        // can be reviewed and reverted; plan mode can't edit files.
+        let scratch = if session.read_only {
+            Some(read_only_copy(session.working_dir.as_deref(), session_id).await?)
+        } else {
+            None
+        };
+        let tree = match session.permission_mode {
+            _ if session.read_only => None,
+            PermissionMode::Plan => None,
+            _ => snapshot_turn(session.working_dir.as_deref(), session_id, &user_msg.id, true).await,
+        };
+
+        let snapshot_after = tree.is_some();
 
         // Store user message and update state.
         {
@@ -218,40 +1338,80 @@ impl ConversationManager {
             if let Some(s) = sessions.get_mut(session_id.as_str()) {
                 s.messages.push(user_msg.clone());
                 s.state = ConversationState::Streaming;
+                s.last_activity = Some(Utc::now());
             }
         }
 
-        // Find Claude CLI.
-        let claude_path = find_claude_binary().ok_or_else(|| {
-            tracing::error!("Claude CLI not found!");
-            ConversationError::ClaudeNotFound
-        })?;
-        tracing::info!("Found Claude CLI at: {:?}", claude_path);
+        // Read-only turns have a Claude session of their own, in the copy's
+        // directory; the first is given the conversation so far.
+        let (resume, fork_session, replay_history) = if session.read_only {
+            let resume = session.read_only_claude_session_id.clone();
+            let replay = resume.is_none() && !session.messages.is_empty();
+            (resume, false, replay)
+        } else {
+            (session.claude_session_id.clone(), session.fork_session, session.replay_history)
+        };
 
         // Build command.
-        let mut cmd = Command::new(&claude_path);
-        cmd.arg("-p").arg(&content);
+        let mut prompt = crate::context::prompt(&context, &attachments, &content);
+        if replay_history {
+            prompt.insert_str(0, &crate::context::transcript(&session.messages));
+        }
+        let attachments_dir = (!attachments.is_empty()).then(|| self.attachments_dir(session_id));
+        let (mut cmd, proxy) = match &scratch {
+            Some(scratch) => {
+                let copy = ConversationSession {
+                    working_dir: Some(scratch.working_dir().to_string_lossy().to_string()),
+                    ..session.clone()
+                };
+                self.claude_command(&copy, attachments_dir.as_slice())?
+            }
+            None => self.claude_command(&session, attachments_dir.as_slice())?,
+        };
+        // The prompt goes over stdin, which stays open so the CLI's prompts
+        // can be answered mid-response (see `send_response_input`).
+        cmd.arg("-p");
+        cmd.arg("--input-format").arg("stream-json");
         cmd.arg("--output-format").arg("stream-json");
         cmd.arg("--verbose");
         cmd.arg("--model").arg(&model);
 
-        // CRITICAL: Remove CLAUDECODE env var to prevent "nested sessions" error.
-        // This allows mado-daemon to spawn Claude CLI even when running in a
-        // terminal that's inside another Claude Code session.
-        cmd.env_remove("CLAUDECODE");
+        let project_config = crate::project_config::for_dir(session.working_dir.as_deref());
+        if let Some(system_prompt) = session.system_prompt.as_ref().or(project_config.system_prompt.as_ref()) {
+            cmd.arg("--append-system-prompt").arg(system_prompt);
+        }
+        for config in crate::project_config::mcp_config(&session.mcp_config, &project_config) {
+            cmd.arg("--mcp-config").arg(config);
+        }
+        let permission_mode = crate::read_only::permission_mode(session.permission_mode, session.read_only);
+        if permission_mode != PermissionMode::Ask {
+            cmd.arg("--permission-mode").arg(permission_mode.cli_value());
+        }
+        if session.read_only {
+            cmd.args(crate::read_only::cli_args());
+        }
+        if session.extended_thinking {
+            cmd.arg("--max-thinking-tokens").arg(EXTENDED_THINKING_TOKENS.to_string());
+        }
 
         // Add --resume if we have a Claude session ID.
-        if let Some(ref claude_sid) = session.claude_session_id {
+        if let Some(ref claude_sid) = resume {
             cmd.arg("--resume").arg(claude_sid);
+            if fork_session {
+                cmd.arg("--fork-session");
+            }
         }
 
-        // Set working directory.
-        if let Some(ref dir) = session.working_dir {
-            cmd.current_dir(dir);
+        // Let Claude read attachments, which live outside the working directory.
+        if let Some(dir) = &attachments_dir {
+            cmd.arg("--add-dir").arg(dir);
         }
 
+        cmd.stdin(Stdio::piped());
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+        let time_limits = self.settings.read().unwrap_or_else(|e| e.into_inner()).chat.clone();
 
         tracing::info!("Spawning Claude CLI: {:?}", cmd);
 
@@ -264,14 +1424,31 @@ impl ConversationManager {
             })?;
         tracing::info!("Spawned Claude CLI process with PID: {:?}", child.id());
 
-        let stdout = child.stdout.take().ok_or_else(|| {
-            ConversationError::SpawnFailed("Failed to capture stdout".to_string())
-        })?;
-
-        // Store child for cancellation.
+        let (Some(pid), Some(mut stdin), Some(stdout), Some(stderr)) =
+            (child.id(), child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(ConversationError::SpawnFailed("Failed to capture output".to_string()));
+        };
+        stdin
+            .write_all(input_line(&prompt).as_bytes())
+            .await
+            .map_err(|e| ConversationError::SpawnFailed(format!("Failed to send prompt: {}", e)))?;
+        let stdin = Arc::new(Mutex::new(Some(stdin)));
+        let stderr = tokio::spawn(read_stderr(stderr));
+
+        // Register the turn for cancellation and input; the reader task keeps
+        // the child.
+        let (cancel, mut cancelled) = oneshot::channel();
         {
             let mut active = self.active_processes.lock().await;
-            active.insert(session_id.as_str().to_string(), child);
+            active.insert(
+                session_id.as_str().to_string(),
+                ActiveResponse {
+                    pid,
+                    cancel,
+                    stdin: stdin.clone(),
+                },
+            );
         }
 
         // Get broadcast sender.
@@ -281,19 +1458,54 @@ impl ConversationManager {
         let active_ref = self.active_processes.clone();
         let daemon_state_ref = self.daemon_state.clone();
         let state_path_ref = self.state_path.clone();
+        let manager = self.clone();
+        let turn_message_id = user_msg.id.clone();
 
         // Spawn reader task.
-        tokio::task::spawn_blocking(move || {
-            let reader = BufReader::new(stdout);
+        tokio::spawn(async move {
+            // A sandboxed CLI connects through its proxy until the turn ends.
+            let _proxy = proxy;
+            let mut scratch = scratch;
+            let mut lines = BufReader::new(stdout).lines();
+            let mut was_cancelled = false;
+            let mut timed_out: Option<String> = None;
+            // A tool that edits files, called in a read-only session.
+            let mut blocked: Option<String> = None;
+            let started = tokio::time::Instant::now();
             let mut accumulated_text = String::new();
+            let mut accumulated_thinking = String::new();
             let mut tool_calls: Vec<ToolCall> = Vec::new();
             let mut final_usage: Option<TokenUsage> = None;
             let mut final_cost: Option<f64> = None;
             let mut final_claude_sid: Option<String> = None;
+            let mut result_error: Option<String> = None;
+            let mut turn_usage = TurnUsage::default();
+            let mut subagents = Subagents::default();
+            let mut audit = crate::audit::TurnAudit::new(manager.audit_path(&session_id_clone));
+            let mut last_usage_update: Option<Instant> = None;
+            let usage_update = |usage: TokenUsage, context_tokens: u64| StreamEvent::UsageUpdate {
+                usage,
+                context_tokens,
+                context_window,
+                context_utilization: crate::usage::utilization(context_tokens, context_window),
+            };
 
-            for line in reader.lines() {
+            loop {
+                // Cancelled when the sender is used or dropped.
+                let line = tokio::select! {
+                    line = lines.next_line() => line,
+                    _ = &mut cancelled => {
+                        was_cancelled = true;
+                        break;
+                    }
+                    reason = response_expiry(started, tokio::time::Instant::now(), &time_limits) => {
+                        timed_out = Some(reason);
+                        break;
+                    }
+                };
                 let line = match line {
-                    Ok(l) => l,
+                    Ok(Some(l)) => l,
+                    Ok(None) => break,
                     Err(e) => {
                         tracing::error!("Failed to read line from Claude CLI: {}", e);
                         break;
@@ -315,14 +1527,45 @@ impl ConversationManager {
 
                 let event_type = event["type"].as_str().unwrap_or("");
                 tracing::info!("Claude event: type={}", event_type);
+                audit.record(&event);
+
+                if let Some((prompt, options)) = input_request(&event) {
+                    tracing::info!("Claude CLI is asking for input in session {}: {}", session_id_clone, prompt);
+                    let _ = tx.send(StreamEvent::InputRequested { prompt, options });
+                    continue;
+                }
+                if let Some((kind, retry_after)) = api_retry(&event) {
+                    tracing::warn!("Claude CLI is retrying after {:?} for session {}", kind, session_id_clone);
+                    let until = Utc::now() + Duration::from_secs(retry_after.unwrap_or(0));
+                    if let Some(s) = sessions_ref.write().await.get_mut(session_id_clone.as_str()) {
+                        let failures = s.backoff.as_ref().map_or(0, |backoff| backoff.failures);
+                        s.backoff = Some(RateLimitBackoff {
+                            kind,
+                            until,
+                            failures,
+                            retrying: true,
+                        });
+                    }
+                    let _ = tx.send(StreamEvent::RateLimited {
+                        kind,
+                        retry_after,
+                        retrying: true,
+                    });
+                    continue;
+                }
 
                 match event_type {
+                    "assistant" if crate::subagent::parent_id(&event).is_some() => {
+                        // A subagent's step, reported below; not part of the reply.
+                    }
                     "assistant" => {
                         // Assistant message content - extract text from message.content
-                        if let Some(message) = event.get("message") {
-                            if let Some(content_arr) = message.get("content").and_then(|c| c.as_array()) {
-                                for block in content_arr {
-                                    if block.get("type").and_then(|t| t.as_str()) == Some("text") {
+                        if let Some(message) = event.get("message")
+                            && let Some(content_arr) = message.get("content").and_then(|c| c.as_array())
+                        {
+                            for block in content_arr {
+                                match block.get("type").and_then(|t| t.as_str()) {
+                                    Some("text") => {
                                         if let Some(text) = block.get("text").and_then(|t| t.as_str()) {
                                             accumulated_text.push_str(text);
                                             let _ = tx.send(StreamEvent::TextDelta {
@@ -330,83 +1573,166 @@ impl ConversationManager {
                                             });
                                         }
                                     }
+                                    Some("thinking") => {
+                                        if let Some(text) = block.get("thinking").and_then(|t| t.as_str()) {
+                                            accumulated_thinking.push_str(text);
+                                            let _ = tx.send(StreamEvent::ThinkingDelta {
+                                                text: text.to_string(),
+                                            });
+                                        }
+                                    }
+                                    Some("tool_use") => {
+                                        let id = block["id"].as_str().unwrap_or("").to_string();
+                                        let input = block.get("input").cloned().unwrap_or_default();
+                                        // Already announced by `content_block_start`.
+                                        if let Some(call) = tool_calls.iter_mut().find(|c| c.id == id) {
+                                            call.input = input;
+                                            continue;
+                                        }
+                                        let name = block["name"].as_str().unwrap_or("").to_string();
+                                        let _ = tx.send(StreamEvent::ToolUseStart {
+                                            tool_call_id: id.clone(),
+                                            name: name.clone(),
+                                            input: input.clone(),
+                                        });
+                                        tool_calls.push(ToolCall {
+                                            id,
+                                            name,
+                                            input,
+                                            output: None,
+                                            status: ToolCallStatus::Running,
+                                            subagent: Vec::new(),
+                                        });
+                                    }
+                                    _ => {}
                                 }
                             }
                         }
+                        if turn_usage.record(&event)
+                            && last_usage_update
+                                .is_none_or(|at| at.elapsed() >= crate::usage::UPDATE_INTERVAL)
+                        {
+                            let context_tokens = turn_usage.context_tokens();
+                            let _ = tx.send(usage_update(turn_usage.total(), context_tokens));
+                            last_usage_update = Some(Instant::now());
+                        }
+                    }
+                    "user" if crate::subagent::parent_id(&event).is_none() => {
+                        // Results of the tools Claude called.
+                        let blocks = event
+                            .get("message")
+                            .and_then(|m| m.get("content"))
+                            .and_then(|c| c.as_array())
+                            .cloned()
+                            .unwrap_or_default();
+                        for block in blocks.iter().filter(|b| b["type"] == "tool_result") {
+                            let id = block["tool_use_id"].as_str().unwrap_or("");
+                            let output = crate::subagent::tool_result_text(block);
+                            let is_error = block["is_error"].as_bool().unwrap_or(false);
+                            if let Some(call) = tool_calls.iter_mut().find(|c| c.id == id) {
+                                call.output = Some(output.clone());
+                                call.status = if is_error {
+                                    ToolCallStatus::Failed
+                                } else {
+                                    ToolCallStatus::Completed
+                                };
+                            }
+                            let _ = tx.send(StreamEvent::ToolResult {
+                                tool_call_id: id.to_string(),
+                                output,
+                                is_error,
+                            });
+                        }
+                    }
+                    "user" => {
+                        // A subagent's tool results; its steps are reported below.
                     }
                     "content_block_delta" => {
-                        // Streaming text delta.
-                        if let Some(delta) = event.get("delta") {
-                            if delta.get("type").and_then(|t| t.as_str()) == Some("text_delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
+                        // Streaming text or thinking delta.
+                        let delta = event.get("delta");
+                        match delta.and_then(|d| d.get("type")).and_then(|t| t.as_str()) {
+                            Some("text_delta") => {
+                                if let Some(text) = delta.and_then(|d| d.get("text")).and_then(|t| t.as_str()) {
                                     accumulated_text.push_str(text);
                                     let _ = tx.send(StreamEvent::TextDelta {
                                         text: text.to_string(),
                                     });
                                 }
                             }
+                            Some("thinking_delta") => {
+                                if let Some(text) =
+                                    delta.and_then(|d| d.get("thinking")).and_then(|t| t.as_str())
+                                {
+                                    accumulated_thinking.push_str(text);
+                                    let _ = tx.send(StreamEvent::ThinkingDelta {
+                                        text: text.to_string(),
+                                    });
+                                }
+                            }
+                            _ => {}
                         }
                     }
                     "content_block_start" => {
                         // Check for tool use start.
-                        if let Some(content_block) = event.get("content_block") {
-                            if content_block.get("type").and_then(|t| t.as_str()) == Some("tool_use")
-                            {
-                                let tool_id = content_block
-                                    .get("id")
-                                    .and_then(|i| i.as_str())
-                                    .unwrap_or("")
-                                    .to_string();
-                                let tool_name = content_block
-                                    .get("name")
-                                    .and_then(|n| n.as_str())
-                                    .unwrap_or("")
-                                    .to_string();
-
-                                let _ = tx.send(StreamEvent::ToolUseStart {
-                                    tool_call_id: tool_id.clone(),
-                                    name: tool_name.clone(),
-                                    input: Value::Object(Default::default()),
-                                });
-
-                                tool_calls.push(ToolCall {
-                                    id: tool_id,
-                                    name: tool_name,
-                                    input: Value::Object(Default::default()),
-                                    output: None,
-                                    status: ToolCallStatus::Running,
-                                });
-                            }
+                        if let Some(content_block) = event.get("content_block")
+                            && content_block.get("type").and_then(|t| t.as_str()) == Some("tool_use")
+                        {
+                            let tool_id = content_block
+                                .get("id")
+                                .and_then(|i| i.as_str())
+                                .unwrap_or("")
+                                .to_string();
+                            let tool_name = content_block
+                                .get("name")
+                                .and_then(|n| n.as_str())
+                                .unwrap_or("")
+                                .to_string();
+
+                            let _ = tx.send(StreamEvent::ToolUseStart {
+                                tool_call_id: tool_id.clone(),
+                                name: tool_name.clone(),
+                                input: Value::Object(Default::default()),
+                            });
+
+                            tool_calls.push(ToolCall {
+                                id: tool_id,
+                                name: tool_name,
+                                input: Value::Object(Default::default()),
+                                output: None,
+                                status: ToolCallStatus::Running,
+                                subagent: Vec::new(),
+                            });
                         }
                     }
                     "result" => {
-                        // Final result with metadata.
+                        // Final result with metadata. Closing stdin lets the
+                        // CLI exit.
                         tracing::info!("Result event: {:?}", event);
+                        stdin.lock().await.take();
                         final_claude_sid = event
                             .get("session_id")
                             .and_then(|s| s.as_str())
                             .map(String::from);
-                        final_cost = event.get("cost_usd").and_then(|c| c.as_f64());
+                        final_cost = event
+                            .get("total_cost_usd")
+                            .or_else(|| event.get("cost_usd"))
+                            .and_then(|c| c.as_f64());
+                        if event.get("is_error").and_then(|e| e.as_bool()) == Some(true) {
+                            result_error = Some(
+                                event
+                                    .get("result")
+                                    .and_then(|r| r.as_str())
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            );
+                        }
 
                         if let Some(usage) = event.get("usage") {
                             tracing::info!("Usage found: {:?}", usage);
-                            final_usage = Some(TokenUsage {
-                                input_tokens: usage
-                                    .get("input_tokens")
-                                    .and_then(|t| t.as_u64())
-                                    .unwrap_or(0),
-                                output_tokens: usage
-                                    .get("output_tokens")
-                                    .and_then(|t| t.as_u64())
-                                    .unwrap_or(0),
-                                cache_read_tokens: usage
-                                    .get("cache_read_input_tokens")
-                                    .and_then(|t| t.as_u64()),
-                                cache_write_tokens: usage
-                                    .get("cache_creation_input_tokens")
-                                    .and_then(|t| t.as_u64()),
-                            });
+                            final_usage = Some(crate::usage::parse(usage));
                         }
+                        let usage = final_usage.clone().unwrap_or_else(|| turn_usage.total());
+                        let _ = tx.send(usage_update(usage, turn_usage.context_tokens()));
 
                         // Create the complete assistant message.
                         let assistant_msg = Message {
@@ -417,8 +1743,22 @@ impl ConversationManager {
                             timestamp: Utc::now(),
                             usage: final_usage.clone(),
                             cost_usd: final_cost,
+                            context_files: Vec::new(),
+                            attachments: Vec::new(),
+                            thinking: (!accumulated_thinking.is_empty())
+                                .then(|| accumulated_thinking.clone()),
+                            source: None,
+                            compaction: None,
                         };
 
+                        if let Some(ref feed) = manager.activity_feed {
+                            feed.publish(
+                                &session_id_clone,
+                                ActivityKind::MessageComplete {
+                                    message_id: assistant_msg.id.clone(),
+                                },
+                            );
+                        }
                         let _ = tx.send(StreamEvent::MessageComplete {
                             message: Box::new(assistant_msg),
                         });
@@ -428,11 +1768,170 @@ impl ConversationManager {
                         tracing::debug!("Unknown event type: {}", event_type);
                     }
                 }
+
+                if matches!(event_type, "assistant" | "user") {
+                    for update in subagents.record(&event) {
+                        if let Some(call) = tool_calls.iter_mut().find(|c| c.id == update.id) {
+                            call.subagent.push(update.step.clone());
+                        }
+                        let _ = tx.send(update.event());
+                    }
+                }
+
+                if session.read_only
+                    && let Some(call) = tool_calls
+                        .iter_mut()
+                        .find(|c| c.status == ToolCallStatus::Running && crate::read_only::writes(&c.name))
+                {
+                    let message = crate::read_only::blocked_message(&call.name);
+                    audit.finish(&call.id, AuditOutcome::Blocked);
+                    call.status = ToolCallStatus::Failed;
+                    call.output = Some(message.clone());
+                    let _ = tx.send(StreamEvent::ToolResult {
+                        tool_call_id: call.id.clone(),
+                        output: message,
+                        is_error: true,
+                    });
+                    blocked = Some(call.name.clone());
+                    break;
+                }
+            }
+
+            let mut failure: Option<Message> = None;
+            let mut backoff: Option<RateLimitBackoff> = None;
+            if was_cancelled || timed_out.is_some() || blocked.is_some() {
+                if let Err(e) = crate::process_group::stop_child(&mut child).await {
+                    tracing::warn!("Failed to stop Claude CLI for session {}: {}", session_id_clone, e);
+                }
+                if let Some(tool) = blocked.as_deref() {
+                    tracing::warn!("Stopped Claude CLI for session {}: it called {} while read-only", session_id_clone, tool);
+                    let message = crate::read_only::blocked_message(tool);
+                    let error_msg = system_message(message.clone());
+                    let _ = tx.send(StreamEvent::MessageComplete {
+                        message: Box::new(error_msg.clone()),
+                    });
+                    let _ = tx.send(StreamEvent::Error {
+                        message,
+                        kind: ChatErrorKind::ReadOnly,
+                        detail: None,
+                    });
+                    failure = Some(error_msg);
+                }
+                if let Some(message) = timed_out {
+                    tracing::warn!("Stopped Claude CLI for session {}: {}", session_id_clone, message);
+                    let _ = tx.send(StreamEvent::Error {
+                        message,
+                        kind: ChatErrorKind::Timeout,
+                        detail: None,
+                    });
+                }
+            } else {
+                let exit = match child.wait().await {
+                    Ok(status) if !status.success() && final_claude_sid.is_none() => Some(status.to_string()),
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::error!("Failed to wait for Claude CLI: {}", e);
+                        None
+                    }
+                };
+                if exit.is_some() || result_error.is_some() {
+                    let stderr = stderr.await.unwrap_or_default();
+                    let detail = match result_error.as_deref() {
+                        Some(result) if !result.trim().is_empty() => result.trim().to_string(),
+                        _ => stderr.trim().to_string(),
+                    };
+                    let kind = crate::cli_error::classify(&format!("{}\n{}", detail, stderr));
+                    let exit = exit.unwrap_or_else(|| "an error result".to_string());
+                    let mut message = crate::cli_error::describe(kind, &exit);
+                    if crate::cli_error::is_rate_limit(kind) {
+                        let failures = sessions_ref
+                            .read()
+                            .await
+                            .get(session_id_clone.as_str())
+                            .and_then(|s| s.backoff.as_ref())
+                            .map_or(0, |backoff| backoff.failures)
+                            + 1;
+                        let retry_after = crate::cli_error::retry_after(&format!("{}\n{}", detail, stderr));
+                        let delay = crate::cli_error::backoff(failures, retry_after, &time_limits);
+                        let retrying = time_limits.retry_rate_limits && failures <= time_limits.max_retries;
+                        if retrying {
+                            message.push_str(&format!(" Retrying in {} seconds.", delay.as_secs()));
+                        }
+                        let _ = tx.send(StreamEvent::RateLimited {
+                            kind,
+                            retry_after: Some(delay.as_secs()),
+                            retrying,
+                        });
+                        backoff = Some(RateLimitBackoff {
+                            kind,
+                            until: Utc::now() + delay,
+                            failures,
+                            retrying,
+                        });
+                    }
+                    tracing::error!(
+                        "Claude CLI failed for session {} ({:?}, {}): {}",
+                        session_id_clone,
+                        kind,
+                        exit,
+                        detail
+                    );
+
+                    // Kept in the conversation, so the failure is still
+                    // shown after a reload.
+                    let error_msg = system_message(message.clone());
+                    let _ = tx.send(StreamEvent::MessageComplete {
+                        message: Box::new(error_msg.clone()),
+                    });
+                    let _ = tx.send(StreamEvent::Error {
+                        message,
+                        kind,
+                        detail: (!detail.is_empty()).then_some(detail),
+                    });
+                    failure = Some(error_msg);
+                }
             }
 
             // Update session state after completion.
-            let rt = tokio::runtime::Handle::current();
-            rt.block_on(async {
+            if final_usage.is_some() || final_cost.is_some() {
+                let usage = final_usage.clone().unwrap_or_default();
+                manager
+                    .record_spend(&session_id_clone, &project, &usage, final_cost.unwrap_or(0.0))
+                    .await;
+            }
+
+            if snapshot_after {
+                snapshot_turn(session.working_dir.as_deref(), &session_id_clone, &turn_message_id, false).await;
+            }
+            // The copy a read-only turn ran in goes, with what it changed.
+            let mut discarded: Option<String> = None;
+            if let Some(scratch) = scratch.take() {
+                match discard_copy(scratch).await {
+                    Ok(paths) if paths.is_empty() => {}
+                    Ok(paths) => {
+                        tracing::info!("Discarded changes of read-only session {}: {:?}", session_id_clone, paths);
+                        discarded = Some(crate::read_only::discarded_message(&paths));
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to list changes of read-only session {}: {}", session_id_clone, e);
+                    }
+                }
+            }
+            // Kept in the conversation like a failure, after the reply.
+            let discarded = discarded.map(|message| {
+                let notice = system_message(message.clone());
+                let _ = tx.send(StreamEvent::MessageComplete {
+                    message: Box::new(notice.clone()),
+                });
+                let _ = tx.send(StreamEvent::Error {
+                    message,
+                    kind: ChatErrorKind::ReadOnly,
+                    detail: None,
+                });
+                notice
+            });
+
+            {
                 let mut sessions = sessions_ref.write().await;
                 if let Some(s) = sessions.get_mut(session_id_clone.as_str()) {
                     // Create final assistant message if we have accumulated text.
@@ -445,62 +1944,139 @@ impl ConversationManager {
                             timestamp: Utc::now(),
                             usage: final_usage.clone(),
                             cost_usd: final_cost,
+                            context_files: Vec::new(),
+                            attachments: Vec::new(),
+                            thinking: (!accumulated_thinking.is_empty())
+                                .then(|| accumulated_thinking.clone()),
+                            source: None,
+                            compaction: None,
                         };
                         s.messages.push(assistant_msg);
                     }
 
                     // Update session metadata.
                     if let Some(ref sid) = final_claude_sid {
-                        s.claude_session_id = Some(sid.clone());
+                        if session.read_only {
+                            s.read_only_claude_session_id = Some(sid.clone());
+                        } else {
+                            s.claude_session_id = Some(sid.clone());
+                            s.fork_session = false;
+                            s.replay_history = false;
+                        }
                     }
                     if let Some(usage) = final_usage {
-                        s.total_usage.input_tokens += usage.input_tokens;
-                        s.total_usage.output_tokens += usage.output_tokens;
+                        crate::usage::add(&mut s.total_usage, &usage);
+                    }
+                    if turn_usage.context_tokens() > 0 {
+                        s.context_tokens = turn_usage.context_tokens();
                     }
                     if let Some(cost) = final_cost {
                         s.total_cost_usd += cost;
                     }
-                    s.state = ConversationState::Idle;
+                    s.messages.extend(discarded);
+                    s.backoff = backoff.clone();
+                    s.state = match failure {
+                        Some(error_msg) => {
+                            s.messages.push(error_msg);
+                            ConversationState::Error
+                        }
+                        None => ConversationState::Idle,
+                    };
+                    s.last_activity = Some(Utc::now());
                 }
+            }
 
-                // Persist claude_session_id to DaemonState so it survives restarts.
-                if let Some(ref sid) = final_claude_sid {
-                    let mut daemon_state = daemon_state_ref.lock().await;
-                    if let Some(session) = daemon_state.sessions.get_mut(session_id_clone.as_str()) {
-                        session.claude_session_id = Some(sid.clone());
-                        session.updated_at = Utc::now();
-                        // Save state to disk.
-                        if let Err(e) = daemon_state.save(&state_path_ref) {
-                            tracing::error!("Failed to persist daemon state: {}", e);
-                        } else {
-                            tracing::debug!("Persisted claude_session_id {} for session {}", sid, session_id_clone);
-                        }
+            // Persist claude_session_id to DaemonState so it survives restarts.
+            if let Some(ref sid) = final_claude_sid
+                && !session.read_only
+            {
+                let mut daemon_state = daemon_state_ref.lock().await;
+                if let Some(session) = daemon_state.sessions.get_mut(session_id_clone.as_str()) {
+                    session.claude_session_id = Some(sid.clone());
+                    session.updated_at = Utc::now();
+                    // Save state to disk.
+                    if let Err(e) = daemon_state.save(&state_path_ref) {
+                        tracing::error!("Failed to persist daemon state: {}", e);
+                    } else {
+                        tracing::debug!("Persisted claude_session_id {} for session {}", sid, session_id_clone);
                     }
                 }
+            }
 
-                // Remove from active processes.
-                let mut active = active_ref.lock().await;
+            // Remove from active processes, unless a newer turn replaced it.
+            let mut active = active_ref.lock().await;
+            if active
+                .get(session_id_clone.as_str())
+                .is_some_and(|response| response.pid == pid)
+            {
                 active.remove(session_id_clone.as_str());
-            });
+            }
+            drop(active);
 
-            let _ = tx.send(StreamEvent::Idle);
+            manager.end_turn(&session_id_clone).await;
+            if let Some(backoff) = backoff.filter(|backoff| backoff.retrying) {
+                manager.schedule_retry(&session_id_clone, &backoff);
+            }
         });
 
-        Ok(user_msg_id)
+        Ok(())
     }
 
-    /// Cancel an in-progress response.
+    /// Send the last message again once `backoff` has passed, unless the
+    /// conversation has moved on by then.
+    fn schedule_retry(&self, session_id: &SessionId, backoff: &RateLimitBackoff) {
+        let manager = self.clone();
+        let session_id = session_id.clone();
+        let failures = backoff.failures;
+        let delay = (backoff.until - Utc::now()).to_std().unwrap_or_default();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let waiting = manager
+                .sessions
+                .read()
+                .await
+                .get(session_id.as_str())
+                .and_then(|s| s.backoff.as_ref())
+                .is_some_and(|backoff| backoff.retrying && backoff.failures == failures);
+            if !waiting {
+                return;
+            }
+            tracing::info!("Retrying session {} after a rate limit ({} failed in a row)", session_id, failures);
+            if let Err(e) = manager.retry_message(&session_id).await {
+                tracing::warn!("Failed to retry session {} after a rate limit: {}", session_id, e);
+                manager.update_session(&session_id, |s| {
+                    if let Some(backoff) = &mut s.backoff {
+                        backoff.retrying = false;
+                    }
+                })
+                .await;
+            }
+        });
+    }
+
+    /// Pids of the Claude CLI processes producing responses, by session.
+    pub async fn response_pids(&self) -> Vec<(SessionId, u32)> {
+        self.active_processes
+            .lock()
+            .await
+            .iter()
+            .map(|(id, response)| (SessionId::new(id.clone()), response.pid))
+            .collect()
+    }
+
+    /// Cancel an in-progress response, stopping the Claude CLI and everything
+    /// it spawned.
     pub async fn cancel_response(&self, session_id: &SessionId) -> Result<(), ConversationError> {
         let mut active = self.active_processes.lock().await;
-        if let Some(mut child) = active.remove(session_id.as_str()) {
-            child
-                .kill()
-                .map_err(|e| ConversationError::KillFailed(e.to_string()))?;
+        if let Some(response) = active.remove(session_id.as_str()) {
+            // The reader task stops the process; it may have just finished.
+            let _ = response.cancel.send(());
 
             // Update state.
             let mut sessions = self.sessions.write().await;
             if let Some(s) = sessions.get_mut(session_id.as_str()) {
                 s.state = ConversationState::Idle;
+                s.last_activity = Some(Utc::now());
             }
 
             // Send idle event.
@@ -513,6 +2089,27 @@ impl ConversationManager {
         }
     }
 
+    /// Answer a prompt the Claude CLI raised mid-response (see
+    /// [`StreamEvent::InputRequested`]), sent to it as a user message.
+    pub async fn send_response_input(&self, session_id: &SessionId, text: &str) -> Result<(), ConversationError> {
+        let stdin = self
+            .active_processes
+            .lock()
+            .await
+            .get(session_id.as_str())
+            .map(|response| response.stdin.clone())
+            .ok_or(ConversationError::NoActiveResponse)?;
+        let mut stdin = stdin.lock().await;
+        let pipe = stdin.as_mut().ok_or(ConversationError::NoActiveResponse)?;
+        if let Err(e) = pipe.write_all(input_line(text).as_bytes()).await {
+            // The CLI exited; the reader task reports how.
+            tracing::warn!("Failed to send input to Claude CLI for session {}: {}", session_id, e);
+            stdin.take();
+            return Err(ConversationError::NoActiveResponse);
+        }
+        Ok(())
+    }
+
     /// Get all messages for a session.
     pub async fn get_messages(
         &self,
@@ -528,10 +2125,10 @@ impl ConversationManager {
         let mut messages = session.messages.clone();
 
         // Apply before_id filter.
-        if let Some(ref bid) = before_id {
-            if let Some(pos) = messages.iter().position(|m| m.id == *bid) {
-                messages = messages[..pos].to_vec();
-            }
+        if let Some(ref bid) = before_id
+            && let Some(pos) = messages.iter().position(|m| m.id == *bid)
+        {
+            messages = messages[..pos].to_vec();
         }
 
         // Apply limit.
@@ -543,45 +2140,585 @@ impl ConversationManager {
         Ok(messages)
     }
 
+    /// Cumulative token usage and cost, and how full the context window is.
+    pub async fn usage(&self, session_id: &SessionId) -> Result<SessionUsage, ConversationError> {
+        let sessions = self.sessions.read().await;
+        let session = sessions.get(session_id.as_str()).ok_or_else(|| {
+            ConversationError::SessionNotFound(session_id.as_str().to_string())
+        })?;
+        let context_window = crate::usage::context_window(&session.model);
+        Ok(SessionUsage {
+            total: session.total_usage.clone(),
+            total_cost_usd: session.total_cost_usd,
+            context_tokens: session.context_tokens,
+            context_window,
+            context_utilization: crate::usage::utilization(session.context_tokens, context_window),
+            backoff: session.backoff.clone(),
+        })
+    }
+
     /// Get the current conversation state.
     pub async fn get_state(&self, session_id: &SessionId) -> Option<ConversationState> {
         let sessions = self.sessions.read().await;
         sessions.get(session_id.as_str()).map(|s| s.state.clone())
     }
 
-    /// Initialize a session (called when creating a new session).
-    /// Only creates a new session if one doesn't already exist.
-    /// If `claude_session_id` is provided, it will be used for resuming conversations.
-    pub async fn init_session(
+    /// Get the conversation state and when it last changed.
+    pub async fn chat_activity(
         &self,
         session_id: &SessionId,
-        model: &str,
-        working_dir: Option<String>,
-        claude_session_id: Option<String>,
-    ) {
+    ) -> Option<(ConversationState, Option<DateTime<Utc>>)> {
+        let sessions = self.sessions.read().await;
+        sessions
+            .get(session_id.as_str())
+            .map(|s| (s.state.clone(), s.last_activity))
+    }
+
+    /// Number of sessions with a response streaming.
+    pub async fn busy_count(&self) -> usize {
+        let sessions = self.sessions.read().await;
+        sessions.values().filter(|s| s.busy).count()
+    }
+
+    /// Cumulative token usage and cost per session.
+    pub async fn usage_by_session(&self) -> Vec<(String, TokenUsage, f64)> {
+        let sessions = self.sessions.read().await;
+        sessions
+            .iter()
+            .map(|(id, s)| (id.clone(), s.total_usage.clone(), s.total_cost_usd))
+            .collect()
+    }
+
+    /// Live event stream subscribers per session.
+    pub async fn subscriber_counts(&self) -> Vec<(String, usize)> {
+        let senders = self.event_senders.read().await;
+        senders
+            .iter()
+            .map(|(id, tx)| (id.clone(), tx.receiver_count()))
+            .collect()
+    }
+
+    /// Broadcast an event to a session's stream subscribers.
+    pub async fn publish(&self, session_id: &SessionId, event: StreamEvent) {
+        let senders = self.event_senders.read().await;
+        if let Some(tx) = senders.get(session_id.as_str()) {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Initialize a session (called when creating a new session).
+    /// Only creates a new session if one doesn't already exist.
+    /// If the session has a `claude_session_id`, it will be used for resuming conversations.
+    pub async fn init_session(&self, session: &Session) {
         let mut sessions = self.sessions.write().await;
-        sessions.entry(session_id.as_str().to_string()).or_insert_with(|| {
+        sessions.entry(session.id.as_str().to_string()).or_insert_with(|| {
             ConversationSession {
-                model: model.to_string(),
-                working_dir,
-                claude_session_id,
+                model: session.model.clone(),
+                working_dir: session.working_dir.clone(),
+                claude_session_id: session.claude_session_id.clone(),
+                system_prompt: session.system_prompt.clone(),
+                env: session.env.clone(),
+                mcp_config: session.mcp_config.clone(),
+                claude_flags: session.claude_flags.clone(),
+                permission_mode: session.permission_mode,
+                read_only: session.read_only,
+                sandbox: session.sandbox.clone(),
+                extended_thinking: session.extended_thinking,
                 ..Default::default()
             }
         });
     }
 
+    /// A copy of `source`'s conversation up to and including `at_message_id`
+    /// (all of it if `None`), for a fork to continue from.
+    ///
+    /// Forking at the latest message resumes the source's Claude session with
+    /// `--fork-session`, so Claude keeps its whole context without adding to
+    /// the original thread. A Claude session can't be cut short, so forking
+    /// earlier starts a new one with the copied messages replayed ahead of
+    /// the first prompt.
+    pub async fn fork(
+        &self,
+        source: &SessionId,
+        at_message_id: Option<&str>,
+    ) -> Result<ConversationSession, ConversationError> {
+        let sessions = self.sessions.read().await;
+        let session = sessions
+            .get(source.as_str())
+            .ok_or_else(|| ConversationError::SessionNotFound(source.as_str().to_string()))?;
+        if session.busy {
+            return Err(ConversationError::Busy);
+        }
+        let end = match at_message_id {
+            Some(id) => {
+                session
+                    .messages
+                    .iter()
+                    .position(|m| m.id == id)
+                    .ok_or_else(|| ConversationError::MessageNotFound(id.to_string()))?
+                    + 1
+            }
+            None => session.messages.len(),
+        };
+        let messages = session.messages[..end].to_vec();
+        let latest = end == session.messages.len();
+        // Read-only turns went to a Claude session of their own, run in the
+        // source's copy of the workspace, which the fork can't resume.
+        let claude_session_id = session
+            .claude_session_id
+            .clone()
+            .filter(|_| latest && session.read_only_claude_session_id.is_none());
+
+        Ok(ConversationSession {
+            state: if messages.is_empty() {
+                ConversationState::Empty
+            } else {
+                ConversationState::Idle
+            },
+            fork_session: claude_session_id.is_some(),
+            context_tokens: if claude_session_id.is_some() { session.context_tokens } else { 0 },
+            replay_history: claude_session_id.is_none() && !messages.is_empty(),
+            claude_session_id,
+            messages,
+            working_dir: session.working_dir.clone(),
+            model: session.model.clone(),
+            system_prompt: session.system_prompt.clone(),
+            env: session.env.clone(),
+            mcp_config: session.mcp_config.clone(),
+            claude_flags: session.claude_flags.clone(),
+            permission_mode: session.permission_mode,
+            read_only: session.read_only,
+            sandbox: session.sandbox.clone(),
+            extended_thinking: session.extended_thinking,
+            ..Default::default()
+        })
+    }
+
+    /// The Claude session a conversation resumes, if it has one.
+    pub async fn claude_session_id(&self, session_id: &SessionId) -> Option<String> {
+        let sessions = self.sessions.read().await;
+        sessions.get(session_id.as_str())?.claude_session_id.clone()
+    }
+
+    /// Carry a conversation on from a turn taken in a fork of it (see
+    /// [`Self::fork`]), e.g. the answer picked from a comparison: the turn's
+    /// `messages` are added and `claude_session_id`, the fork's, is resumed
+    /// from the next turn on. Fails if the conversation has moved on from
+    /// `after_message_id`, its last message when the fork was made.
+    pub async fn adopt_fork(
+        &self,
+        session_id: &SessionId,
+        after_message_id: Option<&str>,
+        messages: Vec<Message>,
+        claude_session_id: String,
+    ) -> Result<(), ConversationError> {
+        {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions
+                .get_mut(session_id.as_str())
+                .ok_or_else(|| ConversationError::SessionNotFound(session_id.as_str().to_string()))?;
+            if session.busy {
+                return Err(ConversationError::Busy);
+            }
+            if session.messages.last().map(|m| m.id.as_str()) != after_message_id {
+                return Err(ConversationError::Diverged);
+            }
+            session.messages.extend(messages.iter().cloned());
+            session.count_imported(&messages);
+            session.claude_session_id = Some(claude_session_id.clone());
+            session.fork_session = false;
+            session.replay_history = false;
+            session.state = ConversationState::Idle;
+            session.last_activity = Some(Utc::now());
+        }
+        self.persist_session(session_id, |s| s.claude_session_id = Some(claude_session_id))
+            .await;
+
+        let sender = self.get_sender(session_id).await;
+        for message in messages {
+            sender.send(StreamEvent::MessageComplete {
+                message: Box::new(message),
+            });
+        }
+        Ok(())
+    }
+
+    /// Start a session off with `conversation`, e.g. from [`Self::fork`].
+    pub async fn insert_session(&self, session_id: &SessionId, conversation: ConversationSession) {
+        let mut sessions = self.sessions.write().await;
+        sessions.insert(session_id.as_str().to_string(), conversation);
+    }
+
+    /// Pick up changes to a session's model, working directory, system
+    /// prompt, environment, CLI flags, permission mode, read-only mode and
+    /// thinking setting, from the next turn on.
+    pub async fn sync_session(&self, session: &Session) {
+        self.update_session(&session.id, |s| {
+            s.model = session.model.clone();
+            s.working_dir = session.working_dir.clone();
+            s.system_prompt = session.system_prompt.clone();
+            s.env = session.env.clone();
+            s.mcp_config = session.mcp_config.clone();
+            s.claude_flags = session.claude_flags.clone();
+            s.permission_mode = session.permission_mode;
+            // The Claude session of its read-only turns ends with them; the
+            // next turn starts another, given the whole conversation.
+            if s.read_only && !session.read_only && s.read_only_claude_session_id.take().is_some() {
+                s.claude_session_id = None;
+                s.fork_session = false;
+                s.replay_history = true;
+            }
+            s.read_only = session.read_only;
+            s.sandbox = session.sandbox.clone();
+            s.extended_thinking = session.extended_thinking;
+        })
+        .await;
+    }
+
+    /// Loaded conversations with a Claude session, whose transcripts
+    /// [`crate::cli_sync`] follows.
+    pub async fn transcripts(&self) -> Vec<crate::cli_sync::Transcript> {
+        let sessions = self.sessions.read().await;
+        sessions
+            .iter()
+            .filter_map(|(id, session)| {
+                Some(crate::cli_sync::Transcript {
+                    session_id: SessionId::new(id.clone()),
+                    claude_session_id: session.claude_session_id.clone()?,
+                    working_dir: session.working_dir.clone(),
+                    busy: session.busy,
+                    cli_runs: session.cli_runs,
+                })
+            })
+            .collect()
+    }
+
+    /// Add messages the Claude CLI recorded outside the daemon, e.g. turns
+    /// taken in a terminal, to a conversation and send them to subscribers.
+    /// Messages it already has are skipped.
+    pub async fn append_external(&self, session_id: &SessionId, messages: Vec<Message>) {
+        let mut added = Vec::new();
+        self.update_session(session_id, |s| {
+            for message in messages {
+                if !s.messages.iter().any(|m| m.id == message.id) {
+                    s.messages.push(message.clone());
+                    added.push(message);
+                }
+            }
+            if !added.is_empty() {
+                s.count_imported(&added);
+                s.state = ConversationState::Idle;
+                s.last_activity = Some(Utc::now());
+            }
+        })
+        .await;
+
+        let sender = self.get_sender(session_id).await;
+        for message in added {
+            if message.role == MessageRole::Assistant
+                && let Some(ref feed) = self.activity_feed
+            {
+                feed.publish(session_id, ActivityKind::MessageComplete { message_id: message.id.clone() });
+            }
+            sender.send(StreamEvent::MessageComplete {
+                message: Box::new(message),
+            });
+        }
+    }
+
+    /// Merge messages imported from a coding agent's history into the
+    /// conversation by timestamp, adding their usage and cost to the totals.
+    /// Messages it already has are skipped, so importing the same history
+    /// again, or history live sync brought in, changes nothing. Returns how
+    /// many were added and skipped.
+    pub async fn merge_imported(&self, session_id: &SessionId, messages: &[Message]) -> (usize, usize) {
+        let mut added = 0;
+        self.update_session(session_id, |s| {
+            let mut have: HashSet<String> = s.messages.iter().map(|m| m.id.clone()).collect();
+            for message in messages {
+                if !have.insert(message.id.clone()) {
+                    continue;
+                }
+                let at = s.messages.partition_point(|m| m.timestamp <= message.timestamp);
+                s.messages.insert(at, message.clone());
+                added += 1;
+            }
+            s.count_imported(messages);
+            if added > 0 && s.state == ConversationState::Empty {
+                s.state = ConversationState::Idle;
+            }
+        })
+        .await;
+        (added, messages.len() - added)
+    }
+
     /// Remove a session.
     pub async fn remove_session(&self, session_id: &SessionId) {
         let mut sessions = self.sessions.write().await;
-        sessions.remove(session_id.as_str());
+        let removed = sessions.remove(session_id.as_str());
+        drop(sessions);
 
         let mut senders = self.event_senders.write().await;
         senders.remove(session_id.as_str());
 
-        let mut active = self.active_processes.lock().await;
-        if let Some(mut child) = active.remove(session_id.as_str()) {
-            let _ = child.kill();
+        // Dropping the turn's cancel sender stops its process.
+        self.active_processes.lock().await.remove(session_id.as_str());
+
+        let attachments_dir = self.attachments_dir(session_id);
+        if let Err(e) = std::fs::remove_dir_all(&attachments_dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove attachments of session {}: {}", session_id, e);
+        }
+        let snapshots_dir = self.snapshots_dir(session_id);
+        if let Err(e) = std::fs::remove_dir_all(&snapshots_dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove snapshots of session {}: {}", session_id, e);
+        }
+        let runs_dir = self.runs_dir(session_id);
+        if let Err(e) = std::fs::remove_dir_all(&runs_dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove command runs of session {}: {}", session_id, e);
+        }
+        let comparisons_dir = self.comparisons_dir(session_id);
+        if let Err(e) = std::fs::remove_dir_all(&comparisons_dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove comparisons of session {}: {}", session_id, e);
+        }
+        let compactions_dir = self.compactions_dir(session_id);
+        if let Err(e) = std::fs::remove_dir_all(&compactions_dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove compactions of session {}: {}", session_id, e);
+        }
+        if let Err(e) = crate::memory::save_suggestions(&self.memory_suggestions_path(session_id), &[]) {
+            tracing::warn!("Failed to remove memory suggestions of session {}: {}", session_id, e);
+        }
+        // Not loaded after a restart: the session itself knows its directory.
+        let working_dir = match removed.and_then(|s| s.working_dir) {
+            Some(dir) => Some(dir),
+            None => self
+                .daemon_state
+                .lock()
+                .await
+                .sessions
+                .get(session_id.as_str())
+                .and_then(|s| s.working_dir.clone()),
+        };
+        if let Some(root) = working_dir.and_then(|dir| crate::git_ops::discover_repo_root(Path::new(&dir))) {
+            if let Err(e) = crate::review::remove_turn_refs(&root, session_id.as_str()) {
+                tracing::warn!("Failed to remove turn snapshots of session {}: {}", session_id, e);
+            }
+            // Left behind if the daemon stopped mid-turn.
+            if let Ok(dir) = crate::read_only::scratch_dir(&root, session_id.as_str())
+                && let Err(e) = crate::read_only::remove_dir(&dir)
+            {
+                tracing::warn!("Failed to remove read-only copy of session {}: {}", session_id, e);
+            }
+        }
+    }
+
+    /// Drop every conversation, stopping turns in progress, and forget the
+    /// Claude sessions they would resume. Returns how many there were.
+    pub async fn clear_all(&self) -> usize {
+        let loaded: Vec<String> = self.sessions.read().await.keys().cloned().collect();
+        for id in &loaded {
+            self.remove_session(&SessionId::new(id.clone())).await;
+        }
+
+        let mut cleared: std::collections::HashSet<String> = loaded.into_iter().collect();
+        let mut daemon_state = self.daemon_state.lock().await;
+        for (id, session) in daemon_state.sessions.iter_mut() {
+            if session.claude_session_id.take().is_some() || session.message_count > 0 {
+                cleared.insert(id.clone());
+            }
+            session.conversation_state = ConversationState::Empty;
+            session.message_count = 0;
+            session.total_usage = None;
+            session.total_cost_usd = None;
+        }
+        if let Err(e) = daemon_state.save(&self.state_path) {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        cleared.len()
+    }
+}
+
+/// A turn whose Claude CLI is running.
+struct ActiveResponse {
+    pid: u32,
+    /// Sending or dropping this stops the process and everything it spawned.
+    cancel: oneshot::Sender<()>,
+    /// The CLI's stdin, until the response is complete.
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+}
+
+/// A user message for `--input-format stream-json`.
+fn input_line(text: &str) -> String {
+    let message = serde_json::json!({
+        "type": "user",
+        "message": {
+            "role": "user",
+            "content": [{ "type": "text", "text": text }],
+        },
+    });
+    format!("{}\n", message)
+}
+
+/// What the CLI is retrying after, and in how many seconds if it says, from
+/// a `system` event of subtype `api_retry`; only rate limits and an
+/// overloaded API count.
+fn api_retry(event: &Value) -> Option<(ChatErrorKind, Option<u64>)> {
+    if event["type"] != "system" || event["subtype"] != "api_retry" {
+        return None;
+    }
+    let kind = match event["error_status"].as_u64() {
+        Some(429) => ChatErrorKind::RateLimited,
+        Some(503 | 529) => ChatErrorKind::Overloaded,
+        _ => crate::cli_error::classify(&event["error"].to_string()),
+    };
+    let retry_after = event["retry_delay_ms"].as_u64().map(|ms| ms.div_ceil(1000));
+    crate::cli_error::is_rate_limit(kind).then_some((kind, retry_after))
+}
+
+/// The question a stream-json event asks the user, with the answers to
+/// choose from if it offers any. The CLI reports these as `input_request`
+/// events, or as `system` events of that subtype.
+fn input_request(event: &Value) -> Option<(String, Vec<String>)> {
+    let is_request = match event.get("type").and_then(|t| t.as_str()) {
+        Some("input_request") => true,
+        Some("system") => event.get("subtype").and_then(|s| s.as_str()) == Some("input_request"),
+        _ => false,
+    };
+    if !is_request {
+        return None;
+    }
+    let prompt = ["prompt", "message", "question"]
+        .iter()
+        .find_map(|key| event.get(key).and_then(|p| p.as_str()))
+        .unwrap_or_default()
+        .to_string();
+    let options = event
+        .get("options")
+        .and_then(|o| o.as_array())
+        .map(|options| {
+            options
+                .iter()
+                .filter_map(|option| {
+                    option
+                        .as_str()
+                        .or_else(|| option.get("label").and_then(|l| l.as_str()))
+                        .map(String::from)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Some((prompt, options))
+}
+
+/// The tail of a Claude CLI's stderr, for reporting why it failed.
+async fn read_stderr(mut stderr: ChildStderr) -> String {
+    let mut kept = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        match stderr.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                kept.extend_from_slice(&buf[..n]);
+                if kept.len() > MAX_STDERR_BYTES {
+                    kept.drain(..kept.len() - MAX_STDERR_BYTES);
+                }
+            }
+        }
+    }
+    String::from_utf8_lossy(&kept).into_owned()
+}
+
+/// Snapshot the repository containing `working_dir`, if there is one, for
+/// the turn of `message_id` (see [`crate::review::snapshot_turn`]). Returns
+/// the snapshot's tree.
+async fn snapshot_turn(working_dir: Option<&str>, session_id: &SessionId, message_id: &str, before: bool) -> Option<String> {
+    let root = crate::git_ops::discover_repo_root(Path::new(working_dir?))?;
+    let session_id = session_id.to_string();
+    let message_id = message_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        crate::review::snapshot_turn(&root, &session_id, &message_id, before)
+            .inspect_err(|e| tracing::warn!("Failed to snapshot {}: {}", root.display(), e))
+            .ok()
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Copy the workspace of a read-only session for a turn (see
+/// [`crate::read_only::Scratch`]).
+async fn read_only_copy(working_dir: Option<&str>, session_id: &SessionId) -> Result<Scratch, ConversationError> {
+    let working_dir = PathBuf::from(working_dir.unwrap_or_default());
+    let root = crate::git_ops::discover_repo_root(&working_dir)
+        .ok_or_else(|| ConversationError::ReadOnlyCopyFailed(format!("not in a repository: {}", working_dir.display())))?;
+    let session_id = session_id.to_string();
+    tokio::task::spawn_blocking(move || Scratch::create(&root, &working_dir, &session_id))
+        .await
+        .map_err(|e| ConversationError::ReadOnlyCopyFailed(e.to_string()))?
+        .map_err(|e| ConversationError::ReadOnlyCopyFailed(e.to_string()))
+}
+
+/// Delete the copy a read-only turn ran in. Returns the paths the turn
+/// changed there.
+async fn discard_copy(scratch: Scratch) -> Result<Vec<String>, GitError> {
+    tokio::task::spawn_blocking(move || scratch.changes())
+        .await
+        .map_err(|e| GitError::PathError(e.to_string()))?
+}
+
+/// A message from the daemon itself, e.g. about a failure.
+fn system_message(content: String) -> Message {
+    Message {
+        id: Uuid::new_v4().to_string(),
+        role: MessageRole::System,
+        content,
+        tool_calls: Vec::new(),
+        timestamp: Utc::now(),
+        usage: None,
+        cost_usd: None,
+        context_files: Vec::new(),
+        attachments: Vec::new(),
+        thinking: None,
+        source: None,
+        compaction: None,
+    }
+}
+
+/// Resolves when a response that started at `started` and last printed a
+/// stream event at `last_event` has run out of time, with a message saying
+/// which limit it hit. Never resolves if neither limit is set.
+async fn response_expiry(
+    started: tokio::time::Instant,
+    last_event: tokio::time::Instant,
+    limits: &ChatConfig,
+) -> String {
+    let timeout = limits.timeout_minutes.filter(|m| *m > 0).map(|minutes| {
+        (
+            started + Duration::from_secs(minutes * 60),
+            format!("Claude didn't finish within {} minutes, so the response was stopped.", minutes),
+        )
+    });
+    let stall = limits.stall_seconds.filter(|s| *s > 0).map(|seconds| {
+        (
+            last_event + Duration::from_secs(seconds),
+            format!("Claude sent nothing for {} seconds, so the response was stopped.", seconds),
+        )
+    });
+    match timeout.into_iter().chain(stall).min_by_key(|(deadline, _)| *deadline) {
+        Some((deadline, reason)) => {
+            tokio::time::sleep_until(deadline).await;
+            reason
         }
+        None => std::future::pending().await,
     }
 }
 
@@ -597,6 +2734,9 @@ pub enum ConversationError {
     #[error("Claude CLI not found on system")]
     ClaudeNotFound,
 
+    #[error(transparent)]
+    Cli(#[from] CliError),
+
     #[error("Failed to spawn process: {0}")]
     SpawnFailed(String),
 
@@ -606,6 +2746,51 @@ pub enum ConversationError {
     #[error("No active response to cancel")]
     NoActiveResponse,
 
+    #[error("A response is in progress")]
+    Busy,
+
+    #[error("Queued message not found: {0}")]
+    QueuedMessageNotFound(String),
+
+    #[error("Message not found: {0}")]
+    MessageNotFound(String),
+
+    #[error("Not a user message: {0}")]
+    NotAUserMessage(String),
+
+    #[error("The last response didn't fail, nothing to retry")]
+    NothingToRetry,
+
+    #[error("The conversation has moved on since")]
+    Diverged,
+
+    #[error("Too few messages to compact")]
+    NothingToCompact,
+
+    #[error("The session is read-only but its workspace couldn't be copied for the turn: {0}")]
+    ReadOnlyCopyFailed(String),
+
+    #[error("No reply from Claude: {0}")]
+    NoReply(String),
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error(transparent)]
+    Env(#[from] crate::session_env::EnvError),
+
+    #[error("Too many context files: {0} (at most {max})", max = crate::context::MAX_CONTEXT_FILES)]
+    TooManyContextFiles(usize),
+
+    #[error("Context file: {0}")]
+    ContextFile(#[from] FilesError),
+
+    #[error("Attachment: {0}")]
+    Attachment(#[from] AttachmentError),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
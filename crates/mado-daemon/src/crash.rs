@@ -0,0 +1,265 @@
+//! Crash reports.
+//!
+//! A panic hook writes a JSON report (message, backtrace, the last daemon log
+//! lines and the sessions at the time) to `<base>/crashes/` before the
+//! default hook runs. Background tasks started with [`spawn_supervised`] are
+//! named in the report and restarted after a panic. Reports are kept until a
+//! client deletes them (`DELETE /crashes/{id}`).
+
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once, RwLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::FutureExt;
+use tokio::sync::Mutex;
+use tracing;
+
+use mado_core::types::{CrashReport, CrashSession};
+
+use crate::state::DaemonState;
+
+/// Log lines included in a report.
+const RECENT_LOG_LINES: usize = 100;
+
+/// Pause before a supervised task that panicked is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Where reports go and what they describe, set while a daemon is running.
+static CONTEXT: RwLock<Option<CrashContext>> = RwLock::new(None);
+
+static INSTALL_HOOK: Once = Once::new();
+
+tokio::task_local! {
+    /// Name of the supervised task being polled.
+    static TASK_NAME: &'static str;
+}
+
+#[derive(Clone)]
+struct CrashContext {
+    dir: PathBuf,
+    log_dir: Option<PathBuf>,
+    daemon_state: Arc<Mutex<DaemonState>>,
+}
+
+/// Write crash reports to `dir` until the returned guard is dropped.
+pub fn install(
+    dir: PathBuf,
+    log_dir: Option<PathBuf>,
+    daemon_state: Arc<Mutex<DaemonState>>,
+) -> CrashReporterGuard {
+    INSTALL_HOOK.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            write_panic_report(info);
+            default_hook(info);
+        }));
+    });
+
+    *CONTEXT.write().unwrap_or_else(|e| e.into_inner()) = Some(CrashContext {
+        dir,
+        log_dir,
+        daemon_state,
+    });
+    CrashReporterGuard(())
+}
+
+/// Stops crash reporting when dropped; panics after that only reach the
+/// default hook.
+pub struct CrashReporterGuard(());
+
+impl Drop for CrashReporterGuard {
+    fn drop(&mut self) {
+        *CONTEXT.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+/// Spawn a background task that is restarted if it panics. `task` builds a
+/// fresh future for every run; a run that returns ends the task.
+pub fn spawn_supervised<F, Fut>(name: &'static str, task: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            let run = AssertUnwindSafe(TASK_NAME.scope(name, task()));
+            if run.catch_unwind().await.is_ok() {
+                return;
+            }
+            tracing::error!("Task {} panicked, restarting in {:?}", name, RESTART_DELAY);
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    })
+}
+
+fn write_panic_report(info: &PanicHookInfo<'_>) {
+    // Never block in the hook: the panic may have happened under this lock.
+    let Some(context) = CONTEXT.try_read().ok().and_then(|context| context.clone()) else {
+        return;
+    };
+
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string());
+    let sessions = context
+        .daemon_state
+        .try_lock()
+        .map(|state| {
+            state
+                .sessions
+                .values()
+                .map(|session| CrashSession {
+                    id: session.id.clone(),
+                    name: session.name.clone(),
+                    status: session.status.clone(),
+                    conversation_state: session.conversation_state.clone(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let recent_logs = context
+        .log_dir
+        .as_deref()
+        .and_then(|dir| crate::logs::recent(dir, RECENT_LOG_LINES, None).ok())
+        .unwrap_or_default();
+
+    let created_at = Utc::now();
+    let report = CrashReport {
+        id: format!(
+            "{}-{}",
+            created_at.format("%Y%m%dT%H%M%SZ"),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        ),
+        created_at,
+        daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+        pid: std::process::id(),
+        task: TASK_NAME.try_with(|name| name.to_string()).ok(),
+        thread: std::thread::current().name().map(str::to_string),
+        message,
+        location: info.location().map(|l| l.to_string()),
+        backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+        recent_logs,
+        sessions,
+    };
+
+    match save(&context.dir, &report) {
+        Ok(path) => tracing::error!(
+            "Panic{}: {} (crash report {})",
+            report.task.as_deref().map(|t| format!(" in task {}", t)).unwrap_or_default(),
+            report.message,
+            path.display()
+        ),
+        Err(e) => tracing::error!("Failed to write crash report: {}", e),
+    }
+}
+
+/// Write a report to `dir` as `<id>.json`, with secrets redacted.
+pub fn save(dir: &Path, report: &CrashReport) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", report.id));
+    let json = serde_json::to_string_pretty(report)?;
+    std::fs::write(&path, crate::redact::redact(&json).as_bytes())?;
+    Ok(path)
+}
+
+/// All reports in `dir`, newest first. Unreadable files are skipped.
+pub fn list(dir: &Path) -> std::io::Result<Vec<CrashReport>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut reports: Vec<CrashReport> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let bytes = std::fs::read(&path).ok()?;
+            serde_json::from_slice(&bytes)
+                .inspect_err(|e| tracing::warn!("Skipping crash report {}: {}", path.display(), e))
+                .ok()
+        })
+        .collect();
+    reports.sort_by_key(|report| std::cmp::Reverse(report.created_at));
+    Ok(reports)
+}
+
+/// The report with this id, if any.
+pub fn load(dir: &Path, id: &str) -> std::io::Result<Option<CrashReport>> {
+    let Some(path) = report_path(dir, id) else {
+        return Ok(None);
+    };
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Delete a report. Returns false if there was none with this id.
+pub fn delete(dir: &Path, id: &str) -> std::io::Result<bool> {
+    let Some(path) = report_path(dir, id) else {
+        return Ok(false);
+    };
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Path of a report, or `None` for an id that could escape `dir`.
+fn report_path(dir: &Path, id: &str) -> Option<PathBuf> {
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then(|| dir.join(format!("{}.json", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_supervised_task_panic_writes_report_and_restarts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let _guard = install(
+            dir.path().to_path_buf(),
+            None,
+            Arc::new(Mutex::new(DaemonState::default())),
+        );
+
+        let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let task_runs = runs.clone();
+        let handle = spawn_supervised("flaky", move || {
+            let runs = task_runs.clone();
+            async move {
+                if runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                    panic!("first run fails");
+                }
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("supervisor did not finish")
+            .unwrap();
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let reports = list(dir.path()).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.task.as_deref(), Some("flaky"));
+        assert_eq!(report.message, "first run fails");
+        assert!(report.location.as_deref().unwrap().contains("crash.rs"));
+
+        assert_eq!(load(dir.path(), &report.id).unwrap().unwrap().id, report.id);
+        assert!(load(dir.path(), "../state").unwrap().is_none());
+        assert!(delete(dir.path(), &report.id).unwrap());
+        assert!(!delete(dir.path(), &report.id).unwrap());
+    }
+}
@@ -0,0 +1,375 @@
+//! HTTP error taxonomy for the daemon API.
+//!
+//! Handlers return `Result<Json<DaemonResponse>, ApiError>`. Each error maps to
+//! an HTTP status and a machine-readable `ErrorCode`, serialized as a
+//! `DaemonResponse::Error` body so clients can match on the code instead of
+//! parsing message strings.
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::de::DeserializeOwned;
+
+use mado_core::protocol::{DaemonResponse, ErrorCode};
+
+use crate::attachments::AttachmentError;
+use crate::claude_history::HistoryError;
+use crate::compare::CompareError;
+use crate::conversation::ConversationError;
+use crate::files::FilesError;
+use crate::forge::ForgeError;
+use crate::git_ops::GitError;
+use crate::handover::HandoverError;
+use crate::process::ProcessError;
+use crate::prompts::PromptError;
+use crate::runs::RunError;
+use crate::sandbox::SandboxError;
+use crate::search::SearchError;
+use crate::session::SessionError;
+use crate::snapshot::SnapshotError;
+use crate::templates::TemplateError;
+use crate::workspace_clone::CloneError;
+
+/// Result type for API handlers.
+pub type ApiResult = Result<Json<DaemonResponse>, ApiError>;
+
+/// An error returned from an API handler.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The session does not exist (404).
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+
+    /// Some other resource does not exist (404).
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The request conflicts with current state (409).
+    #[error("{0}")]
+    Conflict(String),
+
+    /// The request is malformed or fails validation (422).
+    #[error("{0}")]
+    Validation(String),
+
+    /// The session's working directory is not a git repository (409).
+    #[error("Not a git repository: {0}")]
+    NotARepository(String),
+
+    /// Missing or invalid credentials (401).
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    /// A budget limit is exceeded and new messages are blocked (402).
+    #[error("{0}")]
+    BudgetExceeded(String),
+
+    /// Sessions may not run in this directory until it is trusted (403).
+    #[error("Workspace not trusted: {0}")]
+    WorkspaceUntrusted(String),
+
+    /// Anything else (500).
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    /// HTTP status for this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::SessionNotFound(_) | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) | ApiError::NotARepository(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::BudgetExceeded(_) => StatusCode::PAYMENT_REQUIRED,
+            ApiError::WorkspaceUntrusted(_) => StatusCode::FORBIDDEN,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine-readable error code.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::SessionNotFound(_) => ErrorCode::SessionNotFound,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::Validation(_) => ErrorCode::Validation,
+            ApiError::NotARepository(_) => ErrorCode::NotARepository,
+            ApiError::Unauthorized => ErrorCode::Unauthorized,
+            ApiError::BudgetExceeded(_) => ErrorCode::BudgetExceeded,
+            ApiError::WorkspaceUntrusted(_) => ErrorCode::WorkspaceUntrusted,
+            ApiError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("API error: {}", self);
+        }
+
+        let body = match self {
+            ApiError::NotARepository(path) => DaemonResponse::NotARepository { path },
+            other => DaemonResponse::Error {
+                code: other.code(),
+                message: other.to_string(),
+            },
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<ProcessError> for ApiError {
+    fn from(e: ProcessError) -> Self {
+        match e {
+            ProcessError::SessionNotFound(id) => ApiError::SessionNotFound(id),
+            ProcessError::InvalidModel(_) | ProcessError::InvalidCommand(_) | ProcessError::Cli(_) => {
+                ApiError::Validation(e.to_string())
+            }
+            ProcessError::Exited(_) => ApiError::Conflict(e.to_string()),
+            ProcessError::Sandbox(SandboxError::Unavailable(_) | SandboxError::Unsupported) => {
+                ApiError::Conflict(e.to_string())
+            }
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<TemplateError> for ApiError {
+    fn from(e: TemplateError) -> Self {
+        match e {
+            TemplateError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            TemplateError::InvalidName(_) => ApiError::Validation(e.to_string()),
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<PromptError> for ApiError {
+    fn from(e: PromptError) -> Self {
+        match e {
+            PromptError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            PromptError::InvalidName(_) | PromptError::Empty => ApiError::Validation(e.to_string()),
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(e: SessionError) -> Self {
+        match e {
+            SessionError::ProcessError(e) => e.into(),
+            SessionError::NotFound(id) => ApiError::SessionNotFound(id),
+            SessionError::AlreadyRunning(_) => ApiError::Conflict(e.to_string()),
+            SessionError::InvalidUpdate(_) | SessionError::NotADirectory(_) | SessionError::Env(_) => {
+                ApiError::Validation(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<ConversationError> for ApiError {
+    fn from(e: ConversationError) -> Self {
+        match e {
+            ConversationError::SessionNotFound(id) => ApiError::SessionNotFound(id),
+            ConversationError::NoActiveResponse
+            | ConversationError::Busy
+            | ConversationError::NothingToRetry
+            | ConversationError::NothingToCompact
+            | ConversationError::ReadOnlyCopyFailed(_)
+            | ConversationError::Diverged => {
+                ApiError::Conflict(e.to_string())
+            }
+            ConversationError::QueuedMessageNotFound(_) | ConversationError::MessageNotFound(_) => {
+                ApiError::NotFound(e.to_string())
+            }
+            ConversationError::TooManyContextFiles(_)
+            | ConversationError::NotAUserMessage(_)
+            | ConversationError::Cli(_)
+            | ConversationError::Env(_) => {
+                ApiError::Validation(e.to_string())
+            }
+            ConversationError::BudgetExceeded(_) => ApiError::BudgetExceeded(e.to_string()),
+            ConversationError::Sandbox(SandboxError::Unavailable(_) | SandboxError::Unsupported) => {
+                ApiError::Conflict(e.to_string())
+            }
+            ConversationError::ContextFile(FilesError::Io(_)) => ApiError::Internal(e.to_string()),
+            ConversationError::ContextFile(_) => ApiError::Validation(e.to_string()),
+            ConversationError::Attachment(AttachmentError::Io(_)) => ApiError::Internal(e.to_string()),
+            ConversationError::Attachment(_) => ApiError::Validation(e.to_string()),
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<GitError> for ApiError {
+    fn from(e: GitError) -> Self {
+        match &e {
+            GitError::Git(inner) => match inner.code() {
+                git2::ErrorCode::NotFound => ApiError::NotFound(e.to_string()),
+                git2::ErrorCode::Conflict
+                | git2::ErrorCode::MergeConflict
+                | git2::ErrorCode::Locked
+                | git2::ErrorCode::Modified
+                | git2::ErrorCode::Exists
+                | git2::ErrorCode::NotFastForward
+                | git2::ErrorCode::Unmerged
+                | git2::ErrorCode::UnbornBranch
+                | git2::ErrorCode::ApplyFail => ApiError::Conflict(e.to_string()),
+                git2::ErrorCode::InvalidSpec | git2::ErrorCode::Invalid => {
+                    ApiError::Validation(e.to_string())
+                }
+                _ => ApiError::Internal(e.to_string()),
+            },
+            GitError::NothingToCommit
+            | GitError::PushFailed(_)
+            | GitError::DetachedHead
+            | GitError::UncommittedChanges
+            | GitError::MergeConflict(_) => ApiError::Conflict(e.to_string()),
+            GitError::CommitNotFound(_) => ApiError::NotFound(e.to_string()),
+            GitError::PathError(_) => ApiError::Validation(e.to_string()),
+        }
+    }
+}
+
+impl From<HandoverError> for ApiError {
+    fn from(e: HandoverError) -> Self {
+        match e {
+            HandoverError::InProgress | HandoverError::Busy(_) => ApiError::Conflict(e.to_string()),
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<HistoryError> for ApiError {
+    fn from(e: HistoryError) -> Self {
+        match e {
+            HistoryError::ProjectNotFound(_) | HistoryError::SessionNotFound(_) => {
+                ApiError::NotFound(e.to_string())
+            }
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+impl From<AttachmentError> for ApiError {
+    fn from(e: AttachmentError) -> Self {
+        match &e {
+            AttachmentError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            AttachmentError::InvalidName(_) | AttachmentError::Empty => {
+                ApiError::Validation(e.to_string())
+            }
+            AttachmentError::Io(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<SnapshotError> for ApiError {
+    fn from(e: SnapshotError) -> Self {
+        match &e {
+            SnapshotError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ForgeError> for ApiError {
+    fn from(e: ForgeError) -> Self {
+        match &e {
+            ForgeError::NoRemote
+            | ForgeError::UnknownRemote(_)
+            | ForgeError::UnknownHost(_)
+            | ForgeError::NoToken(_) => ApiError::Validation(e.to_string()),
+            // Already exists, or the branch isn't on the forge.
+            ForgeError::Api { status: 409 | 422, .. } => ApiError::Conflict(e.to_string()),
+            ForgeError::Api { status: 404, .. } => ApiError::NotFound(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<RunError> for ApiError {
+    fn from(e: RunError) -> Self {
+        match &e {
+            RunError::UnknownCommand(_) | RunError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            RunError::Config(_) | RunError::Env(_) => ApiError::Validation(e.to_string()),
+            RunError::ReadOnly | RunError::Sandbox(SandboxError::Unavailable(_) | SandboxError::Unsupported) => {
+                ApiError::Conflict(e.to_string())
+            }
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<CompareError> for ApiError {
+    fn from(e: CompareError) -> Self {
+        match e {
+            CompareError::Variants(_) => ApiError::Validation(e.to_string()),
+            CompareError::NotFound(_) | CompareError::VariantNotFound(_) => ApiError::NotFound(e.to_string()),
+            CompareError::InProgress | CompareError::Applied | CompareError::Failed(_) => {
+                ApiError::Conflict(e.to_string())
+            }
+            CompareError::Conversation(inner) => inner.into(),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<CloneError> for ApiError {
+    fn from(e: CloneError) -> Self {
+        match e {
+            CloneError::TargetExists(_) => ApiError::Conflict(e.to_string()),
+            CloneError::InsideSource(_) => ApiError::Validation(e.to_string()),
+            CloneError::NotARepository(path) => ApiError::NotARepository(path),
+            CloneError::Io(_) | CloneError::Git(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<FilesError> for ApiError {
+    fn from(e: FilesError) -> Self {
+        match e {
+            FilesError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            FilesError::Changed { .. } => ApiError::Conflict(e.to_string()),
+            FilesError::OutsideWorkspace(_)
+            | FilesError::NotADirectory(_)
+            | FilesError::NotAFile(_)
+            | FilesError::NotText(_)
+            | FilesError::TooLarge(_) => ApiError::Validation(e.to_string()),
+            FilesError::Io(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(e: SearchError) -> Self {
+        ApiError::Validation(e.to_string())
+    }
+}
+
+/// JSON body extractor whose rejection is an `ApiError::Validation`.
+pub struct ApiJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(ApiJson(value)),
+            Err(rejection) => Err(rejection.into()),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::Validation(rejection.body_text())
+    }
+}
@@ -0,0 +1,410 @@
+//! Conversation export for `GET /sessions/{id}/export`.
+//!
+//! Renders a session's messages, with their tool calls, usage, costs and
+//! timestamps, as Markdown (to paste into PRs and docs), JSON (the session
+//! and its messages as the API returns them) or a self-contained HTML page.
+//! Message text is exported as typed; nothing is rendered as Markdown in the
+//! HTML page. Claude's thinking is left out unless asked for, and then shown
+//! collapsed.
+
+use std::borrow::Cow;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use mado_core::types::{ExportFormat, Message, MessageRole, Session, ToolCall, ToolCallStatus};
+
+/// What the JSON export contains.
+#[derive(Debug, Serialize)]
+struct ConversationExport<'a> {
+    exported_at: DateTime<Utc>,
+    session: &'a Session,
+    messages: &'a [Message],
+}
+
+/// `session`'s conversation in `format`, with Claude's thinking if
+/// `include_thinking`.
+pub fn render(
+    format: ExportFormat,
+    session: &Session,
+    messages: &[Message],
+    include_thinking: bool,
+    now: DateTime<Utc>,
+) -> String {
+    let messages: Cow<[Message]> = if include_thinking {
+        Cow::Borrowed(messages)
+    } else {
+        Cow::Owned(
+            messages
+                .iter()
+                .map(|m| Message {
+                    thinking: None,
+                    source: None,
+                    ..m.clone()
+                })
+                .collect(),
+        )
+    };
+    let messages = &*messages;
+    match format {
+        ExportFormat::Markdown => markdown(session, messages, now),
+        ExportFormat::Json => serde_json::to_string_pretty(&ConversationExport {
+            exported_at: now,
+            session,
+            messages,
+        })
+        .expect("conversation serializes"),
+        ExportFormat::Html => html(session, messages, now),
+    }
+}
+
+/// File name to save an export of `session` under, e.g. `my-session.md`.
+pub fn file_name(session: &Session, format: ExportFormat) -> String {
+    format!("{}.{}", file_stem(session), format.extension())
+}
+
+/// `session`'s name made safe for a file name.
+pub fn file_stem(session: &Session) -> String {
+    let stem: String = session
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    let stem = stem.trim_matches('-');
+    let stem = if stem.is_empty() { "conversation" } else { stem };
+    stem.to_string()
+}
+
+fn role_label(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "User",
+        MessageRole::Assistant => "Assistant",
+        MessageRole::System => "System",
+    }
+}
+
+fn status_label(status: &ToolCallStatus) -> &'static str {
+    match status {
+        ToolCallStatus::Running => "running",
+        ToolCallStatus::Completed => "completed",
+        ToolCallStatus::Failed => "failed",
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Tokens and cost of a message, e.g. `1200 in / 300 out · $0.0042`.
+fn usage_line(message: &Message) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(usage) = &message.usage {
+        parts.push(format!("{} in / {} out", usage.input_tokens, usage.output_tokens));
+    }
+    if let Some(cost) = message.cost_usd {
+        parts.push(format!("${:.4}", cost));
+    }
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Summary lines shown above the messages.
+fn summary(session: &Session, messages: &[Message], now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+    let mut lines = vec![("Model", session.model.clone())];
+    if let Some(dir) = &session.working_dir {
+        lines.push(("Working directory", dir.clone()));
+    }
+    lines.push(("Messages", messages.len().to_string()));
+    let (input, output) = messages
+        .iter()
+        .filter_map(|m| m.usage.as_ref())
+        .fold((0, 0), |(i, o), u| (i + u.input_tokens, o + u.output_tokens));
+    if input + output > 0 {
+        lines.push(("Tokens", format!("{} in / {} out", input, output)));
+    }
+    let cost: f64 = messages.iter().filter_map(|m| m.cost_usd).sum();
+    if cost > 0.0 {
+        lines.push(("Cost", format!("${:.4}", cost)));
+    }
+    lines.push(("Exported", timestamp(now)));
+    lines
+}
+
+/// A Markdown code fence longer than any backtick run in `text`.
+fn fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn code_block(out: &mut String, info: &str, text: &str) {
+    let fence = fence(text);
+    out.push_str(&format!("{}{}\n{}\n{}\n\n", fence, info, text.trim_end(), fence));
+}
+
+fn markdown(session: &Session, messages: &[Message], now: DateTime<Utc>) -> String {
+    let mut out = format!("# {}\n\n", session.name);
+    for (label, value) in summary(session, messages, now) {
+        out.push_str(&format!("- **{}:** {}\n", label, value));
+    }
+
+    for message in messages {
+        out.push_str(&format!(
+            "\n---\n\n### {} · {}\n\n",
+            role_label(&message.role),
+            timestamp(message.timestamp)
+        ));
+        if let Some(thinking) = &message.thinking {
+            out.push_str(&format!(
+                "<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n\n",
+                thinking.trim_end()
+            ));
+        }
+        if !message.content.is_empty() {
+            out.push_str(message.content.trim_end());
+            out.push_str("\n\n");
+        }
+        if !message.context_files.is_empty() {
+            let files: Vec<String> =
+                message.context_files.iter().map(|f| format!("`{}`", f.path)).collect();
+            out.push_str(&format!("**Context files:** {}\n\n", files.join(", ")));
+        }
+        if !message.attachments.is_empty() {
+            let names: Vec<&str> = message.attachments.iter().map(|a| a.name.as_str()).collect();
+            out.push_str(&format!("**Attachments:** {}\n\n", names.join(", ")));
+        }
+        for call in &message.tool_calls {
+            markdown_tool_call(&mut out, call);
+        }
+        if let Some(usage) = usage_line(message) {
+            out.push_str(&format!("*{}*\n", usage));
+        }
+    }
+    out
+}
+
+fn markdown_tool_call(out: &mut String, call: &ToolCall) {
+    out.push_str(&format!("**Tool: {}** ({})\n\n", call.name, status_label(&call.status)));
+    let input = serde_json::to_string_pretty(&call.input).unwrap_or_default();
+    code_block(out, "json", &input);
+    if let Some(output) = &call.output {
+        code_block(out, "", output);
+    }
+}
+
+/// Escape text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:50rem;margin:2rem auto;padding:0 1rem;color:#1f2328}\
+.message{border-top:1px solid #d0d7de;padding:1rem 0}\
+.meta{color:#656d76;font-size:.85rem}\
+pre{white-space:pre-wrap;word-wrap:break-word;background:#f6f8fa;padding:.75rem;border-radius:6px}\
+.content{white-space:pre-wrap}";
+
+fn html(session: &Session, messages: &[Message], now: DateTime<Utc>) -> String {
+    let title = escape(&session.name);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<ul>\n",
+        title, HTML_STYLE, title
+    );
+    for (label, value) in summary(session, messages, now) {
+        out.push_str(&format!("<li><strong>{}:</strong> {}</li>\n", label, escape(&value)));
+    }
+    out.push_str("</ul>\n");
+
+    for message in messages {
+        out.push_str(&format!(
+            "<section class=\"message {}\">\n<h3>{} <span class=\"meta\">{}</span></h3>\n",
+            role_label(&message.role).to_lowercase(),
+            role_label(&message.role),
+            timestamp(message.timestamp)
+        ));
+        if let Some(thinking) = &message.thinking {
+            out.push_str(&format!(
+                "<details class=\"thinking\">\n<summary>Thinking</summary>\n<div class=\"content\">{}</div>\n</details>\n",
+                escape(thinking.trim_end())
+            ));
+        }
+        if !message.content.is_empty() {
+            out.push_str(&format!(
+                "<div class=\"content\">{}</div>\n",
+                escape(message.content.trim_end())
+            ));
+        }
+        if !message.context_files.is_empty() {
+            let files: Vec<String> =
+                message.context_files.iter().map(|f| format!("<code>{}</code>", escape(&f.path))).collect();
+            out.push_str(&format!("<p><strong>Context files:</strong> {}</p>\n", files.join(", ")));
+        }
+        if !message.attachments.is_empty() {
+            let names: Vec<String> = message.attachments.iter().map(|a| escape(&a.name)).collect();
+            out.push_str(&format!("<p><strong>Attachments:</strong> {}</p>\n", names.join(", ")));
+        }
+        for call in &message.tool_calls {
+            let input = serde_json::to_string_pretty(&call.input).unwrap_or_default();
+            out.push_str(&format!(
+                "<details>\n<summary>Tool: {} ({})</summary>\n<pre>{}</pre>\n",
+                escape(&call.name),
+                status_label(&call.status),
+                escape(&input)
+            ));
+            if let Some(output) = &call.output {
+                out.push_str(&format!("<pre>{}</pre>\n", escape(output)));
+            }
+            out.push_str("</details>\n");
+        }
+        if let Some(usage) = usage_line(message) {
+            out.push_str(&format!("<p class=\"meta\">{}</p>\n", usage));
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mado_core::types::{
+        ConversationState, PermissionMode, SessionActivity, SessionId, SessionStatus, TokenUsage,
+    };
+
+    fn session() -> Session {
+        let now = Utc::now();
+        Session {
+            id: SessionId::new("s1"),
+            name: "Fix <login> bug".to_string(),
+            model: "sonnet".to_string(),
+            status: SessionStatus::Terminated,
+            created_at: now,
+            updated_at: now,
+            working_dir: Some("/work".to_string()),
+            repo_root: None,
+            command: None,
+            shell_fallback: false,
+            custom_command: None,
+            env: Default::default(),
+            activity: SessionActivity::Exited,
+            is_busy: false,
+            last_activity: None,
+            conversation_state: ConversationState::Idle,
+            claude_session_id: None,
+            message_count: 0,
+            total_usage: None,
+            total_cost_usd: None,
+            system_prompt: None,
+            permission_mode: PermissionMode::Ask,
+            read_only: false,
+            sandbox: Default::default(),
+            extended_thinking: false,
+            tags: Vec::new(),
+            color: None,
+            pinned: false,
+            notes: None,
+            mcp_config: Vec::new(),
+            claude_flags: Vec::new(),
+            auto_milestone: None,
+            exit_code: None,
+            branch: None,
+        }
+    }
+
+    fn messages() -> Vec<Message> {
+        let message = |role, content: &str| Message {
+            id: content.to_string(),
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            timestamp: DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().into(),
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: None,
+            compaction: None,
+        };
+        let mut reply = message(MessageRole::Assistant, "Use ```rust``` fences & <b>.");
+        reply.tool_calls.push(ToolCall {
+            id: "t1".to_string(),
+            name: "Read".to_string(),
+            input: serde_json::json!({ "file_path": "src/login.rs" }),
+            output: Some("fn login() {}".to_string()),
+            status: ToolCallStatus::Completed,
+            subagent: Vec::new(),
+        });
+        reply.usage = Some(TokenUsage {
+            input_tokens: 1200,
+            output_tokens: 300,
+            ..Default::default()
+        });
+        reply.cost_usd = Some(0.0042);
+        reply.thinking = Some("The session cookie <expires>.".to_string());
+        vec![message(MessageRole::User, "Why does login fail?"), reply]
+    }
+
+    #[test]
+    fn test_render() {
+        let now = Utc::now();
+        let (session, messages) = (session(), messages());
+
+        let md = render(ExportFormat::Markdown, &session, &messages, false, now);
+        assert!(md.starts_with("# Fix <login> bug\n"));
+        assert!(md.contains("- **Cost:** $0.0042\n"));
+        assert!(md.contains("### User · 2026-01-02 03:04:05 UTC\n\nWhy does login fail?\n"));
+        assert!(md.contains("**Tool: Read** (completed)\n\n```json\n"));
+        assert!(md.contains("*1200 in / 300 out · $0.0042*"));
+
+        assert!(!md.contains("Thinking"));
+
+        let html = render(ExportFormat::Html, &session, &messages, false, now);
+        assert!(html.contains("<title>Fix &lt;login&gt; bug</title>"));
+        assert!(html.contains("fences &amp; &lt;b&gt;."));
+        assert!(html.contains("<summary>Tool: Read (completed)</summary>"));
+        assert!(!html.contains("Thinking"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(ExportFormat::Json, &session, &messages, false, now)).unwrap();
+        assert_eq!(json["messages"][1]["tool_calls"][0]["name"], "Read");
+        assert!(json["messages"][1].get("thinking").is_none());
+        assert_eq!(json["session"]["name"], "Fix <login> bug");
+    }
+
+    #[test]
+    fn test_render_thinking() {
+        let now = Utc::now();
+        let (session, messages) = (session(), messages());
+
+        let md = render(ExportFormat::Markdown, &session, &messages, true, now);
+        assert!(md.contains("<details>\n<summary>Thinking</summary>\n\nThe session cookie <expires>.\n\n</details>\n"));
+
+        let html = render(ExportFormat::Html, &session, &messages, true, now);
+        assert!(html.contains("<summary>Thinking</summary>\n<div class=\"content\">The session cookie &lt;expires&gt;.</div>"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(ExportFormat::Json, &session, &messages, true, now)).unwrap();
+        assert_eq!(json["messages"][1]["thinking"], "The session cookie <expires>.");
+    }
+
+    #[test]
+    fn test_file_name_and_fence() {
+        assert_eq!(file_name(&session(), ExportFormat::Markdown), "Fix--login--bug.md");
+        assert_eq!(fence("no ticks"), "```");
+        assert_eq!(fence("a ```` b"), "`````");
+    }
+}
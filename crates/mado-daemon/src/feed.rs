@@ -0,0 +1,73 @@
+//! Daemon-wide activity feed.
+//!
+//! High-level events from every session (response finished, milestone saved,
+//! process exited, push done) on one stream, `GET /events`, so a client can
+//! badge inactive panes without holding a stream per session. Per-session
+//! detail stays on `/sessions/{id}/stream`.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+
+use mado_core::types::{ActivityEvent, ActivityKind, SessionId};
+
+use crate::replay::{ReplayChannel, ReplaySubscription, REPLAY_CAPACITY};
+
+/// Capacity of the channel for listeners inside the daemon.
+const LISTENER_CAPACITY: usize = 64;
+
+/// Broadcasts `ActivityEvent`s, replayable via `Last-Event-ID`.
+pub struct ActivityFeed {
+    channel: ReplayChannel<ActivityEvent>,
+    /// Listeners inside the daemon, kept apart from client subscribers.
+    listeners: broadcast::Sender<ActivityEvent>,
+}
+
+impl Default for ActivityFeed {
+    fn default() -> Self {
+        Self {
+            channel: ReplayChannel::new(REPLAY_CAPACITY),
+            listeners: broadcast::channel(LISTENER_CAPACITY).0,
+        }
+    }
+}
+
+impl ActivityFeed {
+    /// Publish an event for a session.
+    pub fn publish(&self, session_id: &SessionId, kind: ActivityKind) {
+        tracing::debug!("Activity for session {}: {:?}", session_id, kind);
+        let event = ActivityEvent {
+            session_id: session_id.clone(),
+            timestamp: Utc::now(),
+            kind,
+        };
+        let _ = self.listeners.send(event.clone());
+        self.channel.send(event);
+    }
+
+    /// Publish an event about the daemon rather than one session, with an
+    /// empty session id.
+    pub fn publish_daemon(&self, kind: ActivityKind) {
+        self.publish(&SessionId::new(String::new()), kind);
+    }
+
+    /// Listen from inside the daemon: live events only, not counted as a
+    /// subscriber.
+    pub fn listen(&self) -> broadcast::Receiver<ActivityEvent> {
+        self.listeners.subscribe()
+    }
+
+    /// Number of live subscribers.
+    pub fn receiver_count(&self) -> usize {
+        self.channel.receiver_count()
+    }
+
+    /// Subscribe, replaying events after `last_event_id`.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> ReplaySubscription<ActivityEvent> {
+        self.channel.subscribe(last_event_id)
+    }
+}
+
+/// Thread-safe handle to the activity feed.
+pub type SharedActivityFeed = Arc<ActivityFeed>;
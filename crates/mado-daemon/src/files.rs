@@ -0,0 +1,407 @@
+//! Workspace files for `/sessions/{id}/files`.
+//!
+//! Lists a directory of the session's working directory as a tree, skipping
+//! what `.gitignore` ignores (and `.git` itself) but showing other dotfiles.
+//! Each entry carries its git status when the workspace is in a repository;
+//! a directory is "modified" when anything below it changed.
+//!
+//! Single text files can be read and written. Every read returns an ETag
+//! derived from the file's modification time and size; a write carrying one
+//! fails with [`FilesError::Changed`] if the file changed since, so saving
+//! from the app never clobbers an edit Claude just made.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+
+use ignore::overrides::Override;
+use ignore::WalkBuilder;
+
+use mado_core::types::{FileEntry, FileKind};
+
+/// Most entries returned by one listing.
+const MAX_ENTRIES: usize = 5000;
+
+/// Largest file that can be read or written (5 MiB).
+pub const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Distinguishes temp files of concurrent writes.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Errors from listing files.
+#[derive(Debug, thiserror::Error)]
+pub enum FilesError {
+    #[error("Path is outside the workspace: {0}")]
+    OutsideWorkspace(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Not a directory: {0}")]
+    NotADirectory(String),
+
+    #[error("Not a file: {0}")]
+    NotAFile(String),
+
+    #[error("Not a UTF-8 text file: {0}")]
+    NotText(String),
+
+    #[error("File is larger than {max} bytes: {0}", max = MAX_FILE_BYTES)]
+    TooLarge(String),
+
+    #[error("File changed on disk since it was read: {path}")]
+    Changed { path: String, etag: Option<String> },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A directory listing.
+#[derive(Debug, Default)]
+pub struct FileTree {
+    pub entries: Vec<FileEntry>,
+    /// Listing stopped at the entry limit.
+    pub truncated: bool,
+}
+
+/// Resolve `relative` inside `root`, refusing anything (including a symlink)
+/// that leads outside it.
+pub fn resolve(root: &Path, relative: &str) -> Result<PathBuf, FilesError> {
+    let outside = || FilesError::OutsideWorkspace(relative.to_string());
+    if Path::new(relative)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    let root = root.canonicalize()?;
+    let path = root.join(relative);
+    let path = path.canonicalize().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => FilesError::NotFound(relative.to_string()),
+        _ => FilesError::Io(e),
+    })?;
+    if !path.starts_with(&root) {
+        return Err(outside());
+    }
+    Ok(path)
+}
+
+/// A text file and the ETag to write it back with.
+#[derive(Debug)]
+pub struct FileContent {
+    pub content: String,
+    pub etag: String,
+}
+
+/// ETag of a file: modification time in nanoseconds and size.
+fn etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    format!("{:x}-{:x}", modified, metadata.len())
+}
+
+/// Read a text file under `root`.
+pub fn read(root: &Path, relative: &str) -> Result<FileContent, FilesError> {
+    let path = resolve(root, relative)?;
+    let metadata = std::fs::metadata(&path)?;
+    if !metadata.is_file() {
+        return Err(FilesError::NotAFile(relative.to_string()));
+    }
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(FilesError::TooLarge(relative.to_string()));
+    }
+    let bytes = std::fs::read(&path)?;
+    let content =
+        String::from_utf8(bytes).map_err(|_| FilesError::NotText(relative.to_string()))?;
+    Ok(FileContent {
+        content,
+        etag: etag(&metadata),
+    })
+}
+
+/// Write a text file under `root`, creating it if needed, and return its new
+/// ETag. With `expected_etag` the write only happens if the file is unchanged
+/// since it was read; `Some("")` requires that it does not exist yet.
+pub fn write(
+    root: &Path,
+    relative: &str,
+    content: &str,
+    expected_etag: Option<&str>,
+) -> Result<String, FilesError> {
+    if content.len() as u64 > MAX_FILE_BYTES {
+        return Err(FilesError::TooLarge(relative.to_string()));
+    }
+    let relative_path = Path::new(relative);
+    let (Some(parent), Some(name)) = (relative_path.parent(), relative_path.file_name()) else {
+        return Err(FilesError::NotAFile(relative.to_string()));
+    };
+    let dir = resolve(root, &parent.to_string_lossy())?;
+    if !dir.is_dir() {
+        return Err(FilesError::NotADirectory(parent.to_string_lossy().to_string()));
+    }
+    let mut path = dir.join(name);
+
+    let existing = match std::fs::symlink_metadata(&path) {
+        Ok(_) => {
+            // Write through symlinks, as editors do, but only inside the workspace.
+            path = resolve(root, relative)?;
+            let metadata = std::fs::metadata(&path)?;
+            if !metadata.is_file() {
+                return Err(FilesError::NotAFile(relative.to_string()));
+            }
+            Some(metadata)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(expected) = expected_etag {
+        let current = existing.as_ref().map(etag);
+        if current.as_deref().unwrap_or("") != expected {
+            return Err(FilesError::Changed {
+                path: relative.to_string(),
+                etag: current,
+            });
+        }
+    }
+
+    let tmp_path = dir.join(format!(
+        ".{}.mado-{}-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        if let Some(metadata) = &existing {
+            file.set_permissions(std::fs::Permissions::from_mode(metadata.permissions().mode()))?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result?;
+    Ok(etag(&std::fs::metadata(&path)?))
+}
+
+/// List `relative` (a directory under `root`) down to `depth` levels,
+/// leaving out `ignored_paths` (globs relative to `root`).
+/// Directories come first, then files, each sorted by name.
+pub fn list(root: &Path, relative: &str, depth: usize, ignored_paths: &[String]) -> Result<FileTree, FilesError> {
+    let dir = resolve(root, relative)?;
+    if !dir.is_dir() {
+        return Err(FilesError::NotADirectory(relative.to_string()));
+    }
+    let root = root.canonicalize()?;
+
+    let git = crate::git_ops::discover_repo_root(&root).and_then(|repo_root| {
+        let repo_root = repo_root.canonicalize().ok()?;
+        let statuses = crate::git_ops::file_statuses(&repo_root)
+            .inspect_err(|e| tracing::debug!("No git status for file tree: {}", e))
+            .ok()?;
+        Some(GitStatuses::new(repo_root, statuses))
+    });
+
+    let ignored = crate::project_config::ignored(&root, ignored_paths);
+    let mut lister = Lister {
+        root: &root,
+        git: git.as_ref(),
+        ignored: ignored.as_ref(),
+        count: 0,
+        truncated: false,
+    };
+    let entries = lister.read_dir(&dir, depth.max(1));
+    Ok(FileTree {
+        entries,
+        truncated: lister.truncated,
+    })
+}
+
+struct Lister<'a> {
+    root: &'a Path,
+    git: Option<&'a GitStatuses>,
+    ignored: Option<&'a Override>,
+    count: usize,
+    truncated: bool,
+}
+
+impl Lister<'_> {
+    fn read_dir(&mut self, dir: &Path, depth: usize) -> Vec<FileEntry> {
+        let walker = WalkBuilder::new(dir)
+            .max_depth(Some(1))
+            .hidden(false)
+            .require_git(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+
+        let mut entries = Vec::new();
+        for entry in walker.filter_map(|entry| entry.ok()).filter(|entry| entry.depth() == 1) {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if crate::project_config::is_ignored(self.ignored, entry.path(), is_dir) {
+                continue;
+            }
+            if self.count >= MAX_ENTRIES {
+                self.truncated = true;
+                break;
+            }
+            self.count += 1;
+
+            let path = entry.path();
+            let file_type = entry.file_type();
+            let kind = match file_type {
+                Some(t) if t.is_symlink() => FileKind::Symlink,
+                Some(t) if t.is_dir() => FileKind::Directory,
+                _ => FileKind::File,
+            };
+            let size = (kind == FileKind::File)
+                .then(|| entry.metadata().ok().map(|m| m.len()))
+                .flatten();
+            entries.push(FileEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                path: path
+                    .strip_prefix(self.root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .to_string(),
+                kind,
+                size,
+                git_status: self.git.and_then(|git| git.status(path, kind)),
+                children: None,
+            });
+        }
+
+        entries.sort_by(|a, b| {
+            (a.kind != FileKind::Directory)
+                .cmp(&(b.kind != FileKind::Directory))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        if depth > 1 {
+            for entry in &mut entries {
+                if entry.kind == FileKind::Directory && !self.truncated {
+                    entry.children = Some(self.read_dir(&self.root.join(&entry.path), depth - 1));
+                }
+            }
+        }
+        entries
+    }
+}
+
+/// Git status of the files in a repository, and the directories above them.
+struct GitStatuses {
+    repo_root: PathBuf,
+    files: HashMap<String, String>,
+    changed_dirs: HashSet<PathBuf>,
+}
+
+impl GitStatuses {
+    fn new(repo_root: PathBuf, files: HashMap<String, String>) -> Self {
+        let changed_dirs = files
+            .keys()
+            .flat_map(|path| Path::new(path).ancestors().skip(1).map(Path::to_path_buf))
+            .collect();
+        Self {
+            repo_root,
+            files,
+            changed_dirs,
+        }
+    }
+
+    fn status(&self, path: &Path, kind: FileKind) -> Option<String> {
+        let relative = path.strip_prefix(&self.repo_root).ok()?;
+        if kind == FileKind::Directory {
+            return self.changed_dirs.contains(relative).then(|| "modified".to_string());
+        }
+        self.files.get(relative.to_str()?).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_tree_with_git_status() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        git2::Repository::init(root).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::create_dir(root.join("target")).unwrap();
+        std::fs::write(root.join("src/nested/lib.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("README.md"), "hello").unwrap();
+
+        let tree = list(root, "", 2, &[]).unwrap();
+        let names: Vec<&str> = tree.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["src", ".gitignore", "README.md"]);
+        assert!(!tree.truncated);
+
+        let src = &tree.entries[0];
+        assert_eq!(src.kind, FileKind::Directory);
+        assert_eq!(src.git_status.as_deref(), Some("modified"));
+        let children = src.children.as_ref().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].path, "src/nested");
+        assert!(children[0].children.is_none());
+
+        let readme = &tree.entries[2];
+        assert_eq!(readme.size, Some(5));
+        assert_eq!(readme.git_status.as_deref(), Some("added"));
+
+        let nested = list(root, "src/nested", 1, &[]).unwrap();
+        assert_eq!(nested.entries[0].path, "src/nested/lib.rs");
+
+        assert!(matches!(list(root, "../", 1, &[]), Err(FilesError::OutsideWorkspace(_))));
+        assert!(matches!(list(root, "/etc", 1, &[]), Err(FilesError::OutsideWorkspace(_))));
+        assert!(matches!(list(root, "missing", 1, &[]), Err(FilesError::NotFound(_))));
+        assert!(matches!(list(root, "README.md", 1, &[]), Err(FilesError::NotADirectory(_))));
+    }
+
+    #[test]
+    fn test_write_detects_changes_since_read() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+
+        // Creating a file that must not exist yet.
+        let etag = write(root, "src/main.rs", "v1", Some("")).unwrap();
+        assert!(matches!(
+            write(root, "src/main.rs", "v1 again", Some("")),
+            Err(FilesError::Changed { .. })
+        ));
+
+        let read_back = read(root, "src/main.rs").unwrap();
+        assert_eq!(read_back.content, "v1");
+        assert_eq!(read_back.etag, etag);
+
+        // Someone else edits the file after it was read.
+        std::fs::write(root.join("src/main.rs"), "edited elsewhere").unwrap();
+        match write(root, "src/main.rs", "v2", Some(&etag)) {
+            Err(FilesError::Changed { etag: current, .. }) => {
+                assert_eq!(current, Some(read(root, "src/main.rs").unwrap().etag));
+            }
+            other => panic!("Expected Changed, got: {:?}", other),
+        }
+        assert_eq!(read(root, "src/main.rs").unwrap().content, "edited elsewhere");
+
+        // Without an ETag the write is unconditional.
+        write(root, "src/main.rs", "v3", None).unwrap();
+        assert_eq!(read(root, "src/main.rs").unwrap().content, "v3");
+
+        std::fs::write(root.join("blob.bin"), [0xff, 0xfe]).unwrap();
+        assert!(matches!(read(root, "blob.bin"), Err(FilesError::NotText(_))));
+        assert!(matches!(read(root, "src"), Err(FilesError::NotAFile(_))));
+        assert!(matches!(
+            write(root, "../escape.txt", "x", None),
+            Err(FilesError::OutsideWorkspace(_))
+        ));
+    }
+}
@@ -0,0 +1,579 @@
+//! Pull requests on GitHub and merge requests on GitLab, for
+//! `/sessions/{id}/git/pull-request`.
+//!
+//! The forge is worked out from the `origin` remote's URL: github.com and
+//! gitlab.com are known, self-hosted instances are configured under
+//! `forges` in config.json. Tokens are kept in the keystore by host (see
+//! [`KeyStore::get_forge_token`]), falling back to `GITHUB_TOKEN` or
+//! `GITLAB_TOKEN`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls;
+
+use mado_core::client::encode_query_value;
+use mado_core::types::{Message, MessageRole, PullRequest};
+
+use crate::config::{ForgeConfig, ForgeKind};
+use crate::keystore::{KeyStore, KeyStoreError};
+
+/// How long a forge API request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest generated title, in characters.
+const MAX_TITLE_CHARS: usize = 72;
+
+/// Errors from talking to a forge.
+#[derive(Debug, thiserror::Error)]
+pub enum ForgeError {
+    #[error("No origin remote")]
+    NoRemote,
+
+    #[error("Can't tell the repository from remote URL {0:?}")]
+    UnknownRemote(String),
+
+    #[error("{0} is not a known forge; add it under `forges` in config.json")]
+    UnknownHost(String),
+
+    #[error("No token for {0}; add one in settings")]
+    NoToken(String),
+
+    #[error("Request to {0} failed: {1}")]
+    Request(String, String),
+
+    /// The forge refused the request.
+    #[error("{message}")]
+    Api { status: u16, message: String },
+
+    #[error("Unexpected response from forge: {0}")]
+    Response(String),
+
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+}
+
+/// A repository on a forge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forge {
+    pub kind: ForgeKind,
+    /// Host in the remote URL, which tokens are stored under.
+    pub host: String,
+    /// API root, without a trailing slash.
+    pub api_url: String,
+    /// `owner/repo` on GitHub, `group/subgroup/project` on GitLab.
+    pub project: String,
+}
+
+/// Reads a pull request from a forge's JSON.
+type ParseFn = fn(&Value) -> Result<PullRequest, ForgeError>;
+
+/// What to open a pull request with.
+#[derive(Debug, Clone)]
+pub struct NewPullRequest {
+    pub title: String,
+    pub body: String,
+    pub head: String,
+    /// The repository's default branch if unset.
+    pub base: Option<String>,
+    pub draft: bool,
+}
+
+/// URL of the repository at `root`'s `origin` remote.
+pub fn remote_url(root: &Path) -> Result<String, ForgeError> {
+    let repo = git2::Repository::open(root)?;
+    let remote = repo.find_remote("origin").map_err(|_| ForgeError::NoRemote)?;
+    remote.url().map(str::to_string).ok_or(ForgeError::NoRemote)
+}
+
+/// Host and repository path of a remote URL: `https://host/path.git`,
+/// `ssh://git@host:port/path.git` or `git@host:path.git`.
+pub fn parse_remote(url: &str) -> Option<(String, String)> {
+    let url = url.trim();
+    let (authority, path) = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?,
+        None => url.split_once(':')?,
+    };
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or(host);
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || !path.contains('/') {
+        return None;
+    }
+    Some((host.to_lowercase(), path.to_string()))
+}
+
+/// The forge hosting the repository at `remote`.
+pub fn resolve(remote: &str, forges: &HashMap<String, ForgeConfig>) -> Result<Forge, ForgeError> {
+    let (host, project) = parse_remote(remote).ok_or_else(|| ForgeError::UnknownRemote(remote.to_string()))?;
+    let (kind, api_url) = match forges.get(&host) {
+        Some(config) => (config.kind, config.api_url.clone()),
+        None if host == "github.com" => (ForgeKind::Github, None),
+        None if host == "gitlab.com" => (ForgeKind::Gitlab, None),
+        None => return Err(ForgeError::UnknownHost(host)),
+    };
+    let api_url = api_url.unwrap_or_else(|| match kind {
+        ForgeKind::Github if host == "github.com" => "https://api.github.com".to_string(),
+        ForgeKind::Github => format!("https://{}/api/v3", host),
+        ForgeKind::Gitlab => format!("https://{}/api/v4", host),
+    });
+    Ok(Forge {
+        kind,
+        host,
+        api_url: api_url.trim_end_matches('/').to_string(),
+        project,
+    })
+}
+
+/// The token for `forge`: from the keystore, else the environment.
+pub fn token(forge: &Forge) -> Result<String, ForgeError> {
+    match KeyStore::get_forge_token(&forge.host) {
+        Ok(token) => return Ok(token),
+        Err(KeyStoreError::NotFound) => {}
+        Err(e) => tracing::warn!("Failed to read {} token: {}", forge.host, e),
+    }
+    let var = match forge.kind {
+        ForgeKind::Github => "GITHUB_TOKEN",
+        ForgeKind::Gitlab => "GITLAB_TOKEN",
+    };
+    match std::env::var(var) {
+        Ok(token) if !token.is_empty() => {
+            crate::redact::remember(&token);
+            Ok(token)
+        }
+        _ => Err(ForgeError::NoToken(forge.host.clone())),
+    }
+}
+
+/// A title and body for a pull request made in a conversation: the first
+/// request as the title, and every request followed by Claude's last reply
+/// as the body. `None` if nothing was asked.
+pub fn describe(messages: &[Message]) -> Option<(String, String)> {
+    let requests: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == MessageRole::User && !m.content.starts_with('/'))
+        .filter_map(|m| m.content.lines().map(str::trim).find(|line| !line.is_empty()))
+        .collect();
+    let first = requests.first()?;
+    let title = if first.chars().count() > MAX_TITLE_CHARS {
+        let cut: String = first.chars().take(MAX_TITLE_CHARS - 1).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        first.to_string()
+    };
+
+    let mut body = String::from("## Requests\n\n");
+    for request in &requests {
+        body.push_str(&format!("- {}\n", request));
+    }
+    if let Some(reply) = messages
+        .iter()
+        .rev()
+        .find(|m| m.role == MessageRole::Assistant && !m.content.trim().is_empty())
+    {
+        body.push_str(&format!("\n## Summary\n\n{}\n", reply.content.trim()));
+    }
+    Some((title, body))
+}
+
+impl Forge {
+    /// Open a pull request.
+    pub async fn create(&self, token: &str, request: &NewPullRequest) -> Result<PullRequest, ForgeError> {
+        let base = match &request.base {
+            Some(base) => base.clone(),
+            None => self.default_branch(token).await?,
+        };
+        match self.kind {
+            ForgeKind::Github => {
+                let body = json!({
+                    "title": request.title,
+                    "body": request.body,
+                    "head": request.head,
+                    "base": base,
+                    "draft": request.draft,
+                });
+                let value = self.send("POST", &format!("/repos/{}/pulls", self.project), token, Some(body)).await?;
+                github_pull(&value)
+            }
+            ForgeKind::Gitlab => {
+                let title = if request.draft {
+                    format!("Draft: {}", request.title)
+                } else {
+                    request.title.clone()
+                };
+                let body = json!({
+                    "title": title,
+                    "description": request.body,
+                    "source_branch": request.head,
+                    "target_branch": base,
+                });
+                let path = format!("/projects/{}/merge_requests", encode_query_value(&self.project));
+                let value = self.send("POST", &path, token, Some(body)).await?;
+                gitlab_merge_request(&value)
+            }
+        }
+    }
+
+    /// Pull requests from `branch`, newest first.
+    pub async fn list(&self, token: &str, branch: &str) -> Result<Vec<PullRequest>, ForgeError> {
+        let (path, parse): (String, ParseFn) = match self.kind {
+            ForgeKind::Github => {
+                let owner = self.project.split('/').next().unwrap_or_default();
+                (
+                    format!(
+                        "/repos/{}/pulls?state=all&per_page=100&head={}",
+                        self.project,
+                        encode_query_value(&format!("{}:{}", owner, branch))
+                    ),
+                    github_pull,
+                )
+            }
+            ForgeKind::Gitlab => (
+                format!(
+                    "/projects/{}/merge_requests?per_page=100&source_branch={}",
+                    encode_query_value(&self.project),
+                    encode_query_value(branch)
+                ),
+                gitlab_merge_request,
+            ),
+        };
+        let value = self.send("GET", &path, token, None).await?;
+        value
+            .as_array()
+            .ok_or_else(|| ForgeError::Response("expected a list".to_string()))?
+            .iter()
+            .map(parse)
+            .collect()
+    }
+
+    async fn default_branch(&self, token: &str) -> Result<String, ForgeError> {
+        let path = match self.kind {
+            ForgeKind::Github => format!("/repos/{}", self.project),
+            ForgeKind::Gitlab => format!("/projects/{}", encode_query_value(&self.project)),
+        };
+        let value = self.send("GET", &path, token, None).await?;
+        value["default_branch"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| ForgeError::Response("no default branch".to_string()))
+    }
+
+    async fn send(&self, method: &str, path: &str, token: &str, body: Option<Value>) -> Result<Value, ForgeError> {
+        let url = format!("{}{}", self.api_url, path);
+        let auth = match self.kind {
+            ForgeKind::Github => ("Authorization", format!("Bearer {}", token)),
+            ForgeKind::Gitlab => ("PRIVATE-TOKEN", token.to_string()),
+        };
+        let (status, bytes) = tokio::time::timeout(REQUEST_TIMEOUT, request(method, &url, auth, body))
+            .await
+            .map_err(|_| ForgeError::Request(self.host.clone(), "timed out".to_string()))?
+            .map_err(|e| ForgeError::Request(self.host.clone(), e))?;
+        let value: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        if !(200..300).contains(&status) {
+            return Err(ForgeError::Api {
+                status,
+                message: api_message(&value).unwrap_or_else(|| format!("{} answered {}", self.host, status)),
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// Send one request, returning the status and body.
+async fn request(method: &str, url: &str, auth: (&str, String), body: Option<Value>) -> Result<(u16, Bytes), String> {
+    let uri: hyper::Uri = url.parse().map_err(|e| format!("invalid URL {}: {}", url, e))?;
+    let tls = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err(format!("invalid URL {}", url)),
+    };
+    let host = uri.host().ok_or_else(|| format!("invalid URL {}", url))?.to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let authority = uri.authority().map(|a| a.to_string()).unwrap_or_else(|| host.clone());
+
+    let mut builder = hyper::Request::builder()
+        .method(method)
+        .uri(uri.path_and_query().map_or("/", |p| p.as_str()))
+        .header("Host", authority)
+        .header("User-Agent", concat!("mado/", env!("CARGO_PKG_VERSION")))
+        .header("Accept", "application/json")
+        .header(auth.0, auth.1);
+    if body.is_some() {
+        builder = builder.header("Content-Type", "application/json");
+    }
+    let bytes = body.map(|b| b.to_string()).unwrap_or_default();
+    let req = builder.body(Full::new(Bytes::from(bytes))).map_err(|e| e.to_string())?;
+
+    let tcp = TcpStream::connect((host.as_str(), port)).await.map_err(|e| e.to_string())?;
+    if !tls {
+        return exchange(tcp, req).await;
+    }
+    let name = rustls::pki_types::ServerName::try_from(host).map_err(|e| e.to_string())?;
+    let stream = TlsConnector::from(tls_config()?)
+        .connect(name, tcp)
+        .await
+        .map_err(|e| e.to_string())?;
+    exchange(stream, req).await
+}
+
+async fn exchange(
+    io: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    req: hyper::Request<Full<Bytes>>,
+) -> Result<(u16, Bytes), String> {
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(io))
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            tracing::debug!("Forge connection error: {}", e);
+        }
+    });
+    let response = sender.send_request(req).await.map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.map_err(|e| e.to_string())?;
+    Ok((status, body.to_bytes()))
+}
+
+/// Trusting the webpki roots.
+fn tls_config() -> Result<Arc<rustls::ClientConfig>, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// The message of a forge's error body. GitHub sends `message` with details
+/// in `errors`; GitLab sends `message` as a string, list or map.
+fn api_message(value: &Value) -> Option<String> {
+    let mut parts = Vec::new();
+    match &value["message"] {
+        Value::String(message) => parts.push(message.clone()),
+        Value::Array(messages) => parts.extend(messages.iter().filter_map(|m| m.as_str()).map(str::to_string)),
+        Value::Object(fields) => {
+            for (field, messages) in fields {
+                parts.push(format!("{} {}", field, messages));
+            }
+        }
+        _ => {}
+    }
+    if let Some(errors) = value["errors"].as_array() {
+        parts.extend(errors.iter().filter_map(|e| e["message"].as_str()).map(str::to_string));
+    }
+    (!parts.is_empty()).then(|| parts.join(": "))
+}
+
+fn github_pull(value: &Value) -> Result<PullRequest, ForgeError> {
+    let state = if !value["merged_at"].is_null() {
+        "merged"
+    } else {
+        value["state"].as_str().unwrap_or("open")
+    };
+    Ok(PullRequest {
+        number: value["number"].as_u64().ok_or_else(|| ForgeError::Response("no number".to_string()))?,
+        title: value["title"].as_str().unwrap_or_default().to_string(),
+        url: value["html_url"].as_str().unwrap_or_default().to_string(),
+        state: state.to_string(),
+        draft: value["draft"].as_bool().unwrap_or(false),
+        head: value["head"]["ref"].as_str().unwrap_or_default().to_string(),
+        base: value["base"]["ref"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn gitlab_merge_request(value: &Value) -> Result<PullRequest, ForgeError> {
+    let state = match value["state"].as_str().unwrap_or("opened") {
+        "opened" => "open",
+        "merged" => "merged",
+        _ => "closed",
+    };
+    Ok(PullRequest {
+        number: value["iid"].as_u64().ok_or_else(|| ForgeError::Response("no iid".to_string()))?,
+        title: value["title"].as_str().unwrap_or_default().to_string(),
+        url: value["web_url"].as_str().unwrap_or_default().to_string(),
+        state: state.to_string(),
+        draft: value["draft"].as_bool().or_else(|| value["work_in_progress"].as_bool()).unwrap_or(false),
+        head: value["source_branch"].as_str().unwrap_or_default().to_string(),
+        base: value["target_branch"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::extract::{Json, Query};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use chrono::Utc;
+
+    #[test]
+    fn test_parse_and_resolve() {
+        let project = |url| parse_remote(url).map(|(_, path)| path);
+        assert_eq!(
+            parse_remote("git@github.com:tensakulabs/mado.git"),
+            Some(("github.com".to_string(), "tensakulabs/mado".to_string()))
+        );
+        assert_eq!(project("https://github.com/tensakulabs/mado").as_deref(), Some("tensakulabs/mado"));
+        assert_eq!(
+            parse_remote("ssh://git@git.example.com:2222/group/sub/app.git/"),
+            Some(("git.example.com".to_string(), "group/sub/app".to_string()))
+        );
+        assert_eq!(parse_remote("/srv/repos/app.git"), None);
+
+        let forges = HashMap::from([(
+            "git.example.com".to_string(),
+            ForgeConfig {
+                kind: ForgeKind::Gitlab,
+                api_url: None,
+            },
+        )]);
+        let github = resolve("git@github.com:o/r.git", &forges).unwrap();
+        assert_eq!((github.kind, github.api_url.as_str()), (ForgeKind::Github, "https://api.github.com"));
+        let gitlab = resolve("https://git.example.com/group/sub/app.git", &forges).unwrap();
+        assert_eq!(gitlab.api_url, "https://git.example.com/api/v4");
+        assert_eq!(gitlab.project, "group/sub/app");
+        assert!(matches!(resolve("https://example.org/o/r", &forges), Err(ForgeError::UnknownHost(_))));
+    }
+
+    #[test]
+    fn test_describe() {
+        let message = |role, content: &str| Message {
+            id: content.to_string(),
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            timestamp: Utc::now(),
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: None,
+            compaction: None,
+        };
+        assert_eq!(describe(&[]), None);
+        let (title, body) = describe(&[
+            message(MessageRole::User, "\nAdd a --verbose flag\nto the CLI"),
+            message(MessageRole::Assistant, "Added it."),
+            message(MessageRole::User, "/cost"),
+            message(MessageRole::User, "Document it too"),
+            message(MessageRole::Assistant, "Added the flag and documented it in the README."),
+        ])
+        .unwrap();
+        assert_eq!(title, "Add a --verbose flag");
+        assert_eq!(
+            body,
+            "## Requests\n\n- Add a --verbose flag\n- Document it too\n\n## Summary\n\nAdded the flag and documented it in the README.\n"
+        );
+
+        let (title, _) = describe(&[message(MessageRole::User, &"word ".repeat(30))]).unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+        assert!(title.ends_with('…'));
+    }
+
+    #[test]
+    fn test_api_message() {
+        let github = json!({"message": "Validation Failed", "errors": [{"message": "A pull request already exists for o:feature."}]});
+        assert_eq!(
+            api_message(&github).unwrap(),
+            "Validation Failed: A pull request already exists for o:feature."
+        );
+        assert_eq!(api_message(&json!({"message": ["Another open merge request already exists"]})).unwrap(), "Another open merge request already exists");
+        assert_eq!(api_message(&json!({})), None);
+    }
+
+    /// A stand-in for GitHub's API that checks the token.
+    async fn fake_github() -> String {
+        let pull = |number: u64, head: &str| {
+            json!({
+                "number": number,
+                "title": "Add a flag",
+                "html_url": format!("https://github.com/o/r/pull/{}", number),
+                "state": "open",
+                "merged_at": null,
+                "draft": false,
+                "head": {"ref": head},
+                "base": {"ref": "main"},
+            })
+        };
+        let authorized = |headers: &HeaderMap| headers.get("authorization").is_some_and(|v| v == "Bearer t0ken");
+        let app = axum::Router::new()
+            .route(
+                "/repos/o/r",
+                get(move |headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        return (StatusCode::UNAUTHORIZED, Json(json!({"message": "Bad credentials"})));
+                    }
+                    (StatusCode::OK, Json(json!({"default_branch": "main"})))
+                }),
+            )
+            .route(
+                "/repos/o/r/pulls",
+                get(move |Query(query): Query<HashMap<String, String>>| async move {
+                    let head = query.get("head").cloned().unwrap_or_default();
+                    let branch = head.strip_prefix("o:").unwrap_or_default().to_string();
+                    Json(json!([pull(7, &branch)]))
+                })
+                .post(move |headers: HeaderMap, Json(body): Json<Value>| async move {
+                    if !authorized(&headers) {
+                        return (StatusCode::UNAUTHORIZED, Json(json!({"message": "Bad credentials"})));
+                    }
+                    let mut created = pull(8, body["head"].as_str().unwrap());
+                    created["title"] = body["title"].clone();
+                    created["base"]["ref"] = body["base"].clone();
+                    (StatusCode::CREATED, Json(created))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_github_create_and_list() {
+        let forge = Forge {
+            kind: ForgeKind::Github,
+            host: "github.com".to_string(),
+            api_url: fake_github().await,
+            project: "o/r".to_string(),
+        };
+        let request = NewPullRequest {
+            title: "Add a --verbose flag".to_string(),
+            body: "Adds it.".to_string(),
+            head: "feature/verbose".to_string(),
+            base: None,
+            draft: false,
+        };
+        let created = forge.create("t0ken", &request).await.unwrap();
+        assert_eq!(created.number, 8);
+        assert_eq!(created.title, "Add a --verbose flag");
+        assert_eq!((created.head.as_str(), created.base.as_str()), ("feature/verbose", "main"));
+
+        let listed = forge.list("t0ken", "feature/verbose").await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].head, "feature/verbose");
+        assert_eq!(listed[0].url, "https://github.com/o/r/pull/7");
+
+        match forge.create("wrong", &request).await {
+            Err(ForgeError::Api { status, message }) => {
+                assert_eq!(status, 401);
+                assert_eq!(message, "Bad credentials");
+            }
+            other => panic!("Expected an API error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Gemini CLI chat logs.
+//!
+//! The Gemini CLI keeps each project's data in
+//! `~/.gemini/tmp/<sha256 of the project directory>/`: full chats in
+//! `chats/session-*.json` (recent versions), and every prompt typed in
+//! `logs.json`. Chats are imported when there are any; otherwise the
+//! prompts are, as one session.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use mado_core::types::{HistorySource, Message, MessageRole, ToolCall, ToolCallStatus};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::claude_history::HistoryError;
+use crate::history_import::{join_text, message_id, HistoryImporter};
+
+/// Imports Gemini CLI chats and prompt logs.
+pub struct GeminiImporter;
+
+impl HistoryImporter for GeminiImporter {
+    fn source(&self) -> HistorySource {
+        HistorySource::Gemini
+    }
+
+    fn sessions(&self, working_dir: &Path) -> Vec<PathBuf> {
+        match dirs::home_dir() {
+            Some(home) => sessions(&project_dir(&home.join(".gemini").join("tmp"), working_dir)),
+            None => Vec::new(),
+        }
+    }
+
+    fn parse(&self, session: &Path) -> Result<Vec<Message>, HistoryError> {
+        let contents: Value = serde_json::from_str(&std::fs::read_to_string(session)?)?;
+        Ok(match contents {
+            // logs.json: prompts only.
+            Value::Array(entries) => parse_log(session, &entries),
+            chat => parse_chat(session, &chat),
+        })
+    }
+}
+
+/// The Gemini CLI's directory for a project, under `tmp`.
+fn project_dir(tmp: &Path, working_dir: &Path) -> PathBuf {
+    let hash = Sha256::digest(working_dir.to_string_lossy().as_bytes());
+    let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    tmp.join(hex)
+}
+
+/// Chats in `dir`, newest first, or its prompt log if there are none.
+fn sessions(dir: &Path) -> Vec<PathBuf> {
+    let mut chats: Vec<PathBuf> = std::fs::read_dir(dir.join("chats"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    if chats.is_empty() {
+        let log = dir.join("logs.json");
+        return if log.is_file() { vec![log] } else { Vec::new() };
+    }
+    chats.sort_by_key(|path| std::cmp::Reverse(std::fs::metadata(path).and_then(|m| m.modified()).ok()));
+    chats
+}
+
+fn timestamp(value: &Value) -> DateTime<Utc> {
+    value
+        .as_str()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map_or_else(Utc::now, |t| t.with_timezone(&Utc))
+}
+
+fn message(session: &Path, index: usize, role: MessageRole, content: String, timestamp: DateTime<Utc>) -> Message {
+    Message {
+        id: message_id(HistorySource::Gemini, session, index),
+        role,
+        content,
+        tool_calls: Vec::new(),
+        timestamp,
+        usage: None,
+        cost_usd: None,
+        context_files: Vec::new(),
+        attachments: Vec::new(),
+        thinking: None,
+        source: Some(HistorySource::Gemini),
+        compaction: None,
+    }
+}
+
+/// Messages of a recorded chat; info and error entries are left out.
+fn parse_chat(session: &Path, chat: &Value) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for entry in chat["messages"].as_array().into_iter().flatten() {
+        let role = match entry["type"].as_str() {
+            Some("user") => MessageRole::User,
+            Some("gemini") => MessageRole::Assistant,
+            _ => continue,
+        };
+        let mut message = message(session, messages.len(), role, join_text(&entry["content"]), timestamp(&entry["timestamp"]));
+        for call in entry["toolCalls"].as_array().into_iter().flatten() {
+            message.tool_calls.push(ToolCall {
+                id: call["id"].as_str().unwrap_or_default().to_string(),
+                name: call["name"].as_str().unwrap_or("tool").to_string(),
+                input: call["args"].clone(),
+                output: None,
+                status: if call["status"] == "error" {
+                    ToolCallStatus::Failed
+                } else {
+                    ToolCallStatus::Completed
+                },
+                subagent: Vec::new(),
+            });
+        }
+        if !message.content.trim().is_empty() || !message.tool_calls.is_empty() {
+            messages.push(message);
+        }
+    }
+    messages
+}
+
+/// The prompts in `logs.json`.
+fn parse_log(session: &Path, entries: &[Value]) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for entry in entries.iter().filter(|entry| entry["type"] == "user") {
+        let Some(text) = entry["message"].as_str().filter(|text| !text.trim().is_empty()) else {
+            continue;
+        };
+        messages.push(message(session, messages.len(), MessageRole::User, text.to_string(), timestamp(&entry["timestamp"])));
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_and_parse() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = project_dir(tmp.path(), Path::new("/repo"));
+        assert!(dir.ends_with("816fc349d3faebf805d1bed70fce7e14754cad5251c77dda31c414ee961a0bdd"));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(sessions(&dir).is_empty());
+
+        let log = serde_json::json!([
+            {"sessionId": "a", "messageId": 0, "type": "user", "message": "Explain main.rs", "timestamp": "2025-07-01T10:00:00Z"},
+            {"sessionId": "a", "messageId": 1, "type": "user", "message": "/quit", "timestamp": "2025-07-01T10:01:00Z"},
+        ]);
+        std::fs::write(dir.join("logs.json"), log.to_string()).unwrap();
+        let importer = GeminiImporter;
+        assert_eq!(sessions(&dir), [dir.join("logs.json")]);
+        let prompts = importer.parse(&dir.join("logs.json")).unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert_eq!(prompts[0].role, MessageRole::User);
+
+        let chat = serde_json::json!({
+            "sessionId": "a",
+            "messages": [
+                {"id": "1", "timestamp": "2025-07-01T10:00:00Z", "type": "user", "content": "Explain main.rs"},
+                {"id": "2", "timestamp": "2025-07-01T10:00:05Z", "type": "gemini", "content": "It starts the server.",
+                 "toolCalls": [{"id": "t1", "name": "read_file", "args": {"path": "main.rs"}, "status": "success"}]},
+                {"id": "3", "timestamp": "2025-07-01T10:00:06Z", "type": "info", "content": "Saved."},
+            ],
+        });
+        std::fs::create_dir(dir.join("chats")).unwrap();
+        let chat_path = dir.join("chats").join("session-2025-07-01T10-00-a.json");
+        std::fs::write(&chat_path, chat.to_string()).unwrap();
+        assert_eq!(sessions(&dir), vec![chat_path.clone()]);
+
+        let messages = importer.parse(&chat_path).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].role, MessageRole::Assistant);
+        assert_eq!(messages[1].tool_calls[0].input["path"], "main.rs");
+        assert_eq!(messages[1].source, Some(HistorySource::Gemini));
+    }
+}
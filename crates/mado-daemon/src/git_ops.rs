@@ -1,7 +1,8 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, TimeZone, Utc};
 use git2::{DiffOptions, Repository, Signature, StatusOptions};
+use mado_core::types::SessionBranch;
 use serde::{Deserialize, Serialize};
 use tracing;
 
@@ -39,6 +40,25 @@ pub struct FileDiff {
     pub status: String, // "added", "modified", "deleted", "renamed"
 }
 
+impl From<DiffSummary> for mado_core::types::DiffSummary {
+    fn from(diff: DiffSummary) -> Self {
+        Self {
+            files: diff
+                .files
+                .into_iter()
+                .map(|f| mado_core::types::FileDiff {
+                    path: f.path,
+                    insertions: f.insertions,
+                    deletions: f.deletions,
+                    status: f.status,
+                })
+                .collect(),
+            total_insertions: diff.total_insertions,
+            total_deletions: diff.total_deletions,
+        }
+    }
+}
+
 /// Errors from git operations.
 #[derive(Debug, thiserror::Error)]
 pub enum GitError {
@@ -53,12 +73,86 @@ pub enum GitError {
 
     #[error("Path error: {0}")]
     PathError(String),
+
+    #[error("Push failed: {0}")]
+    PushFailed(String),
+
+    #[error("HEAD is not on a branch")]
+    DetachedHead,
+
+    #[error("Uncommitted changes; save a milestone first")]
+    UncommittedChanges,
+
+    #[error("Merge conflicts in: {0}")]
+    MergeConflict(String),
+}
+
+/// Directories never descended into when scanning a workspace for repos.
+const REPO_SCAN_SKIP_DIRS: &[&str] = &["node_modules", "target", "vendor", "dist", "build"];
+
+/// Maximum directory depth below the workspace scanned for nested repos.
+const REPO_SCAN_MAX_DEPTH: usize = 3;
+
+/// Find the root (working directory) of the repository containing `path`.
+///
+/// Walks up parent directories looking for `.git`, like `git rev-parse --show-toplevel`.
+/// Returns `None` if `path` is not inside a non-bare repository.
+pub fn discover_repo_root(path: &Path) -> Option<PathBuf> {
+    let repo = Repository::discover(path).ok()?;
+    let workdir = repo.workdir()?;
+    // git2 returns the workdir with a trailing slash; normalize it.
+    Some(workdir.components().collect())
+}
+
+/// List the roots of all repositories in a workspace.
+///
+/// Includes the repo enclosing `path` (if any) plus repos nested below it, up to
+/// `REPO_SCAN_MAX_DEPTH` levels deep. Hidden directories and common build/dependency
+/// directories are skipped. Results are sorted and deduplicated.
+pub fn find_repos(path: &Path) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    if let Some(root) = discover_repo_root(path) {
+        repos.push(root);
+    }
+    scan_for_repos(path, 0, &mut repos);
+    repos.sort();
+    repos.dedup();
+    repos
+}
+
+fn scan_for_repos(dir: &Path, depth: usize, repos: &mut Vec<PathBuf>) {
+    if depth >= REPO_SCAN_MAX_DEPTH {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if !file_type.is_dir() {
+            continue;
+        }
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || REPO_SCAN_SKIP_DIRS.contains(&name.as_ref()) {
+            continue;
+        }
+        let child = entry.path();
+        if child.join(".git").exists() {
+            repos.push(child.clone());
+        }
+        scan_for_repos(&child, depth + 1, repos);
+    }
 }
 
-/// Initialize a git repository at the given path if one doesn't exist.
+/// Open the repository containing `path`, or initialize one at `path` if none exists.
+///
+/// A path inside an existing repo opens that repo rather than creating a nested one.
 pub fn init_repo(path: &Path) -> Result<Repository, GitError> {
-    if path.join(".git").exists() {
-        Ok(Repository::open(path)?)
+    if let Some(root) = discover_repo_root(path) {
+        Ok(Repository::open(root)?)
     } else {
         tracing::info!("Initializing git repo at: {}", path.display());
         let repo = Repository::init(path)?;
@@ -210,7 +304,11 @@ pub fn diff_milestones(
         Some(&to_commit.tree()?),
         Some(&mut diff_opts),
     )?;
+    summarize_diff(&diff)
+}
 
+/// Files and line counts of a tree-to-tree diff.
+fn summarize_diff(diff: &git2::Diff) -> Result<DiffSummary, GitError> {
     // Use diff stats and print callback approach to avoid borrow issues.
     let stats = diff.stats()?;
     let total_insertions = stats.insertions();
@@ -246,12 +344,10 @@ pub fn diff_milestones(
 
     // Get per-file line stats by iterating patches.
     for (i, file) in files.iter_mut().enumerate() {
-        if let Ok(patch) = git2::Patch::from_diff(&diff, i) {
-            if let Some(patch) = patch {
-                let (_, additions, deletions) = patch.line_stats().unwrap_or((0, 0, 0));
-                file.insertions = additions;
-                file.deletions = deletions;
-            }
+        if let Ok(Some(patch)) = git2::Patch::from_diff(diff, i) {
+            let (_, additions, deletions) = patch.line_stats().unwrap_or((0, 0, 0));
+            file.insertions = additions;
+            file.deletions = deletions;
         }
     }
 
@@ -329,12 +425,10 @@ pub fn workspace_changes(path: &Path) -> Result<DiffSummary, GitError> {
 
     // Get per-file line stats.
     for (i, file) in files.iter_mut().enumerate() {
-        if let Ok(patch) = git2::Patch::from_diff(&diff, i) {
-            if let Some(patch) = patch {
-                let (_, additions, deletions) = patch.line_stats().unwrap_or((0, 0, 0));
-                file.insertions = additions;
-                file.deletions = deletions;
-            }
+        if let Ok(Some(patch)) = git2::Patch::from_diff(&diff, i) {
+            let (_, additions, deletions) = patch.line_stats().unwrap_or((0, 0, 0));
+            file.insertions = additions;
+            file.deletions = deletions;
         }
     }
 
@@ -345,6 +439,27 @@ pub fn workspace_changes(path: &Path) -> Result<DiffSummary, GitError> {
     })
 }
 
+/// Snapshot the working directory, untracked files included, as a tree
+/// object. The index on disk is left alone. Returns the tree's OID, to pass
+/// to [`diff_snapshots`].
+pub fn snapshot_workdir(path: &Path) -> Result<String, GitError> {
+    let repo = Repository::open(path)?;
+    let mut index = repo.index()?;
+    index.add_all(["."], git2::IndexAddOption::DEFAULT, None)?;
+    // Drop files deleted since the index was written.
+    index.update_all(["."], None)?;
+    Ok(index.write_tree()?.to_string())
+}
+
+/// Changes between two snapshots taken with [`snapshot_workdir`].
+pub fn diff_snapshots(path: &Path, from_tree: &str, to_tree: &str) -> Result<DiffSummary, GitError> {
+    let repo = Repository::open(path)?;
+    let from = repo.find_tree(git2::Oid::from_str(from_tree)?)?;
+    let to = repo.find_tree(git2::Oid::from_str(to_tree)?)?;
+    let diff = repo.diff_tree_to_tree(Some(&from), Some(&to), None)?;
+    summarize_diff(&diff)
+}
+
 /// Git staging status: staged and unstaged files separately.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitStatus {
@@ -495,6 +610,38 @@ pub fn git_status(path: &Path) -> Result<GitStatus, GitError> {
     Ok(GitStatus { staged, unstaged })
 }
 
+/// Status of every changed or untracked file, staged or not, keyed by path
+/// relative to the repository root.
+pub fn file_statuses(path: &Path) -> Result<std::collections::HashMap<String, String>, GitError> {
+    let repo = Repository::open(path)?;
+
+    let mut status_opts = StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true);
+
+    let mut statuses = std::collections::HashMap::new();
+    for entry in repo.statuses(Some(&mut status_opts))?.iter() {
+        let Some(file_path) = entry.path() else {
+            continue;
+        };
+        let s = entry.status();
+        let status = if s.intersects(git2::Status::WT_NEW | git2::Status::INDEX_NEW) {
+            "added"
+        } else if s.intersects(git2::Status::WT_DELETED | git2::Status::INDEX_DELETED) {
+            "deleted"
+        } else if s.intersects(git2::Status::WT_RENAMED | git2::Status::INDEX_RENAMED) {
+            "renamed"
+        } else if s.is_ignored() || s.is_empty() {
+            continue;
+        } else {
+            "modified"
+        };
+        statuses.insert(file_path.to_string(), status.to_string());
+    }
+    Ok(statuses)
+}
+
 /// Get the unified diff content for a single file.
 /// If `is_staged` is true, diffs index vs HEAD. Otherwise diffs workdir vs index.
 pub fn git_file_diff(path: &Path, file_path: &str, is_staged: bool) -> Result<String, GitError> {
@@ -789,18 +936,254 @@ pub fn git_push(path: &Path) -> Result<(), GitError> {
         .args(["push"])
         .current_dir(path)
         .output()
-        .map_err(|e| GitError::PathError(format!("Failed to run git push: {}", e)))?;
+        .map_err(|e| GitError::PushFailed(format!("could not run git: {}", e)))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(GitError::PathError(format!("git push failed: {}", stderr.trim())));
+        return Err(GitError::PushFailed(stderr.trim().to_string()));
     }
 
     tracing::info!("Pushed to origin from {}", path.display());
     Ok(())
 }
 
+/// Create a branch named `prefix` followed by a slug of `session_name` at
+/// HEAD and check it out, leaving the working tree as it is. A number is
+/// appended if the name is taken.
+pub fn create_session_branch(path: &Path, prefix: &str, session_name: &str) -> Result<SessionBranch, GitError> {
+    let repo = Repository::open(path)?;
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(GitError::DetachedHead);
+    }
+    let base = head.shorthand().unwrap_or("HEAD").to_string();
+    let commit = head.peel_to_commit()?;
+
+    let slug = branch_slug(session_name);
+    let mut name = format!("{}{}", prefix, slug);
+    let mut n = 2;
+    while repo.find_branch(&name, git2::BranchType::Local).is_ok() {
+        name = format!("{}{}-{}", prefix, slug, n);
+        n += 1;
+    }
+    repo.branch(&name, &commit, false)?;
+    repo.set_head(&format!("refs/heads/{}", name))?;
+
+    tracing::info!("Created branch {} from {} at {}", name, base, path.display());
+    Ok(SessionBranch { name, base })
+}
+
+/// Lowercase words of `name` joined by dashes, e.g. "fix-login-timeout".
+fn branch_slug(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect();
+    let mut slug = String::new();
+    for word in words {
+        if !slug.is_empty() && slug.len() + word.len() >= 40 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+    if slug.is_empty() {
+        "session".to_string()
+    } else {
+        slug.truncate(40);
+        slug
+    }
+}
+
+/// Merge the branch `branch` into `into` and check `into` out, fast-forwarding
+/// when possible. Refuses with uncommitted changes in the working tree, and
+/// leaves everything as it was on conflicts. Returns the commit `into` ends
+/// up at.
+pub fn merge_branch(path: &Path, branch: &str, into: &str) -> Result<String, GitError> {
+    let repo = Repository::open(path)?;
+    let mut status_opts = StatusOptions::new();
+    status_opts.include_untracked(true).include_ignored(false);
+    if !repo.statuses(Some(&mut status_opts))?.is_empty() {
+        return Err(GitError::UncommittedChanges);
+    }
+
+    let ours = repo.find_branch(into, git2::BranchType::Local)?.get().peel_to_commit()?;
+    let theirs = repo.find_branch(branch, git2::BranchType::Local)?.get().peel_to_commit()?;
+    let oid = if repo.graph_descendant_of(ours.id(), theirs.id())? || ours.id() == theirs.id() {
+        ours.id()
+    } else if repo.graph_descendant_of(theirs.id(), ours.id())? {
+        theirs.id()
+    } else {
+        let mut index = repo.merge_commits(&ours, &theirs, None)?;
+        if index.has_conflicts() {
+            let mut paths: Vec<String> = index
+                .conflicts()?
+                .filter_map(|conflict| {
+                    let conflict = conflict.ok()?;
+                    let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+                    Some(String::from_utf8_lossy(&entry.path).into_owned())
+                })
+                .collect();
+            paths.dedup();
+            return Err(GitError::MergeConflict(paths.join(", ")));
+        }
+        let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+        let sig = make_signature()?;
+        let message = format!("Merge branch '{}' into {}", branch, into);
+        repo.commit(None, &sig, &sig, &message, &tree, &[&ours, &theirs])?
+    };
+
+    let commit = repo.find_commit(oid)?;
+    repo.checkout_tree(commit.as_object(), Some(git2::build::CheckoutBuilder::new().safe()))?;
+    repo.reference(&format!("refs/heads/{}", into), oid, true, &format!("merge {}", branch))?;
+    repo.set_head(&format!("refs/heads/{}", into))?;
+
+    tracing::info!("Merged {} into {} at {}", branch, into, path.display());
+    Ok(oid.to_string())
+}
+
+/// Delete the local branch `name`, which must not be checked out.
+pub fn delete_branch(path: &Path, name: &str) -> Result<(), GitError> {
+    let repo = Repository::open(path)?;
+    repo.find_branch(name, git2::BranchType::Local)?.delete()?;
+    Ok(())
+}
+
 /// Create a git signature for commits.
 fn make_signature<'a>() -> Result<Signature<'a>, git2::Error> {
     Signature::now("Mado", "mado@local")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_init_repo_in_subdirectory_uses_enclosing_repo() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        Repository::init(&root).unwrap();
+        let sub = root.join("src").join("nested");
+        std::fs::create_dir_all(&sub).unwrap();
+
+        assert_eq!(discover_repo_root(&sub), Some(root.clone()));
+
+        init_repo(&sub).unwrap();
+        assert!(!sub.join(".git").exists(), "Should not create a nested repo");
+    }
+
+    #[test]
+    fn test_find_repos_lists_nested_repos() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        for name in ["api", "web", "node_modules/dep"] {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            Repository::init(&dir).unwrap();
+        }
+
+        let repos = find_repos(&root);
+        assert_eq!(repos, vec![root.join("api"), root.join("web")]);
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        init_repo(&root).unwrap();
+        std::fs::write(root.join("keep.txt"), "one\n").unwrap();
+        std::fs::write(root.join("gone.txt"), "bye\n").unwrap();
+
+        let before = snapshot_workdir(&root).unwrap();
+        std::fs::write(root.join("keep.txt"), "one\ntwo\n").unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+        std::fs::write(root.join("new.txt"), "hi\n").unwrap();
+        let after = snapshot_workdir(&root).unwrap();
+
+        let diff = diff_snapshots(&root, &before, &after).unwrap();
+        let mut files: Vec<(&str, &str)> =
+            diff.files.iter().map(|f| (f.path.as_str(), f.status.as_str())).collect();
+        files.sort();
+        assert_eq!(files, [("gone.txt", "deleted"), ("keep.txt", "modified"), ("new.txt", "added")]);
+        assert_eq!((diff.total_insertions, diff.total_deletions), (2, 1));
+
+        // Nothing was staged.
+        let repo = Repository::open(&root).unwrap();
+        assert!(repo.index().unwrap().get_path(Path::new("new.txt"), 0).is_none());
+    }
+
+    #[test]
+    fn test_session_branch_merges_back() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        init_repo(&root).unwrap();
+        let base = git_branch_info(&root).unwrap().branch;
+
+        assert_eq!(branch_slug("Fix login: timeout!"), "fix-login-timeout");
+        assert_eq!(branch_slug("???"), "session");
+        let branch = create_session_branch(&root, "mado/", "Fix login timeout").unwrap();
+        assert_eq!(branch, SessionBranch { name: "mado/fix-login-timeout".to_string(), base: base.clone() });
+        assert_eq!(git_branch_info(&root).unwrap().branch, branch.name);
+
+        // Taken names get a number.
+        let repo = Repository::open(&root).unwrap();
+        repo.set_head(&format!("refs/heads/{}", base)).unwrap();
+        let second = create_session_branch(&root, "mado/", "Fix login timeout").unwrap();
+        assert_eq!(second.name, "mado/fix-login-timeout-2");
+        repo.set_head(&format!("refs/heads/{}", branch.name)).unwrap();
+
+        std::fs::write(root.join("a.txt"), "a\n").unwrap();
+        assert!(matches!(merge_branch(&root, &branch.name, &base), Err(GitError::UncommittedChanges)));
+        let milestone = save_milestone(&root, "Add a").unwrap();
+
+        // Fast-forward.
+        assert_eq!(merge_branch(&root, &branch.name, &base).unwrap(), milestone.oid);
+        assert_eq!(git_branch_info(&root).unwrap().branch, base);
+        assert!(root.join("a.txt").exists());
+
+        // Diverged: a merge commit.
+        repo.set_head(&format!("refs/heads/{}", second.name)).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+        std::fs::write(root.join("b.txt"), "b\n").unwrap();
+        save_milestone(&root, "Add b").unwrap();
+        let merged = merge_branch(&root, &second.name, &base).unwrap();
+        assert_eq!(repo.find_commit(git2::Oid::from_str(&merged).unwrap()).unwrap().parent_count(), 2);
+        assert!(root.join("a.txt").exists() && root.join("b.txt").exists());
+
+        delete_branch(&root, &second.name).unwrap();
+        assert!(repo.find_branch(&second.name, git2::BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_merge_branch_conflict_changes_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        init_repo(&root).unwrap();
+        let base = git_branch_info(&root).unwrap().branch;
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        save_milestone(&root, "One").unwrap();
+
+        let branch = create_session_branch(&root, "mado/", "edit").unwrap();
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        let ours = save_milestone(&root, "Two").unwrap();
+        let repo = Repository::open(&root).unwrap();
+        repo.set_head(&format!("refs/heads/{}", base)).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+        std::fs::write(root.join("a.txt"), "three\n").unwrap();
+        save_milestone(&root, "Three").unwrap();
+        repo.set_head(&format!("refs/heads/{}", branch.name)).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+
+        match merge_branch(&root, &branch.name, &base) {
+            Err(GitError::MergeConflict(paths)) => assert_eq!(paths, "a.txt"),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert_eq!(git_branch_info(&root).unwrap().branch, branch.name);
+        assert_eq!(repo.head().unwrap().target().unwrap().to_string(), ours.oid);
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "two\n");
+    }
+}
@@ -0,0 +1,514 @@
+//! Graceful restart: hand live PTYs and listeners to a fresh daemon process.
+//!
+//! On `POST /admin/restart` (or SIGUSR2) the daemon saves state, pauses its PTY
+//! readers and spawns its own binary with `MADO_HANDOVER` pointing at a
+//! manifest. The PTY master fds, the listening sockets and the write end of a
+//! "ready" pipe are inherited by the successor (duplicated without
+//! `FD_CLOEXEC`). The successor adopts them, starts serving and writes a byte
+//! to the pipe; the old daemon then exits and the successor resumes the PTY
+//! readers. The Claude processes never see their terminal close.
+//!
+//! Until the successor reports ready nothing has been given up: if it fails,
+//! the old daemon resumes its readers and keeps serving.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing;
+
+use crate::pid::is_process_alive;
+use crate::server::AppState;
+use crate::state::{DaemonState, StateError};
+
+/// Environment variable carrying the manifest path to a successor.
+pub const HANDOVER_ENV: &str = "MADO_HANDOVER";
+
+/// How long the successor has to start serving.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the successor waits for its predecessor to exit before killing it.
+const PREDECESSOR_EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Grace period for the restart response to reach the client before exiting.
+const EXIT_DELAY: Duration = Duration::from_millis(200);
+
+/// A live PTY passed to a successor daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtyHandover {
+    pub session_id: String,
+    /// Inherited master fd.
+    pub master_fd: RawFd,
+    /// Pid of the process in the PTY, if known.
+    pub pid: Option<u32>,
+    /// Base64 scrollback, replayed to clients attaching to the successor.
+    pub scrollback: String,
+    /// Stream offset at the end of `scrollback`.
+    pub offset: u64,
+}
+
+/// Everything a successor inherits from its predecessor.
+///
+/// The fds are only valid in the successor, and each must be taken once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handover {
+    /// Pid of the daemon handing over.
+    pub predecessor: u32,
+    /// Listening Unix socket.
+    pub listener_fd: RawFd,
+    /// Listening TCP socket, when remote access is enabled.
+    pub tcp_listener_fd: Option<RawFd>,
+    /// Write end of the pipe the predecessor waits on.
+    pub ready_fd: RawFd,
+    pub ptys: Vec<PtyHandover>,
+}
+
+impl Handover {
+    /// The handover left for this process by its predecessor, if any.
+    ///
+    /// Removes `MADO_HANDOVER` from the environment so PTY processes and later
+    /// successors don't see it, which makes this unsafe to call once other
+    /// threads are running: call it first thing in `main`.
+    pub fn from_env() -> Result<Option<Self>, HandoverError> {
+        let Some(path) = std::env::var_os(HANDOVER_ENV) else {
+            return Ok(None);
+        };
+        // Safety: documented to be called before any other thread starts.
+        unsafe { std::env::remove_var(HANDOVER_ENV) };
+
+        let path = PathBuf::from(path);
+        let contents = fs::read(&path).map_err(|source| HandoverError::Manifest {
+            path: path.clone(),
+            source,
+        })?;
+        let _ = fs::remove_file(&path);
+        Ok(Some(serde_json::from_slice(&contents)?))
+    }
+
+    /// Take the inherited Unix listener.
+    pub fn unix_listener(&self) -> std::io::Result<std::os::unix::net::UnixListener> {
+        // Safety: inherited for us, taken once.
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(self.listener_fd) };
+        set_cloexec(self.listener_fd)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }
+
+    /// Take the inherited TCP listener, if there is one.
+    pub fn tcp_listener(&self) -> Option<std::io::Result<std::net::TcpListener>> {
+        self.tcp_listener_fd.map(|fd| {
+            // Safety: inherited for us, taken once.
+            let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            set_cloexec(fd)?;
+            Ok(listener)
+        })
+    }
+
+    /// Tell the predecessor we are serving, so it can exit.
+    pub fn notify_ready(&self) {
+        // Safety: inherited for us, taken once; closed when the file drops.
+        let mut ready = unsafe { fs::File::from_raw_fd(self.ready_fd) };
+        if let Err(e) = ready.write_all(&[1]) {
+            tracing::error!("Failed to notify predecessor daemon: {}", e);
+        }
+    }
+
+    /// Wait for the predecessor to exit, killing it if it lingers. Until then
+    /// it may still be reading from the PTYs.
+    pub async fn wait_for_predecessor(&self) {
+        let start = Instant::now();
+        while is_process_alive(self.predecessor) {
+            if start.elapsed() >= PREDECESSOR_EXIT_TIMEOUT {
+                tracing::warn!("Predecessor {} did not exit, killing it", self.predecessor);
+                unsafe {
+                    libc::kill(self.predecessor as i32, libc::SIGKILL);
+                }
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Close the fds in the process that created them, after spawning.
+    fn close_fds(&self) {
+        let fds = [Some(self.listener_fd), self.tcp_listener_fd, Some(self.ready_fd)];
+        for fd in fds.into_iter().flatten() {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+        for pty in &self.ptys {
+            crate::process::close_inherited(pty);
+        }
+    }
+}
+
+/// How to start a successor daemon.
+#[derive(Debug, Clone)]
+pub struct SuccessorCommand {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+}
+
+impl SuccessorCommand {
+    /// Re-run the current binary with `args`.
+    ///
+    /// The successor should run in the foreground: this process is already
+    /// detached.
+    pub fn current(args: Vec<OsString>) -> std::io::Result<Self> {
+        let exe = std::env::current_exe()?;
+        // Linux reports a binary replaced by an upgrade as "<path> (deleted)";
+        // the new binary is at the original path.
+        let program = match exe.to_str().and_then(|s| s.strip_suffix(" (deleted)")) {
+            Some(path) => PathBuf::from(path),
+            None => exe,
+        };
+        Ok(Self { program, args })
+    }
+}
+
+/// Performs graceful restarts for a running server.
+pub struct Restarter {
+    successor: SuccessorCommand,
+    listener_fd: RawFd,
+    tcp_listener_fd: Option<RawFd>,
+    daemon_state: Arc<Mutex<DaemonState>>,
+    state_path: PathBuf,
+    in_progress: AtomicBool,
+}
+
+impl Restarter {
+    pub fn new(
+        successor: SuccessorCommand,
+        listener_fd: RawFd,
+        tcp_listener_fd: Option<RawFd>,
+        daemon_state: Arc<Mutex<DaemonState>>,
+        state_path: PathBuf,
+    ) -> Self {
+        Self {
+            successor,
+            listener_fd,
+            tcp_listener_fd,
+            daemon_state,
+            state_path,
+            in_progress: AtomicBool::new(false),
+        }
+    }
+
+    /// Hand everything over to a successor and schedule this process's exit.
+    /// Returns the successor's pid.
+    ///
+    /// Refused while a chat response is streaming: its `claude -p` pipe cannot
+    /// be handed over.
+    pub async fn restart(&self, state: &AppState) -> Result<u32, HandoverError> {
+        if self.in_progress.swap(true, Ordering::SeqCst) {
+            return Err(HandoverError::InProgress);
+        }
+
+        match self.try_restart(state).await {
+            Ok(pid) => {
+                tracing::info!("Handed over to successor daemon {}, exiting", pid);
+                tokio::spawn(async {
+                    tokio::time::sleep(EXIT_DELAY).await;
+                    // Skip destructors: the socket and PID file now belong to the successor.
+                    std::process::exit(0);
+                });
+                Ok(pid)
+            }
+            Err(e) => {
+                self.in_progress.store(false, Ordering::SeqCst);
+                Err(e)
+            }
+        }
+    }
+
+    async fn try_restart(&self, state: &AppState) -> Result<u32, HandoverError> {
+        let busy = state.conversation_manager.busy_count().await;
+        if busy > 0 {
+            return Err(HandoverError::Busy(busy));
+        }
+
+        self.daemon_state.lock().await.save(&self.state_path)?;
+
+        let ptys = state
+            .session_manager
+            .handover_ptys()
+            .await
+            .map_err(HandoverError::Fd)?;
+        let result = self.spawn_successor(ptys).await;
+        if result.is_err() {
+            state.session_manager.resume_ptys().await;
+        }
+        result
+    }
+
+    async fn spawn_successor(&self, ptys: Vec<PtyHandover>) -> Result<u32, HandoverError> {
+        let (ready_read, ready_write) = pipe().map_err(HandoverError::Fd)?;
+        let fds = inheritable_fds(self.listener_fd, self.tcp_listener_fd, ready_write);
+        unsafe {
+            libc::close(ready_write);
+        }
+        let (listener_fd, tcp_listener_fd, ready_fd) = match fds {
+            Ok(fds) => fds,
+            Err(e) => {
+                unsafe {
+                    libc::close(ready_read);
+                }
+                for pty in &ptys {
+                    crate::process::close_inherited(pty);
+                }
+                return Err(HandoverError::Fd(e));
+            }
+        };
+        let handover = Handover {
+            predecessor: std::process::id(),
+            listener_fd,
+            tcp_listener_fd,
+            ready_fd,
+            ptys,
+        };
+
+        let manifest_path = self
+            .state_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("handover.json");
+        let spawned = write_manifest(&manifest_path, &handover).and_then(|()| {
+            std::process::Command::new(&self.successor.program)
+                .args(&self.successor.args)
+                .env(HANDOVER_ENV, &manifest_path)
+                .stdin(std::process::Stdio::null())
+                .spawn()
+                .map_err(HandoverError::Spawn)
+        });
+        // Only the successor needs these now.
+        handover.close_fds();
+
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                unsafe {
+                    libc::close(ready_read);
+                }
+                let _ = fs::remove_file(&manifest_path);
+                return Err(e);
+            }
+        };
+        let pid = child.id();
+        tracing::info!(
+            "Spawned successor daemon {} ({} live PTYs)",
+            pid,
+            handover.ptys.len()
+        );
+
+        let ready = tokio::task::spawn_blocking(move || wait_ready(ready_read, READY_TIMEOUT))
+            .await
+            .unwrap_or(Err(HandoverError::NotReady("wait task failed".to_string())));
+        if let Err(e) = ready {
+            let _ = child.kill();
+            let _ = tokio::task::spawn_blocking(move || child.wait()).await;
+            let _ = fs::remove_file(&manifest_path);
+            return Err(e);
+        }
+        Ok(pid)
+    }
+}
+
+/// Restart gracefully on SIGUSR2.
+pub fn spawn_signal_handler(state: AppState) -> Option<tokio::task::JoinHandle<()>> {
+    let restarter = state.restart.clone()?;
+    let mut sigusr2 =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2()) {
+            Ok(signal) => signal,
+            Err(e) => {
+                tracing::error!("Failed to install SIGUSR2 handler: {}", e);
+                return None;
+            }
+        };
+
+    Some(tokio::spawn(async move {
+        while sigusr2.recv().await.is_some() {
+            tracing::info!("Received SIGUSR2, restarting gracefully");
+            if let Err(e) = restarter.restart(&state).await {
+                tracing::error!("Graceful restart failed: {}", e);
+            }
+        }
+    }))
+}
+
+fn write_manifest(path: &Path, handover: &Handover) -> Result<(), HandoverError> {
+    let json = serde_json::to_vec(handover)?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(&json))
+        .map_err(|source| HandoverError::Manifest {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// A close-on-exec pipe, as (read, write).
+fn pipe() -> std::io::Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((fds[0], fds[1]))
+}
+
+/// Duplicate `fd` without `FD_CLOEXEC`, so a spawned child inherits it.
+fn dup_inheritable(fd: RawFd) -> std::io::Result<RawFd> {
+    // Safety: plain dup of a descriptor we own.
+    let new_fd = unsafe { libc::dup(fd) };
+    if new_fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(new_fd)
+}
+
+/// Inheritable duplicates of the listeners and the ready pipe's write end.
+/// On failure none of them are left open.
+fn inheritable_fds(
+    listener_fd: RawFd,
+    tcp_listener_fd: Option<RawFd>,
+    ready_fd: RawFd,
+) -> std::io::Result<(RawFd, Option<RawFd>, RawFd)> {
+    let close = |fds: &[Option<RawFd>]| {
+        for fd in fds.iter().flatten() {
+            unsafe {
+                libc::close(*fd);
+            }
+        }
+    };
+    let listener = dup_inheritable(listener_fd)?;
+    let tcp_listener = tcp_listener_fd
+        .map(dup_inheritable)
+        .transpose()
+        .inspect_err(|_| close(&[Some(listener)]))?;
+    let ready = dup_inheritable(ready_fd).inspect_err(|_| close(&[Some(listener), tcp_listener]))?;
+    Ok((listener, tcp_listener, ready))
+}
+
+fn set_cloexec(fd: RawFd) -> std::io::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Wait for the successor's ready byte on `fd`, then close it.
+fn wait_ready(fd: RawFd, timeout: Duration) -> Result<(), HandoverError> {
+    // Safety: we own the read end; closed when the file drops.
+    let mut ready = unsafe { fs::File::from_raw_fd(fd) };
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let polled = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+    if polled == 0 {
+        return Err(HandoverError::NotReady("timed out".to_string()));
+    }
+
+    let mut byte = [0u8; 1];
+    match std::io::Read::read(&mut ready, &mut byte) {
+        Ok(1) => Ok(()),
+        Ok(_) => Err(HandoverError::NotReady("exited before serving".to_string())),
+        Err(e) => Err(HandoverError::NotReady(e.to_string())),
+    }
+}
+
+/// Errors from a graceful restart.
+#[derive(Debug, thiserror::Error)]
+pub enum HandoverError {
+    #[error("A restart is already in progress")]
+    InProgress,
+
+    #[error("{0} chat response(s) in progress; retry once they finish")]
+    Busy(usize),
+
+    #[error("Failed to save state: {0}")]
+    State(#[from] StateError),
+
+    #[error("Failed to prepare file descriptors: {0}")]
+    Fd(std::io::Error),
+
+    #[error("Handover manifest {path}: {source}")]
+    Manifest {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid handover manifest: {0}")]
+    InvalidManifest(#[from] serde_json::Error),
+
+    #[error("Failed to spawn successor daemon: {0}")]
+    Spawn(std::io::Error),
+
+    #[error("Successor daemon did not start: {0}")]
+    NotReady(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_ready() {
+        let (read, write) = pipe().unwrap();
+        let mut writer = unsafe { fs::File::from_raw_fd(write) };
+        writer.write_all(&[1]).unwrap();
+        assert!(wait_ready(read, Duration::from_secs(1)).is_ok());
+
+        // A successor that dies first closes its end without writing.
+        let (read, write) = pipe().unwrap();
+        unsafe {
+            libc::close(write);
+        }
+        assert!(matches!(
+            wait_ready(read, Duration::from_secs(1)),
+            Err(HandoverError::NotReady(_))
+        ));
+
+        let (read, write) = pipe().unwrap();
+        assert!(matches!(
+            wait_ready(read, Duration::from_millis(10)),
+            Err(HandoverError::NotReady(_))
+        ));
+        unsafe {
+            libc::close(write);
+        }
+    }
+
+    #[test]
+    fn test_inheritable_fds() {
+        let (read, write) = pipe().unwrap();
+        let (listener, tcp_listener, ready) = inheritable_fds(read, None, write).unwrap();
+        assert!(tcp_listener.is_none());
+        for fd in [listener, ready] {
+            assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC, 0);
+        }
+
+        // A bad descriptor fails the whole set instead of handing on -1.
+        assert!(dup_inheritable(-1).is_err());
+        assert!(inheritable_fds(read, Some(-1), write).is_err());
+        assert!(set_cloexec(-1).is_err());
+        for fd in [read, write, listener, ready] {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Importing chat history from coding agents.
+//!
+//! Each supported tool has a [`HistoryImporter`] that finds its sessions
+//! for a working directory and parses them into messages tagged with the
+//! tool (`Message::source`), so a project's history carries over when
+//! switching tools. [`detect`] reports which tools have history for a
+//! directory.
+//!
+//! The Claude CLI's importer is the one chat sessions resume from (see
+//! `crate::claude_history`); the others are read-only.
+
+use std::path::{Path, PathBuf};
+
+use mado_core::types::{HistorySource, Message};
+
+use crate::claude_history::HistoryError;
+
+/// Reads one tool's chat history.
+pub trait HistoryImporter: Send + Sync {
+    /// The tool, as recorded on imported messages.
+    fn source(&self) -> HistorySource;
+
+    /// The tool's session files for `working_dir`, newest first; none if it
+    /// was never used there.
+    fn sessions(&self, working_dir: &Path) -> Vec<PathBuf>;
+
+    /// Messages of one session file, oldest first.
+    fn parse(&self, session: &Path) -> Result<Vec<Message>, HistoryError>;
+}
+
+/// The importer for `source`.
+pub fn importer(source: HistorySource) -> &'static dyn HistoryImporter {
+    match source {
+        HistorySource::Claude => &crate::claude_history::ClaudeImporter,
+        HistorySource::Aider => &crate::aider_history::AiderImporter,
+        HistorySource::Codex => &crate::codex_history::CodexImporter,
+        HistorySource::Gemini => &crate::gemini_history::GeminiImporter,
+    }
+}
+
+/// Tools with history for `working_dir`.
+pub fn detect(working_dir: &Path) -> Vec<HistorySource> {
+    HistorySource::ALL
+        .into_iter()
+        .filter(|source| !importer(*source).sessions(working_dir).is_empty())
+        .collect()
+}
+
+/// Import `source`'s latest session for `working_dir`, or all of them merged
+/// by timestamp, keeping the newest `limit` messages after skipping the
+/// newest `offset`.
+pub fn import(
+    source: HistorySource,
+    working_dir: &Path,
+    all_sessions: bool,
+    limit: Option<usize>,
+    offset: usize,
+) -> Result<Vec<Message>, HistoryError> {
+    let importer = importer(source);
+    let sessions = importer.sessions(working_dir);
+    if sessions.is_empty() {
+        return Err(HistoryError::ProjectNotFound(working_dir.to_path_buf()));
+    }
+
+    let mut messages = Vec::new();
+    let take = if all_sessions { sessions.len() } else { 1 };
+    for session in &sessions[..take] {
+        messages.append(&mut importer.parse(session)?);
+    }
+    if all_sessions {
+        messages.sort_by_key(|m| m.timestamp);
+    }
+    Ok(page(messages, limit, offset))
+}
+
+/// The newest `limit` of `messages` after skipping the newest `offset`.
+pub fn page(mut messages: Vec<Message>, limit: Option<usize>, offset: usize) -> Vec<Message> {
+    messages.truncate(messages.len().saturating_sub(offset));
+    if let Some(limit) = limit {
+        messages.drain(..messages.len().saturating_sub(limit));
+    }
+    messages
+}
+
+/// Id of the `index`th message of `session`, stable across imports.
+pub fn message_id(source: HistorySource, session: &Path, index: usize) -> String {
+    format!(
+        "imported-{}-{}-{}",
+        source.as_str(),
+        session.file_stem().unwrap_or_default().to_string_lossy(),
+        index
+    )
+}
+
+/// Text of a message part list: the `text` of each part, one per line.
+pub fn join_text(parts: &serde_json::Value) -> String {
+    match parts {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,87 @@
+//! Idle auto-shutdown.
+//!
+//! With `idle_timeout_minutes` set in config.json, the daemon exits cleanly
+//! once it has had no sessions, no open event streams and no requests for
+//! that long. Clients start it again when they next need it
+//! (`DaemonClient::ensure_daemon_running`).
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::server::AppState;
+
+/// Longest gap between idleness checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// When the daemon last handled a request.
+#[derive(Debug)]
+pub struct IdleTracker {
+    last_request: Mutex<Instant>,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self {
+            last_request: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl IdleTracker {
+    /// Record activity now.
+    pub fn touch(&self) {
+        *self.last_request.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Time since the last request.
+    pub fn idle_for(&self) -> Duration {
+        self.last_request.lock().unwrap_or_else(|e| e.into_inner()).elapsed()
+    }
+}
+
+/// Middleware recording when each request starts and finishes.
+pub async fn track_requests(
+    State(tracker): State<Arc<IdleTracker>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    tracker.touch();
+    let response = next.run(request).await;
+    tracker.touch();
+    response
+}
+
+/// Resolve once the daemon has been idle for the configured timeout. Never
+/// resolves while the timeout is unset; it is re-read on every check, so a
+/// config reload takes effect.
+pub async fn wait_until_idle(state: &AppState) {
+    loop {
+        let timeout = state.settings.read().unwrap_or_else(|e| e.into_inner()).idle_timeout;
+        let check_in = timeout.map_or(CHECK_INTERVAL, |timeout| (timeout / 2).min(CHECK_INTERVAL));
+        tokio::time::sleep(check_in).await;
+
+        let Some(timeout) = timeout else {
+            continue;
+        };
+        if state.idle.idle_for() < timeout || !state.session_manager.list_sessions().await.is_empty()
+        {
+            continue;
+        }
+        let streams: usize = state
+            .conversation_manager
+            .subscriber_counts()
+            .await
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum::<usize>()
+            + state.activity_feed.receiver_count();
+        if streams == 0 {
+            tracing::info!("No sessions, streams or requests for {:?}, shutting down", timeout);
+            return;
+        }
+    }
+}
@@ -1,32 +1,243 @@
+//! API keys and other secrets.
+//!
+//! Secrets live in the OS keychain (macOS Keychain / Linux Secret Service)
+//! under service `mado`. Headless daemons without a keychain can set
+//! `secrets_file` in config.json to keep them in `~/.mado/secrets.json`
+//! instead, readable only by the owner.
+//!
+//! Secrets are moved to the configured store the first time they are read,
+//! so keys saved before `secrets_file` was turned on or off keep working.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
 use tracing;
 
 const SERVICE_NAME: &str = "mado";
-const USERNAME: &str = "anthropic-api-key";
+/// Provider whose key `get_api_key` and friends handle, and which Claude
+/// uses in `api_key` auth mode.
+pub const ANTHROPIC: &str = "anthropic";
+/// Prefix of the keychain account names of secrets referred to by name.
+const SECRET_PREFIX: &str = "secret:";
+/// Prefix of the keychain account names of forge tokens, by host.
+const FORGE_PREFIX: &str = "forge:";
+
+/// Held across reading, changing and writing back the secrets file, so
+/// concurrent updates don't lose each other's changes.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// An API provider the keystore holds a key for, and the environment
+/// variables spawned CLIs read its key and base URL from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Provider {
+    pub name: &'static str,
+    pub key_var: &'static str,
+    pub base_url_var: &'static str,
+}
+
+/// Providers whose keys can be stored.
+pub const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: ANTHROPIC,
+        key_var: "ANTHROPIC_API_KEY",
+        base_url_var: "ANTHROPIC_BASE_URL",
+    },
+    Provider {
+        name: "openai",
+        key_var: "OPENAI_API_KEY",
+        base_url_var: "OPENAI_BASE_URL",
+    },
+];
+
+/// The provider called `name`.
+pub fn provider(name: &str) -> Result<&'static Provider, KeyStoreError> {
+    PROVIDERS
+        .iter()
+        .find(|provider| provider.name == name)
+        .ok_or_else(|| KeyStoreError::UnknownProvider(name.to_string()))
+}
+
+/// Keychain account of a provider's key.
+fn account(provider: &str) -> String {
+    format!("{}-api-key", provider)
+}
+
+/// The secrets file used when `secrets_file` is set.
+pub fn secrets_path() -> PathBuf {
+    crate::config::config_dir().join("secrets.json")
+}
+
+/// Where secrets are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Store {
+    Keychain,
+    File(PathBuf),
+}
+
+impl Store {
+    /// The store config.json selects.
+    fn configured() -> Self {
+        let config = crate::config::MadoConfig::read(&crate::config::config_path()).unwrap_or_default();
+        if config.secrets_file {
+            Store::File(secrets_path())
+        } else {
+            Store::Keychain
+        }
+    }
+
+    /// The store secrets are migrated from.
+    fn other(&self) -> Self {
+        match self {
+            Store::Keychain => Store::File(secrets_path()),
+            Store::File(_) => Store::Keychain,
+        }
+    }
+
+    fn get(&self, account: &str) -> Result<String, KeyStoreError> {
+        match self {
+            Store::Keychain => {
+                let entry = keyring::Entry::new(SERVICE_NAME, account)
+                    .map_err(|e| KeyStoreError::KeychainError(e.to_string()))?;
+                match entry.get_password() {
+                    Ok(secret) => Ok(secret),
+                    Err(keyring::Error::NoEntry) => Err(KeyStoreError::NotFound),
+                    Err(e) => Err(KeyStoreError::KeychainError(e.to_string())),
+                }
+            }
+            Store::File(path) => read_file(path)?.remove(account).ok_or(KeyStoreError::NotFound),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), KeyStoreError> {
+        match self {
+            Store::Keychain => keyring::Entry::new(SERVICE_NAME, account)
+                .and_then(|entry| entry.set_password(secret))
+                .map_err(|e| KeyStoreError::KeychainError(e.to_string())),
+            Store::File(path) => {
+                let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+                let mut secrets = read_file(path)?;
+                secrets.insert(account.to_string(), secret.to_string());
+                write_file(path, &secrets)
+            }
+        }
+    }
+
+    /// Delete a secret; one that isn't there is not an error.
+    fn delete(&self, account: &str) -> Result<(), KeyStoreError> {
+        match self {
+            Store::Keychain => {
+                let entry = keyring::Entry::new(SERVICE_NAME, account)
+                    .map_err(|e| KeyStoreError::KeychainError(e.to_string()))?;
+                match entry.delete_credential() {
+                    Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                    Err(e) => Err(KeyStoreError::KeychainError(e.to_string())),
+                }
+            }
+            Store::File(path) => {
+                let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+                let mut secrets = read_file(path)?;
+                if secrets.remove(account).is_some() {
+                    write_file(path, &secrets)?;
+                }
+                Ok(())
+            }
+        }
+    }
 
-/// Secure storage for API keys using the OS keychain (macOS Keychain / Linux libsecret).
+    /// Get a secret, moving it here from the other store if it is only
+    /// there. Secrets read are redacted from logs from then on.
+    fn get_or_migrate(&self, account: &str) -> Result<String, KeyStoreError> {
+        let secret = self.find_or_migrate(account)?;
+        crate::redact::remember(&secret);
+        Ok(secret)
+    }
+
+    fn find_or_migrate(&self, account: &str) -> Result<String, KeyStoreError> {
+        match self.get(account) {
+            Err(KeyStoreError::NotFound) => {}
+            result => return result,
+        }
+        let other = self.other();
+        let secret = match other.get(account) {
+            Ok(secret) => secret,
+            Err(KeyStoreError::NotFound) => return Err(KeyStoreError::NotFound),
+            Err(e) => {
+                tracing::debug!("Not migrating {}: {}", account, e);
+                return Err(KeyStoreError::NotFound);
+            }
+        };
+        match self.set(account, &secret).and_then(|()| other.delete(account)) {
+            Ok(()) => tracing::info!("Migrated {} to the {}", account, self.describe()),
+            Err(e) => tracing::warn!("Failed to migrate {}: {}", account, e),
+        }
+        Ok(secret)
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Store::Keychain => "OS keychain",
+            Store::File(_) => "secrets file",
+        }
+    }
+}
+
+/// Secrets in the file at `path`, by account; none if it doesn't exist.
+fn read_file(path: &Path) -> Result<BTreeMap<String, String>, KeyStoreError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| KeyStoreError::FileError(e.to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(KeyStoreError::FileError(e.to_string())),
+    }
+}
+
+/// Replace the file at `path` with `secrets`, readable only by the owner.
+fn write_file(path: &Path, secrets: &BTreeMap<String, String>) -> Result<(), KeyStoreError> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let to_error = |e: std::io::Error| KeyStoreError::FileError(e.to_string());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(to_error)?;
+    }
+    let contents = serde_json::to_string_pretty(secrets).map_err(|e| KeyStoreError::FileError(e.to_string()))?;
+    // A leftover temp file would keep its own permissions; start afresh.
+    let tmp = path.with_extension("json.tmp");
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(to_error(e)),
+        _ => {}
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .map_err(to_error)?;
+    file.write_all(contents.as_bytes()).map_err(to_error)?;
+    fs::rename(&tmp, path).map_err(to_error)
+}
+
+/// Secure storage for API keys using the OS keychain (macOS Keychain / Linux libsecret),
+/// or the secrets file when `secrets_file` is set.
 pub struct KeyStore;
 
 impl KeyStore {
     /// Get the Anthropic API key.
     ///
-    /// Checks the OS keychain first, then falls back to the ANTHROPIC_API_KEY environment variable.
+    /// Checks the secret store first, then falls back to the ANTHROPIC_API_KEY environment variable.
     pub fn get_api_key() -> Result<String, KeyStoreError> {
-        // Try OS keychain first.
-        match keyring::Entry::new(SERVICE_NAME, USERNAME) {
-            Ok(entry) => match entry.get_password() {
-                Ok(key) => {
-                    tracing::debug!("API key loaded from OS keychain");
-                    return Ok(key);
-                }
-                Err(keyring::Error::NoEntry) => {
-                    tracing::debug!("No API key in OS keychain, checking env var");
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to read from keychain: {}", e);
-                }
-            },
+        let store = Store::configured();
+        match store.get_or_migrate(&account(ANTHROPIC)) {
+            Ok(key) => {
+                tracing::debug!("API key loaded from {}", store.describe());
+                return Ok(key);
+            }
+            Err(KeyStoreError::NotFound) => {
+                tracing::debug!("No API key in {}, checking env var", store.describe());
+            }
             Err(e) => {
-                tracing::warn!("Failed to access keychain: {}", e);
+                tracing::warn!("Failed to read API key: {}", e);
             }
         }
 
@@ -34,51 +245,101 @@ impl KeyStore {
         match std::env::var("ANTHROPIC_API_KEY") {
             Ok(key) if !key.is_empty() => {
                 tracing::debug!("API key loaded from ANTHROPIC_API_KEY env var");
+                crate::redact::remember(&key);
                 Ok(key)
             }
             _ => Err(KeyStoreError::NotFound),
         }
     }
 
-    /// Store the Anthropic API key in the OS keychain.
+    /// Store the Anthropic API key.
     pub fn set_api_key(key: &str) -> Result<(), KeyStoreError> {
+        Self::set_provider_key(ANTHROPIC, key)
+    }
+
+    /// Delete the stored Anthropic API key.
+    pub fn delete_api_key() -> Result<(), KeyStoreError> {
+        Self::delete_provider_key(ANTHROPIC)
+    }
+
+    /// Get a provider's stored API key.
+    pub fn get_provider_key(name: &str) -> Result<String, KeyStoreError> {
+        let provider = provider(name)?;
+        Store::configured().get_or_migrate(&account(provider.name))
+    }
+
+    /// Store a provider's API key.
+    pub fn set_provider_key(name: &str, key: &str) -> Result<(), KeyStoreError> {
+        let provider = provider(name)?;
         if key.is_empty() {
             return Err(KeyStoreError::InvalidKey("API key cannot be empty".into()));
         }
 
-        let entry = keyring::Entry::new(SERVICE_NAME, USERNAME)
-            .map_err(|e| KeyStoreError::KeychainError(e.to_string()))?;
-
-        entry
-            .set_password(key)
-            .map_err(|e| KeyStoreError::KeychainError(e.to_string()))?;
-
-        tracing::info!("API key stored in OS keychain");
+        let store = Store::configured();
+        store.set(&account(provider.name), key)?;
+        tracing::info!("{} API key stored in {}", provider.name, store.describe());
         Ok(())
     }
 
-    /// Delete the Anthropic API key from the OS keychain.
-    pub fn delete_api_key() -> Result<(), KeyStoreError> {
-        let entry = keyring::Entry::new(SERVICE_NAME, USERNAME)
-            .map_err(|e| KeyStoreError::KeychainError(e.to_string()))?;
-
-        match entry.delete_credential() {
-            Ok(()) => {
-                tracing::info!("API key deleted from OS keychain");
-                Ok(())
-            }
-            Err(keyring::Error::NoEntry) => {
-                // Already gone, that's fine.
-                Ok(())
-            }
-            Err(e) => Err(KeyStoreError::KeychainError(e.to_string())),
+    /// Delete a provider's stored API key, from both stores.
+    pub fn delete_provider_key(name: &str) -> Result<(), KeyStoreError> {
+        let provider = provider(name)?;
+        let store = Store::configured();
+        store.delete(&account(provider.name))?;
+        if let Err(e) = store.other().delete(&account(provider.name)) {
+            tracing::debug!("Failed to delete {} API key from {}: {}", provider.name, store.other().describe(), e);
         }
+        tracing::info!("{} API key deleted", provider.name);
+        Ok(())
     }
 
-    /// Check if an API key is available (either keychain or env var).
+    /// Check if an API key is available (either stored or env var).
     pub fn has_api_key() -> bool {
         Self::get_api_key().is_ok()
     }
+
+    /// Providers with a stored key.
+    pub fn list_provider_keys() -> Vec<&'static str> {
+        PROVIDERS
+            .iter()
+            .filter(|provider| Self::get_provider_key(provider.name).is_ok())
+            .map(|provider| provider.name)
+            .collect()
+    }
+
+    /// Get a named secret, stored under service `mado` and account
+    /// `secret:<name>`.
+    pub fn get_secret(name: &str) -> Result<String, KeyStoreError> {
+        Store::configured().get_or_migrate(&format!("{}{}", SECRET_PREFIX, name))
+    }
+
+    /// Get the API token for the forge at `host`, e.g. `github.com`.
+    pub fn get_forge_token(host: &str) -> Result<String, KeyStoreError> {
+        Store::configured().get_or_migrate(&format!("{}{}", FORGE_PREFIX, host))
+    }
+
+    /// Store the API token for the forge at `host`.
+    pub fn set_forge_token(host: &str, token: &str) -> Result<(), KeyStoreError> {
+        if host.is_empty() || token.is_empty() {
+            return Err(KeyStoreError::InvalidKey("Forge host and token cannot be empty".into()));
+        }
+        let store = Store::configured();
+        store.set(&format!("{}{}", FORGE_PREFIX, host), token)?;
+        tracing::info!("{} token stored in {}", host, store.describe());
+        Ok(())
+    }
+
+    /// Delete the token for the forge at `host`, from both stores.
+    pub fn delete_forge_token(host: &str) -> Result<(), KeyStoreError> {
+        let account = format!("{}{}", FORGE_PREFIX, host);
+        let store = Store::configured();
+        store.delete(&account)?;
+        if let Err(e) = store.other().delete(&account) {
+            tracing::debug!("Failed to delete {} token from {}: {}", host, store.other().describe(), e);
+        }
+        tracing::info!("{} token deleted", host);
+        Ok(())
+    }
 }
 
 /// Errors from key storage operations.
@@ -90,6 +351,63 @@ pub enum KeyStoreError {
     #[error("Invalid API key: {0}")]
     InvalidKey(String),
 
+    #[error("Unknown API provider: {0}")]
+    UnknownProvider(String),
+
     #[error("Keychain error: {0}")]
     KeychainError(String),
+
+    #[error("Secrets file error: {0}")]
+    FileError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+        let store = Store::File(path.clone());
+
+        assert!(matches!(store.get("anthropic-api-key"), Err(KeyStoreError::NotFound)));
+        store.set("anthropic-api-key", "sk-ant-1").unwrap();
+        store.set("secret:token", "abc").unwrap();
+        assert_eq!(store.get("anthropic-api-key").unwrap(), "sk-ant-1");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        store.delete("anthropic-api-key").unwrap();
+        store.delete("anthropic-api-key").unwrap();
+        assert!(matches!(store.get("anthropic-api-key"), Err(KeyStoreError::NotFound)));
+        assert_eq!(store.get("secret:token").unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_file_store_replaces_stale_tmp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, "{}").unwrap();
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o644)).unwrap();
+
+        Store::File(path.clone()).set("anthropic-api-key", "sk-ant-1").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!tmp.exists());
+    }
+
+    #[test]
+    fn test_file_store_concurrent_sets() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::File(dir.path().join("secrets.json"));
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let store = &store;
+                scope.spawn(move || store.set(&format!("secret:{}", i), "x").unwrap());
+            }
+        });
+        assert_eq!(read_file(&dir.path().join("secrets.json")).unwrap().len(), 8);
+    }
 }
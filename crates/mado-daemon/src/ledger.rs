@@ -0,0 +1,232 @@
+//! Daemon-wide ledger of what chat turns cost, and the budget limits
+//! checked against it.
+//!
+//! Every finished turn adds its tokens and cost to the entry for its day and
+//! project (the session's working directory). The ledger is kept in
+//! `usage.json` next to state.json, so totals outlive daemon restarts and
+//! removed sessions. `GET /usage` reports it; the `budget` section of
+//! config.json sets daily and monthly limits in USD.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{Datelike, NaiveDate};
+
+use mado_core::types::{BudgetPeriod, TokenUsage, UsageEntry, UsageReport};
+
+use crate::config::BudgetConfig;
+
+/// Per-day, per-project usage of all sessions.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    /// Where the ledger is saved; kept in memory only when unset.
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<UsageEntry>>,
+}
+
+impl Ledger {
+    /// Load the ledger saved at `path`, starting empty if there is none or
+    /// it can't be read.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .inspect_err(|e| tracing::warn!("Unreadable usage ledger at {}: {}", path.display(), e))
+                .unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                tracing::warn!("Failed to read usage ledger at {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        Self {
+            path: Some(path),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Add a turn's usage and cost to `project`'s entry for `date`, and save.
+    pub fn record(&self, date: NaiveDate, project: &str, usage: &TokenUsage, cost_usd: f64) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let index = match entries.iter().position(|e| e.date == date && e.project == project) {
+            Some(index) => index,
+            None => {
+                entries.push(UsageEntry {
+                    date,
+                    project: project.to_string(),
+                    usage: TokenUsage::default(),
+                    cost_usd: 0.0,
+                    turns: 0,
+                });
+                entries.len() - 1
+            }
+        };
+        let entry = &mut entries[index];
+        crate::usage::add(&mut entry.usage, usage);
+        entry.cost_usd += cost_usd;
+        entry.turns += 1;
+
+        if let Some(path) = &self.path {
+            let result = serde_json::to_string_pretty(&*entries)
+                .map_err(|e| e.to_string())
+                .and_then(|json| crate::state::write_atomic(path, &json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                tracing::error!("Failed to save usage ledger: {}", e);
+            }
+        }
+    }
+
+    /// Entries from `since` on (all of them when `None`), oldest first, with
+    /// their totals.
+    pub fn report(&self, since: Option<NaiveDate>) -> UsageReport {
+        let mut entries: Vec<UsageEntry> = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|e| since.is_none_or(|since| e.date >= since))
+            .cloned()
+            .collect();
+        entries.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.project.cmp(&b.project)));
+
+        let mut total = TokenUsage::default();
+        for entry in &entries {
+            crate::usage::add(&mut total, &entry.usage);
+        }
+        UsageReport {
+            since,
+            total_cost_usd: entries.iter().map(|e| e.cost_usd).sum(),
+            total,
+            entries,
+        }
+    }
+
+    /// Forget all usage, and delete the saved ledger.
+    pub fn clear(&self) -> std::io::Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clear();
+        match &self.path {
+            Some(path) => match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Cost of all turns from `since` on.
+    pub fn spent_since(&self, since: NaiveDate) -> f64 {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|e| e.date >= since)
+            .map(|e| e.cost_usd)
+            .sum()
+    }
+}
+
+/// A budget limit whose warning level spending has reached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetAlert {
+    pub period: BudgetPeriod,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+    /// Spending has reached the limit itself.
+    pub exceeded: bool,
+}
+
+/// The most severe alert for spending as of `today`: an exceeded limit over
+/// one only warned about, the daily limit over the monthly one.
+pub fn check(budget: &BudgetConfig, ledger: &Ledger, today: NaiveDate) -> Option<BudgetAlert> {
+    let month_start = today.with_day(1).unwrap_or(today);
+    let limits = [
+        (BudgetPeriod::Daily, budget.daily_usd, today),
+        (BudgetPeriod::Monthly, budget.monthly_usd, month_start),
+    ];
+
+    let mut alert: Option<BudgetAlert> = None;
+    for (period, limit, since) in limits {
+        let Some(limit_usd) = limit.filter(|limit| *limit > 0.0) else {
+            continue;
+        };
+        let spent_usd = ledger.spent_since(since);
+        let exceeded = spent_usd >= limit_usd;
+        if !exceeded && spent_usd < limit_usd * budget.warn_at {
+            continue;
+        }
+        if alert.is_none_or(|a| exceeded && !a.exceeded) {
+            alert = Some(BudgetAlert {
+                period,
+                spent_usd,
+                limit_usd,
+                exceeded,
+            });
+        }
+    }
+    alert
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn usage(input: u64, output: u64) -> TokenUsage {
+        TokenUsage {
+            input_tokens: input,
+            output_tokens: output,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_and_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+
+        let ledger = Ledger::load(path.clone());
+        ledger.record(date("2026-03-01"), "/work/a", &usage(10, 5), 0.5);
+        ledger.record(date("2026-03-02"), "/work/b", &usage(20, 5), 1.0);
+        ledger.record(date("2026-03-02"), "/work/a", &usage(30, 5), 0.25);
+        ledger.record(date("2026-03-02"), "/work/a", &usage(40, 5), 0.25);
+
+        // Reloaded from disk.
+        let report = Ledger::load(path).report(Some(date("2026-03-02")));
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.entries[0].project, "/work/a");
+        assert_eq!(report.entries[0].turns, 2);
+        assert_eq!(report.entries[0].usage.input_tokens, 70);
+        assert_eq!(report.total.input_tokens, 90);
+        assert_eq!(report.total_cost_usd, 1.5);
+
+        assert_eq!(Ledger::default().report(None).entries.len(), 0);
+    }
+
+    #[test]
+    fn test_check() {
+        let ledger = Ledger::default();
+        ledger.record(date("2026-03-01"), "/work", &usage(1, 1), 6.0);
+        ledger.record(date("2026-03-15"), "/work", &usage(1, 1), 4.0);
+        let today = date("2026-03-15");
+
+        let mut budget = BudgetConfig::default();
+        assert_eq!(check(&budget, &ledger, today), None);
+
+        budget.daily_usd = Some(5.0);
+        let alert = check(&budget, &ledger, today).unwrap();
+        assert_eq!((alert.period, alert.spent_usd, alert.exceeded), (BudgetPeriod::Daily, 4.0, false));
+
+        // An exceeded monthly limit outranks a daily warning.
+        budget.monthly_usd = Some(10.0);
+        let alert = check(&budget, &ledger, today).unwrap();
+        assert_eq!((alert.period, alert.spent_usd, alert.exceeded), (BudgetPeriod::Monthly, 10.0, true));
+
+        budget.daily_usd = Some(20.0);
+        budget.monthly_usd = None;
+        assert_eq!(check(&budget, &ledger, today), None);
+    }
+}
@@ -1,11 +1,65 @@
+pub mod aider_history;
+pub mod attachments;
+pub mod audit;
+pub mod auth;
+pub mod claude_cli;
 pub mod claude_history;
+pub mod cli_error;
+pub mod cli_sync;
+pub mod codex_history;
+pub mod compact;
+pub mod compare;
 pub mod config;
+pub mod context;
+pub mod crash;
 pub mod conversation;
+pub mod error;
+pub mod export;
+pub mod feed;
+pub mod files;
+pub mod forge;
+pub mod gemini_history;
 pub mod git_ops;
+pub mod handover;
+pub mod history_import;
+pub mod idle;
 pub mod keystore;
+pub mod ledger;
+pub mod limits;
 pub mod lifecycle;
+pub mod logs;
+pub mod memory;
+pub mod metrics;
+pub mod openapi;
 pub mod pid;
+pub mod presence;
 pub mod process;
+pub mod process_group;
+pub mod project_config;
+pub mod prompts;
+pub mod purge;
+pub mod read_only;
+pub mod recording;
+pub mod redact;
+pub mod remote;
+pub mod replay;
+pub mod respawn;
+pub mod retention;
+pub mod review;
+pub mod runs;
+pub mod sandbox;
+pub mod scheduler;
+pub mod search;
 pub mod server;
+pub mod service;
 pub mod session;
+pub mod session_env;
+pub mod slash;
+pub mod snapshot;
 pub mod state;
+pub mod subagent;
+pub mod task;
+pub mod templates;
+pub mod usage;
+pub mod workspace_clone;
+pub mod ws;
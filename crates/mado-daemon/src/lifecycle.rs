@@ -1,13 +1,20 @@
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use tokio::signal;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tracing;
+use tracing_subscriber::{reload, EnvFilter, Registry};
 
+use mado_core::client::TOKEN_FILE_NAME;
+
+use crate::config::{DaemonSettings, MadoConfig, SharedSettings};
+use crate::handover::{Handover, SuccessorCommand};
 use crate::pid::{PidFile, PidError};
-use crate::server;
+use crate::remote::TcpListenConfig;
+use crate::server::{self, ServerOptions};
 use crate::state::DaemonState;
 
 /// Configuration for starting the daemon.
@@ -21,6 +28,42 @@ pub struct DaemonConfig {
     pub state_path: PathBuf,
     /// Whether to daemonize (double-fork into background).
     pub daemonize: bool,
+    /// Optional TCP listener for remote access, served alongside the socket.
+    pub listen: Option<TcpListenConfig>,
+    /// Command to start a successor for graceful restarts; disabled when unset.
+    pub successor: Option<SuccessorCommand>,
+    /// State inherited from a predecessor daemon restarting gracefully.
+    pub handover: Option<Handover>,
+    /// Respawn persisted sessions at startup (`claude --resume`) instead of
+    /// marking them terminated.
+    pub respawn_sessions: bool,
+    /// Log filter that `log_level` in config.json is applied to.
+    pub log_filter: Option<LogFilter>,
+    /// Directory the daemon logs to. Pruned to the `logs` limits in
+    /// config.json and served by `GET /logs`.
+    pub log_dir: Option<PathBuf>,
+}
+
+/// The daemon's log filter, adjustable at runtime.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub handle: reload::Handle<EnvFilter, Registry>,
+    /// Level from `--log-level`, used when the config sets none.
+    pub default_level: String,
+}
+
+impl LogFilter {
+    fn apply(&self, level: Option<&str>) {
+        let level = level.unwrap_or(&self.default_level);
+        match EnvFilter::try_new(level) {
+            Ok(filter) => {
+                if let Err(e) = self.handle.reload(filter) {
+                    tracing::warn!("Failed to set log level: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Invalid log level {:?}: {}", level, e),
+        }
+    }
 }
 
 impl DaemonConfig {
@@ -30,6 +73,26 @@ impl DaemonConfig {
             .parent()
             .expect("Socket path should have a parent directory")
     }
+
+    /// Path of the API token file, next to the socket.
+    pub fn token_path(&self) -> PathBuf {
+        self.base_dir().join(TOKEN_FILE_NAME)
+    }
+
+    /// Path of the app config, next to the socket.
+    pub fn config_path(&self) -> PathBuf {
+        self.base_dir().join("config.json")
+    }
+
+    /// Directory of crash reports, next to the socket.
+    pub fn crash_dir(&self) -> PathBuf {
+        self.base_dir().join("crashes")
+    }
+
+    /// Directory of archived sessions, next to the socket.
+    pub fn archive_dir(&self) -> PathBuf {
+        self.base_dir().join("archive")
+    }
 }
 
 /// Errors that can occur during daemon lifecycle management.
@@ -47,6 +110,12 @@ pub enum LifecycleError {
         source: std::io::Error,
     },
 
+    #[error("Failed to load API token from {path}: {source}")]
+    TokenFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[error("Daemonization failed: {0}")]
     DaemonizeFailed(String),
 }
@@ -104,16 +173,33 @@ pub async fn start_with_shutdown(
         tracing::debug!("Running in daemon mode (daemonized before tokio started)");
     }
 
-    // Step 3: Acquire PID file (prevents duplicates, cleans stale).
-    let _pid_file = PidFile::acquire(&config.pid_path, Some(&config.socket_path))?;
+    // Step 3: Acquire PID file (prevents duplicates, cleans stale). A
+    // successor takes it over from the daemon handing over to it.
+    let _pid_file = match &config.handover {
+        Some(handover) => PidFile::take_over(&config.pid_path, handover.predecessor)?,
+        None => PidFile::acquire(&config.pid_path, Some(&config.socket_path))?,
+    };
 
     // Step 4: Create a socket guard for cleanup on panic.
     let _socket_guard = SocketGuard::new(&config.socket_path);
 
-    // Step 5: Load existing state (best-effort -- if missing, start fresh).
+    // Step 5: Load the API token, generating it on first start.
+    let token_path = config.token_path();
+    let auth_token = crate::auth::load_or_create_token(&token_path)
+        .map_err(|source| LifecycleError::TokenFailed {
+            path: token_path,
+            source,
+        })?;
+
+    // Step 6: Load existing state (best-effort -- if missing, start fresh).
     let state_path = config.state_path.clone();
     let state = DaemonState::load(&state_path).unwrap_or_else(|e| {
         tracing::warn!("Failed to load state from {}: {}, starting fresh", state_path.display(), e);
+        match DaemonState::set_aside(&state_path) {
+            Ok(Some(backup)) => tracing::warn!("Kept the unreadable state as {}", backup.display()),
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to set aside unreadable state: {}", e),
+        }
         DaemonState::default()
     });
     tracing::info!("Loaded state with {} sessions", state.sessions.len());
@@ -121,7 +207,26 @@ pub async fn start_with_shutdown(
     // Wrap state in Arc<Mutex<>> for sharing with server and shutdown handler.
     let daemon_state = Arc::new(Mutex::new(state));
 
-    // Step 6: Start the server.
+    // Panics from here on leave a crash report.
+    let crash_dir = config.crash_dir();
+    let _crash_reporter =
+        crate::crash::install(crash_dir.clone(), config.log_dir.clone(), daemon_state.clone());
+    let autosave_task = crate::state::spawn_autosave(daemon_state.clone(), state_path.clone());
+
+    // Step 7: Load settings from config.json, reloaded on SIGHUP and when
+    // the file changes.
+    let settings = SharedSettings::default();
+    let config_path = config.config_path();
+    reload_settings(&config_path, &settings, config.log_filter.as_ref());
+    let (config_changes_tx, config_changes) = watch::channel(());
+    let reload_task =
+        spawn_reload_handler(config_path, settings.clone(), config.log_filter.clone(), config_changes_tx);
+    let prune_task = config
+        .log_dir
+        .clone()
+        .map(|dir| crate::logs::spawn_pruner(dir, settings.clone()));
+
+    // Step 8: Start the server.
     tracing::info!("Starting server on {}", config.socket_path.display());
 
     // Create a oneshot channel to signal when shutdown is requested.
@@ -142,10 +247,29 @@ pub async fn start_with_shutdown(
         let _ = shutdown_tx.send(());
     });
 
-    server::start_server(config.socket_path, state_path, daemon_state, async {
+    let archive_dir = config.archive_dir();
+    let options = ServerOptions {
+        auth_token: Some(auth_token),
+        tcp: config.listen,
+        successor: config.successor,
+        handover: config.handover,
+        respawn_sessions: config.respawn_sessions,
+        settings,
+        config_changes: Some(config_changes),
+        log_dir: config.log_dir,
+        crash_dir: Some(crash_dir),
+        archive_dir: Some(archive_dir),
+        conversations_dir: None,
+    };
+    server::start_server_with_options(config.socket_path, options, state_path, daemon_state, async {
         shutdown_rx.await.ok();
     })
     .await?;
+    reload_task.abort();
+    autosave_task.abort();
+    if let Some(prune_task) = prune_task {
+        prune_task.abort();
+    }
 
     tracing::info!("Daemon shut down cleanly");
     // _pid_file and _socket_guard are dropped here, cleaning up files.
@@ -161,9 +285,6 @@ async fn create_shutdown_signal() {
     let mut sigint =
         signal::unix::signal(signal::unix::SignalKind::interrupt())
             .expect("Failed to install SIGINT handler");
-    let mut sighup =
-        signal::unix::signal(signal::unix::SignalKind::hangup())
-            .expect("Failed to install SIGHUP handler");
 
     tokio::select! {
         _ = sigterm.recv() => {
@@ -172,20 +293,84 @@ async fn create_shutdown_signal() {
         _ = sigint.recv() => {
             tracing::info!("Received SIGINT, initiating graceful shutdown");
         }
-        _ = sighup.recv() => {
-            tracing::info!("Received SIGHUP (config reload not yet implemented), ignoring");
-            // For now, SIGHUP does nothing. In the future it could reload config.
-            // We need to wait for another signal.
+    }
+}
+
+/// How often config.json's modification time is checked. The app's settings
+/// page and hand edits save the file without signalling the daemon, so it is
+/// polled as well as reloaded on SIGHUP.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Re-read config.json (and the keystore) into `settings` and apply its log
+/// level. A config that fails to load leaves the current settings in place;
+/// returns whether the settings were replaced.
+pub fn reload_settings(config_path: &Path, settings: &SharedSettings, log_filter: Option<&LogFilter>) -> bool {
+    let config = match MadoConfig::read(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("Keeping current settings, {} failed to load: {}", config_path.display(), e);
+            return false;
+        }
+    };
+
+    if let Some(log_filter) = log_filter {
+        log_filter.apply(config.log_level.as_deref());
+    }
+    let new_settings = DaemonSettings::from_config(&config);
+    tracing::info!(
+        "Loaded settings from {} (default model {}, auto-milestone {})",
+        config_path.display(),
+        new_settings.default_model,
+        new_settings.auto_milestone
+    );
+    *settings.write().unwrap_or_else(|e| e.into_inner()) = new_settings;
+    true
+}
+
+/// When config.json was last modified, if it exists.
+fn modified(config_path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(config_path).and_then(|m| m.modified()).ok()
+}
+
+/// Reload settings on every SIGHUP and whenever config.json's modification
+/// time changes (checked every [`CONFIG_POLL_INTERVAL`]), notifying `changes`
+/// after each reload. Sessions are left untouched.
+pub fn spawn_reload_handler(
+    config_path: PathBuf,
+    settings: SharedSettings,
+    log_filter: Option<LogFilter>,
+    changes: watch::Sender<()>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sighup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(sighup) => Some(sighup),
+            Err(e) => {
+                tracing::warn!("Failed to install SIGHUP handler, reloading only on file changes: {}", e);
+                None
+            }
+        };
+        let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_modified = modified(&config_path);
+        loop {
             tokio::select! {
-                _ = sigterm.recv() => {
-                    tracing::info!("Received SIGTERM, initiating graceful shutdown");
+                Some(()) = async { sighup.as_mut()?.recv().await } => {
+                    tracing::info!("Received SIGHUP, reloading config");
                 }
-                _ = sigint.recv() => {
-                    tracing::info!("Received SIGINT, initiating graceful shutdown");
+                _ = interval.tick() => {
+                    let current = modified(&config_path);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    tracing::info!("{} changed, reloading config", config_path.display());
                 }
             }
+            if reload_settings(&config_path, &settings, log_filter.as_ref()) {
+                changes.send_replace(());
+            }
         }
-    }
+    })
 }
 
 /// Ensure the base directory exists with 0700 permissions.
@@ -212,7 +397,6 @@ fn ensure_base_dir(dir: &Path) -> Result<(), LifecycleError> {
 /// 2. setsid(): create new session (no controlling terminal).
 /// 3. Second fork: first child exits, grandchild continues (can never acquire terminal).
 /// 4. Redirect stdin/stdout/stderr to /dev/null.
-/// Daemonize the process (double-fork into background).
 ///
 /// **IMPORTANT:** This must be called BEFORE starting the tokio runtime,
 /// as forking after thread pool creation corrupts the runtime.
@@ -0,0 +1,315 @@
+//! Per-session CPU and memory limits.
+//!
+//! With `limits` set in config.json, a watchdog samples each session's
+//! processes (its PTY process or running `claude -p`, with everything they
+//! spawned) every few seconds using `ps`. A session over a limit has that
+//! process tree killed, and `ResourceLimitExceeded` is published on the
+//! activity feed; a killed PTY process is not respawned.
+//!
+//! CPU time includes processes that have since exited, as far as the samples
+//! saw them, so a loop of short-lived compiles still adds up. `claude -p`
+//! processes also get an `RLIMIT_CPU` of the limit.
+
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+use std::time::Duration;
+
+use mado_core::types::{ActivityKind, LimitedResource, SessionId};
+
+use crate::config::LimitsConfig;
+use crate::server::AppState;
+
+/// How often the watchdog samples processes.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// One process, as reported by `ps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessSample {
+    pub pid: u32,
+    pub ppid: u32,
+    /// Resident memory, in kilobytes.
+    pub rss_kb: u64,
+    /// CPU time used so far.
+    pub cpu: Duration,
+}
+
+/// Parse the output of `ps -A -o pid=,ppid=,rss=,time=`.
+pub fn parse_ps(output: &str) -> Vec<ProcessSample> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(ProcessSample {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                rss_kb: fields.next()?.parse().ok()?,
+                cpu: parse_cpu_time(fields.next()?)?,
+            })
+        })
+        .collect()
+}
+
+/// Parse a `ps` CPU time: `[[dd-]hh:]mm:ss`, with optional fractional
+/// seconds as macOS prints them.
+fn parse_cpu_time(time: &str) -> Option<Duration> {
+    let (days, clock) = match time.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, time),
+    };
+    let mut fields = clock.rsplit(':');
+    let seconds: f64 = fields.next()?.parse().ok()?;
+    let mut total = days * 86_400;
+    for (field, unit) in fields.zip([60, 3600]) {
+        total += field.parse::<u64>().ok()? * unit;
+    }
+    Some(Duration::from_secs(total) + Duration::from_secs_f64(seconds))
+}
+
+/// `root` and its descendants, `root` first.
+pub fn tree(samples: &[ProcessSample], root: u32) -> Vec<ProcessSample> {
+    let Some(root) = samples.iter().find(|sample| sample.pid == root) else {
+        return Vec::new();
+    };
+    let mut tree = vec![*root];
+    let mut pids = HashSet::from([root.pid]);
+    let mut index = 0;
+    while index < tree.len() {
+        let parent = tree[index].pid;
+        for sample in samples {
+            if sample.ppid == parent && pids.insert(sample.pid) {
+                tree.push(*sample);
+            }
+        }
+        index += 1;
+    }
+    tree
+}
+
+/// CPU time used by one process tree, including processes that have exited.
+#[derive(Debug, Default)]
+pub struct CpuAccount {
+    /// Latest CPU time of every process seen in the tree.
+    seen: HashMap<u32, Duration>,
+    /// CPU time of processes whose pid was later reused in the tree.
+    retired: Duration,
+}
+
+impl CpuAccount {
+    /// Add a sample of the tree and return its total CPU time so far.
+    pub fn update(&mut self, tree: &[ProcessSample]) -> Duration {
+        for sample in tree {
+            if let Some(previous) = self.seen.insert(sample.pid, sample.cpu)
+                && previous > sample.cpu
+            {
+                self.retired += previous;
+            }
+        }
+        self.retired + self.seen.values().sum::<Duration>()
+    }
+}
+
+/// The limit a process tree that used `cpu` and holds `memory_mb` is over,
+/// as an activity event.
+pub fn exceeded(limits: &LimitsConfig, cpu: Duration, memory_mb: u64) -> Option<ActivityKind> {
+    if let Some(limit) = limits.cpu_seconds
+        && cpu.as_secs() >= limit
+    {
+        return Some(ActivityKind::ResourceLimitExceeded {
+            resource: LimitedResource::Cpu,
+            limit,
+            used: cpu.as_secs(),
+        });
+    }
+    if let Some(limit) = limits.memory_mb
+        && memory_mb >= limit
+    {
+        return Some(ActivityKind::ResourceLimitExceeded {
+            resource: LimitedResource::Memory,
+            limit,
+            used: memory_mb,
+        });
+    }
+    None
+}
+
+/// Give a command's process a CPU time limit, past which it gets `SIGXCPU`.
+pub fn limit_cpu(cmd: &mut Command, seconds: u64) {
+    use std::os::unix::process::CommandExt;
+
+    let limit = libc::rlimit {
+        rlim_cur: seconds as libc::rlim_t,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    // Safety: setrlimit is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Which of a session's processes a tree belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Root {
+    Pty,
+    Response,
+}
+
+/// Spawn the background task that enforces `limits`.
+pub fn spawn_watchdog(state: AppState) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("limits-watchdog", move || watch(state.clone()))
+}
+
+async fn watch(state: AppState) {
+    let mut interval = tokio::time::interval(WATCHDOG_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut accounts = HashMap::new();
+
+    loop {
+        interval.tick().await;
+        let limits = state.settings.read().unwrap_or_else(|e| e.into_inner()).limits.clone();
+        if limits.cpu_seconds.is_none() && limits.memory_mb.is_none() {
+            accounts.clear();
+            continue;
+        }
+        check(&state, &limits, &mut accounts).await;
+    }
+}
+
+/// Sample every session's processes and kill the trees over a limit.
+async fn check(
+    state: &AppState,
+    limits: &LimitsConfig,
+    accounts: &mut HashMap<(SessionId, Root, u32), CpuAccount>,
+) {
+    let mut roots: Vec<_> = state
+        .session_manager
+        .process_pids()
+        .await
+        .into_iter()
+        .map(|(id, pid)| (id, Root::Pty, pid))
+        .collect();
+    roots.extend(
+        state
+            .conversation_manager
+            .response_pids()
+            .await
+            .into_iter()
+            .map(|(id, pid)| (id, Root::Response, pid)),
+    );
+    // A new process (e.g. after a restart) starts a new account.
+    accounts.retain(|key, _| roots.contains(key));
+    if roots.is_empty() {
+        return;
+    }
+
+    let output = match tokio::process::Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid=,rss=,time="])
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::warn!("Failed to list processes: {}", e);
+            return;
+        }
+    };
+    let samples = parse_ps(&String::from_utf8_lossy(&output.stdout));
+
+    for key in roots {
+        let tree = tree(&samples, key.2);
+        if tree.is_empty() {
+            continue;
+        }
+        let cpu = accounts.entry(key.clone()).or_default().update(&tree);
+        let memory_mb = tree.iter().map(|sample| sample.rss_kb).sum::<u64>() / 1024;
+        let Some(event) = exceeded(limits, cpu, memory_mb) else {
+            continue;
+        };
+
+        let (session_id, root, _) = key;
+        tracing::warn!("Session {} went over a resource limit, killing it: {:?}", session_id, event);
+        // The session's manager kills the root itself.
+        for sample in &tree[1..] {
+            unsafe {
+                libc::kill(sample.pid as i32, libc::SIGKILL);
+            }
+        }
+        let result = match root {
+            Root::Pty => state.session_manager.terminate_process(&session_id).await.map_err(|e| e.to_string()),
+            Root::Response => state
+                .conversation_manager
+                .cancel_response(&session_id)
+                .await
+                .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to stop session {}: {}", session_id, e);
+        }
+        state.activity_feed.publish(&session_id, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(pid: u32, ppid: u32, cpu_secs: u64) -> ProcessSample {
+        ProcessSample {
+            pid,
+            ppid,
+            rss_kb: 1024,
+            cpu: Duration::from_secs(cpu_secs),
+        }
+    }
+
+    #[test]
+    fn test_parse_ps() {
+        let output = "    1     0  9000 00:01:02\n  42     1   512 1-02:00:00\n  43    42   100 1:02.50\nbad line\n";
+        let samples = parse_ps(output);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0], ProcessSample { pid: 1, ppid: 0, rss_kb: 9000, cpu: Duration::from_secs(62) });
+        assert_eq!(samples[1].cpu, Duration::from_secs(86_400 + 2 * 3600));
+        assert_eq!(samples[2].cpu, Duration::from_millis(62_500));
+    }
+
+    #[test]
+    fn test_tree_and_cpu_account() {
+        let samples = vec![sample(1, 0, 100), sample(10, 1, 5), sample(11, 10, 3), sample(12, 1, 1)];
+        let pids: Vec<u32> = tree(&samples, 10).iter().map(|s| s.pid).collect();
+        assert_eq!(pids, vec![10, 11]);
+        assert!(tree(&samples, 99).is_empty());
+
+        let mut account = CpuAccount::default();
+        assert_eq!(account.update(&tree(&samples, 10)), Duration::from_secs(8));
+        // The child exits; its time still counts.
+        assert_eq!(account.update(&[sample(10, 1, 6)]), Duration::from_secs(9));
+        // Its pid is reused by a new child.
+        assert_eq!(account.update(&[sample(10, 1, 6), sample(11, 10, 1)]), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_exceeded() {
+        let limits = LimitsConfig {
+            cpu_seconds: Some(60),
+            memory_mb: Some(512),
+        };
+        assert_eq!(exceeded(&limits, Duration::from_secs(59), 511), None);
+        assert_eq!(
+            exceeded(&limits, Duration::from_secs(61), 0),
+            Some(ActivityKind::ResourceLimitExceeded {
+                resource: LimitedResource::Cpu,
+                limit: 60,
+                used: 61,
+            })
+        );
+        assert!(matches!(
+            exceeded(&limits, Duration::ZERO, 600),
+            Some(ActivityKind::ResourceLimitExceeded { resource: LimitedResource::Memory, used: 600, .. })
+        ));
+        assert_eq!(exceeded(&LimitsConfig::default(), Duration::from_secs(1 << 20), 1 << 20), None);
+    }
+}
@@ -0,0 +1,266 @@
+//! Daemon log files.
+//!
+//! The daemon appends to `~/.mado/logs/daemon.log.YYYY-MM-DD`, starting a new
+//! file each day, so the newest file sorts last. A background task prunes old
+//! files to the limits in config.json (`logs.max_size_mb`,
+//! `logs.retention_days`).
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::Level;
+
+use crate::config::{LogsConfig, SharedSettings};
+
+/// File name prefix of the daily log files.
+pub const LOG_FILE_PREFIX: &str = "daemon.log";
+
+/// How often the log directory is checked against the configured limits.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Directory the daemon logs to (~/.mado/logs/).
+pub fn log_dir() -> PathBuf {
+    dirs::home_dir()
+        .map(|h| h.join(".mado").join("logs"))
+        .unwrap_or_else(|| PathBuf::from("/tmp/mado-logs"))
+}
+
+/// The log file currently written to, if any.
+pub fn latest_log_file(dir: &Path) -> Option<PathBuf> {
+    log_files(dir).pop()
+}
+
+/// All daemon log files in `dir`, oldest first.
+fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_FILE_PREFIX))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Bring the log directory within `limits`: delete files older than the
+/// retention period, then the oldest files until the total fits. The current
+/// file is never deleted, but is truncated if it alone exceeds the limit.
+/// Returns the number of files deleted.
+pub fn prune(dir: &Path, limits: &LogsConfig) -> std::io::Result<usize> {
+    let mut files = log_files(dir);
+    let Some(current) = files.pop() else {
+        return Ok(0);
+    };
+
+    let max_bytes = limits.max_size_mb.saturating_mul(1024 * 1024);
+    let retention = Duration::from_secs(u64::from(limits.retention_days) * 24 * 60 * 60);
+    let now = SystemTime::now();
+
+    let mut removed = 0;
+    let mut kept = Vec::new();
+    for path in files {
+        let metadata = std::fs::metadata(&path)?;
+        let expired = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > retention);
+        if expired {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        } else {
+            kept.push((path, metadata.len()));
+        }
+    }
+
+    let current_len = std::fs::metadata(&current)?.len();
+    let mut total: u64 = current_len + kept.iter().map(|(_, len)| len).sum::<u64>();
+    for (path, len) in kept {
+        if total <= max_bytes {
+            break;
+        }
+        std::fs::remove_file(&path)?;
+        total -= len;
+        removed += 1;
+    }
+
+    // The appender writes in append mode, so it carries on at the new end.
+    if current_len > max_bytes {
+        File::options().write(true).open(&current)?.set_len(0)?;
+        tracing::warn!(
+            "Truncated {} ({} bytes) to stay within the {} MB log limit",
+            current.display(),
+            current_len,
+            limits.max_size_mb
+        );
+    }
+    Ok(removed)
+}
+
+/// Delete every log file in `dir` but the current one, which is emptied.
+/// Returns the files deleted or emptied.
+pub fn clear(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = log_files(dir);
+    let Some(current) = files.pop() else {
+        return Ok(Vec::new());
+    };
+    for path in &files {
+        std::fs::remove_file(path)?;
+    }
+    File::options().write(true).open(&current)?.set_len(0)?;
+    files.push(current);
+    Ok(files)
+}
+
+/// Prune `dir` now and then hourly, with the limits current at each run.
+pub fn spawn_pruner(dir: PathBuf, settings: SharedSettings) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("log-pruner", move || {
+        prune_periodically(dir.clone(), settings.clone())
+    })
+}
+
+async fn prune_periodically(dir: PathBuf, settings: SharedSettings) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let limits = settings.read().unwrap_or_else(|e| e.into_inner()).logs.clone();
+        let dir = dir.clone();
+        match tokio::task::spawn_blocking(move || prune(&dir, &limits)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => tracing::info!("Removed {} old log file(s)", removed),
+            Ok(Err(e)) => tracing::warn!("Failed to prune logs: {}", e),
+            Err(e) => tracing::warn!("Log pruning task failed: {}", e),
+        }
+    }
+}
+
+/// The last `count` lines of the current log file at `min_level` or more
+/// severe. Lines without a level (continuations of multi-line messages)
+/// follow the line they continue.
+pub fn recent(dir: &Path, count: usize, min_level: Option<Level>) -> std::io::Result<Vec<String>> {
+    let Some(path) = latest_log_file(dir) else {
+        return Ok(Vec::new());
+    };
+    let Some(min_level) = min_level else {
+        return tail(&path, count);
+    };
+
+    let mut lines = VecDeque::with_capacity(count);
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut included = false;
+    for line in BufReader::new(File::open(&path)?).lines() {
+        let line = line?;
+        if let Some(level) = line_level(&line) {
+            // `Level` orders by verbosity: ERROR is the smallest.
+            included = level <= min_level;
+        }
+        if !included {
+            continue;
+        }
+        if lines.len() == count {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+    Ok(lines.into())
+}
+
+/// Level of a formatted log line (`<timestamp> <LEVEL> <target>: ...`).
+fn line_level(line: &str) -> Option<Level> {
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// The last `count` lines of a file.
+pub fn tail(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    let mut lines = VecDeque::with_capacity(count);
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    for line in BufReader::new(File::open(path)?).lines() {
+        if lines.len() == count {
+            lines.pop_front();
+        }
+        lines.push_back(line?);
+    }
+    Ok(lines.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_log_file_and_tail() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(latest_log_file(dir.path()).is_none());
+
+        std::fs::write(dir.path().join("daemon.log.2026-01-01"), "old\n").unwrap();
+        std::fs::write(dir.path().join("daemon.log.2026-01-02"), "a\nb\nc\n").unwrap();
+        std::fs::write(dir.path().join("other.txt"), "x\n").unwrap();
+
+        let latest = latest_log_file(dir.path()).unwrap();
+        assert!(latest.ends_with("daemon.log.2026-01-02"));
+        assert_eq!(tail(&latest, 2).unwrap(), vec!["b", "c"]);
+        assert_eq!(tail(&latest, 10).unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_recent_filters_by_level() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("daemon.log.2026-01-01"),
+            "2026-01-01T00:00:00Z  INFO mado_daemon: started\n\
+             2026-01-01T00:00:01Z ERROR mado_daemon::process: spawn failed\n\
+             caused by: not found\n\
+             2026-01-01T00:00:02Z DEBUG mado_daemon::server: request\n\
+             2026-01-01T00:00:03Z  WARN mado_daemon::git: slow status\n",
+        )
+        .unwrap();
+
+        let warnings = recent(dir.path(), 10, Some(Level::WARN)).unwrap();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("spawn failed"));
+        assert_eq!(warnings[1], "caused by: not found");
+        assert!(warnings[2].contains("slow status"));
+
+        assert_eq!(recent(dir.path(), 2, None).unwrap().len(), 2);
+        assert_eq!(recent(dir.path(), 1, Some(Level::ERROR)).unwrap(), vec!["caused by: not found"]);
+    }
+
+    #[test]
+    fn test_prune_enforces_size_limit_but_keeps_current_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let megabyte = vec![b'x'; 1024 * 1024];
+        for day in 1..=3 {
+            std::fs::write(dir.path().join(format!("daemon.log.2026-01-0{}", day)), &megabyte)
+                .unwrap();
+        }
+
+        let limits = LogsConfig {
+            max_size_mb: 2,
+            retention_days: 30,
+        };
+        assert_eq!(prune(dir.path(), &limits).unwrap(), 1);
+        assert!(!dir.path().join("daemon.log.2026-01-01").exists());
+        assert!(dir.path().join("daemon.log.2026-01-02").exists());
+
+        let limits = LogsConfig {
+            max_size_mb: 0,
+            retention_days: 30,
+        };
+        assert_eq!(prune(dir.path(), &limits).unwrap(), 1);
+        let current = dir.path().join("daemon.log.2026-01-03");
+        assert_eq!(std::fs::metadata(current).unwrap().len(), 0);
+    }
+}
@@ -1,104 +1,342 @@
+use std::ffi::OsString;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
+use std::time::Duration;
 
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use clap::{Args, Parser, Subcommand};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
 
-use mado_core::client::{default_pid_path, default_socket_path, default_state_path};
-use mado_daemon::lifecycle::{daemonize, DaemonConfig, start};
+use mado_core::client::{default_pid_path, default_socket_path, default_state_path, DaemonClient};
+use mado_daemon::handover::{Handover, SuccessorCommand};
+use mado_daemon::lifecycle::{daemonize, DaemonConfig, LogFilter, start};
+use mado_daemon::logs;
+use mado_daemon::redact::Redacting;
+use mado_daemon::remote::{parse_listen_addr, TcpListenConfig, TlsFiles};
+use mado_daemon::service::{ServiceManager, ServiceSpec};
 
-/// CLI arguments for the daemon.
+/// How often `logs --follow` checks for new output.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Background daemon for Mado - manages Claude CLI sessions.
+#[derive(Debug, Parser)]
+#[command(name = "mado-daemon", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    daemon: DaemonArgs,
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+enum Command {
+    /// Run the daemon (the default when no command is given).
+    Start,
+    /// Stop the running daemon.
+    Stop,
+    /// Print the running daemon's health.
+    Status,
+    /// Stop the running daemon, then start it again.
+    Restart {
+        /// Hand live sessions over to a new daemon process instead of
+        /// stopping them.
+        #[arg(long)]
+        graceful: bool,
+    },
+    /// Print the end of the daemon log.
+    Logs {
+        /// Keep printing lines as they are written.
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to print.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        lines: usize,
+    },
+    /// Run this binary as a launchd (macOS) or systemd (Linux) user service.
+    InstallService,
+    /// Stop and remove the service.
+    UninstallService,
+    /// Report whether the service is installed and running.
+    ServiceStatus,
+}
+
+/// Options for running the daemon, accepted before or after the command.
+#[derive(Debug, Args)]
 struct DaemonArgs {
     /// Path to the Unix domain socket.
+    #[arg(long, global = true, value_name = "PATH", default_value_os_t = default_socket_path())]
     socket_path: PathBuf,
     /// Path to the PID file.
+    #[arg(long, global = true, value_name = "PATH", default_value_os_t = default_pid_path())]
     pid_path: PathBuf,
     /// Path to the state file.
+    #[arg(long, global = true, value_name = "PATH", default_value_os_t = default_state_path())]
     state_path: PathBuf,
-    /// Run in foreground (don't daemonize). Default: true.
+    /// Run in the foreground (the default).
+    #[arg(long, global = true, overrides_with = "daemonize")]
     foreground: bool,
-    /// Log level filter.
+    /// Detach into the background.
+    #[arg(long, global = true, overrides_with = "foreground")]
+    daemonize: bool,
+    /// Log filter, e.g. "debug" or "mado_daemon=trace".
+    #[arg(long, global = true, value_name = "FILTER", default_value = "info")]
     log_level: String,
+    /// Also serve the API on a TCP address (`tcp://HOST:PORT`).
+    #[arg(long, global = true, value_name = "ADDR")]
+    listen: Option<String>,
+    /// TLS certificate chain (PEM) for the TCP listener.
+    #[arg(long, global = true, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// TLS private key (PEM) for the TCP listener.
+    #[arg(long, global = true, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Respawn persisted sessions at startup instead of marking them terminated.
+    #[arg(long, global = true)]
+    respawn_sessions: bool,
 }
 
 impl DaemonArgs {
-    fn parse() -> Self {
-        let mut args = std::env::args().skip(1);
-        let mut socket_path = None;
-        let mut pid_path = None;
-        let mut state_path = None;
-        let mut foreground = true;
-        let mut log_level = String::from("info");
-
-        while let Some(arg) = args.next() {
-            match arg.as_str() {
-                "--socket-path" => {
-                    socket_path = args.next().map(PathBuf::from);
-                }
-                "--pid-path" => {
-                    pid_path = args.next().map(PathBuf::from);
-                }
-                "--state-path" => {
-                    state_path = args.next().map(PathBuf::from);
-                }
-                "--foreground" => {
-                    foreground = true;
-                }
-                "--daemonize" => {
-                    foreground = false;
-                }
-                "--log-level" => {
-                    if let Some(level) = args.next() {
-                        log_level = level;
-                    }
-                }
-                other => {
-                    eprintln!("Unknown argument: {}", other);
-                    std::process::exit(1);
-                }
-            }
+    /// Arguments that run a daemon like this one in the foreground, with
+    /// every path spelled out (for a service definition or a successor).
+    fn foreground_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--foreground".to_string(),
+            "--socket-path".to_string(),
+            self.socket_path.display().to_string(),
+            "--pid-path".to_string(),
+            self.pid_path.display().to_string(),
+            "--state-path".to_string(),
+            self.state_path.display().to_string(),
+            "--log-level".to_string(),
+            self.log_level.clone(),
+        ];
+        if let Some(listen) = &self.listen {
+            args.extend(["--listen".to_string(), listen.clone()]);
         }
-
-        Self {
-            socket_path: socket_path.unwrap_or_else(default_socket_path),
-            pid_path: pid_path.unwrap_or_else(default_pid_path),
-            state_path: state_path.unwrap_or_else(default_state_path),
-            foreground,
-            log_level,
+        if let Some(cert) = &self.tls_cert {
+            args.extend(["--tls-cert".to_string(), cert.display().to_string()]);
+        }
+        if let Some(key) = &self.tls_key {
+            args.extend(["--tls-key".to_string(), key.display().to_string()]);
         }
+        if self.respawn_sessions {
+            args.push("--respawn-sessions".to_string());
+        }
+        args
+    }
+
+    /// Build the TCP listener config from `--listen` and its companion flags.
+    fn tcp_config(&self) -> Result<Option<TcpListenConfig>, String> {
+        let Some(listen) = &self.listen else {
+            return Ok(None);
+        };
+        let addr = parse_listen_addr(listen).map_err(|e| e.to_string())?;
+
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert_path), Some(key_path)) => Some(TlsFiles {
+                cert_path: cert_path.clone(),
+                key_path: key_path.clone(),
+            }),
+            (None, None) => None,
+            _ => return Err("--tls-cert and --tls-key must be given together".to_string()),
+        };
+
+        Ok(Some(TcpListenConfig { addr, tls }))
     }
 }
 
 fn main() {
-    let args = DaemonArgs::parse();
+    // Before anything else: reading the handover edits the environment.
+    let handover = match Handover::from_env() {
+        Ok(handover) => handover,
+        Err(e) => {
+            eprintln!("Failed to read handover from previous daemon: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let cli = Cli::parse();
+    let args = cli.daemon;
+
+    let result = match cli.command.unwrap_or(Command::Start) {
+        Command::Start => run_daemon(args, handover),
+        Command::Stop => block_on(stop(&args)),
+        Command::Status => block_on(status(&args)),
+        Command::Restart { graceful: true } => block_on(graceful_restart(&args)),
+        Command::Restart { graceful: false } => {
+            block_on(stop(&args)).and_then(|()| run_daemon(args, handover))
+        }
+        Command::Logs { follow, lines } => print_logs(follow, lines),
+        Command::InstallService => install_service(&args),
+        Command::UninstallService => uninstall_service(),
+        Command::ServiceStatus => service_status(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Run a client-side command on a small runtime.
+fn block_on<F: std::future::Future<Output = Result<(), String>>>(future: F) -> Result<(), String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to create tokio runtime: {}", e))?
+        .block_on(future)
+}
+
+/// Run the daemon itself, detaching first unless `--foreground`.
+fn run_daemon(args: DaemonArgs, handover: Option<Handover>) -> Result<(), String> {
+    // Validate the TCP listener config before daemonizing so errors reach the terminal.
+    let listen = args.tcp_config()?;
 
     // CRITICAL: Daemonize BEFORE starting tokio runtime.
     // Forking after tokio starts corrupts the thread pool.
-    if !args.foreground {
-        if let Err(e) = daemonize() {
-            eprintln!("Failed to daemonize: {}", e);
-            std::process::exit(1);
-        }
+    if args.daemonize && handover.is_none() {
+        daemonize().map_err(|e| format!("Failed to daemonize: {}", e))?;
     }
 
     // Now start tokio runtime (after fork if daemonized).
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
-        .expect("Failed to create tokio runtime")
-        .block_on(async_main(args));
+        .map_err(|e| format!("Failed to create tokio runtime: {}", e))?
+        .block_on(async_main(args, listen, handover));
+    Ok(())
+}
+
+/// Stop the daemon whose PID is in the PID file.
+async fn stop(args: &DaemonArgs) -> Result<(), String> {
+    match DaemonClient::stop_daemon(&args.pid_path).await {
+        Ok(Some(pid)) => println!("Stopped daemon (pid {})", pid),
+        Ok(None) => println!("Daemon is not running"),
+        Err(e) => return Err(e.to_string()),
+    }
+    Ok(())
+}
+
+/// Ping the daemon and print its health.
+async fn status(args: &DaemonArgs) -> Result<(), String> {
+    let client = DaemonClient::new(&args.socket_path);
+    let status = client
+        .health()
+        .await
+        .map_err(|e| format!("Daemon is not running at {}: {}", args.socket_path.display(), e))?;
+
+    println!("running: pid {}", status.pid);
+    println!("version: {} (protocol {})", status.version, status.protocol_version);
+    println!("uptime: {}s", status.uptime);
+    println!("sessions: {}", status.session_count);
+    println!("socket: {}", args.socket_path.display());
+    Ok(())
+}
+
+/// Ask the running daemon to hand over to a new process.
+async fn graceful_restart(args: &DaemonArgs) -> Result<(), String> {
+    let client = DaemonClient::new(&args.socket_path);
+    let pid = client.graceful_restart().await.map_err(|e| e.to_string())?;
+    println!("Handed over to daemon (pid {})", pid);
+    Ok(())
+}
+
+/// Print the end of the current log file, then follow it (across daily
+/// rotation) if asked to.
+fn print_logs(follow: bool, lines: usize) -> Result<(), String> {
+    let dir = logs::log_dir();
+    let mut current = logs::latest_log_file(&dir);
+    let mut position = 0;
+
+    if let Some(path) = &current {
+        let tail = logs::tail(path, lines)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        for line in tail {
+            println!("{}", line);
+        }
+        position = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    } else if !follow {
+        return Err(format!("No daemon logs in {}", dir.display()));
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        std::thread::sleep(FOLLOW_POLL_INTERVAL);
+
+        let latest = logs::latest_log_file(&dir);
+        if latest != current {
+            current = latest;
+            position = 0;
+        }
+        let Some(path) = &current else {
+            continue;
+        };
+        let Ok(mut file) = std::fs::File::open(path) else {
+            continue;
+        };
+        if file.seek(SeekFrom::Start(position)).is_err() {
+            continue;
+        }
+        let mut new_output = String::new();
+        if let Ok(read) = file.read_to_string(&mut new_output) {
+            position += read as u64;
+            print!("{}", new_output);
+        }
+    }
+}
+
+/// Install and start the launchd/systemd service for this binary.
+fn install_service(args: &DaemonArgs) -> Result<(), String> {
+    args.tcp_config()?;
+    let manager = ServiceManager::current().map_err(|e| e.to_string())?;
+    let binary = std::env::current_exe()
+        .and_then(|exe| exe.canonicalize())
+        .map_err(|e| format!("Failed to locate the daemon binary: {}", e))?;
+    let spec = ServiceSpec {
+        binary,
+        args: args.foreground_args(),
+    };
+    let path = manager.install(&spec).map_err(|e| e.to_string())?;
+    println!("Installed and started service: {}", path.display());
+    Ok(())
+}
+
+fn uninstall_service() -> Result<(), String> {
+    let manager = ServiceManager::current().map_err(|e| e.to_string())?;
+    if manager.uninstall().map_err(|e| e.to_string())? {
+        println!("Service removed");
+    } else {
+        println!("Service is not installed");
+    }
+    Ok(())
+}
+
+fn service_status() -> Result<(), String> {
+    let manager = ServiceManager::current().map_err(|e| e.to_string())?;
+    let status = manager.status().map_err(|e| e.to_string())?;
+    if status.installed {
+        println!("installed: {}", status.path.display());
+        println!("running: {}", if status.running { "yes" } else { "no" });
+    } else {
+        println!("not installed");
+    }
+    Ok(())
 }
 
-async fn async_main(args: DaemonArgs) {
+async fn async_main(args: DaemonArgs, listen: Option<TcpListenConfig>, handover: Option<Handover>) {
     // Set up tracing/logging with file appender.
+    // Reloadable, so `log_level` in config.json can change it on SIGHUP.
     let filter = EnvFilter::try_new(&args.log_level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, filter_handle) = reload::Layer::new(filter);
 
     // Create log directory.
-    let log_dir = dirs::home_dir()
-        .map(|h| h.join(".mado").join("logs"))
-        .unwrap_or_else(|| PathBuf::from("/tmp/mado-logs"));
+    let log_dir = logs::log_dir();
     std::fs::create_dir_all(&log_dir).ok();
 
     // File appender - writes to ~/.mado/logs/daemon.log.
-    let file_appender = tracing_appender::rolling::daily(&log_dir, "daemon.log");
+    let file_appender = tracing_appender::rolling::daily(&log_dir, logs::LOG_FILE_PREFIX);
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
     // Keep the guard alive for the duration of the program.
@@ -107,8 +345,8 @@ async fn async_main(args: DaemonArgs) {
 
     tracing_subscriber::registry()
         .with(filter)
-        .with(fmt::layer().with_writer(non_blocking))
-        .with(fmt::layer().with_writer(std::io::stdout))
+        .with(fmt::layer().with_writer(Redacting(non_blocking)).with_ansi(false))
+        .with(fmt::layer().with_writer(Redacting(std::io::stdout)))
         .init();
 
     tracing::info!(
@@ -120,18 +358,32 @@ async fn async_main(args: DaemonArgs) {
     tracing::info!("PID path: {}", args.pid_path.display());
     tracing::info!(
         "Mode: {}",
-        if args.foreground {
-            "foreground"
-        } else {
+        if args.daemonize {
             "daemon"
+        } else {
+            "foreground"
         }
     );
 
+    // The successor of a graceful restart runs in the foreground: this
+    // process is already detached.
+    let successor_args = args.foreground_args().into_iter().map(OsString::from).collect();
     let config = DaemonConfig {
         socket_path: args.socket_path,
         pid_path: args.pid_path,
         state_path: args.state_path,
-        daemonize: !args.foreground,
+        daemonize: args.daemonize,
+        listen,
+        successor: SuccessorCommand::current(successor_args)
+            .inspect_err(|e| tracing::warn!("Graceful restart unavailable: {}", e))
+            .ok(),
+        handover,
+        respawn_sessions: args.respawn_sessions,
+        log_filter: Some(LogFilter {
+            handle: filter_handle,
+            default_level: args.log_level,
+        }),
+        log_dir: Some(log_dir),
     };
 
     if let Err(e) = start(config).await {
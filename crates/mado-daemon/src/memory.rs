@@ -0,0 +1,179 @@
+//! The project memory file, `CLAUDE.md`, which the Claude CLI reads into
+//! every session started in the project.
+//!
+//! It lives at the root of the session's repository (its working directory
+//! outside of one). A project that keeps `AGENTS.md` instead, and no
+//! `CLAUDE.md`, has that edited. Entries added with "remember this" are
+//! appended as list items.
+//!
+//! Suggestions are entries the session's model distills from the recent
+//! conversation, mostly from the user's corrections. They wait in
+//! `<session>/memory_suggestions.json` under the conversation storage
+//! directory until the user accepts (appends) or dismisses them.
+
+use std::path::{Path, PathBuf};
+
+use mado_core::types::{MemoryFile, MemorySuggestion, Message};
+
+use crate::files::FilesError;
+
+/// Memory file names, in order of preference.
+pub const FILE_NAMES: [&str; 2] = ["CLAUDE.md", "AGENTS.md"];
+
+/// Latest messages the model reads for suggestions.
+pub const SUGGESTION_MESSAGES: usize = 20;
+
+/// Most suggestions asked for at a time.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Directory the memory file of a session working in `working_dir` is in.
+pub fn root(working_dir: &Path) -> PathBuf {
+    crate::git_ops::discover_repo_root(working_dir).unwrap_or_else(|| working_dir.to_path_buf())
+}
+
+/// Name of the memory file in `root`: the first of [`FILE_NAMES`] there is,
+/// or `CLAUDE.md` to create.
+fn file_name(root: &Path) -> &'static str {
+    FILE_NAMES
+        .into_iter()
+        .find(|name| root.join(name).is_file())
+        .unwrap_or(FILE_NAMES[0])
+}
+
+/// The memory file in `root`; empty, with an empty ETag, if there is none.
+pub fn read(root: &Path) -> Result<MemoryFile, FilesError> {
+    let name = file_name(root);
+    let path = root.join(name).display().to_string();
+    match crate::files::read(root, name) {
+        Ok(file) => Ok(MemoryFile {
+            path,
+            content: file.content,
+            etag: file.etag,
+        }),
+        Err(FilesError::NotFound(_)) => Ok(MemoryFile {
+            path,
+            content: String::new(),
+            etag: String::new(),
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replace the memory file in `root`, creating it if needed. With
+/// `expected_etag`, only if it is unchanged since it was read.
+pub fn write(root: &Path, content: &str, expected_etag: Option<&str>) -> Result<MemoryFile, FilesError> {
+    let name = file_name(root);
+    let etag = crate::files::write(root, name, content, expected_etag)?;
+    Ok(MemoryFile {
+        path: root.join(name).display().to_string(),
+        content: content.to_string(),
+        etag,
+    })
+}
+
+/// Add `entry` to the memory file in `root` as a list item of one line.
+pub fn append(root: &Path, entry: &str) -> Result<MemoryFile, FilesError> {
+    let memory = read(root)?;
+    let mut content = memory.content;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("- {}\n", one_line(entry)));
+    write(root, &content, Some(&memory.etag))
+}
+
+/// `entry` without list markers, on one line.
+pub fn one_line(entry: &str) -> String {
+    let entry = entry.trim();
+    let entry = entry.strip_prefix("- ").or_else(|| entry.strip_prefix("* ")).unwrap_or(entry);
+    entry.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The prompt asking for memory entries from `messages`, given the memory
+/// file's `current` content.
+pub fn suggestion_prompt(messages: &[Message], current: &str) -> String {
+    let mut prompt = crate::context::transcript(messages);
+    if !current.trim().is_empty() {
+        prompt.push_str(&format!("<memory>\n{}\n</memory>\n\n", current.trim_end()));
+    }
+    prompt.push_str(&format!(
+        "Above is a conversation with a coding assistant{}. List at most {} short, lasting \
+         instructions for the project's memory file that would have saved the user from \
+         correcting the assistant: conventions, preferences, commands. Leave out anything \
+         already in the memory file or only about this task. Reply with one per line, each \
+         starting with \"- \", or with NONE.",
+        if current.trim().is_empty() { "" } else { " and the project's memory file" },
+        MAX_SUGGESTIONS
+    ));
+    prompt
+}
+
+/// The entries of a reply to [`suggestion_prompt`].
+pub fn parse_suggestions(reply: &str) -> Vec<String> {
+    reply
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("- ") || line.starts_with("* "))
+        .map(one_line)
+        .filter(|entry| !entry.is_empty())
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// File the pending suggestions of session `session_id` are kept in.
+pub fn suggestions_path(storage_dir: &Path, session_id: &str) -> PathBuf {
+    storage_dir.join(session_id).join("memory_suggestions.json")
+}
+
+/// Pending suggestions at `path`, oldest first; none if unreadable.
+pub fn load_suggestions(path: &Path) -> Vec<MemorySuggestion> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Keep `suggestions` at `path`, removing the file once there are none.
+pub fn save_suggestions(path: &Path, suggestions: &[MemorySuggestion]) -> std::io::Result<()> {
+    if suggestions.is_empty() {
+        return match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string(suggestions).map_err(std::io::Error::other)?;
+    crate::state::write_atomic(path, &json).map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_append_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = read(dir.path()).unwrap();
+        assert!(memory.path.ends_with("CLAUDE.md"));
+        assert!(memory.content.is_empty() && memory.etag.is_empty());
+
+        std::fs::write(dir.path().join("AGENTS.md"), "# Agents").unwrap();
+        let memory = append(dir.path(), "- Use  tabs\nfor Makefiles").unwrap();
+        assert!(memory.path.ends_with("AGENTS.md"));
+        assert_eq!(memory.content, "# Agents\n- Use tabs for Makefiles\n");
+
+        let stale = write(dir.path(), "Other", Some("stale"));
+        assert!(matches!(stale, Err(FilesError::Changed { .. })), "{:?}", stale);
+        write(dir.path(), "Other\n", Some(&memory.etag)).unwrap();
+        assert_eq!(read(dir.path()).unwrap().content, "Other\n");
+    }
+
+    #[test]
+    fn test_parse_suggestions() {
+        let reply = "Here you go:\n- Run `cargo fmt` before committing.\n* Prefer thiserror.\n-\nNONE";
+        assert_eq!(parse_suggestions(reply), ["Run `cargo fmt` before committing.", "Prefer thiserror."]);
+        assert!(parse_suggestions("NONE").is_empty());
+    }
+}
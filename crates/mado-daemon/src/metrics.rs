@@ -0,0 +1,245 @@
+//! Prometheus text-format metrics for `GET /metrics`.
+//!
+//! Request latencies and git operation durations are recorded as they happen;
+//! everything else (uptime, usage, subscriber counts) is sampled from the
+//! managers when the endpoint is scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+/// Histogram bucket upper bounds, in seconds.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// A cumulative histogram over `BUCKETS`.
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Label values, in the order of the metric's label names.
+type Labels = Vec<String>;
+
+/// Metrics recorded as events happen.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<Labels, Histogram>>,
+    git_operations: Mutex<BTreeMap<Labels, Histogram>>,
+}
+
+impl Metrics {
+    /// Record an HTTP request's latency.
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let labels = vec![method.to_string(), route.to_string(), status.to_string()];
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        requests.entry(labels).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    /// Run a git operation, recording its duration and outcome.
+    pub fn time_git<T, E>(
+        &self,
+        operation: &str,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+        let result = f();
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        let labels = vec![operation.to_string(), outcome.to_string()];
+        let mut git = self.git_operations.lock().unwrap_or_else(|e| e.into_inner());
+        git.entry(labels).or_default().observe(start.elapsed().as_secs_f64());
+        result
+    }
+
+    /// Append the recorded histograms to `out`.
+    pub fn encode(&self, out: &mut MetricsWriter) {
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        out.histogram(
+            "mado_http_request_duration_seconds",
+            "HTTP request latency until response headers.",
+            &["method", "route", "status"],
+            &requests,
+        );
+        drop(requests);
+
+        let git = self.git_operations.lock().unwrap_or_else(|e| e.into_inner());
+        out.histogram(
+            "mado_git_operation_duration_seconds",
+            "Duration of git operations.",
+            &["operation", "outcome"],
+            &git,
+        );
+    }
+}
+
+/// Middleware recording request latency, labelled by matched route.
+pub async fn track_requests(
+    State(metrics): State<std::sync::Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Label by route template, not the raw path, to keep cardinality bounded.
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+    let method = request.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics.observe_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Builds a Prometheus text exposition.
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(
+        &mut self,
+        name: &str,
+        names: &[&str],
+        values: &[String],
+        extra: Option<(&str, &str)>,
+        value: f64,
+    ) {
+        let mut labels: Vec<String> = names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        if let Some((name, value)) = extra {
+            labels.push(format!("{}=\"{}\"", name, value));
+        }
+        if labels.is_empty() {
+            let _ = writeln!(self.out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(self.out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+
+    /// A single unlabelled gauge.
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.header(name, help, "gauge");
+        self.sample(name, &[], &[], None, value);
+    }
+
+    /// A labelled gauge or counter family. `kind` is `gauge` or `counter`.
+    pub fn family(
+        &mut self,
+        name: &str,
+        help: &str,
+        kind: &str,
+        label_names: &[&str],
+        samples: &[(Labels, f64)],
+    ) {
+        self.header(name, help, kind);
+        for (values, value) in samples {
+            self.sample(name, label_names, values, None, *value);
+        }
+    }
+
+    fn histogram(
+        &mut self,
+        name: &str,
+        help: &str,
+        label_names: &[&str],
+        series: &BTreeMap<Labels, Histogram>,
+    ) {
+        self.header(name, help, "histogram");
+        let bucket = format!("{}_bucket", name);
+        for (values, histogram) in series {
+            for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+                let le = bound.to_string();
+                self.sample(&bucket, label_names, values, Some(("le", &le)), count as f64);
+            }
+            let count = histogram.count as f64;
+            self.sample(&bucket, label_names, values, Some(("le", "+Inf")), count);
+            self.sample(&format!("{}_sum", name), label_names, values, None, histogram.sum);
+            self.sample(&format!("{}_count", name), label_names, values, None, count);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_encoding() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/sessions/{id}", 200, Duration::from_millis(20));
+        metrics.observe_request("GET", "/sessions/{id}", 200, Duration::from_secs(20));
+        let _ = metrics.time_git("git_push", || Err::<(), _>("rejected"));
+
+        let mut out = MetricsWriter::default();
+        metrics.encode(&mut out);
+        let text = out.finish();
+
+        assert!(text.contains("# TYPE mado_http_request_duration_seconds histogram"));
+        let labels = "method=\"GET\",route=\"/sessions/{id}\",status=\"200\"";
+        assert!(text.contains(&format!(
+            "mado_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "mado_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2",
+            labels
+        )));
+        assert!(text.contains(
+            "mado_git_operation_duration_seconds_count{operation=\"git_push\",outcome=\"error\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        let mut out = MetricsWriter::default();
+        out.family(
+            "mado_test",
+            "Test.",
+            "gauge",
+            &["name"],
+            &[(vec!["a \"quoted\"\nname".to_string()], 1.0)],
+        );
+        assert!(out.finish().contains("mado_test{name=\"a \\\"quoted\\\"\\nname\"} 1"));
+    }
+}
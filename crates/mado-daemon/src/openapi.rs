@@ -0,0 +1,217 @@
+//! OpenAPI document for the daemon HTTP API, served at `/openapi.json`.
+//!
+//! Generated from the `#[utoipa::path]` annotations on the handlers in
+//! `crate::server` and `crate::ws`. Every JSON endpoint answers with a
+//! `DaemonResponse`; its variants are documented as one tagged schema.
+
+use axum::Json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use mado_core::protocol::{DaemonResponse, ErrorCode, WsClientMessage, WsServerMessage};
+use mado_core::types::{ActivityEvent, CompareEvent, RunEvent, StreamEvent, TaskResult};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Mado daemon API"),
+    paths(
+        crate::server::health_handler,
+        crate::server::ping_handler,
+        crate::server::version_handler,
+        crate::server::activity_feed_handler,
+        crate::server::metrics_handler,
+        crate::server::logs_handler,
+        crate::server::cli_info_handler,
+        crate::server::cli_sessions_handler,
+        crate::server::history_sources_handler,
+        crate::server::list_crashes_handler,
+        crate::server::get_crash_handler,
+        crate::server::delete_crash_handler,
+        crate::server::retention_plan_handler,
+        crate::server::usage_report_handler,
+        crate::server::admin_restart_handler,
+        crate::server::admin_purge_handler,
+        openapi_handler,
+        crate::server::list_sessions_handler,
+        crate::server::create_session_handler,
+        crate::server::get_session_handler,
+        crate::server::update_session_handler,
+        crate::server::destroy_session_handler,
+        crate::server::revive_session_handler,
+        crate::server::restart_session_handler,
+        crate::server::fork_session_handler,
+        crate::server::clone_session_handler,
+        crate::server::archive_session_handler,
+        crate::server::get_session_settings_handler,
+        crate::server::update_session_settings_handler,
+        crate::server::effective_config_handler,
+        crate::server::input_handler,
+        crate::server::raw_input_handler,
+        crate::server::resize_handler,
+        crate::server::output_handler,
+        crate::server::scrollback_handler,
+        crate::server::recording_handler,
+        crate::server::pty_stream_handler,
+        crate::ws::ws_handler,
+        crate::server::get_messages_handler,
+        crate::server::send_message_handler,
+        crate::server::edit_message_handler,
+        crate::server::retry_message_handler,
+        crate::server::compare_handler,
+        crate::server::list_comparisons_handler,
+        crate::server::comparison_stream_handler,
+        crate::server::apply_comparison_handler,
+        crate::server::response_input_handler,
+        crate::server::delete_message_handler,
+        crate::server::clear_messages_handler,
+        crate::server::compact_handler,
+        crate::server::cancel_response_handler,
+        crate::server::cancel_queued_handler,
+        crate::server::upload_attachment_handler,
+        crate::server::stream_events_handler,
+        crate::server::import_history_handler,
+        crate::server::export_conversation_handler,
+        crate::server::audit_log_handler,
+        crate::server::export_audit_log_handler,
+        crate::server::session_usage_handler,
+        crate::server::create_task_handler,
+        crate::server::list_schedules_handler,
+        crate::server::create_schedule_handler,
+        crate::server::delete_schedule_handler,
+        crate::server::schedule_runs_handler,
+        crate::server::list_templates_handler,
+        crate::server::save_template_handler,
+        crate::server::delete_template_handler,
+        crate::server::list_prompts_handler,
+        crate::server::save_prompt_handler,
+        crate::server::delete_prompt_handler,
+        crate::server::render_prompt_handler,
+        crate::server::trust_workspace_handler,
+        crate::server::list_files_handler,
+        crate::server::read_file_handler,
+        crate::server::write_file_handler,
+        crate::server::search_handler,
+        crate::server::get_memory_handler,
+        crate::server::write_memory_handler,
+        crate::server::append_memory_handler,
+        crate::server::list_memory_suggestions_handler,
+        crate::server::suggest_memory_handler,
+        crate::server::accept_memory_suggestion_handler,
+        crate::server::dismiss_memory_suggestion_handler,
+        crate::server::save_milestone_handler,
+        crate::server::list_milestones_handler,
+        crate::server::diff_milestones_handler,
+        crate::server::restore_milestone_handler,
+        crate::server::save_snapshot_handler,
+        crate::server::list_snapshots_handler,
+        crate::server::restore_snapshot_handler,
+        crate::server::review_handler,
+        crate::server::apply_review_handler,
+        crate::server::revert_turn_changes_handler,
+        crate::server::project_commands_handler,
+        crate::server::run_command_handler,
+        crate::server::list_runs_handler,
+        crate::server::run_stream_handler,
+        crate::server::workspace_changes_handler,
+        crate::server::git_init_handler,
+        crate::server::git_status_handler,
+        crate::server::git_file_diff_handler,
+        crate::server::git_stage_file_handler,
+        crate::server::git_unstage_file_handler,
+        crate::server::git_stage_files_handler,
+        crate::server::git_unstage_files_handler,
+        crate::server::git_stage_hunk_handler,
+        crate::server::git_branch_info_handler,
+        crate::server::git_repos_handler,
+        crate::server::git_push_handler,
+        crate::server::merge_branch_handler,
+        crate::server::create_pull_request_handler,
+        crate::server::list_pull_requests_handler,
+    ),
+    // Payloads of the SSE and WebSocket endpoints, which utoipa can't infer.
+    components(schemas(
+        DaemonResponse,
+        ErrorCode,
+        StreamEvent,
+        TaskResult,
+        RunEvent,
+        CompareEvent,
+        ActivityEvent,
+        WsClientMessage,
+        WsServerMessage,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags(
+        (name = "daemon", description = "Health, metrics and the activity feed"),
+        (name = "sessions", description = "Session lifecycle"),
+        (name = "pty", description = "Terminal input and output"),
+        (name = "chat", description = "Conversation messages and streaming"),
+        (name = "schedules", description = "Prompts run on a cron schedule"),
+        (name = "templates", description = "Presets for new sessions"),
+        (name = "prompts", description = "The prompt library"),
+        (name = "files", description = "Workspace files and search"),
+        (name = "audit", description = "Logs of the files chat turns wrote and the commands they ran"),
+        (name = "memory", description = "The project memory file (CLAUDE.md) and suggested entries"),
+        (name = "milestones", description = "Workspace snapshots, alone or with the conversation, and reviews of what turns changed"),
+        (name = "git", description = "Git staging, push and pull requests"),
+        (name = "commands", description = "Project commands (test, build, lint) and their runs"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the bearer token scheme enforced by `crate::auth`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The OpenAPI document for this daemon.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses(
+        (status = 200, description = "OpenAPI 3.1 document", content_type = "application/json"),
+    ),
+    tag = "daemon"
+)]
+pub async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_routes_and_responses() {
+        let doc = ApiDoc::openapi();
+
+        for path in [
+            "/health",
+            "/openapi.json",
+            "/sessions/{id}",
+            "/sessions/{id}/ws",
+            "/sessions/{id}/queue/{message_id}",
+            "/sessions/{id}/git/push",
+        ] {
+            assert!(doc.paths.paths.contains_key(path), "missing {}", path);
+        }
+
+        let messages = &doc.paths.paths["/sessions/{id}/messages"];
+        assert!(messages.get.is_some() && messages.post.is_some());
+
+        let components = doc.components.expect("components");
+        for schema in ["DaemonResponse", "StreamEvent", "CreateSessionBody", "ErrorCode"] {
+            assert!(components.schemas.contains_key(schema), "missing {}", schema);
+        }
+        assert!(components.security_schemes.contains_key("bearer"));
+    }
+}
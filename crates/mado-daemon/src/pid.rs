@@ -52,14 +52,14 @@ impl PidFile {
             let _ = fs::remove_file(&path);
 
             // Also clean up stale socket file if it exists.
-            if let Some(sock) = socket_path {
-                if sock.exists() {
-                    tracing::warn!(
-                        "Removing stale socket file: {}",
-                        sock.display()
-                    );
-                    let _ = fs::remove_file(sock);
-                }
+            if let Some(sock) = socket_path
+                && sock.exists()
+            {
+                tracing::warn!(
+                    "Removing stale socket file: {}",
+                    sock.display()
+                );
+                let _ = fs::remove_file(sock);
             }
         }
 
@@ -72,6 +72,25 @@ impl PidFile {
         Ok(Self { path })
     }
 
+    /// Take over the PID file from a predecessor daemon handing over to us
+    /// (see `crate::handover`).
+    pub fn take_over(path: impl Into<PathBuf>, predecessor: u32) -> Result<Self, PidError> {
+        let path = path.into();
+
+        if path.exists() {
+            let existing_pid = Self::read_pid(&path)?;
+            if existing_pid != predecessor && is_process_alive(existing_pid) {
+                return Err(PidError::AlreadyRunning { pid: existing_pid });
+            }
+        }
+
+        let pid = std::process::id();
+        Self::write_pid(&path, pid)?;
+        tracing::info!("PID file taken over from {}: {} (pid: {})", predecessor, path.display(), pid);
+
+        Ok(Self { path })
+    }
+
     /// Update the PID file with a new PID (used after daemonization when the
     /// PID changes due to fork).
     pub fn update_pid(&self, new_pid: u32) -> Result<(), PidError> {
@@ -0,0 +1,219 @@
+//! Session presence: classifies what each session is doing from PTY output
+//! recency and conversation state.
+//!
+//! The presence monitor is the single source of truth for `Session::activity`
+//! (and `is_busy` and `last_activity`, which follow it). Anything that needs
+//! to know whether a session is busy or idle (reaping, notifications, the UI)
+//! should read those fields, listen for `StreamEvent::ActivityChanged`, or
+//! for `Busy`/`Idle` on the daemon-wide feed instead of inventing its own
+//! heuristic.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing;
+
+use mado_core::types::{ActivityKind, ConversationState, SessionActivity, SessionId, StreamEvent};
+
+use crate::conversation::SharedConversationManager;
+use crate::feed::SharedActivityFeed;
+use crate::session::SharedSessionManager;
+
+/// PTY output within this window counts as streaming.
+const STREAMING_WINDOW_MS: i64 = 2_000;
+
+/// A session with no activity for this long is idle rather than waiting on the user.
+const IDLE_AFTER_MS: i64 = 5 * 60 * 1_000;
+
+/// How often the monitor re-classifies sessions.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Raw activity signals for one session.
+#[derive(Debug, Clone, Default)]
+pub struct ActivitySignals {
+    /// Whether the session has a PTY process that has not exited.
+    pub pty_alive: bool,
+    /// When the PTY last produced output.
+    pub last_output: Option<DateTime<Utc>>,
+    /// When input was last written to the PTY.
+    pub last_input: Option<DateTime<Utc>>,
+    /// Current chat conversation state.
+    pub chat_state: ConversationState,
+    /// When the chat conversation last changed state.
+    pub last_chat_activity: Option<DateTime<Utc>>,
+}
+
+/// Classify a session's activity from its signals.
+pub fn classify(signals: &ActivitySignals, now: DateTime<Utc>) -> SessionActivity {
+    let within = |t: Option<DateTime<Utc>>, window_ms: i64| {
+        t.is_some_and(|t| (now - t).num_milliseconds() <= window_ms)
+    };
+
+    if signals.chat_state == ConversationState::Streaming {
+        return SessionActivity::Streaming;
+    }
+    if signals.pty_alive && within(signals.last_output, STREAMING_WINDOW_MS) {
+        return SessionActivity::Streaming;
+    }
+
+    let last_activity = [signals.last_output, signals.last_input, signals.last_chat_activity]
+        .into_iter()
+        .flatten()
+        .max();
+    let recently_active = within(last_activity, IDLE_AFTER_MS);
+
+    if !signals.pty_alive && !within(signals.last_chat_activity, IDLE_AFTER_MS) {
+        SessionActivity::Exited
+    } else if recently_active {
+        SessionActivity::WaitingOnUser
+    } else {
+        SessionActivity::Idle
+    }
+}
+
+/// When the session last produced terminal output or chat events; input
+/// doesn't count.
+pub fn last_activity(signals: &ActivitySignals) -> Option<DateTime<Utc>> {
+    signals.last_output.max(signals.last_chat_activity)
+}
+
+/// Gather the current activity signals for a session.
+async fn gather_signals(
+    session_manager: &SharedSessionManager,
+    conversation_manager: &SharedConversationManager,
+    session_id: &SessionId,
+) -> ActivitySignals {
+    let mut signals = ActivitySignals::default();
+
+    if let Some(pty) = session_manager.pty_activity(session_id).await {
+        signals.pty_alive = !pty.has_exited();
+        signals.last_output = pty.last_output();
+        signals.last_input = pty.last_input();
+    }
+
+    if let Some((state, last)) = conversation_manager.chat_activity(session_id).await {
+        signals.chat_state = state;
+        signals.last_chat_activity = last;
+    }
+
+    signals
+}
+
+/// Spawn the background task that keeps `Session::activity` up to date and
+/// publishes `ActivityChanged` events when it changes, and `Busy`/`Idle` on
+/// `feed` when a session starts or stops streaming.
+pub fn spawn_monitor(
+    session_manager: SharedSessionManager,
+    conversation_manager: SharedConversationManager,
+    feed: SharedActivityFeed,
+) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("presence-monitor", move || {
+        monitor(session_manager.clone(), conversation_manager.clone(), feed.clone())
+    })
+}
+
+async fn monitor(
+    session_manager: SharedSessionManager,
+    conversation_manager: SharedConversationManager,
+    feed: SharedActivityFeed,
+) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        for session in session_manager.list_sessions().await {
+            let signals =
+                gather_signals(&session_manager, &conversation_manager, &session.id).await;
+            let activity = classify(&signals, Utc::now());
+
+            if session_manager
+                .set_activity(&session.id, activity, last_activity(&signals))
+                .await
+            {
+                tracing::debug!("Session {} activity: {:?}", session.id, activity);
+                conversation_manager
+                    .publish(&session.id, StreamEvent::ActivityChanged { activity })
+                    .await;
+                let busy = activity == SessionActivity::Streaming;
+                if busy != session.is_busy {
+                    feed.publish(&session.id, if busy { ActivityKind::Busy } else { ActivityKind::Idle });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ago(now: DateTime<Utc>, ms: i64) -> Option<DateTime<Utc>> {
+        Some(now - chrono::Duration::milliseconds(ms))
+    }
+
+    #[test]
+    fn test_classify_streaming() {
+        let now = Utc::now();
+        let pty = ActivitySignals {
+            pty_alive: true,
+            last_output: ago(now, 500),
+            ..Default::default()
+        };
+        assert_eq!(classify(&pty, now), SessionActivity::Streaming);
+
+        let chat = ActivitySignals {
+            chat_state: ConversationState::Streaming,
+            ..Default::default()
+        };
+        assert_eq!(classify(&chat, now), SessionActivity::Streaming);
+    }
+
+    #[test]
+    fn test_classify_waiting_idle_exited() {
+        let now = Utc::now();
+        let waiting = ActivitySignals {
+            pty_alive: true,
+            last_output: ago(now, 10_000),
+            ..Default::default()
+        };
+        assert_eq!(classify(&waiting, now), SessionActivity::WaitingOnUser);
+
+        let idle = ActivitySignals {
+            pty_alive: true,
+            last_output: ago(now, IDLE_AFTER_MS + 1_000),
+            ..Default::default()
+        };
+        assert_eq!(classify(&idle, now), SessionActivity::Idle);
+
+        let exited = ActivitySignals {
+            pty_alive: false,
+            last_output: ago(now, 500),
+            ..Default::default()
+        };
+        assert_eq!(classify(&exited, now), SessionActivity::Exited);
+
+        // A chat-only session that just finished a response is waiting, not exited.
+        let chat_waiting = ActivitySignals {
+            chat_state: ConversationState::Idle,
+            last_chat_activity: ago(now, 1_000),
+            ..Default::default()
+        };
+        assert_eq!(classify(&chat_waiting, now), SessionActivity::WaitingOnUser);
+    }
+
+    #[test]
+    fn test_last_activity_ignores_input() {
+        let now = Utc::now();
+        let signals = ActivitySignals {
+            pty_alive: true,
+            last_output: ago(now, 60_000),
+            last_input: ago(now, 1_000),
+            last_chat_activity: ago(now, 30_000),
+            ..Default::default()
+        };
+        assert_eq!(last_activity(&signals), ago(now, 30_000));
+        assert_eq!(last_activity(&ActivitySignals::default()), None);
+    }
+}
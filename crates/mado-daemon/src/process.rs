@@ -1,16 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use portable_pty::{CommandBuilder, native_pty_system, PtySize};
-use tokio::sync::{broadcast, Mutex};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use portable_pty::{ChildKiller, CommandBuilder, native_pty_system, PtySize};
+use tokio::sync::{broadcast, watch, Mutex};
 use tracing;
 
-use mado_core::types::SessionId;
+use mado_core::types::{CustomCommand, PermissionMode, SessionId};
+
+use crate::claude_cli::{ClaudeCli, CliError};
+use crate::handover::PtyHandover;
+use crate::recording::Recorder;
+use crate::sandbox::{Policy, Proxy, Sandbox, SandboxError};
 
 /// Valid model identifiers for Claude CLI.
-const VALID_MODELS: &[&str] = &["opus", "sonnet", "haiku"];
+pub(crate) const VALID_MODELS: &[&str] = &["opus", "sonnet", "haiku"];
+
+/// How long a PTY reader waits for output before rechecking whether it is paused.
+const READER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bracketed-paste start and end sequences (DECSET 2004).
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Pasted input is written this many bytes at a time, each once the PTY can
+/// take more, so the program reading it isn't flooded.
+const PASTE_CHUNK_BYTES: usize = 1024;
+
+/// How long a paste waits for the program to read what was written so far.
+const PASTE_STALL_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Result of spawning a process, indicating what was actually launched.
 pub struct SpawnResult {
@@ -20,24 +45,312 @@ pub struct SpawnResult {
     pub command: String,
 }
 
+/// How a PTY's process ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessExit {
+    /// Exit code; `None` if the process was killed by a signal, or was
+    /// adopted from a predecessor daemon and so couldn't be waited on.
+    pub code: Option<i32>,
+}
+
+/// I/O activity of a PTY, updated from the reader thread and input path.
+#[derive(Debug, Default)]
+pub struct PtyActivity {
+    /// Unix millis of the last output chunk (0 = none yet).
+    last_output_ms: AtomicI64,
+    /// Unix millis of the last input write (0 = none yet).
+    last_input_ms: AtomicI64,
+    /// Set once the PTY reader hits EOF or an error (the process exited).
+    exited: AtomicBool,
+}
+
+impl PtyActivity {
+    /// When the PTY last produced output.
+    pub fn last_output(&self) -> Option<DateTime<Utc>> {
+        millis_to_datetime(self.last_output_ms.load(Ordering::Relaxed))
+    }
+
+    /// When input was last written to the PTY.
+    pub fn last_input(&self) -> Option<DateTime<Utc>> {
+        millis_to_datetime(self.last_input_ms.load(Ordering::Relaxed))
+    }
+
+    /// Whether the process behind the PTY has exited.
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::Relaxed)
+    }
+
+    fn record_output(&self) {
+        self.last_output_ms
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    fn record_input(&self) {
+        self.last_input_ms
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    fn mark_exited(&self) {
+        self.exited.store(true, Ordering::Relaxed);
+    }
+}
+
+fn millis_to_datetime(ms: i64) -> Option<DateTime<Utc>> {
+    if ms == 0 {
+        None
+    } else {
+        DateTime::from_timestamp_millis(ms)
+    }
+}
+
+/// Bounded buffer of the most recent PTY output.
+struct Scrollback {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    /// Total bytes ever pushed, i.e. the stream offset at the end of `buf`.
+    total: u64,
+}
+
+impl Scrollback {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.total += data.len() as u64;
+        let data = &data[data.len().saturating_sub(self.capacity)..];
+        let overflow = (self.buf.len() + data.len()).saturating_sub(self.capacity);
+        self.buf.drain(..overflow);
+        self.buf.extend(data);
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.buf.iter().copied().collect()
+    }
+
+    /// Rebuild a scrollback handed over by a predecessor daemon, so stream
+    /// offsets carry on where they left off.
+    fn restore(capacity: usize, data: &[u8], total: u64) -> Self {
+        let mut scrollback = Self::new(capacity);
+        scrollback.push(data);
+        scrollback.total = total.max(data.len() as u64);
+        scrollback
+    }
+
+    /// Output after stream offset `offset`, if it is still buffered.
+    fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        let start = self.total - self.buf.len() as u64;
+        if offset < start || offset > self.total {
+            return None;
+        }
+        Some(self.buf.range((offset - start) as usize..).copied().collect())
+    }
+}
+
+/// A chunk of PTY output tagged with its position in the output stream.
+#[derive(Debug, Clone)]
+pub struct PtyOutput {
+    /// Total bytes the process has output, up to and including this chunk.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// A consistent view of a PTY for a newly attached client: the scrollback up to
+/// the moment of subscribing, then live output from that point on.
+pub struct PtySubscription {
+    /// Recent output, replayed before live data.
+    pub scrollback: Vec<u8>,
+    /// Stream offset at the end of `scrollback`.
+    pub offset: u64,
+    /// True if `scrollback` is exactly the output after the requested resume
+    /// offset, rather than the whole buffer.
+    pub resumed: bool,
+    /// Live output after the scrollback.
+    pub output: broadcast::Receiver<PtyOutput>,
+    /// Set once the process has exited and its output has been read.
+    pub exited: watch::Receiver<Option<ProcessExit>>,
+}
+
+/// The master side of a PTY.
+enum PtyMaster {
+    /// Opened by this daemon.
+    Opened(Box<dyn portable_pty::MasterPty + Send>),
+    /// Inherited from a predecessor daemon at handover.
+    Inherited(OwnedFd),
+}
+
+impl PtyMaster {
+    fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            PtyMaster::Opened(master) => master.as_raw_fd(),
+            PtyMaster::Inherited(fd) => Some(fd.as_raw_fd()),
+        }
+    }
+
+    fn resize(&self, size: PtySize) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            PtyMaster::Opened(master) => Ok(master.resize(size)?),
+            PtyMaster::Inherited(fd) => {
+                let winsize = libc::winsize {
+                    ws_row: size.rows,
+                    ws_col: size.cols,
+                    ws_xpixel: size.pixel_width,
+                    ws_ypixel: size.pixel_height,
+                };
+                // Safety: TIOCSWINSZ reads a `winsize` from the pointer.
+                if unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSWINSZ as _, &winsize) } != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The terminal's size as (cols, rows).
+    fn size(&self) -> Option<(u16, u16)> {
+        let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+        // Safety: TIOCGWINSZ writes a `winsize` to the pointer.
+        if unsafe { libc::ioctl(self.raw_fd()?, libc::TIOCGWINSZ as _, &mut winsize) } != 0 {
+            return None;
+        }
+        Some((winsize.ws_col, winsize.ws_row))
+    }
+}
+
+/// A child process handle, which the PTY reader thread waits on for the
+/// exit status once the PTY closes.
+type PtyWaiter = Box<dyn portable_pty::Child + Send + Sync>;
+
+/// The process running in a PTY.
+enum PtyChild {
+    /// Spawned by this daemon. The child itself is with the reader thread.
+    Spawned {
+        pid: Option<u32>,
+        killer: Box<dyn ChildKiller + Send + Sync>,
+    },
+    /// Spawned by a predecessor daemon; no longer our child, so only its pid is known.
+    Adopted(Option<u32>),
+}
+
+impl PtyChild {
+    /// Split a spawned child into the handle kept here and the one to wait on.
+    fn spawned(child: PtyWaiter) -> (Self, PtyWaiter) {
+        let spawned = PtyChild::Spawned {
+            pid: child.process_id(),
+            killer: child.clone_killer(),
+        };
+        (spawned, child)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        match self {
+            PtyChild::Spawned { pid, .. } => *pid,
+            PtyChild::Adopted(pid) => *pid,
+        }
+    }
+
+    /// Stop the process and everything in its process group (it is a
+    /// session leader), escalating from SIGINT to SIGKILL. The PTY reader
+    /// reaps the child once it is gone.
+    fn kill(&mut self) -> std::io::Result<()> {
+        match self {
+            PtyChild::Spawned { pid: Some(pid), .. } | PtyChild::Adopted(Some(pid)) => {
+                crate::process_group::stop(*pid, || {})
+            }
+            PtyChild::Spawned { pid: None, killer } => killer.kill(),
+            PtyChild::Adopted(None) => Ok(()),
+        }
+    }
+}
+
+/// Lets a handover stop a PTY reader thread between reads.
+///
+/// The reader holds `lock` for each poll-and-read step, so once `pause` has
+/// set the flag and taken the lock, no further output is consumed.
+#[derive(Default)]
+struct ReaderGate {
+    paused: AtomicBool,
+    lock: std::sync::Mutex<()>,
+}
+
+impl ReaderGate {
+    fn paused() -> Self {
+        Self {
+            paused: AtomicBool::new(true),
+            lock: std::sync::Mutex::new(()),
+        }
+    }
+
+    /// Stop reading, waiting for an in-flight read to finish.
+    fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        drop(self.lock.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+}
+
 /// A managed process running in a PTY.
 pub struct ManagedProcess {
     /// The child process handle.
-    _child: Box<dyn portable_pty::Child + Send>,
+    child: PtyChild,
     /// Writer to send input to the PTY.
     writer: Box<dyn std::io::Write + Send>,
     /// The master PTY handle (for resize operations).
-    master: Box<dyn portable_pty::MasterPty + Send>,
+    master: PtyMaster,
     /// Broadcast sender for output data.
-    output_tx: broadcast::Sender<Vec<u8>>,
+    output_tx: broadcast::Sender<PtyOutput>,
+    /// Output/input recency, shared with the reader thread.
+    activity: Arc<PtyActivity>,
+    /// Recent output, shared with the reader thread.
+    scrollback: Arc<std::sync::Mutex<Scrollback>>,
+    /// Exit status, set by the reader thread on EOF.
+    exit_rx: watch::Receiver<Option<ProcessExit>>,
+    /// Pauses the reader thread during a handover.
+    gate: Arc<ReaderGate>,
+    /// Asciicast recording of the output, shared with the reader thread.
+    recorder: Arc<std::sync::Mutex<Option<Recorder>>>,
+    /// Network proxy of a sandboxed process, kept as long as it is.
+    _proxy: Option<Proxy>,
 }
 
 impl ManagedProcess {
     /// Write input data to the PTY (user keystrokes).
-    pub fn write_input(&mut self, data: &[u8]) -> std::io::Result<()> {
+    ///
+    /// With `paste`, the data is wrapped in bracketed-paste sequences (minus
+    /// any end sequence inside it, which would end the paste early) and
+    /// written in chunks, each once the PTY has room for it.
+    pub fn write_input(&mut self, data: &[u8], paste: bool) -> std::io::Result<()> {
         use std::io::Write;
-        self.writer.write_all(data)?;
-        self.writer.flush()?;
+        if paste {
+            let data = strip_paste_end(data);
+            let fd = self.master.raw_fd();
+            for chunk in std::iter::once(PASTE_START)
+                .chain(data.chunks(PASTE_CHUNK_BYTES))
+                .chain(std::iter::once(PASTE_END))
+            {
+                if let Some(fd) = fd
+                    && !wait_writable(fd, PASTE_STALL_TIMEOUT)?
+                {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "program stopped reading the paste",
+                    ));
+                }
+                self.writer.write_all(chunk)?;
+                self.writer.flush()?;
+            }
+        } else {
+            self.writer.write_all(data)?;
+            self.writer.flush()?;
+        }
+        self.activity.record_input();
         Ok(())
     }
 
@@ -49,12 +362,35 @@ impl ManagedProcess {
             pixel_width: 0,
             pixel_height: 0,
         })?;
+        if let Some(recorder) = self.recorder.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            recorder.resize(cols, rows);
+        }
         Ok(())
     }
 
-    /// Subscribe to output from this process.
-    pub fn subscribe_output(&self) -> broadcast::Receiver<Vec<u8>> {
-        self.output_tx.subscribe()
+    /// The scrollback and the stream offset at its end.
+    pub fn scrollback(&self) -> (Vec<u8>, u64) {
+        let scrollback = self.scrollback.lock().unwrap_or_else(|e| e.into_inner());
+        (scrollback.snapshot(), scrollback.total)
+    }
+
+    /// Subscribe to output, starting with a replay of the scrollback.
+    ///
+    /// With `resume_from`, only output after that stream offset is replayed,
+    /// falling back to the whole scrollback if it has already been evicted.
+    pub fn subscribe_with_scrollback(&self, resume_from: Option<u64>) -> PtySubscription {
+        // Hold the scrollback lock while subscribing: the reader thread pushes to
+        // the scrollback and broadcasts under the same lock, so no chunk is lost
+        // or duplicated between the snapshot and the live receiver.
+        let scrollback = self.scrollback.lock().unwrap_or_else(|e| e.into_inner());
+        let missed = resume_from.and_then(|offset| scrollback.since(offset));
+        PtySubscription {
+            resumed: missed.is_some(),
+            scrollback: missed.unwrap_or_else(|| scrollback.snapshot()),
+            offset: scrollback.total,
+            output: self.output_tx.subscribe(),
+            exited: self.exit_rx.clone(),
+        }
     }
 }
 
@@ -63,6 +399,12 @@ pub struct ProcessManager {
     processes: HashMap<String, ManagedProcess>,
 }
 
+impl Default for ProcessManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProcessManager {
     pub fn new() -> Self {
         Self {
@@ -72,21 +414,43 @@ impl ProcessManager {
 
     /// Spawn a new process in a PTY.
     ///
-    /// Attempts to launch Claude CLI with the given model. If Claude CLI is not
-    /// found on the system, falls back to the user's default shell.
+    /// Runs `custom` if given. Otherwise attempts to launch Claude CLI with
+    /// the given model, MCP config files and `cli` flags, resuming the Claude
+    /// conversation `resume` if given.
+    /// If Claude CLI is not found on the system, falls back to the user's
+    /// default shell; a configured `claude_path` that isn't executable is
+    /// an error instead. `env` is set on top of the daemon's environment. The
+    /// last `scrollback_bytes` of output are kept for replay to attaching
+    /// clients.
+    #[allow(clippy::too_many_arguments)]
     pub fn create(
         &mut self,
         session_id: &SessionId,
         model: &str,
+        permission_mode: PermissionMode,
         rows: u16,
         cols: u16,
         working_dir: Option<&str>,
         api_key: Option<&str>,
+        resume: Option<&str>,
+        custom: Option<&CustomCommand>,
+        cli: &ClaudeCli,
+        mcp_config: &[String],
+        env: &[(String, String)],
+        sandbox: Option<&Policy>,
+        scrollback_bytes: usize,
     ) -> Result<SpawnResult, ProcessError> {
         // Validate model.
         if !VALID_MODELS.contains(&model) {
             return Err(ProcessError::InvalidModel(model.to_string()));
         }
+        let custom_program = custom
+            .map(|custom| resolve_program(&custom.program, working_dir))
+            .transpose()?;
+        let claude = match custom {
+            Some(_) => None,
+            None => cli.binary()?,
+        };
 
         let pty_system = native_pty_system();
 
@@ -101,13 +465,43 @@ impl ProcessManager {
             .openpty(pty_size)
             .map_err(|e| ProcessError::PtyOpenFailed(e.to_string()))?;
 
-        // Try to find Claude CLI.
-        let claude_path = find_claude_binary();
+        let (mut cmd, shell_fallback, command_str) = if let (Some(custom), Some(program)) =
+            (custom, custom_program)
+        {
+            let mut cmd = CommandBuilder::new(&program);
+            cmd.args(&custom.args);
+            cmd.env("TERM", "xterm-256color");
+            cmd.env("COLORTERM", "truecolor");
 
-        let (cmd, shell_fallback, command_str) = if let Some(claude) = claude_path {
+            if let Some(dir) = working_dir {
+                cmd.cwd(dir);
+            } else if let Ok(home) = std::env::var("HOME") {
+                cmd.cwd(home);
+            }
+
+            let mut cmd_str = program.display().to_string();
+            for arg in &custom.args {
+                cmd_str.push(' ');
+                cmd_str.push_str(arg);
+            }
+            (cmd, false, cmd_str)
+        } else if let Some(claude) = claude {
             let mut cmd = CommandBuilder::new(&claude);
             cmd.arg("--model");
             cmd.arg(model);
+            if permission_mode != PermissionMode::Ask {
+                cmd.arg("--permission-mode");
+                cmd.arg(permission_mode.cli_value());
+            }
+            if let Some(claude_session_id) = resume {
+                cmd.arg("--resume");
+                cmd.arg(claude_session_id);
+            }
+            for config in mcp_config {
+                cmd.arg("--mcp-config");
+                cmd.arg(config);
+            }
+            cmd.args(&cli.flags);
             cmd.env("TERM", "xterm-256color");
             cmd.env("COLORTERM", "truecolor");
 
@@ -123,7 +517,20 @@ impl ProcessManager {
                 cmd.cwd(home);
             }
 
-            let cmd_str = format!("{} --model {}", claude.display(), model);
+            let mut cmd_str = format!("{} --model {}", claude.display(), model);
+            if permission_mode != PermissionMode::Ask {
+                cmd_str.push_str(&format!(" --permission-mode {}", permission_mode.cli_value()));
+            }
+            if let Some(claude_session_id) = resume {
+                cmd_str.push_str(&format!(" --resume {}", claude_session_id));
+            }
+            for config in mcp_config {
+                cmd_str.push_str(&format!(" --mcp-config {}", config));
+            }
+            for flag in &cli.flags {
+                cmd_str.push(' ');
+                cmd_str.push_str(flag);
+            }
             (cmd, false, cmd_str)
         } else {
             tracing::warn!("Claude CLI not found, falling back to shell");
@@ -141,6 +548,13 @@ impl ProcessManager {
             let cmd_str = shell.clone();
             (cmd, true, cmd_str)
         };
+        for (name, value) in env {
+            cmd.env(name, value);
+        }
+        let sandbox = sandbox.map(Sandbox::start).transpose()?;
+        if let Some(sandbox) = &sandbox {
+            sandbox.wrap(&mut cmd);
+        }
 
         let child = pair
             .slave
@@ -149,31 +563,27 @@ impl ProcessManager {
 
         let reader = pair
             .master
-            .try_clone_reader()
-            .map_err(|e| ProcessError::PtyReadFailed(e.to_string()))?;
+            .as_raw_fd()
+            .ok_or_else(|| ProcessError::PtyReadFailed("PTY has no file descriptor".to_string()))
+            .and_then(|fd| dup_file(fd).map_err(|e| ProcessError::PtyReadFailed(e.to_string())))?;
 
         let writer = pair
             .master
             .take_writer()
             .map_err(|e| ProcessError::PtyWriteFailed(e.to_string()))?;
 
-        // Create broadcast channel for output.
-        let (output_tx, _) = broadcast::channel(64);
-
-        // Spawn a thread to read PTY output and broadcast it.
-        let tx_clone = output_tx.clone();
-        let sid = session_id.as_str().to_string();
-        std::thread::spawn(move || {
-            read_pty_output(reader, tx_clone, sid);
-        });
-
-        let managed = ManagedProcess {
-            _child: child,
+        let (child, waiter) = PtyChild::spawned(child);
+        let mut managed = ManagedProcess::start(
+            session_id,
+            child,
+            Some(waiter),
+            PtyMaster::Opened(pair.master),
             writer,
-            master: pair.master,
-            output_tx,
-        };
-
+            reader,
+            Scrollback::new(scrollback_bytes),
+            ReaderGate::default(),
+        );
+        managed._proxy = sandbox.and_then(|sandbox| sandbox.proxy);
         self.processes.insert(session_id.as_str().to_string(), managed);
 
         tracing::info!(
@@ -189,12 +599,15 @@ impl ProcessManager {
         })
     }
 
-    /// Destroy a process (kill it and clean up).
+    /// Destroy a process (kill it and its process group, and clean up).
     pub fn destroy(&mut self, session_id: &SessionId) -> Result<(), ProcessError> {
         if let Some(mut process) = self.processes.remove(session_id.as_str()) {
             drop(process.writer);
             drop(process.master);
-            if let Err(e) = process._child.kill() {
+            // An exited process has been reaped; its pid may belong to another by now.
+            if !process.activity.has_exited()
+                && let Err(e) = process.child.kill()
+            {
                 tracing::warn!("Failed to kill process for session {}: {}", session_id, e);
             }
             tracing::info!("Destroyed process for session {}", session_id);
@@ -204,19 +617,23 @@ impl ProcessManager {
         }
     }
 
-    /// Write input to a session's PTY.
+    /// Write input to a session's PTY, as a bracketed paste with `paste`.
     pub fn write_input(
         &mut self,
         session_id: &SessionId,
         data: &[u8],
+        paste: bool,
     ) -> Result<(), ProcessError> {
         let process = self
             .processes
             .get_mut(session_id.as_str())
             .ok_or_else(|| ProcessError::SessionNotFound(session_id.as_str().to_string()))?;
+        if process.activity.has_exited() {
+            return Err(ProcessError::Exited(session_id.as_str().to_string()));
+        }
 
         process
-            .write_input(data)
+            .write_input(data, paste)
             .map_err(|e| ProcessError::WriteFailed(e.to_string()))
     }
 
@@ -237,81 +654,369 @@ impl ProcessManager {
             .map_err(|e| ProcessError::ResizeFailed(e.to_string()))
     }
 
-    /// Subscribe to output from a session's PTY.
-    pub fn subscribe_output(
+    /// Subscribe to a session's PTY with scrollback replay and exit notification.
+    pub fn subscribe_pty(
         &self,
         session_id: &SessionId,
-    ) -> Result<broadcast::Receiver<Vec<u8>>, ProcessError> {
+        resume_from: Option<u64>,
+    ) -> Result<PtySubscription, ProcessError> {
         let process = self
             .processes
             .get(session_id.as_str())
             .ok_or_else(|| ProcessError::SessionNotFound(session_id.as_str().to_string()))?;
 
-        Ok(process.subscribe_output())
+        Ok(process.subscribe_with_scrollback(resume_from))
     }
 
-    /// Check if a session has a running process.
+    /// Start appending a session's PTY output to the asciicast recording at
+    /// `path`, up to `max_bytes` (see `crate::recording`).
+    pub fn record(
+        &self,
+        session_id: &SessionId,
+        path: &Path,
+        max_bytes: u64,
+    ) -> Result<(), ProcessError> {
+        let process = self
+            .processes
+            .get(session_id.as_str())
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.as_str().to_string()))?;
+
+        let (cols, rows) = process.master.size().unwrap_or((80, 24));
+        let recorder = Recorder::open(path, cols, rows, max_bytes)
+            .map_err(|e| ProcessError::RecordingFailed(e.to_string()))?;
+        *process.recorder.lock().unwrap_or_else(|e| e.into_inner()) = Some(recorder);
+        Ok(())
+    }
+
+    /// A session's scrollback and the stream offset at its end.
+    pub fn scrollback(&self, session_id: &SessionId) -> Result<(Vec<u8>, u64), ProcessError> {
+        self.processes
+            .get(session_id.as_str())
+            .map(ManagedProcess::scrollback)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.as_str().to_string()))
+    }
+
+    /// Get the I/O activity tracker for a session's PTY.
+    pub fn activity(&self, session_id: &SessionId) -> Option<Arc<PtyActivity>> {
+        self.processes
+            .get(session_id.as_str())
+            .map(|p| p.activity.clone())
+    }
+
+    /// Get a receiver that is set once a session's process exits.
+    pub fn exit_watch(&self, session_id: &SessionId) -> Option<watch::Receiver<Option<ProcessExit>>> {
+        self.processes
+            .get(session_id.as_str())
+            .map(|p| p.exit_rx.clone())
+    }
+
+    /// Number of managed processes.
+    pub fn count(&self) -> usize {
+        self.processes.len()
+    }
+
+    /// Check if a session has a process, running or exited.
     pub fn has_process(&self, session_id: &SessionId) -> bool {
         self.processes.contains_key(session_id.as_str())
     }
-}
 
-/// Find the Claude CLI binary on the system.
-///
-/// Checks: PATH, ~/.claude/local/bin/claude, /usr/local/bin/claude
-fn find_claude_binary() -> Option<PathBuf> {
-    // Check PATH first via `which`.
-    if let Ok(output) = std::process::Command::new("which")
-        .arg("claude")
-        .output()
-    {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !path.is_empty() {
-                let p = PathBuf::from(&path);
-                if p.exists() {
-                    tracing::debug!("Found claude at: {}", p.display());
-                    return Some(p);
+    /// Pids of the processes that haven't exited, by session.
+    pub fn pids(&self) -> Vec<(SessionId, u32)> {
+        self.processes
+            .iter()
+            .filter(|(_, process)| !process.activity.has_exited())
+            .filter_map(|(id, process)| Some((SessionId::new(id.clone()), process.child.pid()?)))
+            .collect()
+    }
+
+    /// Whether a session has a process that hasn't exited.
+    pub fn is_running(&self, session_id: &SessionId) -> bool {
+        self.processes
+            .get(session_id.as_str())
+            .is_some_and(|p| !p.activity.has_exited())
+    }
+
+    /// Stop every PTY reader and describe the PTYs for a successor daemon.
+    ///
+    /// The returned master fds are fresh duplicates without `FD_CLOEXEC`, so a
+    /// spawned successor inherits them; the caller closes them once it has
+    /// spawned. Readers stay paused until `resume_readers`.
+    pub fn handover(&self) -> std::io::Result<Vec<PtyHandover>> {
+        for process in self.processes.values() {
+            process.gate.pause();
+        }
+
+        let mut ptys = Vec::with_capacity(self.processes.len());
+        for (session_id, process) in &self.processes {
+            if process.activity.has_exited() {
+                continue;
+            }
+            let Some(fd) = process.master.raw_fd() else {
+                continue;
+            };
+            // Safety: plain dup of a descriptor we own; the new one has no FD_CLOEXEC.
+            let master_fd = unsafe { libc::dup(fd) };
+            if master_fd < 0 {
+                let err = std::io::Error::last_os_error();
+                for pty in &ptys {
+                    close_inherited(pty);
                 }
+                return Err(err);
             }
+
+            let scrollback = process.scrollback.lock().unwrap_or_else(|e| e.into_inner());
+            ptys.push(PtyHandover {
+                session_id: session_id.clone(),
+                master_fd,
+                pid: process.child.pid(),
+                scrollback: base64::engine::general_purpose::STANDARD
+                    .encode(scrollback.snapshot()),
+                offset: scrollback.total,
+            });
         }
+        Ok(ptys)
     }
 
-    // Check common install locations.
-    let candidates = [
-        dirs::home_dir()
-            .map(|h| h.join(".claude").join("local").join("bin").join("claude")),
-        Some(PathBuf::from("/usr/local/bin/claude")),
-        Some(PathBuf::from("/opt/homebrew/bin/claude")),
-    ];
+    /// Resume PTY readers paused by `handover`, or adopted ones once the
+    /// predecessor daemon has exited.
+    pub fn resume_readers(&self) {
+        for process in self.processes.values() {
+            process.gate.resume();
+        }
+    }
+
+    /// Take over a PTY handed over by a predecessor daemon.
+    ///
+    /// Its reader starts paused: the predecessor may still be reading until it
+    /// exits, so call `resume_readers` after that.
+    pub fn adopt(&mut self, pty: PtyHandover, scrollback_bytes: usize) -> Result<(), ProcessError> {
+        // Safety: the fd was inherited for us alone and is adopted exactly once.
+        let master = unsafe { OwnedFd::from_raw_fd(pty.master_fd) };
+        set_cloexec(master.as_raw_fd());
+
+        let scrollback = base64::engine::general_purpose::STANDARD
+            .decode(&pty.scrollback)
+            .unwrap_or_default();
+        let reader = dup_file(master.as_raw_fd())
+            .map_err(|e| ProcessError::PtyReadFailed(e.to_string()))?;
+        let writer = dup_file(master.as_raw_fd())
+            .map_err(|e| ProcessError::PtyWriteFailed(e.to_string()))?;
+
+        let session_id = SessionId::new(pty.session_id);
+        let managed = ManagedProcess::start(
+            &session_id,
+            PtyChild::Adopted(pty.pid),
+            None,
+            PtyMaster::Inherited(master),
+            Box::new(writer),
+            reader,
+            Scrollback::restore(scrollback_bytes, &scrollback, pty.offset),
+            ReaderGate::paused(),
+        );
+        self.processes.insert(session_id.as_str().to_string(), managed);
+
+        tracing::info!("Adopted process {:?} for session {}", pty.pid, session_id);
+        Ok(())
+    }
+}
 
-    for candidate in candidates.into_iter().flatten() {
-        if candidate.exists() {
-            tracing::debug!("Found claude at: {}", candidate.display());
-            return Some(candidate);
+impl ManagedProcess {
+    /// Wire up a PTY and start its reader thread, which reaps `waiter` once
+    /// the PTY closes.
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        session_id: &SessionId,
+        child: PtyChild,
+        waiter: Option<PtyWaiter>,
+        master: PtyMaster,
+        writer: Box<dyn std::io::Write + Send>,
+        reader: File,
+        scrollback: Scrollback,
+        gate: ReaderGate,
+    ) -> Self {
+        // Create broadcast channel for output.
+        let (output_tx, _) = broadcast::channel(64);
+
+        // Spawn a thread to read PTY output and broadcast it.
+        let activity = Arc::new(PtyActivity::default());
+        let scrollback = Arc::new(std::sync::Mutex::new(scrollback));
+        let gate = Arc::new(gate);
+        let (exit_tx, exit_rx) = watch::channel(None);
+        let recorder = Arc::new(std::sync::Mutex::new(None));
+        let reader_state = PtyReaderState {
+            tx: output_tx.clone(),
+            activity: activity.clone(),
+            scrollback: scrollback.clone(),
+            exit_tx,
+            waiter,
+            gate: gate.clone(),
+            recorder: recorder.clone(),
+        };
+        let sid = session_id.as_str().to_string();
+        std::thread::spawn(move || {
+            read_pty_output(reader, reader_state, sid);
+        });
+
+        Self {
+            child,
+            writer,
+            master,
+            output_tx,
+            activity,
+            scrollback,
+            exit_rx,
+            gate,
+            recorder,
+            _proxy: None,
         }
     }
+}
+
+/// Duplicate a descriptor into a `File` (close-on-exec).
+fn dup_file(fd: RawFd) -> std::io::Result<File> {
+    // Safety: F_DUPFD_CLOEXEC returns a new descriptor we then own.
+    let new_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if new_fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(new_fd) })
+}
+
+fn set_cloexec(fd: RawFd) {
+    unsafe {
+        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+}
+
+/// Close a handed-over master fd that no successor took.
+pub fn close_inherited(pty: &PtyHandover) {
+    unsafe {
+        libc::close(pty.master_fd);
+    }
+}
+
+/// Find the program of a custom command: on PATH for a bare name, else as a
+/// path relative to `working_dir`. It must be an executable file.
+fn resolve_program(program: &str, working_dir: Option<&str>) -> Result<PathBuf, ProcessError> {
+    if program.trim().is_empty() {
+        return Err(ProcessError::InvalidCommand("command is empty".to_string()));
+    }
+
+    let found = if program.contains('/') {
+        let path = match working_dir {
+            Some(dir) => Path::new(dir).join(program),
+            None => PathBuf::from(program),
+        };
+        is_executable(&path).then_some(path)
+    } else {
+        std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|path| is_executable(path))
+        })
+    };
+    found.ok_or_else(|| ProcessError::InvalidCommand(format!("{} is not an executable program", program)))
+}
+
+pub(crate) fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
 
-    tracing::warn!("Claude CLI not found on system");
-    None
+/// Everything the PTY reader thread publishes to.
+struct PtyReaderState {
+    tx: broadcast::Sender<PtyOutput>,
+    activity: Arc<PtyActivity>,
+    scrollback: Arc<std::sync::Mutex<Scrollback>>,
+    exit_tx: watch::Sender<Option<ProcessExit>>,
+    /// The child to reap once the PTY closes; `None` for adopted processes.
+    waiter: Option<PtyWaiter>,
+    gate: Arc<ReaderGate>,
+    recorder: Arc<std::sync::Mutex<Option<Recorder>>>,
+}
+
+/// Wait up to `timeout` for `fd` to become readable (or hang up).
+fn wait_readable(fd: RawFd, timeout: Duration) -> std::io::Result<bool> {
+    poll_fd(fd, libc::POLLIN, timeout)
+}
+
+/// Wait up to `timeout` for `fd` to take more input.
+fn wait_writable(fd: RawFd, timeout: Duration) -> std::io::Result<bool> {
+    poll_fd(fd, libc::POLLOUT, timeout)
+}
+
+/// Drop bracketed-paste end sequences from pasted data, including ones
+/// that removing others would form.
+fn strip_paste_end(data: &[u8]) -> std::borrow::Cow<'_, [u8]> {
+    let mut data = std::borrow::Cow::Borrowed(data);
+    let mut from = 0;
+    while let Some(at) = data[from..]
+        .windows(PASTE_END.len())
+        .position(|w| w == PASTE_END)
+    {
+        let at = from + at;
+        data.to_mut().drain(at..at + PASTE_END.len());
+        from = at.saturating_sub(PASTE_END.len() - 1);
+    }
+    data
+}
+
+fn poll_fd(fd: RawFd, events: libc::c_short, timeout: Duration) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) } {
+        n if n < 0 => {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+        n => Ok(n > 0),
+    }
 }
 
 /// Read PTY output in a blocking thread and broadcast it.
-fn read_pty_output(
-    mut reader: Box<dyn Read + Send>,
-    tx: broadcast::Sender<Vec<u8>>,
-    session_id: String,
-) {
+fn read_pty_output(mut reader: File, state: PtyReaderState, session_id: String) {
     let mut buf = [0u8; 4096];
     loop {
+        let gate = state.gate.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if state.gate.paused.load(Ordering::SeqCst) {
+            drop(gate);
+            std::thread::sleep(READER_POLL_INTERVAL);
+            continue;
+        }
+        match wait_readable(reader.as_raw_fd(), READER_POLL_INTERVAL) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                tracing::error!("PTY poll error for session {}: {}", session_id, e);
+                break;
+            }
+        }
         match reader.read(&mut buf) {
             Ok(0) => {
                 tracing::info!("PTY EOF for session {}", session_id);
                 break;
             }
             Ok(n) => {
+                state.activity.record_output();
                 let data = buf[..n].to_vec();
-                let _ = tx.send(data);
+                if let Some(recorder) = state.recorder.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+                    recorder.output(&data);
+                }
+                let mut scrollback = state.scrollback.lock().unwrap_or_else(|e| e.into_inner());
+                scrollback.push(&data);
+                let _ = state.tx.send(PtyOutput {
+                    offset: scrollback.total,
+                    data,
+                });
             }
             Err(e) => {
                 tracing::error!("PTY read error for session {}: {}", session_id, e);
@@ -319,6 +1024,18 @@ fn read_pty_output(
             }
         }
     }
+    // The PTY closes when the process exits; reap it for the exit status.
+    let code = state.waiter.and_then(|mut child| match child.wait() {
+        Ok(status) if status.signal().is_none() => Some(status.exit_code() as i32),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!("Failed to wait for process of session {}: {}", session_id, e);
+            None
+        }
+    });
+    tracing::info!("Process for session {} exited (code {:?})", session_id, code);
+    state.activity.mark_exited();
+    state.exit_tx.send_replace(Some(ProcessExit { code }));
 }
 
 /// Errors from process management.
@@ -345,8 +1062,23 @@ pub enum ProcessError {
     #[error("Failed to resize: {0}")]
     ResizeFailed(String),
 
+    #[error("Process has exited: {0}")]
+    Exited(String),
+
     #[error("Invalid model: {0}. Valid models: opus, sonnet, haiku")]
     InvalidModel(String),
+
+    #[error("Invalid command: {0}")]
+    InvalidCommand(String),
+
+    #[error("Failed to start recording: {0}")]
+    RecordingFailed(String),
+
+    #[error(transparent)]
+    Cli(#[from] CliError),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
 }
 
 /// Thread-safe wrapper for ProcessManager.
@@ -355,3 +1087,120 @@ pub type SharedProcessManager = Arc<Mutex<ProcessManager>>;
 pub fn new_shared_process_manager() -> SharedProcessManager {
     Arc::new(Mutex::new(ProcessManager::new()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrollback_keeps_most_recent_bytes() {
+        let mut scrollback = Scrollback::new(8);
+        scrollback.push(b"hello");
+        scrollback.push(b" world");
+        assert_eq!(scrollback.snapshot(), b"lo world");
+
+        // A single chunk larger than the capacity keeps only its tail.
+        scrollback.push(b"0123456789");
+        assert_eq!(scrollback.snapshot(), b"23456789");
+    }
+
+    #[test]
+    fn test_strip_paste_end() {
+        assert_eq!(&*strip_paste_end(b"plain"), b"plain");
+        assert_eq!(&*strip_paste_end(b"a\x1b[201~b"), b"ab");
+        // Removing the inner sequence must not leave a new one behind.
+        assert_eq!(&*strip_paste_end(b"\x1b[20\x1b[201~1~x"), b"x");
+    }
+
+    #[test]
+    fn test_scrollback_since_offset() {
+        let mut scrollback = Scrollback::new(8);
+        scrollback.push(b"hello");
+        scrollback.push(b" world");
+        assert_eq!(scrollback.total, 11);
+
+        assert_eq!(scrollback.since(5).unwrap(), b" world");
+        assert_eq!(scrollback.since(11).unwrap(), b"");
+        // Evicted, or beyond what was ever written.
+        assert!(scrollback.since(2).is_none());
+        assert!(scrollback.since(12).is_none());
+    }
+
+    #[test]
+    fn test_scrollback_restore_keeps_offsets() {
+        // Handed over from a predecessor that had written 11 bytes in total.
+        let mut scrollback = Scrollback::restore(8, b"lo world", 11);
+        assert_eq!(scrollback.since(5).unwrap(), b" world");
+
+        scrollback.push(b"!");
+        assert_eq!(scrollback.total, 12);
+        assert_eq!(scrollback.since(11).unwrap(), b"!");
+    }
+
+    #[tokio::test]
+    async fn test_exit_status_is_reported() {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "echo bye; exit 3"]);
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        let reader = dup_file(pair.master.as_raw_fd().unwrap()).unwrap();
+        let writer = pair.master.take_writer().unwrap();
+        let (child, waiter) = PtyChild::spawned(child);
+        let process = ManagedProcess::start(
+            &SessionId::new("exit-test"),
+            child,
+            Some(waiter),
+            PtyMaster::Opened(pair.master),
+            writer,
+            reader,
+            Scrollback::new(1024),
+            ReaderGate::default(),
+        );
+
+        let mut exited = process.exit_rx.clone();
+        let exit = tokio::time::timeout(Duration::from_secs(5), exited.wait_for(Option::is_some))
+            .await
+            .expect("process did not exit")
+            .map(|exit| *exit)
+            .unwrap();
+        assert_eq!(exit, Some(ProcessExit { code: Some(3) }));
+        assert!(process.activity.has_exited());
+        // Output written before the exit is kept.
+        assert!(String::from_utf8_lossy(&process.scrollback().0).contains("bye"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_command() {
+        let mut pm = ProcessManager::new();
+        let id = SessionId::new("custom-test");
+        let custom = CustomCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo hi".to_string()],
+        };
+        let spawned = pm
+            .create(&id, "sonnet", PermissionMode::Ask, 24, 80, None, None, None, Some(&custom), &ClaudeCli::default(), &[], &[], None, 1024)
+            .unwrap();
+        assert!(!spawned.shell_fallback);
+        assert!(spawned.command.ends_with("sh -c echo hi"));
+        pm.destroy(&id).unwrap();
+
+        let missing = CustomCommand {
+            program: "mado-no-such-program".to_string(),
+            args: Vec::new(),
+        };
+        assert!(matches!(
+            pm.create(&id, "sonnet", PermissionMode::Ask, 24, 80, None, None, None, Some(&missing), &ClaudeCli::default(), &[], &[], None, 1024),
+            Err(ProcessError::InvalidCommand(_))
+        ));
+        assert!(!pm.has_process(&id));
+    }
+}
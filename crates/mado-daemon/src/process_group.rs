@@ -0,0 +1,165 @@
+//! Stopping whole process trees.
+//!
+//! Session processes lead their own process group: PTY processes are session
+//! leaders, and `claude -p` is spawned with `process_group(0)`. Signalling
+//! the group also reaches what they started (shells, test runners, dev
+//! servers), which would otherwise be left running as orphans. Stopping
+//! escalates from SIGINT to SIGTERM to SIGKILL, waiting [`GRACE`] after each.
+
+use std::time::{Duration, Instant};
+
+/// How long each signal gets to stop the group before the next one is sent.
+pub const GRACE: Duration = Duration::from_secs(2);
+
+/// How often a stopping group is checked for survivors.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Send `signal` to every process in group `pgid`. Fails with `ESRCH` once
+/// the group is gone.
+pub fn signal(pgid: u32, signal: libc::c_int) -> std::io::Result<()> {
+    // Never signal our own group (0) or every process we can (-1).
+    if pgid <= 1 {
+        return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    if unsafe { libc::kill(-(pgid as libc::pid_t), signal) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Whether any process (including an unreaped zombie) is left in group `pgid`.
+fn alive(pgid: u32) -> bool {
+    match signal(pgid, 0) {
+        Ok(()) => true,
+        Err(e) => e.raw_os_error() == Some(libc::EPERM),
+    }
+}
+
+/// Stop group `pgid`: send SIGINT now, then escalate to SIGTERM and SIGKILL
+/// on a background thread while members survive. `reap` is called while
+/// waiting, to reap the leader if it is our child.
+///
+/// A group that is already gone is not an error.
+pub fn stop(pgid: u32, reap: impl FnMut() + Send + 'static) -> std::io::Result<()> {
+    match signal(pgid, libc::SIGINT) {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(libc::ESRCH) => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    std::thread::spawn(move || escalate(pgid, GRACE, reap));
+    Ok(())
+}
+
+/// Stop `child`, which leads its own process group, escalating as `stop`
+/// does, and reap it. Group members that outlive it are stopped in the
+/// background.
+pub async fn stop_child(child: &mut tokio::process::Child) -> std::io::Result<()> {
+    // Already reaped.
+    let Some(pgid) = child.id() else {
+        return Ok(());
+    };
+    let _ = signal(pgid, libc::SIGINT);
+    let mut escalation = [libc::SIGTERM, libc::SIGKILL].into_iter();
+    loop {
+        if let Ok(status) = tokio::time::timeout(GRACE, child.wait()).await {
+            status?;
+            break;
+        }
+        match escalation.next() {
+            Some(next) => {
+                let _ = signal(pgid, next);
+            }
+            None => {
+                child.kill().await?;
+                break;
+            }
+        }
+    }
+    if alive(pgid) {
+        stop(pgid, || {})?;
+    }
+    Ok(())
+}
+
+/// Wait for group `pgid` to exit after SIGINT, sending SIGTERM and then
+/// SIGKILL to whatever is left after each `grace`.
+fn escalate(pgid: u32, grace: Duration, mut reap: impl FnMut()) {
+    for next in [libc::SIGTERM, libc::SIGKILL] {
+        if wait_gone(pgid, grace, &mut reap) {
+            return;
+        }
+        tracing::debug!("Process group {} still running, sending signal {}", pgid, next);
+        let _ = signal(pgid, next);
+    }
+    if !wait_gone(pgid, grace, &mut reap) {
+        tracing::warn!("Process group {} survived SIGKILL", pgid);
+    }
+}
+
+/// Wait up to `grace` for group `pgid` to be gone.
+fn wait_gone(pgid: u32, grace: Duration, reap: &mut impl FnMut()) -> bool {
+    let deadline = Instant::now() + grace;
+    loop {
+        reap();
+        if !alive(pgid) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::process::{Command, Stdio};
+
+    use super::*;
+
+    #[test]
+    fn test_stop_escalates_and_reaches_children() {
+        // The shell ignores SIGINT and SIGTERM, so only SIGKILL stops it; its
+        // background child must go too.
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' INT TERM; sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pgid = child.id();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let sleep_pid = line.trim().to_string();
+
+        signal(pgid, libc::SIGINT).unwrap();
+        escalate(pgid, Duration::from_millis(200), || {});
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+
+        // Gone, or a zombie waiting for its new parent to reap it.
+        let state = Command::new("ps").args(["-o", "stat=", "-p", &sleep_pid]).output().unwrap();
+        let state = String::from_utf8_lossy(&state.stdout);
+        assert!(state.trim().is_empty() || state.starts_with('Z'), "sleep is {}", state);
+    }
+
+    #[tokio::test]
+    async fn test_stop_child_reaps_it() {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        tokio::time::timeout(GRACE, stop_child(&mut child)).await.unwrap().unwrap();
+        assert!(child.id().is_none());
+        // Stopping it again is a no-op.
+        stop_child(&mut child).await.unwrap();
+    }
+
+    #[test]
+    fn test_refuses_special_groups() {
+        assert!(signal(0, 0).is_err());
+        assert!(signal(1, 0).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Per-project settings from `.mado.toml`.
+//!
+//! A session uses the `.mado.toml` in its working directory or, up to the
+//! root of the repository it is in, the nearest parent. Its settings
+//! override config.json for the project's sessions, and a session's own
+//! settings override both:
+//!
+//! ```toml
+//! model = "opus"                  # for new sessions that don't name one
+//! system_prompt = "Run cargo fmt before committing."
+//! auto_milestone = true
+//! ignored_paths = ["target/**"]   # hidden from file listings and search
+//! mcp_config = ["mcp.json"]       # passed along with the session's own
+//!
+//! [commands]                      # run with `POST /sessions/{id}/run`
+//! test = "cargo test"
+//! lint = "cargo clippy -- -D warnings"
+//!
+//! [prompts]                       # added to the prompt library
+//! review = "Review {{file}} for missing error handling."
+//! ```
+//!
+//! Paths in `mcp_config` are relative to the file, and commands run in its
+//! directory. The file is read each
+//! time it is needed, so edits apply from the next turn on.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use ignore::overrides::{Override, OverrideBuilder};
+use mado_core::types::{ConfigSource, EffectiveConfig, EffectiveSetting, Session};
+use serde::Deserialize;
+
+use crate::config::DaemonSettings;
+
+/// Name of the project config file.
+pub const FILE_NAME: &str = ".mado.toml";
+
+/// Settings from a `.mado.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub model: Option<String>,
+    pub system_prompt: Option<String>,
+    pub auto_milestone: Option<bool>,
+    /// Globs, relative to the session's working directory.
+    #[serde(default)]
+    pub ignored_paths: Vec<String>,
+    /// MCP server config files, made absolute on load.
+    #[serde(default)]
+    pub mcp_config: Vec<String>,
+    /// Shell commands by name, e.g. `test`, `build` or `lint`.
+    #[serde(default)]
+    pub commands: BTreeMap<String, String>,
+    /// Prompts by name, see [`crate::prompts`].
+    #[serde(default)]
+    pub prompts: BTreeMap<String, String>,
+}
+
+/// The `.mado.toml` that applies to `dir`, if any.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let path = dir.join(FILE_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        // Don't pick up another project's file from above this one.
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Read the project config at `path`.
+pub fn load(path: &Path) -> Result<ProjectConfig, ProjectConfigError> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut config: ProjectConfig = toml::from_str(&contents)?;
+    if let Some(dir) = path.parent() {
+        for file in &mut config.mcp_config {
+            *file = dir.join(&*file).display().to_string();
+        }
+    }
+    config.system_prompt = config.system_prompt.filter(|p| !p.trim().is_empty());
+    Ok(config)
+}
+
+/// The project config for a session working in `dir`, and where it is.
+pub fn discover(dir: Option<&str>) -> Result<Option<(PathBuf, ProjectConfig)>, ProjectConfigError> {
+    let Some(path) = dir.and_then(|dir| find(Path::new(dir))) else {
+        return Ok(None);
+    };
+    let config = load(&path).map_err(|e| ProjectConfigError::Invalid(path.clone(), e.to_string()))?;
+    Ok(Some((path, config)))
+}
+
+/// The project config for a session working in `dir`; none (with a warning)
+/// if its file is invalid.
+pub fn for_dir(dir: Option<&str>) -> ProjectConfig {
+    match discover(dir) {
+        Ok(found) => found.map(|(_, config)| config).unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Ignoring project config: {}", e);
+            ProjectConfig::default()
+        }
+    }
+}
+
+/// `session` MCP config files followed by the project's.
+pub fn mcp_config(session: &[String], project: &ProjectConfig) -> Vec<String> {
+    let mut files = session.to_vec();
+    for file in &project.mcp_config {
+        if !files.contains(file) {
+            files.push(file.clone());
+        }
+    }
+    files
+}
+
+/// Matches `ignored_paths` under `root`, if there are any, for walkers to
+/// filter on. (Used as walker overrides they would bring back what
+/// `.gitignore` hides.) Invalid globs are skipped with a warning.
+pub fn ignored(root: &Path, ignored_paths: &[String]) -> Option<Override> {
+    if ignored_paths.is_empty() {
+        return None;
+    }
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in ignored_paths {
+        if let Err(e) = overrides.add(&format!("!{}", glob)) {
+            tracing::warn!("Ignoring invalid ignored_paths glob {:?}: {}", glob, e);
+        }
+    }
+    overrides
+        .build()
+        .inspect_err(|e| tracing::warn!("Ignoring ignored_paths: {}", e))
+        .ok()
+}
+
+/// Whether `ignored` hides `path`.
+pub fn is_ignored(ignored: Option<&Override>, path: &Path, is_dir: bool) -> bool {
+    ignored.is_some_and(|ignored| ignored.matched(path, is_dir).is_ignore())
+}
+
+/// What applies to `session`, layer by layer.
+pub fn effective(session: &Session, settings: &DaemonSettings) -> EffectiveConfig {
+    let mut config = EffectiveConfig::default();
+    let project = match discover(session.working_dir.as_deref()) {
+        Ok(Some((path, project))) => {
+            config.project_file = Some(path.display().to_string());
+            project
+        }
+        Ok(None) => ProjectConfig::default(),
+        Err(e) => {
+            if let ProjectConfigError::Invalid(path, _) = &e {
+                config.project_file = Some(path.display().to_string());
+            }
+            config.project_error = Some(e.to_string());
+            ProjectConfig::default()
+        }
+    };
+
+    let mut add = |name: &str, value: serde_json::Value, source: ConfigSource| {
+        config.settings.push(EffectiveSetting {
+            name: name.to_string(),
+            value,
+            source,
+        });
+    };
+    add("model", session.model.clone().into(), ConfigSource::Session);
+    match (&session.system_prompt, &project.system_prompt) {
+        (Some(prompt), _) => add("system_prompt", prompt.clone().into(), ConfigSource::Session),
+        (None, Some(prompt)) => add("system_prompt", prompt.clone().into(), ConfigSource::Project),
+        (None, None) => add("system_prompt", serde_json::Value::Null, ConfigSource::Default),
+    }
+    match (session.auto_milestone, project.auto_milestone) {
+        (Some(enabled), _) => add("auto_milestone", enabled.into(), ConfigSource::Session),
+        (None, Some(enabled)) => add("auto_milestone", enabled.into(), ConfigSource::Project),
+        (None, None) => add("auto_milestone", settings.auto_milestone.into(), ConfigSource::Global),
+    }
+    let source = if project.ignored_paths.is_empty() {
+        ConfigSource::Default
+    } else {
+        ConfigSource::Project
+    };
+    add("ignored_paths", project.ignored_paths.clone().into(), source);
+    let source = if !session.mcp_config.is_empty() {
+        ConfigSource::Session
+    } else if !project.mcp_config.is_empty() {
+        ConfigSource::Project
+    } else {
+        ConfigSource::Default
+    };
+    add("mcp_config", mcp_config(&session.mcp_config, &project).into(), source);
+    config
+}
+
+/// Errors from reading a project config.
+#[derive(Debug, thiserror::Error)]
+pub enum ProjectConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Invalid {}: {}", .0.display(), .1)]
+    Invalid(PathBuf, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let sub = repo.join("crates").join("app");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::create_dir(repo.join(".git")).unwrap();
+        // Outside the repository, so never used for it.
+        std::fs::write(dir.path().join(FILE_NAME), "model = \"haiku\"").unwrap();
+        assert_eq!(find(&sub), None);
+
+        std::fs::write(
+            repo.join(FILE_NAME),
+            "model = \"opus\"\nignored_paths = [\"target/**\"]\nmcp_config = [\"mcp.json\"]\n",
+        )
+        .unwrap();
+        let (path, config) = discover(sub.to_str()).unwrap().unwrap();
+        assert_eq!(path, repo.join(FILE_NAME));
+        assert_eq!(config.model.as_deref(), Some("opus"));
+        assert_eq!(config.mcp_config, [repo.join("mcp.json").display().to_string()]);
+        assert_eq!(
+            mcp_config(&["a.json".to_string()], &config),
+            ["a.json".to_string(), repo.join("mcp.json").display().to_string()]
+        );
+
+        std::fs::write(repo.join(FILE_NAME), "modle = \"opus\"").unwrap();
+        assert!(matches!(discover(sub.to_str()), Err(ProjectConfigError::Invalid(..))));
+        assert_eq!(for_dir(sub.to_str()), ProjectConfig::default());
+    }
+
+    #[test]
+    fn test_ignored() {
+        let root = Path::new("/project");
+        assert!(ignored(root, &[]).is_none());
+        let ignored = ignored(root, &["target/**".to_string(), "*.log".to_string()]);
+        assert!(is_ignored(ignored.as_ref(), Path::new("/project/target/debug/app"), false));
+        assert!(is_ignored(ignored.as_ref(), Path::new("/project/build.log"), false));
+        assert!(!is_ignored(ignored.as_ref(), Path::new("/project/src/main.rs"), false));
+    }
+}
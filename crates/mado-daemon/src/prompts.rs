@@ -0,0 +1,263 @@
+//! The prompt library: reusable prompts the command palette inserts.
+//!
+//! The user's prompts are JSON files in `prompts/` next to state.json
+//! (`~/.mado/prompts/<name>.json`), like session templates. A project can
+//! add its own in the `[prompts]` table of its `.mado.toml`, committed with
+//! the code so a team shares them; they override the user's of the same
+//! name for sessions in the project:
+//!
+//! ```toml
+//! [prompts]
+//! review = "Review {{file}} on {{branch}} for missing error handling."
+//! ```
+//!
+//! `{{name}}` in a prompt is a variable, filled in when it is rendered for
+//! a session. `{{branch}}` is the session's git branch; `{{file}}` and any
+//! others come with the request.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use mado_core::types::{PromptScope, SavedPrompt};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PromptError {
+    #[error("Prompt not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid prompt name: {0:?}")]
+    InvalidName(String),
+
+    #[error("Prompt is empty")]
+    Empty,
+
+    #[error("Unreadable prompt {name}: {source}")]
+    Unreadable {
+        name: String,
+        source: serde_json::Error,
+    },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The user's prompts in a directory.
+#[derive(Debug)]
+pub struct Prompts {
+    dir: PathBuf,
+}
+
+impl Prompts {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The user's readable prompts and `project`'s, by name. Project prompts
+    /// replace the user's of the same name.
+    pub fn list(&self, project: &BTreeMap<String, String>) -> Vec<SavedPrompt> {
+        let mut prompts: BTreeMap<String, SavedPrompt> = BTreeMap::new();
+        match fs::read_dir(&self.dir) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    let Some(name) = entry.file_name().to_str().and_then(|n| n.strip_suffix(".json")).map(String::from)
+                    else {
+                        continue;
+                    };
+                    match self.get_user(&name) {
+                        Ok(prompt) => {
+                            prompts.insert(name, prompt);
+                        }
+                        Err(e) => tracing::warn!("Skipping prompt: {}", e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to read prompts in {}: {}", self.dir.display(), e),
+        }
+        for (name, content) in project {
+            prompts.insert(name.clone(), project_prompt(name, content));
+        }
+        prompts.into_values().collect()
+    }
+
+    /// The prompt `name`, from `project` if it has one.
+    pub fn get(&self, name: &str, project: &BTreeMap<String, String>) -> Result<SavedPrompt, PromptError> {
+        match project.get(name) {
+            Some(content) => Ok(project_prompt(name, content)),
+            None => self.get_user(name),
+        }
+    }
+
+    fn get_user(&self, name: &str) -> Result<SavedPrompt, PromptError> {
+        let contents = match fs::read_to_string(self.path(name)?) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(PromptError::NotFound(name.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut prompt: SavedPrompt = serde_json::from_str(&contents).map_err(|source| PromptError::Unreadable {
+            name: name.to_string(),
+            source,
+        })?;
+        // The file name is what the prompt is looked up by.
+        prompt.name = name.to_string();
+        prompt.scope = PromptScope::User;
+        prompt.variables = variables(&prompt.content);
+        Ok(prompt)
+    }
+
+    /// Add `prompt` to the user's, replacing any of the same name. Returns
+    /// it as it will be listed.
+    pub fn save(&self, prompt: SavedPrompt) -> Result<SavedPrompt, PromptError> {
+        let path = self.path(&prompt.name)?;
+        if prompt.content.trim().is_empty() {
+            return Err(PromptError::Empty);
+        }
+        let prompt = SavedPrompt {
+            scope: PromptScope::User,
+            variables: variables(&prompt.content),
+            ..prompt
+        };
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string_pretty(&prompt).expect("SavedPrompt serializes");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(prompt)
+    }
+
+    /// Remove one of the user's prompts.
+    pub fn remove(&self, name: &str) -> Result<(), PromptError> {
+        match fs::remove_file(self.path(name)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(PromptError::NotFound(name.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, PromptError> {
+        let valid = !name.trim().is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '));
+        if !valid {
+            return Err(PromptError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+fn project_prompt(name: &str, content: &str) -> SavedPrompt {
+    SavedPrompt {
+        name: name.to_string(),
+        description: None,
+        content: content.to_string(),
+        scope: PromptScope::Project,
+        variables: variables(content),
+    }
+}
+
+/// The `{{name}}` variables in `content`, in order of first use.
+pub fn variables(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (_, name) in placeholders(content) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// `content` with its variables replaced by `values`, and the variables
+/// there was no value for, left as they are.
+pub fn render(content: &str, values: &HashMap<String, String>) -> (String, Vec<String>) {
+    let mut rendered = String::with_capacity(content.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut rest = 0;
+    for (range, name) in placeholders(content) {
+        rendered.push_str(&content[rest..range.start]);
+        match values.get(name) {
+            Some(value) => rendered.push_str(value),
+            None => {
+                rendered.push_str(&content[range.clone()]);
+                if !missing.iter().any(|n| n == name) {
+                    missing.push(name.to_string());
+                }
+            }
+        }
+        rest = range.end;
+    }
+    rendered.push_str(&content[rest..]);
+    (rendered, missing)
+}
+
+/// Each `{{ name }}` in `content` with its byte range. Names are letters,
+/// digits, `_` and `-`; anything else between braces is left alone.
+fn placeholders(content: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = content[from..].find("{{").map(|i| from + i) {
+        let Some(end) = content[start + 2..].find("}}").map(|i| start + 2 + i) else {
+            break;
+        };
+        let name = content[start + 2..end].trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')) {
+            found.push((start..end + 2, name));
+            from = end + 2;
+        } else {
+            from = start + 2;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompts_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let prompts = Prompts::new(dir.path().join("prompts"));
+        let none = BTreeMap::new();
+        assert!(prompts.list(&none).is_empty());
+
+        let saved = prompts
+            .save(SavedPrompt {
+                name: "review".to_string(),
+                description: Some("Careful review".to_string()),
+                content: "Review {{file}} on {{ branch }}.".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(saved.variables, ["file", "branch"]);
+        prompts
+            .save(SavedPrompt {
+                name: "tests".to_string(),
+                content: "Add tests.".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(matches!(prompts.get("../x", &none), Err(PromptError::InvalidName(_))));
+
+        let project = BTreeMap::from([("tests".to_string(), "Add tests for {{file}}.".to_string())]);
+        let listed = prompts.list(&project);
+        let names: Vec<_> = listed.iter().map(|p| (p.name.as_str(), p.scope)).collect();
+        assert_eq!(names, [("review", PromptScope::User), ("tests", PromptScope::Project)]);
+        assert_eq!(prompts.get("tests", &none).unwrap().content, "Add tests.");
+
+        prompts.remove("tests").unwrap();
+        assert!(matches!(prompts.remove("tests"), Err(PromptError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_render() {
+        let values = HashMap::from([("file".to_string(), "src/lib.rs".to_string())]);
+        let (rendered, missing) = render("Fix {{file}} on {{branch}}, then {{ file }}. {{not a var}}", &values);
+        assert_eq!(rendered, "Fix src/lib.rs on {{branch}}, then src/lib.rs. {{not a var}}");
+        assert_eq!(missing, ["branch"]);
+        assert_eq!(variables("{{a}}{{b}}{{a}} {{"), ["a", "b"]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Deleting what the daemon keeps, for `POST /admin/purge`.
+//!
+//! A purge stops what it removes before deleting it, so a conversation in
+//! progress can't write its files back. Directories are deleted without
+//! following symlinks: a link inside `~/.mado` is removed, not what it
+//! points to.
+
+use std::io;
+use std::path::Path;
+
+use mado_core::types::{PurgeReport, PurgeScope};
+
+use crate::server::AppState;
+
+/// Delete `path`, a file or directory tree, recording it in `report`. A
+/// symlink is deleted itself; a path that doesn't exist is skipped.
+fn remove_path(path: &Path, report: &mut PurgeReport) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    report.paths.push(path.display().to_string());
+    Ok(())
+}
+
+/// Drop every conversation and delete chat history, attachments and
+/// archived sessions.
+async fn purge_conversations(state: &AppState, report: &mut PurgeReport) -> io::Result<()> {
+    report.conversations = state.conversation_manager.clear_all().await;
+    remove_path(state.conversation_manager.storage_dir(), report)?;
+    if let Some(dir) = &state.archive_dir {
+        remove_path(dir, report)?;
+    }
+    Ok(())
+}
+
+/// Remove every session, stopping its process, then its conversation and
+/// recording.
+async fn purge_sessions(state: &AppState, report: &mut PurgeReport) -> io::Result<()> {
+    for session in state.session_manager.list_sessions().await {
+        match crate::retention::remove(state, &session.id).await {
+            Ok(()) => report.sessions += 1,
+            Err(e) => tracing::warn!("Failed to remove session {}: {}", session.id, e),
+        }
+    }
+    purge_conversations(state, report).await?;
+    if let Some(dir) = state.session_manager.recordings_dir() {
+        remove_path(&dir, report)?;
+    }
+    Ok(())
+}
+
+/// Delete old logs and crash reports and empty the current log.
+fn purge_logs(state: &AppState, report: &mut PurgeReport) -> io::Result<()> {
+    if let Some(dir) = &state.log_dir {
+        let cleared = crate::logs::clear(dir)?;
+        report.paths.extend(cleared.iter().map(|path| path.display().to_string()));
+    }
+    if let Some(dir) = &state.crash_dir {
+        remove_path(dir, report)?;
+    }
+    Ok(())
+}
+
+/// Delete what `scope` covers.
+pub async fn purge(state: &AppState, scope: PurgeScope) -> io::Result<PurgeReport> {
+    let mut report = PurgeReport::default();
+    match scope {
+        PurgeScope::Conversations => purge_conversations(state, &mut report).await?,
+        PurgeScope::Sessions => purge_sessions(state, &mut report).await?,
+        PurgeScope::Logs => purge_logs(state, &mut report)?,
+        PurgeScope::Everything => {
+            purge_sessions(state, &mut report).await?;
+            purge_logs(state, &mut report)?;
+            state.conversation_manager.ledger().clear()?;
+            state.scheduler.clear();
+            remove_path(state.templates.dir(), &mut report)?;
+            state.session_manager.forget_trusted_workspaces().await;
+        }
+    }
+    tracing::info!(
+        "Purged {}: {} session(s), {} conversation(s), {} path(s)",
+        scope.as_str(),
+        report.sessions,
+        report.conversations,
+        report.paths.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_path_keeps_symlink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("keep.txt"), "keep").unwrap();
+        let purged = dir.path().join("purged");
+        std::fs::create_dir(&purged).unwrap();
+        std::os::unix::fs::symlink(&target, purged.join("link")).unwrap();
+
+        let mut report = PurgeReport::default();
+        remove_path(&purged, &mut report).unwrap();
+        remove_path(&dir.path().join("missing"), &mut report).unwrap();
+        assert!(!purged.exists());
+        assert!(target.join("keep.txt").exists());
+        assert_eq!(report.paths, [purged.display().to_string()]);
+    }
+}
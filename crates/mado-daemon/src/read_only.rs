@@ -0,0 +1,218 @@
+//! Read-only sessions, which the daemon keeps from changing the workspace
+//! whatever the agent asks for.
+//!
+//! The CLI runs in plan mode with its editing tools disallowed, and in chat
+//! mode a turn is stopped as soon as Claude calls a tool that edits files.
+//! Beyond that, a chat turn never runs in the workspace: it runs in a copy
+//! of the repository (a [`Scratch`]) made from a snapshot of the workspace
+//! as the turn starts, and the copy is deleted when the turn ends. Whatever
+//! a shell command or subagent writes, the workspace is left as it was; the
+//! changes made to the copy are listed to the user. A copy needs a git
+//! repository, so only sessions working in one can be read-only.
+//!
+//! The copy has the workspace's tracked and untracked files, not its
+//! gitignored ones, and its own git directory, borrowing the objects of
+//! the repository's. It is made in the same place for every turn of a
+//! session, so the Claude CLI, which keeps its sessions by directory, can
+//! resume the previous turn. The session's terminal is the user's: it is
+//! started in plan mode, in the workspace.
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use mado_core::types::PermissionMode;
+
+use crate::git_ops::GitError;
+
+/// Claude CLI tools that edit files.
+pub const WRITE_TOOLS: [&str; 4] = ["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+/// Whether the tool `name` edits files.
+pub fn writes(name: &str) -> bool {
+    WRITE_TOOLS.contains(&name)
+}
+
+/// The permission mode a session runs in: plan mode if it is read-only.
+pub fn permission_mode(mode: PermissionMode, read_only: bool) -> PermissionMode {
+    if read_only { PermissionMode::Plan } else { mode }
+}
+
+/// Arguments keeping the Claude CLI from editing files, after
+/// `--permission-mode`.
+pub fn cli_args() -> [String; 2] {
+    ["--disallowedTools".to_string(), WRITE_TOOLS.join(",")]
+}
+
+/// What is said when a turn of a read-only session is stopped for calling
+/// `tool`.
+pub fn blocked_message(tool: &str) -> String {
+    format!(
+        "The response was stopped: Claude called {}, which edits files, and this session is read-only.",
+        tool
+    )
+}
+
+/// What is said when a turn of a read-only session changed `paths` in its
+/// copy of the workspace.
+pub fn discarded_message(paths: &[String]) -> String {
+    format!(
+        "This session is read-only, so Claude worked on a copy of the workspace. Its changes were discarded: {}.",
+        paths.join(", ")
+    )
+}
+
+/// Directory the turns of `session_id` in the repository at `root` run in,
+/// inside its git directory.
+pub fn scratch_dir(root: &Path, session_id: &str) -> Result<PathBuf, GitError> {
+    Ok(Repository::open(root)?.path().join("mado-read-only").join(session_id))
+}
+
+/// A throwaway copy of a repository that one read-only turn runs in,
+/// deleted when dropped.
+#[derive(Debug)]
+pub struct Scratch {
+    dir: PathBuf,
+    /// The copy of the session's working directory.
+    working_dir: PathBuf,
+    /// The workspace snapshot the copy was made from.
+    tree: String,
+}
+
+impl Scratch {
+    /// Copy the workspace of the repository at `root` into the session's
+    /// scratch directory, replacing what a turn left there. HEAD and the
+    /// index are the repository's, so the workspace's changes show as
+    /// uncommitted in the copy too.
+    pub fn create(root: &Path, working_dir: &Path, session_id: &str) -> Result<Self, GitError> {
+        let dir = scratch_dir(root, session_id)?;
+        let relative = working_dir.strip_prefix(root).unwrap_or(Path::new(""));
+        remove_dir(&dir).map_err(|e| GitError::PathError(format!("{}: {}", dir.display(), e)))?;
+        // Removed again if anything below fails.
+        let scratch = Self {
+            working_dir: dir.join(relative),
+            tree: crate::git_ops::snapshot_workdir(root)?,
+            dir,
+        };
+
+        let repo = Repository::open(root)?;
+        Repository::init(&scratch.dir)?;
+        let alternates = scratch.dir.join(".git").join("objects").join("info").join("alternates");
+        std::fs::write(&alternates, format!("{}\n", repo.commondir().join("objects").display()))
+            .map_err(|e| GitError::PathError(format!("{}: {}", alternates.display(), e)))?;
+        // Reopened, to read the alternates.
+        let copy = Repository::open(&scratch.dir)?;
+        if let Ok(head) = repo.head()
+            && let Some(oid) = head.target()
+        {
+            match head.name().filter(|_| head.is_branch()) {
+                Some(name) => {
+                    copy.reference(name, oid, true, "Read-only copy")?;
+                    copy.set_head(name)?;
+                }
+                None => copy.set_head_detached(oid)?,
+            }
+            let mut index = copy.index()?;
+            index.read_tree(&copy.find_commit(oid)?.tree()?)?;
+            index.write()?;
+        }
+        let tree = copy.find_tree(git2::Oid::from_str(&scratch.tree)?)?;
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.force().update_index(false);
+        copy.checkout_tree(tree.as_object(), Some(&mut checkout))?;
+        std::fs::create_dir_all(&scratch.working_dir)
+            .map_err(|e| GitError::PathError(format!("{}: {}", scratch.working_dir.display(), e)))?;
+        Ok(scratch)
+    }
+
+    /// Where the turn runs: the copy of the session's working directory.
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
+    /// Paths the turn changed in the copy.
+    pub fn changes(&self) -> Result<Vec<String>, GitError> {
+        let after = crate::git_ops::snapshot_workdir(&self.dir)?;
+        crate::review::turn_paths(&self.dir, &self.tree, &after)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if let Err(e) = remove_dir(&self.dir) {
+            tracing::warn!("Failed to remove read-only copy {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Remove `dir` and everything in it, if it exists.
+pub fn remove_dir(dir: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writes() {
+        assert!(writes("Edit"));
+        assert!(writes("NotebookEdit"));
+        assert!(!writes("Read"));
+        assert!(!writes("Bash"));
+    }
+
+    #[test]
+    fn test_permission_mode() {
+        assert_eq!(permission_mode(PermissionMode::FullAuto, true), PermissionMode::Plan);
+        assert_eq!(permission_mode(PermissionMode::AutoEdit, false), PermissionMode::AutoEdit);
+    }
+
+    #[test]
+    fn test_cli_args() {
+        assert_eq!(cli_args(), ["--disallowedTools", "Edit,MultiEdit,Write,NotebookEdit"]);
+    }
+
+    #[test]
+    fn test_scratch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        crate::git_ops::init_repo(root).unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src").join("lib.rs"), "one\n").unwrap();
+        crate::git_ops::save_milestone(root, "Add lib").unwrap();
+        std::fs::write(root.join("src").join("lib.rs"), "two\n").unwrap();
+        std::fs::write(root.join("notes.txt"), "untracked\n").unwrap();
+
+        let scratch = Scratch::create(root, &root.join("src"), "s1").unwrap();
+        let dir = scratch_dir(root, "s1").unwrap();
+        assert!(dir.starts_with(root.join(".git")));
+        assert_eq!(scratch.working_dir(), dir.join("src"));
+        assert_eq!(std::fs::read_to_string(dir.join("src").join("lib.rs")).unwrap(), "two\n");
+        assert_eq!(std::fs::read_to_string(dir.join("notes.txt")).unwrap(), "untracked\n");
+        // The workspace's changes are uncommitted in the copy as well.
+        let status = crate::git_ops::git_status(&dir).unwrap();
+        assert_eq!(status.unstaged.len(), 2, "{:?}", status.unstaged);
+        assert!(status.staged.is_empty());
+
+        std::fs::write(dir.join("src").join("lib.rs"), "three\n").unwrap();
+        std::fs::write(dir.join("new.txt"), "new\n").unwrap();
+        assert_eq!(scratch.changes().unwrap(), ["new.txt", "src/lib.rs"]);
+        drop(scratch);
+        assert!(!dir.exists());
+        assert_eq!(std::fs::read_to_string(root.join("src").join("lib.rs")).unwrap(), "two\n");
+        assert!(!root.join("new.txt").exists());
+        // Nothing of the copy shows in the workspace.
+        let status = crate::git_ops::git_status(root).unwrap();
+        assert_eq!(status.unstaged.len(), 2, "{:?}", status.unstaged);
+    }
+
+    #[test]
+    fn test_discarded_message() {
+        assert_eq!(
+            discarded_message(&["a.txt".to_string(), "b.txt".to_string()]),
+            "This session is read-only, so Claude worked on a copy of the workspace. Its changes were discarded: a.txt, b.txt."
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Terminal recordings in asciicast v2 format.
+//!
+//! With `recording.enabled` set in config.json, every session's PTY output
+//! is appended to `~/.mado/recordings/<session>.cast` as it is read, so a
+//! session can be replayed later (e.g. with `asciinema play`) to see what
+//! an agent did. A recording stops growing once it reaches
+//! `recording.max_mb`.
+//!
+//! The file is one JSON header line followed by one JSON array per event:
+//! `[seconds, "o", "output"]` for output and `[seconds, "r", "COLSxROWS"]`
+//! for resizes. A respawned process appends to its session's existing
+//! recording; event times stay relative to the header's timestamp, so the
+//! time the session sat without a process shows up as a pause.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The recording of a session, in `dir`.
+pub fn path(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(format!("{}.cast", session_id))
+}
+
+/// Appends a PTY's output to an asciicast file.
+#[derive(Debug)]
+pub struct Recorder {
+    file: File,
+    /// When the recording started, from its header.
+    started: SystemTime,
+    /// Bytes in the file so far.
+    written: u64,
+    max_bytes: u64,
+    /// Trailing bytes of an incomplete UTF-8 sequence, held for the next chunk.
+    pending: Vec<u8>,
+}
+
+impl Recorder {
+    /// Continue the recording at `path`, or start a new one for a terminal
+    /// of `cols` x `rows` if there is none (or it is unreadable).
+    pub fn open(path: &Path, cols: u16, rows: u16, max_bytes: u64) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if let Some(started) = read_start(path) {
+            let file = OpenOptions::new().append(true).open(path)?;
+            let written = file.metadata()?.len();
+            let mut recorder = Self {
+                file,
+                started,
+                written,
+                max_bytes,
+                pending: Vec::new(),
+            };
+            recorder.resize(cols, rows);
+            return Ok(recorder);
+        }
+
+        let started = SystemTime::now();
+        let header = serde_json::json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": started.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        });
+        let mut file = File::create(path)?;
+        let line = format!("{}\n", header);
+        file.write_all(line.as_bytes())?;
+        Ok(Self {
+            file,
+            started,
+            written: line.len() as u64,
+            max_bytes,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Record a chunk of PTY output.
+    pub fn output(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // Hold back a sequence split across reads; give up on invalid ones.
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        if complete == 0 {
+            return;
+        }
+        let rest = self.pending.split_off(complete);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        self.event("o", &text);
+    }
+
+    /// Record a terminal resize.
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.event("r", &format!("{}x{}", cols, rows));
+    }
+
+    fn event(&mut self, kind: &str, data: &str) {
+        if self.written >= self.max_bytes {
+            return;
+        }
+        let elapsed = SystemTime::now()
+            .duration_since(self.started)
+            .unwrap_or(Duration::ZERO)
+            .as_secs_f64();
+        let line = format!("{}\n", serde_json::json!([(elapsed * 1e6).round() / 1e6, kind, data]));
+        self.written += line.len() as u64;
+        if self.written >= self.max_bytes {
+            tracing::info!("Recording reached its size limit, no longer recording");
+        }
+        if let Err(e) = self.file.write_all(line.as_bytes()) {
+            tracing::warn!("Failed to write recording: {}", e);
+            self.written = self.max_bytes;
+        }
+    }
+}
+
+/// When the recording at `path` started, if it has a valid header.
+fn read_start(path: &Path) -> Option<SystemTime> {
+    let file = File::open(path).ok()?;
+    let mut header = String::new();
+    BufReader::new(file).read_line(&mut header).ok()?;
+    let header: serde_json::Value = serde_json::from_str(&header).ok()?;
+    if header.get("version")?.as_u64()? != 2 {
+        return None;
+    }
+    let timestamp = header.get("timestamp")?.as_u64()?;
+    Some(UNIX_EPOCH + Duration::from_secs(timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_recording_format_and_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path(dir.path(), "abc");
+
+        let mut recorder = Recorder::open(&path, 80, 24, 1024).unwrap();
+        recorder.output(b"hello ");
+        // A character split across two reads is written whole.
+        recorder.output(&"é".as_bytes()[..1]);
+        recorder.output(&"é".as_bytes()[1..]);
+        recorder.resize(120, 40);
+        drop(recorder);
+
+        let events = lines(&path);
+        assert_eq!(events[0]["version"], 2);
+        assert_eq!(events[0]["width"], 80);
+        assert_eq!(events[0]["height"], 24);
+        assert_eq!(events[1][1], "o");
+        assert_eq!(events[1][2], "hello ");
+        assert_eq!(events[2][2], "é");
+        assert_eq!(events[3][1], "r");
+        assert_eq!(events[3][2], "120x40");
+        assert_eq!(events.len(), 4);
+
+        // Reopening appends after a resize event, and stops at the limit.
+        let mut recorder = Recorder::open(&path, 100, 30, 1024).unwrap();
+        for _ in 0..100 {
+            recorder.output(b"0123456789");
+        }
+        drop(recorder);
+        let events = lines(&path);
+        assert_eq!(events[4][2], "100x30");
+        assert!(events.len() < 104);
+        let size = std::fs::metadata(&path).unwrap().len();
+        assert!(size < 1024 + 64, "recording grew to {} bytes", size);
+    }
+}
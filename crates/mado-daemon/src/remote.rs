@@ -0,0 +1,267 @@
+//! Optional TCP listener for remote access to the daemon.
+//!
+//! The Unix socket is always served. With `--listen tcp://ADDR:PORT` the same
+//! router (including its bearer-token check, see `auth`) is also served over
+//! TCP, using rustls TLS when a certificate and key are configured.
+
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing;
+
+/// Connections that have not finished the TLS handshake by now are dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Completed TLS handshakes waiting to be served.
+const TLS_ACCEPT_BACKLOG: usize = 64;
+
+/// Configuration for the TCP listener.
+#[derive(Debug, Clone)]
+pub struct TcpListenConfig {
+    /// Address to bind.
+    pub addr: SocketAddr,
+    /// Certificate and key for TLS. Plain TCP when absent.
+    pub tls: Option<TlsFiles>,
+}
+
+/// PEM files for the TLS listener.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    /// Certificate chain.
+    pub cert_path: PathBuf,
+    /// Private key.
+    pub key_path: PathBuf,
+}
+
+/// Errors that can occur setting up the TCP listener.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteError {
+    #[error("Invalid listen address '{0}' (expected tcp://HOST:PORT)")]
+    InvalidAddress(String),
+
+    #[error("Failed to bind TCP listener on {addr}: {source}")]
+    BindFailed {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to read {path}: {message}")]
+    InvalidPem { path: PathBuf, message: String },
+
+    #[error("Invalid TLS configuration: {0}")]
+    Tls(#[from] rustls::Error),
+}
+
+/// Parse a `--listen` value of the form `tcp://HOST:PORT`.
+pub fn parse_listen_addr(value: &str) -> Result<SocketAddr, RemoteError> {
+    value
+        .strip_prefix("tcp://")
+        .and_then(|addr| addr.parse().ok())
+        .ok_or_else(|| RemoteError::InvalidAddress(value.to_string()))
+}
+
+/// Load a rustls server config from PEM certificate and key files.
+pub fn load_tls_config(files: &TlsFiles) -> Result<Arc<rustls::ServerConfig>, RemoteError> {
+    let pem_error = |path: &Path, e: &dyn std::fmt::Display| RemoteError::InvalidPem {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+
+    let certs = CertificateDer::pem_file_iter(&files.cert_path)
+        .map_err(|e| pem_error(&files.cert_path, &e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| pem_error(&files.cert_path, &e))?;
+    let key = PrivateKeyDer::from_pem_file(&files.key_path)
+        .map_err(|e| pem_error(&files.key_path, &e))?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+/// Bind the TCP listener, returning plain TCP or TLS depending on config.
+pub async fn bind(config: &TcpListenConfig) -> Result<RemoteListener, RemoteError> {
+    let tls = config.tls.as_ref().map(load_tls_config).transpose()?;
+
+    let listener = TcpListener::bind(config.addr)
+        .await
+        .map_err(|source| RemoteError::BindFailed {
+            addr: config.addr,
+            source,
+        })?;
+
+    match tls {
+        Some(tls) => Ok(RemoteListener::Tls(TlsListener::new(listener, tls))),
+        None => Ok(RemoteListener::Plain(listener)),
+    }
+}
+
+/// Serve on an already bound listener, e.g. one inherited at a graceful restart.
+pub fn from_std(
+    listener: std::net::TcpListener,
+    config: &TcpListenConfig,
+) -> Result<RemoteListener, RemoteError> {
+    let tls = config.tls.as_ref().map(load_tls_config).transpose()?;
+
+    let listener = listener
+        .set_nonblocking(true)
+        .and_then(|()| TcpListener::from_std(listener))
+        .map_err(|source| RemoteError::BindFailed {
+            addr: config.addr,
+            source,
+        })?;
+
+    match tls {
+        Some(tls) => Ok(RemoteListener::Tls(TlsListener::new(listener, tls))),
+        None => Ok(RemoteListener::Plain(listener)),
+    }
+}
+
+/// A bound TCP listener, with or without TLS.
+pub enum RemoteListener {
+    Plain(TcpListener),
+    Tls(TlsListener),
+}
+
+impl RemoteListener {
+    /// The address the listener is bound to.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            RemoteListener::Plain(listener) => listener.local_addr(),
+            RemoteListener::Tls(listener) => Ok(listener.local_addr),
+        }
+    }
+
+    /// The listening socket's fd, for handing over to a successor daemon.
+    pub fn as_raw_fd(&self) -> RawFd {
+        match self {
+            RemoteListener::Plain(listener) => listener.as_raw_fd(),
+            RemoteListener::Tls(listener) => listener.fd,
+        }
+    }
+}
+
+/// TLS listener for `axum::serve`.
+///
+/// Handshakes run in their own tasks so a slow client cannot stall `accept`.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    /// The listening socket, owned by the acceptor task.
+    fd: RawFd,
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    acceptor_task: JoinHandle<()>,
+}
+
+impl TlsListener {
+    fn new(listener: TcpListener, config: Arc<rustls::ServerConfig>) -> Self {
+        let local_addr = listener
+            .local_addr()
+            .expect("Bound TCP listener should have a local address");
+        let fd = listener.as_raw_fd();
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, accepted) = mpsc::channel(TLS_ACCEPT_BACKLOG);
+
+        let acceptor_task = tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::warn!("TCP accept failed: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(tls)) => {
+                            let _ = tx.send((tls, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+
+        Self {
+            local_addr,
+            fd,
+            accepted,
+            acceptor_task,
+        }
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.acceptor_task.abort();
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(conn) => conn,
+            // The acceptor task only stops when the listener is dropped.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Serve the router on the TCP listener until `shutdown` resolves.
+pub async fn serve(
+    listener: RemoteListener,
+    app: Router,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    match listener {
+        RemoteListener::Plain(listener) => {
+            axum::serve(listener, app).with_graceful_shutdown(shutdown).await
+        }
+        RemoteListener::Tls(listener) => {
+            axum::serve(listener, app).with_graceful_shutdown(shutdown).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(
+            parse_listen_addr("tcp://0.0.0.0:7420").unwrap(),
+            "0.0.0.0:7420".parse::<SocketAddr>().unwrap()
+        );
+        assert!(parse_listen_addr("tcp://[::1]:7420").is_ok());
+        assert!(parse_listen_addr("0.0.0.0:7420").is_err());
+        assert!(parse_listen_addr("tcp://localhost").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Sequenced broadcast with a bounded replay buffer.
+//!
+//! Backs `Last-Event-ID` resumption on the chat SSE stream: every event gets a
+//! monotonically increasing id, and a reconnecting client is handed the events
+//! it missed (as far back as the buffer reaches) before live ones.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast;
+
+/// Events kept per channel for replay.
+pub const REPLAY_CAPACITY: usize = 1024;
+
+/// Capacity of the live broadcast channel.
+const LIVE_CAPACITY: usize = 256;
+
+/// A broadcast channel whose events carry ids and can be replayed.
+pub struct ReplayChannel<T> {
+    inner: Mutex<ReplayInner<T>>,
+}
+
+struct ReplayInner<T> {
+    /// Id of the next event. Ids start at 1.
+    next_id: u64,
+    buffer: VecDeque<(u64, T)>,
+    capacity: usize,
+    tx: broadcast::Sender<(u64, T)>,
+}
+
+/// A subscription resuming after a given event id.
+pub struct ReplaySubscription<T> {
+    /// Buffered events after the requested id, oldest first.
+    pub missed: Vec<(u64, T)>,
+    /// False if events after the requested id were already evicted (or the id
+    /// is from before a daemon restart), so `missed` is not the full gap.
+    pub complete: bool,
+    /// Live events after `missed`.
+    pub live: broadcast::Receiver<(u64, T)>,
+}
+
+impl<T: Clone> ReplayChannel<T> {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(LIVE_CAPACITY);
+        Self {
+            inner: Mutex::new(ReplayInner {
+                next_id: 1,
+                buffer: VecDeque::with_capacity(capacity),
+                capacity,
+                tx,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ReplayInner<T>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record and broadcast an event, returning its id.
+    pub fn send(&self, event: T) -> u64 {
+        let mut inner = self.lock();
+        let id = inner.next_id;
+        inner.next_id += 1;
+
+        if inner.buffer.len() == inner.capacity {
+            inner.buffer.pop_front();
+        }
+        inner.buffer.push_back((id, event.clone()));
+        let _ = inner.tx.send((id, event));
+        id
+    }
+
+    /// Subscribe to live events, first replaying those after `last_event_id`.
+    ///
+    /// With `None`, nothing is replayed.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> ReplaySubscription<T> {
+        // Snapshot and subscribe under one lock so no event falls in between.
+        let inner = self.lock();
+        let live = inner.tx.subscribe();
+
+        let Some(last) = last_event_id else {
+            return ReplaySubscription {
+                missed: Vec::new(),
+                complete: true,
+                live,
+            };
+        };
+
+        if last >= inner.next_id {
+            // Id from a previous daemon instance: everything buffered is new to the client.
+            return ReplaySubscription {
+                missed: inner.buffer.iter().cloned().collect(),
+                complete: false,
+                live,
+            };
+        }
+
+        let oldest = inner.buffer.front().map_or(inner.next_id, |(id, _)| *id);
+        ReplaySubscription {
+            missed: inner
+                .buffer
+                .iter()
+                .filter(|(id, _)| *id > last)
+                .cloned()
+                .collect(),
+            complete: oldest <= last + 1,
+            live,
+        }
+    }
+
+    /// Number of live subscribers.
+    pub fn receiver_count(&self) -> usize {
+        self.lock().tx.receiver_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_after_last_event_id() {
+        let channel = ReplayChannel::new(4);
+        for n in 0..3 {
+            channel.send(n);
+        }
+
+        let sub = channel.subscribe(Some(1));
+        assert!(sub.complete);
+        assert_eq!(sub.missed, vec![(2, 1), (3, 2)]);
+
+        let sub = channel.subscribe(None);
+        assert!(sub.complete);
+        assert!(sub.missed.is_empty());
+    }
+
+    #[test]
+    fn test_replay_reports_evicted_gap() {
+        let channel = ReplayChannel::new(2);
+        for n in 0..5 {
+            channel.send(n);
+        }
+
+        // Ids 4 and 5 are buffered; 2 and 3 were evicted.
+        let sub = channel.subscribe(Some(1));
+        assert!(!sub.complete);
+        assert_eq!(sub.missed, vec![(4, 3), (5, 4)]);
+
+        let sub = channel.subscribe(Some(3));
+        assert!(sub.complete);
+        assert_eq!(sub.missed.len(), 2);
+
+        // An id from before a restart replays everything buffered.
+        let sub = channel.subscribe(Some(99));
+        assert!(!sub.complete);
+        assert_eq!(sub.missed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_live_events_follow_replay() {
+        let channel = ReplayChannel::new(4);
+        channel.send("a");
+        let mut sub = channel.subscribe(Some(0));
+        channel.send("b");
+
+        assert_eq!(sub.missed, vec![(1, "a")]);
+        assert_eq!(sub.live.recv().await.unwrap(), (2, "b"));
+    }
+}
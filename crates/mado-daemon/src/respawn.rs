@@ -0,0 +1,97 @@
+//! Respawning crashed session processes.
+//!
+//! With `respawn.enabled` set in config.json, a session whose process exits
+//! with a failure is revived (resuming its Claude conversation), waiting a
+//! little longer before each attempt. After `respawn.max_retries` crashes in
+//! a row the session is left terminated; a process that stays up for
+//! [`STABLE_AFTER`] starts the count over.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Wait before the first respawn; doubled for each further one in a row.
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a respawned process must run for its crash not to count
+/// towards the previous ones.
+pub const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Attempts {
+    count: u32,
+    last: Instant,
+}
+
+/// Crash respawns in a row, per session.
+#[derive(Debug, Default)]
+pub struct RespawnTracker {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl RespawnTracker {
+    /// Record a crash of `session_id`'s process. Returns how long to wait
+    /// before respawning it, or `None` once `max_retries` is used up.
+    pub fn next_delay(&self, session_id: &str, max_retries: u32) -> Option<Duration> {
+        self.next_delay_at(session_id, max_retries, Instant::now())
+    }
+
+    fn next_delay_at(&self, session_id: &str, max_retries: u32, now: Instant) -> Option<Duration> {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = attempts.entry(session_id.to_string()).or_insert(Attempts {
+            count: 0,
+            last: now,
+        });
+        if now.duration_since(entry.last) >= STABLE_AFTER {
+            entry.count = 0;
+        }
+        if entry.count >= max_retries {
+            return None;
+        }
+        let delay = FIRST_DELAY * 2u32.saturating_pow(entry.count);
+        entry.count += 1;
+        entry.last = now + delay;
+        Some(delay)
+    }
+
+    /// Forget a session's crashes, e.g. after a manual restart.
+    pub fn reset(&self, session_id: &str) {
+        self.attempts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respawns_are_capped_and_back_off() {
+        let tracker = RespawnTracker::default();
+        let start = Instant::now();
+
+        assert_eq!(tracker.next_delay_at("a", 3, start), Some(Duration::from_secs(1)));
+        assert_eq!(tracker.next_delay_at("a", 3, start), Some(Duration::from_secs(2)));
+        assert_eq!(tracker.next_delay_at("a", 3, start), Some(Duration::from_secs(4)));
+        assert_eq!(tracker.next_delay_at("a", 3, start), None);
+        // Other sessions have their own count.
+        assert_eq!(tracker.next_delay_at("b", 3, start), Some(Duration::from_secs(1)));
+
+        tracker.reset("a");
+        assert_eq!(tracker.next_delay_at("a", 3, start), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_stable_process_starts_count_over() {
+        let tracker = RespawnTracker::default();
+        let start = Instant::now();
+
+        assert!(tracker.next_delay_at("a", 1, start).is_some());
+        assert!(tracker.next_delay_at("a", 1, start).is_none());
+
+        let later = start + Duration::from_secs(1) + STABLE_AFTER;
+        assert_eq!(tracker.next_delay_at("a", 1, later), Some(Duration::from_secs(1)));
+    }
+}
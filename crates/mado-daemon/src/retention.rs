@@ -0,0 +1,308 @@
+//! Retention of dead sessions.
+//!
+//! Sessions whose process is gone stay in state.json, with their conversation
+//! in memory, until something removes them. With `retention` limits set in
+//! config.json an hourly task removes dead sessions older than `max_age_days`
+//! or beyond the newest `max_sessions`, first archiving each one (session,
+//! conversation and milestones, gzipped JSON) to `<base>/archive/<id>.json.gz`
+//! unless `archive` is false. `GET /retention` reports what the next run
+//! would remove; `POST /sessions/{id}/archive` archives one session on demand.
+
+use std::cmp::Reverse;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use mado_core::types::{Message, Milestone, RetentionCandidate, RetentionReason, Session, SessionId};
+
+use crate::config::RetentionConfig;
+use crate::server::AppState;
+use crate::session::SessionError;
+
+/// How often the cleanup task runs.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Most milestones recorded in an archive.
+const ARCHIVED_MILESTONES: usize = 1000;
+
+/// Everything kept of an archived session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionArchive {
+    pub archived_at: DateTime<Utc>,
+    pub session: Session,
+    pub messages: Vec<Message>,
+    /// Milestones in the session's repository at the time, newest first.
+    pub milestones: Vec<Milestone>,
+}
+
+/// The sessions in `dead` that `policy` removes at `now`, newest first.
+pub fn plan(
+    dead: &[Session],
+    policy: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> Vec<RetentionCandidate> {
+    let mut dead: Vec<&Session> = dead.iter().collect();
+    dead.sort_by_key(|session| Reverse(session.updated_at));
+    let max_age = policy.max_age_days.map(|days| chrono::Duration::days(days.into()));
+
+    dead.into_iter()
+        .enumerate()
+        .filter_map(|(index, session)| {
+            let reason = if max_age.is_some_and(|max_age| now - session.updated_at > max_age) {
+                RetentionReason::Expired
+            } else if policy.max_sessions.is_some_and(|max| index >= max) {
+                RetentionReason::OverLimit
+            } else {
+                return None;
+            };
+            Some(RetentionCandidate {
+                session_id: session.id.clone(),
+                name: session.name.clone(),
+                updated_at: session.updated_at,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Sessions without a running process.
+async fn dead_sessions(state: &AppState) -> Vec<Session> {
+    let mut dead = Vec::new();
+    for session in state.session_manager.list_sessions().await {
+        if !state.session_manager.has_process(&session.id).await {
+            dead.push(session);
+        }
+    }
+    dead
+}
+
+/// What the policy currently in effect would remove.
+pub async fn current_plan(state: &AppState) -> Vec<RetentionCandidate> {
+    let policy = state.settings.read().unwrap_or_else(|e| e.into_inner()).retention.clone();
+    plan(&dead_sessions(state).await, &policy, Utc::now())
+}
+
+/// Write a session's conversation and milestones to `dir`.
+pub async fn archive(state: &AppState, dir: &Path, session: &Session) -> std::io::Result<PathBuf> {
+    let mut messages = state
+        .conversation_manager
+        .get_messages(&session.id, None, None)
+        .await
+        .unwrap_or_default();
+    // After a restart the conversation is only in the Claude CLI's transcript.
+    if messages.is_empty()
+        && let (Some(working_dir), Some(claude_session_id)) =
+            (session.working_dir.clone(), session.claude_session_id.clone())
+    {
+        messages = tokio::task::spawn_blocking(move || {
+            crate::claude_history::import_session_by_id(
+                Path::new(&working_dir),
+                &claude_session_id,
+                None,
+                0,
+            )
+        })
+        .await
+        .ok()
+        .and_then(|result| result.ok())
+        .unwrap_or_default();
+    }
+
+    let repo_root = session.repo_root.as_ref().map(PathBuf::from).or_else(|| {
+        session
+            .working_dir
+            .as_deref()
+            .and_then(|dir| crate::git_ops::discover_repo_root(Path::new(dir)))
+    });
+    let milestones = match repo_root {
+        Some(path) => {
+            let _lock = state.workspace_locks.acquire(&path).await;
+            crate::git_ops::list_milestones(&path, ARCHIVED_MILESTONES)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|m| Milestone {
+                    oid: m.oid,
+                    message: m.message,
+                    timestamp: m.timestamp,
+                    files_changed: m.files_changed,
+                    insertions: m.insertions,
+                    deletions: m.deletions,
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let archive = SessionArchive {
+        archived_at: Utc::now(),
+        session: session.clone(),
+        messages,
+        milestones,
+    };
+    let dir = dir.to_path_buf();
+    tokio::task::spawn_blocking(move || write(&dir, &archive)).await?
+}
+
+/// Write an archive to `dir` as `<session id>.json.gz`, with secrets
+/// redacted.
+pub fn write(dir: &Path, archive: &SessionArchive) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json.gz", archive.session.id));
+    let json = serde_json::to_string(archive)?;
+    let mut encoder = GzEncoder::new(std::fs::File::create(&path)?, Compression::default());
+    encoder.write_all(crate::redact::redact(&json).as_bytes())?;
+    encoder.finish()?.sync_all()?;
+    Ok(path)
+}
+
+/// Read an archive written by [`write`].
+pub fn read(path: &Path) -> std::io::Result<SessionArchive> {
+    let decoder = GzDecoder::new(std::fs::File::open(path)?);
+    Ok(serde_json::from_reader(decoder)?)
+}
+
+/// Kill a session's process and drop it and its conversation. The
+/// conversation goes first, while the session still says where its turn
+/// snapshots are.
+pub async fn remove(state: &AppState, id: &SessionId) -> Result<(), SessionError> {
+    state.conversation_manager.remove_session(id).await;
+    state.session_manager.destroy_session(id).await?;
+    Ok(())
+}
+
+/// Archive and remove the dead sessions the current policy selects. A
+/// session whose archive can't be written is kept.
+pub async fn run(state: &AppState) -> Vec<RetentionCandidate> {
+    let policy = state.settings.read().unwrap_or_else(|e| e.into_inner()).retention.clone();
+    if !policy.is_enabled() {
+        return Vec::new();
+    }
+    let archive_dir = state.archive_dir.as_deref().filter(|_| policy.archive);
+
+    let dead = dead_sessions(state).await;
+    let mut removed = Vec::new();
+    for candidate in plan(&dead, &policy, Utc::now()) {
+        let Some(session) = dead.iter().find(|s| s.id == candidate.session_id) else {
+            continue;
+        };
+        if let Some(dir) = archive_dir
+            && let Err(e) = archive(state, dir, session).await
+        {
+            tracing::warn!("Failed to archive session {}: {}", session.id, e);
+            continue;
+        }
+        if let Err(e) = remove(state, &session.id).await {
+            tracing::warn!("Failed to remove session {}: {}", session.id, e);
+            continue;
+        }
+        removed.push(candidate);
+    }
+    if !removed.is_empty() {
+        tracing::info!("Removed {} dead session(s)", removed.len());
+    }
+    removed
+}
+
+/// Apply the retention policy now and then hourly, with the limits current
+/// at each run.
+pub fn spawn_cleanup(state: AppState) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("retention", move || clean_up_periodically(state.clone()))
+}
+
+async fn clean_up_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        run(&state).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mado_core::types::{ConversationState, PermissionMode, SessionActivity, SessionStatus};
+
+    fn session(id: &str, days_old: i64, now: DateTime<Utc>) -> Session {
+        let updated_at = now - chrono::Duration::days(days_old);
+        Session {
+            id: SessionId::new(id),
+            name: id.to_string(),
+            model: "sonnet".to_string(),
+            status: SessionStatus::Terminated,
+            created_at: updated_at,
+            updated_at,
+            working_dir: None,
+            repo_root: None,
+            command: None,
+            shell_fallback: false,
+            custom_command: None,
+            env: Default::default(),
+            activity: SessionActivity::Idle,
+            is_busy: false,
+            last_activity: None,
+            conversation_state: ConversationState::Empty,
+            claude_session_id: None,
+            message_count: 0,
+            total_usage: None,
+            total_cost_usd: None,
+            system_prompt: None,
+            permission_mode: PermissionMode::Ask,
+            read_only: false,
+            sandbox: Default::default(),
+            extended_thinking: false,
+            tags: Vec::new(),
+            color: None,
+            pinned: false,
+            notes: None,
+            mcp_config: Vec::new(),
+            claude_flags: Vec::new(),
+            auto_milestone: None,
+            exit_code: None,
+            branch: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_by_age_and_count() {
+        let now = Utc::now();
+        let dead = vec![session("old", 40, now), session("new", 1, now), session("mid", 10, now)];
+
+        let policy = RetentionConfig::default();
+        assert!(plan(&dead, &policy, now).is_empty());
+
+        let policy = RetentionConfig {
+            max_age_days: Some(30),
+            max_sessions: Some(1),
+            archive: true,
+        };
+        let candidates = plan(&dead, &policy, now);
+        let ids: Vec<(&str, RetentionReason)> =
+            candidates.iter().map(|c| (c.session_id.as_str(), c.reason)).collect();
+        assert_eq!(ids, [("mid", RetentionReason::OverLimit), ("old", RetentionReason::Expired)]);
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut session = session("s1", 0, Utc::now());
+        session.notes = Some("key: sk-ant-REDACTED".to_string());
+        let archive = SessionArchive {
+            archived_at: Utc::now(),
+            session,
+            messages: Vec::new(),
+            milestones: Vec::new(),
+        };
+        let path = write(dir.path(), &archive).unwrap();
+        assert_eq!(path, dir.path().join("s1.json.gz"));
+        let read = read(&path).unwrap();
+        assert_eq!(read.session.id.as_str(), "s1");
+        assert_eq!(read.session.notes.as_deref(), Some("key: [REDACTED]"));
+    }
+}
@@ -0,0 +1,325 @@
+//! Reviewing and reverting what the agent changed, for
+//! `/sessions/{id}/review` and `/sessions/{id}/messages/{id}/revert-changes`.
+//!
+//! Before and after each turn that may edit files the workspace is
+//! snapshotted as a tree (see [`crate::git_ops::snapshot_workdir`]) and
+//! kept as a ref under `refs/mado/<session>/<message>/`, by the user
+//! message's id. The refs keep the trees from git's garbage collection and
+//! are where they are looked up, so they outlive a restart of the daemon
+//! and go when the session is removed. A review compares the
+//! workspace as it is now with the tree from before the turn or, for turns
+//! the daemon has no snapshot of, with the latest milestone committed before
+//! the message was sent. Reverting a file puts back its content at that
+//! base, or deletes it if it didn't exist then. Reverting a turn undoes just
+//! the difference between its two trees, leaving later changes alone.
+
+use std::path::{Component, Path};
+
+use chrono::{DateTime, Utc};
+use git2::Repository;
+
+use mado_core::types::ReviewFile;
+
+use crate::git_ops::GitError;
+
+/// Workspace trees snapshotted around one turn.
+#[derive(Debug, Clone)]
+pub struct TurnTrees {
+    pub before: String,
+    /// Unset until the turn ends.
+    pub after: Option<String>,
+}
+
+/// The turn a review counts changes from.
+#[derive(Debug, Clone)]
+pub struct Turn {
+    /// Id of the turn's user message.
+    pub message_id: String,
+    pub sent_at: DateTime<Utc>,
+    /// A response is in progress.
+    pub busy: bool,
+}
+
+/// Snapshot the workspace at `root` for the turn of `message_id`, as it is
+/// `before` or after the turn. Returns the tree.
+pub fn snapshot_turn(root: &Path, session_id: &str, message_id: &str, before: bool) -> Result<String, GitError> {
+    let tree = crate::git_ops::snapshot_workdir(root)?;
+    let repo = Repository::open(root)?;
+    let stage = if before { "before" } else { "after" };
+    repo.reference(
+        &turn_ref(session_id, message_id, stage),
+        git2::Oid::from_str(&tree)?,
+        true,
+        "Turn snapshot",
+    )?;
+    Ok(tree)
+}
+
+/// The trees snapshotted around the turn of `message_id`, if it was.
+pub fn turn_trees(root: &Path, session_id: &str, message_id: &str) -> Result<Option<TurnTrees>, GitError> {
+    let repo = Repository::open(root)?;
+    let tree = |stage: &str| match repo.find_reference(&turn_ref(session_id, message_id, stage)) {
+        Ok(reference) => Ok(reference.target().map(|oid| oid.to_string())),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    };
+    let Some(before) = tree("before")? else {
+        return Ok(None);
+    };
+    Ok(Some(TurnTrees {
+        before,
+        after: tree("after")?,
+    }))
+}
+
+fn turn_ref(session_id: &str, message_id: &str, stage: &str) -> String {
+    format!("refs/mado/{}/{}/{}", session_id, message_id, stage)
+}
+
+/// Drop the refs keeping a session's turn snapshots, once it is removed.
+pub fn remove_turn_refs(root: &Path, session_id: &str) -> Result<(), GitError> {
+    let repo = Repository::open(root)?;
+    for reference in repo.references_glob(&format!("refs/mado/{}/*", session_id))? {
+        reference?.delete()?;
+    }
+    Ok(())
+}
+
+/// Paths that differ between the trees `before` and `after` of a turn.
+pub fn turn_paths(root: &Path, before: &str, after: &str) -> Result<Vec<String>, GitError> {
+    let repo = Repository::open(root)?;
+    let before = repo.find_tree(git2::Oid::from_str(before)?)?;
+    let after = repo.find_tree(git2::Oid::from_str(after)?)?;
+    let diff = repo.diff_tree_to_tree(Some(&before), Some(&after), None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect())
+}
+
+/// Undo the changes between the trees `before` and `after` of a turn in the
+/// workspace. Fails without touching it if a change since conflicts.
+/// Returns the paths reverted.
+pub fn revert_turn(root: &Path, before: &str, after: &str) -> Result<Vec<String>, GitError> {
+    let repo = Repository::open(root)?;
+    let before = repo.find_tree(git2::Oid::from_str(before)?)?;
+    let after = repo.find_tree(git2::Oid::from_str(after)?)?;
+    let mut options = git2::DiffOptions::new();
+    options.show_binary(true);
+    let diff = repo.diff_tree_to_tree(Some(&after), Some(&before), Some(&mut options))?;
+    let paths = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    repo.apply(&diff, git2::ApplyLocation::WorkDir, None)?;
+    Ok(paths)
+}
+
+/// Tree of the latest commit on HEAD made at or before `at`.
+pub fn milestone_before(root: &Path, at: DateTime<Utc>) -> Result<Option<String>, GitError> {
+    let repo = Repository::open(root)?;
+    if repo.head().is_err() {
+        // No commits yet.
+        return Ok(None);
+    }
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TIME)?;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.time().seconds() <= at.timestamp() {
+            return Ok(Some(commit.tree_id().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Files that differ between the tree `base` and the workspace now.
+pub fn changes(root: &Path, base: &str) -> Result<Vec<ReviewFile>, GitError> {
+    let repo = Repository::open(root)?;
+    let from = repo.find_tree(git2::Oid::from_str(base)?)?;
+    let to = repo.find_tree(git2::Oid::from_str(&crate::git_ops::snapshot_workdir(root)?)?)?;
+    let diff = repo.diff_tree_to_tree(Some(&from), Some(&to), None)?;
+
+    let mut files = Vec::new();
+    for (i, delta) in diff.deltas().enumerate() {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let status = match delta.status() {
+            git2::Delta::Added => "added",
+            git2::Delta::Deleted => "deleted",
+            _ => "modified",
+        };
+        let (insertions, deletions, patch) = match git2::Patch::from_diff(&diff, i)? {
+            Some(mut patch) => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                let text = String::from_utf8_lossy(&patch.to_buf()?).into_owned();
+                (insertions, deletions, text)
+            }
+            None => (0, 0, String::new()),
+        };
+        files.push(ReviewFile {
+            path,
+            status: status.to_string(),
+            insertions,
+            deletions,
+            patch,
+        });
+    }
+    Ok(files)
+}
+
+/// Put each of `paths` back as it is in the tree `base`.
+pub fn revert(root: &Path, base: &str, paths: &[String]) -> Result<(), GitError> {
+    let repo = Repository::open(root)?;
+    let tree = repo.find_tree(git2::Oid::from_str(base)?)?;
+    for path in paths {
+        let relative = Path::new(path);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(GitError::PathError(path.clone()));
+        }
+        let full = root.join(relative);
+        let entry = match tree.get_path(relative) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                // Added since: remove it.
+                match std::fs::remove_file(&full) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(GitError::PathError(format!("{}: {}", path, e)));
+                    }
+                    _ => continue,
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let blob = entry.to_object(&repo)?.peel_to_blob()?;
+        write_file(&full, blob.content(), entry.filemode())
+            .map_err(|e| GitError::PathError(format!("{}: {}", path, e)))?;
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &[u8], mode: i32) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Whatever is there now may not be a plain file.
+    if std::fs::symlink_metadata(path).is_ok() {
+        remove(path)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if mode == i32::from(git2::FileMode::Link) {
+            let target = String::from_utf8_lossy(content).into_owned();
+            return std::os::unix::fs::symlink(target, path);
+        }
+        std::fs::write(path, content)?;
+        let executable = mode == i32::from(git2::FileMode::BlobExecutable);
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(if executable { 0o755 } else { 0o644 }))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = mode;
+        std::fs::write(path, content)
+    }
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_and_revert() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        crate::git_ops::init_repo(root).unwrap();
+        std::fs::write(root.join("kept.txt"), "one\n").unwrap();
+        std::fs::write(root.join("edited.txt"), "one\n").unwrap();
+        let base = crate::git_ops::snapshot_workdir(root).unwrap();
+
+        std::fs::write(root.join("kept.txt"), "two\n").unwrap();
+        std::fs::write(root.join("edited.txt"), "two\n").unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src").join("new.rs"), "fn main() {}\n").unwrap();
+
+        let files = changes(root, &base).unwrap();
+        let paths: Vec<_> = files.iter().map(|f| (f.path.as_str(), f.status.as_str())).collect();
+        assert_eq!(paths, [("edited.txt", "modified"), ("kept.txt", "modified"), ("src/new.rs", "added")]);
+        assert!(files[0].patch.contains("-one\n+two\n"));
+
+        revert(root, &base, &["edited.txt".to_string(), "src/new.rs".to_string()]).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("edited.txt")).unwrap(), "one\n");
+        assert!(!root.join("src").join("new.rs").exists());
+        let files = changes(root, &base).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "kept.txt");
+
+        assert!(matches!(
+            revert(root, &base, &["../outside".to_string()]),
+            Err(GitError::PathError(_))
+        ));
+    }
+
+    #[test]
+    fn test_revert_turn_keeps_later_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        crate::git_ops::init_repo(root).unwrap();
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        let before = snapshot_turn(root, "s1", "m1", true).unwrap();
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        std::fs::write(root.join("b.txt"), "new\n").unwrap();
+        let after = snapshot_turn(root, "s1", "m1", false).unwrap();
+        std::fs::write(root.join("c.txt"), "later\n").unwrap();
+
+        let repo = Repository::open(root).unwrap();
+        assert_eq!(repo.find_reference("refs/mado/s1/m1/before").unwrap().target().unwrap().to_string(), before);
+        let trees = turn_trees(root, "s1", "m1").unwrap().unwrap();
+        assert_eq!((trees.before.as_str(), trees.after.as_deref()), (before.as_str(), Some(after.as_str())));
+        assert!(turn_trees(root, "s1", "m2").unwrap().is_none());
+
+        let paths = revert_turn(root, &before, &after).unwrap();
+        assert_eq!(paths, ["a.txt", "b.txt"]);
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "one\n");
+        assert!(!root.join("b.txt").exists());
+        assert!(root.join("c.txt").exists());
+
+        // Done already: a.txt no longer matches the turn's result.
+        assert!(revert_turn(root, &before, &after).is_err());
+
+        remove_turn_refs(root, "s1").unwrap();
+        assert!(repo.find_reference("refs/mado/s1/m1/before").is_err());
+    }
+
+    #[test]
+    fn test_milestone_before() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        crate::git_ops::init_repo(root).unwrap();
+        let before = Utc::now() - chrono::Duration::hours(1);
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        let milestone = crate::git_ops::save_milestone(root, "First").unwrap();
+
+        assert_eq!(milestone_before(root, before).unwrap(), None);
+        let tree = milestone_before(root, Utc::now()).unwrap().unwrap();
+        let repo = Repository::open(root).unwrap();
+        let commit = repo.find_commit(git2::Oid::from_str(&milestone.oid).unwrap()).unwrap();
+        assert_eq!(tree, commit.tree_id().to_string());
+    }
+}
@@ -0,0 +1,375 @@
+//! Project commands (`[commands]` in `.mado.toml`), run for
+//! `POST /sessions/{id}/run`.
+//!
+//! A command runs with `sh -c` in the directory of the `.mado.toml` naming
+//! it, with the session's environment, leading its own process group. In a
+//! sandboxed session it runs in the sandbox like the session's other
+//! processes (see [`crate::sandbox`]); in a read-only one it doesn't run at
+//! all, having nothing to keep it from changing the workspace. While
+//! it runs its output goes line by line to anyone following
+//! `GET /sessions/{id}/runs/{run_id}/stream`; it carries on if no one is.
+//! When it ends, the run and the end of its output are saved as
+//! `<session>/runs/<id>.json` under the conversation storage directory. If
+//! asked, a failed run's output is sent to the conversation with a request
+//! to fix it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use mado_core::types::{CommandRun, OutputStream, RunEvent, SessionId};
+
+use crate::conversation::{MessageOptions, SharedConversationManager};
+use crate::replay::{REPLAY_CAPACITY, ReplayChannel};
+use crate::sandbox::{Policy, Sandbox, SandboxError};
+
+/// Bytes of output kept with a finished run.
+pub const OUTPUT_TAIL: usize = 16 * 1024;
+
+/// Errors from running project commands or loading their runs.
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    #[error("No project command named {0:?}")]
+    UnknownCommand(String),
+
+    #[error("Run not found: {0}")]
+    NotFound(String),
+
+    #[error("Project commands can't run in a read-only session")]
+    ReadOnly,
+
+    #[error("{0}")]
+    Sandbox(#[from] SandboxError),
+
+    #[error("{0}")]
+    Config(#[from] crate::project_config::ProjectConfigError),
+
+    #[error("{0}")]
+    Env(#[from] crate::session_env::EnvError),
+
+    #[error("Unreadable run: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to save run: {0}")]
+    Write(#[from] crate::state::StateError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A command to run for a session.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub session_id: SessionId,
+    pub name: String,
+    pub command: String,
+    /// Directory to run in.
+    pub dir: PathBuf,
+    pub env: Vec<(String, String)>,
+    /// What the command is confined to, if the session is sandboxed.
+    pub sandbox: Option<Policy>,
+    /// Send the output to the conversation if the command fails.
+    pub feed_back: bool,
+}
+
+struct LiveRun {
+    session_id: String,
+    run: CommandRun,
+    events: Arc<ReplayChannel<RunEvent>>,
+}
+
+/// Runs in progress, by id.
+#[derive(Default)]
+pub struct Runs {
+    live: Mutex<HashMap<String, LiveRun>>,
+}
+
+impl Runs {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, LiveRun>> {
+        self.live.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Events of a session's run, if it is still in progress.
+    pub fn events(&self, session_id: &SessionId, run_id: &str) -> Option<Arc<ReplayChannel<RunEvent>>> {
+        self.lock()
+            .get(run_id)
+            .filter(|live| live.session_id == session_id.as_str())
+            .map(|live| live.events.clone())
+    }
+
+    /// A session's runs in progress.
+    pub fn in_progress(&self, session_id: &SessionId) -> Vec<CommandRun> {
+        self.lock()
+            .values()
+            .filter(|live| live.session_id == session_id.as_str())
+            .map(|live| live.run.clone())
+            .collect()
+    }
+
+    /// Start a command. Returns the run as started; it is saved in
+    /// `conversations.runs_dir()` once finished.
+    pub fn start(
+        self: &Arc<Self>,
+        conversations: SharedConversationManager,
+        options: RunOptions,
+    ) -> Result<CommandRun, RunError> {
+        let sandbox = options.sandbox.as_ref().map(Sandbox::start).transpose()?;
+        let sh = Path::new("/bin/sh");
+        let mut cmd = match &sandbox {
+            Some(sandbox) => sandbox.command(sh),
+            None => tokio::process::Command::new(sh),
+        };
+        cmd.arg("-c")
+            .arg(&options.command)
+            .current_dir(&options.dir)
+            .envs(options.env.iter().cloned())
+            .envs(sandbox.iter().flat_map(|sandbox| sandbox.env.iter().cloned()))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        // Its own process group, so a signal to the daemon's doesn't stop it.
+        cmd.process_group(0);
+        let mut child = cmd.spawn()?;
+
+        let run = CommandRun {
+            id: Uuid::new_v4().to_string(),
+            name: options.name.clone(),
+            command: options.command.clone(),
+            started_at: Utc::now(),
+            finished_at: None,
+            exit_code: None,
+            success: false,
+            output: String::new(),
+            follow_up_message_id: None,
+        };
+        let events = Arc::new(ReplayChannel::new(REPLAY_CAPACITY));
+        self.lock().insert(
+            run.id.clone(),
+            LiveRun {
+                session_id: options.session_id.as_str().to_string(),
+                run: run.clone(),
+                events: events.clone(),
+            },
+        );
+        tracing::info!("Running {} ({}) for session {}", options.name, options.command, options.session_id);
+
+        let (lines_tx, mut lines) = mpsc::channel(64);
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(read_lines(stdout, OutputStream::Stdout, lines_tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(read_lines(stderr, OutputStream::Stderr, lines_tx));
+        }
+
+        let runs = self.clone();
+        let mut finished = run.clone();
+        tokio::spawn(async move {
+            // Its proxy, if any, serves it until it exits.
+            let _sandbox = sandbox;
+            let mut tail = String::new();
+            while let Some((stream, text)) = lines.recv().await {
+                push_tail(&mut tail, &text);
+                events.send(RunEvent::Output { stream, text });
+            }
+            let status = child.wait().await;
+
+            finished.finished_at = Some(Utc::now());
+            finished.exit_code = status.as_ref().ok().and_then(|status| status.code());
+            finished.success = status.as_ref().is_ok_and(|status| status.success());
+            finished.output = tail;
+            if !finished.success && options.feed_back {
+                match conversations
+                    .send_message(&options.session_id, follow_up_prompt(&finished), MessageOptions::default())
+                    .await
+                {
+                    Ok(message_id) => finished.follow_up_message_id = Some(message_id),
+                    Err(e) => tracing::warn!("Failed to send {} failure to session {}: {}", finished.name, options.session_id, e),
+                }
+            }
+
+            // Saved before it leaves `live`, so it can always be found.
+            if let Err(e) = save(&conversations.runs_dir(&options.session_id), &finished) {
+                tracing::warn!("Failed to save run {}: {}", finished.id, e);
+            }
+            tracing::info!(
+                "{} for session {} finished with {:?}",
+                finished.name,
+                options.session_id,
+                finished.exit_code
+            );
+            let id = finished.id.clone();
+            events.send(RunEvent::Complete { run: finished });
+            runs.lock().remove(&id);
+        });
+
+        Ok(run)
+    }
+}
+
+/// Send each line of `output` to `lines` until it ends.
+async fn read_lines(
+    output: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    lines: mpsc::Sender<(OutputStream, String)>,
+) {
+    let mut reader = BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&line).into_owned();
+                if lines.send((stream, text)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Append `text` to `tail`, keeping its last [`OUTPUT_TAIL`] bytes.
+fn push_tail(tail: &mut String, text: &str) {
+    tail.push_str(text);
+    if tail.len() > OUTPUT_TAIL {
+        let mut cut = tail.len() - OUTPUT_TAIL;
+        while !tail.is_char_boundary(cut) {
+            cut += 1;
+        }
+        tail.drain(..cut);
+    }
+}
+
+/// The prompt asking Claude to fix a failed run.
+fn follow_up_prompt(run: &CommandRun) -> String {
+    let outcome = match run.exit_code {
+        Some(code) => format!("failed with exit code {}", code),
+        None => "was killed".to_string(),
+    };
+    format!(
+        "The project's {} command (`{}`) {}. Its output ends with:\n\n```\n{}\n```\n\nPlease fix the failures.",
+        run.name,
+        run.command,
+        outcome,
+        run.output.trim_end()
+    )
+}
+
+/// The command named `name` for a session working in `working_dir`, and the
+/// directory to run it in.
+pub fn command(working_dir: Option<&str>, name: &str) -> Result<(PathBuf, String), RunError> {
+    let (path, config) =
+        crate::project_config::discover(working_dir)?.ok_or_else(|| RunError::UnknownCommand(name.to_string()))?;
+    let command = config
+        .commands
+        .get(name)
+        .cloned()
+        .ok_or_else(|| RunError::UnknownCommand(name.to_string()))?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok((dir, command))
+}
+
+/// Directory of a session's runs under the conversation storage dir.
+pub fn dir(storage_dir: &Path, session_id: &str) -> PathBuf {
+    storage_dir.join(session_id).join("runs")
+}
+
+/// Save the finished `run` in `dir`.
+pub fn save(dir: &Path, run: &CommandRun) -> Result<(), RunError> {
+    std::fs::create_dir_all(dir)?;
+    let json = serde_json::to_string(run)?;
+    crate::state::write_atomic(&dir.join(format!("{}.json", run.id)), &json)?;
+    Ok(())
+}
+
+/// The run `id` in `dir`.
+pub fn load(dir: &Path, id: &str) -> Result<CommandRun, RunError> {
+    // Ids are uuids; anything else can't name a file in `dir`.
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return Err(RunError::NotFound(id.to_string()));
+    }
+    let contents = match std::fs::read_to_string(dir.join(format!("{}.json", id))) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(RunError::NotFound(id.to_string())),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Finished runs in `dir`, newest first. Unreadable files are skipped.
+pub fn list(dir: &Path) -> Vec<CommandRun> {
+    let mut runs: Vec<CommandRun> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|contents| serde_json::from_str(&contents).ok())
+        .collect();
+    runs.sort_by_key(|run| std::cmp::Reverse(run.started_at));
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_tail() {
+        let mut tail = String::new();
+        push_tail(&mut tail, "first\n");
+        assert_eq!(tail, "first\n");
+        push_tail(&mut tail, &"é".repeat(OUTPUT_TAIL));
+        assert!(tail.len() <= OUTPUT_TAIL);
+        assert!(tail.starts_with('é'));
+    }
+
+    #[test]
+    fn test_command() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sub = tmp.path().join("src");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::create_dir(tmp.path().join(".git")).unwrap();
+        assert!(matches!(command(sub.to_str(), "test"), Err(RunError::UnknownCommand(_))));
+
+        std::fs::write(
+            tmp.path().join(crate::project_config::FILE_NAME),
+            "[commands]\ntest = \"cargo test\"\n",
+        )
+        .unwrap();
+        let (dir, test) = command(sub.to_str(), "test").unwrap();
+        assert_eq!(dir, tmp.path());
+        assert_eq!(test, "cargo test");
+        assert!(matches!(command(sub.to_str(), "lint"), Err(RunError::UnknownCommand(_))));
+    }
+
+    #[test]
+    fn test_save_load_list() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = dir(tmp.path(), "s1");
+        let run = |id: &str, minutes: i64| CommandRun {
+            id: id.to_string(),
+            name: "test".to_string(),
+            command: "cargo test".to_string(),
+            started_at: Utc::now() - chrono::Duration::minutes(minutes),
+            finished_at: Some(Utc::now()),
+            exit_code: Some(101),
+            success: false,
+            output: "1 failed\n".to_string(),
+            follow_up_message_id: None,
+        };
+        save(&dir, &run("0a1b", 10)).unwrap();
+        save(&dir, &run("2c3d", 5)).unwrap();
+
+        assert_eq!(load(&dir, "0a1b").unwrap().exit_code, Some(101));
+        let ids: Vec<_> = list(&dir).into_iter().map(|run| run.id).collect();
+        assert_eq!(ids, ["2c3d", "0a1b"]);
+        assert!(matches!(load(&dir, "9999"), Err(RunError::NotFound(_))));
+        assert!(matches!(load(&dir, "../s1"), Err(RunError::NotFound(_))));
+    }
+}
@@ -0,0 +1,769 @@
+//! Sandboxed sessions, whose processes run confined by the OS: under
+//! bubblewrap on Linux and `sandbox-exec` on macOS.
+//!
+//! Sandboxed processes — the terminal's, every `claude -p` of a chat and
+//! project command runs — see the system's directories but, of the home
+//! directory, only the working directory, the directories holding the
+//! program they run and those on `PATH`, and what their [`Policy`] adds
+//! (attachments, MCP configs). Toolchains kept elsewhere in the home directory (e.g.
+//! `~/.rustup`) aren't visible. The daemon's own directory, with its token,
+//! secrets and socket, is hidden even inside a working directory.
+//!
+//! Writes go only to the working directory, the temp directory (a private
+//! one on Linux) and where the Claude CLI keeps its transcripts and login
+//! ([`CLAUDE_STATE`]). The rest of `~/.claude` and `~/.claude.json` can be
+//! read but not written, so hooks or MCP servers can't be planted there for
+//! unsandboxed sessions to run, and settings the CLI changes from inside
+//! the sandbox aren't kept.
+//!
+//! Their connections go through a proxy the daemon runs for each process,
+//! passed to it as `HTTPS_PROXY`, which tunnels to the hosts in the
+//! session's `allowed_hosts` and in `sandbox.allowed_hosts` in
+//! config.json, and to nothing else. On Linux the process gets a network
+//! namespace of its own with only loopback: the proxy listens on a Unix
+//! socket bound into its `/tmp`, and `socat` relays a port inside the
+//! namespace to it. On macOS the profile keeps processes from connecting
+//! anywhere but the proxy on localhost, and to Unix sockets at all. A
+//! terminal adopted by a restarted daemon loses its proxy until it is
+//! respawned.
+//!
+//! A process isn't started at all when the sandbox program (or, with hosts
+//! allowed on Linux, `socat`) is missing.
+
+use std::ffi::{OsStr, OsString};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use mado_core::types::SandboxSettings;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+
+use crate::config::SandboxConfig;
+
+/// Where the Claude CLI keeps state it must write, under `~/.claude`:
+/// transcripts, todos, shell snapshots and the like, and its login.
+pub const CLAUDE_STATE: [&str; 6] = ["projects", "todos", "shell-snapshots", "statsig", "session-env", ".credentials.json"];
+
+/// Path of the proxy's socket inside the Linux sandbox.
+const PROXY_SOCKET: &str = "/tmp/mado-proxy.sock";
+
+/// Port `socat` listens on inside the Linux sandbox's network namespace,
+/// which has it to itself.
+const RELAY_PORT: u16 = 3128;
+
+/// Errors from setting up the sandbox for a process.
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("The sandbox needs {0}, which isn't installed")]
+    Unavailable(&'static str),
+
+    #[error("Sessions can't be sandboxed on this platform")]
+    Unsupported,
+
+    #[error("Failed to start the sandbox's proxy: {0}")]
+    Proxy(#[from] std::io::Error),
+}
+
+/// What a sandboxed process may read, write to and connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// Directories it may write to, besides the temp directory and the
+    /// Claude CLI's state.
+    pub writable: Vec<PathBuf>,
+    /// Files and directories it may read besides those, the system's and
+    /// the program's.
+    pub readable: Vec<PathBuf>,
+    /// Hosts it may connect to; with none it has no network.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Policy {
+    /// The policy for a session working in `working_dir`, or `None` if it
+    /// isn't sandboxed.
+    pub fn for_session(sandbox: &SandboxSettings, working_dir: Option<&str>, config: &SandboxConfig) -> Option<Self> {
+        if !sandbox.enabled {
+            return None;
+        }
+        let mut allowed_hosts = config.allowed_hosts.clone();
+        for host in &sandbox.allowed_hosts {
+            if !allowed_hosts.contains(host) {
+                allowed_hosts.push(host.clone());
+            }
+        }
+        Some(Self {
+            writable: working_dir.map(PathBuf::from).into_iter().collect(),
+            readable: Vec::new(),
+            allowed_hosts,
+        })
+    }
+
+    /// Let the process read `paths` as well.
+    pub fn with_readable(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.readable.extend(paths);
+        self
+    }
+}
+
+/// How to start a program in the sandbox.
+#[derive(Debug)]
+pub struct Sandbox {
+    /// The sandbox program, run in place of the sandboxed one.
+    pub program: PathBuf,
+    /// What the process may see, before the program's own directories.
+    paths: Paths,
+    /// `socat`, relaying to the proxy inside the network namespace (Linux).
+    relay: Option<PathBuf>,
+    /// Environment variables pointing the process at the proxy.
+    pub env: Vec<(String, String)>,
+    /// Must be kept until the process exits.
+    pub proxy: Option<Proxy>,
+}
+
+/// The paths a sandboxed process sees, with symlinks resolved (macOS
+/// matches profiles against real paths).
+#[derive(Debug, Clone)]
+struct Paths {
+    /// Hidden but for what is readable or writable.
+    home: Option<PathBuf>,
+    /// Hidden whatever else is readable, but for readable paths within it.
+    hidden: Option<PathBuf>,
+    readable: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Set up the sandbox for a process under `policy`, starting its proxy.
+    /// Must be called within the daemon's runtime.
+    pub fn start(policy: &Policy) -> Result<Self, SandboxError> {
+        let program = sandbox_program()?;
+        let linux = !cfg!(target_os = "macos");
+        let relay = match linux && !policy.allowed_hosts.is_empty() {
+            true => Some(find_program(OsStr::new("socat")).ok_or(SandboxError::Unavailable("socat"))?),
+            false => None,
+        };
+        let proxy = match policy.allowed_hosts.is_empty() {
+            true => None,
+            false if linux => Some(Proxy::start_unix(policy.allowed_hosts.clone())?),
+            false => Some(Proxy::start(policy.allowed_hosts.clone())?),
+        };
+        let proxy_url = proxy.as_ref().and_then(|proxy| match proxy.addr() {
+            Some(addr) => Some(format!("http://{}", addr)),
+            None => linux.then(|| format!("http://127.0.0.1:{}", RELAY_PORT)),
+        });
+        let mut env: Vec<(String, String)> = match proxy_url {
+            Some(url) => ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+                .into_iter()
+                .map(|name| (name.to_string(), url.clone()))
+                .chain(["NO_PROXY", "no_proxy"].into_iter().map(|name| (name.to_string(), String::new())))
+                .collect(),
+            None => Vec::new(),
+        };
+        if linux {
+            env.push(("TMPDIR".to_string(), "/tmp".to_string()));
+        }
+        Ok(Self {
+            program,
+            paths: sandbox_paths(policy, linux),
+            relay,
+            env,
+            proxy,
+        })
+    }
+
+    /// Arguments of the sandbox program to run `program` in it, which
+    /// follows them with its own.
+    fn args(&self, program: &Path) -> Vec<OsString> {
+        let mut paths = self.paths.clone();
+        paths.readable.extend(program_dirs(program));
+        if cfg!(target_os = "macos") {
+            let port = self.proxy.as_ref().and_then(Proxy::addr).map(|addr| addr.port());
+            return vec!["-p".into(), profile(&paths, port).into()];
+        }
+        let socket = self.proxy.as_ref().and_then(Proxy::socket_path);
+        bwrap_args(&paths, socket.as_deref(), self.relay.as_deref())
+    }
+
+    /// A command running `program` in the sandbox, to add its arguments
+    /// to. Its environment is left to the caller, to set `env` last.
+    pub fn command(&self, program: &Path) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(&self.program);
+        cmd.args(self.args(program));
+        cmd.arg(program);
+        cmd
+    }
+
+    /// Make `cmd` run its program in the sandbox.
+    pub fn wrap(&self, cmd: &mut portable_pty::CommandBuilder) {
+        let program = PathBuf::from(cmd.get_argv().first().cloned().unwrap_or_default());
+        let prefix = std::iter::once(self.program.clone().into_os_string()).chain(self.args(&program));
+        cmd.get_argv_mut().splice(0..0, prefix);
+        for (name, value) in &self.env {
+            cmd.env(name, value);
+        }
+    }
+}
+
+/// Whether `host` can be allowed: a host name, an IP address or `*.domain`.
+pub fn valid_host(host: &str) -> bool {
+    let name = host.strip_prefix("*.").unwrap_or(host);
+    !name.is_empty()
+        && name.len() <= 253
+        && name
+            .split('.')
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        || host.parse::<std::net::IpAddr>().is_ok()
+}
+
+/// Check hosts to allow, failing on the first that isn't [`valid_host`].
+pub fn check_hosts(hosts: &[String]) -> Result<(), String> {
+    match hosts.iter().find(|host| !valid_host(host)) {
+        Some(host) => Err(format!("\"{}\" isn't a host name or *.domain", host)),
+        None => Ok(()),
+    }
+}
+
+/// Whether `host` matches one of `allowed`: exactly, or under a `*.domain`.
+pub fn host_allowed(host: &str, allowed: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+            None => host == pattern,
+        }
+    })
+}
+
+fn sandbox_program() -> Result<PathBuf, SandboxError> {
+    if cfg!(target_os = "macos") {
+        let path = PathBuf::from("/usr/bin/sandbox-exec");
+        return match crate::process::is_executable(&path) {
+            true => Ok(path),
+            false => Err(SandboxError::Unavailable("sandbox-exec")),
+        };
+    }
+    if !cfg!(target_os = "linux") {
+        return Err(SandboxError::Unsupported);
+    }
+    find_program(OsStr::new("bwrap")).ok_or(SandboxError::Unavailable("bubblewrap (bwrap)"))
+}
+
+/// `name` as found on `PATH`.
+fn find_program(name: &OsStr) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| crate::process::is_executable(path))
+    })
+}
+
+/// Directories holding `program`, as found on `PATH` and with links
+/// resolved, so one installed in the home directory can run.
+fn program_dirs(program: &Path) -> Vec<PathBuf> {
+    let found = match program.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Some(program.to_path_buf()),
+        _ => find_program(program.as_os_str()),
+    };
+    let Some(found) = found else {
+        return Vec::new();
+    };
+    let resolved = std::fs::canonicalize(&found).ok();
+    [Some(found.as_path()), resolved.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter_map(Path::parent)
+        .map(|dir| std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()))
+        .collect()
+}
+
+/// What a process under `policy` may see. `private_tmp` when the sandbox
+/// gives it a temp directory of its own.
+fn sandbox_paths(policy: &Policy, private_tmp: bool) -> Paths {
+    let home = dirs::home_dir();
+    let mut readable = policy.readable.clone();
+    let mut writable = policy.writable.clone();
+    if !private_tmp {
+        writable.push(std::env::temp_dir());
+    }
+    if let Some(paths) = std::env::var_os("PATH") {
+        readable.extend(std::env::split_paths(&paths));
+    }
+    if let Some(home) = &home {
+        let claude = home.join(".claude");
+        for name in CLAUDE_STATE {
+            let path = claude.join(name);
+            // Created now, as the directory around it can't be written.
+            if !name.starts_with('.') {
+                let _ = std::fs::create_dir_all(&path);
+            }
+            writable.push(path);
+        }
+        readable.push(claude);
+        readable.push(home.join(".claude.json"));
+    }
+    let resolve = |paths: Vec<PathBuf>| -> Vec<PathBuf> {
+        paths
+            .into_iter()
+            .map(|path| std::fs::canonicalize(&path).unwrap_or(path))
+            .collect()
+    };
+    let home = home.map(|home| std::fs::canonicalize(&home).unwrap_or(home));
+    Paths {
+        hidden: home.as_ref().map(|home| home.join(".mado")),
+        home,
+        readable: resolve(readable),
+        writable: resolve(writable),
+    }
+}
+
+/// bubblewrap arguments: the filesystem read-only, the home directory
+/// empty but for `paths`, a private `/tmp`, no network but loopback, and
+/// with a proxy `socket` its relay ahead of the program.
+fn bwrap_args(paths: &Paths, socket: Option<&Path>, relay: Option<&Path>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+        "--unshare-net",
+        "--unshare-pid",
+        "--die-with-parent",
+    ]
+    .into_iter()
+    .map(OsString::from)
+    .collect();
+
+    // Outside the home directory everything can be read already. Mounted
+    // outermost first, so each shows through those around it.
+    let under_home = |path: &&PathBuf| paths.home.as_ref().is_none_or(|home| path.starts_with(home));
+    let mut mounts: Vec<(&Path, &str)> = Vec::new();
+    mounts.extend(paths.home.iter().map(|home| (home.as_path(), "--tmpfs")));
+    mounts.extend(paths.hidden.iter().map(|hidden| (hidden.as_path(), "--tmpfs")));
+    mounts.extend(paths.readable.iter().filter(under_home).map(|path| (path.as_path(), "--ro-bind-try")));
+    mounts.extend(paths.writable.iter().map(|path| (path.as_path(), "--bind-try")));
+    mounts.sort_by_key(|(path, _)| path.components().count());
+    for (path, kind) in mounts {
+        args.push(kind.into());
+        if kind != "--tmpfs" {
+            args.push(path.into());
+        }
+        args.push(path.into());
+    }
+
+    if let Some(socket) = socket {
+        args.extend(["--bind".into(), socket.into(), PROXY_SOCKET.into()]);
+    }
+    args.push("--".into());
+    if let Some(relay) = relay {
+        // The program starts once the relay is listening, or has died.
+        let script = format!(
+            "{} TCP-LISTEN:{port},bind=127.0.0.1,fork,reuseaddr UNIX-CONNECT:{} & \
+             while kill -0 $! 2>/dev/null && ! grep -q ':{port:04X} 00000000:0000 0A' /proc/net/tcp; do sleep 0.01; done; \
+             exec \"$@\"",
+            relay.display(),
+            PROXY_SOCKET,
+            port = RELAY_PORT,
+        );
+        args.extend(["/bin/sh".into(), "-c".into(), script.into(), "sh".into()]);
+    }
+    args
+}
+
+/// A `sandbox-exec` profile: reads in the home directory only of `paths`,
+/// none of the hidden directory, writes only to the writable paths and
+/// `/dev`, and connections only to the proxy on `proxy_port`.
+fn profile(paths: &Paths, proxy_port: Option<u16>) -> String {
+    let quote = |path: &Path| format!("\"{}\"", path.display().to_string().replace('\\', "\\\\").replace('"', "\\\""));
+    let subpaths = |paths: &[&PathBuf]| -> String {
+        paths.iter().map(|path| format!(" (subpath {})", quote(path))).collect()
+    };
+    let visible: Vec<&PathBuf> = paths.readable.iter().chain(&paths.writable).collect();
+    let writable: Vec<&PathBuf> = paths.writable.iter().collect();
+
+    let mut profile = String::from("(version 1)\n(allow default)\n");
+    if let Some(home) = &paths.home {
+        profile.push_str(&format!("(deny file-read-data (subpath {}))\n", quote(home)));
+        profile.push_str(&format!("(allow file-read-data{})\n", subpaths(&visible)));
+    }
+    profile.push_str(&format!("(deny file-write*)\n(allow file-write* (subpath \"/dev\"){})\n", subpaths(&writable)));
+    if let Some(hidden) = &paths.hidden {
+        profile.push_str(&format!("(deny file-read* file-write* (subpath {}))\n", quote(hidden)));
+        let inside: Vec<&PathBuf> = paths.readable.iter().filter(|path| path.starts_with(hidden)).collect();
+        if !inside.is_empty() {
+            profile.push_str(&format!("(allow file-read*{})\n", subpaths(&inside)));
+        }
+    }
+    profile.push_str("(deny network-outbound (remote ip \"*:*\"))\n(deny network-outbound (remote unix-socket))\n");
+    if let Some(port) = proxy_port {
+        profile.push_str(&format!("(allow network-outbound (remote ip \"localhost:{}\"))\n", port));
+    }
+    profile
+}
+
+/// An HTTP proxy tunneling `CONNECT`s to allowed hosts, on localhost or a
+/// Unix socket. Stops taking connections when dropped.
+#[derive(Debug)]
+pub struct Proxy {
+    listening: Listening,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Debug)]
+enum Listening {
+    Tcp(SocketAddr),
+    /// The socket, in a directory of its own removed with it.
+    Unix(PathBuf),
+}
+
+impl Proxy {
+    /// Listen on a free port. Must be called within the daemon's runtime.
+    pub fn start(allowed_hosts: Vec<String>) -> std::io::Result<Self> {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let addr = listener.local_addr()?;
+        let allowed_hosts = Arc::new(allowed_hosts);
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => serve(stream, allowed_hosts.clone()),
+                    Err(e) => tracing::warn!("Sandbox proxy failed to accept a connection: {}", e),
+                }
+            }
+        });
+        Ok(Self {
+            listening: Listening::Tcp(addr),
+            task,
+        })
+    }
+
+    /// Listen on a Unix socket in a new directory only the user can enter.
+    /// Must be called within the daemon's runtime.
+    pub fn start_unix(allowed_hosts: Vec<String>) -> std::io::Result<Self> {
+        use std::os::unix::fs::DirBuilderExt;
+
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "mado-proxy-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let socket = dir.join("proxy.sock");
+        let listener = match UnixListener::bind(&socket) {
+            Ok(listener) => listener,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                return Err(e);
+            }
+        };
+        let allowed_hosts = Arc::new(allowed_hosts);
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => serve(stream, allowed_hosts.clone()),
+                    Err(e) => tracing::warn!("Sandbox proxy failed to accept a connection: {}", e),
+                }
+            }
+        });
+        Ok(Self {
+            listening: Listening::Unix(socket),
+            task,
+        })
+    }
+
+    /// Its address, if it listens on localhost.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        match &self.listening {
+            Listening::Tcp(addr) => Some(*addr),
+            Listening::Unix(_) => None,
+        }
+    }
+
+    /// Its socket, if it listens on a Unix socket.
+    pub fn socket_path(&self) -> Option<PathBuf> {
+        match &self.listening {
+            Listening::Tcp(_) => None,
+            Listening::Unix(socket) => Some(socket.clone()),
+        }
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Listening::Unix(socket) = &self.listening
+            && let Some(dir) = socket.parent()
+        {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Tunnel one connection to the proxy in the background.
+fn serve<S>(stream: S, allowed_hosts: Arc<Vec<String>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(e) = tunnel(stream, &allowed_hosts).await {
+            tracing::debug!("Sandbox proxy connection failed: {}", e);
+        }
+    });
+}
+
+/// Longest request head the proxy reads.
+const MAX_REQUEST_HEAD: u64 = 8 * 1024;
+
+/// Serve one connection to the proxy.
+async fn tunnel<S>(stream: S, allowed_hosts: &[String]) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    // The client sends nothing after the head until it has the reply.
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    {
+        let mut limited = (&mut reader).take(MAX_REQUEST_HEAD);
+        while limited.read_line(&mut head).await? > 0 && !head.ends_with("\r\n\r\n") && !head.ends_with("\n\n") {}
+    }
+    let mut client = reader.into_inner();
+
+    let target = match head.split_whitespace().collect::<Vec<_>>()[..] {
+        ["CONNECT", target, ..] => target.to_string(),
+        _ => {
+            client.write_all(b"HTTP/1.1 405 Method Not Allowed\r\nAllow: CONNECT\r\n\r\n").await?;
+            return Ok(());
+        }
+    };
+    let Some((host, port)) = target
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host.trim_start_matches('[').trim_end_matches(']'), port.parse::<u16>().ok()?)))
+    else {
+        client.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await?;
+        return Ok(());
+    };
+    if !host_allowed(host, allowed_hosts) {
+        tracing::warn!("Sandbox refused a connection to {}", host);
+        client.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n").await?;
+        return Ok(());
+    }
+
+    let mut upstream = match TcpStream::connect((host, port)).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await?;
+            return Err(e);
+        }
+    };
+    client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_host_allowed() {
+        let allowed = vec!["api.anthropic.com".to_string(), "*.npmjs.org".to_string()];
+        assert!(host_allowed("api.anthropic.com", &allowed));
+        assert!(host_allowed("API.Anthropic.com.", &allowed));
+        assert!(host_allowed("registry.npmjs.org", &allowed));
+        assert!(!host_allowed("npmjs.org", &allowed));
+        assert!(!host_allowed("evilnpmjs.org", &allowed));
+        assert!(!host_allowed("anthropic.com", &allowed));
+
+        assert!(valid_host("*.npmjs.org"));
+        assert!(valid_host("10.0.0.1"));
+        assert!(!valid_host("*"));
+        assert!(!valid_host("https://github.com"));
+        assert!(!valid_host("github.com:443"));
+    }
+
+    #[test]
+    fn test_policy_for_session() {
+        let config = SandboxConfig::default();
+        assert_eq!(Policy::for_session(&SandboxSettings::default(), Some("/src/api"), &config), None);
+
+        let sandbox = SandboxSettings {
+            enabled: true,
+            allowed_hosts: vec!["github.com".into(), "api.anthropic.com".into()],
+        };
+        let policy = Policy::for_session(&sandbox, Some("/src/api"), &config)
+            .unwrap()
+            .with_readable([PathBuf::from("/data/attachments")]);
+        assert_eq!(policy.writable, vec![PathBuf::from("/src/api")]);
+        assert_eq!(policy.readable, vec![PathBuf::from("/data/attachments")]);
+        assert_eq!(policy.allowed_hosts, vec!["api.anthropic.com".to_string(), "github.com".to_string()]);
+    }
+
+    #[test]
+    fn test_bwrap_args() {
+        let paths = Paths {
+            home: Some(PathBuf::from("/home/me")),
+            hidden: Some(PathBuf::from("/home/me/.mado")),
+            readable: vec![
+                PathBuf::from("/usr/bin"),
+                PathBuf::from("/home/me/.claude"),
+                PathBuf::from("/home/me/.mado/conversations/s1/attachments"),
+            ],
+            writable: vec![PathBuf::from("/home/me"), PathBuf::from("/home/me/.claude/projects")],
+        };
+        let args = bwrap_args(&paths, None, None);
+        let args: Vec<_> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(&args[..3], ["--ro-bind", "/", "/"]);
+        for flag in ["--unshare-net", "--unshare-pid", "--die-with-parent"] {
+            assert!(args.contains(&flag), "{:?}", args);
+        }
+        assert!(args.windows(2).any(|w| w == ["--tmpfs", "/tmp"]));
+        // Outermost first: the home directory hidden, the working directory
+        // (here all of it) back, the daemon's directory hidden again but
+        // for the attachments, and the CLI's state writable within its
+        // read-only directory.
+        let position = |window: &[&str]| args.windows(window.len()).position(|w| w == window).unwrap();
+        let home = position(&["--tmpfs", "/home/me"]);
+        let workspace = position(&["--bind-try", "/home/me", "/home/me"]);
+        let hidden = position(&["--tmpfs", "/home/me/.mado"]);
+        let claude = position(&["--ro-bind-try", "/home/me/.claude", "/home/me/.claude"]);
+        let projects = position(&["--bind-try", "/home/me/.claude/projects", "/home/me/.claude/projects"]);
+        let attachments = position(&["--ro-bind-try", "/home/me/.mado/conversations/s1/attachments"]);
+        assert!(home < workspace && workspace < hidden && hidden < attachments);
+        assert!(claude < projects);
+        // Readable anyway.
+        assert!(!args.contains(&"/usr/bin"));
+        assert_eq!(args.last(), Some(&"--"));
+
+        let args = bwrap_args(&paths, Some(Path::new("/tmp/mado-proxy-1-0/proxy.sock")), Some(Path::new("/usr/bin/socat")));
+        let args: Vec<_> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+        assert!(args.windows(3).any(|w| w == ["--bind", "/tmp/mado-proxy-1-0/proxy.sock", PROXY_SOCKET]));
+        let relay = args.iter().position(|arg| *arg == "--").unwrap();
+        assert_eq!(args[relay + 1..relay + 3], ["/bin/sh", "-c"]);
+        assert!(args[relay + 3].starts_with("/usr/bin/socat TCP-LISTEN:3128,bind=127.0.0.1"));
+        assert!(args[relay + 3].contains(":0C38 00000000:0000 0A"));
+        assert_eq!(args.last(), Some(&"sh"));
+    }
+
+    #[test]
+    fn test_profile() {
+        let paths = Paths {
+            home: Some(PathBuf::from("/Users/me")),
+            hidden: Some(PathBuf::from("/Users/me/.mado")),
+            readable: vec![PathBuf::from("/Users/me/.mado/conversations/s1/attachments")],
+            writable: vec![PathBuf::from("/src/my \"api\"")],
+        };
+        let profile = profile(&paths, Some(4100));
+        assert!(profile.contains(r#"(deny file-read-data (subpath "/Users/me"))"#));
+        assert!(profile.contains("(deny file-write*)"));
+        assert!(profile.contains(r#"(subpath "/src/my \"api\"")"#));
+        assert!(profile.contains(r#"(deny file-read* file-write* (subpath "/Users/me/.mado"))"#));
+        assert!(profile.contains(r#"(allow file-read* (subpath "/Users/me/.mado/conversations/s1/attachments"))"#));
+        assert!(profile.contains(r#"(deny network-outbound (remote ip "*:*"))"#));
+        assert!(profile.contains("(deny network-outbound (remote unix-socket))"));
+        assert!(profile.contains(r#"(remote ip "localhost:4100")"#));
+        // Later rules win: the daemon's directory stays hidden.
+        assert!(profile.find("(deny file-read* file-write*").unwrap() > profile.find("(allow file-read-data").unwrap());
+    }
+
+    #[test]
+    fn test_program_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let real = tmp.path().join("versions");
+        std::fs::create_dir(&real).unwrap();
+        std::fs::write(real.join("claude-1.0"), "").unwrap();
+        let bin = tmp.path().join("bin");
+        std::fs::create_dir(&bin).unwrap();
+        std::os::unix::fs::symlink(real.join("claude-1.0"), bin.join("claude")).unwrap();
+
+        let dirs = program_dirs(&bin.join("claude"));
+        assert_eq!(dirs, [bin.canonicalize().unwrap(), real.canonicalize().unwrap()]);
+        assert!(program_dirs(Path::new("no-such-program-here")).is_empty());
+    }
+
+    async fn connect(proxy: &Proxy, target: &str) -> (String, TcpStream) {
+        let mut stream = TcpStream::connect(proxy.addr().unwrap()).await.unwrap();
+        stream
+            .write_all(format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target).as_bytes())
+            .await
+            .unwrap();
+        let mut reply = Vec::new();
+        while !reply.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            if stream.read(&mut byte).await.unwrap() == 0 {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        (String::from_utf8(reply).unwrap(), stream)
+    }
+
+    #[tokio::test]
+    async fn test_proxy_tunnels_to_allowed_hosts_only() {
+        let server = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(b"pong").await.unwrap();
+        });
+        let proxy = Proxy::start(vec!["127.0.0.1".to_string()]).unwrap();
+
+        let (reply, mut stream) = connect(&proxy, &format!("127.0.0.1:{}", port)).await;
+        assert!(reply.starts_with("HTTP/1.1 200"), "{}", reply);
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        let (reply, _) = connect(&proxy, &format!("localhost:{}", port)).await;
+        assert!(reply.starts_with("HTTP/1.1 403"), "{}", reply);
+
+        let mut stream = TcpStream::connect(proxy.addr().unwrap()).await.unwrap();
+        stream.write_all(b"GET http://127.0.0.1/ HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.starts_with("HTTP/1.1 405"), "{}", reply);
+    }
+
+    #[tokio::test]
+    async fn test_unix_proxy() {
+        let server = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            stream.write_all(b"pong").await.unwrap();
+        });
+        let proxy = Proxy::start_unix(vec!["127.0.0.1".to_string()]).unwrap();
+        assert!(proxy.addr().is_none());
+        let socket = proxy.socket_path().unwrap();
+
+        let mut stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+        let target = format!("127.0.0.1:{}", port);
+        stream
+            .write_all(format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target).as_bytes())
+            .await
+            .unwrap();
+        let mut reply = [0; 39];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply[..], b"HTTP/1.1 200 Connection Established\r\n\r\n");
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        drop(proxy);
+        assert!(!socket.parent().unwrap().exists());
+    }
+}
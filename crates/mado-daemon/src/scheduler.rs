@@ -0,0 +1,353 @@
+//! Prompts run on a cron schedule.
+//!
+//! `POST /schedules` adds a schedule: a cron expression, a prompt, a working
+//! directory and a model. When one comes due the daemon runs the prompt as a
+//! headless task (see [`crate::task`]) in a fresh conversation, saves a
+//! milestone if it changed the repository, and records the run; the last
+//! [`MAX_RUNS`] runs are served by `GET /schedules/{id}/runs`. Schedules and
+//! their runs are kept in `schedules.json` next to state.json. A run missed
+//! while the daemon was down happens once when it next starts. The
+//! directory must be trusted when the schedule is made and again at each
+//! run; a run in a directory no longer trusted fails without starting.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+
+use mado_core::types::{MessageRole, Schedule, ScheduleRun, ScheduleRunStatus, StreamEvent, TokenUsage};
+
+use crate::server::AppState;
+use crate::task::{TaskEvent, TaskOptions};
+
+/// How often due schedules are looked for.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runs kept per schedule.
+pub const MAX_RUNS: usize = 50;
+
+/// A schedule and its runs, oldest first, as saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    schedule: Schedule,
+    #[serde(default)]
+    runs: Vec<ScheduleRun>,
+}
+
+#[derive(Debug, Default)]
+struct Schedules {
+    entries: Vec<Entry>,
+    /// Schedules with a run in progress, which don't start another.
+    running: HashSet<String>,
+}
+
+/// All schedules.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// Where schedules are saved; kept in memory only when unset.
+    path: Option<PathBuf>,
+    schedules: Mutex<Schedules>,
+}
+
+/// The first time after `after` that `cron` matches, in local time.
+pub fn next_run(cron: &str, after: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let cron = Cron::new(cron)
+        .parse()
+        .map_err(|e| format!("Invalid cron expression: {}", e))?;
+    cron.find_next_occurrence(&after.with_timezone(&Local), false)
+        .map(|next| next.with_timezone(&Utc))
+        .map_err(|e| format!("Cron expression never matches: {}", e))
+}
+
+impl Scheduler {
+    /// Load the schedules saved at `path`, starting with none if there are
+    /// none or they can't be read.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .inspect_err(|e| tracing::warn!("Unreadable schedules at {}: {}", path.display(), e))
+                .unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                tracing::warn!("Failed to read schedules at {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        Self {
+            path: Some(path),
+            schedules: Mutex::new(Schedules {
+                entries,
+                running: HashSet::new(),
+            }),
+        }
+    }
+
+    fn save(&self, entries: &[Entry]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| crate::state::write_atomic(path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            tracing::error!("Failed to save schedules: {}", e);
+        }
+    }
+
+    /// All schedules, oldest first.
+    pub fn list(&self) -> Vec<Schedule> {
+        let schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        schedules.entries.iter().map(|e| e.schedule.clone()).collect()
+    }
+
+    pub fn add(&self, schedule: Schedule) {
+        let mut schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        schedules.entries.push(Entry {
+            schedule,
+            runs: Vec::new(),
+        });
+        self.save(&schedules.entries);
+    }
+
+    /// Remove a schedule and its runs. A run in progress finishes but is not
+    /// recorded. Returns whether the schedule existed.
+    pub fn remove(&self, id: &str) -> bool {
+        let mut schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        let before = schedules.entries.len();
+        schedules.entries.retain(|e| e.schedule.id != id);
+        let removed = schedules.entries.len() < before;
+        if removed {
+            self.save(&schedules.entries);
+        }
+        removed
+    }
+
+    /// Remove every schedule. Returns how many there were.
+    pub fn clear(&self) -> usize {
+        let mut schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        let removed = schedules.entries.len();
+        schedules.entries.clear();
+        self.save(&schedules.entries);
+        removed
+    }
+
+    /// Runs of a schedule, newest first, or `None` if there is no such
+    /// schedule.
+    pub fn runs(&self, id: &str) -> Option<Vec<ScheduleRun>> {
+        let schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = schedules.entries.iter().find(|e| e.schedule.id == id)?;
+        Some(entry.runs.iter().rev().cloned().collect())
+    }
+
+    /// Schedules due at `now` that aren't already running. Each is marked
+    /// running and its next run moved past `now`.
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<Schedule> {
+        let mut guard = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        let Schedules { entries, running } = &mut *guard;
+        let mut due = Vec::new();
+        for entry in entries.iter_mut() {
+            let schedule = &mut entry.schedule;
+            if running.contains(&schedule.id) || schedule.next_run.is_none_or(|next| next > now) {
+                continue;
+            }
+            due.push(schedule.clone());
+            running.insert(schedule.id.clone());
+            schedule.next_run = next_run(&schedule.cron, now)
+                .inspect_err(|e| tracing::warn!("Schedule {} won't run again: {}", schedule.id, e))
+                .ok();
+        }
+        if !due.is_empty() {
+            self.save(entries);
+        }
+        due
+    }
+
+    /// Record a finished run of schedule `id`, keeping the last
+    /// [`MAX_RUNS`].
+    fn finish(&self, id: &str, run: ScheduleRun) {
+        let mut guard = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+        let Schedules { entries, running } = &mut *guard;
+        running.remove(id);
+        let Some(entry) = entries.iter_mut().find(|e| e.schedule.id == id) else {
+            return;
+        };
+        entry.runs.push(run);
+        let excess = entry.runs.len().saturating_sub(MAX_RUNS);
+        entry.runs.drain(..excess);
+        self.save(entries);
+    }
+}
+
+/// Run one schedule now.
+async fn run(state: &AppState, schedule: &Schedule) -> ScheduleRun {
+    let started_at = Utc::now();
+    let mut run = ScheduleRun {
+        started_at,
+        finished_at: started_at,
+        status: ScheduleRunStatus::Failed,
+        reply: None,
+        error: None,
+        changes: None,
+        milestone: None,
+        usage: TokenUsage::default(),
+        cost_usd: 0.0,
+    };
+
+    // Trust may have been revoked or purged since the schedule was made.
+    if !state.session_manager.is_trusted(&schedule.cwd).await {
+        run.error = Some(format!("Workspace {} is not trusted", schedule.cwd));
+        run.finished_at = Utc::now();
+        return run;
+    }
+
+    let options = TaskOptions {
+        prompt: schedule.prompt.clone(),
+        working_dir: PathBuf::from(&schedule.cwd),
+        model: schedule.model.clone(),
+        system_prompt: None,
+        permission_mode: schedule.permission_mode,
+    };
+    let mut events = match crate::task::start(state.conversation_manager.clone(), options).await {
+        Ok(events) => events,
+        Err(e) => {
+            run.error = Some(e.to_string());
+            run.finished_at = Utc::now();
+            return run;
+        }
+    };
+
+    let mut result = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            TaskEvent::Stream(StreamEvent::Error { message, .. }) => run.error = Some(message),
+            TaskEvent::Stream(_) => {}
+            TaskEvent::Complete(complete) => result = Some(complete),
+        }
+    }
+    let Some(result) = result else {
+        run.error.get_or_insert_with(|| "The task ended without a result".to_string());
+        run.finished_at = Utc::now();
+        return run;
+    };
+
+    run.reply = result
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == MessageRole::Assistant)
+        .map(|m| m.content.clone());
+    run.usage = result.usage;
+    run.cost_usd = result.cost_usd;
+    if run.error.is_none() {
+        run.status = ScheduleRunStatus::Succeeded;
+    }
+
+    let changed = result.changes.as_ref().is_some_and(|c| !c.files.is_empty());
+    if let (true, Some(root)) = (changed, crate::git_ops::discover_repo_root(Path::new(&schedule.cwd))) {
+        let _lock = state.workspace_locks.acquire(&root).await;
+        let message = format!("Scheduled run: {}", schedule.prompt.lines().next().unwrap_or(""));
+        match tokio::task::spawn_blocking(move || crate::git_ops::save_milestone(&root, &message)).await {
+            Ok(Ok(milestone)) => run.milestone = Some(milestone.oid),
+            Ok(Err(e)) => tracing::warn!("No milestone for schedule {}: {}", schedule.id, e),
+            Err(e) => tracing::error!("Milestone task for schedule {} failed: {}", schedule.id, e),
+        }
+    }
+    run.changes = result.changes;
+    run.finished_at = Utc::now();
+    run
+}
+
+/// Start the task that runs schedules when they come due.
+pub fn spawn(state: AppState) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("scheduler", move || run_periodically(state.clone()))
+}
+
+async fn run_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for schedule in state.scheduler.take_due(Utc::now()) {
+            let state = state.clone();
+            tokio::spawn(async move {
+                tracing::info!("Running schedule {} in {}", schedule.id, schedule.cwd);
+                let run = run(&state, &schedule).await;
+                if let Some(error) = &run.error {
+                    tracing::warn!("Schedule {} failed: {}", schedule.id, error);
+                }
+                state.scheduler.finish(&schedule.id, run);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mado_core::types::PermissionMode;
+
+    fn schedule(id: &str, next_run: DateTime<Utc>) -> Schedule {
+        Schedule {
+            id: id.to_string(),
+            cron: "0 2 * * *".to_string(),
+            prompt: "Update the CHANGELOG".to_string(),
+            cwd: "/tmp".to_string(),
+            model: "sonnet".to_string(),
+            permission_mode: PermissionMode::AutoEdit,
+            created_at: next_run,
+            next_run: Some(next_run),
+        }
+    }
+
+    fn finished_run(cost_usd: f64) -> ScheduleRun {
+        ScheduleRun {
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            status: ScheduleRunStatus::Succeeded,
+            reply: None,
+            error: None,
+            changes: None,
+            milestone: None,
+            usage: TokenUsage::default(),
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn test_next_run() {
+        let after = Local.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap().with_timezone(&Utc);
+        let next = next_run("0 2 * * *", after).unwrap().with_timezone(&Local);
+        assert_eq!(next.naive_local().to_string(), "2026-03-02 02:00:00");
+        assert!(next_run("not a cron", after).is_err());
+    }
+
+    #[test]
+    fn test_take_due_and_finish() {
+        let now = Utc::now();
+        let scheduler = Scheduler::default();
+        scheduler.add(schedule("due", now - chrono::Duration::minutes(1)));
+        scheduler.add(schedule("later", now + chrono::Duration::hours(1)));
+
+        let due = scheduler.take_due(now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "due");
+        assert!(scheduler.list()[0].next_run.unwrap() > now);
+        // Not started again while running, even if due.
+        assert!(scheduler.take_due(now + chrono::Duration::days(2)).iter().all(|s| s.id != "due"));
+
+        for i in 0..MAX_RUNS + 1 {
+            scheduler.finish("due", finished_run(i as f64));
+        }
+        let runs = scheduler.runs("due").unwrap();
+        assert_eq!(runs.len(), MAX_RUNS);
+        assert_eq!(runs[0].cost_usd, MAX_RUNS as f64);
+
+        assert!(scheduler.remove("due"));
+        assert!(scheduler.runs("due").is_none());
+    }
+}
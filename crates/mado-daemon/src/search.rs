@@ -0,0 +1,220 @@
+//! Workspace content search for `GET /sessions/{id}/search`.
+//!
+//! Walks the working directory the way ripgrep does (`.gitignore`,
+//! `.ignore` and hidden files are skipped, binary files are ignored) and
+//! returns matching lines with surrounding context. The query is a literal
+//! string unless a regex is asked for, and is case-insensitive when it is all
+//! lowercase.
+
+use std::path::Path;
+
+use grep_regex::RegexMatcherBuilder;
+use grep_searcher::{
+    BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch,
+};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+
+use mado_core::types::{SearchMatch, SearchResults};
+
+/// Longer lines are cut to this many characters.
+const MAX_LINE_CHARS: usize = 1000;
+
+/// What to search for.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub query: String,
+    /// Treat `query` as a regular expression instead of a literal.
+    pub regex: bool,
+    /// Only search files matching this glob (relative to the root; a leading
+    /// `!` excludes instead).
+    pub glob: Option<String>,
+    /// Lines of context before and after each match.
+    pub context: usize,
+    /// Stop after this many matches.
+    pub max_matches: usize,
+    /// Globs (relative to the root) of paths to skip, from the project config.
+    pub ignored_paths: Vec<String>,
+}
+
+/// Errors from a search.
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("Invalid glob: {0}")]
+    InvalidGlob(String),
+}
+
+/// Search the files under `root`.
+pub fn search(root: &Path, options: &SearchOptions) -> Result<SearchResults, SearchError> {
+    let matcher = RegexMatcherBuilder::new()
+        .case_smart(true)
+        .fixed_strings(!options.regex)
+        .line_terminator(Some(b'\n'))
+        .build(&options.query)
+        .map_err(|e| SearchError::InvalidQuery(e.to_string()))?;
+
+    let mut walker = WalkBuilder::new(root);
+    walker.require_git(false);
+    if let Some(ignored) = crate::project_config::ignored(root, &options.ignored_paths) {
+        walker.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            !crate::project_config::is_ignored(Some(&ignored), entry.path(), is_dir)
+        });
+    }
+    if let Some(glob) = &options.glob {
+        let mut overrides = OverrideBuilder::new(root);
+        overrides
+            .add(glob)
+            .map_err(|e| SearchError::InvalidGlob(e.to_string()))?;
+        walker.overrides(
+            overrides
+                .build()
+                .map_err(|e| SearchError::InvalidGlob(e.to_string()))?,
+        );
+    }
+
+    let mut searcher = SearcherBuilder::new()
+        .line_number(true)
+        .before_context(options.context)
+        .after_context(options.context)
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .build();
+
+    let mut results = SearchResults::default();
+    for entry in walker.build().filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let mut sink = MatchSink {
+            path: relative.to_string_lossy().to_string(),
+            results: &mut results,
+            before: Vec::new(),
+            max_matches: options.max_matches,
+        };
+        if let Err(e) = searcher.search_path(&matcher, path, &mut sink) {
+            tracing::debug!("Skipping {} in search: {}", path.display(), e);
+        }
+        if results.truncated {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Collects the matches in one file.
+struct MatchSink<'a> {
+    path: String,
+    results: &'a mut SearchResults,
+    /// Context lines waiting for the next match.
+    before: Vec<String>,
+    max_matches: usize,
+}
+
+impl Sink for MatchSink<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if self.results.matches.len() >= self.max_matches {
+            self.results.truncated = true;
+            return Ok(false);
+        }
+        self.results.matches.push(SearchMatch {
+            path: self.path.clone(),
+            line_number: mat.line_number().unwrap_or(0),
+            line: line_text(mat.bytes()),
+            before: std::mem::take(&mut self.before),
+            after: Vec::new(),
+        });
+        Ok(true)
+    }
+
+    fn context(&mut self, _: &Searcher, context: &SinkContext<'_>) -> Result<bool, Self::Error> {
+        let line = line_text(context.bytes());
+        match context.kind() {
+            SinkContextKind::After => {
+                if let Some(last) = self.results.matches.last_mut() {
+                    last.after.push(line);
+                }
+            }
+            _ => self.before.push(line),
+        }
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _: &Searcher) -> Result<bool, Self::Error> {
+        self.before.clear();
+        Ok(true)
+    }
+}
+
+fn line_text(bytes: &[u8]) -> String {
+    let line = String::from_utf8_lossy(bytes);
+    let line = line.trim_end_matches(['\r', '\n']);
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => line[..end].to_string(),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(query: &str) -> SearchOptions {
+        SearchOptions {
+            query: query.to_string(),
+            regex: false,
+            glob: None,
+            context: 1,
+            max_matches: 100,
+            ignored_paths: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_search_respects_gitignore_and_glob() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join("target/out.rs"), "fn needle() {}\n").unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "one\nfn Needle() {}\nthree\n").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "a needle here\n").unwrap();
+
+        let mut results = search(dir.path(), &options("needle")).unwrap();
+        results.matches.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(results.matches.len(), 2);
+        let first = &results.matches[0];
+        assert_eq!(first.path, "lib.rs");
+        assert_eq!(first.line_number, 2);
+        assert_eq!(first.before, ["one"]);
+        assert_eq!(first.after, ["three"]);
+
+        // An uppercase letter makes the search case-sensitive.
+        assert!(search(dir.path(), &options("Needle")).unwrap().matches.len() == 1);
+
+        let mut globbed = options("needle");
+        globbed.glob = Some("*.md".to_string());
+        let results = search(dir.path(), &globbed).unwrap();
+        assert_eq!(results.matches.len(), 1);
+        assert_eq!(results.matches[0].path, "notes.md");
+
+        let mut ignoring = options("needle");
+        ignoring.ignored_paths = vec!["*.md".to_string()];
+        let results = search(dir.path(), &ignoring).unwrap();
+        assert_eq!(results.matches.len(), 1);
+        assert_eq!(results.matches[0].path, "lib.rs");
+
+        let mut limited = options("needle");
+        limited.max_matches = 1;
+        assert!(search(dir.path(), &limited).unwrap().truncated);
+
+        let mut bad = options("(");
+        bad.regex = true;
+        assert!(matches!(search(dir.path(), &bad), Err(SearchError::InvalidQuery(_))));
+    }
+}
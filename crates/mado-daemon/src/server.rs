@@ -1,32 +1,65 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
+use std::os::fd::AsRawFd;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-use axum::extract::{Path as AxumPath, State};
+use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{DefaultBodyLimit, Path as AxumPath, State};
+use axum::http::{header, HeaderMap, HeaderValue};
 use axum::response::sse::{Event, Sse};
-use axum::response::Json;
+use axum::response::{IntoResponse, Json, Response};
 use axum::routing::{get, post};
 use axum::Router;
 use base64::Engine;
 use futures::stream::Stream;
 use serde::Deserialize;
 use tokio::net::UnixListener;
-use tokio::sync::Mutex;
-use tokio_stream::wrappers::BroadcastStream;
+use tokio::sync::{broadcast, Mutex};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
 use tokio_stream::StreamExt;
 use tracing;
-
-use mado_core::protocol::DaemonResponse;
-use mado_core::types::{DaemonStatus, PtySize, SessionId};
-
-use crate::conversation::{ConversationManager, SharedConversationManager};
-use crate::process::new_shared_process_manager;
+use utoipa::IntoParams;
+
+use mado_core::protocol::{
+    ApplyComparisonBody, ApplyReviewBody, CloneSessionBody, CompactBody, CompareBody, CreatePullRequestBody, CreateScheduleBody, CreateSessionBody, CreateTaskBody, DaemonResponse, InputBody, AppendMemoryBody, MergeBranchBody, RenderPromptBody, ResizeBody, RestoreMilestoneBody,
+    ResponseInputBody, ReviveSessionBody, RunCommandBody, SaveMilestoneBody, SendMessageBody, StageFileBody, StageFilesBody, StageHunkBody, TrustWorkspaceBody,
+    WriteFileBody, WriteMemoryBody,
+    PROTOCOL_VERSION,
+};
+use mado_core::types::{
+    ActivityEvent, ActivityKind, AuditEntry, AuditFormat, CompareEvent, CustomCommand, DaemonStatus, ExportFormat, HistoryImport, HistorySource, MemorySuggestion, PtySize, PurgeScope, RenderedPrompt, Review, ReviewAction, RunEvent, SavedPrompt, Schedule, Session, SessionId,
+    SessionSettings, SessionStatus, SessionTemplate, SessionUpdate, StreamEvent, TokenUsage, VersionInfo,
+};
+
+use crate::config::SharedSettings;
+use crate::conversation::{ConversationManager, MessageOptions, SharedConversationManager};
+use crate::compare::Comparisons;
+use crate::error::{ApiError, ApiJson, ApiResult};
+use crate::feed::{ActivityFeed, SharedActivityFeed};
+use crate::handover::{Handover, Restarter, SuccessorCommand};
+use crate::idle::IdleTracker;
+use crate::ledger::Ledger;
+use crate::runs::Runs;
+use crate::scheduler::Scheduler;
+use crate::prompts::Prompts;
+use crate::templates::Templates;
+use crate::metrics::{Metrics, MetricsWriter};
+use crate::process::{new_shared_process_manager, PtySubscription};
+use crate::remote::{RemoteError, TcpListenConfig};
 use crate::session::{SessionManager, SharedSessionManager};
 use crate::state::DaemonState;
 
+/// Maximum decoded size of a single PTY input write (1 MiB).
+pub const MAX_INPUT_BYTES: usize = 1024 * 1024;
+
+/// Base64 input larger than this (in encoded chars) is decoded and written in chunks
+/// instead of being materialized as one buffer. Must be a multiple of 4.
+const INPUT_DECODE_CHUNK: usize = 64 * 1024;
+
 /// Per-workspace mutex to serialize git operations.
 /// Prevents index.lock conflicts when multiple panes share a working directory.
 #[derive(Clone, Default)]
@@ -56,90 +89,128 @@ pub struct AppState {
     pub session_manager: SharedSessionManager,
     pub conversation_manager: SharedConversationManager,
     pub workspace_locks: WorkspaceLocks,
+    pub activity_feed: SharedActivityFeed,
+    pub metrics: Arc<Metrics>,
+    /// Graceful restart support; `None` unless a successor command is configured.
+    pub restart: Option<Arc<Restarter>>,
+    /// Settings from config.json, reloaded on SIGHUP and when the file changes.
+    pub settings: SharedSettings,
+    /// Directory the daemon logs to, served by `GET /logs`.
+    pub log_dir: Option<PathBuf>,
+    /// Directory of crash reports, served by `/crashes`.
+    pub crash_dir: Option<PathBuf>,
+    /// Directory archived sessions are written to.
+    pub archive_dir: Option<PathBuf>,
+    /// When the last request was handled, for idle auto-shutdown.
+    pub idle: Arc<IdleTracker>,
+    /// Prompts run on a cron schedule.
+    pub scheduler: Arc<Scheduler>,
+    /// Named presets for new sessions.
+    pub templates: Arc<Templates>,
+    /// The user's prompt library.
+    pub prompts: Arc<Prompts>,
+    /// Project commands in progress.
+    pub runs: Arc<Runs>,
+    /// Model comparisons in progress.
+    pub comparisons: Arc<Comparisons>,
 }
 
-/// Request body for creating a session.
-#[derive(Debug, Deserialize)]
-pub struct CreateSessionBody {
-    pub name: String,
-    #[serde(default = "default_model")]
-    pub model: String,
-    #[serde(default)]
-    pub rows: Option<u16>,
-    #[serde(default)]
-    pub cols: Option<u16>,
-    /// Working directory for the session.
+/// Query params for file diff.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FileDiffQuery {
+    pub file_path: String,
     #[serde(default)]
-    pub cwd: Option<String>,
-}
-
-fn default_model() -> String {
-    "sonnet".to_string()
+    pub staged: Option<bool>,
 }
 
-/// Request body for writing input.
-#[derive(Debug, Deserialize)]
-pub struct InputBody {
-    /// Base64-encoded input data.
-    pub data: String,
+/// Query params for uploading an attachment.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadAttachmentQuery {
+    /// File name, e.g. `screenshot.png`; the extension determines the type.
+    pub name: String,
 }
 
-/// Request body for resizing.
-#[derive(Debug, Deserialize)]
-pub struct ResizeBody {
-    pub rows: u16,
-    pub cols: u16,
+/// Query params for exporting a conversation.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `markdown` (the default), `json` or `html`.
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Include Claude's thinking, collapsed.
+    #[serde(default)]
+    pub include_thinking: bool,
 }
 
-/// Request body for saving a milestone.
-#[derive(Debug, Deserialize)]
-pub struct SaveMilestoneBody {
-    pub message: String,
+/// Query params for exporting an audit log.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditExportQuery {
+    /// `json` (the default) or `csv`.
+    #[serde(default)]
+    pub format: AuditFormat,
 }
 
-/// Request body for restoring a milestone.
-#[derive(Debug, Deserialize)]
-pub struct RestoreMilestoneBody {
-    pub oid: String,
+/// Query params for listing Claude CLI sessions.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CliSessionsQuery {
+    /// Working directory the sessions ran in.
+    pub cwd: String,
+    /// Most sessions to return (default 50).
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
-/// Request body for staging/unstaging a file.
-#[derive(Debug, Deserialize)]
-pub struct StageFileBody {
-    pub file_path: String,
+/// Query params for detecting importable history.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistorySourcesQuery {
+    /// Working directory to look for history of.
+    pub cwd: String,
 }
 
-/// Request body for batch staging/unstaging multiple files.
-#[derive(Debug, Deserialize)]
-pub struct StageFilesBody {
-    pub file_paths: Vec<String>,
+/// Query params for reviewing a turn's changes.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReviewQuery {
+    /// Review the turn of this message (the user message at or before it);
+    /// the latest turn if omitted.
+    #[serde(default)]
+    pub since_message_id: Option<String>,
 }
 
-/// Request body for staging a single hunk.
-#[derive(Debug, Deserialize)]
-pub struct StageHunkBody {
-    pub file_path: String,
-    pub hunk_index: usize,
+/// Query params for purging daemon data.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PurgeQuery {
+    /// `conversations`, `sessions`, `logs` or `everything`.
+    pub scope: PurgeScope,
 }
 
-/// Query params for file diff.
-#[derive(Debug, Deserialize)]
-pub struct FileDiffQuery {
-    pub file_path: String,
+/// Query params for the daemon-wide usage report.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// First day to include, e.g. `2026-03-01`; every day when unset.
     #[serde(default)]
-    pub staged: Option<bool>,
+    pub since: Option<chrono::NaiveDate>,
 }
 
-/// Request body for sending a message (chat mode).
-#[derive(Debug, Deserialize)]
-pub struct SendMessageBody {
-    pub content: String,
+/// Query params for listing saved prompts.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PromptsQuery {
+    /// Include the prompts of this session's project.
     #[serde(default)]
-    pub model: Option<String>,
+    pub session_id: Option<String>,
 }
 
 /// Query params for getting messages.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetMessagesQuery {
     #[serde(default)]
     pub limit: Option<usize>,
@@ -147,6 +218,40 @@ pub struct GetMessagesQuery {
     pub before_id: Option<String>,
 }
 
+/// Query params for forking a session.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ForkSessionQuery {
+    /// Last message the fork keeps; all of them if omitted.
+    #[serde(default)]
+    pub at_message_id: Option<String>,
+}
+
+/// Query params for raw PTY input.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RawInputQuery {
+    /// Write the body as a bracketed paste (see `InputBody::paste`).
+    #[serde(default)]
+    pub paste: bool,
+}
+
+/// Default and maximum number of lines returned by `GET /logs`.
+const DEFAULT_LOG_LINES: usize = 200;
+const MAX_LOG_LINES: usize = 10_000;
+
+/// Query params for tailing the daemon log.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogsQuery {
+    /// Number of lines to return (default 200, at most 10000).
+    #[serde(default)]
+    pub lines: Option<usize>,
+    /// Minimum level: "error", "warn", "info", "debug" or "trace".
+    #[serde(default)]
+    pub level: Option<String>,
+}
+
 /// Start the daemon's HTTP server on a Unix domain socket.
 pub async fn start_server(
     socket_path: PathBuf,
@@ -154,37 +259,228 @@ pub async fn start_server(
     daemon_state: Arc<Mutex<DaemonState>>,
     shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
 ) -> Result<(), ServerError> {
-    // Ensure parent directory exists with 0700 permissions.
-    ensure_dir(socket_path.parent().unwrap()).await?;
+    start_server_with_options(
+        socket_path,
+        ServerOptions::default(),
+        state_path,
+        daemon_state,
+        shutdown_signal,
+    )
+    .await
+}
 
-    // Clean up stale socket file.
-    cleanup_stale_socket(&socket_path).await?;
+/// Optional server features beyond the bare Unix socket.
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    /// Bearer token required on every request (see `auth`). No auth when unset.
+    pub auth_token: Option<String>,
+    /// TCP listener serving the same routes. Requires `auth_token`.
+    pub tcp: Option<TcpListenConfig>,
+    /// Enables graceful restart (`POST /admin/restart`, SIGUSR2) by spawning
+    /// this command as the successor.
+    pub successor: Option<SuccessorCommand>,
+    /// Listeners and PTYs inherited from a predecessor daemon.
+    pub handover: Option<Handover>,
+    /// Respawn persisted sessions left without a process at startup, instead
+    /// of only marking them terminated.
+    pub respawn_sessions: bool,
+    /// Settings from config.json, shared with whoever reloads them.
+    pub settings: SharedSettings,
+    /// Notified after each reload of `settings`; each is published as
+    /// `ConfigChanged` on the activity feed.
+    pub config_changes: Option<tokio::sync::watch::Receiver<()>>,
+    /// Directory the daemon logs to; `GET /logs` is unavailable when unset.
+    pub log_dir: Option<PathBuf>,
+    /// Directory of crash reports; `/crashes` is empty when unset.
+    pub crash_dir: Option<PathBuf>,
+    /// Directory archived sessions are written to; sessions can't be
+    /// archived when unset, and the retention policy removes them outright.
+    pub archive_dir: Option<PathBuf>,
+    /// Directory conversations keep attachments and snapshots in;
+    /// `~/.mado/conversations` when unset.
+    pub conversations_dir: Option<PathBuf>,
+}
 
-    // Bind the Unix listener.
-    let listener =
-        UnixListener::bind(&socket_path).map_err(|e| ServerError::BindFailed {
-            path: socket_path.clone(),
-            source: e,
-        })?;
+/// Start the daemon's HTTP server on a Unix domain socket and, if configured,
+/// a TCP listener serving the same routes.
+pub async fn start_server_with_options(
+    socket_path: PathBuf,
+    options: ServerOptions,
+    state_path: PathBuf,
+    daemon_state: Arc<Mutex<DaemonState>>,
+    shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(), ServerError> {
+    if options.tcp.is_some() && options.auth_token.is_none() {
+        return Err(ServerError::TcpWithoutToken);
+    }
 
-    // Set socket permissions to 0600 (owner only).
-    std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))
-        .map_err(|e| ServerError::PermissionsFailed {
-            path: socket_path.clone(),
-            source: e,
-        })?;
+    let handover = options.handover;
+    let listener = match &handover {
+        // The predecessor's socket is already bound and listening.
+        Some(handover) => handover
+            .unix_listener()
+            .and_then(UnixListener::from_std)
+            .map_err(|e| ServerError::BindFailed {
+                path: socket_path.clone(),
+                source: e,
+            })?,
+        None => {
+            // Ensure parent directory exists with 0700 permissions.
+            ensure_dir(socket_path.parent().unwrap()).await?;
+
+            // Clean up stale socket file.
+            cleanup_stale_socket(&socket_path).await?;
+
+            // Bind the Unix listener.
+            let listener =
+                UnixListener::bind(&socket_path).map_err(|e| ServerError::BindFailed {
+                    path: socket_path.clone(),
+                    source: e,
+                })?;
+
+            // Set socket permissions to 0600 (owner only).
+            std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| ServerError::PermissionsFailed {
+                    path: socket_path.clone(),
+                    source: e,
+                })?;
+            listener
+        }
+    };
 
     tracing::info!("Daemon listening on {}", socket_path.display());
 
-    let state = create_app_state(daemon_state, state_path);
-    let app = create_router(state);
+    let remote = match options.tcp {
+        Some(config) => {
+            let inherited = handover
+                .as_ref()
+                .and_then(Handover::tcp_listener)
+                .transpose()
+                .map_err(ServerError::ServeFailed)?;
+            let remote_listener = match inherited {
+                Some(listener) => crate::remote::from_std(listener, &config)?,
+                None => crate::remote::bind(&config).await?,
+            };
+            tracing::info!(
+                "Daemon listening on {}://{}",
+                if config.tls.is_some() { "tls" } else { "tcp" },
+                remote_listener.local_addr().map_err(ServerError::ServeFailed)?
+            );
+            if config.tls.is_none() {
+                tracing::warn!("TCP listener is not using TLS; the token is sent in cleartext");
+            }
+            Some(remote_listener)
+        }
+        None => None,
+    };
+
+    let mut state = create_app_state(
+        daemon_state.clone(),
+        state_path.clone(),
+        options.settings,
+        options.conversations_dir,
+    );
+    state.log_dir = options.log_dir;
+    state.crash_dir = options.crash_dir;
+    state.archive_dir = options.archive_dir;
+    let idle_save = (daemon_state.clone(), state_path.clone());
+    state.restart = options.successor.map(|successor| {
+        Arc::new(Restarter::new(
+            successor,
+            listener.as_raw_fd(),
+            remote.as_ref().map(|remote| remote.as_raw_fd()),
+            daemon_state,
+            state_path,
+        ))
+    });
+    let restart_signal = crate::handover::spawn_signal_handler(state.clone());
+
+    if let Some(handover) = handover {
+        state.session_manager.adopt_ptys(handover.ptys.clone()).await;
+        handover.notify_ready();
+        tracing::info!("Took over from daemon {}", handover.predecessor);
+
+        // The predecessor reads from the PTYs until it exits.
+        let session_manager = state.session_manager.clone();
+        tokio::spawn(async move {
+            handover.wait_for_predecessor().await;
+            session_manager.resume_ptys().await;
+        });
+    }
+    state.session_manager.reconcile(options.respawn_sessions).await;
+
+    let auto_milestones = spawn_auto_milestones(state.clone());
+    let config_changes = options
+        .config_changes
+        .map(|changes| spawn_config_change_events(changes, state.activity_feed.clone()));
+    let retention = crate::retention::spawn_cleanup(state.clone());
+    let scheduler = crate::scheduler::spawn(state.clone());
+    let watchdog = crate::limits::spawn_watchdog(state.clone());
+    let cli_sync = crate::cli_sync::spawn(state.conversation_manager.clone());
+    let presence_monitor = crate::presence::spawn_monitor(
+        state.session_manager.clone(),
+        state.conversation_manager.clone(),
+        state.activity_feed.clone(),
+    );
+    let idle_state = state.clone();
+    let mut app = create_router(state);
+    if let Some(token) = options.auth_token {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            crate::auth::require_token,
+        ));
+    }
+
+    // Fan the shutdown signal, or idleness, out to every listener.
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = shutdown_signal => {}
+            _ = crate::idle::wait_until_idle(&idle_state) => {
+                let (daemon_state, state_path) = idle_save;
+                if let Err(e) = daemon_state.lock().await.save(&state_path) {
+                    tracing::error!("Failed to save state on idle shutdown: {}", e);
+                }
+            }
+        }
+        let _ = stop_tx.send(true);
+    });
+    let stopped = |mut rx: tokio::sync::watch::Receiver<bool>| async move {
+        let _ = rx.wait_for(|stopped| *stopped).await;
+    };
+
+    let remote_server = remote.map(|remote_listener| {
+        let app = app.clone();
+        let shutdown = stopped(stop_rx.clone());
+        tokio::spawn(crate::remote::serve(remote_listener, app, shutdown))
+    });
 
     // Serve with graceful shutdown.
     axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal)
+        .with_graceful_shutdown(stopped(stop_rx))
         .await
         .map_err(ServerError::ServeFailed)?;
 
+    if let Some(remote_server) = remote_server {
+        match remote_server.await {
+            Ok(result) => result.map_err(ServerError::ServeFailed)?,
+            Err(e) => tracing::error!("TCP listener task failed: {}", e),
+        }
+    }
+
+    presence_monitor.abort();
+    auto_milestones.abort();
+    if let Some(config_changes) = config_changes {
+        config_changes.abort();
+    }
+    retention.abort();
+    scheduler.abort();
+    watchdog.abort();
+    cli_sync.abort();
+    if let Some(restart_signal) = restart_signal {
+        restart_signal.abort();
+    }
+
     // Clean up socket file after shutdown.
     if socket_path.exists() {
         let _ = std::fs::remove_file(&socket_path);
@@ -195,18 +491,37 @@ pub async fn start_server(
 }
 
 /// Create the shared app state with session and process managers.
-fn create_app_state(daemon_state: Arc<Mutex<DaemonState>>, state_path: PathBuf) -> AppState {
+fn create_app_state(
+    daemon_state: Arc<Mutex<DaemonState>>,
+    state_path: PathBuf,
+    settings: SharedSettings,
+    conversations_dir: Option<PathBuf>,
+) -> AppState {
     let process_manager = new_shared_process_manager();
+    let activity_feed = Arc::new(ActivityFeed::default());
     let session_manager = Arc::new(
         SessionManager::new(daemon_state.clone(), process_manager)
-            .with_state_path(state_path.clone()),
+            .with_state_path(state_path.clone())
+            .with_activity_feed(activity_feed.clone())
+            .with_settings(settings.clone()),
     );
 
     // Create conversation manager with storage in ~/.mado/conversations/.
-    let storage_dir = dirs::home_dir()
-        .map(|h| h.join(".mado").join("conversations"))
-        .unwrap_or_else(|| std::path::PathBuf::from("/tmp/mado/conversations"));
-    let conversation_manager = Arc::new(ConversationManager::new(storage_dir, daemon_state, state_path));
+    let storage_dir = conversations_dir.unwrap_or_else(|| {
+        dirs::home_dir()
+            .map(|h| h.join(".mado").join("conversations"))
+            .unwrap_or_else(|| std::path::PathBuf::from("/tmp/mado/conversations"))
+    });
+    let ledger = Arc::new(Ledger::load(state_path.with_file_name("usage.json")));
+    let scheduler = Arc::new(Scheduler::load(state_path.with_file_name("schedules.json")));
+    let templates = Arc::new(Templates::new(state_path.with_file_name("templates")));
+    let prompts = Arc::new(Prompts::new(state_path.with_file_name("prompts")));
+    let conversation_manager = Arc::new(
+        ConversationManager::new(storage_dir, daemon_state, state_path)
+            .with_activity_feed(activity_feed.clone())
+            .with_settings(settings.clone())
+            .with_ledger(ledger),
+    );
 
     AppState {
         start_time: Instant::now(),
@@ -214,6 +529,19 @@ fn create_app_state(daemon_state: Arc<Mutex<DaemonState>>, state_path: PathBuf)
         session_manager,
         conversation_manager,
         workspace_locks: WorkspaceLocks::default(),
+        activity_feed,
+        metrics: Arc::new(Metrics::default()),
+        restart: None,
+        settings,
+        log_dir: None,
+        crash_dir: None,
+        archive_dir: None,
+        idle: Arc::new(IdleTracker::default()),
+        scheduler,
+        templates,
+        prompts,
+        runs: Arc::new(Runs::default()),
+        comparisons: Arc::new(Comparisons::default()),
     }
 }
 
@@ -223,26 +551,138 @@ fn create_router(state: AppState) -> Router {
         // Health & liveness.
         .route("/health", get(health_handler))
         .route("/ping", get(ping_handler))
+        .route("/version", get(version_handler))
+        .route("/cli/info", get(cli_info_handler))
+        .route("/cli/sessions", get(cli_sessions_handler))
+        .route("/history/sources", get(history_sources_handler))
+        .route("/events", get(activity_feed_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/logs", get(logs_handler))
+        .route("/crashes", get(list_crashes_handler))
+        .route("/crashes/{id}", get(get_crash_handler).delete(delete_crash_handler))
+        .route("/retention", get(retention_plan_handler))
+        .route("/usage", get(usage_report_handler))
+        .route("/openapi.json", get(crate::openapi::openapi_handler))
+        .route("/admin/restart", post(admin_restart_handler))
+        .route("/admin/purge", post(admin_purge_handler))
         // Session CRUD.
         .route("/sessions", get(list_sessions_handler).post(create_session_handler))
-        .route("/sessions/{id}", get(get_session_handler).delete(destroy_session_handler))
+        .route(
+            "/sessions/{id}",
+            get(get_session_handler)
+                .patch(update_session_handler)
+                .delete(destroy_session_handler),
+        )
+        .route("/sessions/{id}/revive", post(revive_session_handler))
+        .route("/sessions/{id}/restart", post(restart_session_handler))
+        .route("/sessions/{id}/fork", post(fork_session_handler))
+        .route("/sessions/{id}/clone", post(clone_session_handler))
+        .route("/sessions/{id}/archive", post(archive_session_handler))
+        .route(
+            "/sessions/{id}/settings",
+            get(get_session_settings_handler).put(update_session_settings_handler),
+        )
+        .route("/sessions/{id}/config", get(effective_config_handler))
         // Session I/O (PTY mode -- legacy).
-        .route("/sessions/{id}/input", post(input_handler))
+        .route(
+            "/sessions/{id}/input",
+            post(input_handler).layer(DefaultBodyLimit::max(MAX_INPUT_BYTES.div_ceil(3) * 4 + 1024)),
+        )
+        .route(
+            "/sessions/{id}/input/raw",
+            post(raw_input_handler).layer(DefaultBodyLimit::max(MAX_INPUT_BYTES)),
+        )
         .route("/sessions/{id}/resize", post(resize_handler))
         .route("/sessions/{id}/output", get(output_handler))
+        .route("/sessions/{id}/scrollback", get(scrollback_handler))
+        .route("/sessions/{id}/recording", get(recording_handler))
+        .route("/sessions/{id}/pty/stream", get(pty_stream_handler))
+        .route("/sessions/{id}/ws", get(crate::ws::ws_handler))
         // Chat mode (new).
-        .route("/sessions/{id}/messages", get(get_messages_handler).post(send_message_handler))
+        .route(
+            "/sessions/{id}/messages",
+            get(get_messages_handler)
+                .post(send_message_handler)
+                .delete(clear_messages_handler),
+        )
         .route("/sessions/{id}/messages/current", axum::routing::delete(cancel_response_handler))
+        .route("/sessions/{id}/messages/current/input", post(response_input_handler))
+        .route("/sessions/{id}/messages/retry", post(retry_message_handler))
+        .route("/sessions/{id}/messages/compare", post(compare_handler))
+        .route("/sessions/{id}/comparisons", get(list_comparisons_handler))
+        .route("/sessions/{id}/comparisons/{comparison_id}/stream", get(comparison_stream_handler))
+        .route("/sessions/{id}/comparisons/{comparison_id}/apply", post(apply_comparison_handler))
+        .route("/sessions/{id}/compact", post(compact_handler))
+        .route("/sessions/{id}/memory", get(get_memory_handler).put(write_memory_handler))
+        .route("/sessions/{id}/memory/append", post(append_memory_handler))
+        .route(
+            "/sessions/{id}/memory/suggestions",
+            get(list_memory_suggestions_handler).post(suggest_memory_handler),
+        )
+        .route(
+            "/sessions/{id}/memory/suggestions/{suggestion_id}",
+            axum::routing::delete(dismiss_memory_suggestion_handler),
+        )
+        .route(
+            "/sessions/{id}/memory/suggestions/{suggestion_id}/accept",
+            post(accept_memory_suggestion_handler),
+        )
+        .route(
+            "/sessions/{id}/messages/{message_id}",
+            axum::routing::delete(delete_message_handler),
+        )
+        .route("/sessions/{id}/messages/{message_id}/edit", post(edit_message_handler))
+        .route("/sessions/{id}/queue/{message_id}", axum::routing::delete(cancel_queued_handler))
+        .route(
+            "/sessions/{id}/attachments",
+            post(upload_attachment_handler)
+                .layer(DefaultBodyLimit::max(crate::attachments::MAX_ATTACHMENT_BYTES)),
+        )
         .route("/sessions/{id}/stream", get(stream_events_handler))
         .route("/sessions/{id}/history", get(import_history_handler))
+        .route("/sessions/{id}/export", get(export_conversation_handler))
+        .route("/sessions/{id}/audit", get(audit_log_handler))
+        .route("/sessions/{id}/audit/export", get(export_audit_log_handler))
+        .route("/sessions/{id}/usage", get(session_usage_handler))
+        .route("/tasks", post(create_task_handler))
+        // Scheduled prompts.
+        .route("/schedules", get(list_schedules_handler).post(create_schedule_handler))
+        .route("/schedules/{id}", axum::routing::delete(delete_schedule_handler))
+        .route("/schedules/{id}/runs", get(schedule_runs_handler))
+        .route("/templates", get(list_templates_handler).post(save_template_handler))
+        .route("/templates/{name}", axum::routing::delete(delete_template_handler))
+        .route("/prompts", get(list_prompts_handler).post(save_prompt_handler))
+        .route("/prompts/{name}", axum::routing::delete(delete_prompt_handler))
+        .route("/prompts/{name}/render", post(render_prompt_handler))
+        .route("/workspaces/trust", post(trust_workspace_handler))
+        // Workspace files.
+        .route("/sessions/{id}/files", get(list_files_handler))
+        .route(
+            "/sessions/{id}/files/content",
+            get(read_file_handler)
+                .put(write_file_handler)
+                .layer(DefaultBodyLimit::max(crate::files::MAX_FILE_BYTES as usize * 2)),
+        )
+        .route("/sessions/{id}/search", get(search_handler))
         // Versioning.
         .route("/sessions/{id}/save", post(save_milestone_handler))
         .route("/sessions/{id}/milestones", get(list_milestones_handler))
         .route("/sessions/{id}/diff", get(diff_milestones_handler))
         .route("/sessions/{id}/restore", post(restore_milestone_handler))
+        .route("/sessions/{id}/snapshot", post(save_snapshot_handler))
+        .route("/sessions/{id}/snapshots", get(list_snapshots_handler))
+        .route("/sessions/{id}/snapshot/{snap_id}/restore", post(restore_snapshot_handler))
+        .route("/sessions/{id}/review", get(review_handler))
+        .route("/sessions/{id}/review/apply", post(apply_review_handler))
+        .route("/sessions/{id}/messages/{message_id}/revert-changes", post(revert_turn_changes_handler))
+        .route("/sessions/{id}/commands", get(project_commands_handler))
+        .route("/sessions/{id}/run", post(run_command_handler))
+        .route("/sessions/{id}/runs", get(list_runs_handler))
+        .route("/sessions/{id}/runs/{run_id}/stream", get(run_stream_handler))
         // Change indicators.
         .route("/sessions/{id}/changes", get(workspace_changes_handler))
         // Git staging operations.
+        .route("/sessions/{id}/git/init", post(git_init_handler))
         .route("/sessions/{id}/git/status", get(git_status_handler))
         .route("/sessions/{id}/git/diff", get(git_file_diff_handler))
         .route("/sessions/{id}/git/stage", post(git_stage_file_handler))
@@ -251,12 +691,32 @@ fn create_router(state: AppState) -> Router {
         .route("/sessions/{id}/git/unstage-files", post(git_unstage_files_handler))
         .route("/sessions/{id}/git/stage-hunk", post(git_stage_hunk_handler))
         .route("/sessions/{id}/git/branch-info", get(git_branch_info_handler))
+        .route("/sessions/{id}/git/repos", get(git_repos_handler))
         .route("/sessions/{id}/git/push", post(git_push_handler))
+        .route("/sessions/{id}/git/merge", post(merge_branch_handler))
+        .route("/sessions/{id}/git/pull-request", post(create_pull_request_handler))
+        .route("/sessions/{id}/git/pull-requests", get(list_pull_requests_handler))
+        .layer(axum::middleware::from_fn_with_state(
+            state.metrics.clone(),
+            crate::metrics::track_requests,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.idle.clone(),
+            crate::idle::track_requests,
+        ))
         .with_state(state)
 }
 
 // ── Health endpoints ──
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
 async fn health_handler(State(state): State<AppState>) -> Json<DaemonResponse> {
     let uptime = state.start_time.elapsed().as_secs();
     let sessions = state.session_manager.list_sessions().await;
@@ -265,16 +725,382 @@ async fn health_handler(State(state): State<AppState>) -> Json<DaemonResponse> {
         uptime,
         session_count: sessions.len(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: PROTOCOL_VERSION,
     };
     Json(DaemonResponse::Health { status })
 }
 
+/// Build and protocol version, checked by clients on connect.
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn version_handler() -> Json<DaemonResponse> {
+    Json(DaemonResponse::Version {
+        version: VersionInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        },
+    })
+}
+
+/// The Claude CLI sessions will run, with its version and login, and
+/// whether chat mode's stream-json output works. Takes a few seconds.
+#[utoipa::path(
+    get,
+    path = "/cli/info",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn cli_info_handler(State(state): State<AppState>) -> Json<DaemonResponse> {
+    let (cli, api_key) = {
+        let settings = state.settings.read().unwrap_or_else(|e| e.into_inner());
+        (
+            crate::claude_cli::ClaudeCli::new(settings.claude_path.clone(), &settings.default_flags, &[]),
+            settings.api_key.clone(),
+        )
+    };
+    let info = crate::claude_cli::info(&cli, api_key.as_deref()).await;
+    Json(DaemonResponse::CliInfo { info })
+}
+
+/// Claude CLI sessions recorded for a working directory, newest first, with
+/// the start of each one's first prompt. Import one into a session with
+/// `GET /sessions/{id}/history?target_session_id=`.
+#[utoipa::path(
+    get,
+    path = "/cli/sessions",
+    params(CliSessionsQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn cli_sessions_handler(
+    axum::extract::Query(params): axum::extract::Query<CliSessionsQuery>,
+) -> ApiResult {
+    let sessions = tokio::task::spawn_blocking(move || {
+        crate::claude_history::list_session_summaries(Path::new(&params.cwd), params.limit)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::CliSessions { sessions }))
+}
+
+/// Coding agents (Claude CLI, aider, Codex CLI, Gemini CLI) with chat history
+/// for a working directory. Import one into a session with
+/// `GET /sessions/{id}/history?source=`.
+#[utoipa::path(
+    get,
+    path = "/history/sources",
+    params(HistorySourcesQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn history_sources_handler(
+    axum::extract::Query(params): axum::extract::Query<HistorySourcesQuery>,
+) -> ApiResult {
+    let sources = tokio::task::spawn_blocking(move || crate::history_import::detect(Path::new(&params.cwd)))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::HistorySources { sources }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ping",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
 async fn ping_handler() -> Json<DaemonResponse> {
     Json(DaemonResponse::Pong)
 }
 
+/// Restart onto a fresh daemon process, keeping live PTY sessions (see
+/// `crate::handover`). The old process exits once the successor is serving.
+#[utoipa::path(
+    post,
+    path = "/admin/restart",
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 409, description = "Restart disabled, in progress, or a response is streaming", body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn admin_restart_handler(State(state): State<AppState>) -> ApiResult {
+    let restarter = state
+        .restart
+        .clone()
+        .ok_or_else(|| ApiError::Conflict("Graceful restart is not enabled".to_string()))?;
+
+    let pid = restarter.restart(&state).await?;
+    Ok(Json(DaemonResponse::Restarting { pid }))
+}
+
+/// Delete daemon data: stop and remove what `scope` covers, then delete its
+/// files (see `crate::purge`). Used when the user deletes all their data.
+#[utoipa::path(
+    post,
+    path = "/admin/purge",
+    params(PurgeQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 500, description = "A file couldn't be deleted", body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn admin_purge_handler(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<PurgeQuery>,
+) -> ApiResult {
+    let report = crate::purge::purge(&state, params.scope)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Purge failed: {}", e)))?;
+    Ok(Json(DaemonResponse::Purged { report }))
+}
+
+/// Tail the daemon's current log file.
+#[utoipa::path(
+    get,
+    path = "/logs",
+    params(LogsQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Daemon is not logging to a file", body = DaemonResponse),
+        (status = 422, description = "Unknown level", body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn logs_handler(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<LogsQuery>,
+) -> ApiResult {
+    let dir = state
+        .log_dir
+        .clone()
+        .ok_or_else(|| ApiError::NotFound("Daemon is not logging to a file".to_string()))?;
+    let level = params
+        .level
+        .map(|level| {
+            level
+                .parse::<tracing::Level>()
+                .map_err(|_| ApiError::Validation(format!("Unknown log level: {}", level)))
+        })
+        .transpose()?;
+    let count = params.lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+
+    let lines = tokio::task::spawn_blocking(move || crate::logs::recent(&dir, count, level))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(format!("Failed to read logs: {}", e)))?;
+    Ok(Json(DaemonResponse::Logs { lines }))
+}
+
+/// Crash reports the daemon wrote after panics, newest first.
+#[utoipa::path(
+    get,
+    path = "/crashes",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn list_crashes_handler(State(state): State<AppState>) -> ApiResult {
+    let Some(dir) = state.crash_dir.clone() else {
+        return Ok(Json(DaemonResponse::CrashReports { reports: Vec::new() }));
+    };
+    let reports = tokio::task::spawn_blocking(move || crate::crash::list(&dir))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(format!("Failed to read crash reports: {}", e)))?;
+    Ok(Json(DaemonResponse::CrashReports { reports }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/crashes/{id}",
+    params(("id" = String, Path, description = "Crash report id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Crash report not found", body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn get_crash_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let not_found = || ApiError::NotFound(format!("Crash report not found: {}", id));
+    let dir = state.crash_dir.clone().ok_or_else(not_found)?;
+    let report = crate::crash::load(&dir, &id)
+        .map_err(|e| ApiError::Internal(format!("Failed to read crash report: {}", e)))?
+        .ok_or_else(not_found)?;
+    Ok(Json(DaemonResponse::CrashReport { report }))
+}
+
+/// Delete a crash report once it has been seen.
+#[utoipa::path(
+    delete,
+    path = "/crashes/{id}",
+    params(("id" = String, Path, description = "Crash report id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Crash report not found", body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn delete_crash_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let not_found = || ApiError::NotFound(format!("Crash report not found: {}", id));
+    let dir = state.crash_dir.clone().ok_or_else(not_found)?;
+    let deleted = crate::crash::delete(&dir, &id)
+        .map_err(|e| ApiError::Internal(format!("Failed to delete crash report: {}", e)))?;
+    if !deleted {
+        return Err(not_found());
+    }
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Dead sessions the retention policy would remove on its next run.
+#[utoipa::path(
+    get,
+    path = "/retention",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn retention_plan_handler(State(state): State<AppState>) -> ApiResult {
+    let candidates = crate::retention::current_plan(&state).await;
+    Ok(Json(DaemonResponse::RetentionPlan { candidates }))
+}
+
+/// Daemon-wide token usage and cost per day and project, from the usage
+/// ledger (see `crate::ledger`).
+#[utoipa::path(
+    get,
+    path = "/usage",
+    params(UsageQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "daemon"
+)]
+async fn usage_report_handler(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<UsageQuery>,
+) -> ApiResult {
+    let report = state.conversation_manager.ledger().report(params.since);
+    Ok(Json(DaemonResponse::Usage { report }))
+}
+
+/// Prometheus text-format metrics (see `crate::metrics`).
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text exposition", content_type = "text/plain", body = String),
+    ),
+    tag = "daemon"
+)]
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let mut out = MetricsWriter::default();
+
+    out.gauge(
+        "mado_uptime_seconds",
+        "Seconds since the daemon started.",
+        state.start_time.elapsed().as_secs_f64(),
+    );
+    out.gauge(
+        "mado_sessions",
+        "Sessions known to the daemon.",
+        state.session_manager.list_sessions().await.len() as f64,
+    );
+    out.gauge(
+        "mado_active_processes",
+        "Sessions with a live PTY process.",
+        state.session_manager.process_count().await as f64,
+    );
+
+    let usage = state.conversation_manager.usage_by_session().await;
+    let per_session = |value: fn(&TokenUsage, f64) -> f64| {
+        usage
+            .iter()
+            .map(|(id, usage, cost)| (vec![id.clone()], value(usage, *cost)))
+            .collect::<Vec<_>>()
+    };
+    out.family(
+        "mado_session_input_tokens_total",
+        "Input tokens used by a session's chat.",
+        "counter",
+        &["session_id"],
+        &per_session(|usage, _| usage.input_tokens as f64),
+    );
+    out.family(
+        "mado_session_output_tokens_total",
+        "Output tokens used by a session's chat.",
+        "counter",
+        &["session_id"],
+        &per_session(|usage, _| usage.output_tokens as f64),
+    );
+    out.family(
+        "mado_session_cost_usd_total",
+        "Cost in USD of a session's chat.",
+        "counter",
+        &["session_id"],
+        &per_session(|_, cost| cost),
+    );
+
+    let mut subscribers: Vec<_> = state
+        .conversation_manager
+        .subscriber_counts()
+        .await
+        .into_iter()
+        .map(|(id, count)| (vec!["chat".to_string(), id], count as f64))
+        .collect();
+    subscribers.push((
+        vec!["activity".to_string(), String::new()],
+        state.activity_feed.receiver_count() as f64,
+    ));
+    out.family(
+        "mado_stream_subscribers",
+        "Live subscribers to event streams (SSE or WebSocket).",
+        "gauge",
+        &["stream", "session_id"],
+        &subscribers,
+    );
+
+    state.metrics.encode(&mut out);
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        out.finish(),
+    )
+}
+
 // ── Session CRUD endpoints ──
 
+/// All sessions, pinned ones first, then oldest first.
+#[utoipa::path(
+    get,
+    path = "/sessions",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
 async fn list_sessions_handler(
     State(state): State<AppState>,
 ) -> Json<DaemonResponse> {
@@ -282,247 +1108,2372 @@ async fn list_sessions_handler(
     Json(DaemonResponse::Sessions { sessions })
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions",
+    request_body = CreateSessionBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Template not found", body = DaemonResponse),
+        (status = 409, description = "The sandbox isn't available", body = DaemonResponse),
+        (status = 422, description = "Invalid model, command, environment or sandbox hosts", body = DaemonResponse),
+        (status = 500, description = "Failed to spawn the session", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
 async fn create_session_handler(
     State(state): State<AppState>,
-    Json(body): Json<CreateSessionBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(mut body): ApiJson<CreateSessionBody>,
+) -> ApiResult {
+    if let Some(name) = body.template.take() {
+        crate::templates::apply(state.templates.get(&name)?, &mut body);
+    }
+    ensure_trusted(&state, body.cwd.as_deref()).await?;
     let pty_size = PtySize {
         rows: body.rows.unwrap_or(24),
         cols: body.cols.unwrap_or(80),
     };
+    let project = crate::project_config::for_dir(body.cwd.as_deref());
+    let model = body.model.or(project.model).unwrap_or_else(|| {
+        state.settings.read().unwrap_or_else(|e| e.into_inner()).default_model.clone()
+    });
+    let custom_command = match body.command {
+        Some(program) => Some(CustomCommand {
+            program,
+            args: body.args,
+        }),
+        None if !body.args.is_empty() => {
+            return Err(ApiError::Validation("args given without a command".to_string()));
+        }
+        None => None,
+    };
+    let sandbox = body.sandbox.unwrap_or_default();
+    crate::sandbox::check_hosts(&sandbox.allowed_hosts).map_err(ApiError::Validation)?;
 
-    match state
+    let session = state
         .session_manager
-        .create_session(body.name, body.model, pty_size, body.cwd)
-        .await
-    {
-        Ok(session) => Json(DaemonResponse::SessionCreated { session }),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        .create_session(
+            body.name,
+            model,
+            pty_size,
+            body.cwd,
+            body.system_prompt,
+            body.permission_mode.unwrap_or_default(),
+            false,
+            sandbox,
+            custom_command,
+            body.mcp_config,
+            body.env,
+        )
+        .await?;
+    let session = match body.auto_milestone {
+        Some(auto_milestone) => {
+            let update = SessionUpdate {
+                auto_milestone: Some(auto_milestone),
+                ..Default::default()
+            };
+            state.session_manager.update_session(&session.id, update).await?
+        }
+        None => session,
+    };
+    let session = start_session_branch(&state, session).await;
+    Ok(Json(DaemonResponse::SessionCreated { session }))
+}
+
+/// Create and check out the session's own branch when `branches.per_session`
+/// is set and it works in a repository. The session goes on without one if
+/// that fails, e.g. before the first commit, or if another running session
+/// in the repository has a branch: checking out a second one in the shared
+/// working tree would move the first session off its own.
+async fn start_session_branch(state: &AppState, session: Session) -> Session {
+    let branches = state.settings.read().unwrap_or_else(|e| e.into_inner()).branches.clone();
+    let Some(repo_root) = session.repo_root.clone().filter(|_| branches.per_session) else {
+        return session;
+    };
+    let path = std::path::Path::new(&repo_root);
+    let _lock = state.workspace_locks.acquire(path).await;
+    let holder = state.session_manager.list_sessions().await.into_iter().find(|other| {
+        other.id != session.id
+            && other.status != SessionStatus::Terminated
+            && other.repo_root.as_deref() == Some(repo_root.as_str())
+            && other.branch.is_some()
+    });
+    if let Some(holder) = holder {
+        tracing::warn!(
+            "No branch for session {}: session {} has one checked out in {}",
+            session.id,
+            holder.id,
+            repo_root
+        );
+        return session;
+    }
+    let created = state.metrics.time_git("create_session_branch", || {
+        crate::git_ops::create_session_branch(path, &branches.prefix, &session.name)
+    });
+    match created {
+        Ok(branch) => state.session_manager.set_branch(&session.id, Some(branch)).await.unwrap_or(session),
+        Err(e) => {
+            tracing::warn!("No branch for session {}: {}", session.id, e);
+            session
+        }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
 async fn get_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = SessionId::new(id);
     match state.session_manager.get_session(&session_id).await {
-        Some(session) => Json(DaemonResponse::Sessions {
+        Some(session) => Ok(Json(DaemonResponse::Sessions {
             sessions: vec![session],
-        }),
-        None => Json(DaemonResponse::Error {
-            message: format!("Session not found: {}", session_id),
-        }),
+        })),
+        None => Err(ApiError::SessionNotFound(session_id.to_string())),
     }
 }
 
+/// Change a session's name, model, working directory, system prompt,
+/// permission mode, read-only mode, or metadata (tags, color, pinned,
+/// notes). A chat picks the changes up from its next message.
+#[utoipa::path(
+    patch,
+    path = "/sessions/{id}",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SessionUpdate,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The new working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Empty name or model, working_dir is not a directory, invalid color, read-only outside a repository, or invalid sandbox hosts", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn update_session_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(update): ApiJson<SessionUpdate>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_trusted(&state, update.working_dir.as_deref()).await?;
+    let session = state.session_manager.update_session(&session_id, update).await?;
+    state.conversation_manager.sync_session(&session).await;
+    Ok(Json(DaemonResponse::SessionUpdated { session }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
 async fn destroy_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = SessionId::new(id);
     match state.session_manager.destroy_session(&session_id).await {
-        Ok(()) => Json(DaemonResponse::Pong), // Simple ACK
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Ok(()) => Ok(Json(DaemonResponse::Pong)), // Simple ACK
+        Err(e) => Err(e.into()),
     }
 }
 
-// ── Session I/O endpoints ──
-
-async fn input_handler(
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/revive",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ReviveSessionBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "The session's process is still running", body = DaemonResponse),
+        (status = 500, description = "Failed to spawn the process", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn revive_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<InputBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<ReviveSessionBody>,
+) -> ApiResult {
     let session_id = SessionId::new(id);
-
-    let data = match base64::engine::general_purpose::STANDARD.decode(&body.data) {
-        Ok(d) => d,
-        Err(e) => {
-            return Json(DaemonResponse::Error {
-                message: format!("Invalid base64 input: {}", e),
-            });
-        }
+    ensure_session_trusted(&state, &session_id).await?;
+    let pty_size = PtySize {
+        rows: body.rows.unwrap_or(24),
+        cols: body.cols.unwrap_or(80),
     };
-
-    match state.session_manager.write_input(&session_id, &data).await {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
-    }
+    let session = state.session_manager.revive_session(&session_id, pty_size).await?;
+    Ok(Json(DaemonResponse::SessionRevived { session }))
 }
 
-async fn resize_handler(
+/// Kill the session's process, if still running, and spawn a fresh one that
+/// resumes its Claude conversation.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/restart",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ReviveSessionBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 500, description = "Failed to spawn the process", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn restart_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<ResizeBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<ReviveSessionBody>,
+) -> ApiResult {
     let session_id = SessionId::new(id);
+    ensure_session_trusted(&state, &session_id).await?;
+    let pty_size = PtySize {
+        rows: body.rows.unwrap_or(24),
+        cols: body.cols.unwrap_or(80),
+    };
+    let session = state.session_manager.restart_session(&session_id, pty_size).await?;
+    Ok(Json(DaemonResponse::SessionRevived { session }))
+}
 
-    match state
+/// Start a new session in the same working directory that continues the
+/// conversation from `at_message_id`, leaving the original thread as it is.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/fork",
+    params(("id" = String, Path, description = "Session id"), ForkSessionQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session or message not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress", body = DaemonResponse),
+        (status = 500, description = "Failed to spawn the session", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn fork_session_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<ForkSessionQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_session_trusted(&state, &session_id).await?;
+    ensure_conversation(&state, &session_id).await?;
+    let source = state
         .session_manager
-        .resize_session(&session_id, body.rows, body.cols)
+        .get_session(&session_id)
         .await
-    {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
-    }
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let conversation = state
+        .conversation_manager
+        .fork(&session_id, params.at_message_id.as_deref())
+        .await?;
+
+    let session = state
+        .session_manager
+        .create_session(
+            format!("{} (fork)", source.name),
+            source.model,
+            PtySize { rows: 24, cols: 80 },
+            source.working_dir,
+            source.system_prompt,
+            source.permission_mode,
+            source.read_only,
+            source.sandbox,
+            source.custom_command,
+            source.mcp_config,
+            source.env,
+        )
+        .await?;
+    let update = SessionUpdate {
+        extended_thinking: Some(source.extended_thinking),
+        auto_milestone: source.auto_milestone,
+        claude_flags: Some(source.claude_flags),
+        ..Default::default()
+    };
+    let session = state.session_manager.update_session(&session.id, update).await?;
+    state.conversation_manager.insert_session(&session.id, conversation).await;
+    Ok(Json(DaemonResponse::SessionCreated { session }))
 }
 
-async fn output_handler(
+/// Copy or git-clone a session's workspace into `target_dir` and start a
+/// session there with the same settings, to try something out without
+/// touching the original. With `fork_conversation` the new session carries
+/// on the conversation, replayed since Claude sessions belong to one
+/// directory. Progress is published on `/events` as `clone_progress` events
+/// of the original session.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/clone",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = CloneSessionBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "Workspace not trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Target not empty, a response is in progress, or git mode outside a repository", body = DaemonResponse),
+        (status = 422, description = "No working directory, or the target is inside it", body = DaemonResponse),
+        (status = 500, description = "Failed to copy the workspace or spawn the session", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn clone_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>> {
+    ApiJson(body): ApiJson<CloneSessionBody>,
+) -> ApiResult {
     let session_id = SessionId::new(id);
-
-    // Try to subscribe to the session's output.
-    let rx = state
+    let source = state
         .session_manager
-        .subscribe_output(&session_id)
-        .await;
-
-    match rx {
-        Ok(rx) => {
-            let stream = BroadcastStream::new(rx).filter_map(|result| match result {
-                Ok(bytes) => {
-                    let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
-                    Some(Ok(Event::default().data(encoded).event("output")))
-                }
-                Err(_) => None, // Lagged receiver, skip
-            });
-
-            // Prepend a "started" event.
-            let started = futures::stream::once(async {
-                Ok(Event::default().data("connected").event("started"))
-            });
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let working_dir = source
+        .working_dir
+        .clone()
+        .ok_or_else(|| ApiError::Validation("Session has no working directory to clone".to_string()))?;
+    ensure_trusted(&state, Some(&working_dir)).await?;
+    let conversation = if body.fork_conversation {
+        ensure_conversation(&state, &session_id).await?;
+        Some(state.conversation_manager.fork(&session_id, None).await?)
+    } else {
+        None
+    };
 
-            Sse::new(Box::pin(started.chain(stream)))
-        }
-        Err(_e) => {
-            // Session not found -- return a stream with just an error event.
-            let error_stream = futures::stream::once(async {
-                Ok(Event::default().data("session_not_found").event("error"))
-            });
-            Sse::new(Box::pin(error_stream))
-        }
+    let feed = state.activity_feed.clone();
+    let progress_id = session_id.clone();
+    let source_dir = PathBuf::from(&working_dir);
+    let target = PathBuf::from(&body.target_dir);
+    let new_dir = tokio::task::spawn_blocking(move || {
+        crate::workspace_clone::clone_workspace(&source_dir, &target, body.mode, &mut |done, total| {
+            feed.publish(&progress_id, ActivityKind::CloneProgress { done, total })
+        })
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Clone task failed: {}", e)))??
+    .display()
+    .to_string();
+    state.session_manager.trust_workspace(&new_dir).await?;
+
+    let session = state
+        .session_manager
+        .create_session(
+            body.name.unwrap_or_else(|| format!("{} (clone)", source.name)),
+            source.model,
+            PtySize { rows: 24, cols: 80 },
+            Some(new_dir.clone()),
+            source.system_prompt,
+            source.permission_mode,
+            false,
+            source.sandbox,
+            source.custom_command,
+            source.mcp_config,
+            source.env,
+        )
+        .await?;
+    let update = SessionUpdate {
+        extended_thinking: Some(source.extended_thinking),
+        auto_milestone: source.auto_milestone,
+        claude_flags: Some(source.claude_flags),
+        ..Default::default()
+    };
+    let session = state.session_manager.update_session(&session.id, update).await?;
+    if let Some(mut conversation) = conversation {
+        conversation.working_dir = Some(new_dir);
+        conversation.claude_session_id = None;
+        conversation.fork_session = false;
+        conversation.context_tokens = 0;
+        conversation.replay_history = !conversation.messages.is_empty();
+        state.conversation_manager.insert_session(&session.id, conversation).await;
     }
+    Ok(Json(DaemonResponse::SessionCreated { session }))
 }
 
-// ── Chat mode endpoints ──
-
-async fn send_message_handler(
+/// Archive a session's conversation and milestones, then remove it like
+/// `DELETE /sessions/{id}`.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/archive",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found, or the daemon has no archive directory", body = DaemonResponse),
+        (status = 500, description = "Failed to write the archive", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn archive_session_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<SendMessageBody>,
-) -> Json<DaemonResponse> {
-    let session_id = SessionId::new(id.clone());
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let dir = state
+        .archive_dir
+        .clone()
+        .ok_or_else(|| ApiError::NotFound("Daemon has no archive directory".to_string()))?;
 
-    // Ensure conversation is initialized for this session.
-    let session = state.session_manager.get_session(&session_id).await;
-    if let Some(ref s) = session {
-        // Pass the stored claude_session_id so conversations can be resumed.
-        state
-            .conversation_manager
-            .init_session(&session_id, &s.model, s.working_dir.clone(), s.claude_session_id.clone())
-            .await;
-    } else {
-        return Json(DaemonResponse::Error {
-            message: format!("Session not found: {}", id),
-        });
+    let path = crate::retention::archive(&state, &dir, &session)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to archive session: {}", e)))?;
+    crate::retention::remove(&state, &session_id).await?;
+    Ok(Json(DaemonResponse::SessionArchived {
+        path: path.display().to_string(),
+    }))
+}
+
+fn session_settings(session: &Session) -> SessionSettings {
+    SessionSettings {
+        system_prompt: session.system_prompt.clone(),
+        env: session.env.clone(),
     }
+}
 
-    match state
-        .conversation_manager
-        .send_message(&session_id, body.content, body.model)
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/settings",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn get_session_settings_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
         .await
-    {
-        Ok(message_id) => Json(DaemonResponse::MessageAccepted { message_id }),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
-    }
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    Ok(Json(DaemonResponse::SessionSettings {
+        settings: session_settings(&session),
+    }))
 }
 
-async fn get_messages_handler(
+/// The settings that apply to a session, from config.json, the project's
+/// `.mado.toml` and the session itself, with where each comes from.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/config",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn effective_config_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    axum::extract::Query(params): axum::extract::Query<GetMessagesQuery>,
-) -> Json<DaemonResponse> {
-    let session_id = SessionId::new(id.clone());
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let settings = state.settings.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let config = tokio::task::spawn_blocking(move || crate::project_config::effective(&session, &settings))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::EffectiveConfig { config }))
+}
 
-    // Ensure conversation is initialized for this session.
-    let session = state.session_manager.get_session(&session_id).await;
-    if let Some(ref s) = session {
-        // Pass the stored claude_session_id so conversations can be resumed.
-        state
-            .conversation_manager
-            .init_session(&session_id, &s.model, s.working_dir.clone(), s.claude_session_id.clone())
-            .await;
+/// Replace a session's settings. A chat in progress picks them up from its
+/// next message.
+#[utoipa::path(
+    put,
+    path = "/sessions/{id}/settings",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SessionSettings,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn update_session_settings_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(mut settings): ApiJson<SessionSettings>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    settings.system_prompt = settings.system_prompt.filter(|p| !p.trim().is_empty());
+    let session = state.session_manager.update_settings(&session_id, settings).await?;
+    state.conversation_manager.sync_session(&session).await;
+    Ok(Json(DaemonResponse::SessionSettings {
+        settings: session_settings(&session),
+    }))
+}
+
+// ── Session I/O endpoints ──
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/input",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = InputBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Invalid request", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn input_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<InputBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let engine = &base64::engine::general_purpose::STANDARD;
+
+    let decoded_len = base64::decoded_len_estimate(body.data.len());
+    if decoded_len > MAX_INPUT_BYTES {
+        return Err(ApiError::Validation(format!(
+            "Input too large: {} bytes (max {})",
+            decoded_len, MAX_INPUT_BYTES
+        )));
+    }
+
+    // Small writes (keystrokes) decode in one go, as do pastes, which must
+    // be bracketed as a whole.
+    if body.data.len() <= INPUT_DECODE_CHUNK || body.paste {
+        let data = engine
+            .decode(&body.data)
+            .map_err(|e| ApiError::Validation(format!("Invalid base64 input: {}", e)))?;
+
+        state.session_manager.write_input(&session_id, &data, body.paste).await?;
+        return Ok(Json(DaemonResponse::Pong));
+    }
+
+    // Other large writes decode chunk by chunk into a reused buffer.
+    let mut buf = vec![0u8; INPUT_DECODE_CHUNK / 4 * 3];
+    let mut written = 0usize;
+    for chunk in body.data.as_bytes().chunks(INPUT_DECODE_CHUNK) {
+        let n = engine.decode_slice(chunk, &mut buf).map_err(|e| {
+            ApiError::Validation(format!(
+                "Invalid base64 input after {} bytes written: {}",
+                written, e
+            ))
+        })?;
+
+        state.session_manager.write_input(&session_id, &buf[..n], false).await?;
+        written += n;
+    }
+
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Binary input path: the request body is the raw bytes to write to the PTY.
+///
+/// Avoids the base64 round trip for per-keystroke writes from the desktop app.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/input/raw",
+    params(("id" = String, Path, description = "Session id"), RawInputQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Invalid request", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn raw_input_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<RawInputQuery>,
+    body: Result<Bytes, BytesRejection>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+
+    let data = body.map_err(|e| ApiError::Validation(format!("Invalid input body: {}", e)))?;
+
+    state.session_manager.write_input(&session_id, &data, params.paste).await?;
+    Ok(Json(DaemonResponse::Pong))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/resize",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ResizeBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn resize_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<ResizeBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+
+    match state
+        .session_manager
+        .resize_session(&session_id, body.rows, body.cols)
+        .await
+    {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Recent PTY output, for clients that draw a terminal once rather than
+/// follow `/sessions/{id}/pty/stream` (which replays the same bytes first).
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/scrollback",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found or has no process", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn scrollback_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let (data, offset) = state.session_manager.scrollback(&SessionId::new(id)).await?;
+    Ok(Json(DaemonResponse::Scrollback {
+        data: base64::engine::general_purpose::STANDARD.encode(data),
+        offset,
+    }))
+}
+
+/// Download a session's terminal recording in asciicast v2 format. Sessions
+/// are only recorded with `recording.enabled` set in config.json.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/recording",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "The recording", content_type = "application/x-asciicast", body = String),
+        (status = 404, description = "Session not found or not recorded", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn recording_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> Result<Response, ApiError> {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return Err(ApiError::SessionNotFound(session_id.to_string()));
+    }
+    let not_recorded = || ApiError::NotFound(format!("No recording for session {}", session_id));
+    let path = state.session_manager.recording_path(&session_id).ok_or_else(not_recorded)?;
+    let recording = match tokio::fs::read(&path).await {
+        Ok(recording) => recording,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(not_recorded()),
+        Err(e) => return Err(ApiError::Internal(format!("Failed to read recording: {}", e))),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-asciicast".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.cast\"", session_id),
+            ),
+        ],
+        recording,
+    )
+        .into_response())
+}
+
+/// Legacy PTY output stream (base64 SSE `output` events).
+///
+/// Deprecated in favor of `/sessions/{id}/pty/stream`, which adds scrollback
+/// replay, exit events, and binary frames. Kept as a shim for older frontends;
+/// responses carry `Deprecation` and `Link: rel="successor-version"` headers.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/output",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Base64 `output` events", content_type = "text/event-stream", body = String),
+    ),
+    tag = "pty"
+)]
+async fn output_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let successor = format!("</sessions/{}/pty/stream>; rel=\"successor-version\"", id);
+    let deprecation = [
+        (header::HeaderName::from_static("deprecation"), HeaderValue::from_static("true")),
+        (
+            header::LINK,
+            HeaderValue::from_str(&successor).unwrap_or(HeaderValue::from_static("")),
+        ),
+    ];
+    let resume_from = last_event_id(&headers);
+    (deprecation, legacy_output_stream(state, id, resume_from).await)
+}
+
+/// Parse the SSE `Last-Event-ID` header sent by reconnecting clients.
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Legacy output stream. Event ids are PTY stream offsets, so a client
+/// reconnecting with `Last-Event-ID` gets the output it missed first.
+async fn legacy_output_stream(
+    state: AppState,
+    id: String,
+    resume_from: Option<u64>,
+) -> Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>> {
+    let session_id = SessionId::new(id);
+
+    // Try to subscribe to the session's output.
+    let subscription = state
+        .session_manager
+        .subscribe_pty(&session_id, resume_from)
+        .await;
+
+    match subscription {
+        Ok(subscription) => {
+            let output_event = |data: &[u8], offset: u64| {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+                Event::default().data(encoded).event("output").id(offset.to_string())
+            };
+
+            // This stream never sent scrollback to fresh clients; only replay on resume.
+            let missed = (resume_from.is_some() && !subscription.scrollback.is_empty())
+                .then(|| Ok(output_event(&subscription.scrollback, subscription.offset)));
+
+            let stream = BroadcastStream::new(subscription.output).filter_map(move |result| match result {
+                Ok(chunk) => Some(Ok(output_event(&chunk.data, chunk.offset))),
+                Err(_) => None, // Lagged receiver, skip
+            });
+            let stream = futures::stream::iter(missed).chain(stream);
+
+            // Prepend a "started" event.
+            let started = futures::stream::once(async {
+                Ok(Event::default().data("connected").event("started"))
+            });
+
+            Sse::new(Box::pin(started.chain(stream)))
+        }
+        Err(_e) => {
+            // Session not found -- return a stream with just an error event.
+            let error_stream = futures::stream::once(async {
+                Ok(Event::default().data("session_not_found").event("error"))
+            });
+            Sse::new(Box::pin(error_stream))
+        }
+    }
+}
+
+/// One unit of the v1 PTY stream, before wire encoding.
+///
+/// `offset` is the PTY stream offset at the end of the chunk.
+pub(crate) enum PtyChunk {
+    Scrollback { data: Vec<u8>, offset: u64 },
+    Output { data: Vec<u8>, offset: u64 },
+    Exit { code: Option<i32> },
+}
+
+impl PtyChunk {
+    /// Encode as a binary PTY frame.
+    pub(crate) fn into_frame(self) -> Vec<u8> {
+        use mado_core::protocol::{encode_pty_frame, PtyFrameKind};
+
+        match self {
+            PtyChunk::Scrollback { data, .. } => encode_pty_frame(PtyFrameKind::Scrollback, &data),
+            PtyChunk::Output { data, .. } => encode_pty_frame(PtyFrameKind::Output, &data),
+            PtyChunk::Exit { code } => {
+                let payload = code.map(i32::to_be_bytes);
+                encode_pty_frame(PtyFrameKind::Exit, payload.as_ref().map_or(&[][..], |p| &p[..]))
+            }
+        }
+    }
+}
+
+/// Turn a PTY subscription into scrollback, then live output, then a final exit.
+///
+/// A resumed subscription's replay is plain output the client missed, so it is
+/// sent as such rather than as scrollback (which clients treat as a reset).
+pub(crate) fn pty_chunks(subscription: PtySubscription) -> impl Stream<Item = PtyChunk> + Send {
+    let PtySubscription {
+        scrollback,
+        offset,
+        resumed,
+        output,
+        exited,
+    } = subscription;
+
+    let replay = if scrollback.is_empty() {
+        None
+    } else if resumed {
+        Some(PtyChunk::Output {
+            data: scrollback,
+            offset,
+        })
+    } else {
+        Some(PtyChunk::Scrollback {
+            data: scrollback,
+            offset,
+        })
+    };
+    let head = futures::stream::iter(replay);
+
+    let live = futures::stream::unfold(Some((output, exited)), |state| async move {
+        let (mut output, mut exited) = state?;
+        loop {
+            let msg = tokio::select! {
+                // Prefer buffered output so nothing written before exit is dropped.
+                biased;
+                msg = output.recv() => msg,
+                exit = exited.wait_for(Option::is_some) => {
+                    let code = exit.ok().and_then(|exit| exit.and_then(|exit| exit.code));
+                    return Some((PtyChunk::Exit { code }, None));
+                }
+            };
+            match msg {
+                Ok(chunk) => {
+                    let chunk = PtyChunk::Output {
+                        data: chunk.data,
+                        offset: chunk.offset,
+                    };
+                    return Some((chunk, Some((output, exited))));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    let code = exited.borrow().and_then(|exit| exit.code);
+                    return Some((PtyChunk::Exit { code }, None));
+                }
+            }
+        }
+    });
+
+    head.chain(live)
+}
+
+/// Versioned PTY stream with scrollback replay and exit events.
+///
+/// Negotiated on `Accept`: `application/vnd.mado.pty.v1` gets length-prefixed
+/// binary frames (see `mado_core::protocol::encode_pty_frame`); anything else
+/// gets SSE with `scrollback`/`output` (base64) and `exit` (`{"code": N}`)
+/// events. SSE event ids are stream offsets and `Last-Event-ID` resumes after one.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/pty/stream",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "`scrollback`, `output` and `exit` events, or binary frames for `application/vnd.mado.pty.v1`", content_type = "text/event-stream", body = String),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+async fn pty_stream_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    use mado_core::protocol::{PTY_STREAM_BINARY_MEDIA_TYPE, PTY_STREAM_VERSION};
+
+    let session_id = SessionId::new(id);
+    let binary = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains(PTY_STREAM_BINARY_MEDIA_TYPE));
+
+    // Binary frames carry no ids, so only SSE clients can resume.
+    let resume_from = if binary { None } else { last_event_id(&headers) };
+    let subscription = match state.session_manager.subscribe_pty(&session_id, resume_from).await {
+        Ok(subscription) => subscription,
+        Err(e) if binary => return ApiError::from(e).into_response(),
+        Err(_) => {
+            // Same shape as the legacy stream so SSE clients handle it uniformly.
+            let error_stream = futures::stream::once(async {
+                Ok::<_, Infallible>(Event::default().data("session_not_found").event("error"))
+            });
+            return Sse::new(error_stream).into_response();
+        }
+    };
+
+    let version_header = (
+        header::HeaderName::from_static("x-mado-pty-stream-version"),
+        HeaderValue::from(PTY_STREAM_VERSION),
+    );
+    let chunks = pty_chunks(subscription);
+
+    if binary {
+        let frames = chunks.map(|chunk| Ok::<_, Infallible>(Bytes::from(chunk.into_frame())));
+        (
+            [
+                version_header,
+                (header::CONTENT_TYPE, HeaderValue::from_static(PTY_STREAM_BINARY_MEDIA_TYPE)),
+            ],
+            axum::body::Body::from_stream(frames),
+        )
+            .into_response()
     } else {
-        return Json(DaemonResponse::Error {
-            message: format!("Session not found: {}", id),
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let events = chunks.map(|chunk| {
+            let event = match chunk {
+                PtyChunk::Scrollback { data, offset } => Event::default()
+                    .event("scrollback")
+                    .data(engine.encode(&data))
+                    .id(offset.to_string()),
+                PtyChunk::Output { data, offset } => Event::default()
+                    .event("output")
+                    .data(engine.encode(&data))
+                    .id(offset.to_string()),
+                PtyChunk::Exit { code } => Event::default()
+                    .event("exit")
+                    .data(serde_json::json!({ "code": code }).to_string()),
+            };
+            Ok::<_, Infallible>(event)
         });
+        let started = futures::stream::once(async move {
+            Ok(Event::default()
+                .event("started")
+                .data(format!("v{}", PTY_STREAM_VERSION)))
+        });
+        ([version_header], Sse::new(started.chain(events))).into_response()
+    }
+}
+
+// ── Chat mode endpoints ──
+
+/// Ensure a conversation is initialized for a session.
+pub(crate) async fn ensure_conversation(
+    state: &AppState,
+    session_id: &SessionId,
+) -> Result<(), ApiError> {
+    let session = state
+        .session_manager
+        .get_session(session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+
+    // Passes the stored claude_session_id so conversations can be resumed.
+    state.conversation_manager.init_session(&session).await;
+    Ok(())
+}
+
+/// Refuse to run agents in `working_dir` until it is trusted. Sessions
+/// without one use the daemon's own directory, which always is.
+pub(crate) async fn ensure_trusted(state: &AppState, working_dir: Option<&str>) -> Result<(), ApiError> {
+    match working_dir {
+        Some(dir) if !state.session_manager.is_trusted(dir).await => Err(ApiError::WorkspaceUntrusted(dir.to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// `ensure_trusted` for a session's working directory.
+pub(crate) async fn ensure_session_trusted(state: &AppState, session_id: &SessionId) -> Result<(), ApiError> {
+    let session = state
+        .session_manager
+        .get_session(session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    ensure_trusted(state, session.working_dir.as_deref()).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SendMessageBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Unreadable context file or unknown attachment", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn send_message_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<SendMessageBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+
+    match state
+        .conversation_manager
+        .send_message(
+            &session_id,
+            body.content,
+            MessageOptions {
+                model: body.model,
+                context_files: body.context_files,
+                attachments: body.attachments,
+            },
+        )
+        .await
+    {
+        Ok(message_id) => Ok(Json(DaemonResponse::MessageAccepted { message_id })),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Ask two or three models the same question at once, each in a read-only
+/// fork of the conversation that leaves the session itself alone. Returns
+/// once they have started; follow their answers on
+/// `/sessions/{id}/comparisons/{comparison_id}/stream` and apply the one to
+/// keep.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages/compare",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = CompareBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress", body = DaemonResponse),
+        (status = 422, description = "Empty prompt, slash command, or not 2 to 3 models", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn compare_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<CompareBody>,
+) -> ApiResult {
+    if body.content.trim().is_empty() {
+        return Err(ApiError::Validation("Prompt is empty".to_string()));
+    }
+    if crate::slash::parse(&body.content).is_some() {
+        return Err(ApiError::Validation("Slash commands can't be compared".to_string()));
+    }
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+
+    let comparison = state
+        .comparisons
+        .start(state.conversation_manager.clone(), &session_id, body.content, body.models)
+        .await?;
+    Ok(Json(DaemonResponse::ComparisonStarted { comparison }))
+}
+
+/// The session's comparisons, newest first, those in progress included.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/comparisons",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn list_comparisons_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return Err(ApiError::SessionNotFound(session_id.to_string()));
     }
+    let mut comparisons = state.comparisons.in_progress(&session_id);
+    let dir = state.conversation_manager.comparisons_dir(&session_id);
+    let finished = tokio::task::spawn_blocking(move || crate::compare::list(&dir))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    // One that just finished may be in both.
+    comparisons.retain(|comparison| !finished.iter().any(|f| f.id == comparison.id));
+    comparisons.extend(finished);
+    comparisons.sort_by_key(|comparison| std::cmp::Reverse(comparison.created_at));
+    Ok(Json(DaemonResponse::Comparisons { comparisons }))
+}
+
+/// A comparison's answers as `variant` events, each a chat event tagged
+/// with its variant's id, then a `complete` event with the comparison.
+/// Events are replayed from the start (as far back as the buffer reaches)
+/// or, with `Last-Event-ID`, from after that event. A finished comparison's
+/// stream is just its `complete` event.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/comparisons/{comparison_id}/stream",
+    params(("id" = String, Path, description = "Session id"), ("comparison_id" = String, Path, description = "Comparison id")),
+    responses(
+        (status = 200, description = "`variant` and `complete` events carrying `CompareEvent` JSON", content_type = "text/event-stream", body = String),
+        (status = 404, description = "Comparison not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn comparison_stream_handler(
+    State(state): State<AppState>,
+    AxumPath((id, comparison_id)): AxumPath<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let session_id = SessionId::new(id);
+    let compare_event = |id: Option<u64>, event: CompareEvent| {
+        let name = match event {
+            CompareEvent::Variant { .. } => "variant",
+            CompareEvent::Complete { .. } => "complete",
+        };
+        let mut sse = Event::default().data(serde_json::to_string(&event).unwrap_or_default()).event(name);
+        if let Some(id) = id {
+            sse = sse.id(id.to_string());
+        }
+        Ok::<_, Infallible>(sse)
+    };
+
+    let Some(events) = state.comparisons.events(&session_id, &comparison_id) else {
+        let dir = state.conversation_manager.comparisons_dir(&session_id);
+        let comparison = tokio::task::spawn_blocking(move || crate::compare::load(&dir, &comparison_id))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))??;
+        let complete = compare_event(None, CompareEvent::Complete { comparison });
+        return Ok(Sse::new(futures::stream::iter([complete])).into_response());
+    };
+
+    let subscription = events.subscribe(Some(last_event_id(&headers).unwrap_or(0)));
+    let missed = futures::stream::iter(subscription.missed);
+    let live = BroadcastStream::new(subscription.live).filter_map(|result| result.ok());
+    // The channel outlives the comparison, so end after `complete` ourselves.
+    let events = futures::StreamExt::scan(missed.chain(live), false, move |done, (id, event)| {
+        if *done {
+            return futures::future::ready(None);
+        }
+        *done = matches!(event, CompareEvent::Complete { .. });
+        futures::future::ready(Some(compare_event(Some(id), event)))
+    });
+    Ok(Sse::new(events).into_response())
+}
+
+/// Keep one variant of a finished comparison: its prompt and answer are
+/// added to the conversation, which carries on from that answer. Only one
+/// variant can be applied, and only while the conversation hasn't moved on
+/// since the comparison started.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/comparisons/{comparison_id}/apply",
+    params(("id" = String, Path, description = "Session id"), ("comparison_id" = String, Path, description = "Comparison id")),
+    request_body = ApplyComparisonBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session, comparison or variant not found", body = DaemonResponse),
+        (status = 409, description = "Still running, applied already, the variant failed, or the conversation moved on", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn apply_comparison_handler(
+    State(state): State<AppState>,
+    AxumPath((id, comparison_id)): AxumPath<(String, String)>,
+    ApiJson(body): ApiJson<ApplyComparisonBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    let comparison = state
+        .comparisons
+        .apply(&state.conversation_manager, &session_id, &comparison_id, &body.variant_id)
+        .await?;
+    Ok(Json(DaemonResponse::ComparisonApplied { comparison }))
+}
+
+/// Replace an earlier user message, drop the conversation after it and run
+/// it again. The body is the same as for sending a message.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages/{message_id}/edit",
+    params(("id" = String, Path, description = "Session id"), ("message_id" = String, Path, description = "User message id")),
+    request_body = SendMessageBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session or message not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress", body = DaemonResponse),
+        (status = 422, description = "Not a user message, unreadable context file or unknown attachment", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn edit_message_handler(
+    State(state): State<AppState>,
+    AxumPath((id, message_id)): AxumPath<(String, String)>,
+    ApiJson(body): ApiJson<SendMessageBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+
+    state
+        .conversation_manager
+        .edit_message(
+            &session_id,
+            &message_id,
+            body.content,
+            MessageOptions {
+                model: body.model,
+                context_files: body.context_files,
+                attachments: body.attachments,
+            },
+        )
+        .await?;
+    Ok(Json(DaemonResponse::MessageAccepted { message_id }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/messages",
+    params(("id" = String, Path, description = "Session id"), GetMessagesQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn get_messages_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<GetMessagesQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
 
     match state
         .conversation_manager
         .get_messages(&session_id, params.limit, params.before_id)
         .await
     {
-        Ok(messages) => Json(DaemonResponse::Messages { messages }),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Ok(messages) => Ok(Json(DaemonResponse::Messages { messages })),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}/messages/current",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
 async fn cancel_response_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = SessionId::new(id);
 
     match state.conversation_manager.cancel_response(&session_id).await {
-        Ok(()) => Json(DaemonResponse::CancelAccepted),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Ok(()) => Ok(Json(DaemonResponse::CancelAccepted)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Answer a prompt the Claude CLI raised during the current response
+/// (announced by an `input_requested` stream event).
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages/current/input",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ResponseInputBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "No response in progress", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn response_input_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<ResponseInputBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+
+    state.conversation_manager.send_response_input(&session_id, &body.text).await?;
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Run the last user message again after the response to it failed.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages/retry",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "The last response didn't fail, or one is in progress", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn retry_message_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+
+    let message_id = state.conversation_manager.retry_message(&session_id).await?;
+    Ok(Json(DaemonResponse::MessageAccepted { message_id }))
+}
+
+/// Delete one message from a conversation. Claude continues in a new session
+/// with the remaining messages replayed.
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}/messages/{message_id}",
+    params(("id" = String, Path, description = "Session id"), ("message_id" = String, Path, description = "Message id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or message not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn delete_message_handler(
+    State(state): State<AppState>,
+    AxumPath((id, message_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+
+    state.conversation_manager.delete_message(&session_id, &message_id).await?;
+    Ok(Json(DaemonResponse::MessagesDeleted { count: 1 }))
+}
+
+/// Delete all messages of a conversation and start a new Claude session.
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}/messages",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn clear_messages_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+
+    let count = state.conversation_manager.clear_messages(&session_id).await?;
+    Ok(Json(DaemonResponse::MessagesDeleted { count }))
+}
+
+/// Replace the older messages of a conversation with a summary written by
+/// the session's model, archiving the originals. The next turn starts a new
+/// Claude session from the summary and the messages kept. Answers with the
+/// estimated tokens before and after.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/compact",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = CompactBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "A response is in progress, or too few messages to compact", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn compact_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<CompactBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+
+    let compaction = state
+        .conversation_manager
+        .compact(&session_id, body.keep_recent, body.instructions)
+        .await?;
+    Ok(Json(DaemonResponse::Compacted { compaction }))
+}
+
+/// Withdraw a message still waiting in the chat queue.
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}/queue/{message_id}",
+    params(("id" = String, Path, description = "Session id"), ("message_id" = String, Path, description = "Queued message id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or queued message not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn cancel_queued_handler(
+    State(state): State<AppState>,
+    AxumPath((id, message_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+
+    match state.conversation_manager.cancel_queued(&session_id, &message_id).await {
+        Ok(()) => Ok(Json(DaemonResponse::CancelAccepted)),
+        Err(e) => Err(e.into()),
     }
 }
 
+/// Upload a file to attach to chat messages. The request body is the file.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/attachments",
+    params(("id" = String, Path, description = "Session id"), UploadAttachmentQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Invalid name, or empty or too large body", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn upload_attachment_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<UploadAttachmentQuery>,
+    body: Result<Bytes, BytesRejection>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return Err(ApiError::SessionNotFound(session_id.to_string()));
+    }
+    let data = body.map_err(|e| ApiError::Validation(format!("Invalid attachment body: {}", e)))?;
+
+    let dir = state.conversation_manager.attachments_dir(&session_id);
+    let attachment = tokio::task::spawn_blocking(move || {
+        crate::attachments::save(&dir, &params.name, &data)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    tracing::info!("Stored attachment {} for session {}", attachment.id, session_id);
+    Ok(Json(DaemonResponse::AttachmentUploaded { attachment }))
+}
+
+/// Chat event stream. Each event carries an id; a client reconnecting with
+/// `Last-Event-ID` gets the events it missed replayed first. If some were
+/// already evicted, a `resync` event tells it to refetch messages.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/stream",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "`message` events carrying `StreamEvent` JSON", content_type = "text/event-stream", body = String),
+    ),
+    tag = "chat"
+)]
 async fn stream_events_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
+    headers: HeaderMap,
 ) -> Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>> {
     let session_id = SessionId::new(id);
 
-    let rx = state.conversation_manager.subscribe(&session_id).await;
+    let subscription = state
+        .conversation_manager
+        .subscribe(&session_id, last_event_id(&headers))
+        .await;
+
+    let message_event = |(id, event): (u64, StreamEvent)| {
+        let json = serde_json::to_string(&event).unwrap_or_default();
+        Ok(Event::default().data(json).event("message").id(id.to_string()))
+    };
+
+    let missed = futures::stream::iter(subscription.missed.into_iter().map(message_event));
+    let live = BroadcastStream::new(subscription.live).filter_map(move |result| match result {
+        Ok(event) => Some(message_event(event)),
+        Err(_) => None, // Lagged receiver, skip
+    });
+
+    // Prepend a "connected" event, plus "resync" if the replay has a gap.
+    let mut head = vec![Ok(Event::default().data("connected").event("connected"))];
+    if !subscription.complete {
+        head.push(Ok(Event::default().data("messages").event("resync")));
+    }
+
+    Sse::new(Box::pin(futures::stream::iter(head).chain(missed).chain(live)))
+}
+
+/// Run one prompt in a directory without creating a session. While Claude
+/// works the response streams `message` events like a session's stream;
+/// the last event, `complete`, carries the conversation and what the task
+/// changed in the directory's repository. Closing the stream cancels the
+/// task.
+#[utoipa::path(
+    post,
+    path = "/tasks",
+    request_body = CreateTaskBody,
+    responses(
+        (status = 200, description = "`message` events carrying `StreamEvent` JSON, then a `complete` event carrying `TaskResult` JSON", content_type = "text/event-stream", body = String),
+        (status = 402, description = "Budget exhausted", body = DaemonResponse),
+        (status = 403, description = "The directory isn't trusted", body = DaemonResponse),
+        (status = 422, description = "Empty prompt, slash command or missing directory", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn create_task_handler(
+    State(state): State<AppState>,
+    ApiJson(body): ApiJson<CreateTaskBody>,
+) -> Result<Response, ApiError> {
+    if body.prompt.trim().is_empty() {
+        return Err(ApiError::Validation("Prompt is empty".to_string()));
+    }
+    // Some slash commands never go idle, so a task running one would not end.
+    if crate::slash::parse(&body.prompt).is_some() {
+        return Err(ApiError::Validation("Tasks can't run slash commands".to_string()));
+    }
+    let working_dir = PathBuf::from(&body.cwd);
+    if !working_dir.is_dir() {
+        return Err(ApiError::Validation(format!("Not a directory: {}", body.cwd)));
+    }
+    ensure_trusted(&state, Some(&body.cwd)).await?;
+    let model = body.model.unwrap_or_else(|| {
+        state.settings.read().unwrap_or_else(|e| e.into_inner()).default_model.clone()
+    });
+
+    let events = crate::task::start(
+        state.conversation_manager.clone(),
+        crate::task::TaskOptions {
+            prompt: body.prompt,
+            working_dir,
+            model,
+            system_prompt: body.system_prompt,
+            permission_mode: body.permission_mode,
+        },
+    )
+    .await?;
+
+    let events = ReceiverStream::new(events).map(|event| {
+        let event = match event {
+            crate::task::TaskEvent::Stream(event) => Event::default()
+                .data(serde_json::to_string(&event).unwrap_or_default())
+                .event("message"),
+            crate::task::TaskEvent::Complete(result) => Event::default()
+                .data(serde_json::to_string(&result).unwrap_or_default())
+                .event("complete"),
+        };
+        Ok::<_, Infallible>(event)
+    });
+    Ok(Sse::new(events).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "schedules"
+)]
+async fn list_schedules_handler(State(state): State<AppState>) -> ApiResult {
+    Ok(Json(DaemonResponse::Schedules {
+        schedules: state.scheduler.list(),
+    }))
+}
+
+/// Run a prompt whenever `cron` matches, each time in a fresh conversation
+/// (see `crate::scheduler`).
+#[utoipa::path(
+    post,
+    path = "/schedules",
+    request_body = CreateScheduleBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The directory isn't trusted", body = DaemonResponse),
+        (status = 422, description = "Invalid cron expression, empty prompt, slash command or missing directory", body = DaemonResponse),
+    ),
+    tag = "schedules"
+)]
+async fn create_schedule_handler(
+    State(state): State<AppState>,
+    ApiJson(body): ApiJson<CreateScheduleBody>,
+) -> ApiResult {
+    if body.prompt.trim().is_empty() {
+        return Err(ApiError::Validation("Prompt is empty".to_string()));
+    }
+    if crate::slash::parse(&body.prompt).is_some() {
+        return Err(ApiError::Validation("Schedules can't run slash commands".to_string()));
+    }
+    if !Path::new(&body.cwd).is_dir() {
+        return Err(ApiError::Validation(format!("Not a directory: {}", body.cwd)));
+    }
+    let now = chrono::Utc::now();
+    let next_run = crate::scheduler::next_run(&body.cron, now).map_err(ApiError::Validation)?;
+    ensure_trusted(&state, Some(&body.cwd)).await?;
+    let model = body.model.unwrap_or_else(|| {
+        state.settings.read().unwrap_or_else(|e| e.into_inner()).default_model.clone()
+    });
+
+    let schedule = Schedule {
+        id: uuid::Uuid::new_v4().to_string(),
+        cron: body.cron,
+        prompt: body.prompt,
+        cwd: body.cwd,
+        model,
+        permission_mode: body.permission_mode,
+        created_at: now,
+        next_run: Some(next_run),
+    };
+    state.scheduler.add(schedule.clone());
+    tracing::info!("Scheduled {} ({}) in {}", schedule.id, schedule.cron, schedule.cwd);
+    Ok(Json(DaemonResponse::ScheduleCreated { schedule }))
+}
+
+/// Remove a schedule and its run history.
+#[utoipa::path(
+    delete,
+    path = "/schedules/{id}",
+    params(("id" = String, Path, description = "Schedule id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Schedule not found", body = DaemonResponse),
+    ),
+    tag = "schedules"
+)]
+async fn delete_schedule_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    if !state.scheduler.remove(&id) {
+        return Err(ApiError::NotFound(format!("Schedule not found: {}", id)));
+    }
+    tracing::info!("Removed schedule {}", id);
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Past runs of a schedule, newest first.
+#[utoipa::path(
+    get,
+    path = "/schedules/{id}/runs",
+    params(("id" = String, Path, description = "Schedule id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Schedule not found", body = DaemonResponse),
+    ),
+    tag = "schedules"
+)]
+async fn schedule_runs_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let runs = state
+        .scheduler
+        .runs(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("Schedule not found: {}", id)))?;
+    Ok(Json(DaemonResponse::ScheduleRuns { runs }))
+}
+
+/// Session templates, by name (see `crate::templates`).
+#[utoipa::path(
+    get,
+    path = "/templates",
+    responses(
+        (status = 200, body = DaemonResponse),
+    ),
+    tag = "templates"
+)]
+async fn list_templates_handler(State(state): State<AppState>) -> ApiResult {
+    Ok(Json(DaemonResponse::Templates {
+        templates: state.templates.list(),
+    }))
+}
 
-    let stream = BroadcastStream::new(rx).filter_map(|result| match result {
-        Ok(event) => {
-            let json = serde_json::to_string(&event).unwrap_or_default();
-            Some(Ok(Event::default().data(json).event("message")))
+/// Add a session template, replacing any of the same name.
+#[utoipa::path(
+    post,
+    path = "/templates",
+    request_body = SessionTemplate,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 422, description = "Invalid name or environment", body = DaemonResponse),
+    ),
+    tag = "templates"
+)]
+async fn save_template_handler(
+    State(state): State<AppState>,
+    ApiJson(template): ApiJson<SessionTemplate>,
+) -> ApiResult {
+    crate::session_env::validate(&template.env).map_err(|e| ApiError::Validation(e.to_string()))?;
+    state.templates.save(&template)?;
+    tracing::info!("Saved template {}", template.name);
+    Ok(Json(DaemonResponse::TemplateSaved { template }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/templates/{name}",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Template not found", body = DaemonResponse),
+    ),
+    tag = "templates"
+)]
+async fn delete_template_handler(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> ApiResult {
+    state.templates.remove(&name)?;
+    tracing::info!("Removed template {}", name);
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// The prompt library, by name: the user's prompts and, for a session, its
+/// project's (see `crate::prompts`).
+#[utoipa::path(
+    get,
+    path = "/prompts",
+    params(PromptsQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "prompts"
+)]
+async fn list_prompts_handler(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<PromptsQuery>,
+) -> ApiResult {
+    let project = match params.session_id {
+        Some(id) => project_prompts(&state, &SessionId::new(id)).await?,
+        None => BTreeMap::new(),
+    };
+    Ok(Json(DaemonResponse::Prompts {
+        prompts: state.prompts.list(&project),
+    }))
+}
+
+/// Add a prompt to the user's library, replacing any of the same name.
+#[utoipa::path(
+    post,
+    path = "/prompts",
+    request_body = SavedPrompt,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 422, description = "Invalid name or empty prompt", body = DaemonResponse),
+    ),
+    tag = "prompts"
+)]
+async fn save_prompt_handler(
+    State(state): State<AppState>,
+    ApiJson(prompt): ApiJson<SavedPrompt>,
+) -> ApiResult {
+    let prompt = state.prompts.save(prompt)?;
+    tracing::info!("Saved prompt {}", prompt.name);
+    Ok(Json(DaemonResponse::PromptSaved { prompt }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/prompts/{name}",
+    params(("name" = String, Path, description = "Prompt name")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Prompt not found", body = DaemonResponse),
+    ),
+    tag = "prompts"
+)]
+async fn delete_prompt_handler(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> ApiResult {
+    state.prompts.remove(&name)?;
+    tracing::info!("Removed prompt {}", name);
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Fill in a saved prompt's variables. With a session, its project's
+/// prompts apply and `{{branch}}` is its git branch; values in the request
+/// take precedence. Variables without a value are left in and listed.
+#[utoipa::path(
+    post,
+    path = "/prompts/{name}/render",
+    params(("name" = String, Path, description = "Prompt name")),
+    request_body = RenderPromptBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Prompt or session not found", body = DaemonResponse),
+    ),
+    tag = "prompts"
+)]
+async fn render_prompt_handler(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    ApiJson(body): ApiJson<RenderPromptBody>,
+) -> ApiResult {
+    let mut values = HashMap::new();
+    let project = match body.session_id {
+        Some(id) => {
+            let session_id = SessionId::new(id);
+            let working_dir = resolve_working_dir(&state, &session_id).await?;
+            if let Some(root) = crate::git_ops::discover_repo_root(Path::new(&working_dir))
+                && let Ok(info) = crate::git_ops::git_branch_info(&root)
+            {
+                values.insert("branch".to_string(), info.branch);
+            }
+            crate::project_config::for_dir(Some(&working_dir)).prompts
         }
+        None => BTreeMap::new(),
+    };
+    let prompt = state.prompts.get(&name, &project)?;
+    values.extend(body.variables);
+    let (content, missing) = crate::prompts::render(&prompt.content, &values);
+    Ok(Json(DaemonResponse::PromptRendered {
+        rendered: RenderedPrompt { content, missing },
+    }))
+}
+
+/// The `[prompts]` of the `.mado.toml` that applies to a session.
+async fn project_prompts(state: &AppState, session_id: &SessionId) -> Result<BTreeMap<String, String>, ApiError> {
+    let working_dir = resolve_working_dir(state, session_id).await?;
+    Ok(crate::project_config::for_dir(Some(&working_dir)).prompts)
+}
+
+/// Let sessions and tasks run in a directory and below. Until then, creating
+/// a session or sending a message there fails with `workspace_untrusted`.
+#[utoipa::path(
+    post,
+    path = "/workspaces/trust",
+    request_body = TrustWorkspaceBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 422, description = "Not a directory", body = DaemonResponse),
+    ),
+    tag = "sessions"
+)]
+async fn trust_workspace_handler(
+    State(state): State<AppState>,
+    ApiJson(body): ApiJson<TrustWorkspaceBody>,
+) -> ApiResult {
+    let path = state.session_manager.trust_workspace(&body.path).await?;
+    Ok(Json(DaemonResponse::WorkspaceTrusted { path }))
+}
+
+/// Daemon-wide activity feed across all sessions (see `crate::feed`).
+///
+/// Events are `activity` SSE events carrying `ActivityEvent` JSON, with ids
+/// honoring `Last-Event-ID` like the per-session chat stream.
+#[utoipa::path(
+    get,
+    path = "/events",
+    responses(
+        (status = 200, description = "`activity` events carrying `ActivityEvent` JSON", content_type = "text/event-stream", body = String),
+    ),
+    tag = "daemon"
+)]
+async fn activity_feed_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>> {
+    let subscription = state.activity_feed.subscribe(last_event_id(&headers));
+
+    let activity_event = |(id, event): (u64, ActivityEvent)| {
+        let json = serde_json::to_string(&event).unwrap_or_default();
+        Ok(Event::default().data(json).event("activity").id(id.to_string()))
+    };
+
+    let missed = futures::stream::iter(subscription.missed.into_iter().map(activity_event));
+    let live = BroadcastStream::new(subscription.live).filter_map(move |result| match result {
+        Ok(event) => Some(activity_event(event)),
         Err(_) => None, // Lagged receiver, skip
     });
 
-    // Prepend a "connected" event.
-    let started = futures::stream::once(async {
+    let connected = futures::stream::once(async {
         Ok(Event::default().data("connected").event("connected"))
     });
 
-    Sse::new(Box::pin(started.chain(stream)))
+    Sse::new(Box::pin(connected.chain(missed).chain(live)))
+}
+
+/// Default and maximum depth of a file tree listing.
+const DEFAULT_FILES_DEPTH: usize = 1;
+const MAX_FILES_DEPTH: usize = 5;
+
+/// Query params for listing a session's workspace files.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListFilesQuery {
+    /// Directory to list, relative to the working directory (default: the
+    /// working directory itself).
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Levels of subdirectories to include (default 1, max 5).
+    #[serde(default)]
+    pub depth: Option<usize>,
+}
+
+/// List a directory of a session's working directory as a tree, skipping
+/// files ignored by `.gitignore`.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/files",
+    params(("id" = String, Path, description = "Session id"), ListFilesQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or directory not found", body = DaemonResponse),
+        (status = 422, description = "Path is not a directory inside the workspace", body = DaemonResponse),
+    ),
+    tag = "files"
+)]
+async fn list_files_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<ListFilesQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
+    let path = params.path.unwrap_or_default();
+    let depth = params.depth.unwrap_or(DEFAULT_FILES_DEPTH).clamp(1, MAX_FILES_DEPTH);
+
+    let tree = tokio::task::spawn_blocking(move || {
+        let project = crate::project_config::for_dir(Some(&working_dir));
+        crate::files::list(Path::new(&working_dir), &path, depth, &project.ignored_paths)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::Files {
+        entries: tree.entries,
+        truncated: tree.truncated,
+    }))
+}
+
+/// Query params naming a file in a session's workspace.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilePathQuery {
+    /// File path relative to the working directory.
+    pub path: Option<String>,
+}
+
+fn file_path_param(params: FilePathQuery) -> Result<String, ApiError> {
+    params
+        .path
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| ApiError::Validation("Missing 'path' parameter".to_string()))
+}
+
+fn etag_header(etag: &str) -> [(header::HeaderName, String); 1] {
+    [(header::ETAG, format!("\"{}\"", etag))]
+}
+
+/// Read a text file (up to 5 MiB) from a session's working directory.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/files/content",
+    params(("id" = String, Path, description = "Session id"), FilePathQuery),
+    responses(
+        (status = 200, description = "The file, with its ETag also in the `ETag` header", body = DaemonResponse),
+        (status = 404, description = "Session or file not found", body = DaemonResponse),
+        (status = 422, description = "Not a text file inside the workspace, or too large", body = DaemonResponse),
+    ),
+    tag = "files"
+)]
+async fn read_file_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<FilePathQuery>,
+) -> Result<Response, ApiError> {
+    let session_id = SessionId::new(id);
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
+    let path = file_path_param(params)?;
+
+    let relative = path.clone();
+    let file = tokio::task::spawn_blocking(move || {
+        crate::files::read(Path::new(&working_dir), &relative)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    let headers = etag_header(&file.etag);
+    Ok((
+        headers,
+        Json(DaemonResponse::FileContent {
+            path,
+            content: file.content,
+            etag: file.etag,
+        }),
+    )
+        .into_response())
+}
+
+/// Write a text file in a session's working directory, creating it if needed.
+/// Pass the ETag from reading it to refuse the write if it changed since.
+#[utoipa::path(
+    put,
+    path = "/sessions/{id}/files/content",
+    params(("id" = String, Path, description = "Session id"), FilePathQuery),
+    request_body = WriteFileBody,
+    responses(
+        (status = 200, description = "The new ETag, also in the `ETag` header", body = DaemonResponse),
+        (status = 404, description = "Session or parent directory not found", body = DaemonResponse),
+        (status = 409, description = "The file changed since it was read", body = DaemonResponse),
+        (status = 422, description = "Path is not a file inside the workspace, or content too large", body = DaemonResponse),
+    ),
+    tag = "files"
+)]
+async fn write_file_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<FilePathQuery>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<WriteFileBody>,
+) -> Result<Response, ApiError> {
+    let session_id = SessionId::new(id);
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
+    let path = file_path_param(params)?;
+    let expected_etag = body.etag.or_else(|| {
+        headers
+            .get(header::IF_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().trim_start_matches("W/").trim_matches('"').to_string())
+    });
+
+    // Serialize writes so the ETag check and the write are one step.
+    let _lock = state.workspace_locks.acquire(Path::new(&working_dir)).await;
+    let etag = tokio::task::spawn_blocking(move || {
+        crate::files::write(Path::new(&working_dir), &path, &body.content, expected_etag.as_deref())
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok((etag_header(&etag), Json(DaemonResponse::FileWritten { etag })).into_response())
+}
+
+/// The project memory file (`CLAUDE.md`) of a session's workspace, empty if
+/// there is none yet (see `crate::memory`).
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/memory",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn get_memory_handler(State(state): State<AppState>, AxumPath(id): AxumPath<String>) -> ApiResult {
+    let root = memory_root(&state, &SessionId::new(id)).await?;
+    let memory = tokio::task::spawn_blocking(move || crate::memory::read(&root))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::Memory { memory }))
+}
+
+/// Replace the project memory file, creating it if needed. Pass the ETag
+/// from reading it to refuse the write if it changed since.
+#[utoipa::path(
+    put,
+    path = "/sessions/{id}/memory",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = WriteMemoryBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "The file changed since it was read", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn write_memory_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<WriteMemoryBody>,
+) -> ApiResult {
+    let root = memory_root(&state, &SessionId::new(id)).await?;
+    let _lock = state.workspace_locks.acquire(&root).await;
+    let memory = tokio::task::spawn_blocking(move || crate::memory::write(&root, &body.content, body.etag.as_deref()))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::Memory { memory }))
+}
+
+/// Add an entry to the project memory file ("remember this").
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/memory/append",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = AppendMemoryBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Empty entry", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn append_memory_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<AppendMemoryBody>,
+) -> ApiResult {
+    if crate::memory::one_line(&body.entry).is_empty() {
+        return Err(ApiError::Validation("Entry is empty".to_string()));
+    }
+    let root = memory_root(&state, &SessionId::new(id)).await?;
+    let _lock = state.workspace_locks.acquire(&root).await;
+    let memory = tokio::task::spawn_blocking(move || crate::memory::append(&root, &body.entry))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::Memory { memory }))
+}
+
+/// Memory entries suggested for a session, waiting to be accepted or
+/// dismissed.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/memory/suggestions",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn list_memory_suggestions_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    let path = state.conversation_manager.memory_suggestions_path(&session_id);
+    Ok(Json(DaemonResponse::MemorySuggestions {
+        suggestions: crate::memory::load_suggestions(&path),
+    }))
+}
+
+/// Ask the session's model to distill the recent conversation, mostly the
+/// user's corrections, into memory entries. They are added to the pending
+/// suggestions, which are returned; nothing is written until one is
+/// accepted.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/memory/suggestions",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 402, description = "A budget limit is exceeded", body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn suggest_memory_handler(State(state): State<AppState>, AxumPath(id): AxumPath<String>) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    ensure_session_trusted(&state, &session_id).await?;
+    let path = state.conversation_manager.memory_suggestions_path(&session_id);
+
+    let messages = state.conversation_manager.snapshot_messages(&session_id).await?;
+    let recent = &messages[messages.len().saturating_sub(crate::memory::SUGGESTION_MESSAGES)..];
+    if !recent.iter().any(|m| m.role == mado_core::types::MessageRole::User) {
+        return Ok(Json(DaemonResponse::MemorySuggestions {
+            suggestions: crate::memory::load_suggestions(&path),
+        }));
+    }
+    let root = memory_root(&state, &session_id).await?;
+    let current = tokio::task::spawn_blocking(move || crate::memory::read(&root))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    let prompt = crate::memory::suggestion_prompt(recent, &current.content);
+    let reply = state.conversation_manager.ask(&session_id, &prompt).await?;
+
+    let _lock = state.workspace_locks.acquire(&path).await;
+    let mut suggestions = crate::memory::load_suggestions(&path);
+    for text in crate::memory::parse_suggestions(&reply) {
+        if !suggestions.iter().any(|s| s.text == text) {
+            suggestions.push(MemorySuggestion {
+                id: uuid::Uuid::new_v4().to_string(),
+                text,
+                created_at: chrono::Utc::now(),
+            });
+        }
+    }
+    crate::memory::save_suggestions(&path, &suggestions).map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::MemorySuggestions { suggestions }))
+}
+
+/// Add a suggested entry to the project memory file.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/memory/suggestions/{suggestion_id}/accept",
+    params(("id" = String, Path, description = "Session id"), ("suggestion_id" = String, Path, description = "Suggestion id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or suggestion not found", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn accept_memory_suggestion_handler(
+    State(state): State<AppState>,
+    AxumPath((id, suggestion_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    let root = memory_root(&state, &session_id).await?;
+    let path = state.conversation_manager.memory_suggestions_path(&session_id);
+
+    let _lock = state.workspace_locks.acquire(&path).await;
+    let mut suggestions = crate::memory::load_suggestions(&path);
+    let index = suggestions
+        .iter()
+        .position(|s| s.id == suggestion_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Memory suggestion not found: {}", suggestion_id)))?;
+    let memory = {
+        let _lock = state.workspace_locks.acquire(&root).await;
+        let entry = suggestions[index].text.clone();
+        tokio::task::spawn_blocking(move || crate::memory::append(&root, &entry))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))??
+    };
+    suggestions.remove(index);
+    crate::memory::save_suggestions(&path, &suggestions).map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::Memory { memory }))
+}
+
+/// Drop a suggested memory entry. Returns those left.
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}/memory/suggestions/{suggestion_id}",
+    params(("id" = String, Path, description = "Session id"), ("suggestion_id" = String, Path, description = "Suggestion id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or suggestion not found", body = DaemonResponse),
+    ),
+    tag = "memory"
+)]
+async fn dismiss_memory_suggestion_handler(
+    State(state): State<AppState>,
+    AxumPath((id, suggestion_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    let path = state.conversation_manager.memory_suggestions_path(&session_id);
+
+    let _lock = state.workspace_locks.acquire(&path).await;
+    let mut suggestions = crate::memory::load_suggestions(&path);
+    let before = suggestions.len();
+    suggestions.retain(|s| s.id != suggestion_id);
+    if suggestions.len() == before {
+        return Err(ApiError::NotFound(format!("Memory suggestion not found: {}", suggestion_id)));
+    }
+    crate::memory::save_suggestions(&path, &suggestions).map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::MemorySuggestions { suggestions }))
+}
+
+/// Directory of a session's project memory file.
+async fn memory_root(state: &AppState, session_id: &SessionId) -> Result<PathBuf, ApiError> {
+    let working_dir = resolve_working_dir(state, session_id).await?;
+    Ok(crate::memory::root(Path::new(&working_dir)))
+}
+
+/// Default and maximum lines of context around each search match.
+const DEFAULT_SEARCH_CONTEXT: usize = 2;
+const MAX_SEARCH_CONTEXT: usize = 10;
+
+/// Default and maximum number of search matches returned.
+const DEFAULT_SEARCH_LIMIT: usize = 200;
+const MAX_SEARCH_LIMIT: usize = 2000;
+
+/// Query params for searching a session's workspace.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Text to search for; case-insensitive unless it contains an uppercase letter.
+    pub q: Option<String>,
+    /// Only search files matching this glob, e.g. `*.rs` or `!tests/**`.
+    #[serde(default)]
+    pub glob: Option<String>,
+    /// Treat `q` as a regular expression (default false).
+    #[serde(default)]
+    pub regex: Option<bool>,
+    /// Lines of context around each match (default 2, max 10).
+    #[serde(default)]
+    pub context: Option<usize>,
+    /// Maximum matches to return (default 200, max 2000).
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Search file contents in a session's working directory, skipping files
+/// ignored by `.gitignore`.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/search",
+    params(("id" = String, Path, description = "Session id"), SearchQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Missing query, or invalid regex or glob", body = DaemonResponse),
+    ),
+    tag = "files"
+)]
+async fn search_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<SearchQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
+    let query = params
+        .q
+        .filter(|q| !q.is_empty())
+        .ok_or_else(|| ApiError::Validation("Missing 'q' parameter".to_string()))?;
+    let options = crate::search::SearchOptions {
+        query,
+        regex: params.regex.unwrap_or(false),
+        glob: params.glob.filter(|glob| !glob.is_empty()),
+        context: params.context.unwrap_or(DEFAULT_SEARCH_CONTEXT).min(MAX_SEARCH_CONTEXT),
+        max_matches: params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT),
+        ignored_paths: Vec::new(),
+    };
+
+    let results = tokio::task::spawn_blocking(move || {
+        let mut options = options;
+        options.ignored_paths = crate::project_config::for_dir(Some(&working_dir)).ignored_paths;
+        crate::search::search(Path::new(&working_dir), &options)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::SearchResults { results }))
 }
 
 /// Query params for importing history.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ImportHistoryQuery {
     #[serde(default)]
     pub limit: Option<usize>,
@@ -531,13 +3482,34 @@ pub struct ImportHistoryQuery {
     /// If provided, import a specific CLI session by its ID (UUID file stem).
     #[serde(default)]
     pub target_session_id: Option<String>,
+    /// Newest messages to skip, to page back through a long history.
+    #[serde(default)]
+    pub offset: Option<usize>,
+    /// Agent to import from: `claude` (the default), `aider`, `codex` or
+    /// `gemini`.
+    #[serde(default)]
+    pub source: HistorySource,
 }
 
+/// Import a coding agent's history for the session's working directory into
+/// its conversation. Messages the conversation already has are skipped, so
+/// importing again is harmless; the response counts both.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/history",
+    params(("id" = String, Path, description = "Session id"), ImportHistoryQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "target_session_id given for a source other than claude", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
 async fn import_history_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
     axum::extract::Query(params): axum::extract::Query<ImportHistoryQuery>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = SessionId::new(id.clone());
 
     // Get session's working directory.
@@ -548,21 +3520,27 @@ async fn import_history_handler(
                 .map(|h| h.to_string_lossy().to_string())
                 .unwrap_or_else(|| "/tmp".to_string())
         }),
-        None => {
-            return Json(DaemonResponse::Error {
-                message: format!("Session not found: {}", id),
-            });
-        }
+        None => return Err(ApiError::SessionNotFound(id)),
     };
 
     let path = std::path::Path::new(&working_dir);
 
-    let result = if let Some(ref target_id) = params.target_session_id {
-        crate::claude_history::import_session_by_id(path, target_id, params.limit)
+    let offset = params.offset.unwrap_or(0);
+    let source = params.source;
+    if source != HistorySource::Claude && params.target_session_id.is_some() {
+        return Err(ApiError::Validation(format!(
+            "target_session_id only applies to claude history, not {}",
+            source.as_str()
+        )));
+    }
+    let result = if source != HistorySource::Claude {
+        crate::history_import::import(source, path, params.all_sessions.unwrap_or(false), params.limit, offset)
+    } else if let Some(ref target_id) = params.target_session_id {
+        crate::claude_history::import_session_by_id(path, target_id, params.limit, offset)
     } else if params.all_sessions.unwrap_or(false) {
-        crate::claude_history::import_all_history(path, params.limit)
+        crate::claude_history::import_all_history(path, params.limit, offset)
     } else {
-        crate::claude_history::import_history(path, params.limit)
+        crate::claude_history::import_history(path, params.limit, offset)
     };
 
     match result {
@@ -575,78 +3553,273 @@ async fn import_history_handler(
                     .set_claude_session_id(&session_id, target_id)
                     .await;
             }
-            Json(DaemonResponse::Messages { messages })
+            ensure_conversation(&state, &session_id).await?;
+            let (added, skipped) = state.conversation_manager.merge_imported(&session_id, &messages).await;
+            Ok(Json(DaemonResponse::HistoryImported {
+                import: HistoryImport { messages, added, skipped },
+            }))
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(e.into()),
     }
 }
 
+/// Render a session's conversation (messages, tool calls, usage and costs)
+/// as Markdown, JSON or HTML.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/export",
+    params(("id" = String, Path, description = "Session id"), ExportQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn export_conversation_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<ExportQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let messages = state.conversation_manager.get_messages(&session_id, None, None).await?;
+
+    Ok(Json(DaemonResponse::ConversationExport {
+        file_name: crate::export::file_name(&session, params.format),
+        content: crate::export::render(
+            params.format,
+            &session,
+            &messages,
+            params.include_thinking,
+            chrono::Utc::now(),
+        ),
+    }))
+}
+
+/// Read a session's audit log in a blocking task.
+async fn load_audit_log(state: &AppState, session_id: &SessionId) -> Result<Vec<AuditEntry>, ApiError> {
+    let path = state.conversation_manager.audit_path(session_id);
+    tokio::task::spawn_blocking(move || crate::audit::load(&path))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(format!("Failed to read the audit log: {}", e)))
+}
+
+/// The files a session's chat turns wrote and the commands they ran, with
+/// how each call ended, oldest first.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/audit",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "audit"
+)]
+async fn audit_log_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let entries = load_audit_log(&state, &session_id).await?;
+    Ok(Json(DaemonResponse::AuditLog { entries }))
+}
+
+/// Render a session's audit log as JSON or CSV.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/audit/export",
+    params(("id" = String, Path, description = "Session id"), AuditExportQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "audit"
+)]
+async fn export_audit_log_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<AuditExportQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let entries = load_audit_log(&state, &session_id).await?;
+    Ok(Json(DaemonResponse::AuditExport {
+        file_name: crate::audit::file_name(&session, params.format),
+        content: crate::audit::render(params.format, &entries),
+    }))
+}
+
+/// Cumulative token usage and cost of a session's conversation, and how full
+/// its model's context window is.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/usage",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "chat"
+)]
+async fn session_usage_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    ensure_conversation(&state, &session_id).await?;
+
+    let usage = state.conversation_manager.usage(&session_id).await?;
+    Ok(Json(DaemonResponse::SessionUsage { usage }))
+}
+
 // ── Versioning endpoints ──
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/save",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SaveMilestoneBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
 async fn save_milestone_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<SaveMilestoneBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<SaveMilestoneBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
+    let milestone = save_session_milestone(&state, &session_id, &body.message).await?;
+    Ok(Json(DaemonResponse::MilestoneSaved { milestone }))
+}
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+/// Commit everything in a session's repository as a milestone and announce
+/// it on the activity feed.
+async fn save_session_milestone(
+    state: &AppState,
+    session_id: &SessionId,
+    message: &str,
+) -> Result<mado_core::types::Milestone, ApiError> {
+    let repo_root = resolve_repo_root(state, session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
+    let milestone = state
+        .metrics
+        .time_git("save_milestone", || crate::git_ops::save_milestone(path, message))?;
+    state.activity_feed.publish(
+        session_id,
+        ActivityKind::MilestoneSaved {
+            oid: milestone.oid.clone(),
+            message: milestone.message.clone(),
+        },
+    );
+    Ok(mado_core::types::Milestone {
+        oid: milestone.oid,
+        message: milestone.message,
+        timestamp: milestone.timestamp,
+        files_changed: milestone.files_changed,
+        insertions: milestone.insertions,
+        deletions: milestone.deletions,
+    })
+}
+
+/// Publish `ConfigChanged` on the activity feed after each settings reload.
+fn spawn_config_change_events(
+    mut changes: tokio::sync::watch::Receiver<()>,
+    feed: SharedActivityFeed,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while changes.changed().await.is_ok() {
+            feed.publish_daemon(ActivityKind::ConfigChanged);
+        }
+    })
+}
+
+/// Save a milestone after every completed response while `auto_milestone`
+/// is set for the session, or in config.json for sessions without a setting.
+fn spawn_auto_milestones(state: AppState) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("auto-milestones", move || auto_milestones(state.clone()))
+}
 
-    match crate::git_ops::save_milestone(path, &body.message) {
-        Ok(milestone) => {
-            let core_milestone = mado_core::types::Milestone {
-                oid: milestone.oid,
-                message: milestone.message,
-                timestamp: milestone.timestamp,
-                files_changed: milestone.files_changed,
-                insertions: milestone.insertions,
-                deletions: milestone.deletions,
-            };
-            Json(DaemonResponse::MilestoneSaved {
-                milestone: core_milestone,
-            })
+async fn auto_milestones(state: AppState) {
+    let mut events = state.activity_feed.listen();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if !matches!(event.kind, ActivityKind::MessageComplete { .. }) {
+            continue;
+        }
+        let Some(session) = state.session_manager.get_session(&event.session_id).await else {
+            continue;
+        };
+        let enabled = session
+            .auto_milestone
+            .or_else(|| crate::project_config::for_dir(session.working_dir.as_deref()).auto_milestone)
+            .unwrap_or_else(|| state.settings.read().unwrap_or_else(|e| e.into_inner()).auto_milestone);
+        if !enabled {
+            continue;
+        }
+        // Usually nothing changed or the workspace is not a repository.
+        if let Err(e) = save_session_milestone(&state, &event.session_id, "Auto-save after response").await {
+            tracing::debug!("No auto-milestone for session {}: {}", event.session_id, e);
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/milestones",
+    params(("id" = String, Path, description = "Session id"), ("limit" = Option<usize>, Query, description = "Maximum milestones to return (default 20)")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
 async fn list_milestones_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
     let limit = params
         .get("limit")
         .and_then(|l| l.parse().ok())
         .unwrap_or(20usize);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::list_milestones(path, limit) {
+    match state.metrics.time_git("list_milestones", || {
+        crate::git_ops::list_milestones(path, limit)
+    }) {
         Ok(milestones) => {
             let core_milestones: Vec<mado_core::types::Milestone> = milestones
                 .into_iter()
@@ -659,49 +3832,50 @@ async fn list_milestones_handler(
                     deletions: m.deletions,
                 })
                 .collect();
-            Json(DaemonResponse::Milestones {
+            Ok(Json(DaemonResponse::Milestones {
                 milestones: core_milestones,
-            })
+            }))
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/diff",
+    params(("id" = String, Path, description = "Session id"), ("from" = String, Query, description = "Base milestone oid"), ("to" = String, Query, description = "Target milestone oid")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+        (status = 422, description = "Invalid request", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
 async fn diff_milestones_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let from_oid = match params.get("from") {
-        Some(f) => f.clone(),
-        None => {
-            return Json(DaemonResponse::Error {
-                message: "Missing 'from' parameter".to_string(),
-            });
-        }
-    };
-    let to_oid = match params.get("to") {
-        Some(t) => t.clone(),
-        None => {
-            return Json(DaemonResponse::Error {
-                message: "Missing 'to' parameter".to_string(),
-            });
-        }
-    };
+    let from_oid = params
+        .get("from")
+        .cloned()
+        .ok_or_else(|| ApiError::Validation("Missing 'from' parameter".to_string()))?;
+    let to_oid = params
+        .get("to")
+        .cloned()
+        .ok_or_else(|| ApiError::Validation("Missing 'to' parameter".to_string()))?;
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::diff_milestones(path, &from_oid, &to_oid) {
+    match state.metrics.time_git("diff_milestones", || {
+        crate::git_ops::diff_milestones(path, &from_oid, &to_oid)
+    }) {
         Ok(diff) => {
             let core_diff = mado_core::types::DiffSummary {
                 files: diff
@@ -717,61 +3891,536 @@ async fn diff_milestones_handler(
                 total_insertions: diff.total_insertions,
                 total_deletions: diff.total_deletions,
             };
-            Json(DaemonResponse::DiffResult { diff: core_diff })
+            Ok(Json(DaemonResponse::DiffResult { diff: core_diff }))
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/restore",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = RestoreMilestoneBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
 async fn restore_milestone_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<RestoreMilestoneBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<RestoreMilestoneBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+
+    match state.metrics.time_git("restore_milestone", || {
+        crate::git_ops::restore_milestone(path, &body.oid)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Save a milestone and a copy of the conversation together, so both can be
+/// restored at once. With no changes since the latest milestone, that one
+/// is used.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/snapshot",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SaveMilestoneBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository, or a response is in progress", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn save_snapshot_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<SaveMilestoneBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+    ensure_conversation(&state, &session_id).await?;
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+
+    let messages = state.conversation_manager.snapshot_messages(&session_id).await?;
+    let milestone = match state
+        .metrics
+        .time_git("save_milestone", || crate::git_ops::save_milestone(path, &body.message))
+    {
+        Ok(milestone) => {
+            state.activity_feed.publish(
+                &session_id,
+                ActivityKind::MilestoneSaved {
+                    oid: milestone.oid.clone(),
+                    message: milestone.message.clone(),
+                },
+            );
+            milestone
+        }
+        Err(crate::git_ops::GitError::NothingToCommit) => crate::git_ops::list_milestones(path, 1)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ApiError::Conflict("No milestone to snapshot".to_string()))?,
+        Err(e) => return Err(e.into()),
     };
 
-    let path = std::path::Path::new(&working_dir);
+    let saved = crate::snapshot::SavedSnapshot {
+        snapshot: mado_core::types::Snapshot {
+            id: uuid::Uuid::new_v4().to_string(),
+            milestone: mado_core::types::Milestone {
+                oid: milestone.oid,
+                message: milestone.message,
+                timestamp: milestone.timestamp,
+                files_changed: milestone.files_changed,
+                insertions: milestone.insertions,
+                deletions: milestone.deletions,
+            },
+            message_count: messages.len(),
+            created_at: chrono::Utc::now(),
+        },
+        messages,
+    };
+    let dir = state.conversation_manager.snapshots_dir(&session_id);
+    let snapshot = tokio::task::spawn_blocking(move || crate::snapshot::save(&dir, &saved).map(|()| saved.snapshot))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(DaemonResponse::SnapshotSaved { snapshot }))
+}
+
+/// A session's snapshots, newest first.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/snapshots",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn list_snapshots_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return Err(ApiError::SessionNotFound(session_id.to_string()));
+    }
+    let dir = state.conversation_manager.snapshots_dir(&session_id);
+    let snapshots = tokio::task::spawn_blocking(move || crate::snapshot::list(&dir))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(DaemonResponse::Snapshots { snapshots }))
+}
+
+/// Reset the workspace to a snapshot's milestone and the conversation to its
+/// messages. The conversation continues in a new Claude session, with the
+/// messages replayed ahead of the next prompt.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/snapshot/{snap_id}/restore",
+    params(("id" = String, Path, description = "Session id"), ("snap_id" = String, Path, description = "Snapshot id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or snapshot not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository, or a response is in progress", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn restore_snapshot_handler(
+    State(state): State<AppState>,
+    AxumPath((id, snap_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+    ensure_conversation(&state, &session_id).await?;
+    let dir = state.conversation_manager.snapshots_dir(&session_id);
+    let saved = tokio::task::spawn_blocking(move || crate::snapshot::load(&dir, &snap_id))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::restore_milestone(path, &body.oid) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    // Refuse before touching the files if a response is running.
+    state.conversation_manager.snapshot_messages(&session_id).await?;
+    state.metrics.time_git("restore_milestone", || {
+        crate::git_ops::restore_milestone(path, &saved.snapshot.milestone.oid)
+    })?;
+    state
+        .conversation_manager
+        .restore_messages(&session_id, saved.messages)
+        .await?;
+    Ok(Json(DaemonResponse::Pong))
+}
+
+/// Repository root, turn and base tree of a review since `message_id`
+/// (see [`crate::review`]).
+async fn review_base(
+    state: &AppState,
+    session_id: &SessionId,
+    message_id: Option<&str>,
+) -> Result<(PathBuf, crate::review::Turn, String), ApiError> {
+    let repo_root = resolve_repo_root(state, session_id).await?;
+    ensure_conversation(state, session_id).await?;
+    let turn = state
+        .conversation_manager
+        .review_turn(session_id, message_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No turn to review".to_string()))?;
+    let trees = crate::review::turn_trees(&repo_root, session_id.as_str(), &turn.message_id)?;
+    let base = match trees {
+        Some(trees) => trees.before,
+        None => crate::review::milestone_before(&repo_root, turn.sent_at)?.ok_or_else(|| {
+            ApiError::NotFound(format!("Nothing to compare with from before message {}", turn.message_id))
+        })?,
+    };
+    Ok((repo_root, turn, base))
+}
+
+/// Files changed since a turn started, with their diffs. Changes made by
+/// hand since then are included: the workspace is compared as a whole.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/review",
+    params(("id" = String, Path, description = "Session id"), ReviewQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or message not found, or nothing to compare with", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn review_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    axum::extract::Query(params): axum::extract::Query<ReviewQuery>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let (repo_root, turn, base) = review_base(&state, &session_id, params.since_message_id.as_deref()).await?;
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+    let files = state.metrics.time_git("review", || crate::review::changes(path, &base))?;
+    Ok(Json(DaemonResponse::Review {
+        review: Review {
+            message_id: turn.message_id,
+            base,
+            files,
+        },
+    }))
+}
+
+/// Keep or revert each file of a review. Returns the review as it is
+/// afterwards, with the kept files still in it.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/review/apply",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ApplyReviewBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or message not found, or nothing to compare with", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository, or a response is in progress", body = DaemonResponse),
+        (status = 422, description = "A file is not among the review's changes", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn apply_review_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<ApplyReviewBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let (repo_root, turn, base) = review_base(&state, &session_id, body.since_message_id.as_deref()).await?;
+    // The agent may be writing the very files being reverted.
+    if turn.busy {
+        return Err(crate::conversation::ConversationError::Busy.into());
+    }
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+    let changed = state.metrics.time_git("review", || crate::review::changes(path, &base))?;
+    if let Some(decision) = body.decisions.iter().find(|d| !changed.iter().any(|f| f.path == d.path)) {
+        return Err(ApiError::Validation(format!("Not changed since the turn: {}", decision.path)));
+    }
+    let reverted: Vec<String> = body
+        .decisions
+        .into_iter()
+        .filter(|d| d.action == ReviewAction::Revert)
+        .map(|d| d.path)
+        .collect();
+    if !reverted.is_empty() {
+        state.metrics.time_git("review_revert", || crate::review::revert(path, &base, &reverted))?;
+        tracing::info!("Reverted {} files of session {}", reverted.len(), session_id);
+    }
+
+    let files = state.metrics.time_git("review", || crate::review::changes(path, &base))?;
+    Ok(Json(DaemonResponse::Review {
+        review: Review {
+            message_id: turn.message_id,
+            base,
+            files,
+        },
+    }))
+}
+
+/// Undo the file changes of one turn, that of `message_id` (the user
+/// message at or before it), keeping changes made since. Works for turns run
+/// by this daemon outside plan mode, which are snapshotted before and after.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/messages/{message_id}/revert-changes",
+    params(("id" = String, Path, description = "Session id"), ("message_id" = String, Path, description = "Message id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or message not found, or the turn has no snapshots", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository, a response is in progress, or later changes conflict", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
+async fn revert_turn_changes_handler(
+    State(state): State<AppState>,
+    AxumPath((id, message_id)): AxumPath<(String, String)>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+    ensure_conversation(&state, &session_id).await?;
+    let turn = state
+        .conversation_manager
+        .review_turn(&session_id, Some(&message_id))
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No turn at message {}", message_id)))?;
+    if turn.busy {
+        return Err(crate::conversation::ConversationError::Busy.into());
+    }
+    let (before, after) = crate::review::turn_trees(&repo_root, session_id.as_str(), &turn.message_id)?
+        .and_then(|trees| Some((trees.before, trees.after?)))
+        .ok_or_else(|| ApiError::NotFound(format!("No snapshots of the turn of message {}", turn.message_id)))?;
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+    let files = state
+        .metrics
+        .time_git("revert_turn", || crate::review::revert_turn(path, &before, &after))?;
+    tracing::info!("Reverted turn {} of session {}: {} files", turn.message_id, session_id, files.len());
+    Ok(Json(DaemonResponse::TurnReverted { files }))
+}
+
+// ── Project command endpoints ──
+
+/// The commands in the `[commands]` of the session's `.mado.toml`.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/commands",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 422, description = "Invalid .mado.toml", body = DaemonResponse),
+    ),
+    tag = "commands"
+)]
+async fn project_commands_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let commands = crate::project_config::discover(session.working_dir.as_deref())
+        .map_err(|e| ApiError::Validation(e.to_string()))?
+        .map(|(_, config)| config.commands)
+        .unwrap_or_default();
+    Ok(Json(DaemonResponse::ProjectCommands { commands }))
+}
+
+/// Run one of the project's commands in the session's workspace. Returns
+/// once it has started; follow its output on
+/// `/sessions/{id}/runs/{run_id}/stream`. With `feed_back`, a failure is
+/// sent to the conversation as a follow-up prompt.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/run",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = RunCommandBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 403, description = "The working directory isn't trusted", body = DaemonResponse),
+        (status = 404, description = "Session or command not found", body = DaemonResponse),
+        (status = 409, description = "The session is read-only, or can't be sandboxed here", body = DaemonResponse),
+        (status = 422, description = "Invalid .mado.toml or session environment", body = DaemonResponse),
+    ),
+    tag = "commands"
+)]
+async fn run_command_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<RunCommandBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    ensure_trusted(&state, session.working_dir.as_deref()).await?;
+    if session.read_only {
+        return Err(crate::runs::RunError::ReadOnly.into());
+    }
+    let (dir, command) = crate::runs::command(session.working_dir.as_deref(), &body.name)?;
+    if body.feed_back {
+        ensure_conversation(&state, &session_id).await?;
+    }
+    let env = crate::session_env::resolve(&session.env).map_err(crate::runs::RunError::from)?;
+    let sandbox = {
+        let settings = state.settings.read().unwrap_or_else(|e| e.into_inner());
+        crate::sandbox::Policy::for_session(&session.sandbox, session.working_dir.as_deref(), &settings.sandbox)
+            .map(|policy| policy.with_readable([dir.clone()]))
+    };
+
+    let run = state.runs.start(
+        state.conversation_manager.clone(),
+        crate::runs::RunOptions {
+            session_id,
+            name: body.name,
+            command,
+            dir,
+            env,
+            sandbox,
+            feed_back: body.feed_back,
+        },
+    )?;
+    Ok(Json(DaemonResponse::CommandRunStarted { run }))
+}
+
+/// The session's command runs, newest first, those in progress included.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/runs",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "commands"
+)]
+async fn list_runs_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return Err(ApiError::SessionNotFound(session_id.to_string()));
     }
+    let mut runs = state.runs.in_progress(&session_id);
+    let dir = state.conversation_manager.runs_dir(&session_id);
+    let finished = tokio::task::spawn_blocking(move || crate::runs::list(&dir))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    // A run that just finished may be in both.
+    runs.retain(|run| !finished.iter().any(|f| f.id == run.id));
+    runs.extend(finished);
+    runs.sort_by_key(|run| std::cmp::Reverse(run.started_at));
+    Ok(Json(DaemonResponse::CommandRuns { runs }))
+}
+
+/// A command run's output as `output` events, then a `complete` event with
+/// the run. Output is replayed from the start (as far back as the buffer
+/// reaches) or, with `Last-Event-ID`, from after that event. A finished
+/// run's stream is just its `complete` event.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/runs/{run_id}/stream",
+    params(("id" = String, Path, description = "Session id"), ("run_id" = String, Path, description = "Run id")),
+    responses(
+        (status = 200, description = "`output` and `complete` events carrying `RunEvent` JSON", content_type = "text/event-stream", body = String),
+        (status = 404, description = "Run not found", body = DaemonResponse),
+    ),
+    tag = "commands"
+)]
+async fn run_stream_handler(
+    State(state): State<AppState>,
+    AxumPath((id, run_id)): AxumPath<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let session_id = SessionId::new(id);
+    let run_event = |id: Option<u64>, event: RunEvent| {
+        let name = match event {
+            RunEvent::Output { .. } => "output",
+            RunEvent::Complete { .. } => "complete",
+        };
+        let mut sse = Event::default().data(serde_json::to_string(&event).unwrap_or_default()).event(name);
+        if let Some(id) = id {
+            sse = sse.id(id.to_string());
+        }
+        Ok::<_, Infallible>(sse)
+    };
+
+    let Some(events) = state.runs.events(&session_id, &run_id) else {
+        let dir = state.conversation_manager.runs_dir(&session_id);
+        let run = tokio::task::spawn_blocking(move || crate::runs::load(&dir, &run_id))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))??;
+        let complete = run_event(None, RunEvent::Complete { run });
+        return Ok(Sse::new(futures::stream::iter([complete])).into_response());
+    };
+
+    let subscription = events.subscribe(Some(last_event_id(&headers).unwrap_or(0)));
+    let missed = futures::stream::iter(subscription.missed);
+    let live = BroadcastStream::new(subscription.live).filter_map(|result| result.ok());
+    // The channel outlives the run, so end after `complete` ourselves.
+    let events = futures::StreamExt::scan(missed.chain(live), false, move |done, (id, event)| {
+        if *done {
+            return futures::future::ready(None);
+        }
+        *done = matches!(event, RunEvent::Complete { .. });
+        futures::future::ready(Some(run_event(Some(id), event)))
+    });
+    Ok(Sse::new(events).into_response())
 }
 
 // ── Change indicator endpoint ──
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/changes",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "milestones"
+)]
 async fn workspace_changes_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists before querying changes.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::workspace_changes(path) {
+    match state.metrics.time_git("workspace_changes", || crate::git_ops::workspace_changes(path)) {
         Ok(diff) => {
             let core_diff = mado_core::types::DiffSummary {
                 files: diff
@@ -787,38 +4436,37 @@ async fn workspace_changes_handler(
                 total_insertions: diff.total_insertions,
                 total_deletions: diff.total_deletions,
             };
-            Json(DaemonResponse::WorkspaceChanges { changes: core_diff })
+            Ok(Json(DaemonResponse::WorkspaceChanges { changes: core_diff }))
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(e.into()),
     }
 }
 
 // ── Git staging endpoints ──
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/git/status",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_status_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::git_status(path) {
+    match state.metrics.time_git("git_status", || crate::git_ops::git_status(path)) {
         Ok(status) => {
             let core_status = mado_core::types::GitStatus {
                 staged: status
@@ -842,238 +4490,511 @@ async fn git_status_handler(
                     })
                     .collect(),
             };
-            Json(DaemonResponse::GitStatusResult {
+            Ok(Json(DaemonResponse::GitStatusResult {
                 status: core_status,
-            })
+            }))
         }
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/git/diff",
+    params(("id" = String, Path, description = "Session id"), FileDiffQuery),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_file_diff_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
     axum::extract::Query(params): axum::extract::Query<FileDiffQuery>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
     let is_staged = params.staged.unwrap_or(false);
 
-    match crate::git_ops::git_file_diff(path, &params.file_path, is_staged) {
-        Ok(diff) => Json(DaemonResponse::FileDiffContent { diff }),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_file_diff", || {
+        crate::git_ops::git_file_diff(path, &params.file_path, is_staged)
+    }) {
+        Ok(diff) => Ok(Json(DaemonResponse::FileDiffContent { diff })),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/stage",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = StageFileBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_stage_file_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<StageFileBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<StageFileBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::git_stage_file(path, &body.file_path) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_stage_file", || {
+        crate::git_ops::git_stage_file(path, &body.file_path)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/unstage",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = StageFileBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_unstage_file_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<StageFileBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<StageFileBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::git_unstage_file(path, &body.file_path) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_unstage_file", || {
+        crate::git_ops::git_unstage_file(path, &body.file_path)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/stage-files",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = StageFilesBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_stage_files_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<StageFilesBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<StageFilesBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::git_stage_files(path, &body.file_paths) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_stage_files", || {
+        crate::git_ops::git_stage_files(path, &body.file_paths)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/unstage-files",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = StageFilesBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_unstage_files_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<StageFilesBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<StageFilesBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::git_unstage_files(path, &body.file_paths) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_unstage_files", || {
+        crate::git_ops::git_unstage_files(path, &body.file_paths)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/stage-hunk",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = StageHunkBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_stage_hunk_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-    Json(body): Json<StageHunkBody>,
-) -> Json<DaemonResponse> {
+    ApiJson(body): ApiJson<StageHunkBody>,
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    // Ensure git repo exists.
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::git_stage_hunk(path, &body.file_path, body.hunk_index) {
-        Ok(()) => Json(DaemonResponse::Pong),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+    match state.metrics.time_git("git_stage_hunk", || {
+        crate::git_ops::git_stage_hunk(path, &body.file_path, body.hunk_index)
+    }) {
+        Ok(()) => Ok(Json(DaemonResponse::Pong)),
+        Err(e) => Err(e.into()),
     }
 }
 
 // ── Git branch & push endpoints ──
 
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/git/branch-info",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_branch_info_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
-    };
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
 
-    let path = std::path::Path::new(&working_dir);
+    let path = repo_root.as_path();
     let _lock = state.workspace_locks.acquire(path).await;
 
-    if let Err(e) = crate::git_ops::init_repo(path) {
-        return Json(DaemonResponse::Error {
-            message: format!("Failed to init git repo: {}", e),
-        });
-    }
-
-    match crate::git_ops::git_branch_info(path) {
-        Ok(info) => Json(DaemonResponse::GitBranchInfo {
+    match state.metrics.time_git("git_branch_info", || crate::git_ops::git_branch_info(path)) {
+        Ok(info) => Ok(Json(DaemonResponse::GitBranchInfo {
             info: mado_core::types::BranchInfo {
                 branch: info.branch,
                 has_remote: info.has_remote,
             },
-        }),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
+        })),
+        Err(e) => Err(e.into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/push",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+        (status = 409, description = "Workspace is not a git repository", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
 async fn git_push_handler(
     State(state): State<AppState>,
     AxumPath(id): AxumPath<String>,
-) -> Json<DaemonResponse> {
+) -> ApiResult {
     let session_id = mado_core::types::SessionId::new(id);
 
-    let working_dir = match resolve_working_dir(&state, &session_id).await {
-        Ok(wd) => wd,
-        Err(resp) => return resp,
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+
+    let path = repo_root.as_path();
+    let _lock = state.workspace_locks.acquire(path).await;
+
+    match state.metrics.time_git("git_push", || crate::git_ops::git_push(path)) {
+        Ok(()) => {
+            state.activity_feed.publish(&session_id, ActivityKind::GitPushed);
+            Ok(Json(DaemonResponse::GitPushResult))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Merge the session's own branch back into the branch it was created from,
+/// or `into`, and check that out. Uncommitted changes must be saved as a
+/// milestone first. The session's branch is deleted unless `keep_branch`.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/merge",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = MergeBranchBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session or target branch not found", body = DaemonResponse),
+        (status = 409, description = "No branch of its own, uncommitted changes, or merge conflicts", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
+async fn merge_branch_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<MergeBranchBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let session = state
+        .session_manager
+        .get_session(&session_id)
+        .await
+        .ok_or_else(|| ApiError::SessionNotFound(session_id.to_string()))?;
+    let branch = session
+        .branch
+        .ok_or_else(|| ApiError::Conflict("The session has no branch of its own".to_string()))?;
+    let into = body.into.unwrap_or(branch.base);
+    let repo_root = resolve_repo_root(&state, &session_id).await?;
+
+    let path = repo_root.as_path();
+    let commit = {
+        let _lock = state.workspace_locks.acquire(path).await;
+        let commit = state
+            .metrics
+            .time_git("merge_branch", || crate::git_ops::merge_branch(path, &branch.name, &into))?;
+        if !body.keep_branch
+            && let Err(e) = crate::git_ops::delete_branch(path, &branch.name)
+        {
+            tracing::warn!("Failed to delete merged branch {}: {}", branch.name, e);
+        }
+        commit
+    };
+    let session = state.session_manager.set_branch(&session_id, None).await?;
+    state.activity_feed.publish(
+        &session_id,
+        ActivityKind::BranchMerged {
+            branch: branch.name,
+            into,
+        },
+    );
+    Ok(Json(DaemonResponse::BranchMerged { session, commit }))
+}
+
+/// The forge of the session's repository, its token and the current branch.
+async fn session_forge(
+    state: &AppState,
+    session_id: &SessionId,
+) -> Result<(crate::forge::Forge, String, String), ApiError> {
+    let repo_root = resolve_repo_root(state, session_id).await?;
+    let (branch, remote) = {
+        let path = repo_root.as_path();
+        let _lock = state.workspace_locks.acquire(path).await;
+        let info = state.metrics.time_git("git_branch_info", || crate::git_ops::git_branch_info(path))?;
+        (info.branch, crate::forge::remote_url(path)?)
+    };
+    if branch == "HEAD" {
+        return Err(ApiError::Conflict("Not on a branch".to_string()));
+    }
+    let forges = state.settings.read().unwrap_or_else(|e| e.into_inner()).forges.clone();
+    let forge = crate::forge::resolve(&remote, &forges)?;
+    // The keychain may block.
+    let (forge, token) = tokio::task::spawn_blocking(move || crate::forge::token(&forge).map(|token| (forge, token)))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok((forge, token, branch))
+}
+
+/// Open a pull request (a merge request on GitLab) for the session's
+/// branch, which must have been pushed. The forge comes from the `origin`
+/// remote and its token from the keystore. With `from_conversation`, a
+/// missing title and body are written from the conversation.
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/pull-request",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = CreatePullRequestBody,
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found, or the forge doesn't know the repository", body = DaemonResponse),
+        (status = 409, description = "Not a git repository, not on a branch, or the pull request exists", body = DaemonResponse),
+        (status = 422, description = "No title, no origin on a known forge, or no token", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
+async fn create_pull_request_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ApiJson(body): ApiJson<CreatePullRequestBody>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let (forge, token, branch) = session_forge(&state, &session_id).await?;
+
+    let described = if body.from_conversation && (body.title.is_none() || body.body.is_none()) {
+        ensure_conversation(&state, &session_id).await?;
+        let messages = state.conversation_manager.get_messages(&session_id, None, None).await?;
+        crate::forge::describe(&messages)
+    } else {
+        None
     };
+    let (described_title, described_body) = described.unzip();
+    let title = body
+        .title
+        .filter(|title| !title.trim().is_empty())
+        .or(described_title)
+        .ok_or_else(|| ApiError::Validation("A title is required".to_string()))?;
+
+    let pull_request = forge
+        .create(
+            &token,
+            &crate::forge::NewPullRequest {
+                title,
+                body: body.body.or(described_body).unwrap_or_default(),
+                head: branch,
+                base: body.base,
+                draft: body.draft,
+            },
+        )
+        .await?;
+    tracing::info!("Opened {} for session {}", pull_request.url, session_id);
+    state.activity_feed.publish(
+        &session_id,
+        ActivityKind::PullRequestCreated {
+            url: pull_request.url.clone(),
+        },
+    );
+    Ok(Json(DaemonResponse::PullRequestCreated { pull_request }))
+}
+
+/// Pull requests (merge requests on GitLab) from the session's current
+/// branch, open or not, newest first.
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/git/pull-requests",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found, or the forge doesn't know the repository", body = DaemonResponse),
+        (status = 409, description = "Not a git repository or not on a branch", body = DaemonResponse),
+        (status = 422, description = "No origin on a known forge, or no token", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
+async fn list_pull_requests_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = SessionId::new(id);
+    let (forge, token, branch) = session_forge(&state, &session_id).await?;
+    let pull_requests = forge.list(&token, &branch).await?;
+    Ok(Json(DaemonResponse::PullRequests { pull_requests }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/git/init",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
+async fn git_init_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = mado_core::types::SessionId::new(id);
+
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
 
     let path = std::path::Path::new(&working_dir);
     let _lock = state.workspace_locks.acquire(path).await;
 
-    match crate::git_ops::git_push(path) {
-        Ok(()) => Json(DaemonResponse::GitPushResult),
-        Err(e) => Json(DaemonResponse::Error {
-            message: e.to_string(),
-        }),
-    }
+    state
+        .metrics
+        .time_git("init_repo", || crate::git_ops::init_repo(path))
+        .map_err(|e| ApiError::Internal(format!("Failed to init git repo: {}", e)))?;
+
+    let repo_root = crate::git_ops::discover_repo_root(path)
+        .unwrap_or_else(|| path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+    state
+        .session_manager
+        .set_repo_root(&session_id, &repo_root)
+        .await;
+
+    Ok(Json(DaemonResponse::GitInitialized { repo_root }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/git/repos",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = DaemonResponse),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "git"
+)]
+async fn git_repos_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult {
+    let session_id = mado_core::types::SessionId::new(id);
+
+    let working_dir = resolve_working_dir(&state, &session_id).await?;
+
+    let path = PathBuf::from(working_dir);
+    let repos = tokio::task::spawn_blocking(move || crate::git_ops::find_repos(&path))
+        .await
+        .map_err(|e| ApiError::Internal(format!("Repo scan failed: {}", e)))?;
+
+    Ok(Json(DaemonResponse::GitRepos {
+        repos: repos
+            .into_iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+    }))
 }
 
-/// Resolve the working directory for a session, returning an error response if not found.
+/// Resolve the working directory for a session, returning an error if not found.
 async fn resolve_working_dir(
     state: &AppState,
     session_id: &mado_core::types::SessionId,
-) -> Result<String, Json<DaemonResponse>> {
+) -> Result<String, ApiError> {
     let session = state.session_manager.get_session(session_id).await;
     match session {
         Some(s) => Ok(s.working_dir.unwrap_or_else(|| {
@@ -1081,12 +5002,25 @@ async fn resolve_working_dir(
                 .map(|h| h.to_string_lossy().to_string())
                 .unwrap_or_else(|| "/tmp".to_string())
         })),
-        None => Err(Json(DaemonResponse::Error {
-            message: format!("Session not found: {}", session_id),
-        })),
+        None => Err(ApiError::SessionNotFound(session_id.to_string())),
     }
 }
 
+/// Resolve the git repository root for a session's working directory.
+///
+/// Walks up from the working directory to the enclosing repo so a session
+/// started in a subdirectory operates on the existing repo. Returns a
+/// `NotARepository` response when there is none; repos are only created by
+/// an explicit `POST /sessions/{id}/git/init`.
+async fn resolve_repo_root(
+    state: &AppState,
+    session_id: &mado_core::types::SessionId,
+) -> Result<PathBuf, ApiError> {
+    let working_dir = resolve_working_dir(state, session_id).await?;
+    crate::git_ops::discover_repo_root(Path::new(&working_dir))
+        .ok_or(ApiError::NotARepository(working_dir))
+}
+
 // ── Utility functions ──
 
 async fn ensure_dir(dir: &Path) -> Result<(), ServerError> {
@@ -1158,4 +5092,10 @@ pub enum ServerError {
 
     #[error("Server error: {0}")]
     ServeFailed(std::io::Error),
+
+    #[error("TCP listener error: {0}")]
+    Remote(#[from] RemoteError),
+
+    #[error("A TCP listener requires an auth token")]
+    TcpWithoutToken,
 }
@@ -0,0 +1,362 @@
+//! Running the daemon as a per-user service.
+//!
+//! `mado-daemon install-service` writes a launchd agent (macOS) or a systemd
+//! user unit (Linux) that starts the current binary in the foreground with
+//! explicit socket/PID/state paths, and registers it with the service
+//! manager. The desktop app then starts or restarts the service rather than
+//! spawning a daemon of its own.
+//!
+//! The service manager only restarts the daemon when it fails: a clean exit
+//! is either a deliberate stop or a graceful restart, whose successor (and
+//! the PTY processes) must outlive the process the service started.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use tracing;
+
+/// launchd label, and the plist's file name.
+pub const LAUNCHD_LABEL: &str = "com.tensakulabs.mado.daemon";
+
+/// systemd user unit name.
+pub const SYSTEMD_UNIT: &str = "mado-daemon.service";
+
+/// What the service runs.
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    /// Absolute path of the daemon binary.
+    pub binary: PathBuf,
+    /// Arguments passed to the daemon.
+    pub args: Vec<String>,
+}
+
+/// Service manager of the current platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceManager {
+    Launchd,
+    Systemd,
+}
+
+/// Whether the service is installed and running.
+#[derive(Debug, Clone)]
+pub struct ServiceStatus {
+    pub manager: ServiceManager,
+    /// Path of the plist or unit file.
+    pub path: PathBuf,
+    pub installed: bool,
+    pub running: bool,
+}
+
+impl ServiceManager {
+    /// The service manager for this platform.
+    pub fn current() -> Result<Self, ServiceError> {
+        if cfg!(target_os = "macos") {
+            Ok(Self::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Self::Systemd)
+        } else {
+            Err(ServiceError::Unsupported)
+        }
+    }
+
+    /// Where the plist or unit file lives.
+    pub fn path(self) -> Result<PathBuf, ServiceError> {
+        let home = dirs::home_dir().ok_or(ServiceError::NoHomeDir)?;
+        Ok(match self {
+            Self::Launchd => home
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+            Self::Systemd => dirs::config_dir()
+                .unwrap_or_else(|| home.join(".config"))
+                .join("systemd/user")
+                .join(SYSTEMD_UNIT),
+        })
+    }
+
+    /// Contents of the plist or unit file for `spec`.
+    pub fn render(self, spec: &ServiceSpec) -> String {
+        match self {
+            Self::Launchd => render_launchd(spec),
+            Self::Systemd => render_systemd(spec),
+        }
+    }
+
+    /// Write the service file, register it and start it.
+    pub fn install(self, spec: &ServiceSpec) -> Result<PathBuf, ServiceError> {
+        let path = self.path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|source| ServiceError::Write {
+                path: path.clone(),
+                source,
+            })?;
+        }
+
+        // Re-installing replaces the service, e.g. after the binary moved.
+        if path.exists() {
+            self.unregister();
+        }
+        std::fs::write(&path, self.render(spec)).map_err(|source| ServiceError::Write {
+            path: path.clone(),
+            source,
+        })?;
+
+        match self {
+            Self::Launchd => {
+                run("launchctl", &["bootstrap", &gui_domain(), &path.to_string_lossy()])?;
+            }
+            Self::Systemd => {
+                run("systemctl", &["--user", "daemon-reload"])?;
+                run("systemctl", &["--user", "enable", "--now", SYSTEMD_UNIT])?;
+            }
+        }
+        tracing::info!("Installed service at {}", path.display());
+        Ok(path)
+    }
+
+    /// Stop and remove the service. Returns false if it was not installed.
+    pub fn uninstall(self) -> Result<bool, ServiceError> {
+        let path = self.path()?;
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        self.unregister();
+        std::fs::remove_file(&path).map_err(|source| ServiceError::Write {
+            path: path.clone(),
+            source,
+        })?;
+        if self == Self::Systemd {
+            run("systemctl", &["--user", "daemon-reload"])?;
+        }
+        tracing::info!("Removed service at {}", path.display());
+        Ok(true)
+    }
+
+    /// Whether the service is installed and running.
+    pub fn status(self) -> Result<ServiceStatus, ServiceError> {
+        let path = self.path()?;
+        let installed = path.exists();
+        let running = installed
+            && match self {
+                Self::Launchd => output("launchctl", &["print", &launchd_target()])
+                    .is_some_and(|out| out.contains("state = running")),
+                Self::Systemd => output("systemctl", &["--user", "is-active", SYSTEMD_UNIT])
+                    .is_some_and(|out| out.trim() == "active"),
+            };
+
+        Ok(ServiceStatus {
+            manager: self,
+            path,
+            installed,
+            running,
+        })
+    }
+
+    /// Start the installed service, or restart it if `restart` is set.
+    pub fn start(self, restart: bool) -> Result<(), ServiceError> {
+        match self {
+            Self::Launchd => {
+                let target = launchd_target();
+                let mut args = vec!["kickstart"];
+                if restart {
+                    args.push("-k");
+                }
+                args.push(&target);
+                run("launchctl", &args)
+            }
+            Self::Systemd => run(
+                "systemctl",
+                &["--user", if restart { "restart" } else { "start" }, SYSTEMD_UNIT],
+            ),
+        }
+    }
+
+    /// Stop and deregister the service, ignoring failures (it may not be loaded).
+    fn unregister(self) {
+        let result = match self {
+            Self::Launchd => run("launchctl", &["bootout", &launchd_target()]),
+            Self::Systemd => run("systemctl", &["--user", "disable", "--now", SYSTEMD_UNIT]),
+        };
+        if let Err(e) = result {
+            tracing::debug!("Ignoring failure to deregister service: {}", e);
+        }
+    }
+}
+
+/// Whether the daemon is installed as a service on this platform.
+pub fn is_installed() -> bool {
+    ServiceManager::current()
+        .and_then(ServiceManager::path)
+        .is_ok_and(|path| path.exists())
+}
+
+fn render_launchd(spec: &ServiceSpec) -> String {
+    let program_arguments: String = std::iter::once(spec.binary.to_string_lossy().into_owned())
+        .chain(spec.args.iter().cloned())
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(&arg)))
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{program_arguments}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>AbandonProcessGroup</key>
+    <true/>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+</dict>
+</plist>
+"#,
+        label = LAUNCHD_LABEL,
+    )
+}
+
+fn render_systemd(spec: &ServiceSpec) -> String {
+    let exec_start = std::iter::once(spec.binary.to_string_lossy().into_owned())
+        .chain(spec.args.iter().cloned())
+        .map(|arg| systemd_quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "[Unit]
+Description=Mado daemon
+
+[Service]
+Type=simple
+ExecStart={exec_start}
+Restart=on-failure
+KillMode=process
+
+[Install]
+WantedBy=default.target
+"
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quote a word for `ExecStart=`: double quotes, with `\`, `"` and
+/// specifier `%` escaped.
+fn systemd_quote(s: &str) -> String {
+    let escaped = s.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+fn gui_domain() -> String {
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+fn launchd_target() -> String {
+    format!("{}/{}", gui_domain(), LAUNCHD_LABEL)
+}
+
+/// Run a service manager command, failing on a non-zero exit.
+fn run(program: &str, args: &[&str]) -> Result<(), ServiceError> {
+    let out = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|source| ServiceError::Command {
+            command: format!("{} {}", program, args.join(" ")),
+            source,
+        })?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(ServiceError::Failed {
+            command: format!("{} {}", program, args.join(" ")),
+            stderr: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        })
+    }
+}
+
+/// Stdout of a command, or `None` if it could not run or failed.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Errors from installing or controlling the service.
+#[derive(Debug, thiserror::Error)]
+pub enum ServiceError {
+    #[error("Running as a service is only supported on macOS (launchd) and Linux (systemd)")]
+    Unsupported,
+
+    #[error("Could not determine home directory")]
+    NoHomeDir,
+
+    #[error("Failed to write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to run `{command}`: {source}")]
+    Command {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("`{command}` failed: {stderr}")]
+    Failed { command: String, stderr: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ServiceSpec {
+        ServiceSpec {
+            binary: PathBuf::from("/Applications/Mado Beta.app/Contents/MacOS/mado-daemon"),
+            args: vec![
+                "--foreground".to_string(),
+                "--socket-path".to_string(),
+                "/home/u/.mado/mado.sock".to_string(),
+                "--log-level".to_string(),
+                "mado=debug,100%".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit_quotes_exec_start() {
+        let unit = ServiceManager::Systemd.render(&spec());
+        assert!(unit.contains(
+            "ExecStart=\"/Applications/Mado Beta.app/Contents/MacOS/mado-daemon\" \"--foreground\" \
+             \"--socket-path\" \"/home/u/.mado/mado.sock\" \"--log-level\" \"mado=debug,100%%\"\n"
+        ));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+    }
+
+    #[test]
+    fn test_launchd_plist_lists_arguments() {
+        let plist = ServiceManager::Launchd.render(&spec());
+        assert!(plist.contains(&format!("<string>{}</string>", LAUNCHD_LABEL)));
+        assert!(plist.contains(
+            "        <string>/Applications/Mado Beta.app/Contents/MacOS/mado-daemon</string>\n\
+             \x20       <string>--foreground</string>\n"
+        ));
+        assert!(plist.contains("<key>SuccessfulExit</key>\n        <false/>"));
+    }
+}
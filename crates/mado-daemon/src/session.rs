@@ -1,20 +1,38 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use chrono::Utc;
-use tokio::sync::{broadcast, Mutex};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use tokio::sync::Mutex;
 use tracing;
 use uuid::Uuid;
 
-use mado_core::types::{PtySize, Session, SessionId, SessionStatus};
+use mado_core::types::{
+    ActivityKind, CustomCommand, PermissionMode, PtySize, SandboxSettings, Session, SessionActivity, SessionBranch, SessionId,
+    SessionSettings, SessionStatus, SessionUpdate,
+};
 
-use crate::process::{ProcessError, SharedProcessManager};
+use crate::claude_cli::ClaudeCli;
+use crate::config::SharedSettings;
+use crate::feed::SharedActivityFeed;
+use crate::handover::PtyHandover;
+use crate::process::{ProcessError, ProcessManager, PtyActivity, PtySubscription, SharedProcessManager};
+use crate::respawn::RespawnTracker;
+use crate::sandbox::Policy;
+use crate::session_env::EnvError;
 use crate::state::DaemonState;
 
 /// Manages session lifecycle and coordinates with ProcessManager.
+///
+/// Clones share the same state.
+#[derive(Clone)]
 pub struct SessionManager {
     state: Arc<Mutex<DaemonState>>,
     process_manager: SharedProcessManager,
     state_path: Option<std::path::PathBuf>,
+    activity_feed: Option<SharedActivityFeed>,
+    settings: SharedSettings,
+    respawns: Arc<RespawnTracker>,
 }
 
 impl SessionManager {
@@ -23,6 +41,9 @@ impl SessionManager {
             state,
             process_manager,
             state_path: None,
+            activity_feed: None,
+            settings: SharedSettings::default(),
+            respawns: Arc::default(),
         }
     }
 
@@ -32,13 +53,164 @@ impl SessionManager {
         self
     }
 
-    /// Create a new session with a Claude CLI (or fallback shell) process.
+    /// Publish process exits to the daemon-wide activity feed.
+    pub fn with_activity_feed(mut self, feed: SharedActivityFeed) -> Self {
+        self.activity_feed = Some(feed);
+        self
+    }
+
+    /// Read the API key for spawned processes from these settings.
+    pub fn with_settings(mut self, settings: SharedSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    fn api_key(&self) -> Option<String> {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).api_key.clone()
+    }
+
+    /// Environment for a spawned process: provider keys and base URLs, then
+    /// the session's own `env`, which wins.
+    fn spawn_env(&self, env: &HashMap<String, String>) -> Result<Vec<(String, String)>, EnvError> {
+        let mut resolved = self.settings.read().unwrap_or_else(|e| e.into_inner()).provider_env.clone();
+        resolved.extend(crate::session_env::resolve(env)?);
+        Ok(resolved)
+    }
+
+    /// The Claude CLI for a session with `flags` of its own.
+    fn claude_cli(&self, flags: &[String]) -> ClaudeCli {
+        let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+        ClaudeCli::new(settings.claude_path.clone(), &settings.default_flags, flags)
+    }
+
+    /// What the processes of a session working in `working_dir` are
+    /// confined to, if it is sandboxed. Its `mcp_config` files can be read.
+    fn sandbox_policy(&self, sandbox: &SandboxSettings, working_dir: Option<&str>, mcp_config: &[String]) -> Option<Policy> {
+        let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+        Policy::for_session(sandbox, working_dir, &settings.sandbox)
+            .map(|policy| policy.with_readable(mcp_config.iter().map(std::path::PathBuf::from)))
+    }
+
+    fn scrollback_bytes(&self) -> usize {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).scrollback_bytes
+    }
+
+    /// Where terminal recordings are kept, next to the state file.
+    pub fn recordings_dir(&self) -> Option<std::path::PathBuf> {
+        Some(self.state_path.as_ref()?.with_file_name("recordings"))
+    }
+
+    /// Where a session's terminal recording is kept.
+    pub fn recording_path(&self, id: &SessionId) -> Option<std::path::PathBuf> {
+        Some(crate::recording::path(&self.recordings_dir()?, id.as_str()))
+    }
+
+    /// Start recording a session's new process, if recordings are enabled.
+    fn start_recording(&self, pm: &ProcessManager, id: &SessionId) {
+        let recording = self.settings.read().unwrap_or_else(|e| e.into_inner()).recording.clone();
+        if !recording.enabled {
+            return;
+        }
+        let Some(path) = self.recording_path(id) else {
+            return;
+        };
+        if let Err(e) = pm.record(id, &path, recording.max_mb.saturating_mul(1024 * 1024)) {
+            tracing::warn!("Not recording session {}: {}", id, e);
+        }
+    }
+
+    /// Once a session's process exits on its own, mark the session
+    /// terminated with the exit code, publish `ProcessExited`, and respawn
+    /// it if it crashed and the respawn policy allows.
+    async fn watch_exit(&self, session_id: &SessionId) {
+        let Some(mut exited) = self.process_manager.lock().await.exit_watch(session_id) else {
+            return;
+        };
+
+        let manager = self.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let Ok(exit) = exited.wait_for(Option::is_some).await.map(|exit| exit.unwrap_or_default()) else {
+                return;
+            };
+            // Destroyed and restarted sessions lose their process before the
+            // kill lands; skip those.
+            let current = manager.process_manager.lock().await.exit_watch(&session_id);
+            if !current.is_some_and(|current| current.same_channel(&exited)) {
+                return;
+            }
+
+            {
+                let mut state = manager.state.lock().await;
+                if let Some(session) = state.sessions.get_mut(session_id.as_str()) {
+                    session.status = SessionStatus::Terminated;
+                    session.exit_code = exit.code;
+                    session.updated_at = Utc::now();
+                }
+                if let Some(ref state_path) = manager.state_path
+                    && let Err(e) = state.save(state_path)
+                {
+                    tracing::error!("Failed to persist daemon state: {}", e);
+                }
+            }
+            if let Some(ref feed) = manager.activity_feed {
+                feed.publish(&session_id, ActivityKind::ProcessExited { code: exit.code });
+            }
+            if exit.code != Some(0) {
+                manager.respawn_after_crash(&session_id).await;
+            }
+        });
+    }
+
+    /// Revive a session whose process crashed, after a delay, if the
+    /// respawn policy allows.
+    ///
+    /// Boxed because reviving watches the new process, which may land here
+    /// again.
+    fn respawn_after_crash<'a>(&'a self, id: &'a SessionId) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let policy = self.settings.read().unwrap_or_else(|e| e.into_inner()).respawn.clone();
+            if !policy.enabled {
+                return;
+            }
+            let Some(delay) = self.respawns.next_delay(id.as_str(), policy.max_retries) else {
+                tracing::warn!(
+                    "Session {} crashed {} times in a row; not respawning it",
+                    id,
+                    policy.max_retries
+                );
+                return;
+            };
+
+            tokio::time::sleep(delay).await;
+            // Restarted, revived or destroyed in the meantime.
+            if self.process_manager.lock().await.is_running(id) || self.get_session(id).await.is_none() {
+                return;
+            }
+            match self.revive_session(id, PtySize { rows: 24, cols: 80 }).await {
+                Ok(_) => tracing::info!("Respawned crashed process of session {}", id),
+                Err(e) => tracing::warn!("Failed to respawn session {}: {}", id, e),
+            }
+        })
+    }
+
+    /// Create a new session with a Claude CLI (or fallback shell) process,
+    /// or with `custom_command` if given. A `read_only` session's process
+    /// starts in plan mode (see [`crate::read_only`]).
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_session(
         &self,
         name: String,
         model: String,
         pty_size: PtySize,
         cwd: Option<String>,
+        system_prompt: Option<String>,
+        permission_mode: PermissionMode,
+        read_only: bool,
+        sandbox: SandboxSettings,
+        custom_command: Option<CustomCommand>,
+        mcp_config: Vec<String>,
+        env: HashMap<String, String>,
     ) -> Result<Session, SessionError> {
         let session_id = SessionId::new(Uuid::new_v4().to_string());
         let now = Utc::now();
@@ -59,17 +231,31 @@ impl SessionManager {
         };
 
         // Spawn the PTY process with Claude CLI.
+        let resolved_env = self.spawn_env(&env)?;
+        let sandbox_policy = self.sandbox_policy(&sandbox, Some(&working_dir), &mcp_config);
+        let api_key = self.api_key();
+        let scrollback_bytes = self.scrollback_bytes();
         let spawn_result = {
             let mut pm = self.process_manager.lock().await;
-            pm.create(
+            let result = pm.create(
                 &session_id,
                 &model,
+                crate::read_only::permission_mode(permission_mode, read_only),
                 pty_size.rows,
                 pty_size.cols,
                 Some(&working_dir),
-                None, // api_key - from keystore
+                api_key.as_deref(),
+                None,
+                custom_command.as_ref(),
+                &self.claude_cli(&[]),
+                &crate::project_config::mcp_config(&mcp_config, &crate::project_config::for_dir(Some(&working_dir))),
+                &resolved_env,
+                sandbox_policy.as_ref(),
+                scrollback_bytes,
             )
-            .map_err(SessionError::ProcessError)?
+            .map_err(SessionError::ProcessError)?;
+            self.start_recording(&pm, &session_id);
+            result
         };
 
         let session = Session {
@@ -79,15 +265,36 @@ impl SessionManager {
             status: SessionStatus::Active,
             created_at: now,
             updated_at: now,
+            repo_root: crate::git_ops::discover_repo_root(std::path::Path::new(&working_dir))
+                .map(|p| p.to_string_lossy().to_string()),
             working_dir: Some(working_dir),
             command: Some(spawn_result.command),
             shell_fallback: spawn_result.shell_fallback,
+            custom_command,
+            env,
+            activity: SessionActivity::Idle,
+            is_busy: false,
+            last_activity: None,
             // Chat mode fields (initialized to defaults).
             conversation_state: mado_core::types::ConversationState::Empty,
             claude_session_id: None,
             message_count: 0,
             total_usage: None,
             total_cost_usd: None,
+            system_prompt,
+            permission_mode,
+            read_only,
+            sandbox,
+            extended_thinking: false,
+            tags: Vec::new(),
+            color: None,
+            pinned: false,
+            notes: None,
+            mcp_config,
+            claude_flags: Vec::new(),
+            auto_milestone: None,
+            exit_code: None,
+            branch: None,
         };
 
         // Persist the session.
@@ -95,6 +302,7 @@ impl SessionManager {
             let mut state = self.state.lock().await;
             state.add_session(session.clone());
         }
+        self.watch_exit(&session.id).await;
 
         tracing::info!(
             "Created session: {} ({}) [fallback={}]",
@@ -105,10 +313,170 @@ impl SessionManager {
         Ok(session)
     }
 
+    /// Respawn the process of a session whose process is gone (e.g. after a
+    /// daemon restart), resuming its Claude conversation in its working directory.
+    /// Sessions with a custom command run it again.
+    pub async fn revive_session(
+        &self,
+        id: &SessionId,
+        pty_size: PtySize,
+    ) -> Result<Session, SessionError> {
+        let api_key = self.api_key();
+        let scrollback_bytes = self.scrollback_bytes();
+        let mut state = self.state.lock().await;
+        let session = state
+            .sessions
+            .get_mut(id.as_str())
+            .ok_or_else(|| SessionError::NotFound(id.to_string()))?;
+
+        let resolved_env = self.spawn_env(&session.env)?;
+        let sandbox_policy = self.sandbox_policy(&session.sandbox, session.working_dir.as_deref(), &session.mcp_config);
+        let spawn_result = {
+            let mut pm = self.process_manager.lock().await;
+            if pm.is_running(id) {
+                return Err(SessionError::AlreadyRunning(id.to_string()));
+            }
+            let result = pm.create(
+                id,
+                &session.model,
+                crate::read_only::permission_mode(session.permission_mode, session.read_only),
+                pty_size.rows,
+                pty_size.cols,
+                session.working_dir.as_deref(),
+                api_key.as_deref(),
+                session.claude_session_id.as_deref(),
+                session.custom_command.as_ref(),
+                &self.claude_cli(&session.claude_flags),
+                &crate::project_config::mcp_config(
+                    &session.mcp_config,
+                    &crate::project_config::for_dir(session.working_dir.as_deref()),
+                ),
+                &resolved_env,
+                sandbox_policy.as_ref(),
+                scrollback_bytes,
+            )?;
+            self.start_recording(&pm, id);
+            result
+        };
+
+        session.status = SessionStatus::Active;
+        session.exit_code = None;
+        session.updated_at = Utc::now();
+        session.command = Some(spawn_result.command);
+        session.shell_fallback = spawn_result.shell_fallback;
+        let session = session.clone();
+
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        drop(state);
+        self.watch_exit(id).await;
+
+        tracing::info!("Revived session: {} ({})", session.id, session.name);
+        Ok(session)
+    }
+
+    /// Kill a session's process, if it has one, and spawn a new one as
+    /// `revive_session` does. Starts the crash respawn count over.
+    pub async fn restart_session(
+        &self,
+        id: &SessionId,
+        pty_size: PtySize,
+    ) -> Result<Session, SessionError> {
+        if self.get_session(id).await.is_none() {
+            return Err(SessionError::NotFound(id.to_string()));
+        }
+        {
+            let mut pm = self.process_manager.lock().await;
+            if pm.has_process(id) {
+                pm.destroy(id)?;
+            }
+        }
+        self.respawns.reset(id.as_str());
+        self.revive_session(id, pty_size).await
+    }
+
+    /// Kill a session's process and mark the session terminated, without
+    /// respawning it.
+    pub async fn terminate_process(&self, id: &SessionId) -> Result<(), SessionError> {
+        {
+            let mut pm = self.process_manager.lock().await;
+            if pm.has_process(id) {
+                pm.destroy(id)?;
+            }
+        }
+        let mut state = self.state.lock().await;
+        let session = state
+            .sessions
+            .get_mut(id.as_str())
+            .ok_or_else(|| SessionError::NotFound(id.to_string()))?;
+        session.status = SessionStatus::Terminated;
+        session.exit_code = None;
+        session.updated_at = Utc::now();
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Reconcile persisted sessions with the processes actually running, at
+    /// startup. Sessions without a process are marked terminated, or revived
+    /// when `respawn` is set.
+    pub async fn reconcile(&self, respawn: bool) {
+        let dead: Vec<SessionId> = {
+            let state = self.state.lock().await;
+            let pm = self.process_manager.lock().await;
+            state
+                .sessions
+                .values()
+                .filter(|s| s.status != SessionStatus::Terminated && !pm.has_process(&s.id))
+                .map(|s| s.id.clone())
+                .collect()
+        };
+        if dead.is_empty() {
+            return;
+        }
+
+        let mut revived = 0;
+        for id in &dead {
+            if respawn {
+                match self.revive_session(id, PtySize { rows: 24, cols: 80 }).await {
+                    Ok(_) => {
+                        revived += 1;
+                        continue;
+                    }
+                    Err(e) => tracing::warn!("Failed to revive session {}: {}", id, e),
+                }
+            }
+            let mut state = self.state.lock().await;
+            if let Some(session) = state.sessions.get_mut(id.as_str()) {
+                session.status = SessionStatus::Terminated;
+            }
+        }
+
+        let state = self.state.lock().await;
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        tracing::info!(
+            "Reconciled {} sessions without a process ({} revived)",
+            dead.len(),
+            revived
+        );
+    }
+
     /// List all sessions.
     pub async fn list_sessions(&self) -> Vec<Session> {
         let state = self.state.lock().await;
-        state.sessions.values().cloned().collect()
+        let mut sessions: Vec<Session> = state.sessions.values().cloned().collect();
+        sessions.sort_by_key(|s| (!s.pinned, s.created_at));
+        sessions
     }
 
     /// Get a specific session.
@@ -132,19 +500,23 @@ impl SessionManager {
             let mut state = self.state.lock().await;
             state.remove_session(id);
         }
+        if let Some(path) = self.recording_path(id) {
+            let _ = std::fs::remove_file(path);
+        }
 
         tracing::info!("Destroyed session: {}", id);
         Ok(())
     }
 
-    /// Write input to a session's PTY.
+    /// Write input to a session's PTY, as a bracketed paste with `paste`.
     pub async fn write_input(
         &self,
         id: &SessionId,
         data: &[u8],
+        paste: bool,
     ) -> Result<(), SessionError> {
         let mut pm = self.process_manager.lock().await;
-        pm.write_input(id, data)
+        pm.write_input(id, data, paste)
             .map_err(SessionError::ProcessError)
     }
 
@@ -160,14 +532,316 @@ impl SessionManager {
             .map_err(SessionError::ProcessError)
     }
 
-    /// Subscribe to output from a session's PTY.
-    pub async fn subscribe_output(
+    /// Pause every PTY reader and describe the PTYs for a successor daemon
+    /// (see `crate::handover`).
+    pub async fn handover_ptys(&self) -> std::io::Result<Vec<PtyHandover>> {
+        self.process_manager.lock().await.handover()
+    }
+
+    /// Resume PTY readers after a failed handover, or adopted ones once the
+    /// predecessor has exited.
+    pub async fn resume_ptys(&self) {
+        self.process_manager.lock().await.resume_readers();
+    }
+
+    /// Take over PTYs from a predecessor daemon. PTYs of sessions that are no
+    /// longer in the state are killed.
+    pub async fn adopt_ptys(&self, ptys: Vec<PtyHandover>) {
+        let mut adopted = Vec::new();
+        let scrollback_bytes = self.scrollback_bytes();
+        {
+            let state = self.state.lock().await;
+            let mut pm = self.process_manager.lock().await;
+            for pty in ptys {
+                let session_id = SessionId::new(pty.session_id.clone());
+                if state.get_session(&session_id).is_none() {
+                    tracing::warn!("Handed-over PTY for unknown session {}, killing it", session_id);
+                    if let Some(pid) = pty.pid {
+                        unsafe {
+                            libc::kill(pid as i32, libc::SIGKILL);
+                        }
+                    }
+                    crate::process::close_inherited(&pty);
+                    continue;
+                }
+                match pm.adopt(pty, scrollback_bytes) {
+                    Ok(()) => {
+                        self.start_recording(&pm, &session_id);
+                        adopted.push(session_id);
+                    }
+                    Err(e) => tracing::error!("Failed to adopt PTY for session {}: {}", session_id, e),
+                }
+            }
+        }
+        for session_id in &adopted {
+            self.watch_exit(session_id).await;
+        }
+    }
+
+    /// Whether a session has a running process.
+    pub async fn has_process(&self, id: &SessionId) -> bool {
+        self.process_manager.lock().await.has_process(id)
+    }
+
+    /// Pids of the running PTY processes, by session.
+    pub async fn process_pids(&self) -> Vec<(SessionId, u32)> {
+        self.process_manager.lock().await.pids()
+    }
+
+    /// Number of sessions with a PTY process.
+    pub async fn process_count(&self) -> usize {
+        self.process_manager.lock().await.count()
+    }
+
+    /// Get the PTY activity tracker for a session, if it has a live process.
+    pub async fn pty_activity(&self, id: &SessionId) -> Option<Arc<PtyActivity>> {
+        let pm = self.process_manager.lock().await;
+        pm.activity(id)
+    }
+
+    /// Set a session's activity classification and move its
+    /// `last_activity` forward to `last_activity`. Returns true if the
+    /// classification changed.
+    ///
+    /// Not persisted immediately: activity is runtime state and is recomputed
+    /// by the presence monitor after a restart.
+    pub async fn set_activity(
+        &self,
+        id: &SessionId,
+        activity: SessionActivity,
+        last_activity: Option<DateTime<Utc>>,
+    ) -> bool {
+        let mut state = self.state.lock().await;
+        let Some(session) = state.sessions.get_mut(id.as_str()) else {
+            return false;
+        };
+        session.last_activity = session.last_activity.max(last_activity);
+        if session.activity == activity {
+            return false;
+        }
+        session.activity = activity;
+        session.is_busy = activity == SessionActivity::Streaming;
+        true
+    }
+
+    /// Record the repository root for a session and persist to disk.
+    pub async fn set_repo_root(&self, id: &SessionId, repo_root: &str) {
+        let mut state = self.state.lock().await;
+        if let Some(session) = state.sessions.get_mut(id.as_str()) {
+            session.repo_root = Some(repo_root.to_string());
+            if let Some(ref state_path) = self.state_path
+                && let Err(e) = state.save(state_path)
+            {
+                tracing::error!("Failed to persist daemon state: {}", e);
+            }
+        }
+    }
+
+    /// Record the branch created for a session, or clear it once merged,
+    /// and persist to disk. Returns the updated session.
+    pub async fn set_branch(
+        &self,
+        id: &SessionId,
+        branch: Option<SessionBranch>,
+    ) -> Result<Session, SessionError> {
+        let mut state = self.state.lock().await;
+        let session = state
+            .sessions
+            .get_mut(id.as_str())
+            .ok_or_else(|| SessionError::NotFound(id.to_string()))?;
+        session.branch = branch;
+        session.updated_at = Utc::now();
+        let session = session.clone();
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        Ok(session)
+    }
+
+    /// Subscribe to a session's PTY with scrollback replay and exit notification.
+    ///
+    /// `resume_from` is a stream offset from an earlier subscription; see
+    /// `ManagedProcess::subscribe_with_scrollback`.
+    pub async fn subscribe_pty(
         &self,
         id: &SessionId,
-    ) -> Result<broadcast::Receiver<Vec<u8>>, SessionError> {
+        resume_from: Option<u64>,
+    ) -> Result<PtySubscription, SessionError> {
         let pm = self.process_manager.lock().await;
-        pm.subscribe_output(id)
-            .map_err(SessionError::ProcessError)
+        pm.subscribe_pty(id, resume_from).map_err(SessionError::ProcessError)
+    }
+
+    /// A session's PTY scrollback and the stream offset at its end.
+    pub async fn scrollback(&self, id: &SessionId) -> Result<(Vec<u8>, u64), SessionError> {
+        let pm = self.process_manager.lock().await;
+        pm.scrollback(id).map_err(SessionError::ProcessError)
+    }
+
+    /// Apply a `PATCH /sessions/{id}` update and persist to disk.
+    pub async fn update_session(
+        &self,
+        id: &SessionId,
+        update: SessionUpdate,
+    ) -> Result<Session, SessionError> {
+        if update.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+            return Err(SessionError::InvalidUpdate("name must not be empty".to_string()));
+        }
+        if update.model.as_deref().is_some_and(|model| model.trim().is_empty()) {
+            return Err(SessionError::InvalidUpdate("model must not be empty".to_string()));
+        }
+        if let Some(flags) = &update.claude_flags {
+            crate::claude_cli::validate_flags(flags).map_err(|e| SessionError::InvalidUpdate(e.to_string()))?;
+        }
+        if let Some(sandbox) = &update.sandbox {
+            crate::sandbox::check_hosts(&sandbox.allowed_hosts).map_err(SessionError::InvalidUpdate)?;
+        }
+        if let Some(color) = update.color.as_deref()
+            && !color.is_empty()
+            && !is_hex_color(color)
+        {
+            return Err(SessionError::InvalidUpdate(format!(
+                "color must be #rgb or #rrggbb: {}",
+                color
+            )));
+        }
+        let repo_root = match &update.working_dir {
+            Some(dir) if !std::path::Path::new(dir).is_dir() => {
+                return Err(SessionError::InvalidUpdate(format!("not a directory: {}", dir)));
+            }
+            Some(dir) => Some(
+                crate::git_ops::discover_repo_root(std::path::Path::new(dir))
+                    .map(|p| p.to_string_lossy().to_string()),
+            ),
+            None => None,
+        };
+
+        let mut state = self.state.lock().await;
+        let session = state
+            .sessions
+            .get_mut(id.as_str())
+            .ok_or_else(|| SessionError::NotFound(id.to_string()))?;
+        // Turns run in a copy of the repository, so there must be one.
+        let read_only = update.read_only.unwrap_or(session.read_only);
+        if read_only && repo_root.clone().unwrap_or_else(|| session.repo_root.clone()).is_none() {
+            return Err(SessionError::InvalidUpdate(
+                "read-only mode needs the working directory to be in a git repository".to_string(),
+            ));
+        }
+        session.read_only = read_only;
+        if let Some(name) = update.name {
+            session.name = name;
+        }
+        if let Some(model) = update.model {
+            session.model = model;
+        }
+        if let Some(dir) = update.working_dir {
+            session.working_dir = Some(dir);
+            session.repo_root = repo_root.flatten();
+        }
+        if let Some(prompt) = update.system_prompt {
+            session.system_prompt = Some(prompt).filter(|p| !p.trim().is_empty());
+        }
+        if let Some(mode) = update.permission_mode {
+            session.permission_mode = mode;
+        }
+        if let Some(sandbox) = update.sandbox {
+            session.sandbox = sandbox;
+        }
+        if let Some(extended_thinking) = update.extended_thinking {
+            session.extended_thinking = extended_thinking;
+        }
+        if let Some(tags) = update.tags {
+            session.tags.clear();
+            for tag in tags {
+                let tag = tag.trim();
+                if !tag.is_empty() && !session.tags.iter().any(|t| t == tag) {
+                    session.tags.push(tag.to_string());
+                }
+            }
+        }
+        if let Some(color) = update.color {
+            session.color = Some(color).filter(|c| !c.is_empty());
+        }
+        if let Some(pinned) = update.pinned {
+            session.pinned = pinned;
+        }
+        if let Some(notes) = update.notes {
+            session.notes = Some(notes).filter(|n| !n.trim().is_empty());
+        }
+        if let Some(auto_milestone) = update.auto_milestone {
+            session.auto_milestone = Some(auto_milestone);
+        }
+        if let Some(flags) = update.claude_flags {
+            session.claude_flags = flags;
+        }
+        session.updated_at = Utc::now();
+        let session = session.clone();
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        Ok(session)
+    }
+
+    /// Change a session's settings and persist to disk.
+    pub async fn update_settings(
+        &self,
+        id: &SessionId,
+        settings: SessionSettings,
+    ) -> Result<Session, SessionError> {
+        crate::session_env::validate(&settings.env)?;
+        let mut state = self.state.lock().await;
+        let session = state
+            .sessions
+            .get_mut(id.as_str())
+            .ok_or_else(|| SessionError::NotFound(id.to_string()))?;
+        session.system_prompt = settings.system_prompt;
+        session.env = settings.env;
+        session.updated_at = Utc::now();
+        let session = session.clone();
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        Ok(session)
+    }
+
+    /// Whether sessions may run in `dir`.
+    pub async fn is_trusted(&self, dir: &str) -> bool {
+        self.state.lock().await.is_trusted(std::path::Path::new(dir))
+    }
+
+    /// Let sessions run in `dir` and below, and persist that. Returns the
+    /// directory as stored.
+    pub async fn trust_workspace(&self, dir: &str) -> Result<String, SessionError> {
+        let path = std::path::Path::new(dir);
+        if !path.is_dir() {
+            return Err(SessionError::NotADirectory(dir.to_string()));
+        }
+        let mut state = self.state.lock().await;
+        let trusted = state.trust_workspace(path);
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
+        tracing::info!("Trusted workspace {}", trusted);
+        Ok(trusted)
+    }
+
+    /// Forget every trusted workspace, and persist that.
+    pub async fn forget_trusted_workspaces(&self) {
+        let mut state = self.state.lock().await;
+        state.trusted_workspaces.clear();
+        if let Some(ref state_path) = self.state_path
+            && let Err(e) = state.save(state_path)
+        {
+            tracing::error!("Failed to persist daemon state: {}", e);
+        }
     }
 
     /// Update a session's `claude_session_id` and persist to disk.
@@ -197,9 +871,31 @@ impl SessionManager {
 /// Thread-safe wrapper for SessionManager.
 pub type SharedSessionManager = Arc<SessionManager>;
 
+/// `#rgb` or `#rrggbb`.
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
 /// Errors from session management.
 #[derive(Debug, thiserror::Error)]
 pub enum SessionError {
     #[error("Process error: {0}")]
     ProcessError(#[from] ProcessError),
+
+    #[error("Session not found: {0}")]
+    NotFound(String),
+
+    #[error("Session already has a running process: {0}")]
+    AlreadyRunning(String),
+
+    #[error("Invalid session update: {0}")]
+    InvalidUpdate(String),
+
+    #[error("Not a directory: {0}")]
+    NotADirectory(String),
+
+    #[error(transparent)]
+    Env(#[from] EnvError),
 }
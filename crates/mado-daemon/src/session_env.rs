@@ -0,0 +1,86 @@
+//! Per-session environment variables for spawned processes, e.g. a custom
+//! `PATH`, `NODE_OPTIONS` or a provider base URL.
+//!
+//! A value of the form `keychain:<name>` refers to a secret in the OS
+//! keychain (see [`KeyStore::get_secret`]). It is looked up each time a
+//! process is spawned, so the secret itself never lands in daemon state.
+
+use std::collections::HashMap;
+
+use crate::keystore::KeyStore;
+
+/// Prefix of values that refer to a keychain secret.
+pub const SECRET_PREFIX: &str = "keychain:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvError {
+    #[error("Invalid environment variable name: {0:?}")]
+    InvalidName(String),
+
+    #[error("Environment variable {0} contains a NUL byte")]
+    InvalidValue(String),
+
+    #[error("Secret {name:?} for {var} is not in the keychain")]
+    SecretNotFound { var: String, name: String },
+}
+
+/// Check that every variable can be passed to a process.
+pub fn validate(env: &HashMap<String, String>) -> Result<(), EnvError> {
+    for (name, value) in env {
+        if name.is_empty() || name.contains(['=', '\0']) {
+            return Err(EnvError::InvalidName(name.clone()));
+        }
+        if value.contains('\0') {
+            return Err(EnvError::InvalidValue(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The variables to set on a spawned process, with secret references
+/// replaced by the secrets. Sorted by name.
+pub fn resolve(env: &HashMap<String, String>) -> Result<Vec<(String, String)>, EnvError> {
+    validate(env)?;
+    let mut resolved = env
+        .iter()
+        .map(|(var, value)| {
+            let value = match value.strip_prefix(SECRET_PREFIX) {
+                Some(name) => KeyStore::get_secret(name).map_err(|_| EnvError::SecretNotFound {
+                    var: var.clone(),
+                    name: name.to_string(),
+                })?,
+                None => value.clone(),
+            };
+            Ok((var.clone(), value))
+        })
+        .collect::<Result<Vec<_>, EnvError>>()?;
+    resolved.sort();
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_plain_values() {
+        let env = HashMap::from([
+            ("NODE_OPTIONS".to_string(), "--max-old-space-size=4096".to_string()),
+            ("BASE_URL".to_string(), "http://localhost:8080".to_string()),
+        ]);
+        assert_eq!(
+            resolve(&env).unwrap(),
+            vec![
+                ("BASE_URL".to_string(), "http://localhost:8080".to_string()),
+                ("NODE_OPTIONS".to_string(), "--max-old-space-size=4096".to_string()),
+            ]
+        );
+
+        for name in ["", "A=B", "A\0"] {
+            let env = HashMap::from([(name.to_string(), "x".to_string())]);
+            assert!(matches!(validate(&env), Err(EnvError::InvalidName(_))));
+        }
+        let env = HashMap::from([("A".to_string(), "x\0".to_string())]);
+        assert!(matches!(validate(&env), Err(EnvError::InvalidValue(_))));
+    }
+}
@@ -0,0 +1,80 @@
+//! Slash commands typed into the chat.
+//!
+//! A message that is one of these commands is handled by the
+//! `ConversationManager` instead of being sent to Claude, and answered with a
+//! system message:
+//!
+//! - `/model [name]` shows or switches the session's model
+//! - `/clear` starts a fresh Claude conversation
+//! - `/compact [instructions]` has the Claude CLI compact the conversation
+//! - `/cost` shows the session's token usage and cost
+//! - `/resume <id>` continues a Claude CLI session, importing its history
+//!
+//! Any other `/word` (the CLI's custom commands, for instance) goes to Claude
+//! verbatim.
+
+/// A slash command and its argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    Model(Option<String>),
+    Clear,
+    Compact(Option<String>),
+    Cost,
+    Resume(Option<String>),
+}
+
+impl SlashCommand {
+    /// Whether the command changes the Claude conversation, and so has to wait
+    /// for a response in progress.
+    pub fn needs_idle(&self) -> bool {
+        matches!(
+            self,
+            SlashCommand::Clear | SlashCommand::Compact(_) | SlashCommand::Resume(_)
+        )
+    }
+}
+
+/// Parse `content` as a slash command, or `None` if it's a message for Claude.
+pub fn parse(content: &str) -> Option<SlashCommand> {
+    let rest = content.trim().strip_prefix('/')?;
+    let (name, argument) = match rest.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim().to_string())),
+        None => (rest, None),
+    };
+    let argument = argument.filter(|a| !a.is_empty());
+    match name {
+        "model" => Some(SlashCommand::Model(argument)),
+        "clear" if argument.is_none() => Some(SlashCommand::Clear),
+        "compact" => Some(SlashCommand::Compact(argument)),
+        "cost" if argument.is_none() => Some(SlashCommand::Cost),
+        "resume" => Some(SlashCommand::Resume(argument)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("/model opus"), Some(SlashCommand::Model(Some("opus".to_string()))));
+        assert_eq!(parse("  /model  "), Some(SlashCommand::Model(None)));
+        assert_eq!(parse("/clear"), Some(SlashCommand::Clear));
+        assert_eq!(
+            parse("/compact keep the API notes"),
+            Some(SlashCommand::Compact(Some("keep the API notes".to_string())))
+        );
+        assert_eq!(parse("/cost"), Some(SlashCommand::Cost));
+        assert_eq!(
+            parse("/resume 4f1c2d"),
+            Some(SlashCommand::Resume(Some("4f1c2d".to_string())))
+        );
+
+        // Messages for Claude.
+        assert_eq!(parse("/review the diff"), None);
+        assert_eq!(parse("/clear the cache please"), None);
+        assert_eq!(parse("what does /model do?"), None);
+        assert_eq!(parse("/"), None);
+    }
+}
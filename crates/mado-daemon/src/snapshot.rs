@@ -0,0 +1,139 @@
+//! Snapshots of a session's workspace and conversation together.
+//!
+//! `POST /sessions/{id}/snapshot` saves a milestone and, as
+//! `<session>/snapshots/<id>.json` under the conversation storage directory,
+//! the messages the conversation had at that moment. Restoring a snapshot
+//! resets the workspace to its milestone and the conversation to its
+//! messages, which are replayed to a new Claude session: the old one has
+//! heard everything said since.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use mado_core::types::{Message, Snapshot};
+
+/// Errors from storing or loading snapshots.
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Snapshot not found: {0}")]
+    NotFound(String),
+
+    #[error("Unreadable snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to save snapshot: {0}")]
+    Write(#[from] crate::state::StateError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A snapshot as saved: its summary and the conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSnapshot {
+    #[serde(flatten)]
+    pub snapshot: Snapshot,
+    pub messages: Vec<Message>,
+}
+
+/// Directory of a session's snapshots under the conversation storage dir.
+pub fn dir(storage_dir: &Path, session_id: &str) -> PathBuf {
+    storage_dir.join(session_id).join("snapshots")
+}
+
+/// Save `saved` in `dir`, with secrets redacted.
+pub fn save(dir: &Path, saved: &SavedSnapshot) -> Result<(), SnapshotError> {
+    std::fs::create_dir_all(dir)?;
+    let json = serde_json::to_string(saved)?;
+    crate::state::write_atomic(&dir.join(format!("{}.json", saved.snapshot.id)), &crate::redact::redact(&json))?;
+    Ok(())
+}
+
+/// The snapshot `id` in `dir`.
+pub fn load(dir: &Path, id: &str) -> Result<SavedSnapshot, SnapshotError> {
+    // Ids are uuids; anything else can't name a file in `dir`.
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return Err(SnapshotError::NotFound(id.to_string()));
+    }
+    let contents = match std::fs::read_to_string(dir.join(format!("{}.json", id))) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(SnapshotError::NotFound(id.to_string())),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Snapshots in `dir`, newest first. Unreadable files are skipped.
+pub fn list(dir: &Path) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|contents| serde_json::from_str::<SavedSnapshot>(&contents).ok())
+        .map(|saved| saved.snapshot)
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+    use mado_core::types::{Milestone, MessageRole};
+
+    fn saved(id: &str, messages: Vec<Message>) -> SavedSnapshot {
+        SavedSnapshot {
+            snapshot: Snapshot {
+                id: id.to_string(),
+                milestone: Milestone {
+                    oid: "abc123".to_string(),
+                    message: "Before lunch".to_string(),
+                    timestamp: Utc::now(),
+                    files_changed: 1,
+                    insertions: 2,
+                    deletions: 0,
+                },
+                message_count: messages.len(),
+                created_at: Utc::now(),
+            },
+            messages,
+        }
+    }
+
+    #[test]
+    fn test_save_load_list() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = dir(tmp.path(), "s1");
+        let message = Message {
+            id: "m1".to_string(),
+            role: MessageRole::User,
+            content: "hello, my key is sk-ant-REDACTED".to_string(),
+            tool_calls: Vec::new(),
+            timestamp: Utc::now(),
+            usage: None,
+            cost_usd: None,
+            context_files: Vec::new(),
+            attachments: Vec::new(),
+            thinking: None,
+            source: None,
+            compaction: None,
+        };
+        save(&dir, &saved("0a1b", vec![message])).unwrap();
+        save(&dir, &saved("2c3d", Vec::new())).unwrap();
+
+        let loaded = load(&dir, "0a1b").unwrap();
+        assert_eq!(loaded.snapshot.message_count, 1);
+        assert_eq!(loaded.messages[0].content, "hello, my key is [REDACTED]");
+
+        let ids: Vec<_> = list(&dir).into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, ["2c3d", "0a1b"]);
+
+        assert!(matches!(load(&dir, "9999"), Err(SnapshotError::NotFound(_))));
+        assert!(matches!(load(&dir, "../s1"), Err(SnapshotError::NotFound(_))));
+    }
+}
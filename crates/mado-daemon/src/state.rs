@@ -1,18 +1,56 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
 use tracing;
 
 use mado_core::types::{Session, SessionId};
 
+/// Version of the state.json format. Bump it, and add a migration to
+/// `MIGRATIONS`, on any change older daemons' files can't be read with.
+pub const STATE_SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: [fn(&mut Value); STATE_SCHEMA_VERSION as usize] = [migrate_v0_to_v1];
+
+/// How often the autosave task writes the state if it changed.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Distinguishes concurrent writers' temp files.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Version 0 files have no `schema_version`, and were written as `{}` before
+/// the first session existed.
+fn migrate_v0_to_v1(state: &mut Value) {
+    if let Some(object) = state.as_object_mut() {
+        object.entry("sessions").or_insert_with(|| Value::Object(Default::default()));
+    }
+}
+
 /// Persistent state for the daemon.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DaemonState {
     /// Active sessions tracked by the daemon.
     pub sessions: HashMap<String, Session>,
+    /// Directories the user has let sessions run in, canonicalized. Trust
+    /// extends to their subdirectories.
+    #[serde(default)]
+    pub trusted_workspaces: BTreeSet<String>,
+}
+
+/// The state as written to disk, tagged with its schema version.
+#[derive(Serialize)]
+struct VersionedState<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    state: &'a DaemonState,
 }
 
 impl DaemonState {
@@ -21,47 +59,29 @@ impl DaemonState {
         Self::default()
     }
 
+    /// The state.json contents for this state.
+    pub fn to_json(&self) -> Result<String, StateError> {
+        serde_json::to_string_pretty(&VersionedState {
+            schema_version: STATE_SCHEMA_VERSION,
+            state: self,
+        })
+        .map_err(StateError::SerializeFailed)
+    }
+
     /// Save state to disk atomically.
     ///
     /// Writes to a temporary file first, then renames to avoid corruption
     /// if the process crashes mid-write.
     pub fn save(&self, path: &Path) -> Result<(), StateError> {
-        let json =
-            serde_json::to_string_pretty(self).map_err(|e| StateError::SerializeFailed(e))?;
-
-        // Write to a temp file in the same directory (for atomic rename).
-        let tmp_path = path.with_extension("json.tmp");
-
-        let mut file = fs::File::create(&tmp_path).map_err(|e| StateError::IoError {
-            path: tmp_path.clone(),
-            source: e,
-        })?;
-
-        file.write_all(json.as_bytes())
-            .map_err(|e| StateError::IoError {
-                path: tmp_path.clone(),
-                source: e,
-            })?;
-
-        file.sync_all().map_err(|e| StateError::IoError {
-            path: tmp_path.clone(),
-            source: e,
-        })?;
-
-        // Atomic rename.
-        fs::rename(&tmp_path, path).map_err(|e| StateError::IoError {
-            path: path.to_path_buf(),
-            source: e,
-        })?;
-
+        write_atomic(path, &self.to_json()?)?;
         tracing::debug!("State saved to {}", path.display());
         Ok(())
     }
 
-    /// Load state from disk.
+    /// Load state from disk, migrating files written by older daemons.
     ///
-    /// If the file is missing or corrupt, returns a default empty state
-    /// with a warning log.
+    /// A missing file gives an empty state; a corrupt file, or one written by
+    /// a newer daemon, is an error.
     pub fn load(path: &Path) -> Result<Self, StateError> {
         if !path.exists() {
             tracing::debug!("No state file at {}, starting fresh", path.display());
@@ -73,13 +93,8 @@ impl DaemonState {
             source: e,
         })?;
 
-        let state: Self = serde_json::from_str(&contents).map_err(|e| {
-            tracing::warn!(
-                "Corrupt state file at {}: {}, starting fresh",
-                path.display(),
-                e
-            );
-            StateError::DeserializeFailed(e)
+        let state = Self::from_json(&contents).inspect_err(|e| {
+            tracing::warn!("Unreadable state file at {}: {}", path.display(), e);
         })?;
 
         tracing::info!(
@@ -91,6 +106,55 @@ impl DaemonState {
         Ok(state)
     }
 
+    /// Parse state.json contents of any supported schema version.
+    pub fn from_json(contents: &str) -> Result<Self, StateError> {
+        let mut document: Value =
+            serde_json::from_str(contents).map_err(StateError::DeserializeFailed)?;
+
+        let version = document
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        if version > u64::from(STATE_SCHEMA_VERSION) {
+            return Err(StateError::UnsupportedVersion {
+                found: version,
+                supported: STATE_SCHEMA_VERSION,
+            });
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut document);
+        }
+        if version < u64::from(STATE_SCHEMA_VERSION) {
+            tracing::info!("Migrated state from schema v{} to v{}", version, STATE_SCHEMA_VERSION);
+        }
+
+        // Files from before workspace trust: keep their sessions working.
+        let trust_session_dirs = document.get("trusted_workspaces").is_none();
+        let mut state: Self = serde_json::from_value(document).map_err(StateError::DeserializeFailed)?;
+        if trust_session_dirs {
+            let dirs: Vec<String> = state.sessions.values().filter_map(|s| s.working_dir.clone()).collect();
+            for dir in dirs {
+                state.trust_workspace(Path::new(&dir));
+            }
+        }
+        Ok(state)
+    }
+
+    /// Move an unreadable state file aside so starting fresh doesn't
+    /// overwrite it. Returns where it went, or `None` if there was no file.
+    pub fn set_aside(path: &Path) -> Result<Option<PathBuf>, StateError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+        let backup = path.with_extension(format!("json.unreadable-{}", stamp));
+        fs::rename(path, &backup).map_err(|e| StateError::IoError {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        Ok(Some(backup))
+    }
+
     /// Add a session to the state.
     pub fn add_session(&mut self, session: Session) {
         self.sessions.insert(session.id.0.clone(), session);
@@ -105,6 +169,24 @@ impl DaemonState {
     pub fn get_session(&self, id: &SessionId) -> Option<&Session> {
         self.sessions.get(&id.0)
     }
+
+    /// Let sessions run in `dir` and below. Returns the directory as stored.
+    pub fn trust_workspace(&mut self, dir: &Path) -> String {
+        let dir = canonical(dir).display().to_string();
+        self.trusted_workspaces.insert(dir.clone());
+        dir
+    }
+
+    /// Whether `dir` is, or is inside, a trusted workspace.
+    pub fn is_trusted(&self, dir: &Path) -> bool {
+        let dir = canonical(dir);
+        self.trusted_workspaces.iter().any(|trusted| dir.starts_with(trusted))
+    }
+}
+
+/// `dir` with symlinks and `..` resolved, or as given if it doesn't exist.
+fn canonical(dir: &Path) -> PathBuf {
+    fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
 }
 
 /// Errors related to state persistence.
@@ -121,6 +203,84 @@ pub enum StateError {
 
     #[error("Failed to deserialize state: {0}")]
     DeserializeFailed(serde_json::Error),
+
+    #[error("State schema v{found} is newer than this daemon supports (v{supported})")]
+    UnsupportedVersion { found: u64, supported: u32 },
+}
+
+/// Write `contents` to `path` through a uniquely named temp file in the same
+/// directory, so a crash mid-write leaves the old file intact and concurrent
+/// writers never share a temp file.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<(), StateError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| StateError::IoError { path, source }
+    };
+
+    let tmp_path = path.with_extension(format!(
+        "json.{}-{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path).map_err(io_error(&tmp_path))?;
+        file.write_all(contents.as_bytes()).map_err(io_error(&tmp_path))?;
+        file.sync_all().map_err(io_error(&tmp_path))?;
+        fs::rename(&tmp_path, path).map_err(io_error(path))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    // Make the rename itself durable.
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = fs::File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Periodically write the state to `path` when it has changed since the last
+/// write, so a crash loses at most `AUTOSAVE_INTERVAL` of changes.
+pub fn spawn_autosave(
+    daemon_state: Arc<Mutex<DaemonState>>,
+    path: PathBuf,
+) -> tokio::task::JoinHandle<()> {
+    crate::crash::spawn_supervised("state-autosave", move || {
+        autosave(daemon_state.clone(), path.clone())
+    })
+}
+
+async fn autosave(daemon_state: Arc<Mutex<DaemonState>>, path: PathBuf) {
+    let mut interval = tokio::time::interval(AUTOSAVE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_saved = None;
+
+    loop {
+        interval.tick().await;
+        let state = daemon_state.lock().await;
+        let json = match state.to_json() {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Failed to serialize state for autosave: {}", e);
+                continue;
+            }
+        };
+        if last_saved.as_ref() == Some(&json) {
+            continue;
+        }
+        // Written under the lock, like every other save, so an older
+        // snapshot never lands after a newer one.
+        match write_atomic(&path, &json) {
+            Ok(()) => {
+                tracing::debug!("Autosaved state to {}", path.display());
+                last_saved = Some(json);
+            }
+            Err(e) => tracing::error!("Failed to autosave state: {}", e),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -139,13 +299,33 @@ mod tests {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             working_dir: None,
+            repo_root: None,
             command: None,
             shell_fallback: false,
+            custom_command: None,
+            env: Default::default(),
+            activity: mado_core::types::SessionActivity::Idle,
+            is_busy: false,
+            last_activity: None,
             conversation_state: mado_core::types::ConversationState::Empty,
             claude_session_id: None,
             message_count: 0,
             total_usage: None,
             total_cost_usd: None,
+            system_prompt: None,
+            permission_mode: mado_core::types::PermissionMode::Ask,
+            read_only: false,
+            sandbox: Default::default(),
+            extended_thinking: false,
+            tags: Vec::new(),
+            color: None,
+            pinned: false,
+            notes: None,
+            mcp_config: Vec::new(),
+            claude_flags: Vec::new(),
+            auto_milestone: None,
+            exit_code: None,
+            branch: None,
         }
     }
 
@@ -193,15 +373,71 @@ mod tests {
     fn test_atomic_save_uses_temp_file() {
         let tmp = TempDir::new().unwrap();
         let state_path = tmp.path().join("state.json");
-        let tmp_path = state_path.with_extension("json.tmp");
 
         let state = DaemonState::new();
         state.save(&state_path).unwrap();
 
-        // After save, temp file should not exist (it was renamed).
-        assert!(!tmp_path.exists());
-        // But the state file should exist.
-        assert!(state_path.exists());
+        // After save, no temp file should be left (it was renamed).
+        let files: Vec<_> = fs::read_dir(tmp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["state.json"]);
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], STATE_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_load_migrates_unversioned_file() {
+        let tmp = TempDir::new().unwrap();
+        let state_path = tmp.path().join("state.json");
+
+        fs::write(&state_path, "{}").unwrap();
+        assert!(DaemonState::load(&state_path).unwrap().sessions.is_empty());
+
+        let mut state = DaemonState::new();
+        state.add_session(make_session("s1", "Old"));
+        let mut legacy: Value = serde_json::from_str(&state.to_json().unwrap()).unwrap();
+        legacy.as_object_mut().unwrap().remove("schema_version");
+        fs::write(&state_path, legacy.to_string()).unwrap();
+        assert!(DaemonState::load(&state_path).unwrap().sessions.contains_key("s1"));
+    }
+
+    #[test]
+    fn test_load_rejects_newer_schema() {
+        let result = DaemonState::from_json(r#"{"schema_version": 99, "sessions": {}}"#);
+        assert!(matches!(
+            result,
+            Err(StateError::UnsupportedVersion { found: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn test_workspace_trust() {
+        let tmp = TempDir::new().unwrap();
+        let project = tmp.path().join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+
+        let mut state = DaemonState::new();
+        assert!(!state.is_trusted(&project));
+        state.trust_workspace(&project.join("src").join(".."));
+        assert!(state.is_trusted(&project));
+        assert!(state.is_trusted(&project.join("src")));
+        assert!(!state.is_trusted(tmp.path()));
+        assert!(!state.is_trusted(&tmp.path().join("project-other")));
+
+        // Sessions saved before workspace trust existed keep working.
+        let mut session = make_session("s1", "Old");
+        session.working_dir = Some(tmp.path().display().to_string());
+        let mut old = DaemonState::new();
+        old.add_session(session);
+        let mut document: Value = serde_json::from_str(&old.to_json().unwrap()).unwrap();
+        document.as_object_mut().unwrap().remove("trusted_workspaces");
+        let loaded = DaemonState::from_json(&document.to_string()).unwrap();
+        assert!(loaded.is_trusted(&project));
+        // But not once the file records trust.
+        assert!(!DaemonState::from_json(&old.to_json().unwrap()).unwrap().is_trusted(&project));
     }
 
     #[test]
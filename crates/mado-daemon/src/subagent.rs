@@ -0,0 +1,250 @@
+//! Subagents Claude starts with the `Task` tool.
+//!
+//! A `Task` tool_use in a top-level `assistant` event starts a subagent.
+//! Everything the subagent does arrives as `assistant` and `user` events
+//! whose `parent_tool_use_id` is that tool_use's id, and the `tool_result`
+//! for the id ends it. [`Subagents`] follows them through a turn, turning
+//! each step into a `SubagentUpdate` for the UI and the Task call's timeline.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde_json::Value;
+
+use mado_core::types::{StreamEvent, SubagentStep, ToolCallStatus};
+
+/// Names the CLI has given the tool that starts subagents.
+const TASK_TOOLS: [&str; 2] = ["Task", "Agent"];
+
+/// Longest summary of a step, in characters.
+const MAX_SUMMARY_CHARS: usize = 120;
+
+/// Input fields that say what a tool call is about, in order of preference.
+const TOOL_SUMMARY_FIELDS: [&str; 7] =
+    ["description", "command", "file_path", "pattern", "path", "url", "query"];
+
+/// A step of the subagent started by tool call `id`.
+#[derive(Debug, Clone)]
+pub struct SubagentUpdate {
+    pub id: String,
+    pub name: String,
+    pub step: SubagentStep,
+}
+
+impl SubagentUpdate {
+    pub fn event(&self) -> StreamEvent {
+        StreamEvent::SubagentUpdate {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            status: self.step.status.clone(),
+            summary: self.step.summary.clone(),
+        }
+    }
+}
+
+/// Subagents running in one turn, by the id of the Task call that started
+/// them.
+#[derive(Debug, Default)]
+pub struct Subagents {
+    names: HashMap<String, String>,
+}
+
+/// The Task call an event belongs to, if a subagent sent it.
+pub fn parent_id(event: &Value) -> Option<&str> {
+    event.get("parent_tool_use_id").and_then(|p| p.as_str())
+}
+
+/// Content blocks of an `assistant` or `user` event.
+fn blocks(event: &Value) -> &[Value] {
+    event
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Text of a `tool_result` block, whose content is a string or text blocks.
+pub fn tool_result_text(block: &Value) -> String {
+    match block.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// First non-empty line of `text`, shortened to [`MAX_SUMMARY_CHARS`].
+fn summarize(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    if line.chars().count() <= MAX_SUMMARY_CHARS {
+        return line.to_string();
+    }
+    let mut short: String = line.chars().take(MAX_SUMMARY_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+/// A tool call as a step summary, e.g. `Read src/main.rs`.
+fn tool_summary(name: &str, input: &Value) -> String {
+    match TOOL_SUMMARY_FIELDS
+        .iter()
+        .find_map(|field| input.get(field).and_then(|v| v.as_str()))
+    {
+        Some(detail) => summarize(&format!("{} {}", name, detail)),
+        None => name.to_string(),
+    }
+}
+
+impl Subagents {
+    /// Follow an `assistant` or `user` event, returning the subagent steps
+    /// it holds.
+    pub fn record(&mut self, event: &Value) -> Vec<SubagentUpdate> {
+        let mut updates = Vec::new();
+        let mut push = |id: &str, name: &str, status, summary: String| {
+            updates.push(SubagentUpdate {
+                id: id.to_string(),
+                name: name.to_string(),
+                step: SubagentStep {
+                    timestamp: Utc::now(),
+                    status,
+                    summary,
+                },
+            });
+        };
+
+        // A step of a running subagent.
+        if let Some(parent) = parent_id(event) {
+            let Some(name) = self.names.get(parent) else {
+                return updates;
+            };
+            if event["type"] != "assistant" {
+                return updates;
+            }
+            for block in blocks(event) {
+                let summary = match block["type"].as_str() {
+                    Some("text") => summarize(block["text"].as_str().unwrap_or("")),
+                    Some("tool_use") => tool_summary(block["name"].as_str().unwrap_or(""), &block["input"]),
+                    _ => continue,
+                };
+                if !summary.is_empty() {
+                    push(parent, name, ToolCallStatus::Running, summary);
+                }
+            }
+            return updates;
+        }
+
+        for block in blocks(event) {
+            match block["type"].as_str() {
+                // The main agent starts a subagent.
+                Some("tool_use") if TASK_TOOLS.contains(&block["name"].as_str().unwrap_or("")) => {
+                    let Some(id) = block["id"].as_str() else {
+                        continue;
+                    };
+                    let input = &block["input"];
+                    let name = input["subagent_type"].as_str().unwrap_or("general-purpose").to_string();
+                    let summary = summarize(
+                        input["description"]
+                            .as_str()
+                            .or(input["prompt"].as_str())
+                            .unwrap_or("Started"),
+                    );
+                    push(id, &name, ToolCallStatus::Running, summary);
+                    self.names.insert(id.to_string(), name);
+                }
+                // A subagent's result comes back to the main agent.
+                Some("tool_result") => {
+                    let Some(id) = block["tool_use_id"].as_str() else {
+                        continue;
+                    };
+                    let Some(name) = self.names.remove(id) else {
+                        continue;
+                    };
+                    let status = if block["is_error"].as_bool().unwrap_or(false) {
+                        ToolCallStatus::Failed
+                    } else {
+                        ToolCallStatus::Completed
+                    };
+                    let summary = summarize(&tool_result_text(block));
+                    push(id, &name, status, summary);
+                }
+                _ => {}
+            }
+        }
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_subagent_timeline() {
+        let mut subagents = Subagents::default();
+
+        let start = json!({
+            "type": "assistant",
+            "parent_tool_use_id": null,
+            "message": { "content": [
+                { "type": "text", "text": "Let me look around." },
+                {
+                    "type": "tool_use",
+                    "id": "task1",
+                    "name": "Task",
+                    "input": { "description": "Find the login handler", "prompt": "...", "subagent_type": "Explore" },
+                },
+            ]},
+        });
+        let updates = subagents.record(&start);
+        assert_eq!(updates.len(), 1);
+        assert_eq!((updates[0].id.as_str(), updates[0].name.as_str()), ("task1", "Explore"));
+        assert_eq!(updates[0].step.summary, "Find the login handler");
+
+        let step = json!({
+            "type": "assistant",
+            "parent_tool_use_id": "task1",
+            "message": { "content": [
+                { "type": "text", "text": "\nSearching the source.\nMore detail." },
+                { "type": "tool_use", "id": "t2", "name": "Grep", "input": { "pattern": "fn login" } },
+            ]},
+        });
+        let summaries: Vec<String> = subagents.record(&step).into_iter().map(|u| u.step.summary).collect();
+        assert_eq!(summaries, ["Searching the source.", "Grep fn login"]);
+
+        // Its own tool results are not steps.
+        let tool_result = json!({
+            "type": "user",
+            "parent_tool_use_id": "task1",
+            "message": { "content": [{ "type": "tool_result", "tool_use_id": "t2", "content": "src/auth.rs" }] },
+        });
+        assert!(subagents.record(&tool_result).is_empty());
+
+        let done = json!({
+            "type": "user",
+            "parent_tool_use_id": null,
+            "message": { "content": [{
+                "type": "tool_result",
+                "tool_use_id": "task1",
+                "content": [{ "type": "text", "text": "It is in src/auth.rs." }],
+            }]},
+        });
+        let updates = subagents.record(&done);
+        assert_eq!(updates[0].step.status, ToolCallStatus::Completed);
+        assert_eq!(updates[0].step.summary, "It is in src/auth.rs.");
+        assert!(subagents.names.is_empty());
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize("  \n  first line \nsecond"), "first line");
+        let long = "x".repeat(200);
+        assert_eq!(summarize(&long).chars().count(), MAX_SUMMARY_CHARS);
+        assert_eq!(tool_summary("Bash", &json!({ "command": "cargo test" })), "Bash cargo test");
+        assert_eq!(tool_summary("TodoWrite", &json!({})), "TodoWrite");
+    }
+}
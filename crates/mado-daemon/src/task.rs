@@ -0,0 +1,139 @@
+//! Headless one-shot tasks for `POST /tasks`.
+//!
+//! A task runs one prompt with `claude -p` in a working directory without
+//! creating a session: its conversation lives in the conversation manager
+//! under a throwaway id only while it runs. When the working directory is in
+//! a git repository it is snapshotted before and after (see
+//! [`crate::git_ops::snapshot_workdir`]), so the result can say what the
+//! task changed even if the tree was dirty to begin with.
+
+use std::path::{Path, PathBuf};
+
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use mado_core::types::{PermissionMode, SessionId, StreamEvent, TaskResult, TokenUsage};
+
+use crate::conversation::{
+    ConversationError, ConversationSession, MessageOptions, SharedConversationManager,
+};
+
+/// Events buffered for a client that reads a task's stream slowly.
+const TASK_EVENT_BUFFER: usize = 256;
+
+/// What to run.
+#[derive(Debug, Clone)]
+pub struct TaskOptions {
+    pub prompt: String,
+    pub working_dir: PathBuf,
+    pub model: String,
+    pub system_prompt: Option<String>,
+    pub permission_mode: PermissionMode,
+}
+
+/// An event of a running task.
+#[derive(Debug, Clone)]
+pub enum TaskEvent {
+    /// A chat event, as on a session's stream.
+    Stream(StreamEvent),
+    /// The task finished; always the last event.
+    Complete(Box<TaskResult>),
+}
+
+/// Snapshot the repository containing `dir`, if there is one. Returns the
+/// repository root and the snapshot's tree.
+async fn snapshot(dir: &Path) -> Option<(PathBuf, String)> {
+    let root = crate::git_ops::discover_repo_root(dir)?;
+    tokio::task::spawn_blocking(move || {
+        crate::git_ops::snapshot_workdir(&root)
+            .inspect_err(|e| tracing::warn!("Failed to snapshot {}: {}", root.display(), e))
+            .ok()
+            .map(|tree| (root, tree))
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Start a task. Its events arrive on the returned channel, ending with
+/// [`TaskEvent::Complete`]. Dropping the receiver cancels the task.
+pub async fn start(
+    manager: SharedConversationManager,
+    options: TaskOptions,
+) -> Result<mpsc::Receiver<TaskEvent>, ConversationError> {
+    let task_id = SessionId::new(format!("task-{}", Uuid::new_v4()));
+    let before = snapshot(&options.working_dir).await;
+
+    manager
+        .insert_session(
+            &task_id,
+            ConversationSession {
+                working_dir: Some(options.working_dir.to_string_lossy().to_string()),
+                model: options.model,
+                system_prompt: options.system_prompt,
+                permission_mode: options.permission_mode,
+                ..Default::default()
+            },
+        )
+        .await;
+    let mut live = manager.subscribe(&task_id, None).await.live;
+    if let Err(e) = manager
+        .send_message(&task_id, options.prompt, MessageOptions::default())
+        .await
+    {
+        manager.remove_session(&task_id).await;
+        return Err(e);
+    }
+    tracing::info!("Started task {} in {}", task_id, options.working_dir.display());
+
+    let (tx, rx) = mpsc::channel(TASK_EVENT_BUFFER);
+    tokio::spawn(async move {
+        loop {
+            let event = match live.recv().await {
+                Ok((_, event)) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let idle = matches!(event, StreamEvent::Idle);
+            if tx.send(TaskEvent::Stream(event)).await.is_err() {
+                tracing::info!("Client left task {}, cancelling it", task_id);
+                manager.remove_session(&task_id).await;
+                return;
+            }
+            if idle {
+                break;
+            }
+        }
+
+        let messages = manager.get_messages(&task_id, None, None).await.unwrap_or_default();
+        let mut usage = TokenUsage::default();
+        for message in &messages {
+            if let Some(message_usage) = &message.usage {
+                crate::usage::add(&mut usage, message_usage);
+            }
+        }
+        let changes = match (before, snapshot(&options.working_dir).await) {
+            (Some((root, from)), Some((_, to))) => tokio::task::spawn_blocking(move || {
+                crate::git_ops::diff_snapshots(&root, &from, &to)
+                    .inspect_err(|e| tracing::warn!("Failed to diff task snapshots: {}", e))
+                    .ok()
+            })
+            .await
+            .ok()
+            .flatten()
+            .map(Into::into),
+            _ => None,
+        };
+        let result = TaskResult {
+            task_id: task_id.to_string(),
+            cost_usd: messages.iter().filter_map(|m| m.cost_usd).sum(),
+            messages,
+            changes,
+            usage,
+        };
+        manager.remove_session(&task_id).await;
+        tracing::info!("Finished task {}", task_id);
+        let _ = tx.send(TaskEvent::Complete(Box::new(result))).await;
+    });
+    Ok(rx)
+}
@@ -0,0 +1,192 @@
+//! Session templates: named presets for new sessions.
+//!
+//! Each template is a JSON file in `templates/` next to state.json
+//! (`~/.mado/templates/<name>.json`), so they can also be written by hand
+//! or shared. `POST /sessions` with a `template` takes the fields the
+//! request leaves out from it.
+
+use std::fs;
+use std::path::PathBuf;
+
+use mado_core::protocol::CreateSessionBody;
+use mado_core::types::{CustomCommand, SessionTemplate};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Template not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid template name: {0:?}")]
+    InvalidName(String),
+
+    #[error("Unreadable template {name}: {source}")]
+    Unreadable {
+        name: String,
+        source: serde_json::Error,
+    },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The templates in a directory.
+#[derive(Debug)]
+pub struct Templates {
+    dir: PathBuf,
+}
+
+impl Templates {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Directory the templates are kept in.
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    /// All readable templates, by name.
+    pub fn list(&self) -> Vec<SessionTemplate> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to read templates in {}: {}", self.dir.display(), e);
+                }
+                return Vec::new();
+            }
+        };
+        let mut templates: Vec<SessionTemplate> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().to_str()?.strip_suffix(".json")?.to_string();
+                self.get(&name)
+                    .inspect_err(|e| tracing::warn!("Skipping template: {}", e))
+                    .ok()
+            })
+            .collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    }
+
+    pub fn get(&self, name: &str) -> Result<SessionTemplate, TemplateError> {
+        let contents = match fs::read_to_string(self.path(name)?) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(TemplateError::NotFound(name.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut template: SessionTemplate =
+            serde_json::from_str(&contents).map_err(|source| TemplateError::Unreadable {
+                name: name.to_string(),
+                source,
+            })?;
+        // The file name is what the template is looked up by.
+        template.name = name.to_string();
+        Ok(template)
+    }
+
+    /// Add `template`, replacing any of the same name.
+    pub fn save(&self, template: &SessionTemplate) -> Result<(), TemplateError> {
+        let path = self.path(&template.name)?;
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string_pretty(template).expect("SessionTemplate serializes");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), TemplateError> {
+        match fs::remove_file(self.path(name)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(TemplateError::NotFound(name.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, TemplateError> {
+        let valid = !name.trim().is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '));
+        if !valid {
+            return Err(TemplateError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+/// Fill the fields `body` leaves out from `template`.
+pub fn apply(template: SessionTemplate, body: &mut CreateSessionBody) {
+    body.model = body.model.take().or(template.model);
+    body.cwd = body.cwd.take().or(template.cwd);
+    body.system_prompt = body.system_prompt.take().or(template.system_prompt);
+    body.permission_mode = body.permission_mode.or(template.permission_mode);
+    let mut env = template.env;
+    env.extend(std::mem::take(&mut body.env));
+    body.env = env;
+    if body.command.is_none()
+        && let Some(CustomCommand { program, args }) = template.command
+    {
+        body.command = Some(program);
+        body.args = args;
+    }
+    if body.mcp_config.is_empty() {
+        body.mcp_config = template.mcp_config;
+    }
+    body.auto_milestone = body.auto_milestone.or(template.auto_milestone);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mado_core::types::PermissionMode;
+
+    use super::*;
+
+    #[test]
+    fn test_templates_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = Templates::new(dir.path().join("templates"));
+        assert!(templates.list().is_empty());
+
+        let template = SessionTemplate {
+            name: "api".to_string(),
+            model: Some("opus".to_string()),
+            cwd: Some("/src/api".to_string()),
+            permission_mode: Some(PermissionMode::AutoEdit),
+            env: HashMap::from([("A".to_string(), "1".to_string()), ("B".to_string(), "1".to_string())]),
+            ..Default::default()
+        };
+        templates.save(&template).unwrap();
+        templates
+            .save(&SessionTemplate {
+                name: "docs".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let names: Vec<_> = templates.list().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["api", "docs"]);
+        assert!(matches!(templates.get("../x"), Err(TemplateError::InvalidName(_))));
+
+        let mut body = CreateSessionBody {
+            name: "pane".to_string(),
+            model: Some("haiku".to_string()),
+            env: HashMap::from([("B".to_string(), "2".to_string())]),
+            ..Default::default()
+        };
+        apply(templates.get("api").unwrap(), &mut body);
+        assert_eq!(body.model.as_deref(), Some("haiku"));
+        assert_eq!(body.cwd.as_deref(), Some("/src/api"));
+        assert_eq!(body.permission_mode, Some(PermissionMode::AutoEdit));
+        assert_eq!(body.env["A"], "1");
+        assert_eq!(body.env["B"], "2");
+
+        templates.remove("docs").unwrap();
+        assert!(matches!(templates.remove("docs"), Err(TemplateError::NotFound(_))));
+    }
+}
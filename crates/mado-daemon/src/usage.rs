@@ -0,0 +1,163 @@
+//! Token usage of chat turns and how full the context window is.
+//!
+//! Each `assistant` event of `claude -p --output-format stream-json` carries
+//! the usage of the API call it belongs to; one turn makes a call per tool
+//! round trip, and every content block of a call repeats the same usage.
+//! [`TurnUsage`] adds the calls up for the tokens-so-far shown while a
+//! response streams. The context in use is the latest call's prompt (input
+//! plus cache reads and writes) and its output.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use mado_core::types::TokenUsage;
+
+/// Least time between two `UsageUpdate` events of a turn.
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Context window of the 1M-token variants (`sonnet[1m]`).
+const LONG_CONTEXT_WINDOW: u64 = 1_000_000;
+
+/// Context window of every other Claude model.
+const DEFAULT_CONTEXT_WINDOW: u64 = 200_000;
+
+/// Tokens `model` can hold in context.
+pub fn context_window(model: &str) -> u64 {
+    if model.trim_end().to_ascii_lowercase().ends_with("[1m]") {
+        LONG_CONTEXT_WINDOW
+    } else {
+        DEFAULT_CONTEXT_WINDOW
+    }
+}
+
+/// `context_tokens` as a fraction of `context_window`.
+pub fn utilization(context_tokens: u64, context_window: u64) -> f64 {
+    if context_window == 0 {
+        return 0.0;
+    }
+    context_tokens as f64 / context_window as f64
+}
+
+/// A `usage` object as the CLI reports it.
+pub fn parse(usage: &Value) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage.get("input_tokens").and_then(|t| t.as_u64()).unwrap_or(0),
+        output_tokens: usage.get("output_tokens").and_then(|t| t.as_u64()).unwrap_or(0),
+        cache_read_tokens: usage.get("cache_read_input_tokens").and_then(|t| t.as_u64()),
+        cache_write_tokens: usage.get("cache_creation_input_tokens").and_then(|t| t.as_u64()),
+    }
+}
+
+/// Tokens of context a call used: its whole prompt and its output.
+pub fn context_tokens(usage: &TokenUsage) -> u64 {
+    usage.input_tokens
+        + usage.cache_read_tokens.unwrap_or(0)
+        + usage.cache_write_tokens.unwrap_or(0)
+        + usage.output_tokens
+}
+
+/// Add `usage` to `total`.
+pub fn add(total: &mut TokenUsage, usage: &TokenUsage) {
+    total.input_tokens += usage.input_tokens;
+    total.output_tokens += usage.output_tokens;
+    if let Some(tokens) = usage.cache_read_tokens {
+        *total.cache_read_tokens.get_or_insert(0) += tokens;
+    }
+    if let Some(tokens) = usage.cache_write_tokens {
+        *total.cache_write_tokens.get_or_insert(0) += tokens;
+    }
+}
+
+/// Usage of the API calls of one turn, from its `assistant` events.
+#[derive(Debug, Default)]
+pub struct TurnUsage {
+    /// Message id and latest usage of each call, in order.
+    calls: Vec<(String, TokenUsage)>,
+}
+
+impl TurnUsage {
+    /// Record the usage of an `assistant` event. Returns whether it changed
+    /// anything.
+    pub fn record(&mut self, event: &Value) -> bool {
+        let Some(message) = event.get("message") else {
+            return false;
+        };
+        let Some(usage) = message.get("usage") else {
+            return false;
+        };
+        let id = message.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+        let usage = parse(usage);
+
+        match self.calls.last_mut() {
+            Some((last_id, last)) if *last_id == id => {
+                if context_tokens(last) == context_tokens(&usage) {
+                    return false;
+                }
+                *last = usage;
+            }
+            _ => self.calls.push((id, usage)),
+        }
+        true
+    }
+
+    /// Tokens of all calls so far.
+    pub fn total(&self) -> TokenUsage {
+        let mut total = TokenUsage::default();
+        for (_, usage) in &self.calls {
+            add(&mut total, usage);
+        }
+        total
+    }
+
+    /// Context used by the latest call.
+    pub fn context_tokens(&self) -> u64 {
+        self.calls.last().map(|(_, usage)| context_tokens(usage)).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assistant(id: &str, input: u64, cache_read: u64, output: u64) -> Value {
+        serde_json::json!({
+            "type": "assistant",
+            "message": {
+                "id": id,
+                "content": [],
+                "usage": {
+                    "input_tokens": input,
+                    "cache_read_input_tokens": cache_read,
+                    "output_tokens": output,
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn test_turn_usage() {
+        let mut turn = TurnUsage::default();
+        assert!(turn.record(&assistant("m1", 10, 1000, 5)));
+        // Another content block of the same call.
+        assert!(!turn.record(&assistant("m1", 10, 1000, 5)));
+        assert!(turn.record(&assistant("m1", 10, 1000, 50)));
+        assert!(turn.record(&assistant("m2", 20, 1060, 30)));
+        assert!(!turn.record(&serde_json::json!({ "type": "assistant", "message": {} })));
+
+        let total = turn.total();
+        assert_eq!(total.input_tokens, 30);
+        assert_eq!(total.output_tokens, 80);
+        assert_eq!(total.cache_read_tokens, Some(2060));
+        assert_eq!(total.cache_write_tokens, None);
+        assert_eq!(turn.context_tokens(), 1110);
+    }
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(context_window("sonnet"), 200_000);
+        assert_eq!(context_window("claude-sonnet-4-5[1m]"), 1_000_000);
+        assert_eq!(utilization(50_000, 200_000), 0.25);
+        assert_eq!(utilization(1, 0), 0.0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Copies of a session's workspace, for `POST /sessions/{id}/clone`.
+//!
+//! A copy takes every file, `.git` included, so uncommitted work comes
+//! along; a git clone takes only what is committed, with the original as
+//! `origin`. Inside a repository the whole repository is copied and the new
+//! working directory is the same place within it. Progress goes to a
+//! callback as `(done, total)`: files for a copy, objects for a clone.
+
+use std::path::{Path, PathBuf};
+
+use mado_core::types::CloneMode;
+
+/// Errors from cloning a workspace.
+#[derive(Debug, thiserror::Error)]
+pub enum CloneError {
+    #[error("Target directory is not empty: {0}")]
+    TargetExists(String),
+
+    #[error("Target directory is inside the workspace: {0}")]
+    InsideSource(String),
+
+    #[error("Not a git repository: {0}")]
+    NotARepository(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+}
+
+/// Reports progress at most once per percent, and always at the end.
+struct Progress<'a> {
+    report: &'a mut dyn FnMut(u64, u64),
+    last_percent: Option<u64>,
+}
+
+impl Progress<'_> {
+    fn update(&mut self, done: u64, total: u64) {
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        if self.last_percent != Some(percent) {
+            self.last_percent = Some(percent);
+            (self.report)(done, total);
+        }
+    }
+}
+
+/// Clone the workspace `working_dir` into `target`, which must not exist or
+/// be empty. Returns the working directory within `target`. On failure the
+/// target is left as it was found: removed if this call created it,
+/// emptied otherwise.
+pub fn clone_workspace(
+    working_dir: &Path,
+    target: &Path,
+    mode: CloneMode,
+    report: &mut dyn FnMut(u64, u64),
+) -> Result<PathBuf, CloneError> {
+    let working_dir = working_dir.canonicalize()?;
+    let root = match crate::git_ops::discover_repo_root(&working_dir) {
+        Some(root) => root.canonicalize()?,
+        None if mode == CloneMode::Git => return Err(CloneError::NotARepository(working_dir.display().to_string())),
+        None => working_dir.clone(),
+    };
+
+    let created = !target.exists();
+    if !created && std::fs::read_dir(target)?.next().is_some() {
+        return Err(CloneError::TargetExists(target.display().to_string()));
+    }
+    std::fs::create_dir_all(target)?;
+    let target = target.canonicalize()?;
+    if target.starts_with(&root) {
+        if created {
+            std::fs::remove_dir(&target)?;
+        }
+        return Err(CloneError::InsideSource(target.display().to_string()));
+    }
+
+    let mut progress = Progress { report, last_percent: None };
+    let result = match mode {
+        CloneMode::Copy => count_files(&root).and_then(|total| {
+            let mut done = 0;
+            progress.update(done, total);
+            copy_dir(&root, &target, &mut done, total, &mut progress)
+        })
+        .map_err(CloneError::Io),
+        CloneMode::Git => git_clone(&root, &target, &mut progress).map_err(CloneError::Git),
+    };
+    if let Err(e) = result {
+        if let Err(cleanup) = clean_target(&target, created) {
+            tracing::warn!("Failed to clean up {}: {}", target.display(), cleanup);
+        }
+        return Err(e);
+    }
+
+    let relative = working_dir.strip_prefix(&root).unwrap_or(Path::new(""));
+    Ok(target.join(relative))
+}
+
+/// Undo a partial clone: remove `target` if it was created for the clone,
+/// otherwise remove what was put in it.
+fn clean_target(target: &Path, created: bool) -> std::io::Result<()> {
+    if created {
+        return std::fs::remove_dir_all(target);
+    }
+    for entry in std::fs::read_dir(target)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn count_files(dir: &Path) -> std::io::Result<u64> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        count += if entry.file_type()?.is_dir() {
+            count_files(&entry.path())?
+        } else {
+            1
+        };
+    }
+    Ok(count)
+}
+
+fn copy_dir(source: &Path, target: &Path, done: &mut u64, total: u64, progress: &mut Progress) -> std::io::Result<()> {
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let to = target.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &to, done, total, progress)?;
+            continue;
+        }
+        if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &to)?;
+        } else {
+            std::fs::copy(entry.path(), &to)?;
+        }
+        *done += 1;
+        progress.update(*done, total);
+    }
+    Ok(())
+}
+
+/// Recreate the link rather than copy what it points to, so links within
+/// the workspace keep pointing within the copy.
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(source)?, target)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::fs::copy(source, target).map(|_| ())
+}
+
+fn git_clone(root: &Path, target: &Path, progress: &mut Progress) -> Result<(), git2::Error> {
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.transfer_progress(|stats| {
+        progress.update(stats.received_objects() as u64, stats.total_objects() as u64);
+        true
+    });
+    let mut fetch = git2::FetchOptions::new();
+    fetch.remote_callbacks(callbacks);
+    git2::build::RepoBuilder::new()
+        .fetch_options(fetch)
+        .clone(&root.to_string_lossy(), target)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_keeps_uncommitted_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = tmp.path().join("repo");
+        std::fs::create_dir_all(repo.join("src")).unwrap();
+        git2::Repository::init(&repo).unwrap();
+        std::fs::write(repo.join("src").join("main.rs"), "fn main() {}").unwrap();
+
+        let mut reports = Vec::new();
+        let target = tmp.path().join("copy");
+        let dir = clone_workspace(&repo.join("src"), &target, CloneMode::Copy, &mut |done, total| {
+            reports.push((done, total))
+        })
+        .unwrap();
+
+        assert_eq!(dir, target.canonicalize().unwrap().join("src"));
+        assert_eq!(std::fs::read_to_string(dir.join("main.rs")).unwrap(), "fn main() {}");
+        assert!(target.join(".git").is_dir());
+        let (done, total) = *reports.last().unwrap();
+        assert_eq!(done, total);
+
+        assert!(matches!(
+            clone_workspace(&repo, &target, CloneMode::Copy, &mut |_, _| {}),
+            Err(CloneError::TargetExists(_))
+        ));
+        assert!(matches!(
+            clone_workspace(&repo, &repo.join("inner"), CloneMode::Copy, &mut |_, _| {}),
+            Err(CloneError::InsideSource(_))
+        ));
+        assert!(!repo.join("inner").exists());
+
+        std::fs::create_dir(repo.join("kept")).unwrap();
+        assert!(matches!(
+            clone_workspace(&repo, &repo.join("kept"), CloneMode::Copy, &mut |_, _| {}),
+            Err(CloneError::InsideSource(_))
+        ));
+        assert!(repo.join("kept").is_dir());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_copy_cleans_up() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("source");
+        std::fs::create_dir_all(source.join("a")).unwrap();
+        std::fs::write(source.join("a").join("file.txt"), "data").unwrap();
+        // A socket can't be copied, so the copy fails partway.
+        let _socket = std::os::unix::net::UnixListener::bind(source.join("z.sock")).unwrap();
+
+        let created = tmp.path().join("created");
+        assert!(clone_workspace(&source, &created, CloneMode::Copy, &mut |_, _| {}).is_err());
+        assert!(!created.exists());
+
+        let existing = tmp.path().join("existing");
+        std::fs::create_dir(&existing).unwrap();
+        assert!(clone_workspace(&source, &existing, CloneMode::Copy, &mut |_, _| {}).is_err());
+        assert!(existing.is_dir());
+        assert_eq!(std::fs::read_dir(&existing).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_git_clone_takes_committed_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let repo = git2::Repository::init(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("committed.txt"), "saved").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("committed.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[]).unwrap();
+        std::fs::write(repo_dir.join("scratch.txt"), "unsaved").unwrap();
+
+        let target = tmp.path().join("clone");
+        let dir = clone_workspace(&repo_dir, &target, CloneMode::Git, &mut |_, _| {}).unwrap();
+        assert!(dir.join("committed.txt").is_file());
+        assert!(!dir.join("scratch.txt").exists());
+
+        let plain = tmp.path().join("plain");
+        std::fs::create_dir(&plain).unwrap();
+        assert!(matches!(
+            clone_workspace(&plain, &tmp.path().join("other"), CloneMode::Git, &mut |_, _| {}),
+            Err(CloneError::NotARepository(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+//! WebSocket transport for session I/O.
+//!
+//! `/sessions/{id}/ws` multiplexes PTY input/output, resize, chat stream events
+//! and cancellation on one connection. Binary frames carry PTY bytes in both
+//! directions; text frames carry JSON control messages (see
+//! `mado_core::protocol::WsClientMessage` and `WsServerMessage`).
+
+use std::pin::Pin;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path as AxumPath, State};
+use axum::response::{IntoResponse, Response};
+use futures::stream::{self, Stream};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::broadcast;
+use tracing;
+
+use mado_core::protocol::{DaemonResponse, WsClientMessage, WsServerMessage, WS_PROTOCOL_VERSION};
+use mado_core::types::SessionId;
+
+use crate::conversation::MessageOptions;
+use crate::error::ApiError;
+use crate::server::{ensure_conversation, ensure_session_trusted, pty_chunks, AppState, PtyChunk, MAX_INPUT_BYTES};
+
+#[utoipa::path(
+    get,
+    path = "/sessions/{id}/ws",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 101, description = "WebSocket upgrade; see `WsClientMessage` and `WsServerMessage`"),
+        (status = 404, description = "Session not found", body = DaemonResponse),
+    ),
+    tag = "pty"
+)]
+pub async fn ws_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let session_id = SessionId::new(id);
+    if state.session_manager.get_session(&session_id).await.is_none() {
+        return ApiError::SessionNotFound(session_id.to_string()).into_response();
+    }
+
+    ws.max_message_size(MAX_INPUT_BYTES)
+        .on_upgrade(move |socket| run_connection(state, session_id, socket))
+}
+
+async fn run_connection(state: AppState, session_id: SessionId, socket: WebSocket) {
+    let (mut sink, mut incoming) = socket.split();
+
+    // Sessions restored from disk have no live process; they still get chat events.
+    let subscription = state.session_manager.subscribe_pty(&session_id, None).await.ok();
+    let has_pty = subscription.is_some();
+    let mut pty: Pin<Box<dyn Stream<Item = PtyChunk> + Send>> = match subscription {
+        Some(subscription) => Box::pin(pty_chunks(subscription)),
+        None => Box::pin(stream::pending()),
+    };
+    let mut events = state.conversation_manager.subscribe(&session_id, None).await.live;
+
+    let connected = WsServerMessage::Connected {
+        version: WS_PROTOCOL_VERSION,
+        pty: has_pty,
+    };
+    if sink.send(json_message(&connected)).await.is_err() {
+        return;
+    }
+
+    loop {
+        let outgoing = tokio::select! {
+            msg = incoming.next() => match msg {
+                Some(Ok(Message::Binary(data))) => state
+                    .session_manager
+                    .write_input(&session_id, &data, false)
+                    .await
+                    .err()
+                    .map(|e| error_message(e.into())),
+                Some(Ok(Message::Text(text))) => {
+                    match handle_control(&state, &session_id, text.as_str()).await {
+                        Ok(reply) => reply.map(|reply| json_message(&reply)),
+                        Err(e) => Some(error_message(e)),
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by the websocket layer.
+                Some(Ok(_)) => None,
+            },
+            chunk = pty.next() => match chunk {
+                Some(chunk) => Some(Message::Binary(chunk.into_frame().into())),
+                None => {
+                    // Process exited; keep the connection for chat events.
+                    pty = Box::pin(stream::pending());
+                    None
+                }
+            },
+            event = events.recv() => match event {
+                Ok((_, event)) => Some(json_message(&WsServerMessage::Event { event })),
+                Err(broadcast::error::RecvError::Lagged(_)) => None,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+
+        if let Some(message) = outgoing
+            && sink.send(message).await.is_err()
+        {
+            break;
+        }
+    }
+
+    tracing::debug!("WebSocket closed for session {}", session_id);
+    let _ = sink.close().await;
+}
+
+/// Handle one JSON control message, returning an optional reply.
+async fn handle_control(
+    state: &AppState,
+    session_id: &SessionId,
+    text: &str,
+) -> Result<Option<WsServerMessage>, ApiError> {
+    let message: WsClientMessage = serde_json::from_str(text)
+        .map_err(|e| ApiError::Validation(format!("Invalid message: {}", e)))?;
+
+    match message {
+        WsClientMessage::Resize { rows, cols } => {
+            state.session_manager.resize_session(session_id, rows, cols).await?;
+            Ok(None)
+        }
+        WsClientMessage::SendMessage {
+            content,
+            model,
+            context_files,
+            attachments,
+        } => {
+            ensure_conversation(state, session_id).await?;
+            ensure_session_trusted(state, session_id).await?;
+            let options = MessageOptions {
+                model,
+                context_files,
+                attachments,
+            };
+            let message_id =
+                state.conversation_manager.send_message(session_id, content, options).await?;
+            Ok(Some(WsServerMessage::MessageAccepted { message_id }))
+        }
+        WsClientMessage::Cancel => {
+            state.conversation_manager.cancel_response(session_id).await?;
+            Ok(Some(WsServerMessage::CancelAccepted))
+        }
+    }
+}
+
+fn json_message(message: &WsServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default().into())
+}
+
+fn error_message(e: ApiError) -> Message {
+    json_message(&WsServerMessage::Error {
+        code: e.code(),
+        message: e.to_string(),
+    })
+}
@@ -0,0 +1,81 @@
+//! Helpers shared by the daemon integration tests.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use mado_core::types::{
+    ConversationState, PermissionMode, Session, SessionActivity, SessionId, SessionStatus,
+};
+use mado_daemon::state::DaemonState;
+
+/// Create empty test state for server tests.
+pub fn create_test_state(tmp_dir: &TempDir) -> (Arc<Mutex<DaemonState>>, PathBuf) {
+    test_state(tmp_dir, DaemonState::default())
+}
+
+/// Wrap `state` for a test server, keeping its state file in `tmp_dir`.
+pub fn test_state(tmp_dir: &TempDir, state: DaemonState) -> (Arc<Mutex<DaemonState>>, PathBuf) {
+    let state_path = tmp_dir.path().join("state.json");
+    (Arc::new(Mutex::new(state)), state_path)
+}
+
+/// Wait for a socket file to appear on disk, with a timeout.
+pub async fn wait_for_socket(socket_path: &Path, timeout: Duration) -> bool {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        if socket_path.exists() {
+            // Give the server a moment to start accepting connections.
+            sleep(Duration::from_millis(50)).await;
+            return true;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+/// An idle, active session with no working directory. Override fields with
+/// `Session { .., ..session(id) }`.
+pub fn session(id: &str) -> Session {
+    Session {
+        id: SessionId::new(id),
+        name: id.to_string(),
+        model: "sonnet".to_string(),
+        status: SessionStatus::Active,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        working_dir: None,
+        repo_root: None,
+        command: None,
+        shell_fallback: false,
+        custom_command: None,
+        env: Default::default(),
+        activity: SessionActivity::Idle,
+        is_busy: false,
+        last_activity: None,
+        conversation_state: ConversationState::Empty,
+        claude_session_id: None,
+        message_count: 0,
+        total_usage: None,
+        total_cost_usd: None,
+        system_prompt: None,
+        permission_mode: PermissionMode::Ask,
+        read_only: false,
+        sandbox: Default::default(),
+        extended_thinking: false,
+        tags: Vec::new(),
+        color: None,
+        pinned: false,
+        notes: None,
+        mcp_config: Vec::new(),
+        claude_flags: Vec::new(),
+        auto_milestone: None,
+        exit_code: None,
+        branch: None,
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use mado_core::client::DaemonClient;
+use mado_core::types::{ActivityEvent, ActivityKind, Session, SessionId};
+use mado_daemon::state::DaemonState;
+
+use common::{session, test_state, wait_for_socket};
+
+/// Create test state holding one restored session working in a git repo.
+fn create_test_state(tmp_dir: &TempDir) -> (Arc<Mutex<DaemonState>>, PathBuf) {
+    let repo = tmp_dir.path().join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    mado_daemon::git_ops::init_repo(&repo).expect("Failed to init repo");
+    std::fs::write(repo.join("README.md"), "hello\n").unwrap();
+
+    let mut state = DaemonState::default();
+    state.add_session(Session {
+        name: "Events Session".to_string(),
+        working_dir: Some(repo.to_string_lossy().to_string()),
+        ..session("ev-1")
+    });
+    test_state(tmp_dir, state)
+}
+
+/// Read SSE events from `/events` until one named `activity` arrives.
+async fn next_activity(client: &DaemonClient, last_event_id: &str) -> (String, ActivityEvent) {
+    let io = TokioIo::new(client.open_stream().await.expect("Failed to connect"));
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+        .await
+        .expect("Handshake failed");
+    tokio::spawn(conn);
+
+    let req = client
+        .request("/events")
+        .header("Accept", "text/event-stream")
+        .header("Last-Event-ID", last_event_id)
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let mut body = sender.send_request(req).await.expect("Request failed").into_body();
+
+    let mut buffer = String::new();
+    loop {
+        let frame = timeout(Duration::from_secs(5), body.frame())
+            .await
+            .expect("Timed out waiting for activity")
+            .expect("Stream ended")
+            .expect("Stream error");
+        if let Ok(data) = frame.into_data() {
+            buffer.push_str(&String::from_utf8_lossy(&data));
+        }
+
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let field = |name: &str| {
+                event
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|v| v.trim().to_string())
+            };
+            if field("event:").as_deref() == Some("activity") {
+                let data = field("data:").expect("Missing data");
+                let id = field("id:").expect("Missing id");
+                return (id, serde_json::from_str(&data).expect("Invalid ActivityEvent"));
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_activity_feed_replays_milestone() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    let client = DaemonClient::new(&socket_path);
+    let milestone = client
+        .save_milestone("ev-1", "First milestone")
+        .await
+        .expect("Failed to save milestone");
+
+    // Published before anyone was listening; a resuming client still gets it.
+    let (id, event) = next_activity(&client, "0").await;
+    assert_eq!(id, "1");
+    assert_eq!(event.session_id, SessionId::new("ev-1"));
+    assert_eq!(
+        event.kind,
+        ActivityKind::MilestoneSaved {
+            oid: milestone.oid,
+            message: "First milestone".to_string(),
+        }
+    );
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
+}
@@ -1,25 +1,32 @@
+mod common;
+
+use std::collections::HashMap;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures::StreamExt;
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper::Request;
 use hyper_util::rt::TokioIo;
 use tempfile::TempDir;
 use tokio::net::UnixStream;
-use tokio::sync::Mutex;
 use tokio::time::sleep;
 
 use mado_core::protocol::DaemonResponse;
-use mado_daemon::state::DaemonState;
+use mado_core::types::{CompareEvent, ConfigSource, HistorySource, OutputStream, ReviewAction, RunEvent, StreamEvent};
+
+use common::{create_test_state, session, wait_for_socket};
 
-/// Create test state for server tests.
-fn create_test_state(tmp_dir: &TempDir) -> (Arc<Mutex<DaemonState>>, PathBuf) {
-    let state_path = tmp_dir.path().join("state.json");
-    let daemon_state = Arc::new(Mutex::new(DaemonState::default()));
-    (daemon_state, state_path)
+/// A persisted session whose process is gone.
+fn terminated_session(id: &str) -> mado_core::types::Session {
+    mado_core::types::Session {
+        status: mado_core::types::SessionStatus::Terminated,
+        activity: mado_core::types::SessionActivity::Exited,
+        ..session(id)
+    }
 }
 
 /// Helper to send a GET request to the daemon over a Unix socket.
@@ -49,18 +56,26 @@ async fn get_request(socket_path: &std::path::Path, path: &str) -> (u16, Bytes)
     (status, body)
 }
 
-/// Wait for a socket file to appear on disk, with a timeout.
-async fn wait_for_socket(socket_path: &std::path::Path, timeout: Duration) -> bool {
-    let start = std::time::Instant::now();
-    while start.elapsed() < timeout {
-        if socket_path.exists() {
-            // Give the server a moment to start accepting connections.
-            sleep(Duration::from_millis(50)).await;
-            return true;
-        }
-        sleep(Duration::from_millis(20)).await;
-    }
-    false
+/// Helper to send a POST request with a JSON body to the daemon over a Unix socket.
+async fn post_json(socket_path: &std::path::Path, path: &str, body: serde_json::Value) -> (u16, Bytes) {
+    let stream = UnixStream::connect(socket_path).await.expect("Failed to connect to socket");
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .expect("Handshake failed");
+    tokio::spawn(conn);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("Host", "localhost")
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .expect("Failed to build request");
+
+    let resp = sender.send_request(req).await.expect("Request failed");
+    let status = resp.status().as_u16();
+    let body = resp.into_body().collect().await.expect("Failed to collect body").to_bytes();
+    (status, body)
 }
 
 #[tokio::test]
@@ -162,7 +177,7 @@ async fn test_ping_endpoint_returns_pong() {
 }
 
 #[tokio::test]
-async fn test_socket_permissions_are_0600() {
+async fn test_metrics_endpoint() {
     let tmp_dir = TempDir::new().expect("Failed to create temp dir");
     let socket_path = tmp_dir.path().join("test.sock");
     let (daemon_state, state_path) = create_test_state(&tmp_dir);
@@ -170,7 +185,7 @@ async fn test_socket_permissions_are_0600() {
     let socket_path_clone = socket_path.clone();
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
-    let _server_handle = tokio::spawn(async move {
+    let server_handle = tokio::spawn(async move {
         mado_daemon::server::start_server(
             socket_path_clone,
             state_path,
@@ -188,34 +203,50 @@ async fn test_socket_permissions_are_0600() {
         "Socket did not appear in time"
     );
 
-    let metadata = std::fs::metadata(&socket_path).expect("Failed to get socket metadata");
-    let permissions = metadata.permissions();
-    let mode = permissions.mode() & 0o777;
-    assert_eq!(
-        mode, 0o600,
-        "Socket permissions should be 0600, got {:o}",
-        mode
-    );
+    let (status, _) = get_request(&socket_path, "/ping").await;
+    assert_eq!(status, 200);
+
+    let (status, body) = get_request(&socket_path, "/metrics").await;
+    assert_eq!(status, 200);
+    let text = String::from_utf8(body.to_vec()).expect("Metrics are not UTF-8");
+
+    assert!(text.contains("# TYPE mado_uptime_seconds gauge"));
+    assert!(text.contains("mado_sessions 0"));
+    assert!(text.contains("mado_stream_subscribers{stream=\"activity\",session_id=\"\"} 0"));
+    // The earlier ping was recorded under its route template.
+    assert!(text.contains(
+        "mado_http_request_duration_seconds_count{method=\"GET\",route=\"/ping\",status=\"200\"} 1"
+    ));
 
     shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
 }
 
 #[tokio::test]
-async fn test_stale_socket_cleanup() {
+async fn test_logs_endpoint() {
     let tmp_dir = TempDir::new().expect("Failed to create temp dir");
     let socket_path = tmp_dir.path().join("test.sock");
     let (daemon_state, state_path) = create_test_state(&tmp_dir);
 
-    // Create a dummy file at the socket path to simulate a stale socket.
-    std::fs::write(&socket_path, "stale").expect("Failed to create dummy file");
-    assert!(socket_path.exists(), "Dummy file should exist");
+    let log_dir = tmp_dir.path().join("logs");
+    std::fs::create_dir(&log_dir).unwrap();
+    std::fs::write(
+        log_dir.join("daemon.log.2026-01-01"),
+        "2026-01-01T00:00:00Z  INFO mado_daemon: started\n\
+         2026-01-01T00:00:01Z  WARN mado_daemon::git: slow status\n\
+         2026-01-01T00:00:02Z DEBUG mado_daemon::server: request\n",
+    )
+    .unwrap();
 
     let socket_path_clone = socket_path.clone();
-
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_handle = tokio::spawn(async move {
-        mado_daemon::server::start_server(
+        mado_daemon::server::start_server_with_options(
             socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                log_dir: Some(log_dir),
+                ..Default::default()
+            },
             state_path,
             daemon_state,
             async {
@@ -223,7 +254,7 @@ async fn test_stale_socket_cleanup() {
             },
         )
         .await
-        .expect("Server should handle stale socket file");
+        .expect("Server failed to start");
     });
 
     assert!(
@@ -231,26 +262,176 @@ async fn test_stale_socket_cleanup() {
         "Socket did not appear in time"
     );
 
-    // Verify the daemon is functional after stale socket cleanup.
-    let (status, _body) = get_request(&socket_path, "/ping").await;
+    let (status, body) = get_request(&socket_path, "/logs?lines=2").await;
     assert_eq!(status, 200);
+    match serde_json::from_slice(&body).expect("Invalid JSON") {
+        DaemonResponse::Logs { lines } => {
+            assert_eq!(lines.len(), 2);
+            assert!(lines[1].contains("request"));
+        }
+        other => panic!("Expected Logs response, got: {:?}", other),
+    }
+
+    let (status, body) = get_request(&socket_path, "/logs?level=warn").await;
+    assert_eq!(status, 200);
+    match serde_json::from_slice(&body).expect("Invalid JSON") {
+        DaemonResponse::Logs { lines } => {
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].contains("slow status"));
+        }
+        other => panic!("Expected Logs response, got: {:?}", other),
+    }
+
+    let (status, _) = get_request(&socket_path, "/logs?level=loud").await;
+    assert_eq!(status, 422);
 
     shutdown_tx.send(()).expect("Failed to send shutdown");
     server_handle.await.expect("Server task panicked");
 }
 
 #[tokio::test]
-async fn test_client_health_check() {
+async fn test_purge_logs() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state
+        .lock()
+        .await
+        .sessions
+        .insert("s1".to_string(), terminated_session("s1"));
+
+    let log_dir = tmp_dir.path().join("logs");
+    let crash_dir = tmp_dir.path().join("crashes");
+    std::fs::create_dir(&log_dir).unwrap();
+    std::fs::create_dir(&crash_dir).unwrap();
+    std::fs::write(log_dir.join("daemon.log.2026-01-01"), "old\n").unwrap();
+    std::fs::write(log_dir.join("daemon.log.2026-01-02"), "current\n").unwrap();
+    std::fs::write(crash_dir.join("1.json"), "{}").unwrap();
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_state = daemon_state.clone();
+    let (server_log_dir, server_crash_dir) = (log_dir.clone(), crash_dir.clone());
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                log_dir: Some(server_log_dir),
+                crash_dir: Some(server_crash_dir),
+                ..Default::default()
+            },
+            state_path,
+            server_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    let (status, body) = post_json(&socket_path, "/admin/purge?scope=logs", serde_json::json!({})).await;
+    assert_eq!(status, 200);
+    match serde_json::from_slice(&body).expect("Invalid JSON") {
+        DaemonResponse::Purged { report } => {
+            assert_eq!(report.sessions, 0);
+            assert_eq!(report.paths.len(), 3);
+        }
+        other => panic!("Expected Purged response, got: {:?}", other),
+    }
+    assert!(!log_dir.join("daemon.log.2026-01-01").exists());
+    assert_eq!(std::fs::read_to_string(log_dir.join("daemon.log.2026-01-02")).unwrap(), "");
+    assert!(!crash_dir.exists());
+    // Sessions are outside the scope.
+    assert!(daemon_state.lock().await.sessions.contains_key("s1"));
+
+    let (status, _) = post_json(&socket_path, "/admin/purge?scope=cache", serde_json::json!({})).await;
+    assert_ne!(status, 200);
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
+}
+
+#[tokio::test]
+async fn test_idle_timeout_shuts_down_server() {
     let tmp_dir = TempDir::new().expect("Failed to create temp dir");
     let socket_path = tmp_dir.path().join("test.sock");
     let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let saved_state_path = state_path.clone();
+
+    let settings = mado_daemon::config::SharedSettings::default();
+    settings.write().unwrap().idle_timeout = Some(Duration::from_millis(300));
 
     let socket_path_clone = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings,
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            std::future::pending(),
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let (status, _) = get_request(&socket_path, "/ping").await;
+    assert_eq!(status, 200);
+
+    // No sessions, streams or further requests: the server stops by itself.
+    tokio::time::timeout(Duration::from_secs(5), server_handle)
+        .await
+        .expect("Server did not shut down when idle")
+        .expect("Server task panicked");
+    assert!(saved_state_path.exists(), "State was not saved before exiting");
+    assert!(!socket_path.exists());
+}
+
+#[tokio::test]
+async fn test_archive_and_retention_plan() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    for (id, days_old) in [("old", 30), ("new", 0)] {
+        let updated_at = chrono::Utc::now() - chrono::Duration::days(days_old);
+        daemon_state.lock().await.add_session(mado_core::types::Session {
+            created_at: updated_at,
+            updated_at,
+            ..terminated_session(id)
+        });
+    }
+    let settings = mado_daemon::config::DaemonSettings {
+        retention: mado_daemon::config::RetentionConfig {
+            max_age_days: Some(7),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
 
+    let archive_dir = tmp_dir.path().join("archive");
+    let socket_path_clone = socket_path.clone();
+    let options = mado_daemon::server::ServerOptions {
+        settings: Arc::new(std::sync::RwLock::new(settings)),
+        archive_dir: Some(archive_dir.clone()),
+        ..Default::default()
+    };
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
-    let _server_handle = tokio::spawn(async move {
-        mado_daemon::server::start_server(
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
             socket_path_clone,
+            options,
             state_path,
             daemon_state,
             async {
@@ -265,18 +446,2663 @@ async fn test_client_health_check() {
         wait_for_socket(&socket_path, Duration::from_secs(5)).await,
         "Socket did not appear in time"
     );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
 
-    // Test using the DaemonClient from mado-core.
+    // The cleanup task runs at startup and removes the expired session.
+    let start = std::time::Instant::now();
+    while client.list_sessions().await.unwrap().len() > 1 {
+        assert!(start.elapsed() < Duration::from_secs(5), "Expired session was not removed");
+        sleep(Duration::from_millis(50)).await;
+    }
+    let archive = mado_daemon::retention::read(&archive_dir.join("old.json.gz")).unwrap();
+    assert_eq!(archive.session.id.as_str(), "old");
+    assert!(client.retention_plan().await.unwrap().is_empty());
+
+    let path = client.archive_session("new").await.unwrap();
+    assert_eq!(PathBuf::from(path), archive_dir.join("new.json.gz"));
+    assert!(client.list_sessions().await.unwrap().is_empty());
+    assert!(client.archive_session("new").await.is_err());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_file_content_endpoints() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    std::fs::write(workspace.join("notes.txt"), "first").unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
     let client = mado_core::client::DaemonClient::new(&socket_path);
 
-    // connect() should succeed
-    client.connect().await.expect("Client connect should succeed");
+    let (content, etag) = client.read_file("s1", "notes.txt").await.unwrap();
+    assert_eq!(content, "first");
 
-    // health() should return valid status
-    let status = client.health().await.expect("Health check should succeed");
-    assert!(status.pid > 0);
-    assert_eq!(status.session_count, 0);
-    assert!(!status.version.is_empty());
+    // A change on disk after the read makes the save fail.
+    std::fs::write(workspace.join("notes.txt"), "changed by claude").unwrap();
+    let result = client.write_file("s1", "notes.txt", "second", Some(&etag)).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))));
 
-    shutdown_tx.send(()).expect("Failed to send shutdown");
+    let (_, etag) = client.read_file("s1", "notes.txt").await.unwrap();
+    let new_etag = client.write_file("s1", "notes.txt", "second", Some(&etag)).await.unwrap();
+    assert_ne!(new_etag, etag);
+    assert_eq!(std::fs::read_to_string(workspace.join("notes.txt")).unwrap(), "second");
+
+    let (status, _) = get_request(&socket_path, "/sessions/s1/files/content?path=../state.json").await;
+    assert_eq!(status, 422);
+    let (status, _) = get_request(&socket_path, "/sessions/s1/files/content?path=missing.txt").await;
+    assert_eq!(status, 404);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_import_aider_history() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir_all(workspace.join(".git")).unwrap();
+    std::fs::write(
+        workspace.join(".aider.chat.history.md"),
+        "# aider chat started at 2025-05-01 10:00:00\n\n#### Add a flag\n\nAdded.\n",
+    )
+    .unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let sources = client.history_sources(&workspace.to_string_lossy()).await.unwrap();
+    assert!(sources.contains(&HistorySource::Aider));
+
+    let import = client
+        .import_history("s1", None, None, None, None, Some(HistorySource::Aider))
+        .await
+        .unwrap();
+    assert_eq!((import.added, import.skipped), (2, 0));
+    let messages = import.messages;
+    assert_eq!(messages[0].content, "Add a flag");
+    assert_eq!(messages[1].source, Some(HistorySource::Aider));
+
+    // Importing again adds nothing.
+    let import = client
+        .import_history("s1", None, None, None, None, Some(HistorySource::Aider))
+        .await
+        .unwrap();
+    assert_eq!((import.added, import.skipped), (0, 2));
+    assert_eq!(client.get_messages("s1", None, None).await.unwrap().len(), 2);
+
+    // Only Claude CLI sessions can be picked by id.
+    let (status, _) = get_request(&socket_path, "/sessions/s1/history?source=aider&target_session_id=x").await;
+    assert_eq!(status, 422);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_snapshot_restores_files_and_conversation() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    mado_daemon::git_ops::init_repo(&workspace).unwrap();
+    let history = workspace.join(".aider.chat.history.md");
+    std::fs::write(workspace.join("notes.txt"), "before").unwrap();
+    std::fs::write(&history, "#### Add a flag\n\nAdded.\n").unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    let import_aider = || client.import_history("s1", None, None, None, None, Some(HistorySource::Aider));
+
+    import_aider().await.unwrap();
+    let snapshot = client.save_snapshot("s1", "Before lunch").await.unwrap();
+    assert_eq!(snapshot.message_count, 2);
+    assert_eq!(snapshot.milestone.message, "Before lunch");
+
+    std::fs::write(workspace.join("notes.txt"), "after").unwrap();
+    std::fs::write(&history, "#### Add a flag\n\nAdded.\n\n#### And a test\n\nDone.\n").unwrap();
+    import_aider().await.unwrap();
+    assert_eq!(client.get_messages("s1", None, None).await.unwrap().len(), 4);
+
+    let snapshots = client.list_snapshots("s1").await.unwrap();
+    assert_eq!(snapshots.len(), 1);
+    client.restore_snapshot("s1", &snapshot.id).await.unwrap();
+    assert_eq!(std::fs::read_to_string(workspace.join("notes.txt")).unwrap(), "before");
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].content, "Add a flag");
+
+    let result = client.restore_snapshot("s1", "0000").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_clone_session() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    mado_daemon::git_ops::init_repo(&workspace).unwrap();
+    std::fs::write(workspace.join("notes.txt"), "uncommitted").unwrap();
+    std::fs::write(workspace.join(".aider.chat.history.md"), "#### Add a flag\n\nAdded.\n").unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    session.custom_command = Some(mado_core::types::CustomCommand {
+        program: "sh".to_string(),
+        args: vec!["-c".to_string(), "sleep 30".to_string()],
+    });
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_state = daemon_state.clone();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            server_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    client
+        .import_history("s1", None, None, None, None, Some(HistorySource::Aider))
+        .await
+        .unwrap();
+
+    let target = tmp_dir.path().join("experiment");
+    let body = mado_core::protocol::CloneSessionBody {
+        target_dir: target.to_string_lossy().to_string(),
+        fork_conversation: true,
+        ..Default::default()
+    };
+    let clone = client.clone_session("s1", &body).await.expect("Failed to clone session");
+    assert_eq!(clone.name, "s1 (clone)");
+    let clone_dir = PathBuf::from(clone.working_dir.as_deref().unwrap());
+    assert_eq!(clone_dir, target.canonicalize().unwrap());
+    assert_eq!(std::fs::read_to_string(clone_dir.join("notes.txt")).unwrap(), "uncommitted");
+    assert!(clone.custom_command.is_some());
+    assert!(daemon_state.lock().await.is_trusted(&clone_dir));
+    let messages = client.get_messages(clone.id.as_str(), None, None).await.unwrap();
+    assert_eq!(messages.len(), 2);
+
+    let result = client.clone_session("s1", &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    client.destroy_session(clone.id.as_str()).await.unwrap();
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_review_and_revert() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    mado_daemon::git_ops::init_repo(&workspace).unwrap();
+    std::fs::write(workspace.join("a.txt"), "one\n").unwrap();
+    // Dated after the milestone below, which the review falls back to: the
+    // daemon ran no turns to snapshot.
+    std::fs::write(
+        workspace.join(".aider.chat.history.md"),
+        "# aider chat started at 2099-01-01 00:00:00\n\n#### Edit a.txt\n\nEdited.\n",
+    )
+    .unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    let result = client.review("s1", None).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))), "{:?}", result);
+
+    client.save_milestone("s1", "Before the turn").await.unwrap();
+    client
+        .import_history("s1", None, None, None, None, Some(HistorySource::Aider))
+        .await
+        .unwrap();
+    std::fs::write(workspace.join("a.txt"), "two\n").unwrap();
+    std::fs::write(workspace.join("b.txt"), "new\n").unwrap();
+
+    let review = client.review("s1", None).await.unwrap();
+    let files: Vec<_> = review.files.iter().map(|f| (f.path.as_str(), f.status.as_str())).collect();
+    assert_eq!(files, [("a.txt", "modified"), ("b.txt", "added")]);
+    assert!(review.files[0].patch.contains("+two"));
+
+    let decision = |path: &str, action| mado_core::protocol::ReviewDecision {
+        path: path.to_string(),
+        action,
+    };
+    let body = mado_core::protocol::ApplyReviewBody {
+        since_message_id: Some(review.message_id.clone()),
+        decisions: vec![decision("a.txt", ReviewAction::Keep), decision("b.txt", ReviewAction::Revert)],
+    };
+    let after = client.apply_review("s1", &body).await.unwrap();
+    assert_eq!(after.files.len(), 1);
+    assert_eq!(after.files[0].path, "a.txt");
+    assert!(!workspace.join("b.txt").exists());
+    assert_eq!(std::fs::read_to_string(workspace.join("a.txt")).unwrap(), "two\n");
+
+    let body = mado_core::protocol::ApplyReviewBody {
+        since_message_id: None,
+        decisions: vec![decision("b.txt", ReviewAction::Revert)],
+    };
+    let result = client.apply_review("s1", &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))), "{:?}", result);
+    let result = client.review("s1", Some("missing")).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))), "{:?}", result);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_revert_turn_changes() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    mado_daemon::git_ops::init_repo(&workspace).unwrap();
+    std::fs::write(workspace.join("a.txt"), "one\n").unwrap();
+
+    // Stands in for the Claude CLI: edits two files and replies.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+read prompt
+echo two > a.txt
+echo new > b.txt
+echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Edited."}]}}'
+echo '{"type":"result","subtype":"success","session_id":"fake","result":"Edited."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let start = || {
+        let (daemon_state, state_path) = create_test_state(&tmp_dir);
+        let mut session = terminated_session("s1");
+        session.working_dir = Some(workspace.to_string_lossy().to_string());
+        daemon_state.try_lock().unwrap().add_session(session);
+        let settings = mado_daemon::config::DaemonSettings {
+            claude_path: Some(claude.clone()),
+            ..Default::default()
+        };
+        let socket_path = socket_path.clone();
+        let conversations_dir = tmp_dir.path().join("conversations");
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            mado_daemon::server::start_server_with_options(
+                socket_path,
+                mado_daemon::server::ServerOptions {
+                    settings: Arc::new(std::sync::RwLock::new(settings)),
+                    conversations_dir: Some(conversations_dir),
+                    ..Default::default()
+                },
+                state_path,
+                daemon_state,
+                async {
+                    shutdown_rx.await.ok();
+                },
+            )
+            .await
+            .expect("Server failed to start");
+        });
+        (shutdown_tx, handle)
+    };
+
+    let (shutdown_tx, server_handle) = start();
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    let message_id = client.send_message("s1", "Edit the files", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages("s1", None, None).await.unwrap().len() < 2 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(std::fs::read_to_string(workspace.join("a.txt")).unwrap(), "two\n");
+
+    // Made after the turn, so kept.
+    std::fs::write(workspace.join("c.txt"), "mine\n").unwrap();
+    let files = client.revert_turn_changes("s1", &message_id).await.unwrap();
+    assert_eq!(files, ["a.txt", "b.txt"]);
+    assert_eq!(std::fs::read_to_string(workspace.join("a.txt")).unwrap(), "one\n");
+    assert!(!workspace.join("b.txt").exists());
+    assert!(workspace.join("c.txt").exists());
+
+    let result = client.revert_turn_changes("s1", &message_id).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    let result = client.revert_turn_changes("s1", "missing").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))), "{:?}", result);
+
+    // Removing the session drops its snapshots, also after a restart when
+    // its conversation isn't loaded.
+    let _ = shutdown_tx.send(());
+    server_handle.await.expect("Server task panicked");
+    let (shutdown_tx, server_handle) = start();
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let repo = git2::Repository::open(&workspace).unwrap();
+    assert_eq!(repo.references_glob("refs/mado/s1/*").unwrap().count(), 2);
+    client.purge(mado_core::types::PurgeScope::Sessions).await.unwrap();
+    assert_eq!(repo.references_glob("refs/mado/s1/*").unwrap().count(), 0);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_compare_models() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI: answers with the model it was given,
+    // once the test has started following the comparison.
+    let claude = tmp_dir.path().join("claude");
+    let go = tmp_dir.path().join("go");
+    std::fs::write(
+        &claude,
+        format!(
+            r#"#!/bin/sh
+model=none
+while [ $# -gt 0 ]; do
+  [ "$1" = "--model" ] && model=$2
+  shift
+done
+read prompt
+while [ ! -f "{go}" ]; do sleep 0.05; done
+echo "{{\"type\":\"assistant\",\"message\":{{\"content\":[{{\"type\":\"text\",\"text\":\"From $model.\"}}]}}}}"
+echo "{{\"type\":\"result\",\"subtype\":\"success\",\"session_id\":\"fake-$model\",\"result\":\"From $model.\"}}"
+"#,
+            go = go.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+
+    let body = mado_core::protocol::CompareBody {
+        content: "Which is better?".to_string(),
+        models: vec!["sonnet".to_string()],
+    };
+    let result = client.compare("s1", &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))), "{:?}", result);
+
+    let body = mado_core::protocol::CompareBody {
+        models: vec!["sonnet".to_string(), "opus".to_string()],
+        ..body
+    };
+    let started = client.compare("s1", &body).await.expect("Failed to start comparison");
+    assert_eq!(started.variants.len(), 2);
+    let opus = started.variants[1].id.clone();
+
+    let mut events = client.follow_comparison("s1", &started.id).await.unwrap();
+    std::fs::write(&go, "").unwrap();
+    let mut idle = Vec::new();
+    let comparison = tokio::time::timeout(Duration::from_secs(10), async {
+        while let Some(event) = events.next().await {
+            match event {
+                CompareEvent::Variant { variant_id, event: StreamEvent::Idle } => idle.push(variant_id),
+                CompareEvent::Variant { .. } => {}
+                CompareEvent::Complete { comparison } => return comparison,
+            }
+        }
+        panic!("Stream ended without completing");
+    })
+    .await
+    .expect("Comparison did not finish in time");
+    idle.sort();
+    let mut variants: Vec<_> = started.variants.iter().map(|variant| variant.id.clone()).collect();
+    variants.sort();
+    assert_eq!(idle, variants);
+    let answers: Vec<_> = comparison
+        .variants
+        .iter()
+        .map(|variant| variant.messages.last().unwrap().content.as_str())
+        .collect();
+    assert_eq!(answers, ["From sonnet.", "From opus."]);
+    // The session's own conversation is left alone.
+    assert!(client.get_messages("s1", None, None).await.unwrap().is_empty());
+
+    let applied = client.apply_comparison("s1", &comparison.id, &opus).await.unwrap();
+    assert_eq!(applied.applied.as_deref(), Some(opus.as_str()));
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["Which is better?", "From opus."]);
+    let session = client.list_sessions().await.unwrap().into_iter().find(|s| s.id.as_str() == "s1").unwrap();
+    assert_eq!(session.claude_session_id.as_deref(), Some("fake-opus"));
+
+    let result = client.apply_comparison("s1", &comparison.id, &opus).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    let listed = client.list_comparisons("s1").await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].applied.is_some());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_compact_conversation() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI: writes a summary when asked for plain
+    // JSON, otherwise answers at length and keeps the prompt it got.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+for arg in "$@"; do
+  if [ "$arg" = "json" ]; then
+    cat > /dev/null
+    echo '{"type":"result","subtype":"success","session_id":"summary","result":"They talked.","total_cost_usd":0.01}'
+    exit 0
+  fi
+done
+read -r prompt
+printf '%s\n' "$prompt" > last_prompt
+echo '{"type":"assistant","message":{"content":[{"type":"text","text":"A long answer that goes on and on about everything asked."}]}}'
+echo '{"type":"result","subtype":"success","session_id":"fake","result":"A long answer that goes on and on about everything asked."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let conversations_dir_clone = conversations_dir.clone();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir_clone),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+
+    for (turn, question) in ["One?", "Two?", "Three?"].into_iter().enumerate() {
+        client.send_message("s1", question, None, &[], &[]).await.unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while client.get_messages("s1", None, None).await.unwrap().len() < 2 * (turn + 1) {
+            assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    let body = mado_core::protocol::CompactBody {
+        keep_recent: Some(2),
+        instructions: None,
+    };
+    // The last turn may still be winding down.
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let compaction = loop {
+        match client.compact("s1", &body).await {
+            Err(mado_core::client::ClientError::Conflict(_)) if std::time::Instant::now() < deadline => {
+                sleep(Duration::from_millis(50)).await;
+            }
+            result => break result.expect("Failed to compact"),
+        }
+    };
+    assert_eq!(compaction.archived, 4);
+    assert_eq!(compaction.message.content, "They talked.");
+    assert!(compaction.tokens_saved() > 0, "{:?}", compaction);
+
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents[..2], ["They talked.", "Three?"]);
+    assert_eq!(messages[0].compaction.as_deref(), Some(compaction.id.as_str()));
+    let session = client.list_sessions().await.unwrap().into_iter().find(|s| s.id.as_str() == "s1").unwrap();
+    assert_eq!(session.claude_session_id, None);
+    let archive = conversations_dir.join("s1/compactions").join(format!("{}.json", compaction.id));
+    assert!(archive.is_file());
+
+    // Nothing older than the last turn is left.
+    let result = client.compact("s1", &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+
+    // The next turn starts over from the summary.
+    client.send_message("s1", "Four?", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages("s1", None, None).await.unwrap().len() < 5 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+    let prompt = std::fs::read_to_string(workspace.join("last_prompt")).unwrap();
+    assert!(prompt.contains("<summary>\\nThey talked.\\n</summary>"), "{}", prompt);
+    assert!(!prompt.contains("One?"));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_project_memory() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI: suggests an entry when asked for plain
+    // JSON, otherwise just answers.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+for arg in "$@"; do
+  if [ "$arg" = "json" ]; then
+    cat > /dev/null
+    echo '{"type":"result","subtype":"success","session_id":"memory","result":"- Run cargo fmt first.","total_cost_usd":0.01}'
+    exit 0
+  fi
+done
+read -r prompt
+echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Noted."}]}}'
+echo '{"type":"result","subtype":"success","session_id":"fake","result":"Noted."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+
+    let memory = client.memory("s1").await.unwrap();
+    assert!(memory.path.ends_with("CLAUDE.md"));
+    assert!(memory.content.is_empty() && memory.etag.is_empty());
+
+    let memory = client.write_memory("s1", "# Project\n", Some("")).await.unwrap();
+    let result = client.write_memory("s1", "Lost", Some("")).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    client.append_memory("s1", "Use tabs in Makefiles.").await.unwrap();
+    let result = client.append_memory("s1", "  ").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))), "{:?}", result);
+    let result = client.write_memory("s1", "Stale", Some(&memory.etag)).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+
+    // Nothing to learn from before the first turn.
+    assert!(client.suggest_memory("s1").await.unwrap().is_empty());
+
+    client.send_message("s1", "No, run cargo fmt first.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages("s1", None, None).await.unwrap().len() < 2 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    let suggestions = client.suggest_memory("s1").await.unwrap();
+    let texts: Vec<_> = suggestions.iter().map(|s| s.text.as_str()).collect();
+    assert_eq!(texts, ["Run cargo fmt first."]);
+    // The same entry isn't suggested twice.
+    assert_eq!(client.suggest_memory("s1").await.unwrap(), suggestions);
+    assert_eq!(client.memory_suggestions("s1").await.unwrap(), suggestions);
+
+    let memory = client.accept_memory_suggestion("s1", &suggestions[0].id).await.unwrap();
+    assert_eq!(memory.content, "# Project\n- Use tabs in Makefiles.\n- Run cargo fmt first.\n");
+    assert_eq!(std::fs::read_to_string(workspace.join("CLAUDE.md")).unwrap(), memory.content);
+    assert!(client.memory_suggestions("s1").await.unwrap().is_empty());
+    let result = client.dismiss_memory_suggestion("s1", &suggestions[0].id).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))), "{:?}", result);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_session_branch() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    mado_daemon::git_ops::init_repo(&workspace).unwrap();
+    let base = mado_daemon::git_ops::git_branch_info(&workspace).unwrap().branch;
+    let mut settings = mado_daemon::config::DaemonSettings::default();
+    settings.branches.per_session = true;
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    let session = client
+        .create_session_with(&mado_core::protocol::CreateSessionBody {
+            name: "Fix login timeout".to_string(),
+            cwd: Some(workspace.to_string_lossy().to_string()),
+            command: Some("sh".to_string()),
+            args: vec!["-c".to_string(), "sleep 30".to_string()],
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create session");
+    let branch = session.branch.clone().expect("No branch for the session");
+    assert_eq!(branch.name, "mado/fix-login-timeout");
+    assert_eq!(branch.base, base);
+    assert_eq!(mado_daemon::git_ops::git_branch_info(&workspace).unwrap().branch, branch.name);
+
+    // A second session in the same working tree would move the first off
+    // its branch, so it goes without one.
+    let second = client
+        .create_session_with(&mado_core::protocol::CreateSessionBody {
+            name: "Add logging".to_string(),
+            cwd: Some(workspace.to_string_lossy().to_string()),
+            command: Some("sh".to_string()),
+            args: vec!["-c".to_string(), "sleep 30".to_string()],
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create session");
+    assert!(second.branch.is_none());
+    assert_eq!(mado_daemon::git_ops::git_branch_info(&workspace).unwrap().branch, branch.name);
+    let repo = git2::Repository::open(&workspace).unwrap();
+    assert!(repo.find_branch("mado/add-logging", git2::BranchType::Local).is_err());
+    client.destroy_session(second.id.as_str()).await.unwrap();
+
+    std::fs::write(workspace.join("fix.txt"), "fixed").unwrap();
+    let body = mado_core::protocol::MergeBranchBody::default();
+    let result = client.merge_branch(session.id.as_str(), &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+
+    let milestone = mado_daemon::git_ops::save_milestone(&workspace, "Fix").unwrap();
+    let (merged, commit) = client.merge_branch(session.id.as_str(), &body).await.expect("Failed to merge");
+    assert_eq!(commit, milestone.oid);
+    assert!(merged.branch.is_none());
+    assert_eq!(mado_daemon::git_ops::git_branch_info(&workspace).unwrap().branch, base);
+    assert!(repo.find_branch(&branch.name, git2::BranchType::Local).is_err());
+
+    let result = client.merge_branch(session.id.as_str(), &body).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    client.destroy_session(session.id.as_str()).await.unwrap();
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_run_project_command() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    std::fs::write(
+        workspace.join(".mado.toml"),
+        "[commands]\ntest = \"echo checking; echo '1 failed' >&2; exit 3\"\n",
+    )
+    .unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI, answering the follow-up prompt.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+read prompt
+echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Fixed."}]}}'
+echo '{"type":"result","subtype":"success","session_id":"fake","result":"Fixed."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    let commands = client.project_commands("s1").await.unwrap();
+    assert_eq!(commands.keys().collect::<Vec<_>>(), ["test"]);
+    let result = client
+        .run_command("s1", &mado_core::protocol::RunCommandBody { name: "lint".to_string(), feed_back: false })
+        .await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))), "{:?}", result);
+
+    let run = client
+        .run_command("s1", &mado_core::protocol::RunCommandBody { name: "test".to_string(), feed_back: true })
+        .await
+        .unwrap();
+    let events = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut events = client.follow_run("s1", &run.id).await.unwrap();
+        let mut collected = Vec::new();
+        while let Some(event) = events.next().await {
+            let complete = matches!(event, RunEvent::Complete { .. });
+            collected.push(event);
+            if complete {
+                break;
+            }
+        }
+        collected
+    })
+    .await
+    .expect("Run did not finish in time");
+
+    let mut output: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            RunEvent::Output { stream, text } => Some((*stream, text.as_str())),
+            _ => None,
+        })
+        .collect();
+    output.sort_by_key(|(stream, _)| *stream == OutputStream::Stderr);
+    assert_eq!(output, [(OutputStream::Stdout, "checking\n"), (OutputStream::Stderr, "1 failed\n")]);
+    let Some(RunEvent::Complete { run: finished }) = events.last() else {
+        panic!("No complete event: {:?}", events);
+    };
+    assert_eq!(finished.exit_code, Some(3));
+    assert!(!finished.success);
+    assert!(finished.output.contains("1 failed"));
+    let follow_up = finished.follow_up_message_id.clone().expect("Failure was not fed back");
+
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    let prompt = messages.iter().find(|m| m.id == follow_up).unwrap();
+    assert!(prompt.content.contains("failed with exit code 3"), "{}", prompt.content);
+    assert!(prompt.content.contains("1 failed"));
+
+    let runs = client.list_runs("s1").await.unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].id, run.id);
+    // A finished run's stream is just its result.
+    let mut events = client.follow_run("s1", &run.id).await.unwrap();
+    assert!(matches!(events.next().await, Some(RunEvent::Complete { .. })));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_sandboxed_run() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    git2::Repository::init(&workspace).unwrap();
+    std::fs::write(workspace.join(".mado.toml"), "[commands]\nescape = \"echo escaped > ../outside.txt\"\n").unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    session.repo_root = session.working_dir.clone();
+    session.sandbox.enabled = true;
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    let escape = mado_core::protocol::RunCommandBody { name: "escape".to_string(), feed_back: false };
+
+    // The command runs in the sandbox, or not at all where there is none.
+    match client.run_command("s1", &escape).await {
+        Ok(run) => {
+            let finished = tokio::time::timeout(Duration::from_secs(10), async {
+                let mut events = client.follow_run("s1", &run.id).await.unwrap();
+                loop {
+                    match events.next().await {
+                        Some(RunEvent::Complete { run }) => break run,
+                        Some(_) => {}
+                        None => panic!("Run ended without completing"),
+                    }
+                }
+            })
+            .await
+            .expect("Run did not finish in time");
+            assert!(!finished.success, "{}", finished.output);
+        }
+        Err(mado_core::client::ClientError::Conflict(message)) => {
+            assert!(message.contains("isn't installed"), "{}", message);
+        }
+        Err(e) => panic!("Unexpected error: {:?}", e),
+    }
+    assert!(!tmp_dir.path().join("outside.txt").exists());
+
+    // A read-only session runs nothing.
+    let read_only = mado_core::types::SessionUpdate {
+        read_only: Some(true),
+        sandbox: Some(Default::default()),
+        ..Default::default()
+    };
+    client.update_session("s1", &read_only).await.unwrap();
+    let result = client.run_command("s1", &escape).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))), "{:?}", result);
+    assert!(!tmp_dir.path().join("outside.txt").exists());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_slash_commands() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    // Handled by the daemon; no Claude CLI involved.
+    let id = client.send_message("s1", "/model opus", None, &[], &[]).await.unwrap();
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    let reply = messages.last().unwrap();
+    assert_eq!(reply.id, id);
+    assert_eq!(reply.role, mado_core::types::MessageRole::System);
+    assert_eq!(reply.content, "Switched model to opus.");
+    assert_eq!(daemon_state.lock().await.sessions["s1"].model, "opus");
+
+    client.send_message("s1", "/cost", None, &[], &[]).await.unwrap();
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert!(messages.last().unwrap().content.contains("$0.0000"));
+
+    client.send_message("s1", "/clear", None, &[], &[]).await.unwrap();
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].content, "Started a new conversation.");
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_client_subscriptions() {
+    use futures::StreamExt;
+    use mado_core::types::StreamEvent;
+
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    // Subscribed before sending, so the reply is not missed.
+    let mut events = client.subscribe_events("s1").await.unwrap();
+    let id = client.send_message("s1", "/model opus", None, &[], &[]).await.unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("No chat event in time");
+    match event {
+        Some(StreamEvent::MessageComplete { message }) => assert_eq!(message.id, id),
+        other => panic!("Expected the slash command's reply, got {:?}", other),
+    }
+
+    match client.subscribe_output("missing").await {
+        Err(mado_core::client::ClientError::SessionNotFound(_)) => {}
+        Err(e) => panic!("Expected SessionNotFound, got {}", e),
+        Ok(_) => panic!("Expected SessionNotFound, got a stream"),
+    }
+
+    drop(events);
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_session_settings() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let project = tmp_dir.path().join("project");
+    std::fs::create_dir(&project).unwrap();
+    std::fs::write(
+        project.join(".mado.toml"),
+        "system_prompt = \"Use the project's test runner.\"\nauto_milestone = true\n",
+    )
+    .unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(project.display().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let source = |config: &mado_core::types::EffectiveConfig, name: &str| {
+        let setting = config.settings.iter().find(|s| s.name == name).unwrap();
+        (setting.value.clone(), setting.source)
+    };
+    let config = client.effective_config("s1").await.unwrap();
+    assert_eq!(config.project_file, Some(project.join(".mado.toml").display().to_string()));
+    assert_eq!(
+        source(&config, "system_prompt"),
+        ("Use the project's test runner.".into(), ConfigSource::Project)
+    );
+    assert_eq!(source(&config, "auto_milestone"), (true.into(), ConfigSource::Project));
+    assert_eq!(source(&config, "model"), ("sonnet".into(), ConfigSource::Session));
+
+    assert!(client.session_settings("s1").await.unwrap().system_prompt.is_none());
+    let mut settings = mado_core::types::SessionSettings {
+        system_prompt: Some("Never touch migrations.".to_string()),
+        env: HashMap::from([("NODE_OPTIONS".to_string(), "--max-old-space-size=4096".to_string())]),
+    };
+    let updated = client.update_session_settings("s1", &settings).await.unwrap();
+    assert_eq!(updated.system_prompt.as_deref(), Some("Never touch migrations."));
+    assert_eq!(updated.env, settings.env);
+    assert_eq!(
+        client.session_settings("s1").await.unwrap().system_prompt.as_deref(),
+        Some("Never touch migrations.")
+    );
+    assert_eq!(
+        daemon_state.lock().await.sessions["s1"].system_prompt.as_deref(),
+        Some("Never touch migrations.")
+    );
+    assert_eq!(daemon_state.lock().await.sessions["s1"].env, settings.env);
+    let config = client.effective_config("s1").await.unwrap();
+    assert_eq!(
+        source(&config, "system_prompt"),
+        ("Never touch migrations.".into(), ConfigSource::Session)
+    );
+
+    settings.env.insert("BAD=NAME".to_string(), "x".to_string());
+    let result = client.update_session_settings("s1", &settings).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))));
+
+    let result = client.session_settings("missing").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::SessionNotFound(_))));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_update_session() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    // Moving a session to an untrusted directory is refused.
+    let untrusted = TempDir::new().expect("Failed to create temp dir");
+    let move_away = mado_core::types::SessionUpdate {
+        working_dir: Some(untrusted.path().to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let result = client.update_session("s1", &move_away).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::WorkspaceUntrusted(_))), "{:?}", result);
+    assert!(daemon_state.lock().await.sessions["s1"].working_dir.is_none());
+    client.trust_workspace(&tmp_dir.path().to_string_lossy()).await.unwrap();
+
+    let update = mado_core::types::SessionUpdate {
+        name: Some("renamed".to_string()),
+        model: Some("opus".to_string()),
+        working_dir: Some(tmp_dir.path().to_string_lossy().to_string()),
+        permission_mode: Some(mado_core::types::PermissionMode::AutoEdit),
+        extended_thinking: Some(true),
+        tags: Some(vec!["api".to_string(), " ".to_string(), "api".to_string(), "infra".to_string()]),
+        color: Some("#3b82f6".to_string()),
+        pinned: Some(true),
+        notes: Some("Waiting on review".to_string()),
+        ..Default::default()
+    };
+    let session = client.update_session("s1", &update).await.unwrap();
+    assert_eq!(session.name, "renamed");
+    assert_eq!(session.model, "opus");
+    assert_eq!(session.permission_mode, mado_core::types::PermissionMode::AutoEdit);
+    assert!(session.extended_thinking);
+    assert_eq!(session.tags, ["api", "infra"]);
+    assert_eq!(session.color.as_deref(), Some("#3b82f6"));
+    assert!(session.pinned);
+    assert_eq!(session.notes.as_deref(), Some("Waiting on review"));
+    {
+        let state = daemon_state.lock().await;
+        let stored = &state.sessions["s1"];
+        assert_eq!(stored.model, "opus");
+        assert!(stored.extended_thinking);
+        assert_eq!(stored.working_dir.as_deref(), update.working_dir.as_deref());
+        assert!(stored.pinned);
+    }
+
+    let clear = mado_core::types::SessionUpdate {
+        color: Some(String::new()),
+        notes: Some(String::new()),
+        ..Default::default()
+    };
+    let session = client.update_session("s1", &clear).await.unwrap();
+    assert!(session.color.is_none());
+    assert!(session.notes.is_none());
+    assert_eq!(session.tags, ["api", "infra"]);
+
+    let bad_color = mado_core::types::SessionUpdate {
+        color: Some("blue".to_string()),
+        ..Default::default()
+    };
+    let result = client.update_session("s1", &bad_color).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))));
+
+    let bad_dir = mado_core::types::SessionUpdate {
+        working_dir: Some(tmp_dir.path().join("missing").to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let result = client.update_session("s1", &bad_dir).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))));
+
+    let flags = mado_core::types::SessionUpdate {
+        claude_flags: Some(vec!["--allowedTools".to_string(), "Bash".to_string()]),
+        ..Default::default()
+    };
+    let session = client.update_session("s1", &flags).await.unwrap();
+    assert_eq!(session.claude_flags, ["--allowedTools", "Bash"]);
+    let reserved_flag = mado_core::types::SessionUpdate {
+        claude_flags: Some(vec!["--output-format=json".to_string()]),
+        ..Default::default()
+    };
+    let result = client.update_session("s1", &reserved_flag).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))));
+
+    let result = client.update_session("missing", &update).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::SessionNotFound(_))));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_permission_mode() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+
+    // Stands in for the Claude CLI: keeps the arguments of each run, answers
+    // in chat mode and idles in the terminal.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+case " $* " in
+  *" -p "*)
+    printf '%s\n' "$*" > chat_args
+    read -r prompt
+    echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Done."}]}}'
+    echo '{"type":"result","subtype":"success","session_id":"fake","result":"Done."}'
+    ;;
+  *)
+    printf '%s\n' "$*" > pty_args
+    sleep 30
+    ;;
+esac
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let state = daemon_state.clone();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&workspace.to_string_lossy()).await.unwrap();
+    let session = client
+        .create_session_with(&mado_core::protocol::CreateSessionBody {
+            name: "planning".to_string(),
+            cwd: Some(workspace.to_string_lossy().to_string()),
+            permission_mode: Some(mado_core::types::PermissionMode::Plan),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create session");
+    assert_eq!(session.permission_mode, mado_core::types::PermissionMode::Plan);
+    let id = session.id.as_str();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let pty_args = loop {
+        match std::fs::read_to_string(workspace.join("pty_args")) {
+            Ok(args) if !args.is_empty() => break args,
+            _ => {
+                assert!(std::time::Instant::now() < deadline, "Terminal did not start in time");
+                sleep(Duration::from_millis(50)).await;
+            }
+        }
+    };
+    assert!(pty_args.contains("--permission-mode plan"), "{}", pty_args);
+
+    let update = mado_core::types::SessionUpdate {
+        permission_mode: Some(mado_core::types::PermissionMode::FullAuto),
+        ..Default::default()
+    };
+    client.update_session(id, &update).await.unwrap();
+    assert_eq!(
+        daemon_state.lock().await.sessions[id].permission_mode,
+        mado_core::types::PermissionMode::FullAuto
+    );
+
+    client.send_message(id, "Go ahead.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages(id, None, None).await.unwrap().len() < 2 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+    let chat_args = std::fs::read_to_string(workspace.join("chat_args")).unwrap();
+    assert!(chat_args.contains("--permission-mode bypassPermissions"), "{}", chat_args);
+
+    client.destroy_session(id).await.unwrap();
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_read_only_session() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    let repo = git2::Repository::init(&workspace).unwrap();
+    std::fs::write(workspace.join("notes.txt"), "original\n").unwrap();
+    std::fs::write(workspace.join(".gitignore"), "build/\n").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(std::path::Path::new("notes.txt")).unwrap();
+    index.add_path(std::path::Path::new(".gitignore")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[]).unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    session.repo_root = session.working_dir.clone();
+    daemon_state.lock().await.add_session(session);
+    let mut outside = terminated_session("s2");
+    outside.working_dir = Some(tmp_dir.path().to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(outside);
+
+    // Stands in for the Claude CLI: changes files from a "shell", through
+    // paths no tool input names, or calls the Write tool when asked to edit.
+    let claude = tmp_dir.path().join("claude");
+    let args_path = tmp_dir.path().join("args");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+printf '%s %s\n' "$PWD" "$*" >> ARGS
+read -r prompt
+case "$prompt" in
+  *edit*)
+    echo '{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Write","input":{"file_path":"notes.txt","content":"x"}}]}}'
+    sleep 30
+    ;;
+  *)
+    echo '{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"./tidy.sh"}}]}}'
+    echo changed > "$(printf notes).txt"
+    echo new > new.txt
+    mkdir -p build && echo out > build/out
+    echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Looked around."}]}}'
+    echo '{"type":"result","subtype":"success","session_id":"fake","result":"Looked around."}'
+    ;;
+esac
+"#
+        .replace("ARGS", &args_path.to_string_lossy()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&tmp_dir.path().to_string_lossy()).await.unwrap();
+
+    let read_only = mado_core::types::SessionUpdate {
+        read_only: Some(true),
+        ..Default::default()
+    };
+    let result = client.update_session("s2", &read_only).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))), "{:?}", result);
+    assert!(client.update_session("s1", &read_only).await.unwrap().read_only);
+
+    // The turn runs in a copy of the workspace, which goes afterwards with
+    // whatever the agent wrote, ignored files included.
+    client.send_message("s1", "Look around.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages("s1", None, None).await.unwrap().len() < 3 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+    let scratch = workspace.join(".git").join("mado-read-only").join("s1");
+    let args = std::fs::read_to_string(&args_path).unwrap();
+    assert!(args.starts_with(&format!("{} ", scratch.display())), "{}", args);
+    assert!(args.contains("--permission-mode plan"), "{}", args);
+    assert!(args.contains("--disallowedTools Edit,MultiEdit,Write,NotebookEdit"), "{}", args);
+    assert_eq!(std::fs::read_to_string(workspace.join("notes.txt")).unwrap(), "original\n");
+    assert!(!workspace.join("new.txt").exists());
+    assert!(!workspace.join("build").exists());
+    assert!(!scratch.exists());
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert_eq!(messages[1].content, "Looked around.");
+    assert_eq!(messages[2].role, mado_core::types::MessageRole::System);
+    assert!(messages[2].content.ends_with("discarded: new.txt, notes.txt."), "{}", messages[2].content);
+
+    // Calling a tool that edits files stops the turn.
+    client.send_message("s1", "Now edit it.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let messages = loop {
+        let messages = client.get_messages("s1", None, None).await.unwrap();
+        if messages.len() >= 5 {
+            break messages;
+        }
+        assert!(std::time::Instant::now() < deadline, "Turn was not stopped in time");
+        sleep(Duration::from_millis(50)).await;
+    };
+    assert_eq!(messages[4].role, mado_core::types::MessageRole::System);
+    assert!(messages[4].content.contains("Write"), "{}", messages[4].content);
+
+    // A fork is read-only from the start, terminal included.
+    std::fs::remove_file(&args_path).unwrap();
+    let fork = client.fork_session("s1", None).await.unwrap();
+    assert!(fork.read_only);
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let args = loop {
+        let args = std::fs::read_to_string(&args_path).unwrap_or_default();
+        if !args.is_empty() {
+            break args;
+        }
+        assert!(std::time::Instant::now() < deadline, "Fork's terminal did not start in time");
+        sleep(Duration::from_millis(50)).await;
+    };
+    assert!(args.contains("--permission-mode plan"), "{}", args);
+    client.destroy_session(fork.id.as_str()).await.unwrap();
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_sandbox_settings() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let sandbox = mado_core::types::SandboxSettings {
+        enabled: true,
+        allowed_hosts: vec!["github.com".to_string(), "*.npmjs.org".to_string()],
+    };
+    let update = mado_core::types::SessionUpdate {
+        sandbox: Some(sandbox.clone()),
+        ..Default::default()
+    };
+    assert_eq!(client.update_session("s1", &update).await.unwrap().sandbox, sandbox);
+    assert_eq!(client.list_sessions().await.unwrap()[0].sandbox, sandbox);
+    assert_eq!(daemon_state.lock().await.sessions["s1"].sandbox, sandbox);
+
+    let update = mado_core::types::SessionUpdate {
+        sandbox: Some(mado_core::types::SandboxSettings {
+            enabled: true,
+            allowed_hosts: vec!["https://github.com".to_string()],
+        }),
+        ..Default::default()
+    };
+    let result = client.update_session("s1", &update).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))), "{:?}", result);
+    assert_eq!(client.list_sessions().await.unwrap()[0].sandbox, sandbox);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let mut session = terminated_session("s1");
+    session.name = "fix build".to_string();
+    session.working_dir = Some(tmp_dir.path().to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI: runs a command, fails to write a file
+    // and reads one.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        r#"#!/bin/sh
+read -r prompt
+echo '{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo build"}},{"type":"tool_use","id":"t2","name":"Write","input":{"file_path":"src/main.rs","content":"x"}},{"type":"tool_use","id":"t3","name":"Read","input":{"file_path":"Cargo.toml"}}]}}'
+echo '{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"},{"type":"tool_result","tool_use_id":"t2","content":"denied","is_error":true},{"type":"tool_result","tool_use_id":"t3","content":"[package]"}]}}'
+echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Built."}]}}'
+echo '{"type":"result","subtype":"success","session_id":"fake","result":"Built."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&tmp_dir.path().to_string_lossy()).await.unwrap();
+    assert!(client.audit_log("s1").await.unwrap().is_empty());
+
+    client.send_message("s1", "Build it.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while client.get_messages("s1", None, None).await.unwrap().len() < 2 {
+        assert!(std::time::Instant::now() < deadline, "Turn did not finish in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    let entries = client.audit_log("s1").await.unwrap();
+    let logged: Vec<_> = entries.iter().map(|e| (e.tool.as_str(), e.summary.as_str(), e.outcome)).collect();
+    assert_eq!(
+        logged,
+        [
+            ("Bash", "cargo build", mado_core::types::AuditOutcome::Succeeded),
+            ("Write", "src/main.rs", mado_core::types::AuditOutcome::Failed),
+        ]
+    );
+    assert_eq!(entries[1].paths, ["src/main.rs"]);
+
+    let (file_name, csv) = client
+        .export_audit_log("s1", mado_core::types::AuditFormat::Csv)
+        .await
+        .unwrap();
+    assert_eq!(file_name, "fix-build-audit.csv");
+    assert!(csv.starts_with("timestamp,tool,summary,paths,outcome,tool_call_id\r\n"), "{}", csv);
+    assert!(csv.contains(",Write,src/main.rs,src/main.rs,failed,t2\r\n"), "{}", csv);
+    let (_, json) = client
+        .export_audit_log("s1", mado_core::types::AuditFormat::Json)
+        .await
+        .unwrap();
+    assert_eq!(serde_json::from_str::<Vec<mado_core::types::AuditEntry>>(&json).unwrap(), entries);
+
+    let result = client.audit_log("missing").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::SessionNotFound(_))), "{:?}", result);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_rate_limit_retry() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(tmp_dir.path().to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    // Stands in for the Claude CLI: rate limited the first time, after
+    // retrying once by itself, and answers the second.
+    let claude = tmp_dir.path().join("claude");
+    std::fs::write(
+        &claude,
+        format!(
+            r#"#!/bin/sh
+read -r prompt
+if [ ! -f "{marker}" ]; then
+  touch "{marker}"
+  echo '{{"type":"system","subtype":"api_retry","attempt":1,"retry_delay_ms":500,"error_status":429,"error":"rate_limit"}}'
+  echo '{{"type":"result","subtype":"success","is_error":true,"session_id":"fake","result":"API Error: 429 rate_limit_error, retry-after: 2"}}'
+  exit 0
+fi
+echo '{{"type":"assistant","message":{{"content":[{{"type":"text","text":"Done."}}]}}}}'
+echo '{{"type":"result","subtype":"success","session_id":"fake","result":"Done."}}'
+"#,
+            marker = tmp_dir.path().join("limited").display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut settings = mado_daemon::config::DaemonSettings {
+        claude_path: Some(claude),
+        ..Default::default()
+    };
+    settings.chat.retry_rate_limits = true;
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let conversations_dir = tmp_dir.path().join("conversations");
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            mado_daemon::server::ServerOptions {
+                settings: Arc::new(std::sync::RwLock::new(settings)),
+                conversations_dir: Some(conversations_dir),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    client.trust_workspace(&tmp_dir.path().to_string_lossy()).await.unwrap();
+    assert!(client.session_usage("s1").await.unwrap().backoff.is_none());
+
+    client.send_message("s1", "Go.", None, &[], &[]).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let backoff = loop {
+        let usage = client.session_usage("s1").await.unwrap();
+        if let Some(backoff) = usage.backoff.filter(|backoff| backoff.failures == 1) {
+            break backoff;
+        }
+        assert!(std::time::Instant::now() < deadline, "Rate limit was not noticed in time");
+        sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(backoff.kind, mado_core::types::ChatErrorKind::RateLimited);
+    assert!(backoff.retrying);
+    assert!(backoff.until > chrono::Utc::now() + chrono::Duration::milliseconds(1500), "{:?}", backoff);
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert!(messages.last().unwrap().content.contains("Retrying in 2 seconds."), "{:?}", messages);
+
+    // Sent again by itself once the wait is over.
+    loop {
+        let messages = client.get_messages("s1", None, None).await.unwrap();
+        if messages.last().is_some_and(|m| m.content == "Done.") {
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "Message was not retried in time");
+        sleep(Duration::from_millis(50)).await;
+    }
+    assert!(client.session_usage("s1").await.unwrap().backoff.is_none());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_edit_message_rejects_non_user_messages() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let reply = client.send_message("s1", "/cost", None, &[], &[]).await.unwrap();
+    let result = client.edit_message("s1", &reply, "Hi", None, &[], &[]).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Validation(_))));
+    let result = client.edit_message("s1", "missing", "Hi", None, &[], &[]).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))));
+    assert_eq!(client.get_messages("s1", None, None).await.unwrap().len(), 1);
+    // Nothing failed, so there is nothing to retry.
+    let result = client.retry_message("s1").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))));
+    // No response is running, so there is no prompt to answer.
+    let result = client.send_response_input("s1", "yes").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::Conflict(_))));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_delete_and_clear_messages() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let mut session = terminated_session("s1");
+    session.claude_session_id = Some("claude-1".to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let first = client.send_message("s1", "/cost", None, &[], &[]).await.unwrap();
+    client.send_message("s1", "/model", None, &[], &[]).await.unwrap();
+    client.send_message("s1", "/model", None, &[], &[]).await.unwrap();
+
+    client.delete_message("s1", &first).await.unwrap();
+    let messages = client.get_messages("s1", None, None).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(messages.iter().all(|m| m.id != first));
+    assert!(daemon_state.lock().await.sessions["s1"].claude_session_id.is_none());
+    let result = client.delete_message("s1", &first).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))));
+
+    assert_eq!(client.clear_messages("s1").await.unwrap(), 2);
+    assert!(client.get_messages("s1", None, None).await.unwrap().is_empty());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_export_conversation() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    client.send_message("s1", "/model", None, &[], &[]).await.unwrap();
+    let (file_name, content) = client
+        .export_conversation("s1", mado_core::types::ExportFormat::Markdown, false)
+        .await
+        .unwrap();
+    assert_eq!(file_name, "s1.md");
+    assert!(content.starts_with("# s1\n"));
+    assert!(content.contains("Model: sonnet."));
+
+    let (file_name, content) = client
+        .export_conversation("s1", mado_core::types::ExportFormat::Json, false)
+        .await
+        .unwrap();
+    assert_eq!(file_name, "s1.json");
+    let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(json["messages"].as_array().unwrap().len(), 1);
+
+    let (status, _) = get_request(&socket_path, "/sessions/s1/export?format=pdf").await;
+    assert_eq!(status, 400);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_session_usage() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let usage = client.session_usage("s1").await.unwrap();
+    assert_eq!(usage.total.input_tokens, 0);
+    assert_eq!(usage.context_tokens, 0);
+    assert_eq!(usage.context_window, 200_000);
+
+    client.send_message("s1", "/model sonnet[1m]", None, &[], &[]).await.unwrap();
+    assert_eq!(client.session_usage("s1").await.unwrap().context_window, 1_000_000);
+
+    let result = client.session_usage("missing").await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::SessionNotFound(_))));
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_usage_report_and_budget() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+
+    // Today's spending is already over the daily limit.
+    let today = chrono::Utc::now().date_naive();
+    let ledger = serde_json::json!([
+        {
+            "date": "2020-01-01",
+            "project": "/work",
+            "usage": { "input_tokens": 100, "output_tokens": 10 },
+            "cost_usd": 1.0,
+            "turns": 1,
+        },
+        {
+            "date": today.to_string(),
+            "project": "/work",
+            "usage": { "input_tokens": 200, "output_tokens": 20 },
+            "cost_usd": 6.0,
+            "turns": 2,
+        },
+    ]);
+    std::fs::write(tmp_dir.path().join("usage.json"), ledger.to_string()).unwrap();
+    let settings = mado_daemon::config::DaemonSettings {
+        budget: mado_daemon::config::BudgetConfig {
+            daily_usd: Some(5.0),
+            block: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let socket_path_clone = socket_path.clone();
+    let options = mado_daemon::server::ServerOptions {
+        settings: Arc::new(std::sync::RwLock::new(settings)),
+        ..Default::default()
+    };
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            options,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    let report = client.usage_report(None).await.unwrap();
+    assert_eq!(report.entries.len(), 2);
+    assert_eq!(report.total.input_tokens, 300);
+    assert_eq!(report.total_cost_usd, 7.0);
+
+    let report = client.usage_report(Some(today)).await.unwrap();
+    assert_eq!(report.since, Some(today));
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.entries[0].turns, 2);
+
+    let result = client.send_message("s1", "Hello", None, &[], &[]).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::BudgetExceeded(_))));
+    // Slash commands still work.
+    client.send_message("s1", "/cost", None, &[], &[]).await.unwrap();
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_task_validation() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    let cwd = tmp_dir.path().to_string_lossy().to_string();
+    let missing = tmp_dir.path().join("missing").to_string_lossy().to_string();
+    for (prompt, cwd) in [("  ", &cwd), ("/compact", &cwd), ("Fix the tests", &missing)] {
+        let (status, body) =
+            post_json(&socket_path, "/tasks", serde_json::json!({ "prompt": prompt, "cwd": cwd })).await;
+        assert_eq!(status, 422, "prompt {:?} in {}", prompt, cwd);
+        let response: DaemonResponse = serde_json::from_slice(&body).expect("Invalid JSON");
+        assert!(matches!(response, DaemonResponse::Error { .. }));
+    }
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_schedules() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    let cwd = tmp_dir.path().to_string_lossy().to_string();
+    let mode = mado_core::types::PermissionMode::AutoEdit;
+
+    let err = client
+        .create_schedule("every night", "Update the CHANGELOG", &cwd, None, mode)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::Validation(_)), "{:?}", err);
+    let err = client
+        .create_schedule("0 2 * * *", "Update the CHANGELOG", &cwd, None, mode)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::WorkspaceUntrusted(_)), "{:?}", err);
+    client.trust_workspace(&cwd).await.expect("Failed to trust workspace");
+
+    let schedule = client
+        .create_schedule("0 2 * * *", "Update the CHANGELOG", &cwd, Some("haiku"), mode)
+        .await
+        .expect("Failed to create schedule");
+    assert_eq!(schedule.model, "haiku");
+    assert!(schedule.next_run.unwrap() > schedule.created_at);
+    assert_eq!(client.list_schedules().await.unwrap()[0].id, schedule.id);
+    assert!(client.schedule_runs(&schedule.id).await.unwrap().is_empty());
+    // Kept next to state.json.
+    assert!(tmp_dir.path().join("schedules.json").exists());
+
+    client.delete_schedule(&schedule.id).await.expect("Failed to delete schedule");
+    assert!(client.list_schedules().await.unwrap().is_empty());
+    let err = client.schedule_runs(&schedule.id).await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::NotFound(_)), "{:?}", err);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_templates() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+    let cwd = tmp_dir.path().to_string_lossy().to_string();
+
+    let template = mado_core::types::SessionTemplate {
+        name: "shell pane".to_string(),
+        cwd: Some(cwd.clone()),
+        env: HashMap::from([("MADO_TEST".to_string(), "1".to_string())]),
+        command: Some(mado_core::types::CustomCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), "sleep 30".to_string()],
+        }),
+        auto_milestone: Some(true),
+        ..Default::default()
+    };
+    client.save_template(&template).await.expect("Failed to save template");
+    assert_eq!(client.list_templates().await.unwrap(), vec![template.clone()]);
+    // Kept next to state.json.
+    assert!(tmp_dir.path().join("templates").join("shell pane.json").exists());
+
+    let from_template = mado_core::protocol::CreateSessionBody {
+        name: "from template".to_string(),
+        template: Some("shell pane".to_string()),
+        ..Default::default()
+    };
+    let err = client.create_session_with(&from_template).await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::WorkspaceUntrusted(_)), "{:?}", err);
+    let err = client.trust_workspace(&tmp_dir.path().join("missing").to_string_lossy()).await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::Validation(_)), "{:?}", err);
+    client.trust_workspace(&cwd).await.expect("Failed to trust workspace");
+    assert!(daemon_state.lock().await.is_trusted(tmp_dir.path()));
+
+    let session = client
+        .create_session_with(&from_template)
+        .await
+        .expect("Failed to create session from template");
+    assert_eq!(session.working_dir.as_deref(), Some(cwd.as_str()));
+    assert_eq!(session.env, template.env);
+    assert_eq!(session.custom_command, template.command);
+    assert_eq!(session.auto_milestone, Some(true));
+    client.destroy_session(session.id.as_str()).await.unwrap();
+
+    let err = client
+        .create_session_with(&mado_core::protocol::CreateSessionBody {
+            name: "missing".to_string(),
+            template: Some("missing".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::NotFound(_)), "{:?}", err);
+    let invalid = mado_core::types::SessionTemplate {
+        name: "../escape".to_string(),
+        ..Default::default()
+    };
+    let err = client.save_template(&invalid).await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::Validation(_)), "{:?}", err);
+
+    client.delete_template("shell pane").await.expect("Failed to delete template");
+    assert!(client.list_templates().await.unwrap().is_empty());
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_prompt_library() {
+    use mado_core::types::{PromptScope, SavedPrompt};
+
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    let workspace = tmp_dir.path().join("workspace");
+    std::fs::create_dir(&workspace).unwrap();
+    let repo = git2::Repository::init(&workspace).unwrap();
+    let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    repo.commit(Some("refs/heads/feature"), &signature, &signature, "Initial", &tree, &[])
+        .unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+    std::fs::write(
+        workspace.join(".mado.toml"),
+        "[prompts]\ntests = \"Add tests for {{file}} on {{branch}}.\"\n",
+    )
+    .unwrap();
+    let mut session = terminated_session("s1");
+    session.working_dir = Some(workspace.to_string_lossy().to_string());
+    daemon_state.lock().await.add_session(session);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    for (name, content) in [("tests", "Write tests."), ("explain", "Explain {{file}}.")] {
+        let prompt = SavedPrompt {
+            name: name.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        };
+        client.save_prompt(&prompt).await.expect("Failed to save prompt");
+    }
+    // Kept next to state.json.
+    assert!(tmp_dir.path().join("prompts").join("explain.json").exists());
+    let prompts = client.list_prompts(None).await.unwrap();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(prompts[0].variables, ["file"]);
+
+    // The project's prompt of the same name wins for its sessions.
+    let prompts = client.list_prompts(Some("s1")).await.unwrap();
+    let tests = prompts.iter().find(|p| p.name == "tests").unwrap();
+    assert_eq!(tests.scope, PromptScope::Project);
+    assert_eq!(tests.variables, ["file", "branch"]);
+
+    let body = mado_core::protocol::RenderPromptBody {
+        session_id: Some("s1".to_string()),
+        variables: HashMap::from([("file".to_string(), "src/lib.rs".to_string())]),
+    };
+    let rendered = client.render_prompt("tests", &body).await.unwrap();
+    assert_eq!(rendered.content, "Add tests for src/lib.rs on feature.");
+    assert!(rendered.missing.is_empty());
+    let rendered = client
+        .render_prompt("explain", &mado_core::protocol::RenderPromptBody::default())
+        .await
+        .unwrap();
+    assert_eq!(rendered.missing, ["file"]);
+
+    let empty = SavedPrompt {
+        name: "empty".to_string(),
+        ..Default::default()
+    };
+    let err = client.save_prompt(&empty).await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::Validation(_)), "{:?}", err);
+    client.delete_prompt("tests").await.expect("Failed to delete prompt");
+    let err = client.delete_prompt("tests").await.unwrap_err();
+    assert!(matches!(err, mado_core::client::ClientError::NotFound(_)), "{:?}", err);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_fork_session() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+    daemon_state.lock().await.add_session(terminated_session("s1"));
+    let untrusted = TempDir::new().expect("Failed to create temp dir");
+    daemon_state.lock().await.add_session(mado_core::types::Session {
+        working_dir: Some(untrusted.path().to_string_lossy().to_string()),
+        ..terminated_session("s2")
+    });
+
+    let socket_path_clone = socket_path.clone();
+    let server_state = daemon_state.clone();
+    let server_state_path = state_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(socket_path_clone, server_state_path, server_state, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    // Rejected before any process is spawned.
+    let result = client.fork_session("s1", Some("missing")).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::NotFound(_))));
+    let result = client.fork_session("missing", None).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::SessionNotFound(_))));
+    let result = client.fork_session("s2", None).await;
+    assert!(matches!(result, Err(mado_core::client::ClientError::WorkspaceUntrusted(_))), "{:?}", result);
+    assert_eq!(daemon_state.lock().await.sessions.len(), 2);
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_handle).await;
+
+    let manager = mado_daemon::conversation::ConversationManager::new(
+        tmp_dir.path().join("conversations"),
+        daemon_state.clone(),
+        state_path,
+    );
+    let mut session = terminated_session("s1");
+    session.claude_session_id = Some("claude-1".to_string());
+    manager.init_session(&session).await;
+    let source = mado_core::types::SessionId::new("s1");
+    let options = mado_daemon::conversation::MessageOptions::default;
+    let first = manager.send_message(&source, "/cost".to_string(), options()).await.unwrap();
+    manager.send_message(&source, "/model opus".to_string(), options()).await.unwrap();
+
+    // At the latest message: branch off the Claude session.
+    let fork = manager.fork(&source, None).await.unwrap();
+    assert_eq!(fork.messages.len(), 2);
+    assert_eq!(fork.claude_session_id.as_deref(), Some("claude-1"));
+    assert!(fork.fork_session);
+    assert!(!fork.replay_history);
+    assert_eq!(fork.model, "opus");
+
+    // Earlier: a new Claude session with the messages replayed.
+    let fork = manager.fork(&source, Some(&first)).await.unwrap();
+    assert_eq!(fork.messages.len(), 1);
+    assert_eq!(fork.messages[0].id, first);
+    assert!(fork.claude_session_id.is_none());
+    assert!(!fork.fork_session);
+    assert!(fork.replay_history);
+}
+
+#[tokio::test]
+async fn test_socket_permissions_are_0600() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let _server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    let metadata = std::fs::metadata(&socket_path).expect("Failed to get socket metadata");
+    let permissions = metadata.permissions();
+    let mode = permissions.mode() & 0o777;
+    assert_eq!(
+        mode, 0o600,
+        "Socket permissions should be 0600, got {:o}",
+        mode
+    );
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+}
+
+#[tokio::test]
+async fn test_stale_socket_cleanup() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    // Create a dummy file at the socket path to simulate a stale socket.
+    std::fs::write(&socket_path, "stale").expect("Failed to create dummy file");
+    assert!(socket_path.exists(), "Dummy file should exist");
+
+    let socket_path_clone = socket_path.clone();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server should handle stale socket file");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    // Verify the daemon is functional after stale socket cleanup.
+    let (status, _body) = get_request(&socket_path, "/ping").await;
+    assert_eq!(status, 200);
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
+}
+
+#[tokio::test]
+async fn test_client_health_check() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let _server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    // Test using the DaemonClient from mado-core.
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    // connect() should succeed
+    client.connect().await.expect("Client connect should succeed");
+
+    // health() should return valid status
+    let status = client.health().await.expect("Health check should succeed");
+    assert!(status.pid > 0);
+    assert_eq!(status.session_count, 0);
+    assert!(!status.version.is_empty());
+    assert_eq!(status.protocol_version, mado_core::protocol::PROTOCOL_VERSION);
+
+    // version() should report the protocol connect() checked against.
+    let version = client.version().await.expect("Version should succeed");
+    assert_eq!(version.version, status.version);
+    assert_eq!(version.protocol_version, mado_core::protocol::PROTOCOL_VERSION);
+
+    // Only sessions with a live process have scrollback.
+    assert!(matches!(
+        client.get_scrollback("missing").await,
+        Err(mado_core::client::ClientError::SessionNotFound(_))
+    ));
+    assert!(matches!(
+        client.restart_session("missing", 24, 80).await,
+        Err(mado_core::client::ClientError::SessionNotFound(_))
+    ));
+    assert!(matches!(
+        client.get_recording("missing").await,
+        Err(mado_core::client::ClientError::SessionNotFound(_))
+    ));
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+}
+
+#[tokio::test]
+async fn test_client_survives_daemon_restart() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let client = mado_core::client::DaemonClient::new(&socket_path);
+
+    for _ in 0..2 {
+        let (daemon_state, state_path) = create_test_state(&tmp_dir);
+        let socket_path_clone = socket_path.clone();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server_handle = tokio::spawn(async move {
+            mado_daemon::server::start_server(socket_path_clone, state_path, daemon_state, async {
+                shutdown_rx.await.ok();
+            })
+            .await
+            .expect("Server failed to start");
+        });
+        assert!(
+            wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+            "Socket did not appear in time"
+        );
+
+        // The second time round the pooled connection is to the old daemon.
+        client.ping().await.expect("Ping should succeed");
+        client.ping().await.expect("Ping should succeed");
+
+        shutdown_tx.send(()).expect("Failed to send shutdown");
+        server_handle.await.expect("Server task panicked");
+    }
+
+    assert!(matches!(
+        client.ping().await,
+        Err(mado_core::client::ClientError::ConnectionFailed { .. })
+    ));
 }
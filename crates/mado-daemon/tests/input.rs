@@ -0,0 +1,123 @@
+mod common;
+
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use mado_core::protocol::{DaemonResponse, ErrorCode};
+use mado_daemon::server::MAX_INPUT_BYTES;
+use common::{create_test_state, wait_for_socket};
+
+/// Helper to send a POST request to the daemon over a Unix socket.
+async fn post_request(
+    socket_path: &std::path::Path,
+    path: &str,
+    content_type: &str,
+    body: Vec<u8>,
+) -> (u16, Bytes) {
+    let stream = UnixStream::connect(socket_path).await.expect("Failed to connect to socket");
+    let io = TokioIo::new(stream);
+
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+        .await
+        .expect("Handshake failed");
+
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            eprintln!("Connection error: {}", e);
+        }
+    });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("Host", "localhost")
+        .header("Content-Type", content_type)
+        .body(Full::new(Bytes::from(body)))
+        .expect("Failed to build request");
+
+    let resp = sender.send_request(req).await.expect("Request failed");
+    let status = resp.status().as_u16();
+    let body = resp.into_body().collect().await.expect("Failed to collect body").to_bytes();
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_input_size_limits() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    // Base64 payload that decodes past the cap is rejected before decoding.
+    let encoded = "A".repeat(MAX_INPUT_BYTES / 3 * 4 + 8);
+    let body = serde_json::to_vec(&serde_json::json!({ "data": encoded })).unwrap();
+    let (status, body) =
+        post_request(&socket_path, "/sessions/missing/input", "application/json", body).await;
+    assert_eq!(status, 422);
+    match serde_json::from_slice::<DaemonResponse>(&body).expect("Failed to parse response") {
+        DaemonResponse::Error { code, message } => {
+            assert_eq!(code, ErrorCode::Validation);
+            assert!(message.contains("too large"), "unexpected error: {}", message)
+        }
+        other => panic!("Expected Error response, got: {:?}", other),
+    }
+
+    // Raw body over the cap is rejected.
+    let (status, body) = post_request(
+        &socket_path,
+        "/sessions/missing/input/raw",
+        "application/octet-stream",
+        vec![b'a'; MAX_INPUT_BYTES + 1],
+    )
+    .await;
+    assert_eq!(status, 422);
+    assert!(matches!(
+        serde_json::from_slice::<DaemonResponse>(&body).expect("Failed to parse response"),
+        DaemonResponse::Error { code: ErrorCode::Validation, .. }
+    ));
+
+    // Raw body within the cap reaches the session lookup.
+    let (status, body) = post_request(
+        &socket_path,
+        "/sessions/missing/input/raw",
+        "application/octet-stream",
+        b"ls\r".to_vec(),
+    )
+    .await;
+    assert_eq!(status, 404);
+    match serde_json::from_slice::<DaemonResponse>(&body).expect("Failed to parse response") {
+        DaemonResponse::Error { code, message } => {
+            assert_eq!(code, ErrorCode::SessionNotFound);
+            assert!(message.contains("missing"), "unexpected error: {}", message)
+        }
+        other => panic!("Expected Error response, got: {:?}", other),
+    }
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
+}
@@ -1,3 +1,5 @@
+mod common;
+
 use std::fs;
 use std::time::Duration;
 
@@ -9,18 +11,7 @@ use mado_daemon::lifecycle::{DaemonConfig, start_with_shutdown};
 use mado_daemon::pid::PidFile;
 use mado_daemon::state::DaemonState;
 
-/// Wait for a socket file to appear on disk.
-async fn wait_for_socket(socket_path: &std::path::Path, timeout: Duration) -> bool {
-    let start = std::time::Instant::now();
-    while start.elapsed() < timeout {
-        if socket_path.exists() {
-            sleep(Duration::from_millis(50)).await;
-            return true;
-        }
-        sleep(Duration::from_millis(20)).await;
-    }
-    false
-}
+use common::{session, wait_for_socket};
 
 /// Helper to create a daemon config in a temp dir.
 fn make_config(tmp: &TempDir) -> DaemonConfig {
@@ -29,6 +20,12 @@ fn make_config(tmp: &TempDir) -> DaemonConfig {
         pid_path: tmp.path().join("test.pid"),
         state_path: tmp.path().join("state.json"),
         daemonize: false,
+        listen: None,
+        successor: None,
+        handover: None,
+        respawn_sessions: false,
+        log_filter: None,
+        log_dir: None,
     }
 }
 
@@ -156,20 +153,8 @@ fn test_state_persistence_save_and_load() {
 
     let mut state = DaemonState::new();
     state.add_session(mado_core::types::Session {
-        id: mado_core::types::SessionId::new("test-1"),
         name: "Test Session".to_string(),
-        model: "sonnet".to_string(),
-        status: mado_core::types::SessionStatus::Active,
-        created_at: chrono::Utc::now(),
-        updated_at: chrono::Utc::now(),
-        working_dir: None,
-        command: None,
-        shell_fallback: false,
-        conversation_state: mado_core::types::ConversationState::Empty,
-        claude_session_id: None,
-        message_count: 0,
-        total_usage: None,
-        total_cost_usd: None,
+        ..session("test-1")
     });
 
     // Save
@@ -234,3 +219,186 @@ async fn test_state_saved_on_shutdown() {
     // Empty state is fine -- no sessions were created.
     assert!(loaded.sessions.is_empty());
 }
+
+#[tokio::test]
+async fn test_api_token_generated_and_enforced() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config = make_config(&tmp);
+    let socket_path = config.socket_path.clone();
+    let token_path = config.token_path();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        start_with_shutdown(config, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .unwrap();
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear"
+    );
+
+    // Token is generated on first start, owner-only.
+    let mode = fs::metadata(&token_path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o600);
+
+    // Requests without the token are rejected.
+    let url = format!("unix://{}", socket_path.display());
+    let anonymous = DaemonClient::from_url(&url).unwrap();
+    assert!(matches!(
+        anonymous.ping().await,
+        Err(mado_core::client::ClientError::Unauthorized)
+    ));
+
+    // The default client reads the token from next to the socket.
+    DaemonClient::new(&socket_path).ping().await.expect("Ping with token failed");
+
+    shutdown_tx.send(()).unwrap();
+    server_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_persisted_sessions_marked_terminated_on_start() {
+    use mado_core::types::SessionStatus;
+
+    let tmp = TempDir::new().unwrap();
+    let config = make_config(&tmp);
+    let socket_path = config.socket_path.clone();
+    let state_path = config.state_path.clone();
+
+    // A session left behind by a previous daemon; its process is long gone.
+    let mut state = DaemonState::new();
+    state.add_session(mado_core::types::Session {
+        name: "Stale".to_string(),
+        working_dir: Some(tmp.path().to_string_lossy().to_string()),
+        claude_session_id: Some("claude-abc".to_string()),
+        ..session("stale-1")
+    });
+    state.save(&state_path).unwrap();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        start_with_shutdown(config, async {
+            shutdown_rx.await.ok();
+        })
+        .await
+        .unwrap();
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear"
+    );
+
+    let client = DaemonClient::new(&socket_path);
+    let sessions = client.list_sessions().await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].status, SessionStatus::Terminated);
+    // The stored resume id survives for a later revive.
+    assert_eq!(sessions[0].claude_session_id.as_deref(), Some("claude-abc"));
+
+    assert!(matches!(
+        client.revive_session("missing", 24, 80).await,
+        Err(mado_core::client::ClientError::SessionNotFound(_))
+    ));
+
+    shutdown_tx.send(()).unwrap();
+    server_handle.await.unwrap();
+
+    let loaded = DaemonState::load(&state_path).unwrap();
+    assert_eq!(loaded.sessions["stale-1"].status, SessionStatus::Terminated);
+}
+
+#[test]
+fn test_reload_settings_keeps_last_good_config() {
+    use mado_daemon::config::SharedSettings;
+    use mado_daemon::lifecycle::reload_settings;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = tmp.path().join("config.json");
+    let settings = SharedSettings::default();
+
+    // A missing config leaves the defaults.
+    reload_settings(&config_path, &settings, None);
+    assert_eq!(settings.read().unwrap().default_model, "sonnet");
+
+    fs::write(&config_path, r#"{"default_model": "haiku", "auto_milestone": true}"#).unwrap();
+    reload_settings(&config_path, &settings, None);
+    {
+        let current = settings.read().unwrap();
+        assert_eq!(current.default_model, "haiku");
+        assert!(current.auto_milestone);
+        assert!(current.api_key.is_none());
+    }
+
+    // A broken edit is ignored rather than resetting to defaults.
+    fs::write(&config_path, "{ not json").unwrap();
+    assert!(!reload_settings(&config_path, &settings, None));
+    assert_eq!(settings.read().unwrap().default_model, "haiku");
+}
+
+#[tokio::test]
+async fn test_config_file_change_reloads_settings() {
+    use mado_daemon::config::SharedSettings;
+    use mado_daemon::lifecycle::spawn_reload_handler;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = tmp.path().join("config.json");
+    fs::write(&config_path, r#"{"default_model": "sonnet"}"#).unwrap();
+    let settings = SharedSettings::default();
+    let (changes_tx, mut changes) = tokio::sync::watch::channel(());
+    let handler = spawn_reload_handler(config_path.clone(), settings.clone(), None, changes_tx);
+    // Let the handler record the current modification time.
+    sleep(Duration::from_millis(200)).await;
+
+    // Saved without a SIGHUP, as the settings page does.
+    fs::write(&config_path, r#"{"default_model": "haiku"}"#).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), changes.changed())
+        .await
+        .expect("config.json change was not picked up")
+        .unwrap();
+    assert_eq!(settings.read().unwrap().default_model, "haiku");
+
+    handler.abort();
+}
+
+#[test]
+fn test_config_validation() {
+    use mado_daemon::config::{ConfigError, MadoConfig};
+
+    assert!(MadoConfig::default().validate().is_ok());
+
+    let mut config = MadoConfig {
+        default_model: "gpt-4".to_string(),
+        claude_path: Some("/nonexistent/claude".to_string()),
+        default_flags: vec!["--resume".to_string()],
+        ..Default::default()
+    };
+    config.budget.daily_usd = Some(-5.0);
+    config.budget.warn_at = 1.5;
+    config.sandbox.allowed_hosts = vec!["api.anthropic.com:443".to_string()];
+    config.ui.zoom_level = 10;
+    config.base_urls.insert("anthropic".to_string(), "proxy.internal".to_string());
+    let Err(ConfigError::Invalid(errors)) = config.validate() else {
+        panic!("invalid config accepted");
+    };
+    let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+    assert_eq!(
+        fields,
+        [
+            "default_model",
+            "claude_path",
+            "default_flags",
+            "base_urls.anthropic",
+            "budget.daily_usd",
+            "budget.warn_at",
+            "sandbox.allowed_hosts",
+            "ui.zoom_level",
+        ]
+    );
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::sleep;
+
+use mado_core::client::{ClientError, DaemonClient};
+use mado_daemon::remote::{TcpListenConfig, TlsFiles};
+use mado_daemon::server::ServerOptions;
+use common::create_test_state;
+
+const TOKEN: &str = "test-token-0123456789";
+
+/// Pick a free loopback port.
+fn free_addr() -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+    listener.local_addr().expect("No local address")
+}
+
+/// Start a server with a TCP listener, returning its shutdown trigger and task.
+async fn start(
+    tmp_dir: &TempDir,
+    tcp: TcpListenConfig,
+) -> (tokio::sync::oneshot::Sender<()>, tokio::task::JoinHandle<()>) {
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(tmp_dir);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+    let socket_path_clone = socket_path.clone();
+    let handle = tokio::spawn(async move {
+        mado_daemon::server::start_server_with_options(
+            socket_path_clone,
+            ServerOptions {
+                auth_token: Some(TOKEN.to_string()),
+                tcp: Some(tcp),
+                ..Default::default()
+            },
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    let start = std::time::Instant::now();
+    while !socket_path.exists() && start.elapsed() < Duration::from_secs(5) {
+        sleep(Duration::from_millis(20)).await;
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    (shutdown_tx, handle)
+}
+
+#[tokio::test]
+async fn test_tcp_listener_requires_token() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let addr = free_addr();
+    let (shutdown_tx, handle) = start(
+        &tmp_dir,
+        TcpListenConfig {
+            addr,
+            tls: None,
+        },
+    )
+    .await;
+
+    let url = format!("tcp://{}", addr);
+
+    let anonymous = DaemonClient::from_url(&url).unwrap();
+    assert!(matches!(anonymous.ping().await, Err(ClientError::Unauthorized)));
+
+    let wrong = DaemonClient::from_url(&url).unwrap().with_token("nope");
+    assert!(matches!(wrong.ping().await, Err(ClientError::Unauthorized)));
+
+    let client = DaemonClient::from_url(&url).unwrap().with_token(TOKEN);
+    let status = client.health().await.expect("Health over TCP failed");
+    assert_eq!(status.session_count, 0);
+
+    // The Unix socket enforces the same token.
+    let local = DaemonClient::new(tmp_dir.path().join("test.sock"));
+    assert!(matches!(local.ping().await, Err(ClientError::Unauthorized)));
+    let local = local.with_token(TOKEN);
+    local.ping().await.expect("Ping over Unix socket failed");
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    handle.await.expect("Server task panicked");
+}
+
+#[tokio::test]
+async fn test_tls_listener() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .expect("Failed to generate certificate");
+    let cert_pem = cert.cert.pem();
+    let cert_path = tmp_dir.path().join("cert.pem");
+    let key_path = tmp_dir.path().join("key.pem");
+    std::fs::write(&cert_path, &cert_pem).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+    let addr = free_addr();
+    let (shutdown_tx, handle) = start(
+        &tmp_dir,
+        TcpListenConfig {
+            addr,
+            tls: Some(TlsFiles {
+                cert_path,
+                key_path,
+            }),
+        },
+    )
+    .await;
+
+    let url = format!("tls://localhost:{}", addr.port());
+
+    // Self-signed certificate is rejected unless trusted explicitly.
+    let untrusted = DaemonClient::from_url(&url).unwrap().with_token(TOKEN);
+    assert!(matches!(untrusted.ping().await, Err(ClientError::Tls(_))));
+
+    let client = DaemonClient::from_url(&url)
+        .unwrap()
+        .with_token(TOKEN)
+        .with_ca_cert_pem(cert_pem.as_bytes())
+        .expect("Failed to load CA");
+    client.ping().await.expect("Ping over TLS failed");
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    handle.await.expect("Server task panicked");
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use mado_core::client::{ClientError, DaemonClient};
+use mado_core::protocol::{ErrorCode, WsServerMessage, WS_PROTOCOL_VERSION};
+use mado_core::session_socket::SessionSocketEvent;
+use mado_core::types::Session;
+use mado_daemon::state::DaemonState;
+
+use common::{session, test_state, wait_for_socket};
+
+/// Create test state holding one restored session (no live process).
+fn create_test_state(tmp_dir: &TempDir) -> (Arc<Mutex<DaemonState>>, PathBuf) {
+    let mut state = DaemonState::default();
+    state.add_session(Session {
+        name: "WebSocket Session".to_string(),
+        ..session("ws-1")
+    });
+    test_state(tmp_dir, state)
+}
+
+/// Receive the next control message, failing on PTY frames or timeout.
+async fn next_message(socket: &mut mado_core::session_socket::SessionSocket) -> WsServerMessage {
+    match timeout(Duration::from_secs(5), socket.next_event()).await {
+        Ok(Some(Ok(SessionSocketEvent::Message(message)))) => message,
+        other => panic!("Expected control message, got: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_session_websocket() {
+    let tmp_dir = TempDir::new().expect("Failed to create temp dir");
+    let socket_path = tmp_dir.path().join("test.sock");
+    let (daemon_state, state_path) = create_test_state(&tmp_dir);
+
+    let socket_path_clone = socket_path.clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        mado_daemon::server::start_server(
+            socket_path_clone,
+            state_path,
+            daemon_state,
+            async {
+                shutdown_rx.await.ok();
+            },
+        )
+        .await
+        .expect("Server failed to start");
+    });
+
+    assert!(
+        wait_for_socket(&socket_path, Duration::from_secs(5)).await,
+        "Socket did not appear in time"
+    );
+
+    let client = DaemonClient::new(&socket_path);
+
+    // Unknown sessions are rejected before the upgrade.
+    match client.session_socket("missing").await {
+        Err(ClientError::SessionNotFound(message)) => assert!(message.contains("missing")),
+        Err(e) => panic!("Expected SessionNotFound, got: {}", e),
+        Ok(_) => panic!("Expected SessionNotFound, got a socket"),
+    }
+
+    let mut socket = client.session_socket("ws-1").await.expect("Failed to open socket");
+    match next_message(&mut socket).await {
+        WsServerMessage::Connected { version, pty } => {
+            assert_eq!(version, WS_PROTOCOL_VERSION);
+            assert!(!pty, "restored session has no live process");
+        }
+        other => panic!("Expected Connected, got: {:?}", other),
+    }
+
+    // Errors are reported in-band and leave the connection open.
+    socket.resize(24, 80).await.expect("Failed to send resize");
+    match next_message(&mut socket).await {
+        WsServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::SessionNotFound),
+        other => panic!("Expected Error, got: {:?}", other),
+    }
+
+    socket.cancel().await.expect("Failed to send cancel");
+    assert!(matches!(
+        next_message(&mut socket).await,
+        WsServerMessage::Error { .. }
+    ));
+
+    socket.close().await.expect("Failed to close socket");
+
+    // Only queued messages can be removed from the queue.
+    assert!(matches!(
+        client.cancel_queued_message("ws-1", "not-queued").await,
+        Err(ClientError::NotFound(_))
+    ));
+
+    shutdown_tx.send(()).expect("Failed to send shutdown");
+    server_handle.await.expect("Server task panicked");
+}
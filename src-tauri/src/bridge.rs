@@ -1,4 +1,7 @@
-use mado_core::types::StreamEvent;
+use base64::Engine;
+use futures::StreamExt;
+use mado_core::client::DaemonClient;
+use mado_core::types::{ActivityEvent, CompareEvent, RunEvent, StreamEvent};
 use tauri::ipc::Channel;
 use tauri::State;
 
@@ -6,8 +9,9 @@ use crate::commands::DaemonState;
 
 /// Attach to a session's chat event stream (chat mode).
 ///
-/// Connects to the daemon's SSE endpoint for chat events and forwards
-/// structured StreamEvent JSON to the frontend via a Tauri Channel.
+/// Follows the daemon's chat event stream, resuming it if the connection
+/// drops, and forwards structured StreamEvent JSON to the frontend via a
+/// Tauri Channel.
 #[tauri::command]
 pub async fn attach_chat_session(
     state: State<'_, DaemonState>,
@@ -19,242 +23,179 @@ pub async fn attach_chat_session(
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    // Get the socket path for creating a direct SSE connection.
-    let socket_path = client.socket_path().to_path_buf();
+    // Clone the client for the long-running stream.
+    let client = client.clone();
     drop(guard); // Release the lock before long-running stream.
 
-    // Connect to the daemon's chat SSE endpoint.
-    stream_chat_events(&socket_path, &session_id, on_event).await
+    let mut events = client
+        .subscribe_events(&session_id)
+        .await
+        .map_err(|e| format!("Failed to attach to session: {}", e))?;
+    while let Some(event) = events.next().await {
+        if let Err(e) = on_event.send(event) {
+            tracing::warn!("Failed to send to channel: {}", e);
+            break;
+        }
+    }
+    tracing::info!("Chat stream ended for session {}", session_id);
+    Ok(())
 }
 
-/// Stream chat events from the daemon's SSE endpoint to a Tauri channel.
-async fn stream_chat_events(
-    socket_path: &std::path::Path,
-    session_id: &str,
-    on_event: Channel<StreamEvent>,
+/// Attach to the daemon-wide activity feed.
+///
+/// Forwards high-level events from every session (response finished,
+/// milestone saved, process exited, push done) so the shell can badge panes
+/// that are not currently attached.
+#[tauri::command]
+pub async fn attach_activity_feed(
+    state: State<'_, DaemonState>,
+    on_event: Channel<ActivityEvent>,
 ) -> Result<(), String> {
-    use http_body_util::BodyExt;
-    use hyper::body::Bytes;
-    use hyper::Request;
-    use hyper_util::rt::TokioIo;
-    use tokio::net::UnixStream;
-
-    let stream = UnixStream::connect(socket_path)
-        .await
-        .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
-
-    let io = TokioIo::new(stream);
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
-        .await
-        .map_err(|e| format!("HTTP handshake failed: {}", e))?;
+    // Clone the client for the long-running stream.
+    let client = client.clone();
+    drop(guard); // Release the lock before long-running stream.
 
-    // Spawn connection driver.
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            tracing::error!("SSE connection error: {}", e);
+    read_activity_feed(&client, |activity| match on_event.send(activity) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Failed to send to channel: {}", e);
+            false
         }
-    });
-
-    let req = Request::builder()
-        .uri(format!("/sessions/{}/stream", session_id))
-        .header("Host", "localhost")
-        .header("Accept", "text/event-stream")
-        .body(http_body_util::Full::new(Bytes::new()))
-        .map_err(|e| format!("Failed to build request: {}", e))?;
-
-    let resp = sender
-        .send_request(req)
-        .await
-        .map_err(|e| format!("SSE request failed: {}", e))?;
-
-    // Read the SSE stream frame by frame.
-    let mut body = resp.into_body();
-    let mut buffer = String::new();
-
-    loop {
-        match body.frame().await {
-            Some(Ok(frame)) => {
-                if let Ok(data) = frame.into_data() {
-                    let chunk = String::from_utf8_lossy(&data);
-                    buffer.push_str(&chunk);
-
-                    // Parse SSE events from buffer.
-                    while let Some(event_end) = buffer.find("\n\n") {
-                        let event_text = buffer[..event_end].to_string();
-                        buffer = buffer[event_end + 2..].to_string();
+    })
+    .await
+}
 
-                        // Parse event type and data.
-                        let mut event_type = String::new();
-                        let mut event_data = String::new();
+/// Follow a project command run.
+///
+/// Forwards the run's output, replayed from the start, and then the finished
+/// run to the frontend via a Tauri Channel; returns once it has finished.
+#[tauri::command]
+pub async fn follow_command_run(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    run_id: String,
+    on_event: Channel<RunEvent>,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-                        for line in event_text.lines() {
-                            if let Some(val) = line.strip_prefix("event:") {
-                                event_type = val.trim().to_string();
-                            } else if let Some(val) = line.strip_prefix("data:") {
-                                event_data = val.trim().to_string();
-                            }
-                        }
+    // Clone the client for the long-running stream.
+    let client = client.clone();
+    drop(guard); // Release the lock before long-running stream.
 
-                        match event_type.as_str() {
-                            "message" => {
-                                // Parse and forward the StreamEvent.
-                                if let Ok(stream_event) =
-                                    serde_json::from_str::<StreamEvent>(&event_data)
-                                {
-                                    if let Err(e) = on_event.send(stream_event) {
-                                        tracing::warn!("Failed to send to channel: {}", e);
-                                        return Ok(());
-                                    }
-                                }
-                            }
-                            "connected" => {
-                                tracing::debug!(
-                                    "Chat SSE stream connected for session {}",
-                                    session_id
-                                );
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-            Some(Err(e)) => {
-                tracing::warn!("SSE stream error: {}", e);
-                break;
-            }
-            None => {
-                tracing::info!("SSE stream ended for session {}", session_id);
-                break;
-            }
+    let mut events = client
+        .follow_run(&session_id, &run_id)
+        .await
+        .map_err(|e| format!("Failed to follow run: {}", e))?;
+    while let Some(event) = events.next().await {
+        let complete = matches!(event, RunEvent::Complete { .. });
+        if let Err(e) = on_event.send(event) {
+            tracing::warn!("Failed to send to channel: {}", e);
+            break;
+        }
+        if complete {
+            break;
         }
     }
-
     Ok(())
 }
 
-/// Attach to a session's PTY output stream.
+/// Follow a comparison of models' answers.
 ///
-/// Connects to the daemon's SSE endpoint for the given session and forwards
-/// output chunks to the frontend via a Tauri Channel. The channel receives
-/// base64-encoded output data that the frontend decodes and writes to xterm.js.
+/// Forwards each variant's chat events, replayed from the start while it
+/// runs, and then the finished comparison to the frontend via a Tauri
+/// Channel; returns once every variant has answered.
 #[tauri::command]
-pub async fn attach_session(
+pub async fn follow_comparison(
     state: State<'_, DaemonState>,
     session_id: String,
-    on_output: Channel<String>,
+    comparison_id: String,
+    on_event: Channel<CompareEvent>,
 ) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    // Get the socket path for creating a direct SSE connection.
-    let socket_path = client.socket_path().to_path_buf();
+    // Clone the client for the long-running stream.
+    let client = client.clone();
     drop(guard); // Release the lock before long-running stream.
 
-    // Connect to the daemon's SSE endpoint for this session.
-    stream_session_output(&socket_path, &session_id, on_output).await
+    let mut events = client
+        .follow_comparison(&session_id, &comparison_id)
+        .await
+        .map_err(|e| format!("Failed to follow comparison: {}", e))?;
+    while let Some(event) = events.next().await {
+        let complete = matches!(event, CompareEvent::Complete { .. });
+        if let Err(e) = on_event.send(event) {
+            tracing::warn!("Failed to send to channel: {}", e);
+            break;
+        }
+        if complete {
+            break;
+        }
+    }
+    Ok(())
 }
 
-/// Stream output from the daemon's SSE endpoint to a Tauri channel.
-async fn stream_session_output(
-    socket_path: &std::path::Path,
-    session_id: &str,
-    on_output: Channel<String>,
+/// Follow the daemon's activity feed, passing each activity to `on_activity`
+/// until it returns false or the feed ends.
+pub async fn read_activity_feed(
+    client: &DaemonClient,
+    mut on_activity: impl FnMut(ActivityEvent) -> bool,
 ) -> Result<(), String> {
-    use http_body_util::BodyExt;
-    use hyper::body::Bytes;
-    use hyper::Request;
-    use hyper_util::rt::TokioIo;
-    use tokio::net::UnixStream;
-
-    let stream = UnixStream::connect(socket_path)
+    let mut activity = client
+        .subscribe_activity()
         .await
-        .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
-
-    let io = TokioIo::new(stream);
-
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
-        .await
-        .map_err(|e| format!("HTTP handshake failed: {}", e))?;
-
-    // Spawn connection driver.
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            tracing::error!("SSE connection error: {}", e);
+        .map_err(|e| format!("Failed to follow activity feed: {}", e))?;
+    while let Some(event) = activity.next().await {
+        if !on_activity(event) {
+            return Ok(());
         }
-    });
-
-    let req = Request::builder()
-        .uri(format!("/sessions/{}/output", session_id))
-        .header("Host", "localhost")
-        .header("Accept", "text/event-stream")
-        .body(http_body_util::Full::new(Bytes::new()))
-        .map_err(|e| format!("Failed to build request: {}", e))?;
-
-    let resp = sender
-        .send_request(req)
-        .await
-        .map_err(|e| format!("SSE request failed: {}", e))?;
-
-    // Read the SSE stream frame by frame.
-    let mut body = resp.into_body();
-    let mut buffer = String::new();
-
-    loop {
-        match body.frame().await {
-            Some(Ok(frame)) => {
-                if let Ok(data) = frame.into_data() {
-                    let chunk = String::from_utf8_lossy(&data);
-                    buffer.push_str(&chunk);
-
-                    // Parse SSE events from buffer.
-                    while let Some(event_end) = buffer.find("\n\n") {
-                        let event_text = buffer[..event_end].to_string();
-                        buffer = buffer[event_end + 2..].to_string();
+    }
+    tracing::info!("Activity feed ended");
+    Ok(())
+}
 
-                        // Parse event type and data.
-                        let mut event_type = String::new();
-                        let mut event_data = String::new();
+/// Attach to a session's PTY output stream.
+///
+/// Connects to the daemon's v1 PTY stream (SSE encoding) for the given session
+/// and forwards output chunks to the frontend via a Tauri Channel. The channel
+/// receives base64-encoded output data that the frontend decodes and writes to
+/// xterm.js; scrollback is replayed first, so reattaching restores the screen.
+#[tauri::command]
+pub async fn attach_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    on_output: Channel<String>,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-                        for line in event_text.lines() {
-                            if let Some(val) = line.strip_prefix("event:") {
-                                event_type = val.trim().to_string();
-                            } else if let Some(val) = line.strip_prefix("data:") {
-                                event_data = val.trim().to_string();
-                            }
-                        }
+    // Clone the client for the long-running stream.
+    let client = client.clone();
+    drop(guard); // Release the lock before long-running stream.
 
-                        match event_type.as_str() {
-                            "output" => {
-                                // Forward base64-encoded output to frontend.
-                                if let Err(e) = on_output.send(event_data) {
-                                    tracing::warn!("Failed to send to channel: {}", e);
-                                    return Ok(());
-                                }
-                            }
-                            "started" => {
-                                tracing::debug!("SSE stream started for session {}", session_id);
-                            }
-                            "error" => {
-                                return Err(format!("Session error: {}", event_data));
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-            Some(Err(e)) => {
-                tracing::warn!("SSE stream error: {}", e);
-                break;
-            }
-            None => {
-                tracing::info!("SSE stream ended for session {}", session_id);
-                break;
-            }
+    let mut output = client
+        .subscribe_output(&session_id)
+        .await
+        .map_err(|e| format!("Session error: {}", e))?;
+    while let Some(chunk) = output.next().await {
+        // Forward base64-encoded output to frontend.
+        if let Err(e) = on_output.send(base64::engine::general_purpose::STANDARD.encode(chunk)) {
+            tracing::warn!("Failed to send to channel: {}", e);
+            break;
         }
     }
-
+    tracing::info!("PTY stream ended for session {}", session_id);
     Ok(())
 }
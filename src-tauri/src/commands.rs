@@ -1,12 +1,21 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use tauri::State;
+use tauri_plugin_dialog::DialogExt;
 use tokio::sync::RwLock;
 
 use serde::Serialize;
 
-use mado_core::client::DaemonClient;
-use mado_core::types::{DaemonStatus, Message, Session};
+use mado_core::client::{ClientError, DaemonClient};
+use mado_core::protocol::{
+    ApplyReviewBody, CloneSessionBody, CompactBody, CompareBody, CreatePullRequestBody, CreateSessionBody, MergeBranchBody, RenderPromptBody, RunCommandBody,
+};
+use mado_core::types::{
+    Attachment, AuditEntry, AuditFormat, CommandRun, Compaction, Comparison, DaemonStatus, EffectiveConfig, ExportFormat, FileEntry, MemoryFile, MemorySuggestion, Message, PermissionMode, PullRequest, RenderedPrompt, RetentionCandidate,
+    SandboxSettings, SavedPrompt, Schedule, ScheduleRun, SearchResults, Session, SessionSettings, SessionTemplate, SessionUpdate, SessionUsage,
+    UsageReport,
+};
 
 /// Shared daemon state managed by Tauri.
 /// Uses RwLock instead of Mutex to allow concurrent read access.
@@ -36,27 +45,28 @@ pub async fn health_check(
     client.health().await.map_err(|e| e.to_string())
 }
 
-/// Daemon status command -- returns "connected" or "disconnected".
+/// Daemon status command -- returns "connected", "unresponsive" (running but
+/// not answering in time) or "disconnected".
 #[tauri::command]
 pub async fn daemon_status(
     state: State<'_, DaemonState>,
 ) -> Result<String, String> {
     let guard = state.client.read().await;
-    match guard.as_ref() {
-        Some(client) => {
-            if client.is_alive().await {
-                Ok("connected".to_string())
-            } else {
-                Ok("disconnected".to_string())
-            }
-        }
-        None => Ok("disconnected".to_string()),
-    }
+    let status = match guard.as_ref() {
+        Some(client) => match client.ping().await {
+            Ok(()) => "connected",
+            Err(ClientError::Timeout(_) | ClientError::Unresponsive) => "unresponsive",
+            Err(_) => "disconnected",
+        },
+        None => "disconnected",
+    };
+    Ok(status.to_string())
 }
 
 /// Reconnect to the daemon. Will attempt to start daemon if not running.
 #[tauri::command]
 pub async fn reconnect(
+    app: tauri::AppHandle,
     state: State<'_, DaemonState>,
 ) -> Result<String, String> {
     let mut guard = state.client.write().await;
@@ -64,13 +74,67 @@ pub async fn reconnect(
     // Use ensure_daemon which will start the daemon if needed.
     match crate::lifecycle::ensure_daemon().await {
         Ok(client) => {
+            crate::lifecycle::emit_crash_reports(&app, &client).await;
             *guard = Some(client);
+            crate::windows::emit_to_all(&app, "daemon-connected", "connected");
             Ok("connected".to_string())
         }
         Err(e) => Err(format!("Failed to reconnect: {}", e)),
     }
 }
 
+/// Stop the running daemon and start the bundled one in its place.
+/// Offered when the running daemon speaks a different protocol version.
+#[tauri::command]
+pub async fn restart_daemon(
+    app: tauri::AppHandle,
+    state: State<'_, DaemonState>,
+) -> Result<String, String> {
+    let mut guard = state.client.write().await;
+    *guard = None;
+
+    let client = crate::lifecycle::restart_daemon().await?;
+    crate::lifecycle::emit_crash_reports(&app, &client).await;
+    *guard = Some(client);
+    crate::windows::emit_to_all(&app, "daemon-connected", "connected");
+    Ok("connected".to_string())
+}
+
+/// Recent daemon log lines, for attaching to bug reports.
+#[tauri::command]
+pub async fn get_daemon_logs(
+    state: State<'_, DaemonState>,
+    lines: Option<usize>,
+    level: Option<String>,
+) -> Result<Vec<String>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .daemon_logs(lines, level.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Delete a crash report the user has seen.
+#[tauri::command]
+pub async fn dismiss_crash_report(
+    state: State<'_, DaemonState>,
+    report_id: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .delete_crash_report(&report_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 /// List all sessions.
 #[tauri::command]
 pub async fn list_sessions(
@@ -84,8 +148,10 @@ pub async fn list_sessions(
     client.list_sessions().await.map_err(|e| e.to_string())
 }
 
-/// Create a new session.
+/// Create a new session, running `command` with `args` instead of the
+/// Claude CLI if given, with `env` set on its processes.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_session(
     state: State<'_, DaemonState>,
     name: String,
@@ -93,6 +159,44 @@ pub async fn create_session(
     rows: u16,
     cols: u16,
     cwd: Option<String>,
+    system_prompt: Option<String>,
+    command: Option<String>,
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
+    permission_mode: Option<PermissionMode>,
+    sandbox: Option<SandboxSettings>,
+) -> Result<Session, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .create_session_with(&CreateSessionBody {
+            name,
+            model: Some(model),
+            rows: Some(rows),
+            cols: Some(cols),
+            cwd,
+            system_prompt,
+            permission_mode,
+            sandbox,
+            command,
+            args: args.unwrap_or_default(),
+            env: env.unwrap_or_default(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Change a session's name, model, working directory, system prompt,
+/// permission mode or metadata.
+#[tauri::command]
+pub async fn update_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    update: SessionUpdate,
 ) -> Result<Session, String> {
     let guard = state.client.read().await;
     let client = guard
@@ -100,7 +204,59 @@ pub async fn create_session(
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .create_session(&name, &model, rows, cols, cwd.as_deref())
+        .update_session(&session_id, &update)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get a session's settings.
+#[tauri::command]
+pub async fn get_session_settings(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<SessionSettings, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .session_settings(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The settings that apply to a session and where each comes from.
+#[tauri::command]
+pub async fn get_effective_config(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<EffectiveConfig, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .effective_config(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Replace a session's settings.
+#[tauri::command]
+pub async fn update_session_settings(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    settings: SessionSettings,
+) -> Result<SessionSettings, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .update_session_settings(&session_id, &settings)
         .await
         .map_err(|e| e.to_string())
 }
@@ -122,247 +278,1406 @@ pub async fn destroy_session(
         .map_err(|e| e.to_string())
 }
 
-/// Write input to a session's PTY.
+/// Respawn the process of a session left without one by a daemon restart.
 #[tauri::command]
-pub async fn write_input(
+pub async fn revive_session(
     state: State<'_, DaemonState>,
     session_id: String,
-    data: Vec<u8>,
-) -> Result<(), String> {
+    rows: u16,
+    cols: u16,
+) -> Result<Session, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .write_input(&session_id, &data)
+        .revive_session(&session_id, rows, cols)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Resize a session's PTY.
+/// Kill a session's process, if still running, and spawn a fresh one.
 #[tauri::command]
-pub async fn resize_session(
+pub async fn restart_session(
     state: State<'_, DaemonState>,
     session_id: String,
     rows: u16,
     cols: u16,
-) -> Result<(), String> {
+) -> Result<Session, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .resize_session(&session_id, rows, cols)
+        .restart_session(&session_id, rows, cols)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Simple sync ping command to test IPC.
+/// Download a session's terminal recording (asciicast v2).
 #[tauri::command]
-pub fn ping() -> String {
-    tracing::info!("[ping] Called!");
-    "pong".to_string()
-}
+pub async fn get_recording(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-/// Available model info.
-#[derive(Debug, Clone, Serialize)]
-pub struct ModelInfo {
-    pub id: String,
-    pub name: String,
-    pub description: String,
+    client
+        .get_recording(&session_id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Check if an API key is configured.
+/// Start a new session continuing a conversation from one of its messages
+/// (its latest if `at_message_id` is omitted).
 #[tauri::command]
-pub fn has_api_key() -> bool {
-    let result = mado_daemon::keystore::KeyStore::has_api_key();
-    tracing::info!("[has_api_key] Result: {}", result);
-    result
-}
+pub async fn fork_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    at_message_id: Option<String>,
+) -> Result<Session, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-/// Set the Anthropic API key.
-#[tauri::command]
-pub fn set_api_key(key: String) -> Result<(), String> {
-    mado_daemon::keystore::KeyStore::set_api_key(&key).map_err(|e| e.to_string())
+    client
+        .fork_session(&session_id, at_message_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Delete the stored API key.
+/// Clone a session's workspace into `target_dir` and start a session there
+/// with the same settings, optionally carrying on the conversation.
 #[tauri::command]
-pub fn delete_api_key() -> Result<(), String> {
-    mado_daemon::keystore::KeyStore::delete_api_key().map_err(|e| e.to_string())
+pub async fn clone_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    body: CloneSessionBody,
+) -> Result<Session, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .clone_session(&session_id, &body)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Delete all Mado data: config directory (~/.mado/) and stored API key.
-/// Returns the app to a fresh first-launch state.
+/// Archive a session's conversation and milestones, then remove it.
+/// Returns the archive's path.
 #[tauri::command]
-pub fn delete_all_data() -> Result<(), String> {
-    // Delete API key from keychain (ignore errors if none stored).
-    let _ = mado_daemon::keystore::KeyStore::delete_api_key();
-
-    // Remove the ~/.mado/ directory (config, conversations, logs, state).
-    let config_dir = mado_daemon::config::config_dir();
-    if config_dir.exists() {
-        std::fs::remove_dir_all(&config_dir)
-            .map_err(|e| format!("Failed to delete {}: {}", config_dir.display(), e))?;
-        tracing::info!("Deleted data directory: {}", config_dir.display());
-    }
+pub async fn archive_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    Ok(())
+    client
+        .archive_session(&session_id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-// ── Config commands ──
-
-/// Get the current Mado configuration.
+/// Dead sessions the retention policy would remove on its next run.
 #[tauri::command]
-pub fn get_config() -> Result<mado_daemon::config::MadoConfig, String> {
-    mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())
+pub async fn retention_plan(
+    state: State<'_, DaemonState>,
+) -> Result<Vec<RetentionCandidate>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.retention_plan().await.map_err(|e| e.to_string())
 }
 
-/// Update Mado configuration.
+/// Daemon-wide token usage and cost per day and project, from `since` on.
 #[tauri::command]
-pub fn update_config(config: mado_daemon::config::MadoConfig) -> Result<(), String> {
-    config.save().map_err(|e| e.to_string())
+pub async fn get_usage_report(
+    state: State<'_, DaemonState>,
+    since: Option<chrono::NaiveDate>,
+) -> Result<UsageReport, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.usage_report(since).await.map_err(|e| e.to_string())
 }
 
-/// Mark setup as complete in config.
+/// List scheduled prompts.
 #[tauri::command]
-pub fn complete_setup() -> Result<(), String> {
-    let mut config = mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())?;
-    config.setup_complete = true;
-    config.save().map_err(|e| e.to_string())
+pub async fn list_schedules(state: State<'_, DaemonState>) -> Result<Vec<Schedule>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.list_schedules().await.map_err(|e| e.to_string())
 }
 
-/// Check if setup has been completed.
+/// Run a prompt in `cwd` whenever the cron expression matches.
 #[tauri::command]
-pub fn is_setup_complete() -> Result<bool, String> {
-    let config = mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())?;
-    Ok(config.setup_complete)
+pub async fn create_schedule(
+    state: State<'_, DaemonState>,
+    cron: String,
+    prompt: String,
+    cwd: String,
+    model: Option<String>,
+    permission_mode: Option<PermissionMode>,
+) -> Result<Schedule, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .create_schedule(
+            &cron,
+            &prompt,
+            &cwd,
+            model.as_deref(),
+            permission_mode.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Check if Claude CLI is authenticated (subscription login).
-/// Returns true if ~/.claude/.credentials.json exists.
+/// Remove a schedule and its run history.
 #[tauri::command]
-pub fn check_cli_auth() -> bool {
-    let home = dirs::home_dir().expect("Could not determine home directory");
-    let credentials_path = home.join(".claude").join(".credentials.json");
-    let exists = credentials_path.exists();
-    tracing::info!("[check_cli_auth] Credentials at {:?}: {}", credentials_path, exists);
-    exists
+pub async fn delete_schedule(state: State<'_, DaemonState>, id: String) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.delete_schedule(&id).await.map_err(|e| e.to_string())
 }
 
-/// Get the current user's display name from the system.
+/// Past runs of a schedule, newest first.
 #[tauri::command]
-pub fn get_user_display_name() -> String {
-    // Try to get the full name from the system
-    #[cfg(target_os = "macos")]
-    {
-        use std::process::Command;
-        // On macOS, use `id -F` to get full name
-        if let Ok(output) = Command::new("id").arg("-F").output() {
-            if output.status.success() {
-                let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                if !name.is_empty() {
-                    return name;
-                }
-            }
-        }
-    }
+pub async fn get_schedule_runs(
+    state: State<'_, DaemonState>,
+    id: String,
+) -> Result<Vec<ScheduleRun>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    // Fallback to username
-    std::env::var("USER")
-        .or_else(|_| std::env::var("USERNAME"))
-        .unwrap_or_else(|_| "User".to_string())
+    client.schedule_runs(&id).await.map_err(|e| e.to_string())
 }
 
-/// Check if Claude CLI is installed.
-/// Returns the path if found, None if not installed.
+/// List session templates.
 #[tauri::command]
-pub fn check_cli_installed() -> Option<String> {
-    use std::process::Command;
+pub async fn list_templates(state: State<'_, DaemonState>) -> Result<Vec<SessionTemplate>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    // Try `which claude` on Unix
-    let output = Command::new("which")
-        .arg("claude")
-        .output();
+    client.list_templates().await.map_err(|e| e.to_string())
+}
 
-    match output {
-        Ok(result) if result.status.success() => {
-            let path = String::from_utf8_lossy(&result.stdout).trim().to_string();
-            tracing::info!("[check_cli_installed] Found at: {}", path);
-            Some(path)
-        }
-        _ => {
-            tracing::info!("[check_cli_installed] Claude CLI not found");
-            None
-        }
+/// Add a session template, replacing any of the same name.
+#[tauri::command]
+pub async fn save_template(
+    state: State<'_, DaemonState>,
+    template: SessionTemplate,
+) -> Result<SessionTemplate, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.save_template(&template).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_template(state: State<'_, DaemonState>, name: String) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.delete_template(&name).await.map_err(|e| e.to_string())
+}
+
+/// The prompt library, for the command palette; with `session_id`,
+/// including its project's prompts.
+#[tauri::command]
+pub async fn list_prompts(
+    state: State<'_, DaemonState>,
+    session_id: Option<String>,
+) -> Result<Vec<SavedPrompt>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.list_prompts(session_id.as_deref()).await.map_err(|e| e.to_string())
+}
+
+/// Add a prompt to the library, replacing any of the same name.
+#[tauri::command]
+pub async fn save_prompt(state: State<'_, DaemonState>, prompt: SavedPrompt) -> Result<SavedPrompt, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.save_prompt(&prompt).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_prompt(state: State<'_, DaemonState>, name: String) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.delete_prompt(&name).await.map_err(|e| e.to_string())
+}
+
+/// A saved prompt with its variables filled in, ready to insert.
+#[tauri::command]
+pub async fn render_prompt(
+    state: State<'_, DaemonState>,
+    name: String,
+    session_id: Option<String>,
+    variables: HashMap<String, String>,
+) -> Result<RenderedPrompt, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .render_prompt(&name, &RenderPromptBody { session_id, variables })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The project memory file (CLAUDE.md) of a session's workspace.
+#[tauri::command]
+pub async fn read_memory(state: State<'_, DaemonState>, session_id: String) -> Result<MemoryFile, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.memory(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Replace the project memory file; with an ETag, only if it is unchanged.
+#[tauri::command]
+pub async fn write_memory(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    content: String,
+    etag: Option<String>,
+) -> Result<MemoryFile, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .write_memory(&session_id, &content, etag.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Add an entry to the project memory file ("remember this").
+#[tauri::command]
+pub async fn append_memory(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    entry: String,
+) -> Result<MemoryFile, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.append_memory(&session_id, &entry).await.map_err(|e| e.to_string())
+}
+
+/// Pending memory suggestions of a session.
+#[tauri::command]
+pub async fn list_memory_suggestions(state: State<'_, DaemonState>, session_id: String) -> Result<Vec<MemorySuggestion>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.memory_suggestions(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Ask the model for memory entries from the recent conversation.
+#[tauri::command]
+pub async fn suggest_memory(state: State<'_, DaemonState>, session_id: String) -> Result<Vec<MemorySuggestion>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.suggest_memory(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Add a suggested entry to the project memory file.
+#[tauri::command]
+pub async fn accept_memory_suggestion(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    suggestion_id: String,
+) -> Result<MemoryFile, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .accept_memory_suggestion(&session_id, &suggestion_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Drop a suggested memory entry.
+#[tauri::command]
+pub async fn dismiss_memory_suggestion(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    suggestion_id: String,
+) -> Result<Vec<MemorySuggestion>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .dismiss_memory_suggestion(&session_id, &suggestion_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Let sessions run in a directory and below.
+#[tauri::command]
+pub async fn trust_workspace(state: State<'_, DaemonState>, path: String) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.trust_workspace(&path).await.map_err(|e| e.to_string())
+}
+
+/// Create a session named `name` from a template.
+#[tauri::command]
+pub async fn create_session_from_template(
+    state: State<'_, DaemonState>,
+    template: String,
+    name: String,
+    rows: u16,
+    cols: u16,
+) -> Result<Session, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .create_session_with(&CreateSessionBody {
+            name,
+            rows: Some(rows),
+            cols: Some(cols),
+            template: Some(template),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Write input to a session's PTY.
+///
+/// Invoked with a raw binary payload (no JSON encoding); the target session
+/// is passed in the `x-session-id` header, and `x-paste: true` writes it as
+/// a bracketed paste.
+#[tauri::command]
+pub async fn write_input(
+    state: State<'_, DaemonState>,
+    request: tauri::ipc::Request<'_>,
+) -> Result<(), String> {
+    let tauri::ipc::InvokeBody::Raw(data) = request.body() else {
+        return Err("write_input expects a raw binary payload".to_string());
+    };
+    let session_id = request
+        .headers()
+        .get("x-session-id")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing x-session-id header".to_string())?;
+    let paste = request
+        .headers()
+        .get("x-paste")
+        .is_some_and(|v| v.as_bytes() == b"true");
+
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    let result = if paste {
+        client.paste_input(session_id, data).await
+    } else {
+        client.write_input(session_id, data).await
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// Resize a session's PTY.
+#[tauri::command]
+pub async fn resize_session(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    rows: u16,
+    cols: u16,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .resize_session(&session_id, rows, cols)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Simple sync ping command to test IPC.
+#[tauri::command]
+pub fn ping() -> String {
+    tracing::info!("[ping] Called!");
+    "pong".to_string()
+}
+
+/// Available model info.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+}
+
+/// Check if an API key is configured.
+#[tauri::command]
+pub fn has_api_key() -> bool {
+    let result = mado_daemon::keystore::KeyStore::has_api_key();
+    tracing::info!("[has_api_key] Result: {}", result);
+    result
+}
+
+/// Set a provider's API key; the Anthropic key when no provider is given.
+#[tauri::command]
+pub fn set_api_key(key: String, provider: Option<String>) -> Result<(), String> {
+    let provider = provider.as_deref().unwrap_or(mado_daemon::keystore::ANTHROPIC);
+    mado_daemon::keystore::KeyStore::set_provider_key(provider, &key).map_err(|e| e.to_string())
+}
+
+/// Delete a provider's stored API key; the Anthropic key when no provider is given.
+#[tauri::command]
+pub fn delete_api_key(provider: Option<String>) -> Result<(), String> {
+    let provider = provider.as_deref().unwrap_or(mado_daemon::keystore::ANTHROPIC);
+    mado_daemon::keystore::KeyStore::delete_provider_key(provider).map_err(|e| e.to_string())
+}
+
+/// Store the API token for the forge at `host`, e.g. `github.com`.
+#[tauri::command]
+pub fn set_forge_token(host: String, token: String) -> Result<(), String> {
+    mado_daemon::keystore::KeyStore::set_forge_token(&host, &token).map_err(|e| e.to_string())
+}
+
+/// Delete the stored token for the forge at `host`.
+#[tauri::command]
+pub fn delete_forge_token(host: String) -> Result<(), String> {
+    mado_daemon::keystore::KeyStore::delete_forge_token(&host).map_err(|e| e.to_string())
+}
+
+/// Providers with an API key in the keychain.
+#[tauri::command]
+pub fn list_api_keys() -> Vec<String> {
+    mado_daemon::keystore::KeyStore::list_provider_keys()
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Delete all Mado data: daemon sessions and data, config directory
+/// (~/.mado/) and stored API key. Returns the app to a fresh first-launch
+/// state.
+#[tauri::command]
+pub async fn delete_all_data(
+    state: State<'_, DaemonState>,
+) -> Result<(), String> {
+    // Have the daemon stop its sessions and forget them first, or it would
+    // write its state back after the directory is gone.
+    if let Some(client) = state.client.read().await.as_ref() {
+        let report = client
+            .purge(mado_core::types::PurgeScope::Everything)
+            .await
+            .map_err(|e| format!("Failed to purge daemon data: {}", e))?;
+        tracing::info!("Purged {} session(s) from the daemon", report.sessions);
+    }
+
+    // Delete API keys from keychain (ignore errors if none stored).
+    for provider in mado_daemon::keystore::PROVIDERS {
+        let _ = mado_daemon::keystore::KeyStore::delete_provider_key(provider.name);
+    }
+
+    // Remove the ~/.mado/ directory (config, conversations, logs, state).
+    let config_dir = mado_daemon::config::config_dir();
+    if config_dir.exists() {
+        std::fs::remove_dir_all(&config_dir)
+            .map_err(|e| format!("Failed to delete {}: {}", config_dir.display(), e))?;
+        tracing::info!("Deleted data directory: {}", config_dir.display());
+    }
+
+    Ok(())
+}
+
+// ── Config commands ──
+
+/// Get the current Mado configuration.
+#[tauri::command]
+pub fn get_config() -> Result<mado_daemon::config::MadoConfig, String> {
+    mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())
+}
+
+/// Why `update_config` refused a config; `fields` lists the invalid settings.
+#[derive(Debug, Serialize)]
+pub struct ConfigUpdateError {
+    pub message: String,
+    pub fields: Vec<mado_daemon::config::FieldError>,
+}
+
+impl From<mado_daemon::config::ConfigError> for ConfigUpdateError {
+    fn from(error: mado_daemon::config::ConfigError) -> Self {
+        let message = error.to_string();
+        let fields = match error {
+            mado_daemon::config::ConfigError::Invalid(fields) => fields,
+            _ => Vec::new(),
+        };
+        Self { message, fields }
     }
 }
 
-// ── Versioning commands ──
-
-/// Save a milestone for a session.
+/// Validate and save the Mado configuration. The daemon picks up the change
+/// and tells every window.
+#[tauri::command]
+pub fn update_config(config: mado_daemon::config::MadoConfig) -> Result<(), ConfigUpdateError> {
+    config.validate()?;
+    config.save()?;
+    Ok(())
+}
+
+/// Mark setup as complete in config.
+#[tauri::command]
+pub fn complete_setup() -> Result<(), String> {
+    let mut config = mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())?;
+    config.setup_complete = true;
+    config.save().map_err(|e| e.to_string())
+}
+
+/// Check if setup has been completed.
+#[tauri::command]
+pub fn is_setup_complete() -> Result<bool, String> {
+    let config = mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())?;
+    Ok(config.setup_complete)
+}
+
+/// Check if Claude CLI is authenticated (subscription login).
+/// Returns true if ~/.claude/.credentials.json exists.
+#[tauri::command]
+pub fn check_cli_auth() -> bool {
+    let home = dirs::home_dir().expect("Could not determine home directory");
+    let credentials_path = home.join(".claude").join(".credentials.json");
+    let exists = credentials_path.exists();
+    tracing::info!("[check_cli_auth] Credentials at {:?}: {}", credentials_path, exists);
+    exists
+}
+
+/// The Claude CLI as the daemon will spawn it: path, version, login and
+/// whether chat mode works.
+#[tauri::command]
+pub async fn cli_info(state: State<'_, DaemonState>) -> Result<mado_core::types::CliInfo, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.cli_info().await.map_err(|e| e.to_string())
+}
+
+/// Get the current user's display name from the system.
+#[tauri::command]
+pub fn get_user_display_name() -> String {
+    // Try to get the full name from the system
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::Command;
+        // On macOS, use `id -F` to get full name
+        if let Ok(output) = Command::new("id").arg("-F").output() {
+            if output.status.success() {
+                let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if !name.is_empty() {
+                    return name;
+                }
+            }
+        }
+    }
+
+    // Fallback to username
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "User".to_string())
+}
+
+/// Check if Claude CLI is installed, as the daemon resolves it from
+/// `claude_path` in config.json or the system.
+/// Returns the path if found, None if not installed, and an error if
+/// `claude_path` or `default_flags` are invalid.
+#[tauri::command]
+pub fn check_cli_installed() -> Result<Option<String>, String> {
+    let config = mado_daemon::config::MadoConfig::load().map_err(|e| e.to_string())?;
+    let path = config.claude_path.as_deref().map(std::path::Path::new);
+    match mado_daemon::claude_cli::check_config(path, &config.default_flags) {
+        Ok(Some(path)) => {
+            tracing::info!("[check_cli_installed] Found at: {}", path.display());
+            Ok(Some(path.display().to_string()))
+        }
+        Ok(None) => {
+            tracing::info!("[check_cli_installed] Claude CLI not found");
+            Ok(None)
+        }
+        Err(e) => {
+            tracing::warn!("[check_cli_installed] {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+// ── Versioning commands ──
+
+/// Save a milestone for a session.
+#[tauri::command]
+pub async fn save_milestone(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    message: String,
+) -> Result<mado_core::types::Milestone, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .save_milestone(&session_id, &message)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List milestones for a session.
+#[tauri::command]
+pub async fn list_milestones(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<mado_core::types::Milestone>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .list_milestones(&session_id, limit.unwrap_or(20))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Diff between two milestones.
+#[tauri::command]
+pub async fn diff_milestones(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    from_oid: String,
+    to_oid: String,
+) -> Result<mado_core::types::DiffSummary, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .diff_milestones(&session_id, &from_oid, &to_oid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Restore to a milestone.
+#[tauri::command]
+pub async fn restore_milestone(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    oid: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .restore_milestone(&session_id, &oid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Save a milestone and a copy of the conversation together.
+#[tauri::command]
+pub async fn save_snapshot(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    message: String,
+) -> Result<mado_core::types::Snapshot, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .save_snapshot(&session_id, &message)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List a session's snapshots, newest first.
+#[tauri::command]
+pub async fn list_snapshots(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<Vec<mado_core::types::Snapshot>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .list_snapshots(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Restore a snapshot: the workspace files and the conversation.
+#[tauri::command]
+pub async fn restore_snapshot(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    snapshot_id: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .restore_snapshot(&session_id, &snapshot_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Files changed since a turn started, with their diffs.
+#[tauri::command]
+pub async fn review_changes(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    since_message_id: Option<String>,
+) -> Result<mado_core::types::Review, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .review(&session_id, since_message_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Keep or revert the files of a review.
+#[tauri::command]
+pub async fn apply_review(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    body: ApplyReviewBody,
+) -> Result<mado_core::types::Review, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .apply_review(&session_id, &body)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Undo the file changes of one turn, keeping later ones. Returns the files
+/// touched.
+#[tauri::command]
+pub async fn revert_turn_changes(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    message_id: String,
+) -> Result<Vec<String>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .revert_turn_changes(&session_id, &message_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The commands in a session's `.mado.toml`, by name.
+#[tauri::command]
+pub async fn project_commands(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<BTreeMap<String, String>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.project_commands(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Start one of the project's commands, optionally sending a failure back to
+/// the conversation. Follow it with `follow_command_run`.
+#[tauri::command]
+pub async fn run_project_command(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    name: String,
+    feed_back: Option<bool>,
+) -> Result<CommandRun, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .run_command(
+            &session_id,
+            &RunCommandBody {
+                name,
+                feed_back: feed_back.unwrap_or(false),
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A session's command runs, newest first.
+#[tauri::command]
+pub async fn list_command_runs(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<Vec<CommandRun>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.list_runs(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Ask two or three models the same question side by side; follow the
+/// answers with `follow_comparison`.
+#[tauri::command]
+pub async fn compare_models(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    content: String,
+    models: Vec<String>,
+) -> Result<Comparison, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .compare(&session_id, &CompareBody { content, models })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A session's comparisons, newest first.
+#[tauri::command]
+pub async fn list_comparisons(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<Vec<Comparison>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client.list_comparisons(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Keep one variant of a finished comparison in the conversation.
+#[tauri::command]
+pub async fn apply_comparison(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    comparison_id: String,
+    variant_id: String,
+) -> Result<Comparison, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .apply_comparison(&session_id, &comparison_id, &variant_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get current workspace changes for a session.
+#[tauri::command]
+pub async fn workspace_changes(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<mado_core::types::DiffSummary, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .workspace_changes(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ── Git staging commands ──
+
+/// Get git staging status (staged + unstaged files).
+#[tauri::command]
+pub async fn git_status(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<mado_core::types::GitStatus, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_status(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get unified diff content for a single file.
+#[tauri::command]
+pub async fn git_file_diff(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_path: String,
+    staged: bool,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_file_diff(&session_id, &file_path, staged)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stage a single file.
+#[tauri::command]
+pub async fn git_stage_file(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_path: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_stage_file(&session_id, &file_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Unstage a single file.
+#[tauri::command]
+pub async fn git_unstage_file(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_path: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_unstage_file(&session_id, &file_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stage multiple files in a single batch operation.
+#[tauri::command]
+pub async fn git_stage_files(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_paths: Vec<String>,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_stage_files(&session_id, &file_paths)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Unstage multiple files in a single batch operation.
+#[tauri::command]
+pub async fn git_unstage_files(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_paths: Vec<String>,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_unstage_files(&session_id, &file_paths)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stage a single hunk from a file.
+#[tauri::command]
+pub async fn git_stage_hunk(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    file_path: String,
+    hunk_index: usize,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_stage_hunk(&session_id, &file_path, hunk_index)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Commit staged files with a message.
+#[tauri::command]
+pub async fn git_commit(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    message: String,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_commit(&session_id, &message)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get git commit log for a session's workspace.
+#[tauri::command]
+pub async fn git_log(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<mado_core::types::GitLogEntry>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_log(&session_id, limit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get git branch info (name + remote existence).
+#[tauri::command]
+pub async fn git_branch_info(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<mado_core::types::BranchInfo, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_branch_info(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Initialize a git repository in a session's working directory.
+#[tauri::command]
+pub async fn git_init(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_init(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List the roots of all git repositories in a session's workspace.
+#[tauri::command]
+pub async fn git_repos(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<Vec<String>, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_repos(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Push current branch to origin remote.
+#[tauri::command]
+pub async fn git_push(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<(), String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .git_push(&session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Merge the session's own branch back; returns the updated session.
 #[tauri::command]
-pub async fn save_milestone(
+pub async fn merge_session_branch(
     state: State<'_, DaemonState>,
     session_id: String,
-    message: String,
-) -> Result<mado_core::types::Milestone, String> {
+    request: MergeBranchBody,
+) -> Result<Session, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .save_milestone(&session_id, &message)
+        .merge_branch(&session_id, &request)
         .await
+        .map(|(session, _)| session)
         .map_err(|e| e.to_string())
 }
 
-/// List milestones for a session.
+/// Open a pull request for the session's pushed branch.
 #[tauri::command]
-pub async fn list_milestones(
+pub async fn create_pull_request(
     state: State<'_, DaemonState>,
     session_id: String,
-    limit: Option<usize>,
-) -> Result<Vec<mado_core::types::Milestone>, String> {
+    request: CreatePullRequestBody,
+) -> Result<PullRequest, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .list_milestones(&session_id, limit.unwrap_or(20))
+        .create_pull_request(&session_id, &request)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Diff between two milestones.
+/// Pull requests from the session's current branch.
 #[tauri::command]
-pub async fn diff_milestones(
+pub async fn list_pull_requests(
     state: State<'_, DaemonState>,
     session_id: String,
-    from_oid: String,
-    to_oid: String,
-) -> Result<mado_core::types::DiffSummary, String> {
+) -> Result<Vec<PullRequest>, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .diff_milestones(&session_id, &from_oid, &to_oid)
+        .list_pull_requests(&session_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Restore to a milestone.
+/// List available AI models.
 #[tauri::command]
-pub async fn restore_milestone(
+pub fn list_models() -> Vec<ModelInfo> {
+    vec![
+        ModelInfo {
+            id: "opus".to_string(),
+            name: "Claude Opus".to_string(),
+            description: "Most capable, best for complex tasks".to_string(),
+        },
+        ModelInfo {
+            id: "sonnet".to_string(),
+            name: "Claude Sonnet".to_string(),
+            description: "Balanced performance and speed".to_string(),
+        },
+        ModelInfo {
+            id: "haiku".to_string(),
+            name: "Claude Haiku".to_string(),
+            description: "Fastest, great for quick tasks".to_string(),
+        },
+    ]
+}
+
+// ── Chat mode commands ──
+
+/// A message `send_message` took: sent to the daemon, or kept in the outbox
+/// until the daemon and the network are back.
+#[derive(Debug, Clone, Serialize)]
+pub struct SentMessage {
+    /// The daemon's message id, or the outbox's if queued.
+    pub message_id: String,
+    pub queued: bool,
+}
+
+/// Send a message to a session (chat mode), with workspace files as context
+/// and uploaded attachments (by id). While the daemon is down, or earlier
+/// messages to the session are still waiting, it goes to the outbox instead
+/// (see [`crate::outbox`]).
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_message(
+    app: tauri::AppHandle,
     state: State<'_, DaemonState>,
+    outbox: State<'_, crate::outbox::Outbox>,
     session_id: String,
-    oid: String,
+    content: String,
+    model: Option<String>,
+    context_files: Option<Vec<String>>,
+    attachments: Option<Vec<String>>,
+) -> Result<SentMessage, String> {
+    let context_files = context_files.unwrap_or_default();
+    let attachments = attachments.unwrap_or_default();
+    let guard = state.client.read().await;
+    if let Some(client) = guard.as_ref() {
+        if !outbox.holds(&session_id) {
+            match client
+                .send_message(&session_id, &content, model.as_deref(), &context_files, &attachments)
+                .await
+            {
+                Ok(message_id) => {
+                    return Ok(SentMessage {
+                        message_id,
+                        queued: false,
+                    })
+                }
+                Err(e) if !e.is_unreachable() => return Err(e.to_string()),
+                Err(e) => tracing::info!("Keeping a message to session {} for later: {}", session_id, e),
+            }
+        }
+    }
+
+    let message = crate::outbox::PendingMessage::new(session_id, content, model, context_files, attachments);
+    let message_id = message.id.clone();
+    outbox.push(&app, message);
+    Ok(SentMessage {
+        message_id,
+        queued: true,
+    })
+}
+
+/// Messages waiting in the outbox, oldest first.
+#[tauri::command]
+pub fn list_pending_messages(outbox: State<'_, crate::outbox::Outbox>) -> Vec<crate::outbox::PendingMessage> {
+    outbox.list()
+}
+
+/// Drop a message from the outbox before it is sent.
+#[tauri::command]
+pub fn discard_pending_message(
+    app: tauri::AppHandle,
+    outbox: State<'_, crate::outbox::Outbox>,
+    message_id: String,
+) -> Result<(), String> {
+    match outbox.remove(&app, &message_id) {
+        true => Ok(()),
+        false => Err(format!("Message {} isn't waiting to be sent", message_id)),
+    }
+}
+
+/// Replace an earlier user message and run the conversation again from it.
+#[tauri::command]
+pub async fn edit_message(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    message_id: String,
+    content: String,
+    model: Option<String>,
+    context_files: Option<Vec<String>>,
+    attachments: Option<Vec<String>>,
 ) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
@@ -370,72 +1685,77 @@ pub async fn restore_milestone(
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .restore_milestone(&session_id, &oid)
+        .edit_message(
+            &session_id,
+            &message_id,
+            &content,
+            model.as_deref(),
+            &context_files.unwrap_or_default(),
+            &attachments.unwrap_or_default(),
+        )
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Get current workspace changes for a session.
+/// Answer a prompt the Claude CLI raised during the current response.
 #[tauri::command]
-pub async fn workspace_changes(
+pub async fn send_response_input(
     state: State<'_, DaemonState>,
     session_id: String,
-) -> Result<mado_core::types::DiffSummary, String> {
+    text: String,
+) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .workspace_changes(&session_id)
+        .send_response_input(&session_id, &text)
         .await
         .map_err(|e| e.to_string())
 }
 
-// ── Git staging commands ──
-
-/// Get git staging status (staged + unstaged files).
+/// Run the last user message again after the response to it failed.
 #[tauri::command]
-pub async fn git_status(
+pub async fn retry_message(
     state: State<'_, DaemonState>,
     session_id: String,
-) -> Result<mado_core::types::GitStatus, String> {
+) -> Result<String, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_status(&session_id)
+        .retry_message(&session_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Get unified diff content for a single file.
+/// Get messages from a session (chat mode).
 #[tauri::command]
-pub async fn git_file_diff(
+pub async fn get_messages(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_path: String,
-    staged: bool,
-) -> Result<String, String> {
+    limit: Option<usize>,
+    before_id: Option<String>,
+) -> Result<Vec<Message>, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_file_diff(&session_id, &file_path, staged)
+        .get_messages(&session_id, limit, before_id.as_deref())
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Stage a single file.
+/// Cancel an in-progress response (chat mode).
 #[tauri::command]
-pub async fn git_stage_file(
+pub async fn cancel_response(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_path: String,
 ) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
@@ -443,17 +1763,17 @@ pub async fn git_stage_file(
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_stage_file(&session_id, &file_path)
+        .cancel_response(&session_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Unstage a single file.
+/// Remove a queued message before it starts (chat mode).
 #[tauri::command]
-pub async fn git_unstage_file(
+pub async fn cancel_queued_message(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_path: String,
+    message_id: String,
 ) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
@@ -461,17 +1781,17 @@ pub async fn git_unstage_file(
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_unstage_file(&session_id, &file_path)
+        .cancel_queued_message(&session_id, &message_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Stage multiple files in a single batch operation.
+/// Delete one message from a conversation.
 #[tauri::command]
-pub async fn git_stage_files(
+pub async fn delete_message(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_paths: Vec<String>,
+    message_id: String,
 ) -> Result<(), String> {
     let guard = state.client.read().await;
     let client = guard
@@ -479,232 +1799,336 @@ pub async fn git_stage_files(
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_stage_files(&session_id, &file_paths)
+        .delete_message(&session_id, &message_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Unstage multiple files in a single batch operation.
+/// Delete all messages of a conversation. Returns how many there were.
 #[tauri::command]
-pub async fn git_unstage_files(
+pub async fn clear_messages(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_paths: Vec<String>,
-) -> Result<(), String> {
+) -> Result<usize, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_unstage_files(&session_id, &file_paths)
+        .clear_messages(&session_id)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Stage a single hunk from a file.
+/// Summarize the older messages of a conversation, keeping the latest
+/// `keep_recent`.
 #[tauri::command]
-pub async fn git_stage_hunk(
+pub async fn compact_conversation(
     state: State<'_, DaemonState>,
     session_id: String,
-    file_path: String,
-    hunk_index: usize,
-) -> Result<(), String> {
+    keep_recent: Option<usize>,
+    instructions: Option<String>,
+) -> Result<Compaction, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_stage_hunk(&session_id, &file_path, hunk_index)
+        .compact(&session_id, &CompactBody { keep_recent, instructions })
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Commit staged files with a message.
+/// Upload a file (e.g. a pasted screenshot) to attach to chat messages.
 #[tauri::command]
-pub async fn git_commit(
+pub async fn upload_attachment(
     state: State<'_, DaemonState>,
     session_id: String,
-    message: String,
-) -> Result<String, String> {
+    name: String,
+    data: Vec<u8>,
+) -> Result<Attachment, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_commit(&session_id, &message)
+        .upload_attachment(&session_id, &name, data)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Get git commit log for a session's workspace.
+/// List Claude CLI sessions for a working directory.
+/// Returns session metadata (id, first prompt, modified date, message count).
 #[tauri::command]
-pub async fn git_log(
+pub async fn list_cli_sessions(
     state: State<'_, DaemonState>,
-    session_id: String,
+    working_dir: String,
     limit: Option<usize>,
-) -> Result<Vec<mado_core::types::GitLogEntry>, String> {
+) -> Result<Vec<mado_core::types::CliSessionInfo>, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_log(&session_id, limit)
+        .list_cli_sessions(&working_dir, limit)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Get git branch info (name + remote existence).
+/// List the coding agents (claude, aider, codex, gemini) with chat history
+/// for a working directory.
 #[tauri::command]
-pub async fn git_branch_info(
+pub async fn detect_history_sources(
     state: State<'_, DaemonState>,
-    session_id: String,
-) -> Result<mado_core::types::BranchInfo, String> {
+    working_dir: String,
+) -> Result<Vec<mado_core::types::HistorySource>, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_branch_info(&session_id)
+        .history_sources(&working_dir)
         .await
         .map_err(|e| e.to_string())
 }
 
-/// Push current branch to origin remote.
+/// Import a coding agent's history (the Claude CLI's by default) for a
+/// session's working directory.
+/// If `target_cli_session_id` is provided, imports that specific CLI session
+/// and sets the Mado session's claude_session_id for future `--resume`.
+/// Messages the conversation already has are counted as skipped.
 #[tauri::command]
-pub async fn git_push(
+pub async fn import_history(
     state: State<'_, DaemonState>,
     session_id: String,
-) -> Result<(), String> {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    all_sessions: Option<bool>,
+    target_cli_session_id: Option<String>,
+    source: Option<mado_core::types::HistorySource>,
+) -> Result<mado_core::types::HistoryImport, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .git_push(&session_id)
+        .import_history(
+            &session_id,
+            limit,
+            offset,
+            all_sessions,
+            target_cli_session_id.as_deref(),
+            source,
+        )
         .await
         .map_err(|e| e.to_string())
 }
 
-/// List available AI models.
+/// Get a session's token usage and how full its context window is.
 #[tauri::command]
-pub fn list_models() -> Vec<ModelInfo> {
-    vec![
-        ModelInfo {
-            id: "opus".to_string(),
-            name: "Claude Opus".to_string(),
-            description: "Most capable, best for complex tasks".to_string(),
-        },
-        ModelInfo {
-            id: "sonnet".to_string(),
-            name: "Claude Sonnet".to_string(),
-            description: "Balanced performance and speed".to_string(),
-        },
-        ModelInfo {
-            id: "haiku".to_string(),
-            name: "Claude Haiku".to_string(),
-            description: "Fastest, great for quick tasks".to_string(),
-        },
-    ]
+pub async fn get_session_usage(
+    state: State<'_, DaemonState>,
+    session_id: String,
+) -> Result<SessionUsage, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .session_usage(&session_id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-// ── Chat mode commands ──
+/// Export a session's conversation to a file picked in a save dialog.
+/// Returns the file's path, or `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn export_conversation(
+    app: tauri::AppHandle,
+    state: State<'_, DaemonState>,
+    session_id: String,
+    format: Option<ExportFormat>,
+    include_thinking: Option<bool>,
+) -> Result<Option<String>, String> {
+    let format = format.unwrap_or_default();
+    let (file_name, content) = {
+        let guard = state.client.read().await;
+        let client = guard
+            .as_ref()
+            .ok_or_else(|| "Not connected to daemon".to_string())?;
+        client
+            .export_conversation(&session_id, format, include_thinking.unwrap_or(false))
+            .await
+            .map_err(|e| e.to_string())?
+    };
+
+    let Some(picked) = app
+        .dialog()
+        .file()
+        .set_file_name(file_name)
+        .add_filter(format.as_str(), &[format.extension()])
+        .blocking_save_file()
+    else {
+        return Ok(None);
+    };
+    let path = picked.into_path().map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
 
-/// Send a message to a session (chat mode).
+/// The files a session's chat turns wrote and the commands they ran,
+/// oldest first.
 #[tauri::command]
-pub async fn send_message(
+pub async fn audit_log(
     state: State<'_, DaemonState>,
     session_id: String,
-    content: String,
-    model: Option<String>,
-) -> Result<String, String> {
+) -> Result<Vec<AuditEntry>, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    client
-        .send_message(&session_id, &content, model.as_deref())
+    client.audit_log(&session_id).await.map_err(|e| e.to_string())
+}
+
+/// Save a session's audit log to a file the user picks. Returns its path,
+/// or `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn export_audit_log(
+    app: tauri::AppHandle,
+    state: State<'_, DaemonState>,
+    session_id: String,
+    format: Option<AuditFormat>,
+) -> Result<Option<String>, String> {
+    let format = format.unwrap_or_default();
+    let (file_name, content) = {
+        let guard = state.client.read().await;
+        let client = guard
+            .as_ref()
+            .ok_or_else(|| "Not connected to daemon".to_string())?;
+        client
+            .export_audit_log(&session_id, format)
+            .await
+            .map_err(|e| e.to_string())?
+    };
+
+    let Some(picked) = app
+        .dialog()
+        .file()
+        .set_file_name(file_name)
+        .add_filter(format.as_str(), &[format.as_str()])
+        .blocking_save_file()
+    else {
+        return Ok(None);
+    };
+    let path = picked.into_path().map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, content)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Some(path.to_string_lossy().to_string()))
 }
 
-/// Get messages from a session (chat mode).
+/// A directory of a session's workspace.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileListing {
+    pub entries: Vec<FileEntry>,
+    /// The listing stopped at the daemon's entry limit.
+    pub truncated: bool,
+}
+
+/// List a directory of a session's working directory as the daemon sees it,
+/// skipping files ignored by .gitignore.
 #[tauri::command]
-pub async fn get_messages(
+pub async fn list_workspace_files(
     state: State<'_, DaemonState>,
     session_id: String,
-    limit: Option<usize>,
-    before_id: Option<String>,
-) -> Result<Vec<Message>, String> {
+    path: Option<String>,
+    depth: Option<usize>,
+) -> Result<FileListing, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    client
-        .get_messages(&session_id, limit, before_id.as_deref())
+    let (entries, truncated) = client
+        .list_files(&session_id, path.as_deref(), depth)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    Ok(FileListing { entries, truncated })
 }
 
-/// Cancel an in-progress response (chat mode).
+/// A text file of a session's workspace and the ETag to save it with.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceFile {
+    pub content: String,
+    pub etag: String,
+}
+
+/// Read a text file from a session's working directory.
 #[tauri::command]
-pub async fn cancel_response(
+pub async fn read_workspace_file(
     state: State<'_, DaemonState>,
     session_id: String,
-) -> Result<(), String> {
+    path: String,
+) -> Result<WorkspaceFile, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
-    client
-        .cancel_response(&session_id)
+    let (content, etag) = client
+        .read_file(&session_id, &path)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    Ok(WorkspaceFile { content, etag })
 }
 
-/// List Claude CLI sessions for a working directory.
-/// Returns session metadata (id, modified date, estimated message count).
+/// Save a text file in a session's working directory. Fails if the file
+/// changed since it was read with `etag`. Returns the new ETag.
 #[tauri::command]
-pub fn list_cli_sessions(
-    working_dir: String,
-    limit: Option<usize>,
-) -> Result<Vec<mado_daemon::claude_history::SessionInfo>, String> {
-    let path = std::path::Path::new(&working_dir);
-    mado_daemon::claude_history::list_session_summaries(path, limit)
+pub async fn write_workspace_file(
+    state: State<'_, DaemonState>,
+    session_id: String,
+    path: String,
+    content: String,
+    etag: Option<String>,
+) -> Result<String, String> {
+    let guard = state.client.read().await;
+    let client = guard
+        .as_ref()
+        .ok_or_else(|| "Not connected to daemon".to_string())?;
+
+    client
+        .write_file(&session_id, &path, &content, etag.as_deref())
+        .await
         .map_err(|e| e.to_string())
 }
 
-/// Import Claude CLI history for a session's working directory.
-/// If `target_cli_session_id` is provided, imports that specific CLI session
-/// and sets the Mado session's claude_session_id for future `--resume`.
+/// Search file contents in a session's working directory.
 #[tauri::command]
-pub async fn import_history(
+pub async fn search_workspace(
     state: State<'_, DaemonState>,
     session_id: String,
-    limit: Option<usize>,
-    all_sessions: Option<bool>,
-    target_cli_session_id: Option<String>,
-) -> Result<Vec<Message>, String> {
+    query: String,
+    glob: Option<String>,
+    regex: Option<bool>,
+) -> Result<SearchResults, String> {
     let guard = state.client.read().await;
     let client = guard
         .as_ref()
         .ok_or_else(|| "Not connected to daemon".to_string())?;
 
     client
-        .import_history(
-            &session_id,
-            limit,
-            all_sessions,
-            target_cli_session_id.as_deref(),
-        )
+        .search(&session_id, &query, glob.as_deref(), regex.unwrap_or(false))
         .await
         .map_err(|e| e.to_string())
 }
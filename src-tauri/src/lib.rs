@@ -1,12 +1,22 @@
 mod bridge;
 mod commands;
 mod lifecycle;
+mod notifications;
+mod outbox;
+mod windows;
+
+use std::time::Duration;
 
 use commands::DaemonState;
-use tauri::menu::{MenuBuilder, MenuItem, SubmenuBuilder};
-use tauri::{Emitter, Manager};
+use mado_core::types::SessionActivity;
+use tauri::menu::{MenuBuilder, MenuItem, PredefinedMenuItem, SubmenuBuilder};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, Wry};
 use tracing;
 
+/// How often the tray menu's status lines are refreshed.
+const TRAY_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
 fn build_menu(app: &tauri::App) -> tauri::Result<tauri::menu::Menu<tauri::Wry>> {
     // ── Mado (app menu) ──
     let settings = MenuItem::with_id(app, "settings", "Settings...", true, Some("CmdOrCtrl+,"))?;
@@ -27,12 +37,14 @@ fn build_menu(app: &tauri::App) -> tauri::Result<tauri::menu::Menu<tauri::Wry>>
 
     // ── File ──
     let new_conv = MenuItem::with_id(app, "new-conversation", "New Conversation", true, Some("CmdOrCtrl+N"))?;
+    let new_window = MenuItem::with_id(app, "new-window", "New Window", true, Some("CmdOrCtrl+Shift+N"))?;
     let open_folder = MenuItem::with_id(app, "open-folder", "Open Folder...", true, Some("CmdOrCtrl+O"))?;
     let close_pane = MenuItem::with_id(app, "close-pane", "Close Pane", true, Some("CmdOrCtrl+Shift+W"))?;
     let undo_close = MenuItem::with_id(app, "undo-close", "Undo Close", true, Some("CmdOrCtrl+Shift+T"))?;
 
     let file_menu = SubmenuBuilder::new(app, "File")
         .item(&new_conv)
+        .item(&new_window)
         .item(&open_folder)
         .separator()
         .item(&close_pane)
@@ -93,6 +105,124 @@ fn build_menu(app: &tauri::App) -> tauri::Result<tauri::menu::Menu<tauri::Wry>>
         .build()
 }
 
+/// Status lines of the tray menu, refreshed by `refresh_tray`.
+struct TrayStatus {
+    daemon: MenuItem<Wry>,
+    streaming: MenuItem<Wry>,
+    cost: MenuItem<Wry>,
+}
+
+fn build_tray(app: &tauri::App) -> tauri::Result<TrayStatus> {
+    let daemon = MenuItem::with_id(app, "tray-daemon", "Daemon: connecting", false, None::<&str>)?;
+    let streaming = MenuItem::with_id(app, "tray-streaming", "No sessions streaming", false, None::<&str>)?;
+    let cost = MenuItem::with_id(app, "tray-cost", "No session open", false, None::<&str>)?;
+    let open = MenuItem::with_id(app, "tray-open", "Open Mado", true, None::<&str>)?;
+    let new_conv = MenuItem::with_id(app, "tray-new-conversation", "New Conversation", true, None::<&str>)?;
+    let stop = MenuItem::with_id(app, "tray-stop-daemon", "Stop Daemon", true, None::<&str>)?;
+
+    let menu = MenuBuilder::new(app)
+        .item(&daemon)
+        .item(&streaming)
+        .item(&cost)
+        .separator()
+        .item(&open)
+        .item(&new_conv)
+        .separator()
+        .item(&stop)
+        .item(&PredefinedMenuItem::quit(app, None)?)
+        .build()?;
+
+    let mut tray = TrayIconBuilder::with_id("main")
+        .menu(&menu)
+        .tooltip("Mado")
+        .on_menu_event(|app_handle, event| match event.id().as_ref() {
+            "tray-open" => {
+                show_window(app_handle);
+            }
+            "tray-new-conversation" => {
+                if let Some(label) = show_window(app_handle) {
+                    windows::emit_to(app_handle, &label, "menu-action", "new-conversation");
+                }
+            }
+            "tray-stop-daemon" => {
+                let app_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = app_handle.state::<DaemonState>();
+                    let mut guard = state.client.write().await;
+                    *guard = None;
+                    match lifecycle::stop_daemon().await {
+                        Ok(()) => windows::emit_to_all(&app_handle, "daemon-error", "Daemon stopped"),
+                        Err(e) => tracing::error!("{}", e),
+                    }
+                });
+            }
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+
+    Ok(TrayStatus {
+        daemon,
+        streaming,
+        cost,
+    })
+}
+
+/// Bring the focused (or main) window to the front. Returns its label.
+fn show_window(app_handle: &AppHandle) -> Option<String> {
+    let label = windows::target(app_handle)?;
+    if let Some(window) = app_handle.get_webview_window(&label) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    Some(label)
+}
+
+/// Update the tray menu with the daemon's status, how many sessions are
+/// streaming and what the focused pane's session has cost so far.
+async fn refresh_tray(app_handle: &AppHandle, status: &TrayStatus) {
+    let client = app_handle.state::<DaemonState>().client.read().await.clone();
+    let sessions = match &client {
+        Some(client) => client.list_sessions().await.ok(),
+        None => None,
+    };
+    let Some(sessions) = sessions else {
+        let _ = status.daemon.set_text("Daemon: not connected");
+        let _ = status.streaming.set_text("No sessions streaming");
+        let _ = status.cost.set_text("No session open");
+        if let Some(tray) = app_handle.tray_by_id("main") {
+            let _ = tray.set_tooltip(Some("Mado: daemon not connected"));
+        }
+        return;
+    };
+
+    let streaming = sessions
+        .iter()
+        .filter(|s| s.activity == SessionActivity::Streaming)
+        .count();
+    let active = windows::target(app_handle)
+        .and_then(|label| app_handle.state::<windows::ActiveSessions>().get(&label));
+    let cost = active
+        .and_then(|id| sessions.iter().find(|s| s.id.as_str() == id))
+        .map(|s| format!("{}: ${:.2}", s.name, s.total_cost_usd.unwrap_or(0.0)))
+        .unwrap_or_else(|| "No session open".to_string());
+    let streaming_text = match streaming {
+        0 => "No sessions streaming".to_string(),
+        1 => "1 session streaming".to_string(),
+        n => format!("{} sessions streaming", n),
+    };
+
+    let _ = status.daemon.set_text(format!("Daemon: running, {} sessions", sessions.len()));
+    let _ = status.streaming.set_text(&streaming_text);
+    let _ = status.cost.set_text(cost);
+    if let Some(tray) = app_handle.tray_by_id("main") {
+        let _ = tray.set_tooltip(Some(format!("Mado: {}", streaming_text.to_lowercase())));
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Set up tracing for the Tauri app.
@@ -108,15 +238,53 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(DaemonState::new())
+        .manage(windows::ActiveSessions::default())
+        .manage(outbox::Outbox::load())
         .invoke_handler(tauri::generate_handler![
             commands::ping,
             commands::health_check,
             commands::daemon_status,
             commands::reconnect,
+            commands::restart_daemon,
+            commands::get_daemon_logs,
+            commands::dismiss_crash_report,
             commands::list_sessions,
             commands::create_session,
             commands::destroy_session,
+            commands::update_session,
+            commands::get_session_settings,
+            commands::update_session_settings,
+            commands::get_effective_config,
+            commands::revive_session,
+            commands::restart_session,
+            commands::get_recording,
+            commands::fork_session,
+            commands::clone_session,
+            commands::archive_session,
+            commands::retention_plan,
+            commands::get_usage_report,
+            commands::list_schedules,
+            commands::create_schedule,
+            commands::delete_schedule,
+            commands::get_schedule_runs,
+            commands::list_templates,
+            commands::save_template,
+            commands::delete_template,
+            commands::list_prompts,
+            commands::save_prompt,
+            commands::delete_prompt,
+            commands::render_prompt,
+            commands::read_memory,
+            commands::write_memory,
+            commands::append_memory,
+            commands::list_memory_suggestions,
+            commands::suggest_memory,
+            commands::accept_memory_suggestion,
+            commands::dismiss_memory_suggestion,
+            commands::trust_workspace,
+            commands::create_session_from_template,
             commands::write_input,
             commands::resize_session,
             bridge::attach_session,
@@ -124,6 +292,9 @@ pub fn run() {
             commands::has_api_key,
             commands::set_api_key,
             commands::delete_api_key,
+            commands::list_api_keys,
+            commands::set_forge_token,
+            commands::delete_forge_token,
             commands::delete_all_data,
             commands::get_config,
             commands::update_config,
@@ -131,13 +302,27 @@ pub fn run() {
             commands::is_setup_complete,
             commands::check_cli_auth,
             commands::check_cli_installed,
+            commands::cli_info,
             commands::get_user_display_name,
             commands::save_milestone,
             commands::list_milestones,
             commands::diff_milestones,
             commands::restore_milestone,
+            commands::save_snapshot,
+            commands::list_snapshots,
+            commands::restore_snapshot,
+            commands::review_changes,
+            commands::apply_review,
+            commands::revert_turn_changes,
+            commands::project_commands,
+            commands::run_project_command,
+            commands::list_command_runs,
+            commands::compare_models,
+            commands::list_comparisons,
+            commands::apply_comparison,
             commands::workspace_changes,
             // Git staging commands.
+            commands::git_init,
             commands::git_status,
             commands::git_file_diff,
             commands::git_stage_file,
@@ -148,43 +333,111 @@ pub fn run() {
             commands::git_commit,
             commands::git_log,
             commands::git_branch_info,
+            commands::git_repos,
             commands::git_push,
+            commands::merge_session_branch,
+            commands::create_pull_request,
+            commands::list_pull_requests,
             // Claude CLI history.
             commands::list_cli_sessions,
+            commands::detect_history_sources,
             // Chat mode commands.
             commands::send_message,
+            commands::list_pending_messages,
+            commands::discard_pending_message,
+            commands::edit_message,
+            commands::retry_message,
+            commands::send_response_input,
             commands::get_messages,
             commands::cancel_response,
+            commands::delete_message,
+            commands::clear_messages,
+            commands::compact_conversation,
+            commands::cancel_queued_message,
+            commands::upload_attachment,
             commands::import_history,
+            commands::get_session_usage,
+            commands::export_conversation,
+            commands::audit_log,
+            commands::export_audit_log,
+            commands::list_workspace_files,
+            commands::read_workspace_file,
+            commands::write_workspace_file,
+            commands::search_workspace,
             bridge::attach_chat_session,
+            bridge::attach_activity_feed,
+            bridge::follow_command_run,
+            bridge::follow_comparison,
+            windows::set_active_session,
+            windows::new_window,
         ])
+        .on_window_event(|window, event| {
+            // Daemon state is shared; only the window's own record goes.
+            if let tauri::WindowEvent::Destroyed = event {
+                window.state::<windows::ActiveSessions>().remove_window(window.label());
+            }
+        })
         .setup(|app| {
             // Build and set the native menu bar.
             let menu = build_menu(app)?;
             app.set_menu(menu)?;
 
-            // Forward custom menu-item clicks to the frontend.
+            // Forward custom menu-item clicks to the focused window.
             app.on_menu_event(|app_handle, event| {
                 let id = event.id().as_ref().to_string();
-                let _ = app_handle.emit("menu-action", id);
+                match id.as_str() {
+                    "new-window" => {
+                        let app_handle = app_handle.clone();
+                        tauri::async_runtime::spawn(async move {
+                            if let Err(e) = windows::open_window(&app_handle) {
+                                tracing::error!("{}", e);
+                            }
+                        });
+                    }
+                    // Handled by the tray icon.
+                    id if id.starts_with("tray-") => {}
+                    _ => {
+                        if let Some(label) = windows::target(app_handle) {
+                            windows::emit_to(app_handle, &label, "menu-action", id);
+                        }
+                    }
+                }
             });
 
             let state = app.state::<DaemonState>();
             let client_arc = state.client.clone();
             let app_handle = app.handle().clone();
 
+            // Notify about responses in sessions that aren't in front.
+            notifications::spawn(app_handle.clone(), client_arc.clone());
+
+            // Send messages written while the daemon or the network was down.
+            outbox::spawn(app_handle.clone(), client_arc.clone());
+
+            // Tray icon with the daemon's status and quick actions.
+            let tray_status = build_tray(app)?;
+            let tray_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(TRAY_REFRESH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    refresh_tray(&tray_handle, &tray_status).await;
+                }
+            });
+
             // Spawn daemon connection in background.
             tauri::async_runtime::spawn(async move {
                 match lifecycle::ensure_daemon().await {
                     Ok(client) => {
+                        lifecycle::emit_crash_reports(&app_handle, &client).await;
                         let mut guard = client_arc.write().await;
                         *guard = Some(client);
                         drop(guard);
-                        let _ = app_handle.emit("daemon-connected", "connected");
+                        windows::emit_to_all(&app_handle, "daemon-connected", "connected");
                     }
                     Err(e) => {
                         tracing::error!("Failed to connect to daemon: {}", e);
-                        let _ = app_handle.emit("daemon-error", e);
+                        windows::emit_to_all(&app_handle, "daemon-error", e);
                     }
                 }
             });
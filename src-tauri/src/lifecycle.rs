@@ -1,8 +1,12 @@
 use std::path::PathBuf;
 
-use mado_core::client::{default_socket_path, DaemonClient};
+use mado_core::client::{default_socket_path, ClientError, DaemonClient};
+use mado_daemon::service::ServiceManager;
 use tracing;
 
+/// How long a daemon started by the service manager gets to come up.
+const SERVICE_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Find the daemon binary path.
 ///
 /// In development: look in the Cargo workspace target directory.
@@ -47,11 +51,55 @@ fn find_daemon_binary() -> Result<PathBuf, String> {
     ))
 }
 
+/// Connect to a remote daemon at `url` (`tcp://` or `tls://`).
+///
+/// The token comes from `MADO_DAEMON_TOKEN`; `MADO_DAEMON_CA` may point at a
+/// PEM certificate to trust (e.g. a self-signed dev server certificate).
+async fn connect_remote(url: &str) -> Result<DaemonClient, String> {
+    let mut client = DaemonClient::from_url(url).map_err(|e| e.to_string())?;
+    if let Ok(token) = std::env::var("MADO_DAEMON_TOKEN") {
+        client = client.with_token(token.trim());
+    }
+    if let Ok(ca_path) = std::env::var("MADO_DAEMON_CA") {
+        let pem = std::fs::read(&ca_path).map_err(|e| format!("Failed to read {}: {}", ca_path, e))?;
+        client = client.with_ca_cert_pem(&pem).map_err(|e| e.to_string())?;
+    }
+
+    client.connect().await.map_err(|e| format!("Failed to reach daemon at {}: {}", url, e))?;
+    tracing::info!("Connected to remote daemon at {}", url);
+    Ok(client)
+}
+
+/// The service manager, if the daemon is installed as a service
+/// (`mado-daemon install-service`).
+fn installed_service() -> Option<ServiceManager> {
+    ServiceManager::current()
+        .ok()
+        .filter(|_| mado_daemon::service::is_installed())
+}
+
+/// Start (or restart) the installed service and wait for its daemon.
+async fn start_service(manager: ServiceManager, restart: bool) -> Result<DaemonClient, ClientError> {
+    tracing::info!("{} daemon service", if restart { "Restarting" } else { "Starting" });
+    tokio::task::spawn_blocking(move || manager.start(restart))
+        .await
+        .map_err(|e| ClientError::StartFailed(e.to_string()))?
+        .map_err(|e| ClientError::StartFailed(e.to_string()))?;
+    DaemonClient::wait_for_daemon(&default_socket_path(), SERVICE_START_TIMEOUT).await
+}
+
 /// Ensure the daemon is running and return a connected client.
 ///
-/// Attempts to connect to an existing daemon. If none is found,
-/// starts a new one. Retries on failure.
+/// If `MADO_DAEMON_URL` is set, connects to that remote daemon instead.
+/// Otherwise attempts to connect to an existing local daemon, and if none
+/// is found, starts the installed service or else spawns a new daemon.
+/// Retries on failure, except when the running daemon speaks a different
+/// protocol version: that needs `restart_daemon`.
 pub async fn ensure_daemon() -> Result<DaemonClient, String> {
+    if let Ok(url) = std::env::var("MADO_DAEMON_URL") {
+        return connect_remote(&url).await;
+    }
+
     let socket_path = default_socket_path();
     let max_retries = 3;
     let mut last_error = String::new();
@@ -62,11 +110,26 @@ pub async fn ensure_daemon() -> Result<DaemonClient, String> {
         // Try to connect to existing daemon first.
         let client = DaemonClient::new(&socket_path);
         if client.is_alive().await {
+            client.connect().await.map_err(|e| e.to_string())?;
             tracing::info!("Connected to existing daemon");
             return Ok(client);
         }
 
-        // No daemon running -- find and start one.
+        // No daemon running -- prefer the service, so it stays supervised.
+        if let Some(manager) = installed_service() {
+            match start_service(manager, false).await {
+                Ok(client) => return Ok(client),
+                Err(e @ ClientError::IncompatibleDaemon { .. }) => return Err(e.to_string()),
+                Err(e) => {
+                    last_error = e.to_string();
+                    tracing::warn!("Attempt {} failed: {}", attempt, last_error);
+                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                    continue;
+                }
+            }
+        }
+
+        // Otherwise find and start one.
         let daemon_bin = match find_daemon_binary() {
             Ok(bin) => bin,
             Err(e) => {
@@ -79,6 +142,7 @@ pub async fn ensure_daemon() -> Result<DaemonClient, String> {
 
         match DaemonClient::ensure_daemon_running(&socket_path, &daemon_bin).await {
             Ok(client) => return Ok(client),
+            Err(e @ ClientError::IncompatibleDaemon { .. }) => return Err(e.to_string()),
             Err(e) => {
                 last_error = format!("{}", e);
                 tracing::warn!("Attempt {} failed: {}", attempt, last_error);
@@ -92,6 +156,55 @@ pub async fn ensure_daemon() -> Result<DaemonClient, String> {
     Err(format!("Failed to start daemon after {} attempts: {}", max_retries, last_error))
 }
 
+/// Replace the running local daemon with the bundled one, e.g. when it is
+/// left over from a different app version. Its sessions are terminated.
+/// An installed service is restarted instead, with the binary it was
+/// installed with.
+pub async fn restart_daemon() -> Result<DaemonClient, String> {
+    if std::env::var("MADO_DAEMON_URL").is_ok() {
+        return Err("Cannot restart a remote daemon".to_string());
+    }
+
+    if let Some(manager) = installed_service() {
+        return start_service(manager, true)
+            .await
+            .map_err(|e| format!("Failed to restart daemon service: {}", e));
+    }
+
+    let daemon_bin = find_daemon_binary()?;
+    tracing::info!("Restarting daemon with {}", daemon_bin.display());
+    DaemonClient::restart_daemon(&default_socket_path(), &daemon_bin)
+        .await
+        .map_err(|e| format!("Failed to restart daemon: {}", e))
+}
+
+/// Stop the local daemon. Its sessions are terminated. An installed
+/// service only restarts after a failure, so it stays stopped until the app
+/// next connects.
+pub async fn stop_daemon() -> Result<(), String> {
+    if std::env::var("MADO_DAEMON_URL").is_ok() {
+        return Err("Cannot stop a remote daemon".to_string());
+    }
+
+    DaemonClient::stop_daemon(&default_socket_path().with_file_name("mado.pid"))
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to stop daemon: {}", e))
+}
+
+/// Send the daemon's undismissed crash reports to the frontend as a
+/// `daemon-crash-report` event. Called after every connect.
+pub async fn emit_crash_reports(app: &tauri::AppHandle, client: &DaemonClient) {
+    match client.crash_reports().await {
+        Ok(reports) if !reports.is_empty() => {
+            tracing::warn!("Daemon left {} crash report(s)", reports.len());
+            crate::windows::emit_to_all(app, "daemon-crash-report", reports);
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Failed to fetch crash reports: {}", e),
+    }
+}
+
 /// Try to reconnect to the daemon. Called when connection is lost.
 pub async fn reconnect_daemon() -> Result<DaemonClient, String> {
     tracing::info!("Attempting to reconnect to daemon...");
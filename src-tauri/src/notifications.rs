@@ -0,0 +1,92 @@
+//! Native notifications for sessions the user isn't looking at.
+//!
+//! The shell follows the daemon's activity feed itself, independently of any
+//! pane. When a response finishes in a session other than the focused
+//! pane's, or while the window is in the background, it posts a notification
+//! with the session's name and the start of the reply. Each window reports
+//! its focused pane's session with `set_active_session` (see
+//! [`crate::windows`]).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use mado_core::client::DaemonClient;
+use mado_core::types::{ActivityKind, SessionId};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::RwLock;
+
+use crate::windows::ActiveSessions;
+
+/// Longest reply summary shown, in characters.
+const SUMMARY_CHARS: usize = 100;
+
+/// Wait before following the feed again after it ended or failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Whether the user is looking at `session_id` right now.
+fn in_front(app: &AppHandle, session_id: &SessionId) -> bool {
+    app.state::<ActiveSessions>().in_front(app, session_id.as_str())
+}
+
+/// First non-empty line of `text`, shortened to [`SUMMARY_CHARS`].
+fn summarize(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    if line.chars().count() <= SUMMARY_CHARS {
+        return line.to_string();
+    }
+    let mut short: String = line.chars().take(SUMMARY_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+async fn notify_response(app: &AppHandle, client: &DaemonClient, session_id: &SessionId, message_id: &str) {
+    if in_front(app, session_id) {
+        return;
+    }
+    let name = client
+        .list_sessions()
+        .await
+        .ok()
+        .and_then(|sessions| sessions.into_iter().find(|s| &s.id == session_id))
+        .map(|session| session.name)
+        .unwrap_or_else(|| session_id.to_string());
+    let summary = client
+        .get_messages(session_id.as_str(), None, None)
+        .await
+        .ok()
+        .and_then(|messages| messages.into_iter().rev().find(|m| m.id == message_id))
+        .map(|message| summarize(&message.content))
+        .filter(|summary| !summary.is_empty())
+        .unwrap_or_else(|| "Response finished".to_string());
+
+    if let Err(e) = app.notification().builder().title(name).body(summary).show() {
+        tracing::warn!("Failed to show notification: {}", e);
+    }
+}
+
+/// Follow the activity feed for as long as the app runs, picking up the
+/// current client again after a reconnect or daemon restart.
+pub fn spawn(app: AppHandle, client: Arc<RwLock<Option<DaemonClient>>>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let current = client.read().await.clone();
+            if let Some(current) = current {
+                let result = crate::bridge::read_activity_feed(&current, |activity| {
+                    if let ActivityKind::MessageComplete { message_id } = activity.kind {
+                        let (app, client) = (app.clone(), current.clone());
+                        tauri::async_runtime::spawn(async move {
+                            notify_response(&app, &client, &activity.session_id, &message_id).await;
+                        });
+                    }
+                    true
+                })
+                .await;
+                if let Err(e) = result {
+                    tracing::debug!("Activity feed for notifications unavailable: {}", e);
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
@@ -0,0 +1,219 @@
+//! Messages written while the daemon is down.
+//!
+//! Instead of failing, `send_message` keeps a message here when the daemon
+//! can't be reached, and keeps later messages to the same session behind
+//! it. Only a message that never reached the daemon is kept: after a
+//! timeout the daemon may already be working on it, so that is reported as
+//! an error rather than risk a second turn. The outbox is saved to
+//! `~/.mado/outbox.json` (mode 0600), so it survives a restart of the app,
+//! and its messages go out in the order they were written once the daemon
+//! is back.
+//! Every change goes to all windows as `outbox-changed` with the messages
+//! still waiting, so panes can show them as "will send when back online";
+//! a message the daemon turns down is dropped and reported as
+//! `outbox-failed`.
+
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use mado_core::client::DaemonClient;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::RwLock;
+
+/// Wait between attempts to send what is waiting.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A message waiting to be sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingMessage {
+    pub id: String,
+    pub session_id: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub context_files: Vec<String>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub queued_at: DateTime<Utc>,
+}
+
+impl PendingMessage {
+    pub fn new(
+        session_id: String,
+        content: String,
+        model: Option<String>,
+        context_files: Vec<String>,
+        attachments: Vec<String>,
+    ) -> Self {
+        let queued_at = Utc::now();
+        Self {
+            id: format!("pending-{}", queued_at.timestamp_nanos_opt().unwrap_or_default()),
+            session_id,
+            content,
+            model,
+            context_files,
+            attachments,
+            queued_at,
+        }
+    }
+}
+
+/// A waiting message the daemon turned down, and why.
+#[derive(Debug, Clone, Serialize)]
+struct Rejected {
+    message: PendingMessage,
+    error: String,
+}
+
+/// Messages waiting to be sent, oldest first.
+pub struct Outbox {
+    path: Option<PathBuf>,
+    messages: Mutex<Vec<PendingMessage>>,
+}
+
+impl Outbox {
+    /// The outbox as the app left it.
+    pub fn load() -> Self {
+        let path = dirs::home_dir().map(|home| home.join(".mado").join("outbox.json"));
+        let messages = path
+            .as_ref()
+            .and_then(|path| match std::fs::read_to_string(path) {
+                Ok(json) => serde_json::from_str(&json)
+                    .map_err(|e| tracing::warn!("Ignoring unreadable outbox {}: {}", path.display(), e))
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    tracing::warn!("Failed to read outbox {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            messages: Mutex::new(messages),
+        }
+    }
+
+    pub fn list(&self) -> Vec<PendingMessage> {
+        self.lock().clone()
+    }
+
+    /// Whether a message to `session_id` is waiting; later ones must wait
+    /// behind it.
+    pub fn holds(&self, session_id: &str) -> bool {
+        self.lock().iter().any(|m| m.session_id == session_id)
+    }
+
+    /// Keep `message` until it can be sent.
+    pub fn push(&self, app: &AppHandle, message: PendingMessage) {
+        let mut messages = self.lock();
+        messages.push(message);
+        self.changed(app, &messages);
+    }
+
+    /// Drop the message `id`. False if it isn't waiting (anymore).
+    pub fn remove(&self, app: &AppHandle, id: &str) -> bool {
+        let mut messages = self.lock();
+        let Some(index) = messages.iter().position(|m| m.id == id) else {
+            return false;
+        };
+        messages.remove(index);
+        self.changed(app, &messages);
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<PendingMessage>> {
+        self.messages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Save `messages` and tell the windows.
+    fn changed(&self, app: &AppHandle, messages: &[PendingMessage]) {
+        if let Some(path) = &self.path {
+            let result = serde_json::to_vec_pretty(messages)
+                .map_err(std::io::Error::other)
+                .and_then(|json| save(path, &json));
+            if let Err(e) = result {
+                tracing::error!("Failed to save outbox {}: {}", path.display(), e);
+            }
+        }
+        crate::windows::emit_to_all(app, "outbox-changed", messages.to_vec());
+    }
+}
+
+/// Write `json` to `path` readable only by the user, through a temporary
+/// file so a crash never leaves half an outbox.
+fn save(path: &Path, json: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)?;
+    file.write_all(json)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Send what is waiting, oldest first, until a message can't get through.
+async fn flush(app: &AppHandle, client: &DaemonClient) {
+    let outbox = app.state::<Outbox>();
+    while let Some(message) = outbox.list().into_iter().next() {
+        let result = client
+            .send_message(
+                &message.session_id,
+                &message.content,
+                message.model.as_deref(),
+                &message.context_files,
+                &message.attachments,
+            )
+            .await;
+        match result {
+            Ok(_) => tracing::info!("Sent message {} to session {}", message.id, message.session_id),
+            Err(e) if e.is_unreachable() => {
+                tracing::debug!("Daemon unreachable, keeping the outbox: {}", e);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Daemon turned down message {} to session {}: {}", message.id, message.session_id, e);
+                let rejected = Rejected {
+                    message: message.clone(),
+                    error: e.to_string(),
+                };
+                crate::windows::emit_to_all(app, "outbox-failed", rejected);
+            }
+        }
+        outbox.remove(app, &message.id);
+    }
+}
+
+/// Send what is waiting whenever the daemon is up, for as long as the app
+/// runs, with the current client after a reconnect.
+pub fn spawn(app: AppHandle, client: Arc<RwLock<Option<DaemonClient>>>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(RETRY_DELAY).await;
+            if app.state::<Outbox>().list().is_empty() {
+                continue;
+            }
+            let Some(current) = client.read().await.clone() else {
+                continue;
+            };
+            if current.ping().await.is_ok() {
+                flush(&app, &current).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,116 @@
+//! Windows and how shell events reach them.
+//!
+//! Each window runs its own frontend with its own panes, while the daemon
+//! connection is shared by all of them and outlives any one window. Events
+//! for one window (menu actions) go to the focused window; events about the
+//! daemon go to every window. Either way the payload is a [`Routed`] naming
+//! the window it is for.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::utils::config::BackgroundThrottlingPolicy;
+use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder, Window};
+
+/// Label of the window the app starts with.
+pub const MAIN_WINDOW: &str = "main";
+
+/// Numbers the labels of windows opened with `new_window`.
+static NEXT_WINDOW: AtomicUsize = AtomicUsize::new(1);
+
+/// An event payload and the label of the window it is for.
+#[derive(Debug, Clone, Serialize)]
+pub struct Routed<T> {
+    pub window: String,
+    pub payload: T,
+}
+
+/// Send `event` to the window labelled `label`.
+pub fn emit_to<T: Serialize + Clone>(app: &AppHandle, label: &str, event: &str, payload: T) {
+    let routed = Routed {
+        window: label.to_string(),
+        payload,
+    };
+    if let Err(e) = app.emit_to(label, event, routed) {
+        tracing::warn!("Failed to send {} to window {}: {}", event, label, e);
+    }
+}
+
+/// Send `event` to every window.
+pub fn emit_to_all<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    for label in app.webview_windows().into_keys() {
+        emit_to(app, &label, event, payload.clone());
+    }
+}
+
+/// The focused window, or the main window when none is focused (e.g. the
+/// app is in the background).
+pub fn target(app: &AppHandle) -> Option<String> {
+    let windows = app.webview_windows();
+    windows
+        .iter()
+        .find(|(_, window)| window.is_focused().unwrap_or(false))
+        .map(|(label, _)| label.clone())
+        .or_else(|| windows.contains_key(MAIN_WINDOW).then(|| MAIN_WINDOW.to_string()))
+        .or_else(|| windows.into_keys().next())
+}
+
+/// Session shown in each window's focused pane, by window label.
+#[derive(Default)]
+pub struct ActiveSessions(Mutex<HashMap<String, String>>);
+
+impl ActiveSessions {
+    fn set(&self, window: &str, session_id: Option<String>) {
+        let mut active = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match session_id {
+            Some(session_id) => active.insert(window.to_string(), session_id),
+            None => active.remove(window),
+        };
+    }
+
+    /// Forget a closed window.
+    pub fn remove_window(&self, window: &str) {
+        self.set(window, None);
+    }
+
+    /// Session in the focused pane of window `label`.
+    pub fn get(&self, label: &str) -> Option<String> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).get(label).cloned()
+    }
+
+    /// Whether a focused window shows `session_id` in its focused pane.
+    pub fn in_front(&self, app: &AppHandle, session_id: &str) -> bool {
+        let active = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        app.webview_windows().iter().any(|(label, window)| {
+            window.is_focused().unwrap_or(false) && active.get(label).is_some_and(|s| s == session_id)
+        })
+    }
+}
+
+/// Record which session the calling window's focused pane shows (`None`
+/// for an empty pane).
+#[tauri::command]
+pub fn set_active_session(window: Window, session_id: Option<String>) {
+    window.state::<ActiveSessions>().set(window.label(), session_id);
+}
+
+/// Open another window. Returns its label.
+#[tauri::command]
+pub async fn new_window(app: AppHandle) -> Result<String, String> {
+    open_window(&app)
+}
+
+pub fn open_window(app: &AppHandle) -> Result<String, String> {
+    let label = format!("window-{}", NEXT_WINDOW.fetch_add(1, Ordering::Relaxed));
+    WebviewWindowBuilder::new(app, &label, WebviewUrl::default())
+        .title("Mado")
+        .inner_size(1600.0, 1000.0)
+        .min_inner_size(900.0, 640.0)
+        .background_throttling(BackgroundThrottlingPolicy::Disabled)
+        .build()
+        .map_err(|e| format!("Failed to open window: {}", e))?;
+    tracing::info!("Opened window {}", label);
+    Ok(label)
+}
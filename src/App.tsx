@@ -1,11 +1,19 @@
 import { useEffect, useState, useCallback } from "react";
+import { ask } from "@tauri-apps/plugin-dialog";
 import {
   type DaemonStatus,
   healthCheck,
   reconnect,
+  restartDaemon,
   isSetupComplete,
   onDaemonConnected,
   onDaemonError,
+  listPendingMessages,
+  onOutboxChanged,
+  onOutboxFailed,
+  setActiveSession,
+  attachActivityFeed,
+  setWorkspaceTrustPrompt,
 } from "./lib/ipc";
 import { ApiKeySetup } from "./components/ApiKeySetup";
 import { CommandPalette } from "./components/CommandPalette";
@@ -13,6 +21,7 @@ import { StatusBar } from "./components/StatusBar";
 import { Settings } from "./components/Settings";
 import { usePaneStore } from "./stores/panes";
 import { useSessionStore } from "./stores/sessions";
+import { useConversationStore } from "./stores/conversations";
 import { useUiStore } from "./stores/ui";
 import { Layout } from "./components/Layout";
 import { LayoutModal } from "./components/LayoutModal";
@@ -27,6 +36,23 @@ type ConnectionState = "connecting" | "connected" | "disconnected";
 function friendlyError(error: string): { title: string; detail: string; action?: string } {
   const err = String(error).toLowerCase();
 
+  if (err.includes("incompatible daemon")) {
+    return {
+      title: "Daemon version mismatch",
+      detail:
+        "The running daemon is from a different Mado version. Restart it to continue; its open sessions will be closed.",
+      action: "restart",
+    };
+  }
+
+  if (err.includes("daemon is unresponsive") || err.includes("daemon did not respond")) {
+    return {
+      title: "Daemon unresponsive",
+      detail: "The Mado daemon is running but not answering. Restart it if this persists.",
+      action: "restart",
+    };
+  }
+
   if (err.includes("no such file or directory") || err.includes("socket")) {
     return {
       title: "Cannot connect to Mado",
@@ -95,6 +121,31 @@ function App() {
     onOpenSettings: () => setSettingsOpen(true),
   });
 
+  // Report the focused pane's session; the shell notifies about the others.
+  const activeSessionId = usePaneStore((s) => {
+    for (const column of s.columns) {
+      const cell = column.cells.find((c) => c.id === s.activePaneId);
+      if (cell) return cell.sessionId ?? null;
+    }
+    return null;
+  });
+  useEffect(() => {
+    setActiveSession(activeSessionId).catch(() => {});
+  }, [activeSessionId]);
+
+  // Ask before agents run in a folder for the first time.
+  useEffect(() => {
+    setWorkspaceTrustPrompt((path) =>
+      ask(`Let agents read, edit and run commands in ${path} and its subfolders?`, {
+        title: "Trust this folder?",
+        kind: "warning",
+        okLabel: "Trust",
+        cancelLabel: "Cancel",
+      }),
+    );
+    return () => setWorkspaceTrustPrompt(null);
+  }, []);
+
   // Load UI config (theme, font size, zoom) when daemon connects.
   useEffect(() => {
     if (connectionState === "connected") {
@@ -102,6 +153,20 @@ function App() {
     }
   }, [connectionState, loadUiConfig]);
 
+  // Reload it whenever config.json changes, e.g. from another window.
+  useEffect(() => {
+    if (connectionState !== "connected") return;
+    let active = true;
+    attachActivityFeed((event) => {
+      if (active && event.type === "config_changed") {
+        loadUiConfig();
+      }
+    }).promise.catch(() => {});
+    return () => {
+      active = false;
+    };
+  }, [connectionState, loadUiConfig]);
+
   const fetchHealth = useCallback(async () => {
     try {
       const status = await healthCheck();
@@ -143,6 +208,21 @@ function App() {
     }
   }, [fetchHealth, ensureInitialPane]);
 
+  const handleRestartDaemon = useCallback(async () => {
+    setConnectionState("connecting");
+    setErrorMessage(null);
+    try {
+      await restartDaemon();
+      const healthy = await fetchHealth();
+      if (healthy) {
+        ensureInitialPane();
+      }
+    } catch (err) {
+      setConnectionState("disconnected");
+      setErrorMessage(String(err));
+    }
+  }, [fetchHealth, ensureInitialPane]);
+
   useEffect(() => {
     // Listen for daemon connection events.
     const unlistenConnected = onDaemonConnected(async () => {
@@ -184,6 +264,18 @@ function App() {
     };
   }, [fetchHealth, ensureInitialPane]);
 
+  // Follow the outbox of messages written while offline.
+  useEffect(() => {
+    const { setPendingMessages, outboxFailed } = useConversationStore.getState();
+    listPendingMessages().then(setPendingMessages).catch(console.error);
+    const unlistenChanged = onOutboxChanged(setPendingMessages);
+    const unlistenFailed = onOutboxFailed(({ message, error }) => outboxFailed(message, error));
+    return () => {
+      unlistenChanged.then((fn) => fn());
+      unlistenFailed.then((fn) => fn());
+    };
+  }, []);
+
   // Show API key setup if needed (no skip - must configure a provider).
   if (connectionState === "connected" && needsApiKey === true) {
     return (
@@ -319,14 +411,17 @@ function App() {
           );
         })()}
 
-        {connectionState === "disconnected" && (
-          <button
-            onClick={handleReconnect}
-            className="mt-4 w-full rounded-lg bg-blue-600 px-4 py-2 text-sm font-medium text-theme-primary transition-colors hover:bg-blue-500"
-          >
-            Reconnect
-          </button>
-        )}
+        {connectionState === "disconnected" && (() => {
+          const restart = errorMessage !== null && friendlyError(errorMessage).action === "restart";
+          return (
+            <button
+              onClick={restart ? handleRestartDaemon : handleReconnect}
+              className="mt-4 w-full rounded-lg bg-blue-600 px-4 py-2 text-sm font-medium text-theme-primary transition-colors hover:bg-blue-500"
+            >
+              {restart ? "Restart daemon" : "Reconnect"}
+            </button>
+          );
+        })()}
       </div>
     </div>
   );
@@ -100,6 +100,14 @@ export function ChatView({ sessionId }: ChatViewProps) {
   // Group consecutive AI messages with tool calls
   const groupedMessages = useMemo(() => groupMessages(messages), [messages]);
 
+  const pendingMessages = useConversationStore((s) => s.pendingMessages);
+  const pendingCount = useMemo(
+    () => pendingMessages.filter((m) => m.session_id === sessionId).length,
+    [pendingMessages, sessionId],
+  );
+  const rateLimit = useConversationStore(
+    (s) => (sessionId ? s.getSessionState(sessionId)?.rateLimit : null) ?? null,
+  );
   const error = useConversationStore(
     (s) => (sessionId ? s.getSessionState(sessionId)?.error : null) ?? null,
   );
@@ -302,11 +310,26 @@ export function ChatView({ sessionId }: ChatViewProps) {
                 <span className="inline-block h-2 w-2 animate-bounce rounded-full bg-theme-tertiary [animation-delay:150ms]" />
                 <span className="inline-block h-2 w-2 animate-bounce rounded-full bg-theme-tertiary [animation-delay:300ms]" />
               </div>
-              <span>AI is thinking...</span>
+              <span>
+                {rateLimit?.retrying
+                  ? `${rateLimit.kind === "overloaded" ? "API overloaded" : "Rate limited"}, retrying${
+                      rateLimit.retryAfter !== null ? ` in ${rateLimit.retryAfter}s` : ""
+                    }...`
+                  : "AI is thinking..."}
+              </span>
             </div>
           </div>
         )}
 
+        {/* Messages written offline */}
+        {pendingCount > 0 && (
+          <div className="mx-4 my-2 rounded bg-theme-secondary p-3 text-sm text-theme-muted">
+            {pendingCount === 1
+              ? "1 message will send when back online."
+              : `${pendingCount} messages will send when back online.`}
+          </div>
+        )}
+
         {/* Error state */}
         {error && (
           <div className="mx-4 my-2 rounded bg-red-900/30 p-3 text-sm text-red-300">
@@ -7,6 +7,8 @@ import {
   gitUnstageFiles,
   gitStageHunk,
   gitCommit,
+  gitInit,
+  isNotARepositoryError,
 } from "../lib/ipc";
 import { Tooltip } from "./Tooltip";
 import { FileList } from "./git/FileList";
@@ -33,6 +35,7 @@ export function GitView({ sessionId, onClose }: GitViewProps) {
   const [currentDiff, setCurrentDiff] = useState<string | null>(null);
   const [isCommitting, setIsCommitting] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [notARepo, setNotARepo] = useState(false);
   const [viewMode, setViewMode] = useState<"list" | "tree">("list");
 
   // Escape key closes the git view.
@@ -53,12 +56,28 @@ export function GitView({ sessionId, onClose }: GitViewProps) {
       setStaged(status.staged);
       setUnstaged(status.unstaged);
       setError(null);
+      setNotARepo(false);
     } catch (err) {
+      if (isNotARepositoryError(err)) {
+        setNotARepo(true);
+        setError(null);
+        return;
+      }
       console.error("[GitView] Failed to fetch git status:", err);
       setError(String(err));
     }
   }, [sessionId]);
 
+  const handleInitRepo = useCallback(async () => {
+    try {
+      await gitInit(sessionId);
+      await refreshStatus();
+    } catch (err) {
+      console.error("[GitView] Failed to initialize repository:", err);
+      setError(String(err));
+    }
+  }, [sessionId, refreshStatus]);
+
   // Load status on mount.
   useEffect(() => {
     refreshStatus();
@@ -207,6 +226,17 @@ export function GitView({ sessionId, onClose }: GitViewProps) {
           </Tooltip>
         </div>
         <div className="flex items-center gap-2 text-xs text-theme-muted">
+          {notARepo && (
+            <>
+              <span>Not a git repository</span>
+              <button
+                onClick={handleInitRepo}
+                className="rounded px-2 py-0.5 text-theme-primary hover:bg-theme-tertiary"
+              >
+                Initialize
+              </button>
+            </>
+          )}
           {error && (
             <Tooltip content={error}>
               <span className="text-red-400 mr-2">
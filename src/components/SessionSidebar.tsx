@@ -103,7 +103,7 @@ export function SessionSidebar({ paneId, sessionId, onClose }: SessionSidebarPro
       items.push({
         id: cs.id,
         kind: "cli",
-        name: getSessionName(cs.id) ?? cs.id.slice(0, 8),
+        name: getSessionName(cs.id) ?? cs.preview ?? cs.id.slice(0, 8),
         timestamp: cs.modified ? new Date(cs.modified).getTime() : 0,
         timestampStr: cs.modified ?? "",
         messageCount: cs.message_count,
@@ -387,7 +387,6 @@ export function SessionSidebar({ paneId, sessionId, onClose }: SessionSidebarPro
                 )}
                 {item.messageCount > 0 && (
                   <span>
-                    {item.kind === "cli" ? "~" : ""}
                     {item.messageCount} msg
                     {item.messageCount !== 1 ? "s" : ""}
                   </span>
@@ -1,9 +1,9 @@
 import { useEffect } from "react";
-import { listen } from "@tauri-apps/api/event";
 import { open } from "@tauri-apps/plugin-dialog";
 import { usePaneStore } from "../stores/panes";
 import { useSessionStore } from "../stores/sessions";
 import { useUiStore } from "../stores/ui";
+import { onMenuAction } from "../lib/ipc";
 
 interface UseMenuEventsOptions {
   onOpenCommandPalette: () => void;
@@ -12,15 +12,14 @@ interface UseMenuEventsOptions {
 }
 
 /**
- * Listens for native menu events emitted from the Tauri Rust backend
- * and dispatches the corresponding frontend actions.
+ * Listens for native menu events the Tauri Rust backend sends to this
+ * window and dispatches the corresponding frontend actions.
  *
  * Uses getState() to avoid stale closures in the event listener.
  */
 export function useMenuEvents(options: UseMenuEventsOptions) {
   useEffect(() => {
-    const unlisten = listen<string>("menu-action", async (event) => {
-      const action = event.payload;
+    const unlisten = onMenuAction(async (action) => {
       const { splitPane, closePane, undoClose, activePaneId, getLeaves } =
         usePaneStore.getState();
       const { createSession, defaultModel } = useSessionStore.getState();
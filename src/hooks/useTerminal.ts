@@ -39,10 +39,10 @@ export function useTerminal(sessionId: string | null): UseTerminalResult {
     const inputDisposable = terminal.onData((data: string) => {
       if (disposed) return;
       console.log("[Terminal] onData fired, data length:", data.length);
-      // Convert string to byte array.
+      // Convert string to bytes.
       const encoder = new TextEncoder();
-      const bytes = Array.from(encoder.encode(data));
-      console.log("[Terminal] Sending bytes to daemon:", bytes);
+      const bytes = encoder.encode(data);
+      console.log("[Terminal] Sending bytes to daemon:", bytes.length);
       writeInput(sessionId, bytes)
         .then(() => console.log("[Terminal] writeInput succeeded"))
         .catch((err) => {
@@ -1,5 +1,6 @@
 import { invoke, Channel } from "@tauri-apps/api/core";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+import { getCurrentWindow } from "@tauri-apps/api/window";
 
 // Types matching mado-core types.
 export interface DaemonStatus {
@@ -7,6 +8,7 @@ export interface DaemonStatus {
   uptime: number;
   session_count: number;
   version: string;
+  protocol_version: number;
 }
 
 export interface Session {
@@ -17,12 +19,118 @@ export interface Session {
   created_at: string;
   updated_at: string;
   working_dir?: string;
+  repo_root?: string;
   command?: string;
   shell_fallback: boolean;
+  /** Program run instead of the Claude CLI. */
+  custom_command?: CustomCommand;
+  /** Environment variables for the session's processes. */
+  env?: Record<string, string>;
+  activity: SessionActivity;
+  /** Whether the session is producing output (activity is "streaming"). */
+  is_busy?: boolean;
+  /** When the session last produced terminal output or chat events. */
+  last_activity?: string;
   message_count: number;
   claude_session_id?: string;
+  system_prompt?: string;
+  permission_mode?: PermissionMode;
+  /** Chat turns run in a throwaway copy of the repository. */
+  read_only?: boolean;
+  /** Confinement of the session's processes to the workspace. */
+  sandbox?: SandboxSettings;
+  extended_thinking?: boolean;
+  tags?: string[];
+  /** "#rgb" or "#rrggbb". */
+  color?: string;
+  /** Pinned sessions are listed first. */
+  pinned?: boolean;
+  notes?: string;
+  mcp_config?: string[];
+  /** Claude CLI flags, passed after the config's default_flags. */
+  claude_flags?: string[];
+  /** Save a milestone after each response; unset follows the global setting. */
+  auto_milestone?: boolean;
+  /** Exit code of the last process, once it has exited on its own. */
+  exit_code?: number;
+  /** The session's own branch, until it is merged back. */
+  branch?: SessionBranch;
 }
 
+export interface SessionBranch {
+  /** e.g. "mado/fix-login-timeout". */
+  name: string;
+  /** Branch it was created from. */
+  base: string;
+}
+
+export interface SandboxSettings {
+  /**
+   * Sees only the working directory, programs and Claude's state of the
+   * home directory; writes only to the working directory, the temp
+   * directory and that state.
+   */
+  enabled: boolean;
+  /** Hosts reachable besides the config's sandbox.allowed_hosts, e.g. "*.npmjs.org". */
+  allowed_hosts?: string[];
+}
+
+export interface CustomCommand {
+  program: string;
+  args?: string[];
+}
+
+export type PermissionMode = "plan" | "ask" | "auto-edit" | "full-auto";
+
+/** Fields to change with `updateSession`; omitted fields stay as they are. */
+export interface SessionUpdate {
+  name?: string;
+  model?: string;
+  working_dir?: string;
+  /** An empty string removes the system prompt. */
+  system_prompt?: string;
+  permission_mode?: PermissionMode;
+  /** Only for sessions working in a git repository. */
+  read_only?: boolean;
+  /** Replaces the sandbox settings. */
+  sandbox?: SandboxSettings;
+  extended_thinking?: boolean;
+  /** Replaces the session's tags. */
+  tags?: string[];
+  /** "#rgb" or "#rrggbb"; an empty string removes the color. */
+  color?: string;
+  pinned?: boolean;
+  /** An empty string removes the notes. */
+  notes?: string;
+  auto_milestone?: boolean;
+  /** Replaces the session's Claude CLI flags. */
+  claude_flags?: string[];
+}
+
+export interface SessionSettings {
+  system_prompt?: string | null;
+  /** Values of the form "keychain:<name>" refer to keychain secrets. */
+  env?: Record<string, string>;
+}
+
+/** Where a setting comes from, lowest layer first. */
+export type ConfigSource = "default" | "global" | "project" | "session";
+
+export interface EffectiveSetting {
+  name: string;
+  value: unknown;
+  source: ConfigSource;
+}
+
+/** Settings that apply to a session, from config.json, .mado.toml and the session. */
+export interface EffectiveConfig {
+  project_file?: string;
+  project_error?: string;
+  settings: EffectiveSetting[];
+}
+
+export type SessionActivity = "streaming" | "waiting_on_user" | "idle" | "exited";
+
 export interface ModelInfo {
   id: string;
   name: string;
@@ -38,6 +146,84 @@ export interface Milestone {
   deletions: number;
 }
 
+/** A milestone saved together with a copy of the conversation. */
+export interface Snapshot {
+  id: string;
+  milestone: Milestone;
+  message_count: number;
+  created_at: string;
+}
+
+/** What changed in the workspace since a turn started. */
+export interface Review {
+  /** The user message whose turn the changes are counted from. */
+  message_id: string;
+  /** Tree the workspace is compared with. */
+  base: string;
+  files: ReviewFile[];
+}
+
+export interface ReviewFile {
+  path: string;
+  status: "added" | "deleted" | "modified";
+  insertions: number;
+  deletions: number;
+  patch: string;
+}
+
+export type ReviewAction = "keep" | "revert";
+
+/** A run of one of the project's `[commands]` from `.mado.toml`. */
+export interface CommandRun {
+  id: string;
+  name: string;
+  command: string;
+  started_at: string;
+  /** Unset while the command runs. */
+  finished_at: string | null;
+  /** Unset while running, or if the command was killed. */
+  exit_code: number | null;
+  success: boolean;
+  /** The end of the output, once finished. */
+  output: string;
+  /** The message that sent a failure back to the conversation. */
+  follow_up_message_id?: string;
+}
+
+export type RunEvent =
+  | { type: "output"; stream: "stdout" | "stderr"; text: string }
+  | { type: "complete"; run: CommandRun };
+
+/** One model's answer in a comparison. */
+export interface ComparisonVariant {
+  id: string;
+  model: string;
+  messages: Message[];
+  /** Set if this model's answer failed. */
+  error?: string;
+  usage: TokenUsage;
+  cost_usd: number;
+  claude_session_id?: string;
+}
+
+/** The same prompt answered side by side by several models. */
+export interface Comparison {
+  id: string;
+  prompt: string;
+  created_at: string;
+  /** Unset while the models are answering. */
+  finished_at?: string;
+  /** The conversation's last message when the comparison started. */
+  after_message_id?: string;
+  variants: ComparisonVariant[];
+  /** The variant kept in the conversation. */
+  applied?: string;
+}
+
+export type CompareEvent =
+  | { type: "variant"; variant_id: string; event: StreamEvent }
+  | { type: "complete"; comparison: Comparison };
+
 export interface FileDiff {
   path: string;
   insertions: number;
@@ -68,6 +254,70 @@ export interface GitLogEntry {
   timestamp: string;
 }
 
+export interface CrashSession {
+  id: string;
+  name: string;
+  status: string;
+  conversation_state: string;
+}
+
+export interface CrashReport {
+  id: string;
+  created_at: string;
+  daemon_version: string;
+  pid: number;
+  task?: string;
+  thread?: string;
+  message: string;
+  location?: string;
+  backtrace: string;
+  recent_logs: string[];
+  sessions: CrashSession[];
+}
+
+export type FileKind = "file" | "directory" | "symlink";
+
+export interface FileEntry {
+  name: string;
+  path: string;
+  kind: FileKind;
+  size?: number;
+  git_status?: string;
+  children?: FileEntry[];
+}
+
+export interface FileListing {
+  entries: FileEntry[];
+  truncated: boolean;
+}
+
+export interface WorkspaceFile {
+  content: string;
+  etag: string;
+}
+
+export interface SearchMatch {
+  path: string;
+  line_number: number;
+  line: string;
+  before: string[];
+  after: string[];
+}
+
+export interface SearchResults {
+  matches: SearchMatch[];
+  truncated: boolean;
+}
+
+export type RetentionReason = "expired" | "over_limit";
+
+export interface RetentionCandidate {
+  session_id: string;
+  name: string;
+  updated_at: string;
+  reason: RetentionReason;
+}
+
 // ── Chat mode types ──
 
 export type MessageRole = "user" | "assistant" | "system";
@@ -80,6 +330,14 @@ export interface ToolCall {
   input: unknown;
   output?: string;
   status: ToolCallStatus;
+  /** What the subagent a `Task` call started did, in order. */
+  subagent?: SubagentStep[];
+}
+
+export interface SubagentStep {
+  timestamp: string;
+  status: ToolCallStatus;
+  summary: string;
 }
 
 export interface TokenUsage {
@@ -97,15 +355,128 @@ export interface Message {
   timestamp: string;
   usage?: TokenUsage;
   cost_usd?: number;
+  context_files?: ContextFile[];
+  attachments?: Attachment[];
+  /** Claude's reasoning ahead of the reply; show it collapsed. */
+  thinking?: string;
+  /** Agent the message was imported from. */
+  source?: HistorySource;
+  /** Set on the summary a compaction left in place of older messages. */
+  compaction?: string;
+}
+
+/** Older messages replaced by a summary of them. */
+export interface Compaction {
+  id: string;
+  created_at: string;
+  /** The summary, now the conversation's first message. */
+  message: Message;
+  /** How many messages it replaced. */
+  archived: number;
+  /** Estimated tokens before and after. */
+  tokens_before: number;
+  tokens_after: number;
+  cost_usd: number;
+}
+
+/** History imported into a session's conversation. */
+export interface HistoryImport {
+  /** The page of history read, oldest first. */
+  messages: Message[];
+  /** Messages added to the conversation. */
+  added: number;
+  /** Messages the conversation already had. */
+  skipped: number;
+}
+
+/** A coding agent whose chat history can be imported. */
+export type HistorySource = "claude" | "aider" | "codex" | "gemini";
+
+export interface Attachment {
+  id: string;
+  name: string;
+  mime_type: string;
+  size: number;
+  path: string;
 }
 
+export interface ContextFile {
+  path: string;
+  size: number;
+  truncated: boolean;
+}
+
+/** Why a chat response failed; `other` when the daemon couldn't tell. */
+export type ChatErrorKind =
+  | "auth"
+  | "rate_limited"
+  | "overloaded"
+  | "invalid_arguments"
+  | "network"
+  | "timeout"
+  | "read_only"
+  | "other";
+
 export type StreamEvent =
   | { type: "text_delta"; text: string }
+  | { type: "thinking_delta"; text: string }
+  | { type: "subagent_update"; id: string; name: string; status: ToolCallStatus; summary: string }
   | { type: "tool_use_start"; tool_call_id: string; name: string; input: unknown }
   | { type: "tool_result"; tool_call_id: string; output: string; is_error: boolean }
   | { type: "message_complete"; message: Message }
-  | { type: "error"; message: string }
-  | { type: "idle" };
+  | { type: "error"; message: string; kind?: ChatErrorKind; detail?: string }
+  | { type: "rate_limited"; kind: ChatErrorKind; retry_after?: number; retrying: boolean }
+  | { type: "idle" }
+  | { type: "activity_changed"; activity: SessionActivity }
+  | { type: "input_requested"; prompt: string; options?: string[] }
+  | { type: "queue_position"; message_id: string; position: number }
+  | { type: "reset" }
+  | {
+      type: "usage_update";
+      usage: TokenUsage;
+      context_tokens: number;
+      context_window: number;
+      context_utilization: number;
+    }
+  | {
+      type: "budget_warning";
+      period: BudgetPeriod;
+      spent_usd: number;
+      limit_usd: number;
+      /** The limit itself was reached, not just its warning level. */
+      exceeded: boolean;
+    };
+
+export type ActivityKind =
+  | { type: "message_complete"; message_id: string }
+  | { type: "milestone_saved"; oid: string; message: string }
+  | { type: "process_exited"; code?: number | null }
+  | { type: "git_pushed" }
+  | { type: "pull_request_created"; url: string }
+  | { type: "branch_merged"; branch: string; into: string }
+  /** The session started producing output. */
+  | { type: "busy" }
+  /** The session stopped producing output. */
+  | { type: "idle" }
+  /** The session's processes went over a limit and were killed. */
+  | {
+      type: "resource_limit_exceeded";
+      resource: LimitedResource;
+      /** Seconds for CPU time, megabytes for memory. */
+      limit: number;
+      used: number;
+    }
+  /** config.json changed; session_id is empty. */
+  | { type: "config_changed" }
+  | { type: "clone_progress"; done: number; total: number };
+
+export type LimitedResource = "cpu" | "memory";
+
+/** A high-level event from one session, on the daemon-wide activity feed. */
+export type ActivityEvent = ActivityKind & {
+  session_id: string;
+  timestamp: string;
+};
 
 // ── Daemon commands ──
 
@@ -117,6 +488,7 @@ export async function healthCheck(): Promise<DaemonStatus> {
   return invoke<DaemonStatus>("health_check");
 }
 
+/** "connected", "unresponsive" (running but not answering) or "disconnected". */
 export async function daemonStatus(): Promise<string> {
   return invoke<string>("daemon_status");
 }
@@ -125,8 +497,63 @@ export async function reconnect(): Promise<string> {
   return invoke<string>("reconnect");
 }
 
+/** Replace a running daemon from another app version. Ends its sessions. */
+export async function restartDaemon(): Promise<string> {
+  return invoke<string>("restart_daemon");
+}
+
+/** Delete a crash report after showing it to the user. */
+export async function dismissCrashReport(reportId: string): Promise<void> {
+  return invoke<void>("dismiss_crash_report", { reportId });
+}
+
+/** Recent daemon log lines, optionally only those at `level` or worse. */
+export async function getDaemonLogs(
+  lines?: number,
+  level?: "error" | "warn" | "info" | "debug" | "trace",
+): Promise<string[]> {
+  return invoke<string[]>("get_daemon_logs", { lines, level });
+}
+
 // ── Session commands ──
 
+/** The directory a request was refused for because it isn't trusted yet. */
+export function workspaceUntrustedPath(err: unknown): string | null {
+  const match = /^Workspace not trusted: (.*)$/s.exec(String(err));
+  return match ? match[1] : null;
+}
+
+/** Let sessions run in `path` and below. Returns the directory as stored. */
+export async function trustWorkspace(path: string): Promise<string> {
+  return invoke<string>("trust_workspace", { path });
+}
+
+let workspaceTrustPrompt: ((path: string) => Promise<boolean>) | null = null;
+
+/**
+ * Ask the user whether to trust a directory when a request needs it.
+ * Without a prompt, such requests fail with the daemon's error.
+ */
+export function setWorkspaceTrustPrompt(
+  prompt: ((path: string) => Promise<boolean>) | null,
+): void {
+  workspaceTrustPrompt = prompt;
+}
+
+/** Run `request`, and once more after the user trusts its directory. */
+async function withWorkspaceTrust<T>(request: () => Promise<T>): Promise<T> {
+  try {
+    return await request();
+  } catch (err) {
+    const path = workspaceUntrustedPath(err);
+    if (path === null || !workspaceTrustPrompt || !(await workspaceTrustPrompt(path))) {
+      throw err;
+    }
+    await trustWorkspace(path);
+    return request();
+  }
+}
+
 export async function listSessions(): Promise<Session[]> {
   return invoke<Session[]>("list_sessions");
 }
@@ -137,19 +564,337 @@ export async function createSession(
   rows: number,
   cols: number,
   cwd?: string,
+  systemPrompt?: string,
+  /** Program to run instead of the Claude CLI, e.g. "aider". */
+  command?: string,
+  args?: string[],
+  env?: Record<string, string>,
+  /** Defaults to "ask". */
+  permissionMode?: PermissionMode,
+  /** Defaults to unsandboxed. */
+  sandbox?: SandboxSettings,
+): Promise<Session> {
+  return withWorkspaceTrust(() => invoke<Session>("create_session", {
+    name,
+    model,
+    rows,
+    cols,
+    cwd,
+    systemPrompt,
+    command,
+    args,
+    env,
+    permissionMode,
+    sandbox,
+  }));
+}
+
+export async function updateSession(
+  sessionId: string,
+  update: SessionUpdate,
 ): Promise<Session> {
-  return invoke<Session>("create_session", { name, model, rows, cols, cwd });
+  return withWorkspaceTrust(() => invoke<Session>("update_session", { sessionId, update }));
+}
+
+export async function getSessionSettings(sessionId: string): Promise<SessionSettings> {
+  return invoke<SessionSettings>("get_session_settings", { sessionId });
+}
+
+export async function getEffectiveConfig(sessionId: string): Promise<EffectiveConfig> {
+  return invoke<EffectiveConfig>("get_effective_config", { sessionId });
+}
+
+export async function updateSessionSettings(
+  sessionId: string,
+  settings: SessionSettings,
+): Promise<SessionSettings> {
+  return invoke<SessionSettings>("update_session_settings", { sessionId, settings });
 }
 
 export async function destroySession(sessionId: string): Promise<void> {
   return invoke<void>("destroy_session", { sessionId });
 }
 
+export async function reviveSession(
+  sessionId: string,
+  rows: number,
+  cols: number,
+): Promise<Session> {
+  return invoke<Session>("revive_session", { sessionId, rows, cols });
+}
+
+/** Kill a session's process, if still running, and spawn a fresh one. */
+export async function restartSession(
+  sessionId: string,
+  rows: number,
+  cols: number,
+): Promise<Session> {
+  return invoke<Session>("restart_session", { sessionId, rows, cols });
+}
+
+/** A session's terminal recording, in asciicast v2 format. */
+export async function getRecording(sessionId: string): Promise<string> {
+  return invoke<string>("get_recording", { sessionId });
+}
+
+/** Continue a conversation in a new session, from `atMessageId` or its latest message. */
+export async function forkSession(
+  sessionId: string,
+  atMessageId?: string,
+): Promise<Session> {
+  return withWorkspaceTrust(() => invoke<Session>("fork_session", { sessionId, atMessageId }));
+}
+
+/** How `cloneSession` duplicates a workspace: every file, or `git clone`. */
+export type CloneMode = "copy" | "git";
+
+export interface CloneSessionOptions {
+  /** Directory to clone into; must not exist or be empty. */
+  target_dir: string;
+  mode?: CloneMode;
+  /** Carry the conversation over to the new session. */
+  fork_conversation?: boolean;
+  name?: string;
+}
+
+/**
+ * Clone a session's workspace and start a session there with the same
+ * settings. Progress arrives as `clone_progress` activity events.
+ */
+export async function cloneSession(
+  sessionId: string,
+  body: CloneSessionOptions,
+): Promise<Session> {
+  return invoke<Session>("clone_session", { sessionId, body });
+}
+
+export async function archiveSession(sessionId: string): Promise<string> {
+  return invoke<string>("archive_session", { sessionId });
+}
+
+export async function getRetentionPlan(): Promise<RetentionCandidate[]> {
+  return invoke<RetentionCandidate[]>("retention_plan");
+}
+
+export type BudgetPeriod = "daily" | "monthly";
+
+export interface UsageEntry {
+  /** Day, e.g. `2026-03-01`. */
+  date: string;
+  /** Working directory of the sessions. */
+  project: string;
+  usage: TokenUsage;
+  cost_usd: number;
+  turns: number;
+}
+
+export interface UsageReport {
+  since?: string;
+  entries: UsageEntry[];
+  total: TokenUsage;
+  total_cost_usd: number;
+}
+
+/** Token usage and cost across all sessions per day and project, from `since` (YYYY-MM-DD) on. */
+export async function getUsageReport(since?: string): Promise<UsageReport> {
+  return invoke<UsageReport>("get_usage_report", { since });
+}
+
+/** A prompt the daemon runs on a cron schedule, each time in a fresh conversation. */
+export interface Schedule {
+  id: string;
+  /** Five-field cron expression in local time, e.g. `0 2 * * *`. */
+  cron: string;
+  prompt: string;
+  cwd: string;
+  model: string;
+  permission_mode: PermissionMode;
+  created_at: string;
+  next_run?: string;
+}
+
+export type ScheduleRunStatus = "succeeded" | "failed";
+
+export interface ScheduleRun {
+  started_at: string;
+  finished_at: string;
+  status: ScheduleRunStatus;
+  /** Claude's last reply. */
+  reply?: string;
+  error?: string;
+  changes?: DiffSummary;
+  /** Oid of the milestone the changes were saved in. */
+  milestone?: string;
+  usage: TokenUsage;
+  cost_usd: number;
+}
+
+export async function listSchedules(): Promise<Schedule[]> {
+  return invoke<Schedule[]>("list_schedules");
+}
+
+export async function createSchedule(
+  cron: string,
+  prompt: string,
+  cwd: string,
+  model?: string,
+  permissionMode?: PermissionMode,
+): Promise<Schedule> {
+  return invoke<Schedule>("create_schedule", {
+    cron,
+    prompt,
+    cwd,
+    model,
+    permissionMode,
+  });
+}
+
+export async function deleteSchedule(id: string): Promise<void> {
+  return invoke<void>("delete_schedule", { id });
+}
+
+/** Past runs of a schedule, newest first. */
+export async function getScheduleRuns(id: string): Promise<ScheduleRun[]> {
+  return invoke<ScheduleRun[]>("get_schedule_runs", { id });
+}
+
+/** A named preset for new sessions; sessions take every field they leave out from it. */
+export interface SessionTemplate {
+  /** Letters, digits, "-", "_" and spaces. */
+  name: string;
+  model?: string;
+  cwd?: string;
+  system_prompt?: string;
+  env?: Record<string, string>;
+  command?: CustomCommand;
+  /** MCP server config files passed to Claude with --mcp-config. */
+  mcp_config?: string[];
+  /** Unset follows the global auto_milestone setting. */
+  auto_milestone?: boolean;
+}
+
+export async function listTemplates(): Promise<SessionTemplate[]> {
+  return invoke<SessionTemplate[]>("list_templates");
+}
+
+export async function saveTemplate(template: SessionTemplate): Promise<SessionTemplate> {
+  return invoke<SessionTemplate>("save_template", { template });
+}
+
+export async function deleteTemplate(name: string): Promise<void> {
+  return invoke<void>("delete_template", { name });
+}
+
+/** A reusable prompt; `{{name}}` in its content is a variable. */
+export interface SavedPrompt {
+  /** Letters, digits, "-", "_" and spaces. */
+  name: string;
+  description?: string;
+  content: string;
+  /** "project" prompts come from .mado.toml and can't be edited here. */
+  scope?: "user" | "project";
+  /** Variables in the content, in order; set by the daemon. */
+  variables?: string[];
+}
+
+export interface RenderedPrompt {
+  content: string;
+  /** Variables there was no value for, left as {{name}}. */
+  missing?: string[];
+}
+
+/** The prompt library; with `sessionId`, including its project's prompts. */
+export async function listPrompts(sessionId?: string): Promise<SavedPrompt[]> {
+  return invoke<SavedPrompt[]>("list_prompts", { sessionId });
+}
+
+export async function savePrompt(prompt: SavedPrompt): Promise<SavedPrompt> {
+  return invoke<SavedPrompt>("save_prompt", { prompt });
+}
+
+export async function deletePrompt(name: string): Promise<void> {
+  return invoke<void>("delete_prompt", { name });
+}
+
+/**
+ * Fill in a saved prompt's variables for insertion. With `sessionId`,
+ * `{{branch}}` is the session's branch; pass `file` and any others.
+ */
+export async function renderPrompt(
+  name: string,
+  sessionId?: string,
+  variables: Record<string, string> = {},
+): Promise<RenderedPrompt> {
+  return invoke<RenderedPrompt>("render_prompt", { name, sessionId, variables });
+}
+
+/** The project memory file (CLAUDE.md) of a session's workspace. */
+export interface MemoryFile {
+  path: string;
+  content: string;
+  /** Pass back to writeMemory; empty if the file doesn't exist yet. */
+  etag: string;
+}
+
+/** A memory entry suggested by the model, waiting to be accepted or dismissed. */
+export interface MemorySuggestion {
+  id: string;
+  text: string;
+  created_at: string;
+}
+
+export async function readMemory(sessionId: string): Promise<MemoryFile> {
+  return invoke<MemoryFile>("read_memory", { sessionId });
+}
+
+/** Replace the memory file; with `etag`, fails if it changed since it was read. */
+export async function writeMemory(sessionId: string, content: string, etag?: string): Promise<MemoryFile> {
+  return invoke<MemoryFile>("write_memory", { sessionId, content, etag });
+}
+
+/** "Remember this": add an entry to the memory file. */
+export async function appendMemory(sessionId: string, entry: string): Promise<MemoryFile> {
+  return invoke<MemoryFile>("append_memory", { sessionId, entry });
+}
+
+export async function listMemorySuggestions(sessionId: string): Promise<MemorySuggestion[]> {
+  return invoke<MemorySuggestion[]>("list_memory_suggestions", { sessionId });
+}
+
+/** Ask the model for memory entries from the recent conversation. Returns all pending. */
+export async function suggestMemory(sessionId: string): Promise<MemorySuggestion[]> {
+  return invoke<MemorySuggestion[]>("suggest_memory", { sessionId });
+}
+
+export async function acceptMemorySuggestion(sessionId: string, suggestionId: string): Promise<MemoryFile> {
+  return invoke<MemoryFile>("accept_memory_suggestion", { sessionId, suggestionId });
+}
+
+export async function dismissMemorySuggestion(sessionId: string, suggestionId: string): Promise<MemorySuggestion[]> {
+  return invoke<MemorySuggestion[]>("dismiss_memory_suggestion", { sessionId, suggestionId });
+}
+
+export async function createSessionFromTemplate(
+  template: string,
+  name: string,
+  rows: number,
+  cols: number,
+): Promise<Session> {
+  return withWorkspaceTrust(() =>
+    invoke<Session>("create_session_from_template", { template, name, rows, cols }),
+  );
+}
+
+/** Write to a session's PTY; with `paste`, as a bracketed paste. */
 export async function writeInput(
   sessionId: string,
-  data: number[],
+  data: Uint8Array,
+  paste = false,
 ): Promise<void> {
-  return invoke<void>("write_input", { sessionId, data });
+  // Raw payload: bytes go over IPC without JSON array encoding.
+  return invoke<void>("write_input", data, {
+    headers: { "x-session-id": sessionId, "x-paste": String(paste) },
+  });
 }
 
 export async function resizeSession(
@@ -172,12 +917,30 @@ export async function hasApiKey(): Promise<boolean> {
   return invoke<boolean>("has_api_key");
 }
 
-export async function setApiKey(key: string): Promise<void> {
-  return invoke<void>("set_api_key", { key });
+/** Providers whose API keys can be stored. */
+export type ApiProvider = "anthropic" | "openai";
+
+/** Store a provider's API key; the Anthropic key by default. */
+export async function setApiKey(key: string, provider?: ApiProvider): Promise<void> {
+  return invoke<void>("set_api_key", { key, provider });
+}
+
+export async function deleteApiKey(provider?: ApiProvider): Promise<void> {
+  return invoke<void>("delete_api_key", { provider });
 }
 
-export async function deleteApiKey(): Promise<void> {
-  return invoke<void>("delete_api_key");
+/** Providers with a stored API key. */
+export async function listApiKeys(): Promise<ApiProvider[]> {
+  return invoke<ApiProvider[]>("list_api_keys");
+}
+
+/** Store the token for opening pull requests on `host`, e.g. "github.com". */
+export async function setForgeToken(host: string, token: string): Promise<void> {
+  return invoke<void>("set_forge_token", { host, token });
+}
+
+export async function deleteForgeToken(host: string): Promise<void> {
+  return invoke<void>("delete_forge_token", { host });
 }
 
 export async function deleteAllData(): Promise<void> {
@@ -186,6 +949,75 @@ export async function deleteAllData(): Promise<void> {
 
 // ── Config commands ──
 
+export interface LogsConfig {
+  max_size_mb: number;
+  retention_days: number;
+}
+
+export interface RetentionConfig {
+  max_age_days?: number;
+  max_sessions?: number;
+  archive: boolean;
+}
+
+export interface BudgetConfig {
+  daily_usd?: number;
+  monthly_usd?: number;
+  /** Fraction of a limit at which to warn. */
+  warn_at: number;
+  /** Refuse new messages while a limit is exceeded. */
+  block: boolean;
+}
+
+export interface RespawnConfig {
+  /** Revive a session whose process exits with a failure. */
+  enabled: boolean;
+  /** Crashes in a row after which the session is left terminated. */
+  max_retries: number;
+}
+
+export interface RecordingConfig {
+  /** Record every session's terminal output. */
+  enabled: boolean;
+  /** Megabytes after which a session's recording stops growing. */
+  max_mb: number;
+}
+
+export interface LimitsConfig {
+  /** CPU seconds a session's processes may use, including exited ones. */
+  cpu_seconds?: number;
+  /** Megabytes of memory a session's processes may hold at once. */
+  memory_mb?: number;
+}
+
+export interface ChatConfig {
+  /** Minutes a chat response may take in total; null for no limit. */
+  timeout_minutes: number | null;
+  /** Seconds a chat response may go without a stream event; null to never stop it. */
+  stall_seconds: number | null;
+  /** Send a message again by itself after a rate limit or an overloaded API. */
+  retry_rate_limits: boolean;
+  /** Retries in a row before giving up. */
+  max_retries: number;
+  /** Seconds before the first retry, doubled for each one after. */
+  retry_backoff_seconds: number;
+}
+
+export interface BranchesConfig {
+  /**
+   * Create and check out a branch for each new session in a repository,
+   * unless another running session there already has one.
+   */
+  per_session: boolean;
+  /** Start of the branch names, before the session name. */
+  prefix: string;
+}
+
+export interface SandboxConfig {
+  /** Hosts every sandboxed session may connect to, exactly or as "*.domain". */
+  allowed_hosts: string[];
+}
+
 export interface UiConfig {
   theme: string;
   zoom_level: number;
@@ -194,12 +1026,42 @@ export interface UiConfig {
   ai_name?: string;
 }
 
+export interface ForgeConfig {
+  kind: "github" | "gitlab";
+  /** API root; derived from the host when unset. */
+  api_url?: string;
+}
+
 export interface MadoConfig {
   version: number;
   provider: string;
   auth_method: "cli" | "api_key";
   default_model: string;
+  /** Claude CLI binary; found on the system when unset. */
+  claude_path?: string;
+  /** Flags passed to the Claude CLI on every spawn. */
+  default_flags?: string[];
+  /** API base URLs by provider, passed to spawned CLIs. */
+  base_urls?: Partial<Record<ApiProvider, string>>;
+  /** Keep API keys in ~/.mado/secrets.json instead of the OS keychain. */
+  secrets_file?: boolean;
+  /** Self-hosted forges by the host in remote URLs. */
+  forges?: Record<string, ForgeConfig>;
   setup_complete: boolean;
+  auto_milestone: boolean;
+  log_level?: string;
+  logs: LogsConfig;
+  /** Kilobytes of terminal output kept per session for replay. */
+  scrollback_kb: number;
+  idle_timeout_minutes?: number;
+  retention: RetentionConfig;
+  budget: BudgetConfig;
+  respawn: RespawnConfig;
+  recording: RecordingConfig;
+  limits: LimitsConfig;
+  chat: ChatConfig;
+  branches: BranchesConfig;
+  sandbox: SandboxConfig;
   ui: UiConfig;
 }
 
@@ -207,8 +1069,30 @@ export async function getConfig(): Promise<MadoConfig> {
   return invoke<MadoConfig>("get_config");
 }
 
+/** A setting `updateConfig` rejected. */
+export interface ConfigFieldError {
+  /** Dotted path of the setting, e.g. "budget.daily_usd". */
+  field: string;
+  message: string;
+}
+
+/** Thrown by `updateConfig`; `fields` is empty if saving itself failed. */
+export class ConfigUpdateError extends Error {
+  fields: ConfigFieldError[];
+
+  constructor(message: string, fields: ConfigFieldError[]) {
+    super(message);
+    this.fields = fields;
+  }
+}
+
 export async function updateConfig(config: MadoConfig): Promise<void> {
-  return invoke<void>("update_config", { config });
+  try {
+    await invoke<void>("update_config", { config });
+  } catch (err) {
+    const error = err as Partial<{ message: string; fields: ConfigFieldError[] }>;
+    throw new ConfigUpdateError(error.message ?? String(err), error.fields ?? []);
+  }
 }
 
 export async function completeSetup(): Promise<void> {
@@ -223,6 +1107,26 @@ export async function checkCliAuth(): Promise<boolean> {
   return invoke<boolean>("check_cli_auth");
 }
 
+export type CliAuth = "api_key" | "login" | "logged_out";
+
+export interface CliInfo {
+  /** Binary the daemon runs; absent if none was found. */
+  path?: string;
+  /** Output of `claude --version`. */
+  version?: string;
+  auth: CliAuth;
+  /** Whether chat mode's stream-json output works. */
+  stream_json: boolean;
+  /** Problems to show the user. */
+  warnings?: string[];
+}
+
+/** The Claude CLI as the daemon spawns it. Takes a few seconds. */
+export async function cliInfo(): Promise<CliInfo> {
+  return invoke<CliInfo>("cli_info");
+}
+
+/** Rejects if claude_path or default_flags in the config are invalid. */
 export async function checkCliInstalled(): Promise<string | null> {
   return invoke<string | null>("check_cli_installed");
 }
@@ -266,6 +1170,97 @@ export async function restoreMilestone(
   return invoke<void>("restore_milestone", { sessionId, oid });
 }
 
+/** Save a milestone and a copy of the conversation, restorable together. */
+export async function saveSnapshot(sessionId: string, message: string): Promise<Snapshot> {
+  return invoke<Snapshot>("save_snapshot", { sessionId, message });
+}
+
+export async function listSnapshots(sessionId: string): Promise<Snapshot[]> {
+  return invoke<Snapshot[]>("list_snapshots", { sessionId });
+}
+
+/** Put both the workspace files and the conversation back to a snapshot. */
+export async function restoreSnapshot(sessionId: string, snapshotId: string): Promise<void> {
+  return invoke<void>("restore_snapshot", { sessionId, snapshotId });
+}
+
+/** Files changed since the turn of `sinceMessageId` (or the latest turn) started. */
+export async function reviewChanges(sessionId: string, sinceMessageId?: string): Promise<Review> {
+  return invoke<Review>("review_changes", { sessionId, sinceMessageId });
+}
+
+/** Keep or revert files of a review; returns the review afterwards. */
+export async function applyReview(
+  sessionId: string,
+  decisions: { path: string; action: ReviewAction }[],
+  sinceMessageId?: string,
+): Promise<Review> {
+  return invoke<Review>("apply_review", {
+    sessionId,
+    body: { since_message_id: sinceMessageId, decisions },
+  });
+}
+
+/**
+ * Undo the file changes of the turn of `messageId`, keeping later ones.
+ * Returns the files touched; fails if later changes conflict.
+ */
+export async function revertTurnChanges(sessionId: string, messageId: string): Promise<string[]> {
+  return invoke<string[]>("revert_turn_changes", { sessionId, messageId });
+}
+
+// ── Project commands ──
+
+/** The commands in the session's `.mado.toml`, by name. */
+export async function projectCommands(sessionId: string): Promise<Record<string, string>> {
+  return invoke<Record<string, string>>("project_commands", { sessionId });
+}
+
+/**
+ * Start one of the project's commands. With `feedBack`, a failure is sent
+ * to the conversation as a follow-up prompt. Follow it with `followCommandRun`.
+ */
+export async function runProjectCommand(
+  sessionId: string,
+  name: string,
+  feedBack?: boolean,
+): Promise<CommandRun> {
+  return invoke<CommandRun>("run_project_command", { sessionId, name, feedBack });
+}
+
+/** The session's command runs, newest first. */
+export async function listCommandRuns(sessionId: string): Promise<CommandRun[]> {
+  return invoke<CommandRun[]>("list_command_runs", { sessionId });
+}
+
+// ── Model comparison commands ──
+
+/**
+ * Ask two or three models the same question without touching the
+ * conversation. Follow the answers with `followComparison`.
+ */
+export async function compareModels(
+  sessionId: string,
+  content: string,
+  models: string[],
+): Promise<Comparison> {
+  return invoke<Comparison>("compare_models", { sessionId, content, models });
+}
+
+/** The session's comparisons, newest first. */
+export async function listComparisons(sessionId: string): Promise<Comparison[]> {
+  return invoke<Comparison[]>("list_comparisons", { sessionId });
+}
+
+/** Keep one model's answer in the conversation and continue from it. */
+export async function applyComparison(
+  sessionId: string,
+  comparisonId: string,
+  variantId: string,
+): Promise<Comparison> {
+  return invoke<Comparison>("apply_comparison", { sessionId, comparisonId, variantId });
+}
+
 // ── Change indicator commands ──
 
 export async function workspaceChanges(
@@ -349,11 +1344,75 @@ export async function gitBranchInfo(
   return invoke<BranchInfo>("git_branch_info", { sessionId });
 }
 
+/** Initialize a git repository in the session's working directory. Returns the repo root. */
+export async function gitInit(sessionId: string): Promise<string> {
+  return invoke<string>("git_init", { sessionId });
+}
+
+/** Whether a git command failed because the workspace is not a git repository. */
+export function isNotARepositoryError(err: unknown): boolean {
+  return String(err).startsWith("Not a git repository");
+}
+
+/** List the roots of all git repositories in a session's workspace. */
+export async function gitRepos(sessionId: string): Promise<string[]> {
+  return invoke<string[]>("git_repos", { sessionId });
+}
+
 /** Push current branch to origin remote. */
 export async function gitPush(sessionId: string): Promise<void> {
   return invoke<void>("git_push", { sessionId });
 }
 
+export interface MergeBranchOptions {
+  /** Branch to merge into; the session branch's base if omitted. */
+  into?: string;
+  keep_branch?: boolean;
+}
+
+/** Merge the session's own branch back and check out the target branch. */
+export async function mergeSessionBranch(
+  sessionId: string,
+  request: MergeBranchOptions = {},
+): Promise<Session> {
+  return invoke<Session>("merge_session_branch", { sessionId, request });
+}
+
+/** A pull request on GitHub, or a merge request on GitLab. */
+export interface PullRequest {
+  number: number;
+  title: string;
+  url: string;
+  state: "open" | "closed" | "merged";
+  draft: boolean;
+  head: string;
+  base: string;
+}
+
+export interface CreatePullRequestOptions {
+  /** Required unless `from_conversation` is set. */
+  title?: string;
+  body?: string;
+  /** Target branch; the repository's default branch if omitted. */
+  base?: string;
+  draft?: boolean;
+  /** Fill in a missing title and body from the conversation. */
+  from_conversation?: boolean;
+}
+
+/** Open a pull request for the session's pushed branch. */
+export async function createPullRequest(
+  sessionId: string,
+  request: CreatePullRequestOptions,
+): Promise<PullRequest> {
+  return invoke<PullRequest>("create_pull_request", { sessionId, request });
+}
+
+/** Pull requests from the session's current branch, newest first. */
+export async function listPullRequests(sessionId: string): Promise<PullRequest[]> {
+  return invoke<PullRequest[]>("list_pull_requests", { sessionId });
+}
+
 /**
  * Get diff stats from git status.
  * Wraps gitStatus to provide aggregate file diff statistics.
@@ -377,13 +1436,15 @@ export async function gitDiffStat(
 
 export interface CliSessionInfo {
   id: string;
+  /** Start of the first prompt. */
+  preview?: string;
   modified: string | null;
   message_count: number;
 }
 
 /**
  * List Claude CLI sessions for a working directory.
- * Returns session metadata (id, modified date, estimated message count).
+ * Returns session metadata (id, first prompt, modified date, message count).
  */
 export async function listCliSessions(
   workingDir: string,
@@ -392,6 +1453,11 @@ export async function listCliSessions(
   return invoke<CliSessionInfo[]>("list_cli_sessions", { workingDir, limit });
 }
 
+/** Coding agents with chat history for a working directory. */
+export async function detectHistorySources(workingDir: string): Promise<HistorySource[]> {
+  return invoke<HistorySource[]>("detect_history_sources", { workingDir });
+}
+
 // ── SSE bridge ──
 
 /**
@@ -416,12 +1482,78 @@ export function attachSession(
 
 // ── Chat mode commands ──
 
+/** A message `sendMessage` took: sent, or waiting in the outbox. */
+export interface SentMessage {
+  /** The daemon's message id, or the outbox's if queued. */
+  message_id: string;
+  /** Kept until the daemon and the network are back. */
+  queued: boolean;
+}
+
+/** A message written while the daemon or the network was down. */
+export interface PendingMessage {
+  id: string;
+  session_id: string;
+  content: string;
+  model?: string;
+  context_files: string[];
+  attachments: string[];
+  queued_at: string;
+}
+
 export async function sendMessage(
   sessionId: string,
   content: string,
   model?: string,
-): Promise<string> {
-  return invoke<string>("send_message", { sessionId, content, model });
+  contextFiles?: string[],
+  attachments?: string[],
+): Promise<SentMessage> {
+  return withWorkspaceTrust(() => invoke<SentMessage>("send_message", {
+    sessionId,
+    content,
+    model,
+    contextFiles,
+    attachments,
+  }));
+}
+
+/** Messages waiting in the outbox, oldest first. */
+export async function listPendingMessages(): Promise<PendingMessage[]> {
+  return invoke<PendingMessage[]>("list_pending_messages");
+}
+
+/** Drop a message from the outbox before it is sent. */
+export async function discardPendingMessage(messageId: string): Promise<void> {
+  return invoke<void>("discard_pending_message", { messageId });
+}
+
+/** Replace an earlier user message and run the conversation again from it. */
+export async function editMessage(
+  sessionId: string,
+  messageId: string,
+  content: string,
+  model?: string,
+  contextFiles?: string[],
+  attachments?: string[],
+): Promise<void> {
+  return withWorkspaceTrust(() => invoke<void>("edit_message", {
+    sessionId,
+    messageId,
+    content,
+    model,
+    contextFiles,
+    attachments,
+  }));
+}
+
+/** Answer a prompt the Claude CLI raised during the current response. */
+export async function sendResponseInput(sessionId: string, text: string): Promise<void> {
+  return invoke<void>("send_response_input", { sessionId, text });
+}
+
+/** Run the last user message again after the response to it failed. */
+export async function retryMessage(sessionId: string): Promise<string> {
+  return withWorkspaceTrust(() => invoke<string>("retry_message", { sessionId }));
 }
 
 export async function getMessages(
@@ -436,23 +1568,186 @@ export async function cancelResponse(sessionId: string): Promise<void> {
   return invoke<void>("cancel_response", { sessionId });
 }
 
+export async function deleteMessage(sessionId: string, messageId: string): Promise<void> {
+  return invoke<void>("delete_message", { sessionId, messageId });
+}
+
+/** Delete all messages and start over; resolves to how many were deleted. */
+export async function clearMessages(sessionId: string): Promise<number> {
+  return invoke<number>("clear_messages", { sessionId });
+}
+
+/**
+ * Replace all but the latest `keepRecent` messages (4 by default) with a
+ * summary written by the session's model. The originals are archived.
+ */
+export async function compactConversation(
+  sessionId: string,
+  keepRecent?: number,
+  instructions?: string,
+): Promise<Compaction> {
+  return invoke<Compaction>("compact_conversation", { sessionId, keepRecent, instructions });
+}
+
+export async function cancelQueuedMessage(
+  sessionId: string,
+  messageId: string,
+): Promise<void> {
+  return invoke<void>("cancel_queued_message", { sessionId, messageId });
+}
+
+/** Upload a file (e.g. a pasted screenshot); send its id with a message. */
+export async function uploadAttachment(
+  sessionId: string,
+  name: string,
+  data: Uint8Array,
+): Promise<Attachment> {
+  return invoke<Attachment>("upload_attachment", {
+    sessionId,
+    name,
+    data: Array.from(data),
+  });
+}
+
 /**
  * Import Claude CLI history for a session's working directory.
  * Returns messages from Claude CLI sessions in that folder.
  * If `targetCliSessionId` is provided, imports that specific CLI session
  * and sets the Mado session's claude_session_id for future `--resume`.
  */
+export interface SessionUsage {
+  total: TokenUsage;
+  total_cost_usd: number;
+  /** Context in use as of the latest turn. */
+  context_tokens: number;
+  context_window: number;
+  /** `context_tokens` as a fraction of `context_window`. */
+  context_utilization: number;
+  /** Set while waiting out a rate limit or an overloaded API. */
+  backoff?: RateLimitBackoff;
+}
+
+export interface RateLimitBackoff {
+  kind: ChatErrorKind;
+  /** When the API may be tried again. */
+  until: string;
+  /** Rate-limited responses in a row. */
+  failures: number;
+  /** Whether the message is sent again by itself at `until`. */
+  retrying: boolean;
+}
+
+export async function getSessionUsage(sessionId: string): Promise<SessionUsage> {
+  return invoke<SessionUsage>("get_session_usage", { sessionId });
+}
+
+export type ExportFormat = "markdown" | "json" | "html";
+
+/** Save a conversation to a file picked in a dialog; resolves to its path, or null if cancelled. */
+export async function exportConversation(
+  sessionId: string,
+  format?: ExportFormat,
+  includeThinking?: boolean,
+): Promise<string | null> {
+  return invoke<string | null>("export_conversation", { sessionId, format, includeThinking });
+}
+
+/** A chat turn's tool call that wrote files or ran a command. */
+export interface AuditEntry {
+  timestamp: string;
+  tool: string;
+  /** The command it ran or the file it wrote, on one line. */
+  summary: string;
+  /** Files it wrote; unknown for commands. */
+  paths?: string[];
+  outcome: "succeeded" | "failed" | "blocked" | "interrupted";
+  tool_call_id: string;
+}
+
+export type AuditFormat = "json" | "csv";
+
+/** The files a session's chat turns wrote and the commands they ran, oldest first. */
+export async function auditLog(sessionId: string): Promise<AuditEntry[]> {
+  return invoke<AuditEntry[]>("audit_log", { sessionId });
+}
+
+/** Save a session's audit log to a file picked in a dialog; resolves to its path, or null if cancelled. */
+export async function exportAuditLog(sessionId: string, format?: AuditFormat): Promise<string | null> {
+  return invoke<string | null>("export_audit_log", { sessionId, format });
+}
+
 export async function importHistory(
   sessionId: string,
   limit?: number,
   allSessions?: boolean,
   targetCliSessionId?: string,
-): Promise<Message[]> {
-  return invoke<Message[]>("import_history", {
+  offset?: number,
+  source?: HistorySource,
+): Promise<HistoryImport> {
+  return invoke<HistoryImport>("import_history", {
     sessionId,
     limit,
+    offset,
     allSessions,
     targetCliSessionId,
+    source,
+  });
+}
+
+/**
+ * List a directory of a session's working directory (relative `path`, default
+ * the working directory) down to `depth` levels, skipping ignored files.
+ */
+export async function listWorkspaceFiles(
+  sessionId: string,
+  path?: string,
+  depth?: number,
+): Promise<FileListing> {
+  return invoke<FileListing>("list_workspace_files", { sessionId, path, depth });
+}
+
+/** Read a text file from a session's working directory. */
+export async function readWorkspaceFile(
+  sessionId: string,
+  path: string,
+): Promise<WorkspaceFile> {
+  return invoke<WorkspaceFile>("read_workspace_file", { sessionId, path });
+}
+
+/**
+ * Save a text file in a session's working directory. Pass the `etag` from
+ * `readWorkspaceFile` to fail instead of overwriting a change made since.
+ * Resolves to the new ETag.
+ */
+export async function writeWorkspaceFile(
+  sessionId: string,
+  path: string,
+  content: string,
+  etag?: string,
+): Promise<string> {
+  return invoke<string>("write_workspace_file", {
+    sessionId,
+    path,
+    content,
+    etag,
+  });
+}
+
+/**
+ * Search file contents in a session's working directory, skipping files
+ * ignored by .gitignore. `query` is literal unless `regex` is set.
+ */
+export async function searchWorkspace(
+  sessionId: string,
+  query: string,
+  glob?: string,
+  regex?: boolean,
+): Promise<SearchResults> {
+  return invoke<SearchResults>("search_workspace", {
+    sessionId,
+    query,
+    glob,
+    regex,
   });
 }
 
@@ -476,20 +1771,133 @@ export function attachChatSession(
   return { promise, channel };
 }
 
+/**
+ * Attach to the daemon-wide activity feed (all sessions).
+ * Used to badge panes that are not currently attached.
+ */
+export function attachActivityFeed(
+  onEvent: (event: ActivityEvent) => void,
+): { promise: Promise<void>; channel: Channel<ActivityEvent> } {
+  const channel = new Channel<ActivityEvent>();
+  channel.onmessage = onEvent;
+
+  const promise = invoke<void>("attach_activity_feed", { onEvent: channel });
+
+  return { promise, channel };
+}
+
+/**
+ * Follow a project command run: its output from the start, then the
+ * finished run. The promise resolves once it has finished.
+ */
+export function followCommandRun(
+  sessionId: string,
+  runId: string,
+  onEvent: (event: RunEvent) => void,
+): { promise: Promise<void>; channel: Channel<RunEvent> } {
+  const channel = new Channel<RunEvent>();
+  channel.onmessage = onEvent;
+
+  const promise = invoke<void>("follow_command_run", {
+    sessionId,
+    runId,
+    onEvent: channel,
+  });
+
+  return { promise, channel };
+}
+
+/**
+ * Follow a comparison: each model's chat events, then the finished
+ * comparison. The promise resolves once every model has answered.
+ */
+export function followComparison(
+  sessionId: string,
+  comparisonId: string,
+  onEvent: (event: CompareEvent) => void,
+): { promise: Promise<void>; channel: Channel<CompareEvent> } {
+  const channel = new Channel<CompareEvent>();
+  channel.onmessage = onEvent;
+
+  const promise = invoke<void>("follow_comparison", {
+    sessionId,
+    comparisonId,
+    onEvent: channel,
+  });
+
+  return { promise, channel };
+}
+
+/**
+ * Tell the shell which session this window's focused pane shows, so it
+ * only posts notifications about the others.
+ */
+export async function setActiveSession(sessionId: string | null): Promise<void> {
+  return invoke<void>("set_active_session", { sessionId });
+}
+
 // ── Event listeners ──
 
+/** Payload of a shell event, with the label of the window it is for. */
+interface Routed<T> {
+  window: string;
+  payload: T;
+}
+
+/** Listen for a shell event sent to this window. */
+function listenRouted<T>(
+  name: string,
+  callback: (payload: T) => void,
+): Promise<UnlistenFn> {
+  const label = getCurrentWindow().label;
+  return listen<Routed<T>>(name, (event) => {
+    if (event.payload.window === label) {
+      callback(event.payload.payload);
+    }
+  });
+}
+
+/** Open another window with its own panes. Returns its label. */
+export async function newWindow(): Promise<string> {
+  return invoke<string>("new_window");
+}
+
+/** Native menu actions, sent to the focused window. */
+export function onMenuAction(
+  callback: (action: string) => void,
+): Promise<UnlistenFn> {
+  return listenRouted<string>("menu-action", callback);
+}
+
 export function onDaemonConnected(
   callback: (payload: string) => void,
 ): Promise<UnlistenFn> {
-  return listen<string>("daemon-connected", (event) => {
-    callback(event.payload);
-  });
+  return listenRouted<string>("daemon-connected", callback);
+}
+
+/** Crash reports the daemon left behind, sent after each connect. */
+export function onDaemonCrashReport(
+  callback: (reports: CrashReport[]) => void,
+): Promise<UnlistenFn> {
+  return listenRouted<CrashReport[]>("daemon-crash-report", callback);
+}
+
+/** The messages waiting in the outbox, after each change. */
+export function onOutboxChanged(
+  callback: (messages: PendingMessage[]) => void,
+): Promise<UnlistenFn> {
+  return listenRouted<PendingMessage[]>("outbox-changed", callback);
+}
+
+/** A message from the outbox the daemon turned down. */
+export function onOutboxFailed(
+  callback: (failure: { message: PendingMessage; error: string }) => void,
+): Promise<UnlistenFn> {
+  return listenRouted<{ message: PendingMessage; error: string }>("outbox-failed", callback);
 }
 
 export function onDaemonError(
   callback: (payload: string) => void,
 ): Promise<UnlistenFn> {
-  return listen<string>("daemon-error", (event) => {
-    callback(event.payload);
-  });
+  return listenRouted<string>("daemon-error", callback);
 }
@@ -1,10 +1,14 @@
 import { create } from "zustand";
 import {
+  type ChatErrorKind,
   type Message,
+  type PendingMessage,
   type StreamEvent,
   getMessages as ipcGetMessages,
   sendMessage as ipcSendMessage,
   cancelResponse as ipcCancelResponse,
+  retryMessage as ipcRetryMessage,
+  sendResponseInput as ipcSendResponseInput,
   importHistory as ipcImportHistory,
   attachChatSession,
 } from "../lib/ipc";
@@ -17,11 +21,17 @@ interface PerSessionState {
   streamingToolCalls: Map<string, { name: string; output?: string }>;
   state: ConversationState;
   error: string | null;
+  // A prompt from the Claude CLI waiting for an answer.
+  inputRequest: { prompt: string; options: string[] } | null;
+  // A rate limit or an overloaded API the response is waiting out.
+  rateLimit: { kind: ChatErrorKind; retryAfter: number | null; retrying: boolean } | null;
 }
 
 interface ConversationStoreState {
   sessions: Map<string, PerSessionState>;
   activeChannels: Map<string, ReturnType<typeof attachChatSession>>;
+  // Messages written offline, waiting to be sent (all sessions).
+  pendingMessages: PendingMessage[];
 }
 
 interface ConversationStoreActions {
@@ -40,6 +50,12 @@ interface ConversationStoreActions {
   // Cancel a response.
   cancelResponse: (sessionId: string) => Promise<void>;
 
+  // Run the last user message again after its response failed.
+  retryMessage: (sessionId: string) => Promise<void>;
+
+  // Answer a prompt the Claude CLI raised mid-response.
+  answerInput: (sessionId: string, text: string) => Promise<void>;
+
   // Subscribe to streaming events.
   subscribeToStream: (sessionId: string) => void;
 
@@ -60,6 +76,12 @@ interface ConversationStoreActions {
 
   // Get conversation state.
   getState: (sessionId: string | null) => ConversationState;
+
+  // Replace the messages waiting in the outbox.
+  setPendingMessages: (messages: PendingMessage[]) => void;
+
+  // A message from the outbox the daemon turned down.
+  outboxFailed: (message: PendingMessage, error: string) => void;
 }
 
 const defaultSessionState = (): PerSessionState => ({
@@ -68,6 +90,8 @@ const defaultSessionState = (): PerSessionState => ({
   streamingToolCalls: new Map(),
   state: "loading",
   error: null,
+  inputRequest: null,
+  rateLimit: null,
 });
 
 // Stable empty array to avoid infinite render loops.
@@ -78,6 +102,7 @@ export const useConversationStore = create<
 >()((set, get) => ({
   sessions: new Map(),
   activeChannels: new Map(),
+  pendingMessages: [],
 
   initSession: (sessionId: string) => {
     set((state) => {
@@ -123,7 +148,7 @@ export const useConversationStore = create<
     get().initSession(sessionId);
 
     try {
-      const history = await ipcImportHistory(sessionId, limit, undefined, targetCliSessionId);
+      const { messages: history } = await ipcImportHistory(sessionId, limit, undefined, targetCliSessionId);
       if (history.length > 0) {
         set((state) => {
           const newSessions = new Map(state.sessions);
@@ -192,7 +217,18 @@ export const useConversationStore = create<
     }
 
     try {
-      await ipcSendMessage(sessionId, content, model);
+      const sent = await ipcSendMessage(sessionId, content, model);
+      if (sent.queued) {
+        // Nothing streams until the outbox sends it.
+        set((state) => {
+          const newSessions = new Map(state.sessions);
+          const session = newSessions.get(sessionId);
+          if (session) {
+            newSessions.set(sessionId, { ...session, state: "idle" });
+          }
+          return { sessions: newSessions };
+        });
+      }
     } catch (err) {
       set((state) => {
         const newSessions = new Map(state.sessions);
@@ -227,6 +263,56 @@ export const useConversationStore = create<
     }
   },
 
+  retryMessage: async (sessionId: string) => {
+    try {
+      const messageId = await ipcRetryMessage(sessionId);
+      set((state) => {
+        const newSessions = new Map(state.sessions);
+        const session = newSessions.get(sessionId);
+        if (session) {
+          // The daemon drops the failed turn and runs the message again.
+          const index = session.messages.findIndex((m) => m.id === messageId);
+          newSessions.set(sessionId, {
+            ...session,
+            messages: index >= 0 ? session.messages.slice(0, index + 1) : session.messages,
+            streamingText: "",
+            streamingToolCalls: new Map(),
+            state: "streaming",
+            error: null,
+          });
+        }
+        return { sessions: newSessions };
+      });
+    } catch (err) {
+      set((state) => {
+        const newSessions = new Map(state.sessions);
+        const session = newSessions.get(sessionId) || defaultSessionState();
+        newSessions.set(sessionId, {
+          ...session,
+          error: String(err),
+          state: "error",
+        });
+        return { sessions: newSessions };
+      });
+    }
+  },
+
+  answerInput: async (sessionId: string, text: string) => {
+    try {
+      await ipcSendResponseInput(sessionId, text);
+      set((state) => {
+        const newSessions = new Map(state.sessions);
+        const session = newSessions.get(sessionId);
+        if (session) {
+          newSessions.set(sessionId, { ...session, inputRequest: null });
+        }
+        return { sessions: newSessions };
+      });
+    } catch (err) {
+      console.error("Failed to answer prompt:", err);
+    }
+  },
+
   subscribeToStream: (sessionId: string) => {
     // Check if already subscribed.
     if (get().activeChannels.has(sessionId)) {
@@ -301,6 +387,8 @@ export const useConversationStore = create<
             streamingText: "",
             streamingToolCalls: new Map(),
             state: "idle",
+            inputRequest: null,
+            rateLimit: null,
           });
           break;
 
@@ -309,6 +397,25 @@ export const useConversationStore = create<
             ...session,
             error: event.message,
             state: "error",
+            inputRequest: null,
+          });
+          break;
+
+        case "rate_limited":
+          newSessions.set(sessionId, {
+            ...session,
+            rateLimit: {
+              kind: event.kind,
+              retryAfter: event.retry_after ?? null,
+              retrying: event.retrying,
+            },
+          });
+          break;
+
+        case "input_requested":
+          newSessions.set(sessionId, {
+            ...session,
+            inputRequest: { prompt: event.prompt, options: event.options ?? [] },
           });
           break;
 
@@ -321,6 +428,16 @@ export const useConversationStore = create<
             });
           }
           break;
+
+        case "queue_position":
+          // A queued message left the queue and its response is starting.
+          if (event.position === 0) {
+            newSessions.set(sessionId, {
+              ...session,
+              state: "streaming",
+            });
+          }
+          break;
       }
 
       return { sessions: newSessions };
@@ -346,4 +463,22 @@ export const useConversationStore = create<
     // Return "loading" if session doesn't exist yet (will be initialized soon)
     return get().sessions.get(sessionId)?.state ?? "loading";
   },
+
+  setPendingMessages: (messages: PendingMessage[]) => {
+    set({ pendingMessages: messages });
+  },
+
+  outboxFailed: (message: PendingMessage, error: string) => {
+    set((state) => {
+      const newSessions = new Map(state.sessions);
+      const session = newSessions.get(message.session_id);
+      if (session) {
+        newSessions.set(message.session_id, {
+          ...session,
+          error: `A message written offline couldn't be sent: ${error}`,
+        });
+      }
+      return { sessions: newSessions };
+    });
+  },
 }));